@@ -184,7 +184,7 @@ fn main() {
                     pred_position[i] = vel_pos[i].1;
                     velocity[i] = vel_pos[i].2;
                 }
-                grid.construct_ranges(pred_position);
+                grid.construct_ranges(pred_position).expect("particles are sorted by cell");
             });
 
         for _ in 0..4 {
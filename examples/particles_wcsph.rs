@@ -152,7 +152,7 @@ fn main() {
                 for i in 0..position.len() {
                     position[i] = vel_pos[i].0; velocity[i] = vel_pos[i].1;
                 }
-                grid.construct_ranges(position);
+                grid.construct_ranges(position).expect("particles are sorted by cell");
             })
             // Reset acceleration
             .run(sph::reset_acceleration::<f32, U2>)
@@ -170,7 +170,7 @@ fn main() {
             // Calculate viscosity force
             .run1(sph::wcsph::calculate_viscosity, (smoothing, viscosity, &grid))
             // Integrate position and velocity (apply force)
-            .run1(sph::wcsph::integrate_explicit_euler, timestep)
+            .run1(sph::wcsph::integrate_symplectic_euler, timestep)
             // Boundary checks
             .run(|p| {
                 let mut position = p.write_property::<sph::property::Position<f32, U2>>();
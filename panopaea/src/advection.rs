@@ -0,0 +1,377 @@
+//! Advection on staggered grids
+//!
+//! Velocities are stored as `Staggered2d` fields (vertical components on the
+//! horizontal edges, horizontal components on the vertical edges), scalar
+//! quantities at the face centers. Positions are given in grid units `(y, x)`.
+//!
+//! Sampling honors the boundary conditions of the grid: periodic axes wrap
+//! around and tangential velocities vanish at no-slip walls.
+
+use dec::grid::Staggered2d;
+use domain::{AxisBoundary, BoundaryCondition, Grid2d};
+use math::{self, LinearViewReal, Real, VectorN};
+use math::integration::Integrator;
+use math::vector_n::vec2;
+use ndarray::{Array2, ArrayView2, ArrayViewMut2};
+use std::cmp;
+use typenum::U2;
+
+/// Sample offset of the vertical velocity components.
+pub fn offset_vertical<T: Real>() -> (T, T) { (T::zero(), T::new(0.5)) }
+/// Sample offset of the horizontal velocity components.
+pub fn offset_horizontal<T: Real>() -> (T, T) { (T::new(0.5), T::zero()) }
+/// Sample offset of quantities stored at the face centers.
+pub fn offset_center<T: Real>() -> (T, T) { (T::new(0.5), T::new(0.5)) }
+
+/// Bilinear interpolation stencil `(y0, x0, y1, x1, s, t)` for a field sampled at `index + offset`.
+///
+/// Positions outside of the sample points are clamped, periodic axes wrap around.
+pub fn stencil<T: Real>(grid: &Grid2d, dim: (usize, usize), offset: (T, T), pos: (T, T)) -> (usize, usize, usize, usize, T, T) {
+    let (h, w) = grid.dim();
+    let [by, bx] = grid.boundary();
+    let (y0, y1, t) = stencil_axis(by.is_periodic(), h, dim.0, pos.0 - offset.0);
+    let (x0, x1, s) = stencil_axis(bx.is_periodic(), w, dim.1, pos.1 - offset.1);
+    (y0, x0, y1, x1, s, t)
+}
+
+/// Sample indices `(i0, i1)` and interpolation weight along one axis.
+///
+/// Periodic fields repeat after `cells` samples, samples on the boundary are duplicated.
+fn stencil_axis<T: Real>(periodic: bool, cells: usize, samples: usize, p: T) -> (usize, usize, T) {
+    if periodic {
+        let len = T::new(cells);
+        let p = p - len * (p / len).floor();
+        let i0 = cmp::min(p.floor().to_usize().unwrap(), cells - 1);
+        (i0, (i0 + 1) % cells, p - T::new(i0))
+    } else {
+        let p = p.max(T::zero()).min(T::new(samples - 1));
+        let i0 = p.floor().to_usize().unwrap();
+        (i0, cmp::min(i0 + 1, samples - 1), p - T::new(i0))
+    }
+}
+
+/// Bilinear interpolation of a field sampled at `index + offset`.
+pub fn sample<T: Real>(grid: &Grid2d, field: ArrayView2<T>, offset: (T, T), pos: (T, T)) -> T {
+    let (y0, x0, y1, x1, s, t) = stencil(grid, field.dim(), offset, pos);
+    math::bilinear(
+        field[(y0, x0)], field[(y0, x1)],
+        field[(y1, x0)], field[(y1, x1)],
+        s, t,
+    )
+}
+
+/// Minimum and maximum of the samples used for interpolating at the position.
+fn sample_bounds<T: Real>(grid: &Grid2d, field: ArrayView2<T>, offset: (T, T), pos: (T, T)) -> (T, T) {
+    let (y0, x0, y1, x1, _, _) = stencil(grid, field.dim(), offset, pos);
+    let samples = [field[(y0, x0)], field[(y0, x1)], field[(y1, x0)], field[(y1, x1)]];
+    samples[1..].iter().fold((samples[0], samples[0]), |(lo, hi), &v| (lo.min(v), hi.max(v)))
+}
+
+/// Interpolate the velocity vector `(y, x)` at an arbitrary position.
+pub fn sample_velocity<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>, pos: (T, T)) -> (T, T) {
+    let (h, w) = grid.dim();
+    let [by, bx] = grid.boundary();
+    let (vertical, horizontal) = velocity.split();
+    (
+        sample(grid, vertical, offset_vertical(), pos) * no_slip(bx, pos.1, w),
+        sample(grid, horizontal, offset_horizontal(), pos) * no_slip(by, pos.0, h),
+    )
+}
+
+/// Trace a position `(y, x)` through the velocity field over one timestep.
+pub fn trace<T, I>(integrator: &I, grid: &Grid2d, velocity: &Staggered2d<T>, pos: (T, T), timestep: T) -> (T, T)
+    where T: Real,
+          I: Integrator<T>,
+{
+    let end = integrator.step(&vec2(pos.0, pos.1), T::zero(), timestep, |_, p: &VectorN<T, U2>| {
+        let vel = sample_velocity(grid, velocity, (p[0], p[1]));
+        vec2(vel.0, vel.1)
+    });
+    (end[0], end[1])
+}
+
+/// Scaling of a tangential velocity component next to no-slip walls.
+///
+/// The closest samples lie half a cell away from the wall, the velocity
+/// vanishes at the wall itself.
+fn no_slip<T: Real>(boundary: AxisBoundary, p: T, len: usize) -> T {
+    let half = T::new(0.5);
+    let mut scale = T::one();
+    if boundary.lower == BoundaryCondition::NoSlip {
+        scale = scale.min(p / half);
+    }
+    if boundary.upper == BoundaryCondition::NoSlip {
+        scale = scale.min((T::new(len) - p) / half);
+    }
+    scale.max(T::zero())
+}
+
+/// Trace a position backwards in time along the velocity field (midpoint rule).
+pub fn backtrace<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>, pos: (T, T), timestep: T) -> (T, T) {
+    let half = T::new(0.5) * timestep;
+    let vel = sample_velocity(grid, velocity, pos);
+    let mid = (pos.0 - half * vel.0, pos.1 - half * vel.1);
+    let vel = sample_velocity(grid, velocity, mid);
+    (pos.0 - timestep * vel.0, pos.1 - timestep * vel.1)
+}
+
+/// Advect a field sampled at `index + offset`.
+pub fn advect_field<T: Real>(
+    grid: &Grid2d,
+    mut dst: ArrayViewMut2<T>,
+    src: ArrayView2<T>,
+    offset: (T, T),
+    velocity: &Staggered2d<T>,
+    timestep: T,
+) {
+    par_azip!(index (y, x), mut dst in {
+        let pos = (T::new(y) + offset.0, T::new(x) + offset.1);
+        *dst = sample(grid, src, offset, backtrace(grid, velocity, pos, timestep));
+    });
+}
+
+/// Advect a field sampled at `index + offset` with the MacCormack (BFECC) scheme.
+///
+/// The error of a forward/backward semi-Lagrangian step is used to correct the result,
+/// which is clamped to the values of the interpolation stencil to avoid new extrema.
+pub fn advect_field_maccormack<T: Real>(
+    grid: &Grid2d,
+    dst: ArrayViewMut2<T>,
+    src: ArrayView2<T>,
+    offset: (T, T),
+    velocity: &Staggered2d<T>,
+    timestep: T,
+) {
+    let mut forward = Array2::zeros(src.dim());
+    let mut backward = Array2::zeros(src.dim());
+    advect_field_maccormack_with(grid, dst, src, offset, velocity, timestep, (forward.view_mut(), backward.view_mut()));
+}
+
+/// `advect_field_maccormack` with temporary `(forward, backward)` fields of the shape of `src`.
+pub fn advect_field_maccormack_with<T: Real>(
+    grid: &Grid2d,
+    mut dst: ArrayViewMut2<T>,
+    src: ArrayView2<T>,
+    offset: (T, T),
+    velocity: &Staggered2d<T>,
+    timestep: T,
+    (mut forward, mut backward): (ArrayViewMut2<T>, ArrayViewMut2<T>),
+) {
+    advect_field(grid, forward.view_mut(), src, offset, velocity, timestep);
+    advect_field(grid, backward.view_mut(), forward.view(), offset, velocity, -timestep);
+
+    let half = T::new(0.5);
+    let field = src;
+    par_azip!(index (y, x), mut dst, forward (forward.view()), backward (backward.view()), src (field) in {
+        let pos = backtrace(grid, velocity, (T::new(y) + offset.0, T::new(x) + offset.1), timestep);
+        let (lo, hi) = sample_bounds(grid, field, offset, pos);
+        *dst = (forward + half * (src - backward)).max(lo).min(hi);
+    });
+}
+
+/// Advection scheme.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scheme {
+    /// First order semi-Lagrangian with bilinear interpolation.
+    SemiLagrangian,
+    /// Second order MacCormack (BFECC) with clamping.
+    MacCormack,
+}
+
+impl Scheme {
+    /// Advect a field sampled at `index + offset`.
+    pub fn advect_field<T: Real>(
+        self,
+        grid: &Grid2d,
+        dst: ArrayViewMut2<T>,
+        src: ArrayView2<T>,
+        offset: (T, T),
+        velocity: &Staggered2d<T>,
+        timestep: T,
+    ) {
+        match self {
+            Scheme::SemiLagrangian => advect_field(grid, dst, src, offset, velocity, timestep),
+            Scheme::MacCormack => advect_field_maccormack(grid, dst, src, offset, velocity, timestep),
+        }
+    }
+
+    /// `advect_field` with temporary `(forward, backward)` fields of the shape of `src`, unused by
+    /// the semi-Lagrangian scheme.
+    pub fn advect_field_with<T: Real>(
+        self,
+        grid: &Grid2d,
+        dst: ArrayViewMut2<T>,
+        src: ArrayView2<T>,
+        offset: (T, T),
+        velocity: &Staggered2d<T>,
+        timestep: T,
+        scratch: (ArrayViewMut2<T>, ArrayViewMut2<T>),
+    ) {
+        match self {
+            Scheme::SemiLagrangian => advect_field(grid, dst, src, offset, velocity, timestep),
+            Scheme::MacCormack => advect_field_maccormack_with(grid, dst, src, offset, velocity, timestep, scratch),
+        }
+    }
+}
+
+/// Advect a scalar quantity stored at the face centers.
+pub fn advect_scalar<T: Real>(
+    grid: &Grid2d,
+    scheme: Scheme,
+    dst: &mut Array2<T>,
+    src: &Array2<T>,
+    velocity: &Staggered2d<T>,
+    timestep: T,
+) {
+    scheme.advect_field(grid, dst.view_mut(), src.view(), offset_center(), velocity, timestep);
+}
+
+/// `advect_scalar` with two temporary scalar fields.
+pub fn advect_scalar_with<T: Real>(
+    grid: &Grid2d,
+    scheme: Scheme,
+    dst: &mut Array2<T>,
+    src: &Array2<T>,
+    velocity: &Staggered2d<T>,
+    timestep: T,
+    (forward, backward): (&mut Array2<T>, &mut Array2<T>),
+) {
+    let scratch = (forward.view_mut(), backward.view_mut());
+    scheme.advect_field_with(grid, dst.view_mut(), src.view(), offset_center(), velocity, timestep, scratch);
+}
+
+/// Advect a staggered velocity field, each component is traced from its own sample points.
+pub fn advect_velocity<T: Real>(
+    grid: &Grid2d,
+    scheme: Scheme,
+    dst: &mut Staggered2d<T>,
+    src: &Staggered2d<T>,
+    velocity: &Staggered2d<T>,
+    timestep: T,
+) {
+    let (dst_vertical, dst_horizontal) = dst.split_mut();
+    let (src_vertical, src_horizontal) = src.split();
+    scheme.advect_field(grid, dst_vertical, src_vertical, offset_vertical(), velocity, timestep);
+    scheme.advect_field(grid, dst_horizontal, src_horizontal, offset_horizontal(), velocity, timestep);
+}
+
+/// `advect_velocity` with two temporary velocity fields.
+pub fn advect_velocity_with<T: Real>(
+    grid: &Grid2d,
+    scheme: Scheme,
+    dst: &mut Staggered2d<T>,
+    src: &Staggered2d<T>,
+    velocity: &Staggered2d<T>,
+    timestep: T,
+    (forward, backward): (&mut Staggered2d<T>, &mut Staggered2d<T>),
+) {
+    let (dst_vertical, dst_horizontal) = dst.split_mut();
+    let (src_vertical, src_horizontal) = src.split();
+    let (forward_vertical, forward_horizontal) = forward.split_mut();
+    let (backward_vertical, backward_horizontal) = backward.split_mut();
+    scheme.advect_field_with(grid, dst_vertical, src_vertical, offset_vertical(), velocity, timestep,
+                             (forward_vertical, backward_vertical));
+    scheme.advect_field_with(grid, dst_horizontal, src_horizontal, offset_horizontal(), velocity, timestep,
+                             (forward_horizontal, backward_horizontal));
+}
+
+/// Largest velocity component of the field.
+pub fn max_speed<T: Real>(velocity: &Staggered2d<T>) -> T {
+    velocity.norm_max()
+}
+
+/// Largest timestep satisfying the CFL condition `dt * max_speed <= cfl` (grid units).
+pub fn cfl_timestep<T: Real>(velocity: &Staggered2d<T>, cfl: T) -> T {
+    let speed = max_speed(velocity);
+    if speed > T::zero() { cfl / speed } else { T::infinity() }
+}
+
+/// Split a timestep into equally sized substeps satisfying the CFL condition.
+///
+/// Returns the number of substeps and the substep size.
+pub fn cfl_substeps<T: Real>(velocity: &Staggered2d<T>, timestep: T, cfl: T) -> (usize, T) {
+    let steps = (timestep * max_speed(velocity) / cfl).ceil().to_usize().unwrap_or(1);
+    let steps = cmp::max(steps, 1);
+    (steps, timestep / T::new(steps))
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::Manifold2d;
+    use domain::{AxisBoundary, Grid2d};
+    use ndarray::Array2;
+    use super::*;
+
+    #[test]
+    fn advect_uniform() {
+        let grid = Grid2d::new((8, 8));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().1.fill(1.0);
+
+        let src = Array2::from_shape_fn((8, 8), |(y, x)| x as f64 + 2.0 * y as f64);
+        let mut dst = Array2::zeros((8, 8));
+        advect_scalar(&grid, Scheme::SemiLagrangian, &mut dst, &src, &velocity, 0.5);
+
+        // linear functions are reproduced exactly away from the inflow boundary
+        for ((y, x), &v) in dst.indexed_iter() {
+            if x > 0 {
+                assert!((v - (x as f64 - 0.5 + 2.0 * y as f64)).abs() < 1.0e-10);
+            }
+        }
+
+        let mut advected = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        advect_velocity(&grid, Scheme::SemiLagrangian, &mut advected, &velocity, &velocity, 0.5);
+        assert!((advected.norm_max() - 1.0).abs() < 1.0e-10);
+
+        assert_eq!(cfl_substeps(&velocity, 2.5, 1.0), (3, 2.5 / 3.0));
+        assert_eq!(cfl_timestep(&velocity, 0.5), 0.5);
+    }
+
+    #[test]
+    fn advect_maccormack() {
+        let grid = Grid2d::new((4, 16));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().1.fill(1.0);
+
+        // step function, no new extrema are introduced
+        let mut src = Array2::from_shape_fn((4, 16), |(_, x)| if x < 8 { 1.0 } else { 0.0 });
+        let mut dst = Array2::zeros((4, 16));
+        for _ in 0..4 {
+            advect_scalar(&grid, Scheme::MacCormack, &mut dst, &src, &velocity, 0.7);
+            src.assign(&dst);
+        }
+        for &v in dst.iter() {
+            assert!(v >= 0.0 && v <= 1.0);
+        }
+
+        // temporary fields of the caller give the same result
+        let (mut forward, mut backward) = (Array2::zeros((4, 16)), Array2::zeros((4, 16)));
+        let mut reused = Array2::zeros((4, 16));
+        advect_scalar(&grid, Scheme::MacCormack, &mut dst, &src, &velocity, 0.7);
+        advect_scalar_with(&grid, Scheme::MacCormack, &mut reused, &src, &velocity, 0.7, (&mut forward, &mut backward));
+        assert_eq!(dst, reused);
+
+        // less diffusion than semi-lagrangian
+        let src = Array2::from_shape_fn((4, 16), |(_, x)| if x < 8 { 1.0 } else { 0.0 });
+        let mut semi_lagrangian = Array2::zeros((4, 16));
+        let mut maccormack = Array2::zeros((4, 16));
+        advect_scalar(&grid, Scheme::SemiLagrangian, &mut semi_lagrangian, &src, &velocity, 0.5);
+        advect_scalar(&grid, Scheme::MacCormack, &mut maccormack, &src, &velocity, 0.5);
+        assert!(maccormack[(0, 8)] < semi_lagrangian[(0, 8)]);
+    }
+
+    #[test]
+    fn advect_periodic() {
+        let grid = Grid2d::with_boundary((4, 8), [AxisBoundary::default(), AxisBoundary::periodic()]);
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().1.fill(1.0);
+
+        // shifted by one cell, wrapping around at the boundary
+        let src = Array2::from_shape_fn((4, 8), |(_, x)| x as f64);
+        let mut dst = Array2::zeros((4, 8));
+        advect_scalar(&grid, Scheme::SemiLagrangian, &mut dst, &src, &velocity, 1.0);
+        for ((_, x), &v) in dst.indexed_iter() {
+            assert!((v - ((x + 7) % 8) as f64).abs() < 1.0e-10);
+        }
+    }
+}
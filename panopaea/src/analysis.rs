@@ -0,0 +1,331 @@
+//! Turbulence statistics of velocity fields
+//!
+//! Statistics for validating simulations against turbulence theory without
+//! exporting the fields: radially averaged kinetic energy spectra, enstrophy
+//! and longitudinal structure functions.
+//!
+//! All statistics are computed from velocities interpolated to the cell
+//! centers. Spectra treat the domain as periodic, on closed domains the jump
+//! at the walls leaks energy into high wave numbers. Energy spectra are
+//! normalized such that the sum over all shells is the mean kinetic energy
+//! `1/2 <|u|²>` for unit density, in 2d the inertial range follows
+//! `E(k) ~ k^{-3}` (enstrophy cascade), in 3d `E(k) ~ k^{-5/3}`.
+//!
+//! References:
+//!     [Pop00] Stephen B. Pope, 2000,
+//!             Turbulent Flows,
+//!             Cambridge University Press
+//!     [Fri95] Uriel Frisch, 1995,
+//!             Turbulence: The Legacy of A. N. Kolmogorov,
+//!             Cambridge University Press
+
+use dec::grid::{Staggered2d, Staggered3d};
+use domain::{Grid2d, Grid3d};
+use fft::FFTnum;
+use math::Real;
+use math::fft::{Normalization, Planner, RealFft2d, RealFft3d};
+use ndarray::{Array, Array2, Array3, ArrayView, Axis, RemoveAxis};
+use num::complex::Complex;
+
+use std::f64::consts::PI;
+
+/// Kinetic energy summed over shells of the wave number magnitude.
+#[derive(Clone, Debug)]
+pub struct EnergySpectrum<T> {
+    /// Center of each shell, shells are one fundamental wave number `2π/L` wide.
+    pub wavenumber: Vec<T>,
+    /// Energy of the modes within each shell.
+    pub energy: Vec<T>,
+}
+
+impl<T: Real> EnergySpectrum<T> {
+    /// Mean kinetic energy `1/2 <|u|²>`.
+    pub fn total(&self) -> T {
+        self.energy.iter().fold(T::zero(), |sum, &e| sum + e)
+    }
+
+    /// Mean enstrophy `1/2 <|ω|²> = Σ k² E(k)` of a divergence-free field.
+    pub fn enstrophy(&self) -> T {
+        self.wavenumber.iter().zip(self.energy.iter()).fold(T::zero(), |sum, (&k, &e)| sum + k * k * e)
+    }
+
+    /// Exponent of a power law `E ~ k^α` fitted to the shells within `[k_min, k_max]`.
+    ///
+    /// Least squares fit in log-log space, empty shells are ignored.
+    pub fn slope(&self, k_min: T, k_max: T) -> Option<T> {
+        let points = self.wavenumber.iter().zip(self.energy.iter())
+            .filter(|&(&k, &e)| k >= k_min && k <= k_max && k > T::zero() && e > T::zero())
+            .map(|(&k, &e)| (k.ln(), e.ln()))
+            .collect::<Vec<_>>();
+        if points.len() < 2 {
+            return None;
+        }
+
+        let n = T::new(points.len());
+        let (sx, sy) = points.iter().fold((T::zero(), T::zero()), |(sx, sy), &(x, y)| (sx + x, sy + y));
+        let (mx, my) = (sx / n, sy / n);
+        let (sxy, sxx) = points.iter().fold((T::zero(), T::zero()), |(sxy, sxx), &(x, y)| {
+            (sxy + (x - mx) * (y - my), sxx + (x - mx) * (x - mx))
+        });
+        Some(sxy / sxx)
+    }
+}
+
+/// Shell binning of the modes of a real spectrum.
+struct Shells<T> {
+    delta: f64,
+    energy: Vec<T>,
+}
+
+impl<T: Real> Shells<T> {
+    fn new(lengths: &[f64]) -> Self {
+        let longest = lengths.iter().cloned().fold(0.0, f64::max);
+        Shells { delta: 2.0 * PI / longest, energy: Vec::new() }
+    }
+
+    /// Add the modes of coefficient `c` with wave vector `k`.
+    ///
+    /// Coefficients along the halved last axis stand for their conjugate
+    /// partner as well, except for the zero and Nyquist frequency.
+    fn add(&mut self, k: f64, c: Complex<T>, last: usize, n: usize) {
+        let weight = if last == 0 || 2 * last == n { 0.5 } else { 1.0 };
+        let bin = (k / self.delta).round() as usize;
+        if bin >= self.energy.len() {
+            self.energy.resize(bin + 1, T::zero());
+        }
+        self.energy[bin] = self.energy[bin] + T::new(weight) * c.norm_sqr();
+    }
+
+    fn into_spectrum(self) -> EnergySpectrum<T> {
+        let delta = self.delta;
+        EnergySpectrum {
+            wavenumber: (0..self.energy.len()).map(|i| T::new(i as f64 * delta)).collect(),
+            energy: self.energy,
+        }
+    }
+}
+
+/// Signed frequency of FFT index `i` of `n`.
+fn frequency(i: usize, n: usize) -> f64 {
+    if i <= n / 2 { i as f64 } else { i as f64 - n as f64 }
+}
+
+/// Velocity components `(v, u)` at the cell centers.
+pub fn cell_velocity_2d<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>) -> (Array2<T>, Array2<T>) {
+    let (dy, dx) = grid.spacing();
+    let (vertical, horizontal) = velocity.split();
+    let (sy, sx) = (T::new(0.5 / dy), T::new(0.5 / dx));
+    (
+        Array2::from_shape_fn(grid.dim(), |(y, x)| (vertical[(y, x)] + vertical[(y + 1, x)]) * sy),
+        Array2::from_shape_fn(grid.dim(), |(y, x)| (horizontal[(y, x)] + horizontal[(y, x + 1)]) * sx),
+    )
+}
+
+/// Velocity components `(w, v, u)` at the cell centers.
+pub fn cell_velocity_3d<T: Real>(grid: &Grid3d, velocity: &Staggered3d<T>) -> (Array3<T>, Array3<T>, Array3<T>) {
+    let (vz, vy, vx) = velocity.split();
+    let half = T::new(0.5);
+    (
+        Array3::from_shape_fn(grid.dim(), |(z, y, x)| (vz[(z, y, x)] + vz[(z + 1, y, x)]) * half),
+        Array3::from_shape_fn(grid.dim(), |(z, y, x)| (vy[(z, y, x)] + vy[(z, y + 1, x)]) * half),
+        Array3::from_shape_fn(grid.dim(), |(z, y, x)| (vx[(z, y, x)] + vx[(z, y, x + 1)]) * half),
+    )
+}
+
+/// Radially averaged energy spectrum of a 2d velocity field.
+pub fn energy_spectrum_2d<T>(grid: &Grid2d, velocity: &Staggered2d<T>) -> EnergySpectrum<T>
+    where T: Real + FFTnum
+{
+    let (h, w) = grid.dim();
+    let (dy, dx) = grid.spacing();
+    let (ly, lx) = (h as f64 * dy, w as f64 * dx);
+    let fft = RealFft2d::new(&mut Planner::new(), (h, w), Normalization::Forward);
+    let mut spectrum = Array2::from_elem(fft.spectrum_dim(), Complex::new(T::zero(), T::zero()));
+    let mut shells = Shells::new(&[ly, lx]);
+
+    let (v, u) = cell_velocity_2d(grid, velocity);
+    for component in &[v, u] {
+        fft.forward(component.view(), spectrum.view_mut());
+        for ((j, i), &c) in spectrum.indexed_iter() {
+            let (ky, kx) = (frequency(j, h) / ly, frequency(i, w) / lx);
+            shells.add(2.0 * PI * ky.hypot(kx), c, i, w);
+        }
+    }
+    shells.into_spectrum()
+}
+
+/// Radially averaged energy spectrum of a 3d velocity field.
+pub fn energy_spectrum_3d<T>(grid: &Grid3d, velocity: &Staggered3d<T>) -> EnergySpectrum<T>
+    where T: Real + FFTnum
+{
+    let (d, h, w) = grid.dim();
+    let (lz, ly, lx) = (d as f64, h as f64, w as f64);
+    let fft = RealFft3d::new(&mut Planner::new(), (d, h, w), Normalization::Forward);
+    let mut spectrum = Array3::from_elem(fft.spectrum_dim(), Complex::new(T::zero(), T::zero()));
+    let mut shells = Shells::new(&[lz, ly, lx]);
+
+    let (wz, v, u) = cell_velocity_3d(grid, velocity);
+    for component in &[wz, v, u] {
+        fft.forward(component.view(), spectrum.view_mut());
+        for ((k, j, i), &c) in spectrum.indexed_iter() {
+            let (kz, ky, kx) = (frequency(k, d) / lz, frequency(j, h) / ly, frequency(i, w) / lx);
+            shells.add(2.0 * PI * (kz * kz + ky * ky + kx * kx).sqrt(), c, i, w);
+        }
+    }
+    shells.into_spectrum()
+}
+
+/// Central difference along an axis, wrapping around periodic axes and one-sided at walls.
+fn derivative<T, D>(field: ArrayView<T, D>, axis: usize, periodic: bool, spacing: f64) -> Array<T, D>
+    where T: Real, D: RemoveAxis
+{
+    let n = field.len_of(Axis(axis));
+    let mut result = Array::zeros(field.raw_dim());
+    if n < 2 {
+        return result;
+    }
+    for i in 0..n {
+        let (lo, hi) = if periodic {
+            ((i + n - 1) % n, (i + 1) % n)
+        } else {
+            (i.saturating_sub(1), (i + 1).min(n - 1))
+        };
+        let distance = T::new((if periodic { 2.0 } else { (hi - lo) as f64 }) * spacing);
+        let mut slice = result.subview_mut(Axis(axis), i);
+        slice.assign(&field.subview(Axis(axis), hi));
+        slice.zip_mut_with(&field.subview(Axis(axis), lo), |r, &l| *r = (*r - l) / distance);
+    }
+    result
+}
+
+/// Enstrophy `1/2 ∫ ω² dA` of a 2d velocity field.
+pub fn enstrophy_2d<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>) -> T {
+    let (dy, dx) = grid.spacing();
+    let boundary = grid.boundary();
+    let (v, u) = cell_velocity_2d(grid, velocity);
+    let dv_dx = derivative(v.view(), 1, boundary[1].is_periodic(), dx);
+    let du_dy = derivative(u.view(), 0, boundary[0].is_periodic(), dy);
+    let sum = dv_dx.iter().zip(du_dy.iter()).fold(T::zero(), |sum, (&a, &b)| sum + (a - b) * (a - b));
+    T::new(0.5 * dy * dx) * sum
+}
+
+/// Enstrophy `1/2 ∫ |ω|² dV` of a 3d velocity field.
+pub fn enstrophy_3d<T: Real>(grid: &Grid3d, velocity: &Staggered3d<T>) -> T {
+    let boundary = grid.boundary();
+    let (w, v, u) = cell_velocity_3d(grid, velocity);
+    let d = |field: &Array3<T>, axis: usize| derivative(field.view(), axis, boundary[axis].is_periodic(), 1.0);
+
+    let omega_x = d(&w, 1) - d(&v, 0);
+    let omega_y = d(&u, 0) - d(&w, 2);
+    let omega_z = d(&v, 2) - d(&u, 1);
+    let sum = omega_x.iter().chain(omega_y.iter()).chain(omega_z.iter()).fold(T::zero(), |sum, &c| sum + c * c);
+    T::new(0.5) * sum
+}
+
+/// Accumulate `|δu|^p` of all pairs separated by `r` cells along an axis into `sums[r]`.
+fn increments<T, D>(field: ArrayView<T, D>, axis: usize, periodic: bool, order: T, sums: &mut [(T, usize)])
+    where T: Real, D: RemoveAxis
+{
+    let n = field.len_of(Axis(axis));
+    for r in 1..sums.len() {
+        for i in 0..n {
+            let j = if i + r < n { i + r } else if periodic { (i + r) % n } else { continue };
+            let (a, b) = (field.subview(Axis(axis), i), field.subview(Axis(axis), j));
+            let (ref mut sum, ref mut count) = sums[r];
+            for (&a, &b) in a.iter().zip(b.iter()) {
+                *sum = *sum + (b - a).abs().powf(order);
+            }
+            *count += a.len();
+        }
+    }
+}
+
+fn average<T: Real>(sums: Vec<(T, usize)>) -> Vec<T> {
+    sums.into_iter().map(|(sum, count)| if count > 0 { sum / T::new(count) } else { T::zero() }).collect()
+}
+
+/// Longitudinal structure function `S_p(r) = <|u_L(x + r) - u_L(x)|^p>` of a 2d velocity field.
+///
+/// Element `r` holds the separation of `r` cells, averaged over both axes.
+/// Separations are counted in cells and assume square cells.
+pub fn structure_function_2d<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>, order: T, max_separation: usize) -> Vec<T> {
+    let boundary = grid.boundary();
+    let (v, u) = cell_velocity_2d(grid, velocity);
+    let mut sums = vec![(T::zero(), 0); max_separation + 1];
+    increments(v.view(), 0, boundary[0].is_periodic(), order, &mut sums);
+    increments(u.view(), 1, boundary[1].is_periodic(), order, &mut sums);
+    average(sums)
+}
+
+/// Longitudinal structure function `S_p(r)` of a 3d velocity field, see `structure_function_2d`.
+pub fn structure_function_3d<T: Real>(grid: &Grid3d, velocity: &Staggered3d<T>, order: T, max_separation: usize) -> Vec<T> {
+    let boundary = grid.boundary();
+    let (w, v, u) = cell_velocity_3d(grid, velocity);
+    let mut sums = vec![(T::zero(), 0); max_separation + 1];
+    increments(w.view(), 0, boundary[0].is_periodic(), order, &mut sums);
+    increments(v.view(), 1, boundary[1].is_periodic(), order, &mut sums);
+    increments(u.view(), 2, boundary[2].is_periodic(), order, &mut sums);
+    average(sums)
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::Manifold2d;
+    use domain::AxisBoundary;
+    use super::*;
+
+    #[test]
+    fn periodic_shear_wave() {
+        let n = 32;
+        let grid = Grid2d::with_boundary((n, n), [AxisBoundary::periodic(), AxisBoundary::periodic()])
+            .with_spacing((0.5, 0.5));
+        let length = n as f64 * 0.5;
+        let a = 2.0 * PI * 3.0 / length;
+
+        // u = sin(a y), integrated along the horizontal edges
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (_, mut horizontal) = velocity.split_mut();
+            for ((y, _), u) in horizontal.indexed_iter_mut() {
+                *u = (a * (y as f64 + 0.5) * 0.5).sin() * 0.5;
+            }
+        }
+
+        let spectrum = energy_spectrum_2d(&grid, &velocity);
+        assert!((spectrum.total() - 0.25).abs() < 1e-12);
+        assert!((spectrum.wavenumber[3] - a).abs() < 1e-12);
+        assert!((spectrum.energy[3] - 0.25).abs() < 1e-12);
+        assert!((spectrum.enstrophy() - 0.25 * a * a).abs() < 1e-10);
+
+        let damping = (a * 0.5).sin() / (a * 0.5);
+        let area = length * length;
+        let enstrophy = enstrophy_2d(&grid, &velocity);
+        assert!((enstrophy - 0.25 * a * a * damping * damping * area).abs() < 1e-10);
+
+        // shear flows have no longitudinal increments
+        let s2 = structure_function_2d(&grid, &velocity, 2.0, 4);
+        assert!(s2.iter().all(|&s| s.abs() < 1e-12));
+
+        // u = sin(a x), half of the pairs along y don't contribute
+        let mut wave = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (_, mut horizontal) = wave.split_mut();
+            for ((_, x), u) in horizontal.indexed_iter_mut() {
+                *u = (a * x as f64 * 0.5).sin() * 0.5;
+            }
+        }
+        let c = (a * 0.25).cos();
+        let s2 = structure_function_2d(&grid, &wave, 2.0, 4);
+        for r in 1..5 {
+            let expected = 0.5 * c * c * 2.0 * (a * r as f64 * 0.25).sin().powi(2);
+            assert!((s2[r] - expected).abs() < 1e-12, "{}: {} {}", r, s2[r], expected);
+        }
+
+        // power law fit
+        let spectrum = EnergySpectrum {
+            wavenumber: (0..20).map(|k| k as f64).collect(),
+            energy: (0..20).map(|k| if k > 0 { (k as f64).powf(-3.0) } else { 0.0 }).collect(),
+        };
+        assert!((spectrum.slope(2.0, 16.0).unwrap() + 3.0).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,256 @@
+//! Particle collisions with signed distance obstacles
+//!
+//! Colliders are resolved after the particles have been moved. The path of
+//! each particle over the step is sphere traced through the signed distance
+//! [Har96], so particles hit thin obstacles instead of tunneling through them
+//! when they move further than the obstacle's thickness within one step.
+//! Particles ending up inside are projected back onto the surface along the
+//! normal.
+//!
+//! The normal velocity of particles approaching the surface is reflected and
+//! scaled by the coefficient of restitution, the tangential velocity is
+//! reduced by Coulomb friction proportional to the normal impulse [BFA02].
+//!
+//! Shapes are given in the units of the particle positions, e.g. world
+//! units for SPH and grid units `(x, y)` for FLIP.
+//!
+//! References:
+//!     [Har96] John C. Hart, 1996,
+//!             Sphere tracing: a geometric method for the antialiased ray tracing of implicit surfaces,
+//!             The Visual Computer 12 (10), 527-545
+//!     [BFA02] Robert Bridson, Ronald Fedkiw, and John Anderson, 2002,
+//!             Robust treatment of collisions, contact and friction for cloth animation,
+//!             ACM Trans. Graph. 21, 3 (July 2002), 594-603
+
+use cgmath::InnerSpace;
+use domain::Grid2d;
+use implicit::Implicit;
+use levelset::{LevelSet2d, LevelSet3d};
+use math::{Dim, Real, VectorN};
+use typenum::{U2, U3};
+
+/// Fraction of the path length advanced at least per sphere tracing step.
+const MIN_STEP: f64 = 1.0e-3;
+
+/// Projection iterations for inexact distances, e.g. boolean combinations.
+const PROJECTION_ITERATIONS: usize = 4;
+
+/// Signed distance field, negative inside.
+pub trait Sdf<T: Real, N: Dim<T>>: Send + Sync {
+    fn distance(&self, p: &VectorN<T, N>) -> T;
+
+    /// Outward unit normal, zero where the gradient vanishes.
+    fn normal(&self, p: &VectorN<T, N>) -> VectorN<T, N>;
+}
+
+/// Normalized gradient of a distance function by central differences with step `h`.
+fn gradient_normal<T, N, S>(sdf: &S, p: &VectorN<T, N>, h: T) -> VectorN<T, N>
+    where T: Real,
+          N: Dim<T>,
+          S: Sdf<T, N> + ?Sized,
+          VectorN<T, N>: Copy,
+{
+    let mut gradient = p.clone();
+    for d in 0..N::to_usize() {
+        let (mut forward, mut backward) = (p.clone(), p.clone());
+        forward[d] = forward[d] + h;
+        backward[d] = backward[d] - h;
+        gradient[d] = sdf.distance(&forward) - sdf.distance(&backward);
+    }
+    let norm = gradient.clone().magnitude();
+    if norm > T::zero() { gradient / norm } else { gradient * T::zero() }
+}
+
+impl<T: Real, N: Dim<T>> Sdf<T, N> for Implicit<T, N>
+    where VectorN<T, N>: Copy
+{
+    fn distance(&self, p: &VectorN<T, N>) -> T {
+        Implicit::distance(self, p)
+    }
+
+    fn normal(&self, p: &VectorN<T, N>) -> VectorN<T, N> {
+        Implicit::normal(self, p, T::new(1.0e-6))
+    }
+}
+
+/// Level set on its grid, positions `(x, y)` in grid units.
+impl<T: Real> Sdf<T, U2> for (Grid2d, LevelSet2d<T>) {
+    fn distance(&self, p: &VectorN<T, U2>) -> T {
+        self.1.sample(&self.0, (p[1], p[0]))
+    }
+
+    fn normal(&self, p: &VectorN<T, U2>) -> VectorN<T, U2> {
+        gradient_normal(self, p, T::new(0.5))
+    }
+}
+
+/// Positions `(x, y, z)` in grid units.
+impl<T: Real> Sdf<T, U3> for LevelSet3d<T> {
+    fn distance(&self, p: &VectorN<T, U3>) -> T {
+        self.sample((p[2], p[1], p[0]))
+    }
+
+    fn normal(&self, p: &VectorN<T, U3>) -> VectorN<T, U3> {
+        gradient_normal(self, p, T::new(0.5))
+    }
+}
+
+/// Static obstacle for particles.
+pub struct Collider<T: Real, N: Dim<T>> {
+    pub shape: Box<Sdf<T, N>>,
+    /// Fraction of the normal velocity kept after an impact, `0` inelastic up to `1` elastic.
+    pub restitution: T,
+    /// Coulomb friction coefficient.
+    pub friction: T,
+    /// Distance kept from the surface, e.g. the particle radius.
+    pub thickness: T,
+}
+
+impl<T: Real, N: Dim<T>> Collider<T, N>
+    where VectorN<T, N>: Copy
+{
+    /// Inelastic and frictionless collider.
+    pub fn new<S: Sdf<T, N> + 'static>(shape: S) -> Self {
+        Collider {
+            shape: Box::new(shape),
+            restitution: T::zero(),
+            friction: T::zero(),
+            thickness: T::zero(),
+        }
+    }
+
+    pub fn with_restitution(mut self, restitution: T) -> Self {
+        self.restitution = restitution;
+        self
+    }
+
+    pub fn with_friction(mut self, friction: T) -> Self {
+        self.friction = friction;
+        self
+    }
+
+    pub fn with_thickness(mut self, thickness: T) -> Self {
+        self.thickness = thickness;
+        self
+    }
+
+    fn gap(&self, p: &VectorN<T, N>) -> T {
+        self.shape.distance(p) - self.thickness
+    }
+
+    /// First point along the path from `start` to `end` closer than `thickness` to the surface.
+    ///
+    /// `None` if the path stays outside or starts in contact, particles resting on
+    /// the surface keep their tangential motion and are only projected.
+    fn first_contact(&self, start: &VectorN<T, N>, end: &VectorN<T, N>) -> Option<VectorN<T, N>> {
+        let delta = end.clone() - start.clone();
+        let length = delta.clone().magnitude();
+        let min_step = length * T::new(MIN_STEP);
+        if length == T::zero() || self.gap(start) <= min_step {
+            return None;
+        }
+        let direction = delta / length;
+
+        let mut t = T::zero();
+        loop {
+            let p = start.clone() + direction.clone() * t;
+            let gap = self.gap(&p);
+            if gap < T::zero() {
+                return Some(p);
+            }
+            if t >= length {
+                return None;
+            }
+            t = (t + gap.max(min_step)).min(length);
+        }
+    }
+
+    /// Resolve the collision of a particle moved from `start` to `position` during the step.
+    ///
+    /// Returns `true` if the particle hit the collider.
+    pub fn resolve(&self, start: &VectorN<T, N>, position: &mut VectorN<T, N>, velocity: &mut VectorN<T, N>) -> bool {
+        if let Some(contact) = self.first_contact(start, position) {
+            *position = contact;
+        }
+
+        // project onto the surface
+        let mut hit = false;
+        for _ in 0..PROJECTION_ITERATIONS {
+            let gap = self.gap(position);
+            if gap >= T::zero() {
+                break;
+            }
+            hit = true;
+            let normal = self.shape.normal(position);
+            *position = position.clone() - normal * gap;
+        }
+        if !hit {
+            return false;
+        }
+
+        // reflect the approaching normal velocity and apply friction, [BFA02] Sec. 7.2
+        let normal = self.shape.normal(position);
+        let vn = velocity.clone().dot(normal.clone());
+        if vn < T::zero() {
+            let tangential = velocity.clone() - normal.clone() * vn;
+            let vt = tangential.clone().magnitude();
+            let impulse = -(T::one() + self.restitution) * vn;
+            let scale = if vt > T::zero() {
+                (T::one() - self.friction * impulse / vt).max(T::zero())
+            } else {
+                T::zero()
+            };
+            *velocity = tangential * scale - normal * (self.restitution * vn);
+        }
+        true
+    }
+}
+
+/// Resolve the collisions of a particle with all colliders in order.
+///
+/// Returns `true` if any collider was hit.
+pub fn resolve_all<T, N>(colliders: &[Collider<T, N>], start: &VectorN<T, N>, position: &mut VectorN<T, N>, velocity: &mut VectorN<T, N>) -> bool
+    where T: Real,
+          N: Dim<T>,
+          VectorN<T, N>: Copy,
+{
+    colliders.iter().fold(false, |hit, collider| collider.resolve(start, position, velocity) || hit)
+}
+
+#[cfg(test)]
+mod tests {
+    use math::vector_n::vec2;
+    use super::*;
+
+    #[test]
+    fn thin_wall_collision() {
+        // wall of width 0.1 at x = 1
+        let wall = Implicit::Box { center: vec2(1.0f64, 0.0), half_size: vec2(0.05, 10.0) };
+        let collider = Collider::new(wall).with_restitution(0.5);
+
+        // fast particle crossing the whole wall within one step
+        let start = vec2(0.0, 0.0);
+        let mut velocity = vec2(20.0, 1.0);
+        let mut position = start + velocity * 0.1;
+        assert!(collider.resolve(&start, &mut position, &mut velocity));
+        assert!((position[0] - 0.95).abs() < 1.0e-6 && position[1] < 0.1);
+        assert!((velocity[0] + 10.0).abs() < 1.0e-6 && velocity[1] == 1.0);
+
+        // sliding along a floor with friction
+        let floor = Collider::new(Implicit::HalfSpace { point: vec2(0.0, 0.0), normal: vec2(0.0, 1.0) })
+            .with_friction(0.25)
+            .with_thickness(0.1);
+        let start = vec2(0.0, 0.1);
+        let mut velocity = vec2(2.0, -1.0);
+        let mut position = start + velocity * 0.1;
+        assert!(floor.resolve(&start, &mut position, &mut velocity));
+        assert!((position - vec2(0.2, 0.1)).magnitude() < 1.0e-6);
+        assert!((velocity - vec2(1.75, 0.0)).magnitude() < 1.0e-6);
+
+        // particles away from the colliders are untouched
+        let (start, mut velocity) = (vec2(0.0, 1.0), vec2(1.0, 0.0));
+        let mut position = start + velocity * 0.1;
+        assert!(!resolve_all(&[collider, floor], &start, &mut position, &mut velocity));
+        assert!((position - vec2(0.1, 1.0)).magnitude() < 1.0e-12);
+    }
+}
@@ -0,0 +1,205 @@
+//! Size-checked operators
+//!
+//! The operators of a manifold index the simplex storage directly and assume
+//! it was created by the same manifold. Arrays of another grid either index out
+//! of bounds deep inside an operator or silently produce wrong results.
+//!
+//! `Checked` wraps a manifold and validates the number of elements of every
+//! argument before forwarding to the wrapped operators, failing with the name
+//! of the operator and the mismatching argument. It implements `Manifold2d`
+//! itself and can replace the manifold in solvers while debugging.
+
+use math::LinearView;
+use sparse::{DiagonalMatrix, SparseMatrix};
+use super::manifold::{Hodge0, Hodge1, Hodge2, Manifold2d};
+
+/// Manifold validating the sizes of all simplex arguments.
+#[derive(Copy, Clone, Debug)]
+pub struct Checked<'a, M: 'a>(pub &'a M);
+
+impl<'a, M: 'a> Checked<'a, M> {
+    pub fn new(manifold: &'a M) -> Self {
+        Checked(manifold)
+    }
+
+    pub fn inner(&self) -> &'a M {
+        self.0
+    }
+}
+
+/// Panics if `simplex` doesn't have `expected` elements.
+fn check<L: LinearView>(operator: &str, argument: &str, simplex: &L, expected: usize) {
+    let found = simplex.view_linear().len();
+    assert!(found == expected,
+        "{}: `{}` has {} elements, the manifold expects {}", operator, argument, found, expected);
+}
+
+impl<'a, T, M> Hodge0<T> for Checked<'a, M>
+    where M: Manifold2d<T>, M::Simplex0: LinearView
+{
+    type Simplex0 = M::Simplex0;
+    fn apply(&self, dual: &mut Self::Simplex0, primal: &Self::Simplex0) {
+        check("hodge_0_primal", "dual", dual, self.0.num_elem_0());
+        check("hodge_0_primal", "primal", primal, self.0.num_elem_0());
+        <M as Hodge0<T>>::apply(self.0, dual, primal)
+    }
+
+    fn apply_inv(&self, primal: &mut Self::Simplex0, dual: &Self::Simplex0) {
+        check("hodge_2_dual", "primal", primal, self.0.num_elem_0());
+        check("hodge_2_dual", "dual", dual, self.0.num_elem_0());
+        <M as Hodge0<T>>::apply_inv(self.0, primal, dual)
+    }
+}
+
+impl<'a, T, M> Hodge1<T> for Checked<'a, M>
+    where M: Manifold2d<T>, M::Simplex1: LinearView
+{
+    type Simplex1 = M::Simplex1;
+    fn apply(&self, dual: &mut Self::Simplex1, primal: &Self::Simplex1) {
+        check("hodge_1_primal", "dual", dual, self.0.num_elem_1());
+        check("hodge_1_primal", "primal", primal, self.0.num_elem_1());
+        <M as Hodge1<T>>::apply(self.0, dual, primal)
+    }
+
+    fn apply_inv(&self, primal: &mut Self::Simplex1, dual: &Self::Simplex1) {
+        check("hodge_1_dual", "primal", primal, self.0.num_elem_1());
+        check("hodge_1_dual", "dual", dual, self.0.num_elem_1());
+        <M as Hodge1<T>>::apply_inv(self.0, primal, dual)
+    }
+}
+
+impl<'a, T, M> Hodge2<T> for Checked<'a, M>
+    where M: Manifold2d<T>, M::Simplex2: LinearView
+{
+    type Simplex2 = M::Simplex2;
+    fn apply(&self, dual: &mut Self::Simplex2, primal: &Self::Simplex2) {
+        check("hodge_2_primal", "dual", dual, self.0.num_elem_2());
+        check("hodge_2_primal", "primal", primal, self.0.num_elem_2());
+        <M as Hodge2<T>>::apply(self.0, dual, primal)
+    }
+
+    fn apply_inv(&self, primal: &mut Self::Simplex2, dual: &Self::Simplex2) {
+        check("hodge_0_dual", "primal", primal, self.0.num_elem_2());
+        check("hodge_0_dual", "dual", dual, self.0.num_elem_2());
+        <M as Hodge2<T>>::apply_inv(self.0, primal, dual)
+    }
+}
+
+impl<'a, T, M> Manifold2d<T> for Checked<'a, M>
+where
+    M: Manifold2d<T>,
+    M::Simplex0: LinearView,
+    M::Simplex1: LinearView,
+    M::Simplex2: LinearView,
+{
+    fn num_elem_0(&self) -> usize { self.0.num_elem_0() }
+    fn num_elem_1(&self) -> usize { self.0.num_elem_1() }
+    fn num_elem_2(&self) -> usize { self.0.num_elem_2() }
+
+    fn new_simplex_0(&self) -> Self::Simplex0 { self.0.new_simplex_0() }
+    fn new_simplex_1(&self) -> Self::Simplex1 { self.0.new_simplex_1() }
+    fn new_simplex_2(&self) -> Self::Simplex2 { self.0.new_simplex_2() }
+
+    fn boundary_0(&self) -> Vec<usize> { self.0.boundary_0() }
+    fn boundary_1(&self) -> Vec<usize> { self.0.boundary_1() }
+    fn boundary_2(&self) -> Vec<usize> { self.0.boundary_2() }
+
+    fn derivative_0_primal(&self, edges: &mut Self::Simplex1, vertices: &Self::Simplex0) {
+        check("derivative_0_primal", "edges", edges, self.0.num_elem_1());
+        check("derivative_0_primal", "vertices", vertices, self.0.num_elem_0());
+        self.0.derivative_0_primal(edges, vertices)
+    }
+
+    fn derivative_0_dual(&self, edges: &mut Self::Simplex1, faces: &Self::Simplex2) {
+        check("derivative_0_dual", "edges", edges, self.0.num_elem_1());
+        check("derivative_0_dual", "faces", faces, self.0.num_elem_2());
+        self.0.derivative_0_dual(edges, faces)
+    }
+
+    fn derivative_1_primal(&self, faces: &mut Self::Simplex2, edges: &Self::Simplex1) {
+        check("derivative_1_primal", "faces", faces, self.0.num_elem_2());
+        check("derivative_1_primal", "edges", edges, self.0.num_elem_1());
+        self.0.derivative_1_primal(faces, edges)
+    }
+
+    fn derivative_1_dual(&self, vertices: &mut Self::Simplex0, edges: &Self::Simplex1) {
+        check("derivative_1_dual", "vertices", vertices, self.0.num_elem_0());
+        check("derivative_1_dual", "edges", edges, self.0.num_elem_1());
+        self.0.derivative_1_dual(vertices, edges)
+    }
+
+    fn inner_product_1_dual(&self, a: &Self::Simplex1, b: &Self::Simplex1) -> T {
+        check("inner_product_1_dual", "a", a, self.0.num_elem_1());
+        check("inner_product_1_dual", "b", b, self.0.num_elem_1());
+        self.0.inner_product_1_dual(a, b)
+    }
+
+    fn wedge_01(&self, out: &mut Self::Simplex1, a: &Self::Simplex0, b: &Self::Simplex1) {
+        check("wedge_01", "out", out, self.0.num_elem_1());
+        check("wedge_01", "a", a, self.0.num_elem_0());
+        check("wedge_01", "b", b, self.0.num_elem_1());
+        self.0.wedge_01(out, a, b)
+    }
+
+    fn wedge_11(&self, out: &mut Self::Simplex2, a: &Self::Simplex1, b: &Self::Simplex1) {
+        check("wedge_11", "out", out, self.0.num_elem_2());
+        check("wedge_11", "a", a, self.0.num_elem_1());
+        check("wedge_11", "b", b, self.0.num_elem_1());
+        self.0.wedge_11(out, a, b)
+    }
+
+    fn wedge_02(&self, out: &mut Self::Simplex2, a: &Self::Simplex0, b: &Self::Simplex2) {
+        check("wedge_02", "out", out, self.0.num_elem_2());
+        check("wedge_02", "a", a, self.0.num_elem_0());
+        check("wedge_02", "b", b, self.0.num_elem_2());
+        self.0.wedge_02(out, a, b)
+    }
+
+    fn derivative_0_primal_matrix(&self) -> SparseMatrix<T> { self.0.derivative_0_primal_matrix() }
+    fn derivative_0_dual_matrix(&self) -> SparseMatrix<T> { self.0.derivative_0_dual_matrix() }
+    fn derivative_1_primal_matrix(&self) -> SparseMatrix<T> { self.0.derivative_1_primal_matrix() }
+    fn derivative_1_dual_matrix(&self) -> SparseMatrix<T> { self.0.derivative_1_dual_matrix() }
+
+    fn hodge_0_primal_matrix(&self) -> DiagonalMatrix<T> { self.0.hodge_0_primal_matrix() }
+    fn hodge_1_primal_matrix(&self) -> DiagonalMatrix<T> { self.0.hodge_1_primal_matrix() }
+    fn hodge_2_primal_matrix(&self) -> DiagonalMatrix<T> { self.0.hodge_2_primal_matrix() }
+
+    fn hodge_0_dual_matrix(&self) -> DiagonalMatrix<T> { self.0.hodge_0_dual_matrix() }
+    fn hodge_1_dual_matrix(&self) -> DiagonalMatrix<T> { self.0.hodge_1_dual_matrix() }
+    fn hodge_2_dual_matrix(&self) -> DiagonalMatrix<T> { self.0.hodge_2_dual_matrix() }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::Grid2d;
+    use ndarray::Array2;
+    use std::panic;
+    use super::*;
+
+    #[test]
+    fn mismatched_sizes() {
+        let grid = Grid2d::new((4, 6));
+        let checked = Checked::new(&grid);
+
+        // forwards to the wrapped grid
+        let primal = Array2::from_elem((4, 6), 1.0);
+        let mut dual = <Checked<Grid2d> as Manifold2d<f64>>::new_simplex_2(&checked);
+        checked.hodge_2_primal(&mut dual, &primal);
+        let mut expected = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_2_primal(&mut expected, &primal);
+        assert_eq!(dual, expected);
+
+        // faces of another grid
+        let result = panic::catch_unwind(|| {
+            let mut dual = Array2::<f64>::zeros((4, 6));
+            checked.hodge_2_primal(&mut dual, &Array2::zeros((6, 6)));
+        });
+        assert!(result.is_err());
+
+        let result = panic::catch_unwind(|| {
+            let mut edges = <Checked<Grid2d> as Manifold2d<f64>>::new_simplex_1(&checked);
+            checked.derivative_0_dual(&mut edges, &Array2::<f64>::zeros((5, 6)));
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Helmholtz-Hodge decomposition
+//!
+//! Splits a velocity field (dual 1-form) into a curl-free exact part `d̃ p`,
+//! a divergence-free coexact part `★d ψ` and a harmonic remainder which is
+//! both divergence- and curl-free.
+//!
+//! The scalar potential `p` is a dual 0-form (stored on the primal faces) with
+//! the boundary handling of the dual derivative of the manifold, the stream
+//! function `ψ` is a primal 0-form vanishing on the boundary vertices.
+//!
+//! The harmonic remainder also collects the solver residuals. With a
+//! `HarmonicBasis` of the manifold it can be split into the actual harmonic
+//! component and the error of the decomposition.
+
+use math::{LinearView, Real};
+use pcg;
+use sparse::Jacobi;
+use super::harmonic::HarmonicBasis;
+use super::manifold::{Boundary, Laplacian, Manifold2d};
+
+use std::mem;
+
+/// Restart length of the GMRES solver.
+const RESTART: usize = 32;
+
+pub struct HodgeDecomposition<T, M: Manifold2d<T>> {
+    /// Curl-free part `d̃ p`.
+    pub exact: M::Simplex1,
+    /// Divergence-free part `★d ψ`.
+    pub coexact: M::Simplex1,
+    /// Remainder, both divergence- and curl-free in the interior.
+    pub harmonic: M::Simplex1,
+    /// Scalar potential `p`.
+    pub potential: M::Simplex2,
+    /// Stream function `ψ`.
+    pub stream: M::Simplex0,
+}
+
+impl<T, M> HodgeDecomposition<T, M>
+where
+    T: Real,
+    M: Manifold2d<T>,
+    M::Simplex1: LinearView<Elem = T>,
+{
+    /// Restrict the harmonic part to its component in the span of `basis`.
+    ///
+    /// Returns the rest of the remainder, which isn't harmonic and measures the error of the decomposition.
+    pub fn split_harmonic(&mut self, manifold: &M, basis: &HarmonicBasis<T, M>) -> M::Simplex1 {
+        let mut harmonic = manifold.new_simplex_1();
+        basis.project(manifold, &mut harmonic, &self.harmonic);
+        self.harmonic.view_linear_mut().zip_mut_with(&harmonic.view_linear(), |r, &h| *r = *r - h);
+        mem::replace(&mut self.harmonic, harmonic)
+    }
+}
+
+/// Decompose a velocity field into exact, coexact and harmonic parts.
+///
+/// Both potentials are obtained by solving poisson equations assembled from the operator matrices.
+/// The flux over closed boundaries needs to be compatible (zero net flux).
+pub fn helmholtz_hodge_decompose<T, M>(
+    manifold: &M,
+    velocity: &M::Simplex1,
+    max_iterations: usize,
+    threshold: T,
+) -> HodgeDecomposition<T, M>
+where
+    T: Real,
+    M: Manifold2d<T>,
+    M::Simplex0: LinearView<Elem = T> + Clone,
+    M::Simplex1: LinearView<Elem = T>,
+    M::Simplex2: LinearView<Elem = T> + Clone,
+{
+    let m = manifold;
+
+    // exact: `d1 ★̃1 d̃0 ★2 q = d1 ★̃1 u` with `p = ★2 q`
+    let mut divergence = m.new_simplex_2();
+    {
+        let mut velocity_primal = m.new_simplex_1();
+        m.hodge_1_dual(&mut velocity_primal, velocity);
+        m.derivative_1_primal(&mut divergence, &velocity_primal);
+    }
+
+    let mut faces = m.new_simplex_2();
+    {
+        let laplacian = Laplacian::new(m, Boundary::Neumann).matrix_2();
+        pcg::precond_gmres(
+            &Jacobi::new(&laplacian).expect("laplacian has a nonzero diagonal"), &mut faces, &divergence,
+            RESTART, max_iterations, threshold,
+            &laplacian);
+    }
+
+    let mut potential = m.new_simplex_2();
+    let mut exact = m.new_simplex_1();
+    m.hodge_2_primal(&mut potential, &faces);
+    m.derivative_0_dual(&mut exact, &potential);
+
+    // coexact: `★̃2 d̃1 ★1 d0 ψ = ★̃2 d̃1 u` with `ψ = 0` on the boundary
+    let mut vorticity = m.new_simplex_0();
+    {
+        let mut circulation = m.new_simplex_0();
+        m.derivative_1_dual(&mut circulation, velocity);
+        m.hodge_2_dual(&mut vorticity, &circulation);
+
+        let mut vorticity = vorticity.view_linear_mut();
+        for i in m.boundary_0() {
+            vorticity[i] = T::zero();
+        }
+    }
+
+    let mut stream = m.new_simplex_0();
+    {
+        let laplacian = Laplacian::new(m, Boundary::Dirichlet).matrix_0();
+        pcg::precond_gmres(
+            &Jacobi::new(&laplacian).expect("laplacian has a nonzero diagonal"), &mut stream, &vorticity,
+            RESTART, max_iterations, threshold,
+            &laplacian);
+    }
+
+    let mut coexact = m.new_simplex_1();
+    {
+        let mut edges = m.new_simplex_1();
+        m.derivative_0_primal(&mut edges, &stream);
+        m.hodge_1_primal(&mut coexact, &edges);
+    }
+
+    let mut harmonic = m.new_simplex_1();
+    harmonic.view_linear_mut().assign(&velocity.view_linear());
+    harmonic.view_linear_mut().zip_mut_with(&exact.view_linear(), |h, &e| *h = *h - e);
+    harmonic.view_linear_mut().zip_mut_with(&coexact.view_linear(), |h, &c| *h = *h - c);
+
+    HodgeDecomposition {
+        exact,
+        coexact,
+        harmonic,
+        potential,
+        stream,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::PeriodicGrid2d;
+    use math::LinearViewReal;
+    use super::*;
+
+    #[test]
+    fn periodic_decomposition() {
+        let grid = PeriodicGrid2d::new((8, 8));
+        let new_edges = || <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+
+        let mut potential = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        let mut stream = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        for (i, p) in potential.iter_mut().enumerate() {
+            *p = (i as f64 * 0.37).sin();
+        }
+        for (i, s) in stream.iter_mut().enumerate() {
+            *s = (i as f64 * 0.53).cos();
+        }
+
+        let mut exact = new_edges();
+        grid.derivative_0_dual(&mut exact, &potential);
+
+        let mut coexact = new_edges();
+        {
+            let mut edges = new_edges();
+            grid.derivative_0_primal(&mut edges, &stream);
+            grid.hodge_1_primal(&mut coexact, &edges);
+        }
+
+        // constant flow on the torus is harmonic
+        let mut harmonic = new_edges();
+        harmonic.split_mut().0.fill(0.5);
+        harmonic.split_mut().1.fill(-1.5);
+
+        let mut velocity = new_edges();
+        for (i, v) in velocity.view_linear_mut().iter_mut().enumerate() {
+            *v = exact.view_linear()[i] + coexact.view_linear()[i] + harmonic.view_linear()[i];
+        }
+
+        let mut decomposition = helmholtz_hodge_decompose(&grid, &velocity, 400, 1.0e-10);
+        let basis = HarmonicBasis::compute(&grid, 3, 400, 1.0e-10);
+        let residual = decomposition.split_harmonic(&grid, &basis);
+        assert!(residual.norm_max() < 1.0e-6);
+        for &(result, reference) in &[
+            (&decomposition.exact, &exact),
+            (&decomposition.coexact, &coexact),
+            (&decomposition.harmonic, &harmonic),
+        ] {
+            let mut error = new_edges();
+            error.view_linear_mut().assign(&result.view_linear());
+            error.view_linear_mut().zip_mut_with(&reference.view_linear(), |e, &r| *e -= r);
+            assert!(error.norm_max() < 1.0e-6);
+        }
+    }
+}
@@ -1,12 +1,13 @@
 
-use math::LinearView;
-use ndarray::{Array, ArrayView, ArrayViewMut, Ix1, Ix2, LinalgScalar, Zip};
-use sparse::{DiagonalMatrix, SparseMatrix};
+use math::{simd, LinearView};
+use num::NumCast;
+use ndarray::{Array, ArrayView, ArrayViewMut, Axis, Ix1, Ix2, Ix3, LinalgScalar, RemoveAxis, Zip};
+use sparse::{DiagonalMatrix, SparseMatrix, TripletMatrix};
 use std::ops::Neg;
-use domain::Grid2d;
-use super::manifold::{Hodge0, Hodge1, Hodge2, Manifold2d};
+use domain::{AxisBoundary, BoundaryCondition, Grid2d, Grid3d};
+use super::manifold::{Hodge0, Hodge1, Hodge2, Hodge3, Manifold2d, Manifold3d};
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Staggered2d<T> {
     data: Array<T, Ix1>,
     dim: (usize, usize), // (y, x)
@@ -17,20 +18,28 @@ impl<T> Staggered2d<T> {
         self.dim
     }
 
+    /// Shapes of the (vertical, horizontal) components.
+    pub fn shape(&self) -> [(usize, usize); 2] {
+        let (h, w) = self.dim;
+        [(h + 1, w), (h, w + 1)]
+    }
+
     /// (vertical, horizontal)
     pub fn split(&self) -> (ArrayView<T, Ix2>, ArrayView<T, Ix2>) {
-        let size_0 = self.dim.1 * (self.dim.0 + 1);
+        let shape = self.shape();
+        let (vertical, horizontal) = self.data.view().split_at(Axis(0), shape[0].0 * shape[0].1);
 
-        (unsafe { ArrayView::<T, Ix2>::from_shape_ptr((self.dim.0 + 1, self.dim.1), self.data.as_ptr()) },
-         unsafe { ArrayView::<T, Ix2>::from_shape_ptr((self.dim.0, self.dim.1 + 1), self.data.as_ptr().offset(size_0 as isize)) })
+        (vertical.into_shape(shape[0]).unwrap(),
+         horizontal.into_shape(shape[1]).unwrap())
     }
 
     /// (vertical, horizontal)
     pub fn split_mut(&mut self) -> (ArrayViewMut<T, Ix2>, ArrayViewMut<T, Ix2>) {
-        let size_0 = self.dim.1 * (self.dim.0 + 1);
+        let shape = self.shape();
+        let (vertical, horizontal) = self.data.view_mut().split_at(Axis(0), shape[0].0 * shape[0].1);
 
-        (unsafe { ArrayViewMut::<T, Ix2>::from_shape_ptr((self.dim.0 + 1, self.dim.1), self.data.as_mut_ptr()) },
-         unsafe { ArrayViewMut::<T, Ix2>::from_shape_ptr((self.dim.0, self.dim.1 + 1), self.data.as_mut_ptr().offset(size_0 as isize)) })
+        (vertical.into_shape(shape[0]).unwrap(),
+         horizontal.into_shape(shape[1]).unwrap())
     }
 }
 
@@ -45,150 +54,169 @@ impl<T> LinearView for Staggered2d<T> {
     }
 }
 
+/// Apply the boundary conditions of the grid to the normal velocity components at the boundary.
+///
+/// Walls are closed for flux, periodic boundary edges are set to their mean value.
+/// Open and inflow boundaries are left untouched.
+pub fn enforce_boundary_2d<T: LinalgScalar>(grid: &Grid2d, velocity: &mut Staggered2d<T>) {
+    let [by, bx] = grid.boundary();
+    let (vertical, horizontal) = velocity.split_mut();
+    enforce_normal(by, Axis(0), vertical);
+    enforce_normal(bx, Axis(1), horizontal);
+}
+
+/// Normal velocity components on the boundary edges of one axis, see `enforce_boundary_2d`.
+fn enforce_normal<T, D>(boundary: AxisBoundary, axis: Axis, normal: ArrayViewMut<T, D>)
+    where T: LinalgScalar, D: RemoveAxis
+{
+    let n = normal.len_of(axis) - 1;
+    let (mut lower, mut upper) = normal.split_at(axis, n);
+    let (mut lower, mut upper) = (lower.subview_mut(axis, 0), upper.subview_mut(axis, 0));
+
+    if boundary.is_periodic() {
+        let half = T::one() / (T::one() + T::one());
+        Zip::from(lower).and(upper).apply(|lower, upper| {
+            let mean = (*lower + *upper) * half;
+            *lower = mean;
+            *upper = mean;
+        });
+        return;
+    }
+
+    if boundary.lower.is_wall() { lower.fill(T::zero()); }
+    if boundary.upper.is_wall() { upper.fill(T::zero()); }
+}
+
+/// Average cell values to the adjacent edges, e.g. densities for the velocity edges.
+///
+/// Boundary edges take the value of their cell, periodic boundary edges the mean of both sides.
+pub fn average_to_edges_2d<T: LinalgScalar>(grid: &Grid2d, cells: &Array<T, Ix2>) -> Staggered2d<T> {
+    let (h, w) = grid.dim();
+    assert_eq!(cells.dim(), (h, w));
+    let [by, bx] = grid.boundary();
+    let mut edges = Staggered2d {
+        data: Array::zeros((h + 1) * w + h * (w + 1)),
+        dim: (h, w),
+    };
+
+    {
+        let (mut vertical, mut horizontal) = edges.split_mut();
+        for ((j, i), e) in vertical.indexed_iter_mut() {
+            *e = edge_average(by, j, h, |j| cells[(j, i)]);
+        }
+        for ((j, i), e) in horizontal.indexed_iter_mut() {
+            *e = edge_average(bx, i, w, |i| cells[(j, i)]);
+        }
+    }
+    edges
+}
+
+/// Mean of the cells `index - 1` and `index` along an axis with `len` cells.
+fn edge_average<T, F>(boundary: AxisBoundary, index: usize, len: usize, cell: F) -> T
+    where T: LinalgScalar, F: Fn(usize) -> T
+{
+    let half = T::one() / (T::one() + T::one());
+    if index > 0 && index < len {
+        (cell(index - 1) + cell(index)) * half
+    } else if boundary.is_periodic() {
+        (cell(len - 1) + cell(0)) * half
+    } else {
+        cell(if index == 0 { 0 } else { len - 1 })
+    }
+}
+
+/// Cell spacing `(dy, dx)` of the grid.
+fn spacing_2d<T: NumCast>(grid: &Grid2d) -> (T, T) {
+    let (dy, dx) = grid.spacing();
+    (T::from(dy).unwrap(), T::from(dx).unwrap())
+}
+
+/// `dst = factor * src`, vectorized for contiguous arrays.
+fn scale_2d<T>(mut dst: ArrayViewMut<T, Ix2>, src: ArrayView<T, Ix2>, factor: T)
+    where T: LinalgScalar + Send + Sync
+{
+    if let (Some(dst), Some(src)) = (dst.as_slice_mut(), src.as_slice()) {
+        simd::par_scale(dst, src, factor);
+        return;
+    }
+    par_azip!(mut dst, src in { *dst = src * factor; });
+}
+
+/// Sum of the element-wise products of two arrays.
+fn dot_2d<T: LinalgScalar>(a: ArrayView<T, Ix2>, b: ArrayView<T, Ix2>) -> T {
+    a.iter().zip(b.iter()).fold(T::zero(), |sum, (&a, &b)| sum + a * b)
+}
+
+/// Area of the dual cell of a vertex, cut off at non-periodic grid boundaries.
+fn dual_area_2d<T: LinalgScalar + NumCast>(grid: &Grid2d, (j, i): (usize, usize)) -> T {
+    let (h, w) = grid.dim();
+    let [by, bx] = grid.boundary();
+    let (dy, dx) = spacing_2d::<T>(grid);
+    let half = T::one() / (T::one() + T::one());
+    let mut area = dy * dx;
+    if (j == 0 || j == h) && !by.is_periodic() { area = area * half; }
+    if (i == 0 || i == w) && !bx.is_periodic() { area = area * half; }
+    area
+}
+
 impl<T> Hodge0<T> for Grid2d
-where T: LinalgScalar + Neg<Output = T> + Send + Sync
+where T: LinalgScalar + NumCast + Neg<Output = T> + Send + Sync
 {
     type Simplex0 = Array<T, Ix2>;
     fn apply(&self, dual: &mut Self::Simplex0, primal: &Self::Simplex0) {
-        let two = T::one() + T::one();
-        let four = two + two;
-        let (h, w) = self.dim();
-
-        // corners
-        dual[(0, 0)]     = primal[(0, 0)] / four;
-        dual[(0, w-1)]   = primal[(0, w-1)] / four;
-        dual[(h-1, 0)]   = primal[(h-1, 0)] / four;
-        dual[(h-1, w-1)] = primal[(h-1, w-1)] / four;
-
-        // sides
-        Zip::from(dual.slice_mut(s![..1, 1..-1]))
-            .and(primal.slice(s![..1, 1..-1]))
-            .apply(|dual, &primal| {
-                *dual = primal / two;
-            });
-
-        Zip::from(dual.slice_mut(s![-1.., 1..-1]))
-            .and(primal.slice(s![-1.., 1..-1]))
-            .apply(|dual, &primal| {
-                *dual = primal / two;
-            });
-
-        Zip::from(dual.slice_mut(s![1..-1, ..1]))
-            .and(primal.slice(s![1..-1, ..1]))
-            .apply(|dual, &primal| {
-                *dual = primal / two;
-            });
-
-        Zip::from(dual.slice_mut(s![1..-1, -1..]))
-            .and(primal.slice(s![1..-1, -1..]))
-            .apply(|dual, &primal| {
-                *dual = primal / two;
-            });
-
-        // inner
-        Zip::from(dual.slice_mut(s![1..-1, 1..-1]))
-            .and(primal.slice(s![1..-1, 1..-1]))
-            .apply(|dual, &primal| {
-                *dual = primal;
-            });
+        par_azip!(
+            index idx,
+            mut dual (dual),
+            primal (primal)
+         in { *dual = primal * dual_area_2d::<T>(self, idx); });
     }
     fn apply_inv(&self, primal: &mut Self::Simplex0, dual: &Self::Simplex0) {
-        let two = T::one() + T::one();
-        let four = two + two;
-        let (h, w) = self.dim();
-
-        // corners
-        primal[(0, 0)]     = dual[(0, 0)] * four;
-        primal[(0, w-1)]   = dual[(0, w-1)] * four;
-        primal[(h-1, 0)]   = dual[(h-1, 0)] * four;
-        primal[(h-1, w-1)] = dual[(h-1, w-1)] * four;
-
-        // sides
-        Zip::from(primal.slice_mut(s![..1, 1..-1]))
-            .and(dual.slice(s![..1, 1..-1]))
-            .apply(|primal, &dual| {
-                *primal = dual * two;
-            });
-
-        Zip::from(primal.slice_mut(s![-1.., 1..-1]))
-            .and(dual.slice(s![-1.., 1..-1]))
-            .apply(|primal, &dual| {
-                *primal = dual * two;
-            });
-
-        Zip::from(primal.slice_mut(s![1..-1, ..1]))
-            .and(dual.slice(s![1..-1, ..1]))
-            .apply(|primal, &dual| {
-                *primal = dual * two;
-            });
-
-        Zip::from(primal.slice_mut(s![1..-1, -1..]))
-            .and(dual.slice(s![1..-1, -1..]))
-            .apply(|primal, &dual| {
-                *primal = dual * two;
-            });
-
-        // inner
-        Zip::from(primal.slice_mut(s![1..-1, 1..-1]))
-            .and(dual.slice(s![1..-1, 1..-1]))
-            .apply(|primal, &dual| {
-                *primal = dual;
-            });
+        par_azip!(
+            index idx,
+            mut primal (primal),
+            dual (dual)
+         in { *primal = dual / dual_area_2d::<T>(self, idx); });
     }
 }
 
 impl<T> Hodge1<T> for Grid2d
-where T: LinalgScalar + Neg<Output = T> + Send + Sync
+where T: LinalgScalar + NumCast + Neg<Output = T> + Send + Sync
 {
     type Simplex1 = Staggered2d<T>;
     fn apply(&self, dual: &mut Self::Simplex1, primal: &Self::Simplex1) {
+        let (dy, dx) = spacing_2d::<T>(self);
         let primal = primal.split();
-        let mut dual = dual.split_mut();
-
-        Zip::from(&mut dual.0)
-            .and(&primal.0)
-            .apply(|dual, &primal| {
-                *dual = primal;
-            });
-
-        Zip::from(&mut dual.1)
-            .and(&primal.1)
-            .apply(|dual, &primal| {
-                *dual = -primal;
-            });
+        let dual = dual.split_mut();
+        scale_2d(dual.0, primal.0, dy / dx);
+        scale_2d(dual.1, primal.1, -dx / dy);
     }
     fn apply_inv(&self, primal: &mut Self::Simplex1, dual: &Self::Simplex1) {
+        let (dy, dx) = spacing_2d::<T>(self);
         let dual = dual.split();
-        let mut primal = primal.split_mut();
-
-        Zip::from(&mut primal.0)
-            .and(&dual.0)
-            .apply(|primal, &dual| {
-                *primal = -dual;
-            });
-
-        Zip::from(&mut primal.1)
-            .and(&dual.1)
-            .apply(|primal, &dual| {
-                *primal = dual;
-            });
+        let primal = primal.split_mut();
+        scale_2d(primal.0, dual.0, -dx / dy);
+        scale_2d(primal.1, dual.1, dy / dx);
     }
 }
 
 impl<T> Hodge2<T> for Grid2d
-where T: LinalgScalar
+where T: LinalgScalar + NumCast + Send + Sync
 {
     type Simplex2 = Array<T, Ix2>;
     fn apply(&self, dual: &mut Self::Simplex2, primal: &Self::Simplex2) {
-        dual.assign(primal);
+        let (dy, dx) = spacing_2d::<T>(self);
+        let area = dy * dx;
+        scale_2d(dual.view_mut(), primal.view(), T::one() / area);
     }
     fn apply_inv(&self, primal: &mut Self::Simplex2, dual: &Self::Simplex2) {
-        primal.assign(dual);
+        let (dy, dx) = spacing_2d::<T>(self);
+        let area = dy * dx;
+        scale_2d(primal.view_mut(), dual.view(), area);
     }
 }
 
 impl<T> Manifold2d<T> for Grid2d
-    where T: LinalgScalar + Neg<Output = T> + Send + Sync
+    where T: LinalgScalar + NumCast + Neg<Output = T> + Send + Sync
 {
     fn num_elem_0(&self) -> usize {
         (self.dim().0 + 1) * (self.dim().1 + 1)
@@ -217,6 +245,52 @@ impl<T> Manifold2d<T> for Grid2d
         Array::from_elem((self.dim().0, self.dim().1), T::zero()) // faces
     }
 
+    fn boundary_0(&self) -> Vec<usize> {
+        let (h, w) = self.dim();
+        let mut boundary = Vec::new();
+        for y in 0..(h+1) {
+            for x in 0..(w+1) {
+                if y == 0 || y == h || x == 0 || x == w {
+                    boundary.push(y*(w+1) + x);
+                }
+            }
+        }
+        boundary
+    }
+
+    fn boundary_1(&self) -> Vec<usize> {
+        let (h, w) = self.dim();
+        let offset = (h+1) * w;
+        let mut boundary = Vec::new();
+
+        // vertical
+        for x in 0..w {
+            boundary.push(x);
+            boundary.push(h*w + x);
+        }
+
+        // horizontal
+        for y in 0..h {
+            boundary.push(offset + y*(w+1));
+            boundary.push(offset + y*(w+1) + w);
+        }
+
+        boundary
+    }
+
+    fn boundary_2(&self) -> Vec<usize> {
+        let (h, w) = self.dim();
+        let mut boundary = Vec::new();
+        for y in 0..h {
+            for x in 0..w {
+                if y == 0 || y == h-1 || x == 0 || x == w-1 {
+                    boundary.push(y*w + x);
+                }
+            }
+        }
+        boundary
+    }
+
     fn derivative_0_primal(&self, edges: &mut Self::Simplex1, vertices: &Self::Simplex0) {
         let mut edges = edges.split_mut();
 
@@ -250,6 +324,9 @@ impl<T> Manifold2d<T> for Grid2d
             f1 (faces.slice(s![.., 1..]))
          in { *edge = f0 - f1; });
 
+        let [by, bx] = self.boundary();
+        derivative_dual_boundary(by, Axis(0), edges.0, faces.view());
+        derivative_dual_boundary(bx, Axis(1), edges.1, faces.view());
     }
 
     fn derivative_1_primal(&self, faces: &mut Self::Simplex2, edges: &Self::Simplex1) {
@@ -264,80 +341,681 @@ impl<T> Manifold2d<T> for Grid2d
          in { *face = -bottom + top - left + right; });
     }
 
-    fn derivative_1_dual(&self, faces: &mut Self::Simplex0, edges: &Self::Simplex1) {
-        unimplemented!()
+    fn derivative_1_dual(&self, vertices: &mut Self::Simplex0, edges: &Self::Simplex1) {
+        let edges = edges.split();
+        vertices.fill(T::zero());
+
+        // vertical
+        par_azip!(
+            mut v (vertices.slice_mut(s![.., ..-1])),
+            edge (&edges.0)
+         in { *v = *v - edge; });
+        par_azip!(
+            mut v (vertices.slice_mut(s![.., 1..])),
+            edge (&edges.0)
+         in { *v = *v + edge; });
+
+        // horizontal
+        par_azip!(
+            mut v (vertices.slice_mut(s![..-1, ..])),
+            edge (&edges.1)
+         in { *v = *v + edge; });
+        par_azip!(
+            mut v (vertices.slice_mut(s![1.., ..])),
+            edge (&edges.1)
+         in { *v = *v - edge; });
+    }
+
+    fn inner_product_1_dual(&self, a: &Self::Simplex1, b: &Self::Simplex1) -> T {
+        let (dy, dx) = spacing_2d::<T>(self);
+        let (a, b) = (a.split(), b.split());
+        dot_2d(a.0, b.0) * (dx / dy) + dot_2d(a.1, b.1) * (dy / dx)
+    }
+
+    fn wedge_01(&self, out: &mut Self::Simplex1, a: &Self::Simplex0, b: &Self::Simplex1) {
+        let half = T::one() / (T::one() + T::one());
+        let b = b.split();
+        let mut out = out.split_mut();
+
+        par_azip!(
+            mut out (&mut out.0),
+            b (&b.0),
+            a0 (a.slice(s![.., ..-1])),
+            a1 (a.slice(s![.., 1..]))
+         in { *out = (a0 + a1) * half * b; });
+
+        par_azip!(
+            mut out (&mut out.1),
+            b (&b.1),
+            a0 (a.slice(s![..-1, ..])),
+            a1 (a.slice(s![1.., ..]))
+         in { *out = (a0 + a1) * half * b; });
+    }
+
+    /// Cubical cup product, averaged over the four corners of each face.
+    fn wedge_11(&self, out: &mut Self::Simplex2, a: &Self::Simplex1, b: &Self::Simplex1) {
+        let two = T::one() + T::one();
+        let quarter = T::one() / (two * two);
+        let (a_x, a_y) = a.split();
+        let (b_x, b_y) = b.split();
+
+        par_azip!(index (y, x), mut out (out) in {
+            let a_x = a_x[(y, x)] + a_x[(y+1, x)];
+            let a_y = a_y[(y, x)] + a_y[(y, x+1)];
+            let b_x = b_x[(y, x)] + b_x[(y+1, x)];
+            let b_y = b_y[(y, x)] + b_y[(y, x+1)];
+            *out = (a_x * b_y - a_y * b_x) * quarter;
+        });
+    }
+
+    fn wedge_02(&self, out: &mut Self::Simplex2, a: &Self::Simplex0, b: &Self::Simplex2) {
+        let two = T::one() + T::one();
+        let quarter = T::one() / (two * two);
+
+        par_azip!(
+            mut out (out),
+            b (b),
+            a00 (a.slice(s![..-1, ..-1])),
+            a01 (a.slice(s![..-1, 1..])),
+            a10 (a.slice(s![1.., ..-1])),
+            a11 (a.slice(s![1.., 1..]))
+         in { *out = (a00 + a01 + a10 + a11) * quarter * b; });
     }
 
     fn derivative_0_primal_matrix(&self) -> SparseMatrix<T> {
-        unimplemented!()
-        /*
-        let dim = (self.num_elem_1(), self.num_elem_0());
-        let mut matrix = SparseMatrix::<T>::new(dim);
+        let dim = (Manifold2d::<T>::num_elem_1(self), Manifold2d::<T>::num_elem_0(self));
+        let mut matrix = TripletMatrix::with_capacity(dim, 2 * dim.0);
 
         let (h, w) = self.dim();
         let one = T::one();
         let mut idx = 0;
 
-        // horizontal edges
+        // vertical
         for y in 0..(h+1) {
             for x in 0..w {
                 let v_idx = y*(w+1) + x;
-                matrix.insert((idx, v_idx), -one);
-                matrix.insert((idx, v_idx + 1), one);
+                matrix.push((idx, v_idx), -one);
+                matrix.push((idx, v_idx + 1), one);
                 idx += 1;
             }
         }
 
-        // vertical edges
+        // horizontal
         for y in 0..h {
             for x in 0..(w+1) {
                 let v_idx = y*(w+1) + x;
-                matrix.insert((idx, v_idx), -one);
-                matrix.insert((idx, v_idx + w + 1), one);
+                matrix.push((idx, v_idx), -one);
+                matrix.push((idx, v_idx + w + 1), one);
                 idx += 1;
             }
         }
 
-        matrix
-        */
+        matrix.to_sparse()
     }
 
     fn derivative_0_dual_matrix(&self) -> SparseMatrix<T> {
-        unimplemented!()
+        let dim = (Manifold2d::<T>::num_elem_1(self), Manifold2d::<T>::num_elem_2(self));
+        let mut matrix = TripletMatrix::with_capacity(dim, 2 * dim.0);
+
+        let (h, w) = self.dim();
+        let one = T::one();
+        let offset = (h+1) * w;
+
+        // vertical, boundary edges are only set for open or periodic boundaries
+        for y in 1..h {
+            for x in 0..w {
+                let idx = y*w + x;
+                let f_idx = (y-1)*w + x;
+                matrix.push((idx, f_idx), one);
+                matrix.push((idx, f_idx + w), -one);
+            }
+        }
+
+        // horizontal
+        for y in 0..h {
+            for x in 1..w {
+                let idx = offset + y*(w+1) + x;
+                let f_idx = y*w + x - 1;
+                matrix.push((idx, f_idx), one);
+                matrix.push((idx, f_idx + 1), -one);
+            }
+        }
+
+        let [by, bx] = self.boundary();
+        for x in 0..w {
+            boundary_matrix_entries(&mut matrix, by, (x, h*w + x), (x, (h-1)*w + x));
+        }
+        for y in 0..h {
+            boundary_matrix_entries(&mut matrix, bx, (offset + y*(w+1), offset + y*(w+1) + w), (y*w, y*w + w - 1));
+        }
+
+        matrix.to_sparse()
     }
 
     fn derivative_1_primal_matrix(&self) -> SparseMatrix<T> {
-        unimplemented!()
+        let dim = (Manifold2d::<T>::num_elem_2(self), Manifold2d::<T>::num_elem_1(self));
+        let mut matrix = TripletMatrix::with_capacity(dim, 4 * dim.0);
+
+        let (h, w) = self.dim();
+        let one = T::one();
+        let offset = (h+1) * w;
+
+        for y in 0..h {
+            for x in 0..w {
+                let idx = y*w + x;
+                let top = y*w + x;
+                let left = offset + y*(w+1) + x;
+                matrix.push((idx, top), one);
+                matrix.push((idx, top + w), -one);
+                matrix.push((idx, left), -one);
+                matrix.push((idx, left + 1), one);
+            }
+        }
+
+        matrix.to_sparse()
     }
+
     fn derivative_1_dual_matrix(&self) -> SparseMatrix<T> {
-        unimplemented!()
+        let dim = (Manifold2d::<T>::num_elem_0(self), Manifold2d::<T>::num_elem_1(self));
+        let mut matrix = TripletMatrix::with_capacity(dim, 4 * dim.0);
+
+        let (h, w) = self.dim();
+        let one = T::one();
+        let offset = (h+1) * w;
+
+        for y in 0..(h+1) {
+            for x in 0..(w+1) {
+                let idx = y*(w+1) + x;
+
+                // vertical
+                if x > 0 { matrix.push((idx, y*w + x - 1), one); }
+                if x < w { matrix.push((idx, y*w + x), -one); }
+
+                // horizontal
+                if y > 0 { matrix.push((idx, offset + (y-1)*(w+1) + x), -one); }
+                if y < h { matrix.push((idx, offset + y*(w+1) + x), one); }
+            }
+        }
+
+        matrix.to_sparse()
     }
 
     fn hodge_0_primal_matrix(&self) -> DiagonalMatrix<T> {
-        unimplemented!()
+        hodge_vertex_matrix(self, |area| area)
     }
     fn hodge_1_primal_matrix(&self) -> DiagonalMatrix<T> {
-        unimplemented!()
+        let (dy, dx) = spacing_2d::<T>(self);
+        hodge_edge_matrix(self.dim(), dy / dx, -(dx / dy))
     }
     fn hodge_2_primal_matrix(&self) -> DiagonalMatrix<T> {
-        unimplemented!()
+        let (dy, dx) = spacing_2d::<T>(self);
+        hodge_face_matrix(self.dim(), T::one() / (dy * dx))
     }
 
     fn hodge_0_dual_matrix(&self) -> DiagonalMatrix<T> {
-        unimplemented!()
+        let (dy, dx) = spacing_2d::<T>(self);
+        hodge_face_matrix(self.dim(), dy * dx)
     }
     fn hodge_1_dual_matrix(&self) -> DiagonalMatrix<T> {
-        unimplemented!()
+        let (dy, dx) = spacing_2d::<T>(self);
+        hodge_edge_matrix(self.dim(), -(dx / dy), dy / dx)
     }
     fn hodge_2_dual_matrix(&self) -> DiagonalMatrix<T> {
-        unimplemented!()
+        hodge_vertex_matrix(self, |area| T::one() / area)
+    }
+}
+
+/// Diagonal hodge matrix for vertices, weighted by a function of the dual cell area.
+fn hodge_vertex_matrix<T, F>(grid: &Grid2d, weight: F) -> DiagonalMatrix<T>
+    where T: LinalgScalar + NumCast, F: Fn(T) -> T
+{
+    let (h, w) = grid.dim();
+    let mut matrix = DiagonalMatrix::new((h+1) * (w+1));
+    for y in 0..(h+1) {
+        for x in 0..(w+1) {
+            matrix[y*(w+1) + x] = weight(dual_area_2d(grid, (y, x)));
+        }
+    }
+    matrix
+}
+
+/// Dual derivative on the boundary edges (faces in 3d) of one axis.
+///
+/// Open boundaries use zero ghost values outside of the domain, periodic
+/// boundaries wrap around. Boundary edges of closed sides carry no flux and are zero.
+fn derivative_dual_boundary<T, D>(boundary: AxisBoundary, axis: Axis, edges: ArrayViewMut<T, D>, cells: ArrayView<T, D>)
+    where T: LinalgScalar + Neg<Output = T> + Send + Sync, D: RemoveAxis
+{
+    let n = cells.len_of(axis);
+    let (first, last) = (cells.subview(axis, 0), cells.subview(axis, n-1));
+    let (mut lower, mut upper) = edges.split_at(axis, n);
+    let (mut lower, mut upper) = (lower.subview_mut(axis, 0), upper.subview_mut(axis, 0));
+
+    if boundary.is_periodic() {
+        par_azip!(mut lower (lower), mut upper (upper), first (first), last (last) in {
+            *lower = last - first;
+            *upper = last - first;
+        });
+        return;
+    }
+
+    if boundary.lower == BoundaryCondition::Dirichlet {
+        par_azip!(mut edge (lower), first (first) in { *edge = -first; });
+    } else {
+        lower.fill(T::zero());
     }
+    if boundary.upper == BoundaryCondition::Dirichlet {
+        par_azip!(mut edge (upper), last (last) in { *edge = last; });
+    } else {
+        upper.fill(T::zero());
+    }
+}
+
+/// Entries of the dual derivative matrix for a pair of `(lower, upper)` boundary edges
+/// and the adjacent `(first, last)` faces, see `derivative_dual_boundary`.
+fn boundary_matrix_entries<T>(matrix: &mut TripletMatrix<T>, boundary: AxisBoundary, (lower, upper): (usize, usize), (first, last): (usize, usize))
+    where T: LinalgScalar + Neg<Output = T>
+{
+    let one = T::one();
+    if boundary.is_periodic() {
+        for &edge in &[lower, upper] {
+            matrix.push((edge, last), one);
+            matrix.push((edge, first), -one);
+        }
+        return;
+    }
+
+    if boundary.lower == BoundaryCondition::Dirichlet {
+        matrix.push((lower, first), -one);
+    }
+    if boundary.upper == BoundaryCondition::Dirichlet {
+        matrix.push((upper, last), one);
+    }
+}
+
+/// Diagonal hodge matrix for edges with separate factors for vertical and horizontal edges.
+fn hodge_edge_matrix<T: LinalgScalar>((h, w): (usize, usize), vertical: T, horizontal: T) -> DiagonalMatrix<T> {
+    let num_vertical = (h+1) * w;
+    let num_horizontal = h * (w+1);
+    let mut matrix = DiagonalMatrix::new(num_vertical + num_horizontal);
+    for i in 0..num_vertical {
+        matrix[i] = vertical;
+    }
+    for i in num_vertical..(num_vertical + num_horizontal) {
+        matrix[i] = horizontal;
+    }
+    matrix
+}
+
+fn hodge_face_matrix<T: LinalgScalar>((h, w): (usize, usize), scale: T) -> DiagonalMatrix<T> {
+    let mut matrix = DiagonalMatrix::new(h * w);
+    for i in 0..(h * w) {
+        matrix[i] = scale;
+    }
+    matrix
+}
+
+/// Staggered storage for edges or faces of a 3d grid.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Staggered3d<T> {
+    data: Array<T, Ix1>,
+    dim: (usize, usize, usize), // (z, y, x)
+    shape: [(usize, usize, usize); 3], // (z, y, x)
+}
+
+impl<T> Staggered3d<T> {
+    /// Create a new edge storage, split into (z, y, x) directed edges.
+    pub fn edges(dim: (usize, usize, usize), elem: T) -> Self
+        where T: Clone
+    {
+        Self::from_elem(dim, edge_shape_3d(dim), elem)
+    }
+
+    /// Create a new face storage, split into faces with (z, y, x) normals.
+    pub fn faces(dim: (usize, usize, usize), elem: T) -> Self
+        where T: Clone
+    {
+        Self::from_elem(dim, face_shape_3d(dim), elem)
+    }
+
+    fn from_elem(dim: (usize, usize, usize), shape: [(usize, usize, usize); 3], elem: T) -> Self
+        where T: Clone
+    {
+        let len = shape.iter().fold(0, |len, &(d, h, w)| len + d * h * w);
+        Staggered3d {
+            data: Array::from_elem(len, elem),
+            dim: dim,
+            shape: shape,
+        }
+    }
+
+    pub fn dim(&self) -> (usize, usize, usize) {
+        self.dim
+    }
+
+    /// Shapes of the (z, y, x) components.
+    pub fn shape(&self) -> [(usize, usize, usize); 3] {
+        self.shape
+    }
+
+    /// (z, y, x)
+    pub fn split(&self) -> (ArrayView<T, Ix3>, ArrayView<T, Ix3>, ArrayView<T, Ix3>) {
+        let (size_z, size_y) = (len_3d(self.shape[0]), len_3d(self.shape[1]));
+        let (z, rest) = self.data.view().split_at(Axis(0), size_z);
+        let (y, x) = rest.split_at(Axis(0), size_y);
+
+        (z.into_shape(self.shape[0]).unwrap(),
+         y.into_shape(self.shape[1]).unwrap(),
+         x.into_shape(self.shape[2]).unwrap())
+    }
+
+    /// (z, y, x)
+    pub fn split_mut(&mut self) -> (ArrayViewMut<T, Ix3>, ArrayViewMut<T, Ix3>, ArrayViewMut<T, Ix3>) {
+        let (size_z, size_y) = (len_3d(self.shape[0]), len_3d(self.shape[1]));
+        let (z, rest) = self.data.view_mut().split_at(Axis(0), size_z);
+        let (y, x) = rest.split_at(Axis(0), size_y);
+
+        (z.into_shape(self.shape[0]).unwrap(),
+         y.into_shape(self.shape[1]).unwrap(),
+         x.into_shape(self.shape[2]).unwrap())
+    }
+}
+
+impl<T> LinearView for Staggered3d<T> {
+    type Elem = T;
+    fn view_linear(&self) -> ArrayView<T, Ix1> {
+        self.data.view()
+    }
+
+    fn view_linear_mut(&mut self) -> ArrayViewMut<T, Ix1> {
+        self.data.view_mut()
+    }
+}
+
+fn len_3d((d, h, w): (usize, usize, usize)) -> usize {
+    d * h * w
+}
+
+/// Shapes of the (z, y, x) directed edges of a 3d grid.
+fn edge_shape_3d((d, h, w): (usize, usize, usize)) -> [(usize, usize, usize); 3] {
+    [(d, h+1, w+1), (d+1, h, w+1), (d+1, h+1, w)]
+}
+
+/// Shapes of the faces of a 3d grid with (z, y, x) normals.
+fn face_shape_3d((d, h, w): (usize, usize, usize)) -> [(usize, usize, usize); 3] {
+    [(d+1, h, w), (d, h+1, w), (d, h, w+1)]
+}
+
+/// Volume of the dual cell of a vertex, cut off at non-periodic grid boundaries.
+fn dual_volume_3d<T: LinalgScalar>(grid: &Grid3d, (k, j, i): (usize, usize, usize)) -> T {
+    let (d, h, w) = grid.dim();
+    let [bz, by, bx] = grid.boundary();
+    let half = T::one() / (T::one() + T::one());
+    let mut volume = T::one();
+    if (k == 0 || k == d) && !bz.is_periodic() { volume = volume * half; }
+    if (j == 0 || j == h) && !by.is_periodic() { volume = volume * half; }
+    if (i == 0 || i == w) && !bx.is_periodic() { volume = volume * half; }
+    volume
+}
+
+/// Apply the boundary conditions of the grid to the face velocities, see `enforce_boundary_2d`.
+pub fn enforce_boundary_3d<T: LinalgScalar>(grid: &Grid3d, velocity: &mut Staggered3d<T>) {
+    let [bz, by, bx] = grid.boundary();
+    let (vz, vy, vx) = velocity.split_mut();
+    enforce_normal(bz, Axis(0), vz);
+    enforce_normal(by, Axis(1), vy);
+    enforce_normal(bx, Axis(2), vx);
+}
+
+impl<T> Hodge0<T> for Grid3d
+where T: LinalgScalar + Send + Sync
+{
+    type Simplex0 = Array<T, Ix3>;
+    fn apply(&self, dual: &mut Self::Simplex0, primal: &Self::Simplex0) {
+        par_azip!(
+            index idx,
+            mut dual (dual),
+            primal (primal)
+         in { *dual = primal * dual_volume_3d::<T>(self, idx); });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex0, dual: &Self::Simplex0) {
+        par_azip!(
+            index idx,
+            mut primal (primal),
+            dual (dual)
+         in { *primal = dual / dual_volume_3d::<T>(self, idx); });
+    }
+}
+
+impl<T> Hodge1<T> for Grid3d
+where T: LinalgScalar + Send + Sync
+{
+    type Simplex1 = Staggered3d<T>;
+    fn apply(&self, dual: &mut Self::Simplex1, primal: &Self::Simplex1) {
+        par_azip!(mut dual (&mut dual.data), primal (&primal.data) in { *dual = primal; });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex1, dual: &Self::Simplex1) {
+        par_azip!(mut primal (&mut primal.data), dual (&dual.data) in { *primal = dual; });
+    }
+}
+
+impl<T> Hodge2<T> for Grid3d
+where T: LinalgScalar + Send + Sync
+{
+    type Simplex2 = Staggered3d<T>;
+    fn apply(&self, dual: &mut Self::Simplex2, primal: &Self::Simplex2) {
+        par_azip!(mut dual (&mut dual.data), primal (&primal.data) in { *dual = primal; });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex2, dual: &Self::Simplex2) {
+        par_azip!(mut primal (&mut primal.data), dual (&dual.data) in { *primal = dual; });
+    }
+}
+
+impl<T> Hodge3<T> for Grid3d
+where T: LinalgScalar + Send + Sync
+{
+    type Simplex3 = Array<T, Ix3>;
+    fn apply(&self, dual: &mut Self::Simplex3, primal: &Self::Simplex3) {
+        par_azip!(mut dual (dual), primal (primal) in { *dual = primal; });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex3, dual: &Self::Simplex3) {
+        par_azip!(mut primal (primal), dual (dual) in { *primal = dual; });
+    }
+}
+
+impl<T> Manifold3d<T> for Grid3d
+    where T: LinalgScalar + Neg<Output = T> + Send + Sync
+{
+    fn num_elem_0(&self) -> usize {
+        let (d, h, w) = self.dim();
+        (d + 1) * (h + 1) * (w + 1)
+    }
+
+    fn num_elem_1(&self) -> usize {
+        edge_shape_3d(self.dim()).iter().fold(0, |len, &shape| len + len_3d(shape))
+    }
+
+    fn num_elem_2(&self) -> usize {
+        face_shape_3d(self.dim()).iter().fold(0, |len, &shape| len + len_3d(shape))
+    }
+
+    fn num_elem_3(&self) -> usize {
+        len_3d(self.dim())
+    }
+
+    fn new_simplex_0(&self) -> Self::Simplex0 {
+        let (d, h, w) = self.dim();
+        Array::from_elem((d + 1, h + 1, w + 1), T::zero()) // vertices
+    }
+
+    fn new_simplex_1(&self) -> Self::Simplex1 {
+        Staggered3d::edges(self.dim(), T::zero())
+    }
+
+    fn new_simplex_2(&self) -> Self::Simplex2 {
+        Staggered3d::faces(self.dim(), T::zero())
+    }
+
+    fn new_simplex_3(&self) -> Self::Simplex3 {
+        Array::from_elem(self.dim(), T::zero()) // cells
+    }
+
+    fn derivative_0_primal(&self, edges: &mut Self::Simplex1, vertices: &Self::Simplex0) {
+        let (mut ez, mut ey, mut ex) = edges.split_mut();
+
+        par_azip!(
+            mut edge (&mut ez),
+            v0 (vertices.slice(s![..-1, .., ..])),
+            v1 (vertices.slice(s![1.., .., ..]))
+         in { *edge = v1 - v0; });
+
+        par_azip!(
+            mut edge (&mut ey),
+            v0 (vertices.slice(s![.., ..-1, ..])),
+            v1 (vertices.slice(s![.., 1.., ..]))
+         in { *edge = v1 - v0; });
+
+        par_azip!(
+            mut edge (&mut ex),
+            v0 (vertices.slice(s![.., .., ..-1])),
+            v1 (vertices.slice(s![.., .., 1..]))
+         in { *edge = v1 - v0; });
+    }
+
+    fn derivative_0_dual(&self, faces: &mut Self::Simplex2, cells: &Self::Simplex3) {
+        let (mut fz, mut fy, mut fx) = faces.split_mut();
+
+        // boundary faces are only set for open or periodic boundaries
+        par_azip!(
+            mut face (fz.slice_mut(s![1..-1, .., ..])),
+            c0 (cells.slice(s![..-1, .., ..])),
+            c1 (cells.slice(s![1.., .., ..]))
+         in { *face = c0 - c1; });
+
+        par_azip!(
+            mut face (fy.slice_mut(s![.., 1..-1, ..])),
+            c0 (cells.slice(s![.., ..-1, ..])),
+            c1 (cells.slice(s![.., 1.., ..]))
+         in { *face = c0 - c1; });
+
+        par_azip!(
+            mut face (fx.slice_mut(s![.., .., 1..-1])),
+            c0 (cells.slice(s![.., .., ..-1])),
+            c1 (cells.slice(s![.., .., 1..]))
+         in { *face = c0 - c1; });
+
+        let [bz, by, bx] = self.boundary();
+        derivative_dual_boundary(bz, Axis(0), fz, cells.view());
+        derivative_dual_boundary(by, Axis(1), fy, cells.view());
+        derivative_dual_boundary(bx, Axis(2), fx, cells.view());
+    }
+
+    fn derivative_1_primal(&self, faces: &mut Self::Simplex2, edges: &Self::Simplex1) {
+        let (ez, ey, ex) = edges.split();
+        let (mut fz, mut fy, mut fx) = faces.split_mut();
+
+        // circulation x -> y
+        par_azip!(
+            mut face (&mut fz),
+            x0 (ex.slice(s![.., ..-1, ..])),
+            x1 (ex.slice(s![.., 1.., ..])),
+            y0 (ey.slice(s![.., .., ..-1])),
+            y1 (ey.slice(s![.., .., 1..]))
+         in { *face = x0 + y1 - x1 - y0; });
+
+        // circulation z -> x
+        par_azip!(
+            mut face (&mut fy),
+            z0 (ez.slice(s![.., .., ..-1])),
+            z1 (ez.slice(s![.., .., 1..])),
+            x0 (ex.slice(s![..-1, .., ..])),
+            x1 (ex.slice(s![1.., .., ..]))
+         in { *face = z0 + x1 - z1 - x0; });
+
+        // circulation y -> z
+        par_azip!(
+            mut face (&mut fx),
+            y0 (ey.slice(s![..-1, .., ..])),
+            y1 (ey.slice(s![1.., .., ..])),
+            z0 (ez.slice(s![.., ..-1, ..])),
+            z1 (ez.slice(s![.., 1.., ..]))
+         in { *face = y0 + z1 - y1 - z0; });
+    }
+
+    fn derivative_1_dual(&self, edges: &mut Self::Simplex1, faces: &Self::Simplex2) {
+        let (fz, fy, fx) = faces.split();
+        let (mut ez, mut ey, mut ex) = edges.split_mut();
+
+        ez.fill(T::zero());
+        ey.fill(T::zero());
+        ex.fill(T::zero());
+
+        // transposed primal circulation
+        add_assign_3d(ex.slice_mut(s![.., ..-1, ..]), fz.view());
+        sub_assign_3d(ex.slice_mut(s![.., 1.., ..]), fz.view());
+        add_assign_3d(ey.slice_mut(s![.., .., 1..]), fz.view());
+        sub_assign_3d(ey.slice_mut(s![.., .., ..-1]), fz.view());
+
+        add_assign_3d(ez.slice_mut(s![.., .., ..-1]), fy.view());
+        sub_assign_3d(ez.slice_mut(s![.., .., 1..]), fy.view());
+        add_assign_3d(ex.slice_mut(s![1.., .., ..]), fy.view());
+        sub_assign_3d(ex.slice_mut(s![..-1, .., ..]), fy.view());
+
+        add_assign_3d(ey.slice_mut(s![..-1, .., ..]), fx.view());
+        sub_assign_3d(ey.slice_mut(s![1.., .., ..]), fx.view());
+        add_assign_3d(ez.slice_mut(s![.., 1.., ..]), fx.view());
+        sub_assign_3d(ez.slice_mut(s![.., ..-1, ..]), fx.view());
+    }
+
+    fn derivative_2_primal(&self, cells: &mut Self::Simplex3, faces: &Self::Simplex2) {
+        let (fz, fy, fx) = faces.split();
+
+        par_azip!(
+            mut cell (cells.view_mut()),
+            z0 (fz.slice(s![..-1, .., ..])),
+            z1 (fz.slice(s![1.., .., ..])),
+            y0 (fy.slice(s![.., ..-1, ..])),
+            y1 (fy.slice(s![.., 1.., ..]))
+         in { *cell = z1 - z0 + y1 - y0; });
+
+        par_azip!(
+            mut cell (cells.view_mut()),
+            x0 (fx.slice(s![.., .., ..-1])),
+            x1 (fx.slice(s![.., .., 1..]))
+         in { *cell = *cell + x1 - x0; });
+    }
+
+    fn derivative_2_dual(&self, vertices: &mut Self::Simplex0, edges: &Self::Simplex1) {
+        let (ez, ey, ex) = edges.split();
+        vertices.fill(T::zero());
+
+        // transposed primal difference
+        sub_assign_3d(vertices.slice_mut(s![..-1, .., ..]), ez.view());
+        add_assign_3d(vertices.slice_mut(s![1.., .., ..]), ez.view());
+        sub_assign_3d(vertices.slice_mut(s![.., ..-1, ..]), ey.view());
+        add_assign_3d(vertices.slice_mut(s![.., 1.., ..]), ey.view());
+        sub_assign_3d(vertices.slice_mut(s![.., .., ..-1]), ex.view());
+        add_assign_3d(vertices.slice_mut(s![.., .., 1..]), ex.view());
+    }
+}
+
+fn add_assign_3d<T>(mut dst: ArrayViewMut<T, Ix3>, src: ArrayView<T, Ix3>)
+    where T: LinalgScalar + Send + Sync
+{
+    par_azip!(mut dst, src in { *dst = *dst + src; });
+}
+
+fn sub_assign_3d<T>(mut dst: ArrayViewMut<T, Ix3>, src: ArrayView<T, Ix3>)
+    where T: LinalgScalar + Send + Sync
+{
+    par_azip!(mut dst, src in { *dst = *dst - src; });
 }
 
 #[cfg(test)]
 mod tests {
     use ndarray::*;
     use super::*;
+    use super::super::manifold::{Boundary, Laplacian};
 
     #[test]
     fn grid_2d_divergence() {
@@ -428,6 +1106,68 @@ mod tests {
         assert!(equal, "{:#?} approx eq {:#?} (eps = {:#?})", &laplacian, &laplac, eps);
     }
 
+    #[test]
+    fn grid_2d_laplacian_operator() {
+        let grid = Grid2d::new((3, 3));
+
+        let faces = arr2(&[
+            [-0.0, -3.0, -0.0],
+            [-0.0, 2.0, 6.0],
+            [1.0, -0.0, -0.0],
+        ]);
+
+        let mut laplacian = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        Laplacian::new(&grid, Boundary::Neumann).apply_2(&mut laplacian, &faces);
+
+        let laplacian_ref = arr2(&[[3.0, -11.0, -3.0], [-3.0, 5.0, 16.0], [2.0, -3.0, -6.0]]);
+        assert_approx_linear(&laplacian, &laplacian_ref);
+
+        // dirichlet boundary rows are replaced by the identity
+        Laplacian::new(&grid, Boundary::Dirichlet).apply_2(&mut laplacian, &faces);
+        let laplacian_ref = arr2(&[[-0.0, -3.0, -0.0], [-0.0, 5.0, 6.0], [1.0, -0.0, -0.0]]);
+        assert_approx_linear(&laplacian, &laplacian_ref);
+
+        // constant functions are in the kernel
+        let mut vertices = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        let mut laplacian = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        vertices.fill(1.0);
+        Laplacian::new(&grid, Boundary::Neumann).apply_0(&mut laplacian, &vertices);
+        assert_approx_linear(&laplacian, &<Grid2d as Manifold2d<f64>>::new_simplex_0(&grid));
+    }
+
+    #[test]
+    fn grid_2d_laplacian_matrix() {
+        let grid = Grid2d::new((4, 5));
+
+        for &boundary in &[Boundary::Neumann, Boundary::Dirichlet] {
+            let laplacian = Laplacian::new(&grid, boundary);
+
+            let mut vertices = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+            let mut operator = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+            let mut assembled = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+            fill_linear(&mut vertices);
+            laplacian.apply_0(&mut operator, &vertices);
+            laplacian.matrix_0().mul_vec(assembled.view_linear_mut(), vertices.view_linear());
+            assert_approx_linear(&assembled, &operator);
+
+            let mut edges = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+            let mut operator = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+            let mut assembled = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+            fill_linear(&mut edges);
+            laplacian.apply_1(&mut operator, &edges);
+            laplacian.matrix_1().mul_vec(assembled.view_linear_mut(), edges.view_linear());
+            assert_approx_linear(&assembled, &operator);
+
+            let mut faces = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+            let mut operator = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+            let mut assembled = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+            fill_linear(&mut faces);
+            laplacian.apply_2(&mut operator, &faces);
+            laplacian.matrix_2().mul_vec(assembled.view_linear_mut(), faces.view_linear());
+            assert_approx_linear(&assembled, &operator);
+        }
+    }
+
     #[test]
     fn grid_2d_gradient() {
         let grid = Grid2d::new((3, 3));
@@ -446,4 +1186,289 @@ mod tests {
         let grad = ArrayView::from_shape((3, 3), &gradient_ref).unwrap();
         let eps = 1.0e-3;
     }
+
+    fn fill_linear<L: LinearView<Elem = f64>>(simplex: &mut L) {
+        for (i, v) in simplex.view_linear_mut().iter_mut().enumerate() {
+            *v = (i as f64 * 0.37).sin() + 0.5;
+        }
+    }
+
+    fn assert_approx_linear<L: LinearView<Elem = f64>>(val: &L, reference: &L) {
+        let eps = 1.0e-6;
+        let (val, reference) = (val.view_linear(), reference.view_linear());
+        assert_eq!(val.len(), reference.len());
+        for (&v, &r) in val.iter().zip(reference.iter()) {
+            assert!((v - r).abs() < eps, "{:#?} approx eq {:#?} (eps = {:#?})", &val, &reference, eps);
+        }
+    }
+
+    #[test]
+    fn grid_2d_derivative_matrix() {
+        let grid = Grid2d::new((3, 4));
+
+        let mut vertices = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        let mut edges = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut faces = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        fill_linear(&mut vertices);
+        fill_linear(&mut edges);
+        fill_linear(&mut faces);
+
+        // d0 primal
+        let mut reference = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut result = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        grid.derivative_0_primal(&mut reference, &vertices);
+        <Grid2d as Manifold2d<f64>>::derivative_0_primal_matrix(&grid)
+            .mul_vec(result.view_linear_mut(), vertices.view_linear());
+        assert_approx_linear(&result, &reference);
+
+        // d0 dual
+        let mut reference = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut result = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        grid.derivative_0_dual(&mut reference, &faces);
+        <Grid2d as Manifold2d<f64>>::derivative_0_dual_matrix(&grid)
+            .mul_vec(result.view_linear_mut(), faces.view_linear());
+        assert_approx_linear(&result, &reference);
+
+        // d1 primal
+        let mut reference = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        let mut result = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.derivative_1_primal(&mut reference, &edges);
+        <Grid2d as Manifold2d<f64>>::derivative_1_primal_matrix(&grid)
+            .mul_vec(result.view_linear_mut(), edges.view_linear());
+        assert_approx_linear(&result, &reference);
+
+        // d1 dual
+        let mut reference = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        let mut result = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        grid.derivative_1_dual(&mut reference, &edges);
+        <Grid2d as Manifold2d<f64>>::derivative_1_dual_matrix(&grid)
+            .mul_vec(result.view_linear_mut(), edges.view_linear());
+        assert_approx_linear(&result, &reference);
+
+        // d1 dual * d0 dual = 0 (inner vertices)
+        let mut dual_edges = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        grid.derivative_0_dual(&mut dual_edges, &faces);
+        grid.derivative_1_dual(&mut result, &dual_edges);
+        for &v in result.slice(s![1..-1, 1..-1]).iter() {
+            assert!(v.abs() < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn grid_2d_hodge_matrix() {
+        let grid = Grid2d::new((3, 4));
+
+        let mut vertices = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        let mut edges = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut faces = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        fill_linear(&mut vertices);
+        fill_linear(&mut edges);
+        fill_linear(&mut faces);
+
+        let mut ref_0 = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        let mut res_0 = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        let mut ref_1 = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut res_1 = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut ref_2 = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        let mut res_2 = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+
+        grid.hodge_0_primal(&mut ref_0, &vertices);
+        <Grid2d as Manifold2d<f64>>::hodge_0_primal_matrix(&grid)
+            .mul_vec(res_0.view_linear_mut(), vertices.view_linear());
+        assert_approx_linear(&res_0, &ref_0);
+
+        grid.hodge_2_dual(&mut ref_0, &vertices);
+        <Grid2d as Manifold2d<f64>>::hodge_2_dual_matrix(&grid)
+            .mul_vec(res_0.view_linear_mut(), vertices.view_linear());
+        assert_approx_linear(&res_0, &ref_0);
+
+        grid.hodge_1_primal(&mut ref_1, &edges);
+        <Grid2d as Manifold2d<f64>>::hodge_1_primal_matrix(&grid)
+            .mul_vec(res_1.view_linear_mut(), edges.view_linear());
+        assert_approx_linear(&res_1, &ref_1);
+
+        grid.hodge_1_dual(&mut ref_1, &edges);
+        <Grid2d as Manifold2d<f64>>::hodge_1_dual_matrix(&grid)
+            .mul_vec(res_1.view_linear_mut(), edges.view_linear());
+        assert_approx_linear(&res_1, &ref_1);
+
+        grid.hodge_2_primal(&mut ref_2, &faces);
+        <Grid2d as Manifold2d<f64>>::hodge_2_primal_matrix(&grid)
+            .mul_vec(res_2.view_linear_mut(), faces.view_linear());
+        assert_approx_linear(&res_2, &ref_2);
+
+        grid.hodge_0_dual(&mut ref_2, &faces);
+        <Grid2d as Manifold2d<f64>>::hodge_0_dual_matrix(&grid)
+            .mul_vec(res_2.view_linear_mut(), faces.view_linear());
+        assert_approx_linear(&res_2, &ref_2);
+    }
+
+    #[test]
+    fn grid_2d_wedge() {
+        let grid = Grid2d::new((4, 5));
+        let mut x = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        let mut y = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        for ((j, i), v) in x.indexed_iter_mut() {
+            *v = i as f64 + 0.1 * j as f64;
+        }
+        for ((j, _), v) in y.indexed_iter_mut() {
+            *v = j as f64;
+        }
+
+        let mut dx = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut dy = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        grid.derivative_0_primal(&mut dx, &x);
+        grid.derivative_0_primal(&mut dy, &y);
+
+        // (dx + 0.1 dy) ∧ dy = dA
+        let mut faces = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.wedge_11(&mut faces, &dx, &dy);
+        for &f in faces.iter() {
+            assert!((f - 1.0).abs() < 1.0e-10);
+        }
+
+        // i_X dy = X_y for a constant velocity
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (mut vy, mut vx) = velocity.split_mut();
+            vy.fill(0.5);
+            vx.fill(-2.0);
+        }
+        let mut interior = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.interior_1(&mut interior, &velocity, &dy);
+        for &v in interior.iter() {
+            assert!((v - 0.5).abs() < 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn grid_2d_spacing() {
+        let (dy, dx) = (0.5, 0.25);
+        let grid = Grid2d::new((6, 5)).with_spacing((dy, dx));
+
+        // dual cells cover the domain
+        let hodge = <Grid2d as Manifold2d<f64>>::hodge_0_primal_matrix(&grid);
+        let area = (0..hodge.dim()).fold(0.0, |sum, i| sum + hodge[i]);
+        assert!((area - 6.0 * dy * 5.0 * dx).abs() < 1.0e-10);
+
+        // laplacian of the 2-form of `y^2`, scaled by the cell area
+        let mut faces = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        for ((y, _), f) in faces.indexed_iter_mut() {
+            *f = ((y as f64 + 0.5) * dy).powi(2) * dy * dx;
+        }
+
+        let mut faces_dual = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        let mut edges_dual = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut edges_primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut laplacian = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_2_primal(&mut faces_dual, &faces);
+        grid.derivative_0_dual(&mut edges_dual, &faces_dual);
+        grid.hodge_1_dual(&mut edges_primal, &edges_dual);
+        grid.derivative_1_primal(&mut laplacian, &edges_primal);
+
+        for &l in laplacian.slice(s![1..-1, ..]).iter() {
+            assert!((l + 2.0 * dy * dx).abs() < 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn grid_3d_exact_sequence() {
+        let grid = Grid3d::new((3, 4, 5));
+
+        let mut vertices = <Grid3d as Manifold3d<f64>>::new_simplex_0(&grid);
+        let mut edges = <Grid3d as Manifold3d<f64>>::new_simplex_1(&grid);
+        let mut faces = <Grid3d as Manifold3d<f64>>::new_simplex_2(&grid);
+        let mut cells = <Grid3d as Manifold3d<f64>>::new_simplex_3(&grid);
+        fill_linear(&mut vertices);
+        fill_linear(&mut cells);
+
+        assert_eq!(edges.view_linear().len(), <Grid3d as Manifold3d<f64>>::num_elem_1(&grid));
+        assert_eq!(faces.view_linear().len(), <Grid3d as Manifold3d<f64>>::num_elem_2(&grid));
+
+        // d1 * d0 = 0
+        grid.derivative_0_primal(&mut edges, &vertices);
+        grid.derivative_1_primal(&mut faces, &edges);
+        for &v in faces.view_linear().iter() {
+            assert!(v.abs() < 1.0e-6);
+        }
+
+        // d2 * d1 = 0
+        fill_linear(&mut edges);
+        grid.derivative_1_primal(&mut faces, &edges);
+        grid.derivative_2_primal(&mut cells, &faces);
+        for &v in cells.iter() {
+            assert!(v.abs() < 1.0e-6);
+        }
+
+        // dual d1 * dual d0 = 0 (inner edges)
+        fill_linear(&mut cells);
+        grid.derivative_0_dual(&mut faces, &cells);
+        grid.derivative_1_dual(&mut edges, &faces);
+        let (ez, ey, ex) = edges.split();
+        for &v in ez.slice(s![.., 1..-1, 1..-1]).iter()
+            .chain(ey.slice(s![1..-1, .., 1..-1]).iter())
+            .chain(ex.slice(s![1..-1, 1..-1, ..]).iter())
+        {
+            assert!(v.abs() < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn staggered_2d_split() {
+        let grid = Grid2d::new((3, 4));
+        let mut edges = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        assert_eq!(edges.shape(), [(4, 4), (3, 5)]);
+
+        {
+            let (mut vertical, mut horizontal) = edges.split_mut();
+            assert_eq!(vertical.dim(), (4, 4));
+            assert_eq!(horizontal.dim(), (3, 5));
+
+            vertical[(3, 3)] = 1.0;
+            horizontal[(0, 0)] = 2.0;
+            horizontal[(2, 4)] = 3.0;
+        }
+
+        // vertical edges are followed by the horizontal ones, the views cover all values
+        let linear = edges.view_linear();
+        assert_eq!(linear.len(), 16 + 15);
+        assert_eq!(linear[15], 1.0);
+        assert_eq!(linear[16], 2.0);
+        assert_eq!(linear[30], 3.0);
+
+        let (vertical, horizontal) = edges.split();
+        assert_eq!((vertical[(3, 3)], horizontal[(0, 0)], horizontal[(2, 4)]), (1.0, 2.0, 3.0));
+        assert_eq!(vertical.len() + horizontal.len(), linear.len());
+
+        let clone = edges.clone();
+        assert_eq!(clone.split().1, horizontal);
+    }
+
+    #[test]
+    fn staggered_3d_split() {
+        let mut faces = Staggered3d::faces((2, 3, 4), 0.0);
+        assert_eq!(faces.shape(), [(3, 3, 4), (2, 4, 4), (2, 3, 5)]);
+
+        {
+            let (mut fz, mut fy, mut fx) = faces.split_mut();
+            assert_eq!(fz.dim(), (3, 3, 4));
+            assert_eq!(fy.dim(), (2, 4, 4));
+            assert_eq!(fx.dim(), (2, 3, 5));
+
+            fz[(2, 2, 3)] = 1.0;
+            fy[(0, 0, 0)] = 2.0;
+            fx[(1, 2, 4)] = 3.0;
+        }
+
+        // components are stored consecutively in (z, y, x) order
+        let linear = faces.view_linear();
+        assert_eq!(linear.len(), 36 + 32 + 30);
+        assert_eq!(linear[35], 1.0);
+        assert_eq!(linear[36], 2.0);
+        assert_eq!(linear[36 + 32 + 29], 3.0);
+
+        let edges = Staggered3d::edges((2, 3, 4), 0.0);
+        assert_eq!(edges.shape(), [(2, 4, 5), (3, 3, 5), (3, 4, 4)]);
+        assert_eq!(edges.view_linear().len(), 40 + 45 + 48);
+    }
 }
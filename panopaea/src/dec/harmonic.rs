@@ -0,0 +1,198 @@
+//! Harmonic 1-forms
+//!
+//! On manifolds with nontrivial topology (periodic grids, domains with holes)
+//! the Laplace–deRham operator on 1-forms has a kernel of harmonic forms,
+//! which are both divergence- and curl-free. Its dimension is the first Betti
+//! number, e.g. two for the torus: constant flows along both axes.
+//!
+//! The pressure projection only removes the exact part of a velocity field,
+//! the harmonic component (net flux through periodic boundaries, circulation
+//! around holes) isn't constrained and drifts with advection and diffusion
+//! errors. `HarmonicBasis` measures and prescribes this component.
+//!
+//! The basis is computed by removing the exact and coexact parts of random
+//! 1-forms with the Helmholtz-Hodge decomposition followed by Gram-Schmidt
+//! orthonormalization, which is the approach of [TLHD03] without an explicit
+//! homology basis.
+//!
+//! References:
+//!     [TLHD03] Yiying Tong, Santiago Lombeyda, Anil N. Hirani and Mathieu Desbrun, 2003,
+//!              Discrete multiscale vector field decomposition,
+//!              ACM Trans. Graph. 22, 3, 445-452
+
+use math::{LinearView, Real};
+use math::rng::{Pcg32, DEFAULT_SEED};
+use rand::Rng;
+use super::decomposition::helmholtz_hodge_decompose;
+use super::manifold::Manifold2d;
+
+/// Harmonic parts smaller than this fraction of their random form are solver residuals.
+const RANK_TOLERANCE: f64 = 1.0e-4;
+
+/// `L²` inner product `Σ a ★̃1 b` of two velocities (dual 1-forms).
+///
+/// See `Manifold2d::inner_product_1_dual`.
+pub fn inner_product<T, M>(manifold: &M, a: &M::Simplex1, b: &M::Simplex1) -> T
+where
+    M: Manifold2d<T>,
+{
+    manifold.inner_product_1_dual(a, b)
+}
+
+/// Orthonormal basis of the harmonic velocities (dual 1-forms) of a manifold.
+pub struct HarmonicBasis<T, M: Manifold2d<T>> {
+    forms: Vec<M::Simplex1>,
+}
+
+impl<T, M> HarmonicBasis<T, M>
+where
+    T: Real,
+    M: Manifold2d<T>,
+    M::Simplex0: LinearView<Elem = T> + Clone,
+    M::Simplex1: LinearView<Elem = T>,
+    M::Simplex2: LinearView<Elem = T> + Clone,
+{
+    /// Basis spanned by the harmonic parts of `samples` random forms.
+    ///
+    /// `samples` needs to exceed the expected dimension, a few more are cheap insurance.
+    /// The decompositions are solved to `threshold` within `max_iterations`.
+    pub fn compute(manifold: &M, samples: usize, max_iterations: usize, threshold: T) -> Self {
+        let mut rng = Pcg32::new(DEFAULT_SEED);
+        let boundary = manifold.boundary_1();
+        let mut basis = HarmonicBasis { forms: Vec::new() };
+
+        for _ in 0..samples {
+            let mut sample = manifold.new_simplex_1();
+            {
+                let mut sample = sample.view_linear_mut();
+                for v in sample.iter_mut() {
+                    *v = rng.gen::<T>() - T::new(0.5);
+                }
+                // boundary fluxes belong to the boundary conditions
+                for &i in &boundary {
+                    sample[i] = T::zero();
+                }
+            }
+
+            let scale = inner_product(manifold, &sample, &sample).sqrt();
+            let mut harmonic = helmholtz_hodge_decompose(manifold, &sample, max_iterations, threshold).harmonic;
+            // orthogonalize twice to keep the basis orthogonal in finite precision
+            for _ in 0..2 {
+                basis.remove(manifold, &mut harmonic);
+            }
+
+            let norm = inner_product(manifold, &harmonic, &harmonic).sqrt();
+            if norm > T::new(RANK_TOLERANCE) * scale {
+                harmonic.view_linear_mut().map_inplace(|v| *v = *v / norm);
+                basis.forms.push(harmonic);
+            }
+        }
+
+        basis
+    }
+}
+
+impl<T, M> HarmonicBasis<T, M>
+where
+    T: Real,
+    M: Manifold2d<T>,
+    M::Simplex1: LinearView<Elem = T>,
+{
+    /// Number of basis forms, the first Betti number of the manifold if enough samples were used.
+    pub fn dimension(&self) -> usize {
+        self.forms.len()
+    }
+
+    pub fn forms(&self) -> &[M::Simplex1] {
+        &self.forms
+    }
+
+    /// Coordinates of the harmonic component of a velocity.
+    pub fn coefficients(&self, manifold: &M, velocity: &M::Simplex1) -> Vec<T> {
+        self.forms.iter().map(|h| inner_product(manifold, velocity, h)).collect()
+    }
+
+    /// Harmonic component of a velocity.
+    pub fn project(&self, manifold: &M, harmonic: &mut M::Simplex1, velocity: &M::Simplex1) {
+        harmonic.view_linear_mut().fill(T::zero());
+        for (h, c) in self.forms.iter().zip(self.coefficients(manifold, velocity)) {
+            harmonic.view_linear_mut().scaled_add(c, &h.view_linear());
+        }
+    }
+
+    /// Remove the harmonic component of a velocity.
+    pub fn remove(&self, manifold: &M, velocity: &mut M::Simplex1) {
+        let zero = vec![T::zero(); self.dimension()];
+        self.set_coefficients(manifold, velocity, &zero);
+    }
+
+    /// Replace the harmonic component of a velocity by the given coordinates.
+    pub fn set_coefficients(&self, manifold: &M, velocity: &mut M::Simplex1, coefficients: &[T]) {
+        assert_eq!(coefficients.len(), self.dimension(), "coefficient count mismatch");
+        for (h, &c) in self.forms.iter().zip(coefficients.iter()) {
+            let current = inner_product(manifold, velocity, h);
+            velocity.view_linear_mut().scaled_add(c - current, &h.view_linear());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::vec3;
+    use domain::{Grid2d, PeriodicGrid2d, TriangleMesh};
+    use super::*;
+
+    #[test]
+    fn harmonic_basis() {
+        // torus: constant flows along both axes
+        let torus = PeriodicGrid2d::new((8, 6));
+        let basis = HarmonicBasis::<f64, _>::compute(&torus, 4, 400, 1.0e-10);
+        assert_eq!(basis.dimension(), 2);
+
+        for h in basis.forms() {
+            assert!((inner_product(&torus, h, h) - 1.0).abs() < 1.0e-8);
+            let (vertical, horizontal) = h.split();
+            for v in vertical.iter() {
+                assert!((v - vertical[(0, 0)]).abs() < 1.0e-6);
+            }
+            for u in horizontal.iter() {
+                assert!((u - horizontal[(0, 0)]).abs() < 1.0e-6);
+            }
+        }
+
+        let mut velocity = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_1(&torus);
+        for (i, v) in velocity.view_linear_mut().iter_mut().enumerate() {
+            *v = (i as f64 * 0.37).sin() + 0.25;
+        }
+        basis.set_coefficients(&torus, &mut velocity, &[1.0, -2.0]);
+        let coefficients = basis.coefficients(&torus, &velocity);
+        assert!((coefficients[0] - 1.0).abs() < 1.0e-10 && (coefficients[1] + 2.0).abs() < 1.0e-10);
+
+        // closed box is simply connected
+        let grid = Grid2d::new((6, 6));
+        assert_eq!(HarmonicBasis::<f64, _>::compute(&grid, 2, 400, 1.0e-10).dimension(), 0);
+    }
+
+    #[test]
+    fn mesh_inner_product() {
+        // obtuse angles opposite of the shared edge, its cotan weight is negative
+        let positions = vec![
+            vec3(0.0, 0.0, 0.0),
+            vec3(2.0, 0.0, 0.0),
+            vec3(1.0, 0.2, 0.0),
+            vec3(1.0, -0.2, 0.0),
+        ];
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2], [1, 0, 3]]);
+        assert!(mesh.edge_cotan_weights().iter().any(|&w| w < 0.0));
+
+        let mut a = mesh.new_simplex_1();
+        let mut b = mesh.new_simplex_1();
+        for (i, (a, b)) in a.iter_mut().zip(b.iter_mut()).enumerate() {
+            *a = (i as f64 * 0.7).sin();
+            *b = (i as f64 * 1.3).cos();
+        }
+        let mut flux = mesh.new_simplex_1();
+        mesh.hodge_1_dual(&mut flux, &b);
+        assert!((inner_product(&mesh, &a, &b) - a.dot(&flux)).abs() < 1.0e-10);
+    }
+}
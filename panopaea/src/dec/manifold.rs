@@ -1,6 +1,7 @@
 
 use math::LinearView;
-use sparse::{DiagonalMatrix, SparseMatrix};
+use ndarray::LinalgScalar;
+use sparse::{DiagonalMatrix, SparseMatrix, TripletMatrix};
 use std::marker::PhantomData;
 
 pub trait Manifold2d<T> : Hodge0<T> + Hodge1<T> + Hodge2<T> {
@@ -28,6 +29,13 @@ pub trait Manifold2d<T> : Hodge0<T> + Hodge1<T> + Hodge2<T> {
     ///
     fn new_simplex_2(&self) -> Self::Simplex2;
 
+    /// Linear indices of the 0-simplices on the boundary.
+    fn boundary_0(&self) -> Vec<usize>;
+    /// Linear indices of the 1-simplices on the boundary.
+    fn boundary_1(&self) -> Vec<usize>;
+    /// Linear indices of the 2-simplices adjacent to the boundary.
+    fn boundary_2(&self) -> Vec<usize>;
+
     /// Discrete exterior derivative operator for primal 0-forms.
     ///
     /// The operator maps primal 0-forms to primal 1-forms.
@@ -61,6 +69,39 @@ pub trait Manifold2d<T> : Hodge0<T> + Hodge1<T> + Hodge2<T> {
         Hodge2::apply_inv(self, primal, dual)
     }
 
+    /// `L²` inner product `Σ a ★̃1 b` of two velocities (dual 1-forms).
+    ///
+    /// The dual hodge star is the metric, without the rotation of the dual
+    /// edges on manifolds which orient them along the axes.
+    fn inner_product_1_dual(&self, a: &Self::Simplex1, b: &Self::Simplex1) -> T;
+
+    /// Discrete wedge product of a primal 0-form and a primal 1-form.
+    fn wedge_01(&self, &mut Self::Simplex1, &Self::Simplex0, &Self::Simplex1);
+    /// Discrete wedge product of two primal 1-forms.
+    fn wedge_11(&self, &mut Self::Simplex2, &Self::Simplex1, &Self::Simplex1);
+    /// Discrete wedge product of a primal 0-form and a primal 2-form.
+    fn wedge_02(&self, &mut Self::Simplex2, &Self::Simplex0, &Self::Simplex2);
+
+    /// Interior product `i_X β` of a primal 1-form with the vector field `X` of a velocity (dual 1-form).
+    ///
+    /// Evaluated as `★(β ∧ ★X♭)`, the result is a dual 0-form.
+    fn interior_1(&self, out: &mut Self::Simplex2, velocity: &Self::Simplex1, form: &Self::Simplex1) {
+        let mut flux = self.new_simplex_1();
+        let mut faces = self.new_simplex_2();
+        self.hodge_1_dual(&mut flux, velocity);
+        self.wedge_11(&mut faces, form, &flux);
+        self.hodge_2_primal(out, &faces);
+    }
+
+    /// Interior product `i_X (f dA)` of an area form with the vector field `X` of a velocity (dual 1-form).
+    ///
+    /// The density `f` is given as primal 0-form, the result is the primal 1-form `f ∧ ★X♭`.
+    fn interior_2(&self, out: &mut Self::Simplex1, velocity: &Self::Simplex1, density: &Self::Simplex0) {
+        let mut flux = self.new_simplex_1();
+        self.hodge_1_dual(&mut flux, velocity);
+        self.wedge_01(out, density, &flux);
+    }
+
     //
     fn derivative_0_primal_matrix(&self) -> SparseMatrix<T>;
     fn derivative_0_dual_matrix(&self) -> SparseMatrix<T>;
@@ -101,7 +142,249 @@ pub trait Hodge2<T> {
     fn apply_inv(&self, primal: &mut Self::Simplex2, dual: &Self::Simplex2);
 }
 
+pub trait Hodge3<T> {
+    /// Storage type for 3-simplices (cell).
+    /// A differential primal 3-form is stored for each 3-simplex.
+    type Simplex3;
+    fn apply(&self, dual: &mut Self::Simplex3, primal: &Self::Simplex3);
+    fn apply_inv(&self, primal: &mut Self::Simplex3, dual: &Self::Simplex3);
+}
+
+pub trait Manifold3d<T> : Hodge0<T> + Hodge1<T> + Hodge2<T> + Hodge3<T> {
+    ///
+    fn num_elem_0(&self) -> usize;
+    ///
+    fn num_elem_1(&self) -> usize;
+    ///
+    fn num_elem_2(&self) -> usize;
+    ///
+    fn num_elem_3(&self) -> usize;
+
+    ///
+    fn new_simplex_0(&self) -> Self::Simplex0;
+    ///
+    fn new_simplex_1(&self) -> Self::Simplex1;
+    ///
+    fn new_simplex_2(&self) -> Self::Simplex2;
+    ///
+    fn new_simplex_3(&self) -> Self::Simplex3;
+
+    /// Discrete exterior derivative operator for primal 0-forms.
+    ///
+    /// The operator maps primal 0-forms to primal 1-forms.
+    fn derivative_0_primal(&self, &mut Self::Simplex1, &Self::Simplex0);
+    fn derivative_0_dual(&self, &mut Self::Simplex2, &Self::Simplex3);
+
+    /// Discrete exterior derivative operator for primal 1-forms.
+    ///
+    /// The operator maps primal 1-forms to primal 2-forms.
+    fn derivative_1_primal(&self, &mut Self::Simplex2, &Self::Simplex1);
+    fn derivative_1_dual(&self, &mut Self::Simplex1, &Self::Simplex2);
+
+    /// Discrete exterior derivative operator for primal 2-forms.
+    ///
+    /// The operator maps primal 2-forms to primal 3-forms.
+    fn derivative_2_primal(&self, &mut Self::Simplex3, &Self::Simplex2);
+    fn derivative_2_dual(&self, &mut Self::Simplex0, &Self::Simplex1);
+
+    fn hodge_0_primal(&self, dual: &mut Self::Simplex0, primal: &Self::Simplex0) {
+        Hodge0::apply(self, dual, primal)
+    }
+    fn hodge_3_dual(&self, primal: &mut Self::Simplex0, dual: &Self::Simplex0) {
+        Hodge0::apply_inv(self, primal, dual)
+    }
+
+    fn hodge_1_primal(&self, dual: &mut Self::Simplex1, primal: &Self::Simplex1) {
+        Hodge1::apply(self, dual, primal)
+    }
+    fn hodge_2_dual(&self, primal: &mut Self::Simplex1, dual: &Self::Simplex1) {
+        Hodge1::apply_inv(self, primal, dual)
+    }
+
+    fn hodge_2_primal(&self, dual: &mut Self::Simplex2, primal: &Self::Simplex2) {
+        Hodge2::apply(self, dual, primal)
+    }
+    fn hodge_1_dual(&self, primal: &mut Self::Simplex2, dual: &Self::Simplex2) {
+        Hodge2::apply_inv(self, primal, dual)
+    }
+
+    fn hodge_3_primal(&self, dual: &mut Self::Simplex3, primal: &Self::Simplex3) {
+        Hodge3::apply(self, dual, primal)
+    }
+    fn hodge_0_dual(&self, primal: &mut Self::Simplex3, dual: &Self::Simplex3) {
+        Hodge3::apply_inv(self, primal, dual)
+    }
+}
+
+/// Boundary conditions of the laplacian.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// Values on the boundary simplices are fixed to zero.
+    Dirichlet,
+    /// No flux over the boundary.
+    Neumann,
+}
+
+/// Discrete Laplace-de Rham operator.
+///
+/// The operators are positive semi-definite (`-Δ` for smooth functions) and
+/// map primal k-forms to primal k-forms.
 pub struct Laplacian<'a, T, M: Manifold2d<T> + 'a> {
     pub manifold: &'a M,
+    pub boundary: Boundary,
     _marker: PhantomData<*const T>
 }
+
+impl<'a, T, M> Laplacian<'a, T, M>
+where
+    T: LinalgScalar,
+    M: Manifold2d<T> + 'a,
+    M::Simplex0: LinearView<Elem = T>,
+    M::Simplex1: LinearView<Elem = T>,
+    M::Simplex2: LinearView<Elem = T>,
+{
+    pub fn new(manifold: &'a M, boundary: Boundary) -> Self {
+        Laplacian {
+            manifold,
+            boundary,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Laplacian of primal 0-forms `★d★d`.
+    pub fn apply_0(&self, out: &mut M::Simplex0, input: &M::Simplex0) {
+        let m = self.manifold;
+        let mut edges_primal = m.new_simplex_1();
+        let mut edges_dual = m.new_simplex_1();
+        let mut vertices_dual = m.new_simplex_0();
+
+        m.derivative_0_primal(&mut edges_primal, input);
+        m.hodge_1_primal(&mut edges_dual, &edges_primal);
+        m.derivative_1_dual(&mut vertices_dual, &edges_dual);
+        m.hodge_2_dual(out, &vertices_dual);
+
+        if self.boundary == Boundary::Dirichlet {
+            fix_boundary(out, input, &m.boundary_0());
+        }
+    }
+
+    /// Laplacian of primal 1-forms `d★d★ + ★d★d`.
+    pub fn apply_1(&self, out: &mut M::Simplex1, input: &M::Simplex1) {
+        let m = self.manifold;
+        let mut edges_dual = m.new_simplex_1();
+        let mut vertices_dual = m.new_simplex_0();
+        let mut vertices_primal = m.new_simplex_0();
+        let mut faces_primal = m.new_simplex_2();
+        let mut faces_dual = m.new_simplex_2();
+
+        // d★d★
+        m.hodge_1_primal(&mut edges_dual, input);
+        m.derivative_1_dual(&mut vertices_dual, &edges_dual);
+        m.hodge_2_dual(&mut vertices_primal, &vertices_dual);
+        m.derivative_0_primal(out, &vertices_primal);
+
+        // ★d★d
+        m.derivative_1_primal(&mut faces_primal, input);
+        m.hodge_2_primal(&mut faces_dual, &faces_primal);
+        m.derivative_0_dual(&mut edges_dual, &faces_dual);
+        let mut curl = m.new_simplex_1();
+        m.hodge_1_dual(&mut curl, &edges_dual);
+
+        out.view_linear_mut().zip_mut_with(&curl.view_linear(), |out, &curl| *out = *out + curl);
+
+        if self.boundary == Boundary::Dirichlet {
+            fix_boundary(out, input, &m.boundary_1());
+        }
+    }
+
+    /// Laplacian of primal 2-forms `d★d★`.
+    pub fn apply_2(&self, out: &mut M::Simplex2, input: &M::Simplex2) {
+        let m = self.manifold;
+        let mut faces_dual = m.new_simplex_2();
+        let mut edges_dual = m.new_simplex_1();
+        let mut edges_primal = m.new_simplex_1();
+
+        m.hodge_2_primal(&mut faces_dual, input);
+        m.derivative_0_dual(&mut edges_dual, &faces_dual);
+        m.hodge_1_dual(&mut edges_primal, &edges_dual);
+        m.derivative_1_primal(out, &edges_primal);
+
+        if self.boundary == Boundary::Dirichlet {
+            fix_boundary(out, input, &m.boundary_2());
+        }
+    }
+
+    /// Assemble the laplacian of primal 0-forms, see `apply_0`.
+    pub fn matrix_0(&self) -> SparseMatrix<T> {
+        let m = self.manifold;
+        let flux = &m.hodge_1_primal_matrix() * &m.derivative_0_primal_matrix();
+        let laplacian = &m.hodge_2_dual_matrix() * &(&m.derivative_1_dual_matrix() * &flux);
+
+        match self.boundary {
+            Boundary::Dirichlet => fix_boundary_matrix(&laplacian, &m.boundary_0()),
+            Boundary::Neumann => laplacian,
+        }
+    }
+
+    /// Assemble the laplacian of primal 1-forms, see `apply_1`.
+    pub fn matrix_1(&self) -> SparseMatrix<T> {
+        let m = self.manifold;
+
+        // d★d★
+        let div = &(&m.hodge_2_dual_matrix() * &m.derivative_1_dual_matrix()) * &m.hodge_1_primal_matrix();
+        let grad_div = &m.derivative_0_primal_matrix() * &div;
+
+        // ★d★d
+        let curl = &m.derivative_0_dual_matrix() * &(&m.hodge_2_primal_matrix() * &m.derivative_1_primal_matrix());
+        let curl_curl = &m.hodge_1_dual_matrix() * &curl;
+
+        let laplacian = &grad_div + &curl_curl;
+        match self.boundary {
+            Boundary::Dirichlet => fix_boundary_matrix(&laplacian, &m.boundary_1()),
+            Boundary::Neumann => laplacian,
+        }
+    }
+
+    /// Assemble the laplacian of primal 2-forms, see `apply_2`.
+    pub fn matrix_2(&self) -> SparseMatrix<T> {
+        let m = self.manifold;
+        let gradient = &m.derivative_0_dual_matrix() * &m.hodge_2_primal_matrix();
+        let laplacian = &m.derivative_1_primal_matrix() * &(&m.hodge_1_dual_matrix() * &gradient);
+
+        match self.boundary {
+            Boundary::Dirichlet => fix_boundary_matrix(&laplacian, &m.boundary_2()),
+            Boundary::Neumann => laplacian,
+        }
+    }
+}
+
+/// Replace the rows of the boundary simplices by the identity.
+fn fix_boundary<T: Copy, L: LinearView<Elem = T>>(out: &mut L, input: &L, boundary: &[usize]) {
+    let mut out = out.view_linear_mut();
+    let input = input.view_linear();
+    for &i in boundary {
+        out[i] = input[i];
+    }
+}
+
+/// Replace the rows of the boundary simplices by identity rows.
+fn fix_boundary_matrix<T: LinalgScalar>(matrix: &SparseMatrix<T>, boundary: &[usize]) -> SparseMatrix<T> {
+    let (rows, cols) = matrix.dim();
+    let mut is_boundary = vec![false; rows];
+    for &i in boundary {
+        is_boundary[i] = true;
+    }
+
+    let mut fixed = TripletMatrix::new((rows, cols));
+    for i in 0..rows {
+        if is_boundary[i] {
+            fixed.push((i, i), T::one());
+        } else {
+            let (cols, values) = matrix.row(i);
+            for (&j, &value) in cols.iter().zip(values.iter()) {
+                fixed.push((i, j), value);
+            }
+        }
+    }
+    fixed.to_sparse()
+}
@@ -0,0 +1,445 @@
+//! DEC operators on triangle meshes
+//!
+//! Hodge stars are based on the circumcentric dual of the mesh. The dual
+//! volumes are only positive for Delaunay meshes without obtuse triangles,
+//! cotan weights of boundary edges vanish for right triangles and turn
+//! negative for obtuse ones [Hir03]. Inverse Hodge stars map values on
+//! degenerate dual volumes to zero instead of dividing by them.
+//!
+//! References:
+//!     [Hir03] Anil N. Hirani, 2003,
+//!             Discrete exterior calculus,
+//!             PhD thesis, California Institute of Technology
+
+use domain::TriangleMesh;
+use math::Real;
+use ndarray::{Array, ArrayView, Ix1};
+use sparse::{DiagonalMatrix, SparseMatrix, TripletMatrix};
+use super::manifold::{Hodge0, Hodge1, Hodge2, Manifold2d};
+
+/// Volumes below this fraction of the largest volume are treated as degenerate.
+const DEGENERATE_VOLUME: f64 = 1.0e-10;
+
+/// Degenerate volume threshold for a set of dual or primal volumes.
+fn degenerate_threshold<T: Real>(volumes: &[T]) -> T {
+    volumes.iter().fold(T::zero(), |max, &v| max.max(v.abs())) * T::new(DEGENERATE_VOLUME)
+}
+
+/// Inverse of a volume, zero for degenerate volumes.
+fn inverse<T: Real>(volume: T, threshold: T) -> T {
+    if volume.abs() > threshold { T::one() / volume } else { T::zero() }
+}
+
+impl<T: Real> Hodge0<T> for TriangleMesh<T> {
+    type Simplex0 = Array<T, Ix1>;
+    fn apply(&self, dual: &mut Self::Simplex0, primal: &Self::Simplex0) {
+        let area: ArrayView<T, Ix1> = self.vertex_dual_areas().into();
+        par_azip!(mut dual (dual), primal (primal), area in { *dual = primal * area; });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex0, dual: &Self::Simplex0) {
+        let threshold = degenerate_threshold(self.vertex_dual_areas());
+        let area: ArrayView<T, Ix1> = self.vertex_dual_areas().into();
+        par_azip!(mut primal (primal), dual (dual), area in { *primal = dual * inverse(area, threshold); });
+    }
+}
+
+impl<T: Real> Hodge1<T> for TriangleMesh<T> {
+    type Simplex1 = Array<T, Ix1>;
+    fn apply(&self, dual: &mut Self::Simplex1, primal: &Self::Simplex1) {
+        let weight: ArrayView<T, Ix1> = self.edge_cotan_weights().into();
+        par_azip!(mut dual (dual), primal (primal), weight in { *dual = primal * weight; });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex1, dual: &Self::Simplex1) {
+        let threshold = degenerate_threshold(self.edge_cotan_weights());
+        let weight: ArrayView<T, Ix1> = self.edge_cotan_weights().into();
+        par_azip!(mut primal (primal), dual (dual), weight in { *primal = dual * inverse(weight, threshold); });
+    }
+}
+
+impl<T: Real> Hodge2<T> for TriangleMesh<T> {
+    type Simplex2 = Array<T, Ix1>;
+    fn apply(&self, dual: &mut Self::Simplex2, primal: &Self::Simplex2) {
+        let threshold = degenerate_threshold(self.face_areas());
+        let area: ArrayView<T, Ix1> = self.face_areas().into();
+        par_azip!(mut dual (dual), primal (primal), area in { *dual = primal * inverse(area, threshold); });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex2, dual: &Self::Simplex2) {
+        let area: ArrayView<T, Ix1> = self.face_areas().into();
+        par_azip!(mut primal (primal), dual (dual), area in { *primal = dual * area; });
+    }
+}
+
+fn orientation<T: Real>(positive: bool) -> T {
+    if positive { T::one() } else { -T::one() }
+}
+
+impl<T: Real> Manifold2d<T> for TriangleMesh<T> {
+    fn num_elem_0(&self) -> usize {
+        self.num_vertices()
+    }
+
+    fn num_elem_1(&self) -> usize {
+        self.num_edges()
+    }
+
+    fn num_elem_2(&self) -> usize {
+        self.num_faces()
+    }
+
+    fn new_simplex_0(&self) -> Self::Simplex0 {
+        Array::from_elem(self.num_vertices(), T::zero())
+    }
+
+    fn new_simplex_1(&self) -> Self::Simplex1 {
+        Array::from_elem(self.num_edges(), T::zero())
+    }
+
+    fn new_simplex_2(&self) -> Self::Simplex2 {
+        Array::from_elem(self.num_faces(), T::zero())
+    }
+
+    fn boundary_0(&self) -> Vec<usize> {
+        let mut boundary = vec![false; self.num_vertices()];
+        for edge in self.boundary_1() {
+            let [v0, v1] = self.edges()[edge];
+            boundary[v0] = true;
+            boundary[v1] = true;
+        }
+        indices(&boundary)
+    }
+
+    fn boundary_1(&self) -> Vec<usize> {
+        let mut num_faces = vec![0; self.num_edges()];
+        for face_edges in self.face_edges() {
+            for &(edge, _) in face_edges {
+                num_faces[edge] += 1;
+            }
+        }
+        (0..self.num_edges()).filter(|&edge| num_faces[edge] == 1).collect()
+    }
+
+    fn boundary_2(&self) -> Vec<usize> {
+        let mut boundary = vec![false; self.num_edges()];
+        for edge in self.boundary_1() {
+            boundary[edge] = true;
+        }
+        (0..self.num_faces())
+            .filter(|&face| self.face_edges()[face].iter().any(|&(edge, _)| boundary[edge]))
+            .collect()
+    }
+
+    fn derivative_0_primal(&self, edges: &mut Self::Simplex1, vertices: &Self::Simplex0) {
+        for (edge, &[v0, v1]) in edges.iter_mut().zip(self.edges()) {
+            *edge = vertices[v1] - vertices[v0];
+        }
+    }
+
+    /// Transposed primal derivative `d1^T`.
+    fn derivative_0_dual(&self, edges: &mut Self::Simplex1, faces: &Self::Simplex2) {
+        edges.fill(T::zero());
+        for (&face, face_edges) in faces.iter().zip(self.face_edges()) {
+            for &(edge, positive) in face_edges {
+                edges[edge] += orientation::<T>(positive) * face;
+            }
+        }
+    }
+
+    fn derivative_1_primal(&self, faces: &mut Self::Simplex2, edges: &Self::Simplex1) {
+        for (face, face_edges) in faces.iter_mut().zip(self.face_edges()) {
+            *face = face_edges.iter().fold(T::zero(), |sum, &(edge, positive)| {
+                sum + orientation::<T>(positive) * edges[edge]
+            });
+        }
+    }
+
+    /// Transposed primal derivative `d0^T`.
+    fn derivative_1_dual(&self, vertices: &mut Self::Simplex0, edges: &Self::Simplex1) {
+        vertices.fill(T::zero());
+        for (&edge, &[v0, v1]) in edges.iter().zip(self.edges()) {
+            vertices[v0] -= edge;
+            vertices[v1] += edge;
+        }
+    }
+
+    fn inner_product_1_dual(&self, a: &Self::Simplex1, b: &Self::Simplex1) -> T {
+        let threshold = degenerate_threshold(self.edge_cotan_weights());
+        a.iter().zip(b.iter()).zip(self.edge_cotan_weights())
+            .fold(T::zero(), |sum, ((&a, &b), &weight)| sum + a * b * inverse(weight, threshold))
+    }
+
+    fn wedge_01(&self, out: &mut Self::Simplex1, a: &Self::Simplex0, b: &Self::Simplex1) {
+        let half = T::new(0.5);
+        for ((out, &b), &[v0, v1]) in out.iter_mut().zip(b.iter()).zip(self.edges()) {
+            *out = (a[v0] + a[v1]) * half * b;
+        }
+    }
+
+    /// Antisymmetrized cup product over the permutations of the face vertices [Hir03].
+    fn wedge_11(&self, out: &mut Self::Simplex2, a: &Self::Simplex1, b: &Self::Simplex1) {
+        let sixth = T::one() / T::new(6);
+        for (out, face_edges) in out.iter_mut().zip(self.face_edges()) {
+            // values on the edges (v0, v1), (v1, v2), (v2, v0)
+            let oriented = |form: &Self::Simplex1, i: usize| {
+                let (edge, positive) = face_edges[i];
+                orientation::<T>(positive) * form[edge]
+            };
+            let a = [oriented(a, 0), oriented(a, 1), oriented(a, 2)];
+            let b = [oriented(b, 0), oriented(b, 1), oriented(b, 2)];
+            *out = (0..3).fold(T::zero(), |sum, i| {
+                let j = (i + 1) % 3;
+                sum + a[i] * b[j] - a[j] * b[i]
+            }) * sixth;
+        }
+    }
+
+    fn wedge_02(&self, out: &mut Self::Simplex2, a: &Self::Simplex0, b: &Self::Simplex2) {
+        let third = T::one() / T::new(3);
+        for ((out, &b), &[v0, v1, v2]) in out.iter_mut().zip(b.iter()).zip(self.faces()) {
+            *out = (a[v0] + a[v1] + a[v2]) * third * b;
+        }
+    }
+
+    fn derivative_0_primal_matrix(&self) -> SparseMatrix<T> {
+        let mut matrix = TripletMatrix::with_capacity((self.num_edges(), self.num_vertices()), 2 * self.num_edges());
+        for (i, &[v0, v1]) in self.edges().iter().enumerate() {
+            matrix.push((i, v0), -T::one());
+            matrix.push((i, v1), T::one());
+        }
+        matrix.to_sparse()
+    }
+
+    fn derivative_0_dual_matrix(&self) -> SparseMatrix<T> {
+        let mut matrix = TripletMatrix::with_capacity((self.num_edges(), self.num_faces()), 3 * self.num_faces());
+        for (i, face_edges) in self.face_edges().iter().enumerate() {
+            for &(edge, positive) in face_edges {
+                matrix.push((edge, i), orientation(positive));
+            }
+        }
+        matrix.to_sparse()
+    }
+
+    fn derivative_1_primal_matrix(&self) -> SparseMatrix<T> {
+        let mut matrix = TripletMatrix::with_capacity((self.num_faces(), self.num_edges()), 3 * self.num_faces());
+        for (i, face_edges) in self.face_edges().iter().enumerate() {
+            for &(edge, positive) in face_edges {
+                matrix.push((i, edge), orientation(positive));
+            }
+        }
+        matrix.to_sparse()
+    }
+
+    fn derivative_1_dual_matrix(&self) -> SparseMatrix<T> {
+        let mut matrix = TripletMatrix::with_capacity((self.num_vertices(), self.num_edges()), 2 * self.num_edges());
+        for (i, &[v0, v1]) in self.edges().iter().enumerate() {
+            matrix.push((v0, i), -T::one());
+            matrix.push((v1, i), T::one());
+        }
+        matrix.to_sparse()
+    }
+
+    fn hodge_0_primal_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(self.vertex_dual_areas(), |x| x)
+    }
+    fn hodge_1_primal_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(self.edge_cotan_weights(), |x| x)
+    }
+    fn hodge_2_primal_matrix(&self) -> DiagonalMatrix<T> {
+        let threshold = degenerate_threshold(self.face_areas());
+        diagonal(self.face_areas(), |x| inverse(x, threshold))
+    }
+
+    fn hodge_0_dual_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(self.face_areas(), |x| x)
+    }
+    fn hodge_1_dual_matrix(&self) -> DiagonalMatrix<T> {
+        let threshold = degenerate_threshold(self.edge_cotan_weights());
+        diagonal(self.edge_cotan_weights(), |x| inverse(x, threshold))
+    }
+    fn hodge_2_dual_matrix(&self) -> DiagonalMatrix<T> {
+        let threshold = degenerate_threshold(self.vertex_dual_areas());
+        diagonal(self.vertex_dual_areas(), |x| inverse(x, threshold))
+    }
+}
+
+fn indices(mask: &[bool]) -> Vec<usize> {
+    mask.iter().enumerate().filter(|&(_, &set)| set).map(|(i, _)| i).collect()
+}
+
+fn diagonal<T, F>(values: &[T], func: F) -> DiagonalMatrix<T>
+    where T: Real, F: Fn(T) -> T
+{
+    let mut matrix = DiagonalMatrix::new(values.len());
+    for (i, &x) in values.iter().enumerate() {
+        matrix[i] = func(x);
+    }
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::vec3;
+    use super::*;
+
+    fn square() -> TriangleMesh<f64> {
+        let positions = vec![
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            vec3(1.0, 1.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(0.5, 0.5, 0.0),
+        ];
+        let faces = vec![[0, 1, 4], [1, 2, 4], [2, 3, 4], [3, 0, 4]];
+        TriangleMesh::new(positions, faces)
+    }
+
+    /// Equilateral triangles around a center vertex, all dual volumes positive.
+    fn hexagon() -> TriangleMesh<f64> {
+        let mut positions = vec![vec3(0.0, 0.0, 0.0)];
+        for i in 0..6 {
+            let angle = i as f64 * ::std::f64::consts::PI / 3.0;
+            positions.push(vec3(angle.cos(), angle.sin(), 0.0));
+        }
+        let faces = (0..6).map(|i| [0, i + 1, (i + 1) % 6 + 1]).collect();
+        TriangleMesh::new(positions, faces)
+    }
+
+    #[test]
+    fn mesh_exact_sequence() {
+        let mesh = square();
+        assert_eq!(mesh.num_edges(), 8);
+
+        let mut vertices = mesh.new_simplex_0();
+        let mut edges = mesh.new_simplex_1();
+        let mut faces = mesh.new_simplex_2();
+
+        for (i, v) in vertices.iter_mut().enumerate() {
+            *v = (i as f64 * 0.37).sin();
+        }
+
+        mesh.derivative_0_primal(&mut edges, &vertices);
+        mesh.derivative_1_primal(&mut faces, &edges);
+        for &f in faces.iter() {
+            assert!(f.abs() < 1.0e-6);
+        }
+
+        // interior vertex
+        for (i, f) in faces.iter_mut().enumerate() {
+            *f = (i as f64 * 0.37).cos();
+        }
+        mesh.derivative_0_dual(&mut edges, &faces);
+        mesh.derivative_1_dual(&mut vertices, &edges);
+        assert!(vertices[4].abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn mesh_wedge() {
+        let mesh = square();
+        let mut x = mesh.new_simplex_0();
+        let mut y = mesh.new_simplex_0();
+        for (i, p) in mesh.positions().iter().enumerate() {
+            x[i] = p.x;
+            y[i] = p.y;
+        }
+
+        let mut dx = mesh.new_simplex_1();
+        let mut dy = mesh.new_simplex_1();
+        mesh.derivative_0_primal(&mut dx, &x);
+        mesh.derivative_0_primal(&mut dy, &y);
+
+        // `dx ∧ dy` integrates to the face areas
+        let mut area = mesh.new_simplex_2();
+        mesh.wedge_11(&mut area, &dx, &dy);
+        for &a in area.iter() {
+            assert!((a - 0.25).abs() < 1.0e-6);
+        }
+
+        mesh.wedge_11(&mut area, &dx, &dx);
+        for &a in area.iter() {
+            assert!(a.abs() < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn mesh_hodge() {
+        let mesh = square();
+
+        let area = mesh.vertex_dual_areas().iter().fold(0.0, |sum, &a| sum + a);
+        assert!((area - 1.0).abs() < 1.0e-6);
+        assert!((mesh.vertex_dual_areas()[4] - 0.5).abs() < 1.0e-6);
+
+        for &a in mesh.face_areas() {
+            assert!((a - 0.25).abs() < 1.0e-6);
+        }
+
+        // right angle at the center vertex for the boundary edges
+        for (&[v0, v1], &w) in mesh.edges().iter().zip(mesh.edge_cotan_weights()) {
+            if v0 != 4 && v1 != 4 {
+                assert!(w.abs() < 1.0e-6);
+            } else {
+                assert!((w - 1.0).abs() < 1.0e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn mesh_hodge_inverse() {
+        let mesh = hexagon();
+        assert!(mesh.edge_cotan_weights().iter().all(|&w| w > 0.0));
+        assert!(mesh.vertex_dual_areas().iter().all(|&a| a > 0.0));
+
+        let mut vertices = mesh.new_simplex_0();
+        let mut dual_vertices = mesh.new_simplex_0();
+        for (i, v) in vertices.iter_mut().enumerate() {
+            *v = (i as f64 * 0.37).sin();
+        }
+        Hodge0::apply(&mesh, &mut dual_vertices, &vertices);
+        let mut primal = mesh.new_simplex_0();
+        Hodge0::apply_inv(&mesh, &mut primal, &dual_vertices);
+        for (&p, &v) in primal.iter().zip(vertices.iter()) {
+            assert!(p.is_finite() && (p - v).abs() < 1.0e-12);
+        }
+
+        let mut edges = mesh.new_simplex_1();
+        let mut dual_edges = mesh.new_simplex_1();
+        for (i, e) in edges.iter_mut().enumerate() {
+            *e = (i as f64 * 0.37).cos();
+        }
+        Hodge1::apply(&mesh, &mut dual_edges, &edges);
+        let mut primal = mesh.new_simplex_1();
+        Hodge1::apply_inv(&mesh, &mut primal, &dual_edges);
+        for (&p, &e) in primal.iter().zip(edges.iter()) {
+            assert!(p.is_finite() && (p - e).abs() < 1.0e-12);
+        }
+
+        let identity = mesh.hodge_1_primal_matrix() * mesh.hodge_1_dual_matrix();
+        for i in 0..identity.dim() {
+            assert!((identity[i] - 1.0).abs() < 1.0e-12);
+        }
+        let identity = mesh.hodge_0_primal_matrix() * mesh.hodge_2_dual_matrix();
+        for i in 0..identity.dim() {
+            assert!((identity[i] - 1.0).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn mesh_hodge_inverse_degenerate() {
+        // vanishing cotan weights on the boundary edges
+        let mesh = square();
+        let dual_edges = Array::from_elem(mesh.num_edges(), 1.0);
+        let mut edges = mesh.new_simplex_1();
+        Hodge1::apply_inv(&mesh, &mut edges, &dual_edges);
+        for (&[v0, v1], &e) in mesh.edges().iter().zip(edges.iter()) {
+            assert!(e.is_finite());
+            if v0 != 4 && v1 != 4 {
+                assert_eq!(e, 0.0);
+            } else {
+                assert!((e - 1.0).abs() < 1.0e-6);
+            }
+        }
+
+        let matrix = mesh.hodge_1_dual_matrix();
+        for i in 0..matrix.dim() {
+            assert!(matrix[i].is_finite());
+        }
+    }
+}
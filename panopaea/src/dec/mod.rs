@@ -2,8 +2,22 @@
 
 use std::ops::{Deref, DerefMut};
 
+pub mod checked;
+pub mod decomposition;
+pub mod diffusion;
 pub mod grid;
+pub mod harmonic;
 pub mod manifold;
+pub mod mesh;
+pub mod musical;
+pub mod ops;
+pub mod parallel;
+pub mod periodic;
+pub mod pool;
+pub mod projection;
+pub mod viscosity;
+pub mod weighted;
+pub mod whitney;
 
 pub struct Primal<T>(T);
 
@@ -0,0 +1,134 @@
+//! Musical isomorphisms on grids
+//!
+//! Conversion between velocities stored as dual 1-forms (`Staggered2d`) and
+//! vector fields `(x, y)` sampled at the cell centers or grid nodes.
+//! Dual 1-forms are integrated over the dual edges and scale with the cell spacing.
+
+use cgmath::Vector2;
+use domain::Grid2d;
+use math::Real;
+use ndarray::Array2;
+use super::grid::Staggered2d;
+
+/// Mean of the available values.
+fn mean<T: Real>(a: Option<T>, b: Option<T>) -> T {
+    match (a, b) {
+        (Some(a), Some(b)) => (a + b) * T::new(0.5),
+        (Some(v), None) | (None, Some(v)) => v,
+        (None, None) => T::zero(),
+    }
+}
+
+impl Grid2d {
+    fn spacing_real<T: Real>(&self) -> (T, T) {
+        let (dy, dx) = self.spacing();
+        (T::new(dy), T::new(dx))
+    }
+
+    /// Vector field at the cell centers from a dual 1-form.
+    pub fn sharp_cells<T: Real>(&self, vectors: &mut Array2<Vector2<T>>, form: &Staggered2d<T>) {
+        let (dy, dx) = self.spacing_real::<T>();
+        let half = T::new(0.5);
+        let (vertical, horizontal) = form.split();
+
+        par_azip!(
+            mut v (vectors),
+            y0 (vertical.slice(s![..-1, ..])),
+            y1 (vertical.slice(s![1.., ..])),
+            x0 (horizontal.slice(s![.., ..-1])),
+            x1 (horizontal.slice(s![.., 1..]))
+         in { *v = Vector2::new(half * (x0 + x1) / dx, half * (y0 + y1) / dy); });
+    }
+
+    /// Vector field at the grid nodes from a dual 1-form.
+    ///
+    /// Nodes on the boundary only use the adjacent edges inside of the grid.
+    pub fn sharp_nodes<T: Real>(&self, vectors: &mut Array2<Vector2<T>>, form: &Staggered2d<T>) {
+        let (h, w) = self.dim();
+        let (dy, dx) = self.spacing_real::<T>();
+        let (vertical, horizontal) = form.split();
+
+        par_azip!(index (y, x), mut v (vectors) in {
+            let vx = mean(
+                if y > 0 { Some(horizontal[(y-1, x)]) } else { None },
+                if y < h { Some(horizontal[(y, x)]) } else { None });
+            let vy = mean(
+                if x > 0 { Some(vertical[(y, x-1)]) } else { None },
+                if x < w { Some(vertical[(y, x)]) } else { None });
+            *v = Vector2::new(vx / dx, vy / dy);
+        });
+    }
+
+    /// Dual 1-form from a vector field at the cell centers.
+    ///
+    /// Boundary edges use the adjacent cell, boundary conditions need to be applied afterwards.
+    pub fn flat_cells<T: Real>(&self, form: &mut Staggered2d<T>, vectors: &Array2<Vector2<T>>) {
+        let (h, w) = self.dim();
+        let (dy, dx) = self.spacing_real::<T>();
+        let (mut vertical, mut horizontal) = form.split_mut();
+
+        par_azip!(index (y, x), mut edge (&mut vertical) in {
+            *edge = dy * mean(
+                if y > 0 { Some(vectors[(y-1, x)].y) } else { None },
+                if y < h { Some(vectors[(y, x)].y) } else { None });
+        });
+        par_azip!(index (y, x), mut edge (&mut horizontal) in {
+            *edge = dx * mean(
+                if x > 0 { Some(vectors[(y, x-1)].x) } else { None },
+                if x < w { Some(vectors[(y, x)].x) } else { None });
+        });
+    }
+
+    /// Dual 1-form from a vector field at the grid nodes.
+    pub fn flat_nodes<T: Real>(&self, form: &mut Staggered2d<T>, vectors: &Array2<Vector2<T>>) {
+        let (dy, dx) = self.spacing_real::<T>();
+        let half = T::new(0.5);
+        let (mut vertical, mut horizontal) = form.split_mut();
+
+        par_azip!(
+            mut edge (&mut vertical),
+            v0 (vectors.slice(s![.., ..-1])),
+            v1 (vectors.slice(s![.., 1..]))
+         in { *edge = half * (v0.y + v1.y) * dy; });
+
+        par_azip!(
+            mut edge (&mut horizontal),
+            v0 (vectors.slice(s![..-1, ..])),
+            v1 (vectors.slice(s![1.., ..]))
+         in { *edge = half * (v0.x + v1.x) * dx; });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::vec2;
+    use dec::manifold::Manifold2d;
+    use super::*;
+
+    #[test]
+    fn musical_linear_field() {
+        let grid = Grid2d::new((4, 6)).with_spacing((0.5, 2.0));
+        let (dy, dx) = grid.spacing();
+
+        // v = (x, 2y) in physical coordinates
+        let nodes = Array2::from_shape_fn((5, 7), |(y, x)| vec2(x as f64 * dx, 2.0 * y as f64 * dy));
+        let mut form = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        grid.flat_nodes(&mut form, &nodes);
+
+        let mut cells = Array2::from_elem((4, 6), vec2(0.0, 0.0));
+        grid.sharp_cells(&mut cells, &form);
+        for ((y, x), v) in cells.indexed_iter() {
+            assert!((v.x - (x as f64 + 0.5) * dx).abs() < 1.0e-10);
+            assert!((v.y - 2.0 * (y as f64 + 0.5) * dy).abs() < 1.0e-10);
+        }
+
+        // constant fields are reproduced everywhere
+        let constant = Array2::from_elem((4, 6), vec2(1.5, -0.5));
+        grid.flat_cells(&mut form, &constant);
+        let mut nodes = Array2::from_elem((5, 7), vec2(0.0, 0.0));
+        grid.sharp_nodes(&mut nodes, &form);
+        for v in nodes.iter() {
+            assert!((v.x - 1.5).abs() < 1.0e-10 && (v.y + 0.5).abs() < 1.0e-10);
+        }
+    }
+}
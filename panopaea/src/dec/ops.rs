@@ -0,0 +1,216 @@
+//! Vector calculus operators
+//!
+//! Convenience functions composing the derivative and hodge operators for
+//! the common representation of fluid quantities: scalars are dual 0-forms
+//! (stored on the primal faces), velocities are dual 1-forms and the scalar
+//! vorticity is a primal 0-form.
+//!
+//! In 3d velocities are dual 1-forms stored on the primal faces and the
+//! vorticity vector is a primal 1-form on the edges, one component per edge
+//! direction.
+//!
+//! Velocities are integrated over the dual edges, all other results are point values.
+
+use math::{LinearView, Real};
+use super::manifold::{Manifold2d, Manifold3d};
+
+fn negate<T: Real, L: LinearView<Elem = T>>(simplex: &mut L) {
+    simplex.view_linear_mut().map_inplace(|x| *x = -*x);
+}
+
+/// Gradient of a scalar field.
+pub fn gradient<T, M>(manifold: &M, gradient: &mut M::Simplex1, scalar: &M::Simplex2)
+where
+    T: Real,
+    M: Manifold2d<T>,
+    M::Simplex1: LinearView<Elem = T>,
+{
+    manifold.derivative_0_dual(gradient, scalar);
+    negate(gradient);
+}
+
+/// Divergence of a velocity field.
+pub fn divergence<T, M>(manifold: &M, divergence: &mut M::Simplex2, velocity: &M::Simplex1)
+where
+    T: Real,
+    M: Manifold2d<T>,
+{
+    let mut velocity_primal = manifold.new_simplex_1();
+    let mut faces = manifold.new_simplex_2();
+    manifold.hodge_1_dual(&mut velocity_primal, velocity);
+    manifold.derivative_1_primal(&mut faces, &velocity_primal);
+    manifold.hodge_2_primal(divergence, &faces);
+}
+
+/// Scalar vorticity of a velocity field.
+pub fn curl<T, M>(manifold: &M, vorticity: &mut M::Simplex0, velocity: &M::Simplex1)
+where
+    T: Real,
+    M: Manifold2d<T>,
+    M::Simplex0: LinearView<Elem = T>,
+{
+    let mut circulation = manifold.new_simplex_0();
+    manifold.derivative_1_dual(&mut circulation, velocity);
+    manifold.hodge_2_dual(vorticity, &circulation);
+    negate(vorticity);
+}
+
+/// Vorticity of a 3d velocity field, component along each edge.
+///
+/// The velocity outside of the domain is zero: the vorticity of an edge on the
+/// walls is the circulation around its full dual face, the jump of the tangential
+/// velocity to the wall as for no-slip walls.
+pub fn curl_3d<T, M>(manifold: &M, vorticity: &mut M::Simplex1, velocity: &M::Simplex2)
+where
+    T: Real,
+    M: Manifold3d<T>,
+{
+    let mut circulation = manifold.new_simplex_1();
+    manifold.derivative_1_dual(&mut circulation, velocity);
+    manifold.hodge_2_dual(vorticity, &circulation);
+}
+
+/// Laplacian `div grad` of a scalar field.
+pub fn laplacian<T, M>(manifold: &M, laplacian: &mut M::Simplex2, scalar: &M::Simplex2)
+where
+    T: Real,
+    M: Manifold2d<T>,
+    M::Simplex1: LinearView<Elem = T>,
+{
+    let mut grad = manifold.new_simplex_1();
+    gradient(manifold, &mut grad, scalar);
+    divergence(manifold, laplacian, &grad);
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::{Grid2d, Grid3d};
+    use super::*;
+
+    #[test]
+    fn grid_2d_vector_calculus() {
+        let grid = Grid2d::new((6, 7));
+        let mut scalar = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        for ((y, x), s) in scalar.indexed_iter_mut() {
+            *s = (x as f64 + 0.5).powi(2) + 3.0 * (y as f64 + 0.5);
+        }
+
+        let mut grad = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        gradient(&grid, &mut grad, &scalar);
+        {
+            let (vertical, horizontal) = grad.split();
+            for &v in vertical.slice(s![1..-1, ..]).iter() {
+                assert!((v - 3.0).abs() < 1.0e-10);
+            }
+            for ((_, x), &v) in horizontal.slice(s![.., 1..-1]).indexed_iter() {
+                assert!((v - 2.0 * (x + 1) as f64).abs() < 1.0e-10);
+            }
+        }
+
+        let mut vorticity = <Grid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        curl(&grid, &mut vorticity, &grad);
+        for &w in vorticity.slice(s![1..-1, 1..-1]).iter() {
+            assert!(w.abs() < 1.0e-10);
+        }
+
+        let mut lap = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        laplacian(&grid, &mut lap, &scalar);
+        for &l in lap.slice(s![1..-1, 1..-1]).iter() {
+            assert!((l - 2.0).abs() < 1.0e-10);
+        }
+
+        // rigid rotation `u = (-y, x)` has vorticity 2
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (mut vertical, mut horizontal) = velocity.split_mut();
+            for ((_, x), v) in vertical.indexed_iter_mut() { *v = x as f64 + 0.5; }
+            for ((y, _), v) in horizontal.indexed_iter_mut() { *v = -(y as f64 + 0.5); }
+        }
+        curl(&grid, &mut vorticity, &velocity);
+        for &w in vorticity.slice(s![1..-1, 1..-1]).iter() {
+            assert!((w - 2.0).abs() < 1.0e-10);
+        }
+
+        let grid = Grid3d::new((3, 5, 5));
+        let mut velocity = <Grid3d as Manifold3d<f64>>::new_simplex_2(&grid);
+        {
+            let (_, mut fy, mut fx) = velocity.split_mut();
+            for ((_, _, i), v) in fy.indexed_iter_mut() { *v = i as f64 + 0.5; }
+            for ((_, j, _), v) in fx.indexed_iter_mut() { *v = -(j as f64 + 0.5); }
+        }
+        let mut vorticity = <Grid3d as Manifold3d<f64>>::new_simplex_1(&grid);
+        curl_3d(&grid, &mut vorticity, &velocity);
+        let (cz, cy, cx) = vorticity.split();
+        for &w in cz.slice(s![.., 1..-1, 1..-1]).iter() {
+            assert!((w - 2.0).abs() < 1.0e-10);
+        }
+        for &w in cy.slice(s![1..-1, .., ..]).iter().chain(cx.slice(s![1..-1, .., ..]).iter()) {
+            assert!(w.abs() < 1.0e-10);
+        }
+        // edges on the bottom and top walls see the jump of the tangential velocity to zero
+        for ((_, j, _), &w) in cy.slice(s![..1, .., ..]).indexed_iter() {
+            assert!((w + (j as f64 + 0.5)).abs() < 1.0e-10);
+        }
+        for ((_, j, _), &w) in cy.slice(s![-1.., .., ..]).indexed_iter() {
+            assert!((w - (j as f64 + 0.5)).abs() < 1.0e-10);
+        }
+        for ((_, _, i), &w) in cx.slice(s![..1, .., ..]).indexed_iter() {
+            assert!((w + (i as f64 + 0.5)).abs() < 1.0e-10);
+        }
+        for ((_, _, i), &w) in cx.slice(s![-1.., .., ..]).indexed_iter() {
+            assert!((w - (i as f64 + 0.5)).abs() < 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn grid_3d_curl() {
+        let grid = Grid3d::new((4, 6, 5));
+        let (d, h, w) = grid.dim();
+
+        // rigid rotation `u = (-y, x, 0)` around the z axis has vorticity `(0, 0, 2)`
+        let mut velocity = <Grid3d as Manifold3d<f64>>::new_simplex_2(&grid);
+        {
+            let (_, mut fy, mut fx) = velocity.split_mut();
+            for ((_, _, i), v) in fy.indexed_iter_mut() { *v = i as f64 + 0.5; }
+            for ((_, j, _), v) in fx.indexed_iter_mut() { *v = -(j as f64 + 0.5); }
+        }
+        let mut vorticity = <Grid3d as Manifold3d<f64>>::new_simplex_1(&grid);
+        curl_3d(&grid, &mut vorticity, &velocity);
+        {
+            let (cz, cy, cx) = vorticity.split();
+            for &w in cz.slice(s![.., 1..-1, 1..-1]).iter() {
+                assert!((w - 2.0).abs() < 1.0e-10);
+            }
+            for &w in cy.slice(s![1..-1, .., 1..-1]).iter().chain(cx.slice(s![1..-1, 1..-1, ..]).iter()) {
+                assert!(w.abs() < 1.0e-10);
+            }
+        }
+
+        // gradient fields are curl free
+        let phi = |x: f64, y: f64, z: f64| x * x * y + 0.5 * z * z - 2.0 * x * z + y;
+        {
+            let (mut fz, mut fy, mut fx) = velocity.split_mut();
+            for ((k, j, i), v) in fz.indexed_iter_mut() {
+                let (x, y, z) = (i as f64 + 0.5, j as f64 + 0.5, k as f64);
+                *v = phi(x, y, z + 0.5) - phi(x, y, z - 0.5);
+            }
+            for ((k, j, i), v) in fy.indexed_iter_mut() {
+                let (x, y, z) = (i as f64 + 0.5, j as f64, k as f64 + 0.5);
+                *v = phi(x, y + 0.5, z) - phi(x, y - 0.5, z);
+            }
+            for ((k, j, i), v) in fx.indexed_iter_mut() {
+                let (x, y, z) = (i as f64, j as f64 + 0.5, k as f64 + 0.5);
+                *v = phi(x + 0.5, y, z) - phi(x - 0.5, y, z);
+            }
+        }
+        curl_3d(&grid, &mut vorticity, &velocity);
+        let (cz, cy, cx) = vorticity.split();
+        assert_eq!((cz.dim(), cy.dim(), cx.dim()), ((d, h + 1, w + 1), (d + 1, h, w + 1), (d + 1, h + 1, w)));
+        for &w in cz.slice(s![.., 1..-1, 1..-1]).iter()
+            .chain(cy.slice(s![1..-1, .., 1..-1]).iter())
+            .chain(cx.slice(s![1..-1, 1..-1, ..]).iter())
+        {
+            assert!(w.abs() < 1.0e-10, "{}", w);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Thread pools for DEC operator applications
+//!
+//! Hodge stars and derivatives are applied in parallel on the current rayon
+//! thread pool. An `Executor` selects the pool: the global pool, a dedicated
+//! pool with a fixed number of threads, or a single thread as serial fallback,
+//! e.g. for reproducible timings or when the caller already parallelizes
+//! over multiple simulations.
+
+use rayon::{Configuration, ThreadPool};
+use std::sync::Arc;
+
+/// Threads used for parallel operator applications.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Threads {
+    /// Global rayon thread pool.
+    Global,
+    /// Run everything on a single thread.
+    Serial,
+    /// Dedicated pool with the given number of threads.
+    Pool(usize),
+}
+
+/// Runs operations on the thread pool selected by `Threads`.
+///
+/// Cloning shares the underlying pool.
+#[derive(Clone)]
+pub struct Executor {
+    threads: Threads,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl Executor {
+    /// Panics if the thread pool can't be created.
+    pub fn new(threads: Threads) -> Self {
+        let num_threads = match threads {
+            Threads::Global => return Executor { threads, pool: None },
+            Threads::Serial => 1,
+            Threads::Pool(num_threads) => num_threads,
+        };
+
+        let pool = ThreadPool::new(Configuration::new().num_threads(num_threads))
+            .expect("failed to create thread pool");
+        Executor { threads, pool: Some(Arc::new(pool)) }
+    }
+
+    pub fn threads(&self) -> Threads {
+        self.threads
+    }
+
+    /// Run `op` inside the selected pool, parallel operations called by `op` use its threads.
+    pub fn install<R, F>(&self, op: F) -> R
+        where R: Send, F: FnOnce() -> R + Send
+    {
+        match self.pool {
+            Some(ref pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::new(Threads::Global)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::Grid2d;
+    use dec::manifold::Manifold2d;
+    use math::LinearView;
+    use super::*;
+
+    #[test]
+    fn serial_fallback() {
+        let grid = Grid2d::new((32, 24));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        for (i, v) in velocity.view_linear_mut().iter_mut().enumerate() {
+            *v = (i as f64 * 0.37).sin();
+        }
+
+        let apply = |executor: &Executor| {
+            let mut primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+            let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+            executor.install(|| {
+                grid.hodge_1_dual(&mut primal, &velocity);
+                grid.derivative_1_primal(&mut divergence, &primal);
+            });
+            divergence
+        };
+
+        let global = apply(&Executor::default());
+        assert_eq!(global, apply(&Executor::new(Threads::Serial)));
+        assert_eq!(global, apply(&Executor::new(Threads::Pool(2))));
+    }
+}
@@ -0,0 +1,414 @@
+//! DEC operators on doubly periodic grids
+//!
+//! Same layout and orientation as `Grid2d`, but without boundary: all indices
+//! wrap around, vertices, edges per direction and faces have equal counts.
+
+use math::LinearView;
+use ndarray::{Array, ArrayView, ArrayViewMut, Axis, Ix1, Ix2, LinalgScalar};
+use sparse::{DiagonalMatrix, SparseMatrix, TripletMatrix};
+use std::ops::Neg;
+use domain::PeriodicGrid2d;
+use super::manifold::{Hodge0, Hodge1, Hodge2, Manifold2d};
+
+/// Edge storage of a periodic grid, both directions have the grid dimensions.
+#[derive(Clone, Debug)]
+pub struct PeriodicStaggered2d<T> {
+    data: Array<T, Ix1>,
+    dim: (usize, usize), // (y, x)
+}
+
+impl<T> PeriodicStaggered2d<T> {
+    pub fn dim(&self) -> (usize, usize) {
+        self.dim
+    }
+
+    /// (vertical, horizontal)
+    pub fn split(&self) -> (ArrayView<T, Ix2>, ArrayView<T, Ix2>) {
+        let (vertical, horizontal) = self.data.view().split_at(Axis(0), self.dim.0 * self.dim.1);
+        (vertical.into_shape(self.dim).unwrap(), horizontal.into_shape(self.dim).unwrap())
+    }
+
+    /// (vertical, horizontal)
+    pub fn split_mut(&mut self) -> (ArrayViewMut<T, Ix2>, ArrayViewMut<T, Ix2>) {
+        let (vertical, horizontal) = self.data.view_mut().split_at(Axis(0), self.dim.0 * self.dim.1);
+        (vertical.into_shape(self.dim).unwrap(), horizontal.into_shape(self.dim).unwrap())
+    }
+}
+
+impl<T> LinearView for PeriodicStaggered2d<T> {
+    type Elem = T;
+    fn view_linear(&self) -> ArrayView<T, Ix1> {
+        self.data.view()
+    }
+
+    fn view_linear_mut(&mut self) -> ArrayViewMut<T, Ix1> {
+        self.data.view_mut()
+    }
+}
+
+fn next(i: usize, len: usize) -> usize {
+    (i + 1) % len
+}
+
+fn prev(i: usize, len: usize) -> usize {
+    (i + len - 1) % len
+}
+
+impl<T> Hodge0<T> for PeriodicGrid2d
+where T: LinalgScalar + Send + Sync
+{
+    type Simplex0 = Array<T, Ix2>;
+    fn apply(&self, dual: &mut Self::Simplex0, primal: &Self::Simplex0) {
+        par_azip!(mut dual (dual), primal (primal) in { *dual = primal; });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex0, dual: &Self::Simplex0) {
+        par_azip!(mut primal (primal), dual (dual) in { *primal = dual; });
+    }
+}
+
+impl<T> Hodge1<T> for PeriodicGrid2d
+where T: LinalgScalar + Neg<Output = T> + Send + Sync
+{
+    type Simplex1 = PeriodicStaggered2d<T>;
+    fn apply(&self, dual: &mut Self::Simplex1, primal: &Self::Simplex1) {
+        let primal = primal.split();
+        let mut dual = dual.split_mut();
+        par_azip!(mut dual (&mut dual.0), primal (&primal.0) in { *dual = primal; });
+        par_azip!(mut dual (&mut dual.1), primal (&primal.1) in { *dual = -primal; });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex1, dual: &Self::Simplex1) {
+        let dual = dual.split();
+        let mut primal = primal.split_mut();
+        par_azip!(mut primal (&mut primal.0), dual (&dual.0) in { *primal = -dual; });
+        par_azip!(mut primal (&mut primal.1), dual (&dual.1) in { *primal = dual; });
+    }
+}
+
+impl<T> Hodge2<T> for PeriodicGrid2d
+where T: LinalgScalar + Send + Sync
+{
+    type Simplex2 = Array<T, Ix2>;
+    fn apply(&self, dual: &mut Self::Simplex2, primal: &Self::Simplex2) {
+        par_azip!(mut dual (dual), primal (primal) in { *dual = primal; });
+    }
+    fn apply_inv(&self, primal: &mut Self::Simplex2, dual: &Self::Simplex2) {
+        par_azip!(mut primal (primal), dual (dual) in { *primal = dual; });
+    }
+}
+
+impl<T> Manifold2d<T> for PeriodicGrid2d
+    where T: LinalgScalar + Neg<Output = T> + Send + Sync
+{
+    fn num_elem_0(&self) -> usize {
+        self.dim().0 * self.dim().1
+    }
+
+    fn num_elem_1(&self) -> usize {
+        2 * self.dim().0 * self.dim().1
+    }
+
+    fn num_elem_2(&self) -> usize {
+        self.dim().0 * self.dim().1
+    }
+
+    fn new_simplex_0(&self) -> Self::Simplex0 {
+        Array::from_elem(self.dim(), T::zero()) // vertices
+    }
+
+    fn new_simplex_1(&self) -> Self::Simplex1 {
+        PeriodicStaggered2d {
+            data: Array::from_elem(2 * self.dim().0 * self.dim().1, T::zero()),
+            dim: self.dim(),
+        }
+    }
+
+    fn new_simplex_2(&self) -> Self::Simplex2 {
+        Array::from_elem(self.dim(), T::zero()) // faces
+    }
+
+    fn boundary_0(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn boundary_1(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn boundary_2(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn derivative_0_primal(&self, edges: &mut Self::Simplex1, vertices: &Self::Simplex0) {
+        let (h, w) = self.dim();
+        let (mut vertical, mut horizontal) = edges.split_mut();
+
+        par_azip!(index (y, x), mut edge (&mut vertical) in {
+            *edge = vertices[(y, next(x, w))] - vertices[(y, x)];
+        });
+        par_azip!(index (y, x), mut edge (&mut horizontal) in {
+            *edge = vertices[(next(y, h), x)] - vertices[(y, x)];
+        });
+    }
+
+    fn derivative_0_dual(&self, edges: &mut Self::Simplex1, faces: &Self::Simplex2) {
+        let (h, w) = self.dim();
+        let (mut vertical, mut horizontal) = edges.split_mut();
+
+        par_azip!(index (y, x), mut edge (&mut vertical) in {
+            *edge = faces[(prev(y, h), x)] - faces[(y, x)];
+        });
+        par_azip!(index (y, x), mut edge (&mut horizontal) in {
+            *edge = faces[(y, prev(x, w))] - faces[(y, x)];
+        });
+    }
+
+    fn derivative_1_primal(&self, faces: &mut Self::Simplex2, edges: &Self::Simplex1) {
+        let (h, w) = self.dim();
+        let (vertical, horizontal) = edges.split();
+
+        par_azip!(index (y, x), mut face (faces) in {
+            let (top, bottom) = (vertical[(y, x)], vertical[(next(y, h), x)]);
+            let (left, right) = (horizontal[(y, x)], horizontal[(y, next(x, w))]);
+            *face = -bottom + top - left + right;
+        });
+    }
+
+    fn derivative_1_dual(&self, vertices: &mut Self::Simplex0, edges: &Self::Simplex1) {
+        let (h, w) = self.dim();
+        let (vertical, horizontal) = edges.split();
+
+        par_azip!(index (y, x), mut v (vertices) in {
+            *v = vertical[(y, prev(x, w))] - vertical[(y, x)]
+                + horizontal[(y, x)] - horizontal[(prev(y, h), x)];
+        });
+    }
+
+    fn inner_product_1_dual(&self, a: &Self::Simplex1, b: &Self::Simplex1) -> T {
+        let (a, b) = (a.view_linear(), b.view_linear());
+        a.iter().zip(b.iter()).fold(T::zero(), |sum, (&a, &b)| sum + a * b)
+    }
+
+    fn wedge_01(&self, out: &mut Self::Simplex1, a: &Self::Simplex0, b: &Self::Simplex1) {
+        let (h, w) = self.dim();
+        let half = T::one() / (T::one() + T::one());
+        let (b_x, b_y) = b.split();
+        let (mut out_x, mut out_y) = out.split_mut();
+
+        par_azip!(index (y, x), mut out (&mut out_x), b (&b_x) in {
+            *out = (a[(y, x)] + a[(y, next(x, w))]) * half * b;
+        });
+        par_azip!(index (y, x), mut out (&mut out_y), b (&b_y) in {
+            *out = (a[(y, x)] + a[(next(y, h), x)]) * half * b;
+        });
+    }
+
+    /// Cubical cup product, averaged over the four corners of each face.
+    fn wedge_11(&self, out: &mut Self::Simplex2, a: &Self::Simplex1, b: &Self::Simplex1) {
+        let (h, w) = self.dim();
+        let two = T::one() + T::one();
+        let quarter = T::one() / (two * two);
+        let (a_x, a_y) = a.split();
+        let (b_x, b_y) = b.split();
+
+        par_azip!(index (y, x), mut out (out) in {
+            let a_x = a_x[(y, x)] + a_x[(next(y, h), x)];
+            let a_y = a_y[(y, x)] + a_y[(y, next(x, w))];
+            let b_x = b_x[(y, x)] + b_x[(next(y, h), x)];
+            let b_y = b_y[(y, x)] + b_y[(y, next(x, w))];
+            *out = (a_x * b_y - a_y * b_x) * quarter;
+        });
+    }
+
+    fn wedge_02(&self, out: &mut Self::Simplex2, a: &Self::Simplex0, b: &Self::Simplex2) {
+        let (h, w) = self.dim();
+        let two = T::one() + T::one();
+        let quarter = T::one() / (two * two);
+
+        par_azip!(index (y, x), mut out (out), b (b) in {
+            let (y1, x1) = (next(y, h), next(x, w));
+            *out = (a[(y, x)] + a[(y, x1)] + a[(y1, x)] + a[(y1, x1)]) * quarter * b;
+        });
+    }
+
+    fn derivative_0_primal_matrix(&self) -> SparseMatrix<T> {
+        let dim = (Manifold2d::<T>::num_elem_1(self), Manifold2d::<T>::num_elem_0(self));
+        let mut matrix = TripletMatrix::with_capacity(dim, 2 * dim.0);
+
+        let (h, w) = self.dim();
+        let one = T::one();
+        let offset = h * w;
+
+        for y in 0..h {
+            for x in 0..w {
+                let idx = y*w + x;
+                matrix.push((idx, idx), -one);
+                matrix.push((idx, y*w + next(x, w)), one);
+                matrix.push((offset + idx, idx), -one);
+                matrix.push((offset + idx, next(y, h)*w + x), one);
+            }
+        }
+
+        matrix.to_sparse()
+    }
+
+    fn derivative_0_dual_matrix(&self) -> SparseMatrix<T> {
+        let dim = (Manifold2d::<T>::num_elem_1(self), Manifold2d::<T>::num_elem_2(self));
+        let mut matrix = TripletMatrix::with_capacity(dim, 2 * dim.0);
+
+        let (h, w) = self.dim();
+        let one = T::one();
+        let offset = h * w;
+
+        for y in 0..h {
+            for x in 0..w {
+                let idx = y*w + x;
+                matrix.push((idx, prev(y, h)*w + x), one);
+                matrix.push((idx, idx), -one);
+                matrix.push((offset + idx, y*w + prev(x, w)), one);
+                matrix.push((offset + idx, idx), -one);
+            }
+        }
+
+        matrix.to_sparse()
+    }
+
+    fn derivative_1_primal_matrix(&self) -> SparseMatrix<T> {
+        let dim = (Manifold2d::<T>::num_elem_2(self), Manifold2d::<T>::num_elem_1(self));
+        let mut matrix = TripletMatrix::with_capacity(dim, 4 * dim.0);
+
+        let (h, w) = self.dim();
+        let one = T::one();
+        let offset = h * w;
+
+        for y in 0..h {
+            for x in 0..w {
+                let idx = y*w + x;
+                matrix.push((idx, idx), one);
+                matrix.push((idx, next(y, h)*w + x), -one);
+                matrix.push((idx, offset + idx), -one);
+                matrix.push((idx, offset + y*w + next(x, w)), one);
+            }
+        }
+
+        matrix.to_sparse()
+    }
+
+    fn derivative_1_dual_matrix(&self) -> SparseMatrix<T> {
+        let dim = (Manifold2d::<T>::num_elem_0(self), Manifold2d::<T>::num_elem_1(self));
+        let mut matrix = TripletMatrix::with_capacity(dim, 4 * dim.0);
+
+        let (h, w) = self.dim();
+        let one = T::one();
+        let offset = h * w;
+
+        for y in 0..h {
+            for x in 0..w {
+                let idx = y*w + x;
+                matrix.push((idx, y*w + prev(x, w)), one);
+                matrix.push((idx, idx), -one);
+                matrix.push((idx, offset + idx), one);
+                matrix.push((idx, offset + prev(y, h)*w + x), -one);
+            }
+        }
+
+        matrix.to_sparse()
+    }
+
+    fn hodge_0_primal_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(Manifold2d::<T>::num_elem_0(self), T::one(), T::one())
+    }
+    fn hodge_1_primal_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(Manifold2d::<T>::num_elem_1(self), T::one(), -T::one())
+    }
+    fn hodge_2_primal_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(Manifold2d::<T>::num_elem_2(self), T::one(), T::one())
+    }
+
+    fn hodge_0_dual_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(Manifold2d::<T>::num_elem_2(self), T::one(), T::one())
+    }
+    fn hodge_1_dual_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(Manifold2d::<T>::num_elem_1(self), -T::one(), T::one())
+    }
+    fn hodge_2_dual_matrix(&self) -> DiagonalMatrix<T> {
+        diagonal(Manifold2d::<T>::num_elem_0(self), T::one(), T::one())
+    }
+}
+
+/// Diagonal matrix with separate values for the first and second half.
+fn diagonal<T: LinalgScalar>(len: usize, first: T, second: T) -> DiagonalMatrix<T> {
+    let mut matrix = DiagonalMatrix::new(len);
+    for i in 0..len {
+        matrix[i] = if 2 * i < len { first } else { second };
+    }
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use math::LinearViewReal;
+    use std::f64::consts::PI;
+    use super::*;
+
+    #[test]
+    fn periodic_exact_sequence() {
+        let grid = PeriodicGrid2d::new((5, 6));
+        let mut vertices = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_0(&grid);
+        let mut edges = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut faces = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+
+        for (i, v) in vertices.iter_mut().enumerate() {
+            *v = (i as f64 * 0.37).sin();
+        }
+        grid.derivative_0_primal(&mut edges, &vertices);
+        grid.derivative_1_primal(&mut faces, &edges);
+        assert!(faces.norm_max() < 1.0e-10);
+
+        for (i, f) in faces.iter_mut().enumerate() {
+            *f = (i as f64 * 0.53).cos();
+        }
+        grid.derivative_0_dual(&mut edges, &faces);
+        grid.derivative_1_dual(&mut vertices, &edges);
+        assert!(vertices.norm_max() < 1.0e-10);
+
+        // no boundary, the total divergence vanishes for arbitrary fields
+        for (i, e) in edges.view_linear_mut().iter_mut().enumerate() {
+            *e = (i as f64 * 0.71).sin();
+        }
+        grid.derivative_1_primal(&mut faces, &edges);
+        assert!(faces.scalar_sum().abs() < 1.0e-10);
+
+        let mut reference = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        <PeriodicGrid2d as Manifold2d<f64>>::derivative_1_primal_matrix(&grid)
+            .mul_vec(reference.view_linear_mut(), edges.view_linear());
+        reference.zip_mut_with(&faces, |r, &f| *r -= f);
+        assert!(reference.norm_max() < 1.0e-10);
+    }
+
+    #[test]
+    fn periodic_laplacian_spectrum() {
+        let (h, w) = (8, 12);
+        let grid = PeriodicGrid2d::new((h, w));
+        let (ky, kx) = (2.0 * PI / h as f64, 3.0 * 2.0 * PI / w as f64);
+
+        let mut faces = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        for ((y, x), f) in faces.indexed_iter_mut() {
+            *f = (ky * y as f64).cos() * (kx * x as f64 + 0.3).sin();
+        }
+
+        // `d ★ d ★` on dual 0-forms, fourier modes are eigenvectors
+        let mut faces_dual = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        let mut edges_dual = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut edges_primal = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut laplacian = <PeriodicGrid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_2_primal(&mut faces_dual, &faces);
+        grid.derivative_0_dual(&mut edges_dual, &faces_dual);
+        grid.hodge_1_dual(&mut edges_primal, &edges_dual);
+        grid.derivative_1_primal(&mut laplacian, &edges_primal);
+
+        let eigenvalue = (2.0 - 2.0 * ky.cos()) + (2.0 - 2.0 * kx.cos());
+        for (&l, &f) in laplacian.iter().zip(faces.iter()) {
+            assert!((l - eigenvalue * f).abs() < 1.0e-10);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Reusable field storage
+//!
+//! Operator applications need temporary simplices of the manifold. A
+//! `FieldPool` keeps returned buffers around so that solvers only allocate
+//! during the first steps. Operators which can't work in place (e.g. advection)
+//! write into a buffer of the pool and swap it with the current field.
+
+use super::manifold::Manifold2d;
+
+/// Buffers of the simplices of a manifold for reuse across steps.
+///
+/// Taken buffers have unspecified content.
+pub struct FieldPool<'a, T, M: Manifold2d<T> + 'a> {
+    manifold: &'a M,
+    simplices_0: Vec<M::Simplex0>,
+    simplices_1: Vec<M::Simplex1>,
+    simplices_2: Vec<M::Simplex2>,
+    allocations: usize,
+}
+
+impl<'a, T, M: Manifold2d<T> + 'a> FieldPool<'a, T, M> {
+    pub fn new(manifold: &'a M) -> Self {
+        FieldPool {
+            manifold,
+            simplices_0: Vec::new(),
+            simplices_1: Vec::new(),
+            simplices_2: Vec::new(),
+            allocations: 0,
+        }
+    }
+
+    /// Number of buffers allocated by the pool so far.
+    pub fn allocations(&self) -> usize {
+        self.allocations
+    }
+
+    pub fn take_0(&mut self) -> M::Simplex0 {
+        match self.simplices_0.pop() {
+            Some(simplex) => simplex,
+            None => {
+                self.allocations += 1;
+                self.manifold.new_simplex_0()
+            }
+        }
+    }
+
+    pub fn take_1(&mut self) -> M::Simplex1 {
+        match self.simplices_1.pop() {
+            Some(simplex) => simplex,
+            None => {
+                self.allocations += 1;
+                self.manifold.new_simplex_1()
+            }
+        }
+    }
+
+    pub fn take_2(&mut self) -> M::Simplex2 {
+        match self.simplices_2.pop() {
+            Some(simplex) => simplex,
+            None => {
+                self.allocations += 1;
+                self.manifold.new_simplex_2()
+            }
+        }
+    }
+
+    pub fn recycle_0(&mut self, simplex: M::Simplex0) {
+        self.simplices_0.push(simplex);
+    }
+
+    pub fn recycle_1(&mut self, simplex: M::Simplex1) {
+        self.simplices_1.push(simplex);
+    }
+
+    pub fn recycle_2(&mut self, simplex: M::Simplex2) {
+        self.simplices_2.push(simplex);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::Grid2d;
+    use solvers::pipeline::{GridSolver, Pipeline};
+
+    #[test]
+    fn pipeline_reuses_buffers() {
+        let grid = Grid2d::new((24, 16));
+        let mut pipeline = Pipeline::smoke(&grid);
+        pipeline.state.scalar_mut("density")[(3, 8)] = 1.0;
+        pipeline.state.scalar_mut("temperature")[(3, 8)] = 4.0;
+
+        pipeline.step(0.1);
+        let allocations = pipeline.state.pool.allocations();
+        assert!(allocations > 0);
+        for _ in 0..5 {
+            pipeline.step(0.1);
+        }
+        assert_eq!(pipeline.state.pool.allocations(), allocations);
+    }
+}
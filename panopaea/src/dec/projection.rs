@@ -0,0 +1,473 @@
+//! Pressure projection
+//!
+//! Removes the divergent part of a velocity field by solving a poisson equation
+//! for the pressure.
+//!
+//! Solid obstacles not aligned with the grid can be described by the fraction
+//! of each edge open to the fluid, resulting in the variational formulation of [BBB07].
+//! Moving solids additionally prescribe their velocity on the covered part of the edges.
+//!
+//! A divergence source enforces `∇·u = s` instead, e.g. for expanding gases.
+//! Sources in closed domains need to integrate to zero.
+//!
+//! Variable fluid densities scale the pressure gradient by `1/ρ`, solving
+//! `∇·(1/ρ ∇p) = ∇·u*`. The densities are given per edge and enter the laplacian
+//! as weights of the inverse edge hodge star (see `dec::weighted`), so density
+//! jumps at air/water interfaces or in buoyant smoke accelerate both sides consistently.
+//!
+//! Liquids with a free surface fix the pressure to zero in the air. Instead of
+//! placing the boundary at the centers of the air cells, which snaps the surface
+//! to the grid, the ghost fluid method [GFCK02] [ENGF03] extrapolates the pressure
+//! linearly to the interface given by the liquid level set. The edges crossing
+//! the surface are weighted by `1/θ` with `θ` the liquid fraction of the edge,
+//! keeping the system symmetric. A pressure jump across the surface, e.g. from
+//! surface tension, replaces the zero air pressure by a prescribed one [KFL00].
+//!
+//! On periodic domains or domains with holes the projection leaves the harmonic
+//! component (net flux, circulation around holes) unchanged, including the drift
+//! accumulated by advection. It can be prescribed with a `HarmonicBasis`.
+//!
+//! The operators of each solver iteration run on the thread pool of the
+//! projection's `Executor`.
+//!
+//! References:
+//!     [BBB07] Christopher Batty, Florence Bertails, and Robert Bridson, 2007,
+//!             A fast variational framework for accurate solid-fluid coupling,
+//!             ACM Trans. Graph. 26, 3, Article 100 (July 2007)
+//!     [GFCK02] Frederic Gibou, Ronald Fedkiw, Li-Tien Cheng, and Myungjoo Kang, 2002,
+//!              A second-order-accurate symmetric discretization of the Poisson equation on irregular domains,
+//!              Journal of Computational Physics 176, 205-227
+//!     [ENGF03] Douglas Enright, Duc Nguyen, Frederic Gibou, and Ronald Fedkiw, 2003,
+//!              Using the particle level set method and a second order accurate pressure boundary condition for free surface flows,
+//!              In Proceedings of the 4th ASME-JSME Joint Fluids Engineering Conference, FEDSM2003-45144
+//!     [KFL00] Myungjoo Kang, Ronald Fedkiw, and Xu-Dong Liu, 2000,
+//!             A boundary condition capturing method for multiphase incompressible flow,
+//!             Journal of Scientific Computing 15 (3), 323-360
+
+use math::{LinearView, Real};
+use pcg;
+use super::harmonic::HarmonicBasis;
+use super::manifold::Manifold2d;
+use super::parallel::Executor;
+use super::pool::FieldPool;
+
+pub struct Projection<'a, T, M: Manifold2d<T> + 'a> {
+    manifold: &'a M,
+    divergence: M::Simplex2,
+
+    // conjugate gradient
+    residual: M::Simplex2,
+    auxiliary: M::Simplex2,
+    search: M::Simplex2,
+    // temporaries of the operator applications
+    pool: FieldPool<'a, T, M>,
+
+    pub max_iterations: usize,
+    pub threshold: T,
+
+    /// Fraction of each edge open to the fluid, `None` if the whole domain is fluid.
+    pub fluid_fractions: Option<M::Simplex1>,
+    /// Velocity of the solids on each edge as dual 1-form, `None` if all solids are at rest.
+    pub solid_velocity: Option<M::Simplex1>,
+    /// Divergence of the projected velocity as primal 2-form (integrated over the faces).
+    pub divergence_source: Option<M::Simplex2>,
+    /// Fluid density on each edge, `None` for unit density.
+    ///
+    /// Densities of the cells are averaged with `grid::average_to_edges_2d`.
+    pub edge_density: Option<M::Simplex1>,
+    /// Liquid mask of the faces (`1` inside the liquid, `0` in the air) and ghost fluid
+    /// weights of the edges, `None` if the whole domain is fluid.
+    ///
+    /// Built from the liquid level set by `levelset::free_surface`.
+    pub free_surface: Option<(M::Simplex2, M::Simplex1)>,
+    /// Pressure of the air at the free surface as point values on the faces, `None` for zero.
+    ///
+    /// Only used together with `free_surface`, e.g. the surface tension jump
+    /// from `levelset::surface_tension_pressure`.
+    pub surface_pressure: Option<M::Simplex2>,
+    /// Harmonic basis of the manifold and the coordinates the projected velocity should have.
+    pub harmonic: Option<(HarmonicBasis<T, M>, Vec<T>)>,
+    /// Thread pool for the operator applications, defaults to the global pool.
+    pub executor: Executor,
+}
+
+impl<'a, T, M> Projection<'a, T, M>
+where
+    T: Real,
+    M: Manifold2d<T> + Sync + 'a,
+    M::Simplex0: LinearView<Elem = T> + Send,
+    M::Simplex1: LinearView<Elem = T> + Send,
+    M::Simplex2: LinearView<Elem = T> + Send,
+{
+    pub fn new(manifold: &'a M, max_iterations: usize, threshold: T) -> Self {
+        Projection {
+            manifold,
+            divergence: manifold.new_simplex_2(),
+            residual: manifold.new_simplex_2(),
+            auxiliary: manifold.new_simplex_2(),
+            search: manifold.new_simplex_2(),
+            pool: FieldPool::new(manifold),
+            max_iterations,
+            threshold,
+            fluid_fractions: None,
+            solid_velocity: None,
+            divergence_source: None,
+            edge_density: None,
+            free_surface: None,
+            surface_pressure: None,
+            harmonic: None,
+            executor: Executor::default(),
+        }
+    }
+
+    /// Make the velocity field divergence-free.
+    ///
+    /// The velocity is a dual 1-form, the resulting pressure is a dual 0-form
+    /// stored on the primal faces.
+    /// Flux over closed boundaries isn't modified and needs to be compatible (zero net flux),
+    /// open and periodic boundaries are handled by the dual derivative of the manifold.
+    /// Velocities on edges fully covered by solids are set to the solid velocity.
+    /// The harmonic component is replaced by the prescribed one if `harmonic` is set.
+    /// With a `free_surface` the pressure in the air is zero or the `surface_pressure`
+    /// and only edges touching the liquid are updated.
+    ///
+    /// Ref: [BBB07] Sec. 4, [GFCK02], [KFL00]
+    pub fn project(&mut self, velocity: &mut M::Simplex1, pressure: &mut M::Simplex2, timestep: T) {
+        let executor = self.executor.clone();
+        executor.install(|| self.project_in_pool(velocity, pressure, timestep));
+    }
+
+    /// `project` on the current thread pool.
+    fn project_in_pool(&mut self, velocity: &mut M::Simplex1, pressure: &mut M::Simplex2, timestep: T) {
+        let m = self.manifold;
+        let fractions = self.fluid_fractions.as_ref();
+        let solid_velocity = self.solid_velocity.as_ref();
+        let density = self.edge_density.as_ref();
+        let liquid = self.free_surface.as_ref().map(|&(ref mask, _)| mask);
+        let ghost_weights = self.free_surface.as_ref().map(|&(_, ref weights)| weights);
+        let (mut edges_primal, mut edges_dual) = (self.pool.take_1(), self.pool.take_1());
+        let (mut faces_primal, mut faces_dual) = (self.pool.take_2(), self.pool.take_2());
+
+        // -div of the combined flux `F u + (1 - F) u_solid`
+        {
+            let (velocity_primal, flux) = (&mut edges_primal, &mut edges_dual);
+            flux.view_linear_mut().assign(&velocity.view_linear());
+            apply_fractions(flux, fractions);
+            if let (Some(fractions), Some(solid)) = (fractions, solid_velocity) {
+                let (fractions, solid) = (fractions.view_linear(), solid.view_linear());
+                for ((f, &fraction), &v) in flux.view_linear_mut().iter_mut().zip(fractions.iter()).zip(solid.iter()) {
+                    *f = *f + (T::one() - fraction) * v;
+                }
+            }
+            m.hodge_1_dual(velocity_primal, flux);
+            m.derivative_1_primal(&mut self.divergence, velocity_primal);
+            for div in self.divergence.view_linear_mut().iter_mut() {
+                *div = -*div;
+            }
+            if let Some(ref source) = self.divergence_source {
+                self.divergence.view_linear_mut().zip_mut_with(&source.view_linear(), |div, &s| *div = *div + s);
+            }
+            // zero pressure in the air
+            apply_fractions(&mut self.divergence, liquid);
+        }
+
+        // prescribed pressure in the air moves to the right hand side
+        let mut air_pressure = None;
+        if let (Some(liquid), Some(surface)) = (liquid, self.surface_pressure.as_ref()) {
+            let mut air = self.pool.take_2();
+            m.hodge_0_dual(&mut air, surface);
+            air.view_linear_mut().zip_mut_with(&liquid.view_linear(), |p, &inside| *p = *p * (T::one() - inside));
+
+            m.hodge_2_primal(&mut faces_dual, &air);
+            m.derivative_0_dual(&mut edges_dual, &faces_dual);
+            apply_fractions(&mut edges_dual, fractions);
+            apply_fractions(&mut edges_dual, ghost_weights);
+            apply_inverse_density(&mut edges_dual, density);
+            m.hodge_1_dual(&mut edges_primal, &edges_dual);
+            m.derivative_1_primal(&mut faces_primal, &edges_primal);
+            apply_fractions(&mut faces_primal, Some(liquid));
+            self.divergence.view_linear_mut().scaled_add(-timestep, &faces_primal.view_linear());
+            air_pressure = Some(air);
+        }
+
+        // weighted laplacian `d ★ W/ρ d ★` on the liquid, identity in the air
+        pcg::precond_conjugate_gradient(
+            &(), pressure, &self.divergence,
+            self.max_iterations, self.threshold,
+            &mut self.residual, &mut self.auxiliary, &mut self.search,
+            |out: &mut M::Simplex2, p: &M::Simplex2| {
+                faces_primal.view_linear_mut().assign(&p.view_linear());
+                apply_fractions(&mut faces_primal, liquid);
+                m.hodge_2_primal(&mut faces_dual, &faces_primal);
+                m.derivative_0_dual(&mut edges_dual, &faces_dual);
+                apply_fractions(&mut edges_dual, fractions);
+                apply_fractions(&mut edges_dual, ghost_weights);
+                apply_inverse_density(&mut edges_dual, density);
+                m.hodge_1_dual(&mut edges_primal, &edges_dual);
+                m.derivative_1_primal(out, &edges_primal);
+                apply_fractions(out, liquid);
+                for x in out.view_linear_mut().iter_mut() {
+                    *x = *x * timestep;
+                }
+                if let Some(liquid) = liquid {
+                    let (mut out, p) = (out.view_linear_mut(), p.view_linear());
+                    for (i, &inside) in liquid.view_linear().iter().enumerate() {
+                        if inside <= T::zero() {
+                            out[i] = p[i] * timestep;
+                        }
+                    }
+                }
+            });
+        apply_fractions(pressure, liquid);
+        if let Some(air) = air_pressure {
+            pressure.view_linear_mut().scaled_add(T::one(), &air.view_linear());
+            self.pool.recycle_2(air);
+        }
+
+        // subtract pressure gradient, see `pressure_gradient`
+        m.hodge_2_primal(&mut faces_dual, pressure);
+        m.derivative_0_dual(&mut edges_dual, &faces_dual);
+        apply_fractions(&mut edges_dual, ghost_weights);
+        apply_inverse_density(&mut edges_dual, density);
+        velocity.view_linear_mut().scaled_add(timestep, &edges_dual.view_linear());
+        self.pool.recycle_1(edges_primal);
+        self.pool.recycle_1(edges_dual);
+        self.pool.recycle_2(faces_primal);
+        self.pool.recycle_2(faces_dual);
+
+        if let Some((ref basis, ref coefficients)) = self.harmonic {
+            basis.set_coefficients(m, velocity, coefficients);
+        }
+
+        if let Some(fractions) = fractions {
+            let mut velocity = velocity.view_linear_mut();
+            let solid = solid_velocity.map(|solid| solid.view_linear());
+            for (i, &fraction) in fractions.view_linear().iter().enumerate() {
+                if fraction <= T::zero() {
+                    velocity[i] = solid.as_ref().map_or(T::zero(), |solid| solid[i]);
+                }
+            }
+        }
+    }
+
+    /// Negative pressure gradient `d ★ p` on the edges, as added to the velocity by `project`.
+    ///
+    /// The pressure is scaled by the inverse fluid density and the ghost fluid weights.
+    pub fn pressure_gradient(&mut self, gradient: &mut M::Simplex1, pressure: &M::Simplex2) {
+        let mut pressure_dual = self.pool.take_2();
+        self.manifold.hodge_2_primal(&mut pressure_dual, pressure);
+        self.manifold.derivative_0_dual(gradient, &pressure_dual);
+        self.pool.recycle_2(pressure_dual);
+        apply_fractions(gradient, self.free_surface.as_ref().map(|&(_, ref weights)| weights));
+        apply_inverse_density(gradient, self.edge_density.as_ref());
+    }
+}
+
+/// Scale values by per-element weights, e.g. the fluid fractions of the edges.
+fn apply_fractions<T: Real, L: LinearView<Elem = T>>(edges: &mut L, fractions: Option<&L>) {
+    if let Some(fractions) = fractions {
+        edges.view_linear_mut().zip_mut_with(&fractions.view_linear(), |e, &fraction| *e = *e * fraction);
+    }
+}
+
+/// Divide edge values by the fluid densities.
+fn apply_inverse_density<T: Real, L: LinearView<Elem = T>>(edges: &mut L, density: Option<&L>) {
+    if let Some(density) = density {
+        edges.view_linear_mut().zip_mut_with(&density.view_linear(), |e, &rho| *e = *e / rho);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::grid::average_to_edges_2d;
+    use domain::{AxisBoundary, BoundaryCondition, Grid2d};
+    use levelset::{fluid_fractions, free_surface, surface_tension_pressure, LevelSet2d};
+    use math::{LinearView, LinearViewReal};
+    use ndarray::Array2;
+    use super::*;
+
+    #[test]
+    fn grid_2d_projection() {
+        let grid = Grid2d::new((8, 8));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut pressure = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+
+        {
+            let (mut vy, mut vx) = velocity.split_mut();
+            for ((y, x), v) in vy.slice_mut(s![1..-1, ..]).indexed_iter_mut() {
+                *v = ((y * 7 + x) as f64 * 0.37).sin();
+            }
+            for ((y, x), v) in vx.slice_mut(s![.., 1..-1]).indexed_iter_mut() {
+                *v = ((y * 5 + x) as f64 * 0.53).cos();
+            }
+        }
+
+        Projection::new(&grid, 200, 1.0e-8).project(&mut velocity, &mut pressure, 0.1);
+
+        let mut velocity_primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_1_dual(&mut velocity_primal, &velocity);
+        grid.derivative_1_primal(&mut divergence, &velocity_primal);
+
+        assert!(divergence.norm_max() < 1.0e-6, "{:#?}", divergence.view_linear());
+    }
+
+    #[test]
+    fn grid_2d_projection_open_periodic() {
+        let boundary = [AxisBoundary::uniform(BoundaryCondition::Dirichlet), AxisBoundary::periodic()];
+        let grid = Grid2d::with_boundary((8, 8), boundary);
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut pressure = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+
+        {
+            let (mut vy, mut vx) = velocity.split_mut();
+            for ((y, x), v) in vy.indexed_iter_mut() {
+                *v = ((y * 7 + x) as f64 * 0.37).sin();
+            }
+            for ((y, x), v) in vx.indexed_iter_mut() {
+                *v = ((y * 5 + x % 8) as f64 * 0.53).cos();
+            }
+        }
+
+        Projection::new(&grid, 200, 1.0e-8).project(&mut velocity, &mut pressure, 0.1);
+
+        let mut velocity_primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_1_dual(&mut velocity_primal, &velocity);
+        grid.derivative_1_primal(&mut divergence, &velocity_primal);
+        assert!(divergence.norm_max() < 1.0e-6);
+
+        // periodic boundary edges stay in sync
+        let (_, vx) = velocity.split();
+        for y in 0..8 {
+            assert!((vx[(y, 0)] - vx[(y, 8)]).abs() < 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn grid_2d_projection_variable_density() {
+        let grid = Grid2d::new((12, 8));
+        let mut initial = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (mut vy, mut vx) = initial.split_mut();
+            for ((y, x), v) in vy.slice_mut(s![1..-1, ..]).indexed_iter_mut() {
+                *v = ((y * 7 + x) as f64 * 0.37).sin();
+            }
+            for ((y, x), v) in vx.slice_mut(s![.., 1..-1]).indexed_iter_mut() {
+                *v = ((y * 5 + x) as f64 * 0.53).cos();
+            }
+        }
+
+        // uniform density only scales the pressure
+        let (mut velocity, mut pressure) = (initial.clone(), <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid));
+        Projection::new(&grid, 500, 1.0e-10).project(&mut velocity, &mut pressure, 0.1);
+        let mut heavy = Projection::new(&grid, 500, 1.0e-10);
+        heavy.edge_density = Some(average_to_edges_2d(&grid, &Array2::from_elem((12, 8), 2.0)));
+        let (mut velocity_heavy, mut pressure_heavy) = (initial.clone(), <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid));
+        heavy.project(&mut velocity_heavy, &mut pressure_heavy, 0.1);
+
+        velocity_heavy.view_linear_mut().scaled_add(-1.0, &velocity.view_linear());
+        assert!(velocity_heavy.norm_max() < 1.0e-6);
+        pressure_heavy.scaled_add(-2.0, &pressure);
+        assert!(pressure_heavy.norm_max() < 1.0e-5);
+
+        // water below air, the heavy fluid is accelerated less by the same pressure
+        let density = Array2::from_shape_fn((12, 8), |(y, _)| if y < 6 { 1000.0 } else { 1.0 });
+        let mut projection = Projection::new(&grid, 1000, 1.0e-10);
+        projection.edge_density = Some(average_to_edges_2d(&grid, &density));
+        let (mut velocity, mut pressure) = (initial.clone(), <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid));
+        projection.project(&mut velocity, &mut pressure, 0.1);
+
+        let mut velocity_primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_1_dual(&mut velocity_primal, &velocity);
+        grid.derivative_1_primal(&mut divergence, &velocity_primal);
+        assert!(divergence.norm_max() < 1.0e-6);
+
+        let mut change = velocity.clone();
+        change.view_linear_mut().scaled_add(-1.0, &initial.view_linear());
+        let (change_y, _) = change.split();
+        let max_change = |rows: ::std::ops::Range<usize>| {
+            rows.flat_map(|y| (0..8).map(move |x| (y, x))).fold(0.0f64, |max, idx| max.max(change_y[idx].abs()))
+        };
+        assert!(max_change(1..6) < max_change(7..12));
+    }
+
+    #[test]
+    fn grid_2d_projection_obstacle() {
+        let grid = Grid2d::new((16, 16));
+        let solid = LevelSet2d::from_fn(&grid, |(y, x): (f64, f64)| ((y - 8.0).powi(2) + (x - 7.5).powi(2)).sqrt() - 3.3);
+        let fractions = fluid_fractions(&grid, &solid);
+
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut pressure = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        velocity.split_mut().0.slice_mut(s![1..-1, ..]).fill(1.0);
+
+        let mut projection = Projection::new(&grid, 500, 1.0e-8);
+        projection.fluid_fractions = Some(fractions.clone());
+        projection.project(&mut velocity, &mut pressure, 0.1);
+
+        // weighted divergence vanishes
+        let mut flux = velocity.clone();
+        flux.view_linear_mut().zip_mut_with(&fractions.view_linear(), |v, &f| *v = *v * f);
+        let mut velocity_primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_1_dual(&mut velocity_primal, &flux);
+        grid.derivative_1_primal(&mut divergence, &velocity_primal);
+        assert!(divergence.norm_max() < 1.0e-6);
+
+        // flow goes around the obstacle
+        let (vertical, _) = velocity.split();
+        assert!(vertical[(8, 7)].abs() < 1.0e-10);
+    }
+
+    #[test]
+    fn grid_2d_projection_free_surface() {
+        // liquid at rest below the surface at `y = 5.3`, falling with unit speed
+        let grid = Grid2d::new((10, 6));
+        let liquid = LevelSet2d::from_fn(&grid, |(y, _): (f64, f64)| y - 5.3);
+
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut pressure = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        velocity.split_mut().0.slice_mut(s![1..-1, ..]).fill(-1.0);
+
+        let mut projection = Projection::new(&grid, 500, 1.0e-10);
+        projection.free_surface = Some(free_surface(&grid, &liquid));
+        projection.project(&mut velocity, &mut pressure, 0.1);
+
+        // the wall stops the liquid including the surface edges
+        let (vertical, _) = velocity.split();
+        for y in 1..6 {
+            for x in 0..6 {
+                assert!(vertical[(y, x)].abs() < 1.0e-6, "{:?} {}", (y, x), vertical[(y, x)]);
+            }
+        }
+
+        // hydrostatic pressure vanishes on the interface, not at the air cell centers
+        let gradient = pressure[(0, 0)] / 4.8;
+        assert!(gradient.abs() > 1.0e-3);
+        for ((y, _), &p) in pressure.indexed_iter() {
+            let expected = if y < 5 { gradient * (4.8 - y as f64) } else { 0.0 };
+            assert!((p - expected).abs() < 1.0e-6 * gradient.abs(), "{} {} {}", y, p, expected);
+        }
+    }
+
+    #[test]
+    fn grid_2d_projection_surface_tension() {
+        // droplet at rest, the pressure inside balances the surface tension `σ/R`
+        let grid = Grid2d::new((24, 24));
+        let (radius, coefficient) = (6.0, 0.5);
+        let liquid = LevelSet2d::from_fn(&grid, |(y, x): (f64, f64)| ((y - 12.0).powi(2) + (x - 12.0).powi(2)).sqrt() - radius);
+
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut pressure = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        let mut projection = Projection::new(&grid, 500, 1.0e-10);
+        projection.free_surface = Some(free_surface(&grid, &liquid));
+        projection.surface_pressure = Some(surface_tension_pressure(&grid, &liquid, coefficient));
+        projection.project(&mut velocity, &mut pressure, 0.1);
+
+        let expected = coefficient / radius;
+        assert!((pressure[(12, 12)] - expected).abs() < 0.05 * expected, "{} {}", pressure[(12, 12)], expected);
+        assert!(velocity.norm_max() < 0.1 * expected);
+    }
+}
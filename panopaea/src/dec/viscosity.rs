@@ -0,0 +1,187 @@
+//! Implicit viscosity
+//!
+//! Diffuses the velocity field with backward Euler, `(I - ν dt Δ) u' = u`,
+//! which stays stable for arbitrary viscosities and timesteps. The vector
+//! laplacian acts on each staggered velocity component separately with the
+//! five-point stencil of the grid spacing.
+//!
+//! Normal velocities on the boundary edges are kept fixed. Tangential
+//! velocities mirror at free-slip and open boundaries (zero gradient) and
+//! vanish halfway between the samples and no-slip walls.
+//!
+//! References:
+//!     [Bri15] Robert Bridson, 2015,
+//!             Fluid simulation for computer graphics, Second Edition,
+//!             CRC Press
+
+use domain::{AxisBoundary, BoundaryCondition, Grid2d};
+use math::Real;
+use ndarray::{ArrayView2, ArrayViewMut2};
+use pcg;
+use super::grid::Staggered2d;
+use super::manifold::Manifold2d;
+
+pub struct Viscosity<'a, T: Real> {
+    grid: &'a Grid2d,
+    rhs: Staggered2d<T>,
+
+    // conjugate gradient
+    residual: Staggered2d<T>,
+    auxiliary: Staggered2d<T>,
+    search: Staggered2d<T>,
+
+    pub max_iterations: usize,
+    pub threshold: T,
+}
+
+impl<'a, T: Real> Viscosity<'a, T> {
+    pub fn new(grid: &'a Grid2d, max_iterations: usize, threshold: T) -> Self {
+        Viscosity {
+            grid,
+            rhs: grid.new_simplex_1(),
+            residual: grid.new_simplex_1(),
+            auxiliary: grid.new_simplex_1(),
+            search: grid.new_simplex_1(),
+            max_iterations,
+            threshold,
+        }
+    }
+
+    /// Diffuse the velocity field with the kinematic viscosity `ν`.
+    ///
+    /// Ref: [Bri15] Sec. 10.2
+    pub fn diffuse(&mut self, velocity: &mut Staggered2d<T>, viscosity: T, timestep: T) {
+        let grid = self.grid;
+        let (dy, dx) = grid.spacing();
+        let scale = viscosity * timestep;
+        let weights = [scale / T::new(dy * dy), scale / T::new(dx * dx)];
+
+        // couplings to the fixed boundary values are moved to the right hand side
+        apply(grid, &mut self.rhs, velocity, weights, true);
+        pcg::precond_conjugate_gradient(
+            &(), velocity, &self.rhs,
+            self.max_iterations, self.threshold,
+            &mut self.residual, &mut self.auxiliary, &mut self.search,
+            |out: &mut Staggered2d<T>, u: &Staggered2d<T>| apply(grid, out, u, weights, false));
+
+        // duplicated samples of periodic axes
+        let (h, w) = grid.dim();
+        let [by, bx] = grid.boundary();
+        let (mut vertical, mut horizontal) = velocity.split_mut();
+        if by.is_periodic() {
+            let first = vertical.row(0).to_owned();
+            vertical.row_mut(h).assign(&first);
+        }
+        if bx.is_periodic() {
+            let first = horizontal.column(0).to_owned();
+            horizontal.column_mut(w).assign(&first);
+        }
+    }
+}
+
+/// Apply the system matrix `I - ν dt Δ` or assemble the right hand side.
+fn apply<T: Real>(grid: &Grid2d, out: &mut Staggered2d<T>, input: &Staggered2d<T>, weights: [T; 2], rhs: bool) {
+    let boundary = grid.boundary();
+    let (out_vertical, out_horizontal) = out.split_mut();
+    let (vertical, horizontal) = input.split();
+    apply_component(out_vertical, vertical, 0, boundary, weights, rhs);
+    apply_component(out_horizontal, horizontal, 1, boundary, weights, rhs);
+}
+
+enum Neighbor {
+    Free([usize; 2]),
+    Fixed([usize; 2]),
+    Ghost(BoundaryCondition),
+}
+
+/// Velocity component normal to the edges along `normal`.
+///
+/// Samples lie on the nodes along the normal axis and on the cell centers along the other axis.
+fn apply_component<T: Real>(
+    mut out: ArrayViewMut2<T>,
+    input: ArrayView2<T>,
+    normal: usize,
+    boundary: [AxisBoundary; 2],
+    weights: [T; 2],
+    rhs: bool,
+) {
+    let dim = [input.dim().0, input.dim().1];
+    let n = dim[normal] - 1;
+    let periodic_normal = boundary[normal].is_periodic();
+    let is_fixed = |k: usize| k == n || (k == 0 && !periodic_normal);
+
+    let neighbor = |idx: [usize; 2], axis: usize, lower: bool| {
+        let mut idx = idx;
+        let len = if axis == normal { n } else { dim[axis] };
+        let k = idx[axis];
+        if boundary[axis].is_periodic() {
+            idx[axis] = if lower { (k + len - 1) % len } else { (k + 1) % len };
+            return Neighbor::Free(idx);
+        }
+        if axis != normal && ((lower && k == 0) || (!lower && k + 1 == len)) {
+            return Neighbor::Ghost(if lower { boundary[axis].lower } else { boundary[axis].upper });
+        }
+        idx[axis] = if lower { k - 1 } else { k + 1 };
+        if axis == normal && is_fixed(idx[axis]) {
+            Neighbor::Fixed(idx)
+        } else {
+            Neighbor::Free(idx)
+        }
+    };
+
+    for ((y, x), out) in out.indexed_iter_mut() {
+        let idx = [y, x];
+        let center = input[idx];
+        *out = center;
+        if is_fixed(idx[normal]) {
+            continue;
+        }
+
+        for axis in 0..2 {
+            let w = weights[axis];
+            for &lower in &[true, false] {
+                match neighbor(idx, axis, lower) {
+                    Neighbor::Free(j) => if !rhs { *out += w * (center - input[j]); },
+                    Neighbor::Fixed(j) => if rhs { *out += w * input[j]; } else { *out += w * center; },
+                    Neighbor::Ghost(BoundaryCondition::NoSlip) => if !rhs { *out += T::new(2.0) * w * center; },
+                    Neighbor::Ghost(_) => (),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::{AxisBoundary, BoundaryCondition, Grid2d};
+    use super::*;
+
+    #[test]
+    fn implicit_viscosity() {
+        // shear flow between no-slip walls decays towards rest
+        let boundary = [AxisBoundary::uniform(BoundaryCondition::NoSlip), AxisBoundary::periodic()];
+        let grid = Grid2d::with_boundary((16, 8), boundary);
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().1.fill(1.0);
+
+        let mut viscosity = Viscosity::new(&grid, 500, 1.0e-10);
+        viscosity.diffuse(&mut velocity, 1.0e3, 1.0);
+        {
+            let (vertical, horizontal) = velocity.split();
+            assert!(vertical.iter().all(|&v| v == 0.0));
+            assert!(horizontal.iter().all(|&v| v > 0.0 && v < 0.05));
+            // uniform along the periodic axis, largest in the middle
+            assert!((horizontal[(8, 0)] - horizontal[(8, 5)]).abs() < 1.0e-8);
+            assert!(horizontal[(8, 3)] > horizontal[(0, 3)]);
+        }
+
+        // uniform flow along free-slip walls is unaffected
+        let grid = Grid2d::with_boundary((8, 8), [AxisBoundary::periodic(), AxisBoundary::default()]);
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().0.fill(1.0);
+        let mut viscosity = Viscosity::new(&grid, 500, 1.0e-10);
+        viscosity.diffuse(&mut velocity, 10.0, 1.0);
+        let (vertical, _) = velocity.split();
+        assert!(vertical.iter().all(|&v| (v - 1.0).abs() < 1.0e-8));
+    }
+}
@@ -0,0 +1,247 @@
+//! Spatially varying hodge stars
+//!
+//! Material parameters of variable-coefficient problems enter the DEC
+//! operators as diagonal weights of the hodge stars, e.g. the fluid density
+//! `ρ` as edge weight scales the inverse star by `1/ρ`, which yields the
+//! variable-density pressure laplacian `d ★₁⁻¹ d ★₂` of `∇·(1/ρ ∇p)`.
+//! Different weights for the edges of each direction result in anisotropic
+//! operators, e.g. for layered permeabilities.
+//!
+//! `Weighted` wraps a manifold and scales its primal hodge stars by optional
+//! per-element coefficients, the dual (inverse) stars by their reciprocal.
+//! Everything else is forwarded, so the wrapper can replace the manifold in
+//! the solvers.
+
+use math::{LinearView, Real};
+use sparse::{DiagonalMatrix, SparseMatrix};
+use super::manifold::{Hodge0, Hodge1, Hodge2, Manifold2d};
+
+/// Manifold with coefficient fields for the hodge stars, unweighted if `None`.
+///
+/// Coefficients need to be positive.
+pub struct Weighted<'a, T, M: Manifold2d<T> + 'a> {
+    manifold: &'a M,
+    /// Weights of `hodge_0_primal`, stored per vertex.
+    pub vertices: Option<M::Simplex0>,
+    /// Weights of `hodge_1_primal`, stored per edge.
+    pub edges: Option<M::Simplex1>,
+    /// Weights of `hodge_2_primal`, stored per face.
+    pub faces: Option<M::Simplex2>,
+}
+
+impl<'a, T, M: Manifold2d<T> + 'a> Weighted<'a, T, M> {
+    /// Unweighted manifold, behaves like the wrapped manifold.
+    pub fn new(manifold: &'a M) -> Self {
+        Weighted {
+            manifold,
+            vertices: None,
+            edges: None,
+            faces: None,
+        }
+    }
+
+    pub fn inner(&self) -> &'a M {
+        self.manifold
+    }
+}
+
+/// Multiply or divide element-wise by the coefficients.
+fn apply_weights<T, L>(simplex: &mut L, weights: Option<&L>, inverse: bool)
+    where T: Real, L: LinearView<Elem = T>
+{
+    if let Some(weights) = weights {
+        let weights = weights.view_linear();
+        if inverse {
+            simplex.view_linear_mut().zip_mut_with(&weights, |x, &w| *x = *x / w);
+        } else {
+            simplex.view_linear_mut().zip_mut_with(&weights, |x, &w| *x = *x * w);
+        }
+    }
+}
+
+/// Hodge matrix scaled by the coefficients or their reciprocal.
+fn weight_matrix<T, L>(mut matrix: DiagonalMatrix<T>, weights: Option<&L>, inverse: bool) -> DiagonalMatrix<T>
+    where T: Real, L: LinearView<Elem = T>
+{
+    if let Some(weights) = weights {
+        for (i, &w) in weights.view_linear().iter().enumerate() {
+            matrix[i] = if inverse { matrix[i] / w } else { matrix[i] * w };
+        }
+    }
+    matrix
+}
+
+impl<'a, T, M> Hodge0<T> for Weighted<'a, T, M>
+    where T: Real, M: Manifold2d<T>, M::Simplex0: LinearView<Elem = T>
+{
+    type Simplex0 = M::Simplex0;
+    fn apply(&self, dual: &mut Self::Simplex0, primal: &Self::Simplex0) {
+        <M as Hodge0<T>>::apply(self.manifold, dual, primal);
+        apply_weights(dual, self.vertices.as_ref(), false);
+    }
+
+    fn apply_inv(&self, primal: &mut Self::Simplex0, dual: &Self::Simplex0) {
+        <M as Hodge0<T>>::apply_inv(self.manifold, primal, dual);
+        apply_weights(primal, self.vertices.as_ref(), true);
+    }
+}
+
+impl<'a, T, M> Hodge1<T> for Weighted<'a, T, M>
+    where T: Real, M: Manifold2d<T>, M::Simplex1: LinearView<Elem = T>
+{
+    type Simplex1 = M::Simplex1;
+    fn apply(&self, dual: &mut Self::Simplex1, primal: &Self::Simplex1) {
+        <M as Hodge1<T>>::apply(self.manifold, dual, primal);
+        apply_weights(dual, self.edges.as_ref(), false);
+    }
+
+    fn apply_inv(&self, primal: &mut Self::Simplex1, dual: &Self::Simplex1) {
+        <M as Hodge1<T>>::apply_inv(self.manifold, primal, dual);
+        apply_weights(primal, self.edges.as_ref(), true);
+    }
+}
+
+impl<'a, T, M> Hodge2<T> for Weighted<'a, T, M>
+    where T: Real, M: Manifold2d<T>, M::Simplex2: LinearView<Elem = T>
+{
+    type Simplex2 = M::Simplex2;
+    fn apply(&self, dual: &mut Self::Simplex2, primal: &Self::Simplex2) {
+        <M as Hodge2<T>>::apply(self.manifold, dual, primal);
+        apply_weights(dual, self.faces.as_ref(), false);
+    }
+
+    fn apply_inv(&self, primal: &mut Self::Simplex2, dual: &Self::Simplex2) {
+        <M as Hodge2<T>>::apply_inv(self.manifold, primal, dual);
+        apply_weights(primal, self.faces.as_ref(), true);
+    }
+}
+
+impl<'a, T, M> Manifold2d<T> for Weighted<'a, T, M>
+where
+    T: Real,
+    M: Manifold2d<T>,
+    M::Simplex0: LinearView<Elem = T>,
+    M::Simplex1: LinearView<Elem = T>,
+    M::Simplex2: LinearView<Elem = T>,
+{
+    fn num_elem_0(&self) -> usize { self.manifold.num_elem_0() }
+    fn num_elem_1(&self) -> usize { self.manifold.num_elem_1() }
+    fn num_elem_2(&self) -> usize { self.manifold.num_elem_2() }
+
+    fn new_simplex_0(&self) -> Self::Simplex0 { self.manifold.new_simplex_0() }
+    fn new_simplex_1(&self) -> Self::Simplex1 { self.manifold.new_simplex_1() }
+    fn new_simplex_2(&self) -> Self::Simplex2 { self.manifold.new_simplex_2() }
+
+    fn boundary_0(&self) -> Vec<usize> { self.manifold.boundary_0() }
+    fn boundary_1(&self) -> Vec<usize> { self.manifold.boundary_1() }
+    fn boundary_2(&self) -> Vec<usize> { self.manifold.boundary_2() }
+
+    fn derivative_0_primal(&self, edges: &mut Self::Simplex1, vertices: &Self::Simplex0) {
+        self.manifold.derivative_0_primal(edges, vertices)
+    }
+    fn derivative_0_dual(&self, edges: &mut Self::Simplex1, faces: &Self::Simplex2) {
+        self.manifold.derivative_0_dual(edges, faces)
+    }
+    fn derivative_1_primal(&self, faces: &mut Self::Simplex2, edges: &Self::Simplex1) {
+        self.manifold.derivative_1_primal(faces, edges)
+    }
+    fn derivative_1_dual(&self, vertices: &mut Self::Simplex0, edges: &Self::Simplex1) {
+        self.manifold.derivative_1_dual(vertices, edges)
+    }
+
+    fn inner_product_1_dual(&self, a: &Self::Simplex1, b: &Self::Simplex1) -> T {
+        match self.edges {
+            Some(ref edges) => {
+                let mut scaled = self.manifold.new_simplex_1();
+                scaled.view_linear_mut().assign(&b.view_linear());
+                apply_weights(&mut scaled, Some(edges), true);
+                self.manifold.inner_product_1_dual(a, &scaled)
+            }
+            None => self.manifold.inner_product_1_dual(a, b),
+        }
+    }
+
+    fn wedge_01(&self, out: &mut Self::Simplex1, a: &Self::Simplex0, b: &Self::Simplex1) {
+        self.manifold.wedge_01(out, a, b)
+    }
+    fn wedge_11(&self, out: &mut Self::Simplex2, a: &Self::Simplex1, b: &Self::Simplex1) {
+        self.manifold.wedge_11(out, a, b)
+    }
+    fn wedge_02(&self, out: &mut Self::Simplex2, a: &Self::Simplex0, b: &Self::Simplex2) {
+        self.manifold.wedge_02(out, a, b)
+    }
+
+    fn derivative_0_primal_matrix(&self) -> SparseMatrix<T> { self.manifold.derivative_0_primal_matrix() }
+    fn derivative_0_dual_matrix(&self) -> SparseMatrix<T> { self.manifold.derivative_0_dual_matrix() }
+    fn derivative_1_primal_matrix(&self) -> SparseMatrix<T> { self.manifold.derivative_1_primal_matrix() }
+    fn derivative_1_dual_matrix(&self) -> SparseMatrix<T> { self.manifold.derivative_1_dual_matrix() }
+
+    fn hodge_0_primal_matrix(&self) -> DiagonalMatrix<T> {
+        weight_matrix(self.manifold.hodge_0_primal_matrix(), self.vertices.as_ref(), false)
+    }
+    fn hodge_1_primal_matrix(&self) -> DiagonalMatrix<T> {
+        weight_matrix(self.manifold.hodge_1_primal_matrix(), self.edges.as_ref(), false)
+    }
+    fn hodge_2_primal_matrix(&self) -> DiagonalMatrix<T> {
+        weight_matrix(self.manifold.hodge_2_primal_matrix(), self.faces.as_ref(), false)
+    }
+
+    fn hodge_0_dual_matrix(&self) -> DiagonalMatrix<T> {
+        weight_matrix(self.manifold.hodge_0_dual_matrix(), self.faces.as_ref(), true)
+    }
+    fn hodge_1_dual_matrix(&self) -> DiagonalMatrix<T> {
+        weight_matrix(self.manifold.hodge_1_dual_matrix(), self.edges.as_ref(), true)
+    }
+    fn hodge_2_dual_matrix(&self) -> DiagonalMatrix<T> {
+        weight_matrix(self.manifold.hodge_2_dual_matrix(), self.vertices.as_ref(), true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::Grid2d;
+    use math::LinearViewReal;
+    use ndarray::Array2;
+    use super::*;
+
+    #[test]
+    fn variable_coefficient_laplacian() {
+        // two-phase density ratio with anisotropic edge weights
+        let grid = Grid2d::new((6, 7));
+        let mut weighted = Weighted::<f64, Grid2d>::new(&grid);
+        let mut weights = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (mut vertical, mut horizontal) = weights.split_mut();
+            for ((y, _), w) in vertical.indexed_iter_mut() {
+                *w = if y < 3 { 1.0 } else { 0.001 };
+            }
+            horizontal.fill(0.5);
+        }
+        weighted.edges = Some(weights);
+
+        let x = Array2::from_shape_fn((6, 7), |(y, x)| ((y * 7 + x) as f64 * 0.37).sin());
+
+        // operator chain `d1 ★1⁻¹(w) d0 ★2` matches the assembled matrices
+        let mut dual = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        let (mut edges, mut primal) = (weighted.new_simplex_1(), weighted.new_simplex_1());
+        let mut result = Array2::zeros((6, 7));
+        weighted.hodge_2_primal(&mut dual, &x);
+        weighted.derivative_0_dual(&mut edges, &dual);
+        weighted.hodge_1_dual(&mut primal, &edges);
+        weighted.derivative_1_primal(&mut result, &primal);
+
+        let matrix = &(&(&weighted.derivative_1_primal_matrix() * &weighted.hodge_1_dual_matrix())
+            * &weighted.derivative_0_dual_matrix()) * &weighted.hodge_2_primal_matrix();
+        let mut expected = Array2::zeros((6, 7));
+        matrix.mul_vec(expected.view_linear_mut(), x.view_linear());
+        expected.scaled_add(-1.0, &result);
+        assert!(expected.norm_max() < 1.0e-12);
+
+        // primal and dual stars are inverse to each other, up to the sign `★★ = -1` of 2d 1-forms
+        weighted.hodge_1_primal(&mut edges, &primal);
+        let mut roundtrip = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        weighted.hodge_1_dual(&mut roundtrip, &edges);
+        roundtrip.view_linear_mut().scaled_add(1.0, &primal.view_linear());
+        assert!(roundtrip.norm_max() < 1.0e-12);
+    }
+}
@@ -0,0 +1,93 @@
+use std::f64;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Message passing between the ranks of a distributed run.
+///
+/// Messages between two ranks arrive in the order they have been sent, all
+/// ranks need to call the collective operations in the same order.
+pub trait Communicator {
+    fn rank(&self) -> usize;
+    fn size(&self) -> usize;
+
+    /// Send `send` to `dest` and receive `receive` from `source`.
+    ///
+    /// Either side may be `None`, e.g. for ranks at a domain wall.
+    fn send_receive(&self, send: &[f64], dest: Option<usize>, receive: &mut [f64], source: Option<usize>);
+
+    /// Sum of `value` over all ranks, identical on every rank.
+    fn all_reduce_sum(&self, value: f64) -> f64;
+
+    /// Maximum of `value` over all ranks.
+    fn all_reduce_max(&self, value: f64) -> f64;
+}
+
+/// Ranks running as threads of a single process, connected by channels.
+pub struct ThreadComm {
+    rank: usize,
+    /// Channel to each rank.
+    senders: Vec<Sender<Vec<f64>>>,
+    /// Channel from each rank.
+    receivers: Vec<Receiver<Vec<f64>>>,
+}
+
+impl ThreadComm {
+    /// Communicators of `size` ranks, one to be moved into each thread.
+    pub fn create(size: usize) -> Vec<ThreadComm> {
+        assert!(size > 0, "at least one rank required");
+        let mut senders = (0..size).map(|_| Vec::with_capacity(size)).collect::<Vec<_>>();
+        let mut receivers = (0..size).map(|_| Vec::with_capacity(size)).collect::<Vec<_>>();
+        for from in 0..size {
+            for to in 0..size {
+                let (sender, receiver) = channel();
+                senders[from].push(sender);
+                receivers[to].push(receiver);
+            }
+        }
+
+        senders.into_iter()
+            .zip(receivers.into_iter())
+            .enumerate()
+            .map(|(rank, (senders, receivers))| ThreadComm { rank, senders, receivers })
+            .collect()
+    }
+
+    /// Values of all ranks, ordered by rank.
+    fn all_gather(&self, value: f64) -> Vec<f64> {
+        for sender in &self.senders {
+            sender.send(vec![value]).expect("rank disconnected");
+        }
+        self.receivers.iter()
+            .map(|receiver| receiver.recv().expect("rank disconnected")[0])
+            .collect()
+    }
+}
+
+impl Communicator for ThreadComm {
+    fn rank(&self) -> usize {
+        self.rank
+    }
+
+    fn size(&self) -> usize {
+        self.senders.len()
+    }
+
+    fn send_receive(&self, send: &[f64], dest: Option<usize>, receive: &mut [f64], source: Option<usize>) {
+        if let Some(dest) = dest {
+            self.senders[dest].send(send.to_vec()).expect("rank disconnected");
+        }
+        if let Some(source) = source {
+            let message = self.receivers[source].recv().expect("rank disconnected");
+            assert_eq!(message.len(), receive.len(), "message size mismatch");
+            receive.copy_from_slice(&message);
+        }
+    }
+
+    fn all_reduce_sum(&self, value: f64) -> f64 {
+        // summed in rank order to get bitwise identical results on all ranks
+        self.all_gather(value).iter().fold(0.0, |sum, &v| sum + v)
+    }
+
+    fn all_reduce_max(&self, value: f64) -> f64 {
+        self.all_gather(value).iter().fold(f64::NEG_INFINITY, |max, &v| max.max(v))
+    }
+}
@@ -0,0 +1,272 @@
+use domain::Grid3d;
+use math::Real;
+use ndarray::{Array3, ArrayView3, ArrayViewMut3, Axis};
+
+use super::comm::Communicator;
+
+/// Partition of a `Grid3d` into a tensor grid of blocks, one block per rank.
+#[derive(Copy, Clone, Debug)]
+pub struct Decomposition {
+    grid: Grid3d,
+    /// Number of blocks along `(z, y, x)`.
+    parts: (usize, usize, usize),
+    ghost: usize,
+}
+
+/// Start of block `i` of `parts` blocks along an axis with `n` cells.
+fn split(n: usize, parts: usize, i: usize) -> usize {
+    i * n / parts
+}
+
+impl Decomposition {
+    /// Partition into `ranks` blocks with `ghost` ghost layers, minimizing the interface area.
+    pub fn new(grid: Grid3d, ranks: usize, ghost: usize) -> Self {
+        let (d, h, w) = grid.dim();
+        let mut best = None;
+        for pz in (1..ranks + 1).filter(|&p| ranks % p == 0 && p <= d) {
+            for py in (1..ranks / pz + 1).filter(|&p| (ranks / pz) % p == 0 && p <= h) {
+                let px = ranks / (pz * py);
+                if px > w {
+                    continue;
+                }
+                let area = (pz - 1) * h * w + (py - 1) * d * w + (px - 1) * d * h;
+                if best.map_or(true, |(min, _)| area < min) {
+                    best = Some((area, (pz, py, px)));
+                }
+            }
+        }
+        let (_, parts) = best.expect("domain too small for the number of ranks");
+        Decomposition::with_parts(grid, parts, ghost)
+    }
+
+    /// Partition into `parts` blocks along `(z, y, x)`.
+    pub fn with_parts(grid: Grid3d, parts: (usize, usize, usize), ghost: usize) -> Self {
+        let (d, h, w) = grid.dim();
+        assert!(parts.0 > 0 && parts.1 > 0 && parts.2 > 0, "empty partition");
+        assert!(
+            d / parts.0 >= ghost.max(1) && h / parts.1 >= ghost.max(1) && w / parts.2 >= ghost.max(1),
+            "blocks need to be at least as large as the ghost layers"
+        );
+        Decomposition { grid, parts, ghost }
+    }
+
+    pub fn grid(&self) -> Grid3d {
+        self.grid
+    }
+
+    /// Number of blocks along `(z, y, x)`.
+    pub fn parts(&self) -> (usize, usize, usize) {
+        self.parts
+    }
+
+    pub fn num_subdomains(&self) -> usize {
+        self.parts.0 * self.parts.1 * self.parts.2
+    }
+
+    /// Block of `rank`, ranks are ordered with `x` varying fastest.
+    pub fn subdomain(&self, rank: usize) -> Subdomain {
+        assert!(rank < self.num_subdomains(), "rank {} out of range", rank);
+        let (pz, py, px) = self.parts;
+        let coords = [rank / (py * px), (rank / px) % py, rank % px];
+        let parts = [pz, py, px];
+        let (d, h, w) = self.grid.dim();
+        let dims = [d, h, w];
+        let boundary = self.grid.boundary();
+
+        let mut origin = [0; 3];
+        let mut dim = [0; 3];
+        let mut neighbors = [[None; 2]; 3];
+        for axis in 0..3 {
+            let (c, p) = (coords[axis], parts[axis]);
+            origin[axis] = split(dims[axis], p, c);
+            dim[axis] = split(dims[axis], p, c + 1) - origin[axis];
+
+            let periodic = boundary[axis].is_periodic();
+            let rank_at = |c: usize| {
+                let mut coords = coords;
+                coords[axis] = c;
+                (coords[0] * py + coords[1]) * px + coords[2]
+            };
+            neighbors[axis] = [
+                if c > 0 { Some(rank_at(c - 1)) } else if periodic { Some(rank_at(p - 1)) } else { None },
+                if c + 1 < p { Some(rank_at(c + 1)) } else if periodic { Some(rank_at(0)) } else { None },
+            ];
+        }
+
+        Subdomain {
+            rank,
+            origin: (origin[0], origin[1], origin[2]),
+            dim: (dim[0], dim[1], dim[2]),
+            ghost: self.ghost,
+            neighbors,
+        }
+    }
+}
+
+/// Block of a decomposed domain owned by one rank.
+///
+/// Local fields have `ghost` layers on each side of the block interior.
+/// Ghost layers at domain walls repeat the closest interior layer.
+#[derive(Copy, Clone, Debug)]
+pub struct Subdomain {
+    pub rank: usize,
+    /// Global index of the first interior cell.
+    pub origin: (usize, usize, usize),
+    /// Number of interior cells.
+    pub dim: (usize, usize, usize),
+    ghost: usize,
+    /// Lower and upper neighbor rank of the `[z, y, x]` axes.
+    neighbors: [[Option<usize>; 2]; 3],
+}
+
+impl Subdomain {
+    pub fn ghost(&self) -> usize {
+        self.ghost
+    }
+
+    /// Lower and upper neighbor rank along an axis, `None` at domain walls.
+    pub fn neighbors(&self, axis: Axis) -> [Option<usize>; 2] {
+        self.neighbors[axis.0]
+    }
+
+    /// Dimension of local fields including ghost layers.
+    pub fn storage_dim(&self) -> (usize, usize, usize) {
+        let g = 2 * self.ghost;
+        (self.dim.0 + g, self.dim.1 + g, self.dim.2 + g)
+    }
+
+    pub fn new_field<T: Real>(&self) -> Array3<T> {
+        Array3::zeros(self.storage_dim())
+    }
+
+    /// Local field with the interior copied from a global field, e.g. for initialization.
+    pub fn scatter<T: Real>(&self, global: ArrayView3<T>) -> Array3<T> {
+        let mut field = self.new_field();
+        let (z, y, x) = self.origin;
+        let (d, h, w) = self.dim;
+        self.interior_mut(&mut field).assign(&global.slice(s![
+            z as isize..(z + d) as isize,
+            y as isize..(y + h) as isize,
+            x as isize..(x + w) as isize
+        ]));
+        field
+    }
+
+    pub fn interior<'a, T: Real>(&self, field: &'a Array3<T>) -> ArrayView3<'a, T> {
+        let g = self.ghost as isize;
+        let (d, h, w) = self.dim;
+        field.slice(s![g..g + d as isize, g..g + h as isize, g..g + w as isize])
+    }
+
+    pub fn interior_mut<'a, T: Real>(&self, field: &'a mut Array3<T>) -> ArrayViewMut3<'a, T> {
+        let g = self.ghost as isize;
+        let (d, h, w) = self.dim;
+        field.slice_mut(s![g..g + d as isize, g..g + h as isize, g..g + w as isize])
+    }
+
+    /// Refresh the ghost layers of a local field from the neighboring ranks.
+    ///
+    /// Axes are exchanged one after another including the ghost layers of
+    /// the previous axes, which fills edge and corner ghosts as well.
+    pub fn exchange<T: Real, C: Communicator>(&self, comm: &C, field: &mut Array3<T>) {
+        assert_eq!(field.dim(), self.storage_dim(), "local field dimension mismatch");
+        let g = self.ghost;
+        let dims = [self.dim.0, self.dim.1, self.dim.2];
+        for axis in 0..3 {
+            let n = dims[axis];
+            let (lower, upper) = (self.neighbors[axis][0], self.neighbors[axis][1]);
+            for k in 0..g {
+                // upwards: last interior layers into the lower ghosts of the upper neighbor
+                shift(comm, field, Axis(axis), n + k, upper, k, lower);
+            }
+            for k in 0..g {
+                // downwards: first interior layers into the upper ghosts of the lower neighbor
+                shift(comm, field, Axis(axis), g + k, lower, g + n + k, upper);
+            }
+
+            if lower.is_none() {
+                let layer = field.subview(Axis(axis), g).to_owned();
+                for k in 0..g {
+                    field.subview_mut(Axis(axis), k).assign(&layer);
+                }
+            }
+            if upper.is_none() {
+                let layer = field.subview(Axis(axis), g + n - 1).to_owned();
+                for k in 0..g {
+                    field.subview_mut(Axis(axis), g + n + k).assign(&layer);
+                }
+            }
+        }
+    }
+}
+
+/// Send layer `send` of an axis to `dest` while receiving layer `receive` from `source`.
+fn shift<T: Real, C: Communicator>(
+    comm: &C,
+    field: &mut Array3<T>,
+    axis: Axis,
+    send: usize,
+    dest: Option<usize>,
+    receive: usize,
+    source: Option<usize>,
+) {
+    let message = match dest {
+        Some(_) => field.subview(axis, send).iter().map(|v| v.to_f64().unwrap()).collect(),
+        None => Vec::new(),
+    };
+    let mut buffer = match source {
+        Some(_) => vec![0.0; field.subview(axis, receive).len()],
+        None => Vec::new(),
+    };
+    comm.send_receive(&message, dest, &mut buffer, source);
+    if source.is_some() {
+        for (v, &r) in field.subview_mut(axis, receive).iter_mut().zip(buffer.iter()) {
+            *v = T::new(r);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use distributed::ThreadComm;
+    use domain::AxisBoundary;
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn ghost_exchange() {
+        let grid = Grid3d::with_boundary(
+            (8, 12, 10),
+            [Default::default(), Default::default(), AxisBoundary::periodic()],
+        );
+        let decomposition = Decomposition::new(grid, 4, 2);
+        assert_eq!(decomposition.num_subdomains(), 4);
+
+        fn value(z: usize, y: usize, x: usize) -> f64 {
+            (z * 100 + y * 10 + x) as f64
+        }
+        let global = Array3::from_shape_fn(grid.dim(), |(z, y, x)| value(z, y, x));
+
+        let threads = ThreadComm::create(4).into_iter().map(|comm| {
+            let global = global.clone();
+            thread::spawn(move || {
+                let sub = decomposition.subdomain(comm.rank());
+                let mut field = sub.scatter(global.view());
+                sub.exchange(&comm, &mut field);
+
+                let g = sub.ghost() as isize;
+                let clamp = |i: isize, n: usize| i.max(0).min(n as isize - 1) as usize;
+                for ((lz, ly, lx), &v) in field.indexed_iter() {
+                    let z = clamp(sub.origin.0 as isize + lz as isize - g, 8);
+                    let y = clamp(sub.origin.1 as isize + ly as isize - g, 12);
+                    let x = (sub.origin.2 as isize + lx as isize - g + 10) as usize % 10;
+                    assert_eq!(v, value(z, y, x), "rank {} at {:?}", sub.rank, (lz, ly, lx));
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! Distributed-memory domain decomposition
+//!
+//! `Grid3d` domains are split into a tensor grid of blocks, one per rank.
+//! Each rank stores its block surrounded by ghost layers which are refreshed
+//! from the neighboring ranks by `Subdomain::exchange` before stencil
+//! operations like advection or diffusion. Linear systems are solved with a
+//! conjugate gradient method using global reductions for the dot products.
+//!
+//! Ranks communicate through the `Communicator` trait. `ThreadComm` runs all
+//! ranks as threads of one process, the `mpi` feature adds `MpiComm` for
+//! cluster-scale runs across processes.
+//!
+//! References:
+//!     [GLS99] William Gropp, Ewing Lusk, Anthony Skjellum, 1999,
+//!             Using MPI: Portable Parallel Programming with the Message-Passing Interface,
+//!             MIT Press
+
+pub mod cg;
+pub mod comm;
+pub mod decomposition;
+#[cfg(feature = "mpi")]
+pub mod mpi;
+
+pub use self::comm::{Communicator, ThreadComm};
+pub use self::decomposition::{Decomposition, Subdomain};
+#[cfg(feature = "mpi")]
+pub use self::mpi::MpiComm;
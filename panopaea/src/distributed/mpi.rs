@@ -0,0 +1,62 @@
+//! MPI backend of the `Communicator` trait, enabled by the `mpi` feature.
+
+use mpi::collective::{CommunicatorCollectives, SystemOperation};
+use mpi::point_to_point::{self, Destination, Source};
+use mpi::topology::{Communicator as MpiCommunicator, Rank, SystemCommunicator};
+
+use super::comm::Communicator;
+
+/// Ranks of an MPI communicator, usually the world communicator of the universe.
+///
+/// ```ignore
+/// let universe = mpi::initialize().unwrap();
+/// let comm = MpiComm::new(universe.world());
+/// ```
+pub struct MpiComm {
+    comm: SystemCommunicator,
+}
+
+impl MpiComm {
+    pub fn new(comm: SystemCommunicator) -> Self {
+        MpiComm { comm }
+    }
+}
+
+impl Communicator for MpiComm {
+    fn rank(&self) -> usize {
+        self.comm.rank() as usize
+    }
+
+    fn size(&self) -> usize {
+        self.comm.size() as usize
+    }
+
+    fn send_receive(&self, send: &[f64], dest: Option<usize>, receive: &mut [f64], source: Option<usize>) {
+        match (dest, source) {
+            (Some(dest), Some(source)) => {
+                let dest = self.comm.process_at_rank(dest as Rank);
+                let source = self.comm.process_at_rank(source as Rank);
+                point_to_point::send_receive_into(send, &dest, receive, &source);
+            }
+            (Some(dest), None) => {
+                self.comm.process_at_rank(dest as Rank).send(send);
+            }
+            (None, Some(source)) => {
+                self.comm.process_at_rank(source as Rank).receive_into(receive);
+            }
+            (None, None) => (),
+        }
+    }
+
+    fn all_reduce_sum(&self, value: f64) -> f64 {
+        let mut sum = 0.0;
+        self.comm.all_reduce_into(&value, &mut sum, SystemOperation::sum());
+        sum
+    }
+
+    fn all_reduce_max(&self, value: f64) -> f64 {
+        let mut max = 0.0;
+        self.comm.all_reduce_into(&value, &mut max, SystemOperation::max());
+        max
+    }
+}
@@ -0,0 +1,838 @@
+//! Block-structured adaptive mesh refinement
+//!
+//! A `Hierarchy` stores a cell-centered 2d field on a stack of levels. Level
+//! `0` covers the base grid with a single patch, each finer level consists of
+//! rectangular patches refined by `ratio` relative to the level below. Patches
+//! are aligned to blocks of `block` cells of their level, which guarantees
+//! proper nesting: every fine patch lies inside the patches of its parent level.
+//!
+//! Refinement is driven by boolean tags per cell, e.g. from `tag_vorticity` or
+//! `tag_interface`. `regrid` replaces the next finer level by the blocks
+//! covering the tagged cells, new cells are initialized from the previous fine
+//! data or by piecewise constant (conservative) prolongation.
+//!
+//! Patches carry `ghost` layers of ghost cells filled by `fill_ghosts` from
+//! neighboring patches, coarser levels or the domain boundary (periodic axes
+//! wrap, walls copy the closest cell). After updating all levels with a
+//! conservative scheme, `reflux` replaces the coarse fluxes at coarse-fine
+//! interfaces by the sum of the fine fluxes and `average_down` overwrites
+//! covered coarse cells by the average of their children.
+//!
+//! Multiple fields share the same layout when regridded with the same tags.
+//! All levels are advanced with the same timestep.
+//!
+//! `Hierarchy3d` is the 3d counterpart with box shaped patches, `tag_vorticity_3d`
+//! and `tag_interface_3d`. As `Grid3d` has unit spacing, patch grids are in
+//! cells of their level and spacings are given relative to the base cells.
+//!
+//! References:
+//!     [BC89] Marsha J. Berger and Phillip Colella, 1989,
+//!            Local adaptive mesh refinement for shock hydrodynamics,
+//!            Journal of Computational Physics 82, 64-84
+
+use dec::grid::{Staggered2d, Staggered3d};
+use math::Real;
+use ndarray::{Array2, Array3, ArrayView2, ArrayView3, ArrayViewMut2, ArrayViewMut3};
+use std::collections::BTreeSet;
+use std::mem;
+use vorticity;
+
+use super::grid::{Grid2d, Grid3d};
+
+/// Rectangle of cells `[origin, origin + dim)` `(y, x)` in the index space of a level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region2d {
+    pub origin: (usize, usize),
+    pub dim: (usize, usize),
+}
+
+impl Region2d {
+    pub fn new(origin: (usize, usize), dim: (usize, usize)) -> Self {
+        Region2d { origin, dim }
+    }
+
+    /// First cell after the region along each axis.
+    pub fn end(&self) -> (usize, usize) {
+        (self.origin.0 + self.dim.0, self.origin.1 + self.dim.1)
+    }
+
+    pub fn contains(&self, (y, x): (usize, usize)) -> bool {
+        let end = self.end();
+        y >= self.origin.0 && x >= self.origin.1 && y < end.0 && x < end.1
+    }
+
+    /// Region of the children on the next finer level.
+    pub fn refine(&self, ratio: usize) -> Self {
+        Region2d::new((self.origin.0 * ratio, self.origin.1 * ratio), (self.dim.0 * ratio, self.dim.1 * ratio))
+    }
+
+    /// Smallest region of the coarser level covering this region.
+    pub fn coarsen(&self, ratio: usize) -> Self {
+        let origin = (self.origin.0 / ratio, self.origin.1 / ratio);
+        let end = self.end();
+        let end = ((end.0 + ratio - 1) / ratio, (end.1 + ratio - 1) / ratio);
+        Region2d::new(origin, (end.0 - origin.0, end.1 - origin.1))
+    }
+}
+
+/// Rectangular part of a level with ghost cells.
+#[derive(Clone, Debug)]
+pub struct Patch<T> {
+    pub level: usize,
+    pub region: Region2d,
+    /// Grid of the patch cells with the spacing of its level.
+    pub grid: Grid2d,
+    ghost: usize,
+    /// Cell values including the ghost layers.
+    data: Array2<T>,
+}
+
+impl<T: Real> Patch<T> {
+    fn new(level: usize, region: Region2d, spacing: (f64, f64), ghost: usize) -> Self {
+        let (h, w) = region.dim;
+        Patch {
+            level,
+            region,
+            grid: Grid2d::new(region.dim).with_spacing(spacing),
+            ghost,
+            data: Array2::zeros((h + 2 * ghost, w + 2 * ghost)),
+        }
+    }
+
+    /// Cell values including the ghost layers.
+    pub fn data(&self) -> ArrayView2<T> {
+        self.data.view()
+    }
+
+    pub fn interior(&self) -> ArrayView2<T> {
+        let (g, (h, w)) = (self.ghost as isize, self.region.dim);
+        self.data.slice(s![g..g + h as isize, g..g + w as isize])
+    }
+
+    pub fn interior_mut(&mut self) -> ArrayViewMut2<T> {
+        let (g, (h, w)) = (self.ghost as isize, self.region.dim);
+        self.data.slice_mut(s![g..g + h as isize, g..g + w as isize])
+    }
+
+    /// Storage index of a cell of the level, which may be a ghost cell.
+    fn local(&self, (y, x): (isize, isize)) -> (usize, usize) {
+        let g = self.ghost as isize;
+        ((y - self.region.origin.0 as isize + g) as usize, (x - self.region.origin.1 as isize + g) as usize)
+    }
+}
+
+/// Levels of patches storing a cell-centered field.
+pub struct Hierarchy<T> {
+    base: Grid2d,
+    ratio: usize,
+    block: usize,
+    ghost: usize,
+    max_level: usize,
+    levels: Vec<Vec<Patch<T>>>,
+}
+
+impl<T: Real> Hierarchy<T> {
+    /// Two levels of refinement by a factor of 2 with blocks of 8 cells and 2 ghost layers.
+    pub fn new(base: Grid2d) -> Self {
+        Hierarchy::with_refinement(base, 2, 8, 2)
+    }
+
+    /// Refine by `ratio` per level, `block` needs to be a multiple of the ratio.
+    pub fn with_refinement(base: Grid2d, ratio: usize, block: usize, ghost: usize) -> Self {
+        assert!(ratio > 1, "refinement ratio must be larger than one");
+        assert!(block >= ratio && block % ratio == 0, "block size must be a multiple of the refinement ratio");
+        let region = Region2d::new((0, 0), base.dim());
+        Hierarchy {
+            base,
+            ratio,
+            block,
+            ghost,
+            max_level: 2,
+            levels: vec![vec![Patch::new(0, region, base.spacing(), ghost)]],
+        }
+    }
+
+    /// Finest level created by `regrid`.
+    pub fn with_max_level(self, max_level: usize) -> Self {
+        Hierarchy { max_level, ..self }
+    }
+
+    pub fn base(&self) -> &Grid2d {
+        &self.base
+    }
+
+    pub fn ratio(&self) -> usize {
+        self.ratio
+    }
+
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level(&self, level: usize) -> &[Patch<T>] {
+        &self.levels[level]
+    }
+
+    pub fn level_mut(&mut self, level: usize) -> &mut [Patch<T>] {
+        &mut self.levels[level]
+    }
+
+    /// Number of cells `(y, x)` covering the domain at a level.
+    pub fn level_dim(&self, level: usize) -> (usize, usize) {
+        let scale = self.ratio.pow(level as u32);
+        let (h, w) = self.base.dim();
+        (h * scale, w * scale)
+    }
+
+    /// Cell spacing `(dy, dx)` of a level.
+    pub fn level_spacing(&self, level: usize) -> (f64, f64) {
+        let scale = self.ratio.pow(level as u32) as f64;
+        let (dy, dx) = self.base.spacing();
+        (dy / scale, dx / scale)
+    }
+
+    /// Patch of a level containing the cell.
+    fn find(&self, level: usize, cell: (usize, usize)) -> Option<usize> {
+        self.levels.get(level).and_then(|patches| patches.iter().position(|p| p.region.contains(cell)))
+    }
+
+    /// Value of a cell, taken from the finest patch at or below `level` covering it.
+    ///
+    /// Indices outside of the domain wrap around periodic axes and are clamped otherwise.
+    fn value_at(&self, level: usize, (y, x): (isize, isize)) -> T {
+        let (h, w) = self.level_dim(level);
+        let [by, bx] = self.base.boundary();
+        let cell = (resolve(y, h, by.is_periodic()), resolve(x, w, bx.is_periodic()));
+        match self.find(level, cell) {
+            Some(p) => {
+                let patch = &self.levels[level][p];
+                patch.data[patch.local((cell.0 as isize, cell.1 as isize))]
+            }
+            None => {
+                assert!(level > 0, "level 0 must cover the domain");
+                self.value_at(level - 1, ((cell.0 / self.ratio) as isize, (cell.1 / self.ratio) as isize))
+            }
+        }
+    }
+
+    /// Replace the level above `level` by patches covering the tagged cells.
+    ///
+    /// `tags` contains one array per patch of `level` with the dimensions of the
+    /// patch. Levels above the new level are removed and need to be regridded.
+    pub fn regrid(&mut self, level: usize, tags: &[Array2<bool>]) {
+        assert_eq!(tags.len(), self.levels[level].len(), "expected tags for each patch");
+        let coarse_block = self.block / self.ratio;
+        let (h, w) = self.level_dim(level);
+
+        let mut blocks = BTreeSet::new();
+        if level < self.max_level {
+            for (patch, tags) in self.levels[level].iter().zip(tags) {
+                assert_eq!(tags.dim(), patch.region.dim);
+                let (oy, ox) = patch.region.origin;
+                for ((y, x), _) in tags.indexed_iter().filter(|&(_, &tag)| tag) {
+                    blocks.insert(((oy + y) / coarse_block, (ox + x) / coarse_block));
+                }
+            }
+        }
+
+        let old = if self.levels.len() > level + 1 { mem::replace(&mut self.levels[level + 1], Vec::new()) } else { Vec::new() };
+        self.levels.truncate(level + 1);
+
+        let spacing = self.level_spacing(level + 1);
+        let mut patches = Vec::with_capacity(blocks.len());
+        for (by, bx) in blocks {
+            let origin = (by * coarse_block, bx * coarse_block);
+            let dim = (coarse_block.min(h - origin.0), coarse_block.min(w - origin.1));
+            let mut patch = Patch::new(level + 1, Region2d::new(origin, dim).refine(self.ratio), spacing, self.ghost);
+            let (oy, ox) = patch.region.origin;
+            let values = Array2::from_shape_fn(patch.region.dim, |(y, x)| {
+                let cell = (oy + y, ox + x);
+                match old.iter().find(|p| p.region.contains(cell)) {
+                    Some(p) => p.data[p.local((cell.0 as isize, cell.1 as isize))],
+                    None => self.value_at(level, ((cell.0 / self.ratio) as isize, (cell.1 / self.ratio) as isize)),
+                }
+            });
+            patch.interior_mut().assign(&values);
+            patches.push(patch);
+        }
+        if !patches.is_empty() {
+            self.levels.push(patches);
+        }
+    }
+
+    /// Fill the ghost cells of all patches of a level.
+    pub fn fill_ghosts(&mut self, level: usize) {
+        let g = self.ghost;
+        let mut values = Vec::new();
+        for (p, patch) in self.levels[level].iter().enumerate() {
+            let (h, w) = patch.region.dim;
+            let (oy, ox) = patch.region.origin;
+            for ((j, i), _) in patch.data.indexed_iter() {
+                if j < g || i < g || j >= h + g || i >= w + g {
+                    let cell = ((oy + j) as isize - g as isize, (ox + i) as isize - g as isize);
+                    values.push((p, (j, i), self.value_at(level, cell)));
+                }
+            }
+        }
+        for (p, index, value) in values {
+            self.levels[level][p].data[index] = value;
+        }
+    }
+
+    /// Overwrite the coarse cells covered by patches of `level` with the average of their children.
+    pub fn average_down(&mut self, level: usize) {
+        assert!(level > 0);
+        let r = self.ratio;
+        let scale = T::one() / T::new(r * r);
+        let mut values = Vec::new();
+        for patch in &self.levels[level] {
+            let coarse = patch.region.coarsen(r);
+            let interior = patch.interior();
+            for y in coarse.origin.0..coarse.end().0 {
+                for x in coarse.origin.1..coarse.end().1 {
+                    let (fy, fx) = (y * r - patch.region.origin.0, x * r - patch.region.origin.1);
+                    let sum = interior.slice(s![fy as isize..(fy + r) as isize, fx as isize..(fx + r) as isize]).scalar_sum();
+                    values.push(((y, x), sum * scale));
+                }
+            }
+        }
+        for (cell, value) in values {
+            let p = self.find(level - 1, cell).expect("patches aren't properly nested");
+            let patch = &mut self.levels[level - 1][p];
+            let index = patch.local((cell.0 as isize, cell.1 as isize));
+            patch.data[index] = value;
+        }
+    }
+
+    /// Correct coarse cells next to the patches of `level` for the flux mismatch at their interface.
+    ///
+    /// `coarse` and `fine` contain the fluxes used in the last update of each
+    /// patch of `level - 1` and `level`, integrated over the edges and positive
+    /// along the axes. The update is assumed to be `q -= timestep / area * (outflow - inflow)`.
+    pub fn reflux(&mut self, level: usize, coarse: &[Staggered2d<T>], fine: &[Staggered2d<T>], timestep: T) {
+        assert!(level > 0);
+        assert_eq!(coarse.len(), self.levels[level - 1].len());
+        assert_eq!(fine.len(), self.levels[level].len());
+        let r = self.ratio;
+        let (h, w) = self.level_dim(level - 1);
+        let (dy, dx) = self.level_spacing(level - 1);
+        let factor = timestep / T::new(dy * dx);
+
+        // coarse cell, vertical edge, edge index on the coarse level, sum of fine fluxes, sign of the correction
+        let mut corrections = Vec::new();
+        for (patch, flux) in self.levels[level].iter().zip(fine) {
+            let region = patch.region.coarsen(r);
+            let (vertical, horizontal) = flux.split();
+            let (ph, pw) = patch.region.dim;
+            for x in region.origin.1..region.end().1 {
+                let fine_flux = |row: usize| (0..r).fold(T::zero(), |sum, k| sum + vertical[(row, (x - region.origin.1) * r + k)]);
+                corrections.push(((region.origin.0 as isize - 1, x as isize), true, (region.origin.0, x), fine_flux(0), -T::one()));
+                corrections.push(((region.end().0 as isize, x as isize), true, (region.end().0, x), fine_flux(ph), T::one()));
+            }
+            for y in region.origin.0..region.end().0 {
+                let fine_flux = |col: usize| (0..r).fold(T::zero(), |sum, k| sum + horizontal[((y - region.origin.0) * r + k, col)]);
+                corrections.push(((y as isize, region.origin.1 as isize - 1), false, (y, region.origin.1), fine_flux(0), -T::one()));
+                corrections.push(((y as isize, region.end().1 as isize), false, (y, region.end().1), fine_flux(pw), T::one()));
+            }
+        }
+
+        for ((y, x), vertical, edge, sum, sign) in corrections {
+            if y < 0 || x < 0 || y >= h as isize || x >= w as isize {
+                continue;
+            }
+            let cell = (y as usize, x as usize);
+            let refined = (cell.0 * r, cell.1 * r);
+            if self.find(level, refined).is_some() {
+                continue;
+            }
+            let p = self.find(level - 1, cell).expect("patches aren't properly nested");
+            let patch = &mut self.levels[level - 1][p];
+            let (oy, ox) = patch.region.origin;
+            let (cv, ch) = coarse[p].split();
+            let flux = if vertical { cv[(edge.0 - oy, edge.1 - ox)] } else { ch[(edge.0 - oy, edge.1 - ox)] };
+            let index = patch.local((y, x));
+            patch.data[index] = patch.data[index] + sign * factor * (sum - flux);
+        }
+    }
+}
+
+/// Cell index along an axis of a level, wrapped around periodic axes and clamped otherwise.
+fn resolve(i: isize, len: usize, periodic: bool) -> usize {
+    if periodic {
+        ((i % len as isize + len as isize) % len as isize) as usize
+    } else {
+        i.max(0).min(len as isize - 1) as usize
+    }
+}
+
+/// Box of cells `[origin, origin + dim)` `(z, y, x)` in the index space of a level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region3d {
+    pub origin: (usize, usize, usize),
+    pub dim: (usize, usize, usize),
+}
+
+impl Region3d {
+    pub fn new(origin: (usize, usize, usize), dim: (usize, usize, usize)) -> Self {
+        Region3d { origin, dim }
+    }
+
+    /// First cell after the region along each axis.
+    pub fn end(&self) -> (usize, usize, usize) {
+        (self.origin.0 + self.dim.0, self.origin.1 + self.dim.1, self.origin.2 + self.dim.2)
+    }
+
+    pub fn contains(&self, (z, y, x): (usize, usize, usize)) -> bool {
+        let end = self.end();
+        z >= self.origin.0 && y >= self.origin.1 && x >= self.origin.2 && z < end.0 && y < end.1 && x < end.2
+    }
+
+    /// Region of the children on the next finer level.
+    pub fn refine(&self, ratio: usize) -> Self {
+        let (o, d) = (self.origin, self.dim);
+        Region3d::new((o.0 * ratio, o.1 * ratio, o.2 * ratio), (d.0 * ratio, d.1 * ratio, d.2 * ratio))
+    }
+
+    /// Smallest region of the coarser level covering this region.
+    pub fn coarsen(&self, ratio: usize) -> Self {
+        let origin = (self.origin.0 / ratio, self.origin.1 / ratio, self.origin.2 / ratio);
+        let end = self.end();
+        let end = ((end.0 + ratio - 1) / ratio, (end.1 + ratio - 1) / ratio, (end.2 + ratio - 1) / ratio);
+        Region3d::new(origin, (end.0 - origin.0, end.1 - origin.1, end.2 - origin.2))
+    }
+}
+
+/// Box shaped part of a 3d level with ghost cells.
+#[derive(Clone, Debug)]
+pub struct Patch3d<T> {
+    pub level: usize,
+    pub region: Region3d,
+    /// Grid of the patch cells, in cells of its level.
+    pub grid: Grid3d,
+    ghost: usize,
+    /// Cell values including the ghost layers.
+    data: Array3<T>,
+}
+
+impl<T: Real> Patch3d<T> {
+    fn new(level: usize, region: Region3d, ghost: usize) -> Self {
+        let (d, h, w) = region.dim;
+        Patch3d {
+            level,
+            region,
+            grid: Grid3d::new(region.dim),
+            ghost,
+            data: Array3::zeros((d + 2 * ghost, h + 2 * ghost, w + 2 * ghost)),
+        }
+    }
+
+    /// Cell values including the ghost layers.
+    pub fn data(&self) -> ArrayView3<T> {
+        self.data.view()
+    }
+
+    pub fn interior(&self) -> ArrayView3<T> {
+        let (g, (d, h, w)) = (self.ghost as isize, self.region.dim);
+        self.data.slice(s![g..g + d as isize, g..g + h as isize, g..g + w as isize])
+    }
+
+    pub fn interior_mut(&mut self) -> ArrayViewMut3<T> {
+        let (g, (d, h, w)) = (self.ghost as isize, self.region.dim);
+        self.data.slice_mut(s![g..g + d as isize, g..g + h as isize, g..g + w as isize])
+    }
+
+    /// Storage index of a cell of the level, which may be a ghost cell.
+    fn local(&self, (z, y, x): (isize, isize, isize)) -> (usize, usize, usize) {
+        let (g, o) = (self.ghost as isize, self.region.origin);
+        ((z - o.0 as isize + g) as usize, (y - o.1 as isize + g) as usize, (x - o.2 as isize + g) as usize)
+    }
+}
+
+/// Levels of patches storing a cell-centered 3d field.
+pub struct Hierarchy3d<T> {
+    base: Grid3d,
+    ratio: usize,
+    block: usize,
+    ghost: usize,
+    max_level: usize,
+    levels: Vec<Vec<Patch3d<T>>>,
+}
+
+impl<T: Real> Hierarchy3d<T> {
+    /// Two levels of refinement by a factor of 2 with blocks of 8 cells and 2 ghost layers.
+    pub fn new(base: Grid3d) -> Self {
+        Hierarchy3d::with_refinement(base, 2, 8, 2)
+    }
+
+    /// Refine by `ratio` per level, `block` needs to be a multiple of the ratio.
+    pub fn with_refinement(base: Grid3d, ratio: usize, block: usize, ghost: usize) -> Self {
+        assert!(ratio > 1, "refinement ratio must be larger than one");
+        assert!(block >= ratio && block % ratio == 0, "block size must be a multiple of the refinement ratio");
+        let region = Region3d::new((0, 0, 0), base.dim());
+        Hierarchy3d {
+            base,
+            ratio,
+            block,
+            ghost,
+            max_level: 2,
+            levels: vec![vec![Patch3d::new(0, region, ghost)]],
+        }
+    }
+
+    /// Finest level created by `regrid`.
+    pub fn with_max_level(self, max_level: usize) -> Self {
+        Hierarchy3d { max_level, ..self }
+    }
+
+    pub fn base(&self) -> &Grid3d {
+        &self.base
+    }
+
+    pub fn ratio(&self) -> usize {
+        self.ratio
+    }
+
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level(&self, level: usize) -> &[Patch3d<T>] {
+        &self.levels[level]
+    }
+
+    pub fn level_mut(&mut self, level: usize) -> &mut [Patch3d<T>] {
+        &mut self.levels[level]
+    }
+
+    /// Number of cells `(z, y, x)` covering the domain at a level.
+    pub fn level_dim(&self, level: usize) -> (usize, usize, usize) {
+        let scale = self.ratio.pow(level as u32);
+        let (d, h, w) = self.base.dim();
+        (d * scale, h * scale, w * scale)
+    }
+
+    /// Cell spacing of a level relative to the cells of the base grid.
+    pub fn level_spacing(&self, level: usize) -> f64 {
+        1.0 / self.ratio.pow(level as u32) as f64
+    }
+
+    /// Patch of a level containing the cell.
+    fn find(&self, level: usize, cell: (usize, usize, usize)) -> Option<usize> {
+        self.levels.get(level).and_then(|patches| patches.iter().position(|p| p.region.contains(cell)))
+    }
+
+    /// Value of a cell, taken from the finest patch at or below `level` covering it.
+    ///
+    /// Indices outside of the domain wrap around periodic axes and are clamped otherwise.
+    fn value_at(&self, level: usize, (z, y, x): (isize, isize, isize)) -> T {
+        let (d, h, w) = self.level_dim(level);
+        let [bz, by, bx] = self.base.boundary();
+        let cell = (resolve(z, d, bz.is_periodic()), resolve(y, h, by.is_periodic()), resolve(x, w, bx.is_periodic()));
+        match self.find(level, cell) {
+            Some(p) => {
+                let patch = &self.levels[level][p];
+                patch.data[patch.local((cell.0 as isize, cell.1 as isize, cell.2 as isize))]
+            }
+            None => {
+                assert!(level > 0, "level 0 must cover the domain");
+                let r = self.ratio;
+                self.value_at(level - 1, ((cell.0 / r) as isize, (cell.1 / r) as isize, (cell.2 / r) as isize))
+            }
+        }
+    }
+
+    /// Replace the level above `level` by patches covering the tagged cells.
+    ///
+    /// `tags` contains one array per patch of `level` with the dimensions of the
+    /// patch. Levels above the new level are removed and need to be regridded.
+    pub fn regrid(&mut self, level: usize, tags: &[Array3<bool>]) {
+        assert_eq!(tags.len(), self.levels[level].len(), "expected tags for each patch");
+        let coarse_block = self.block / self.ratio;
+        let (d, h, w) = self.level_dim(level);
+
+        let mut blocks = BTreeSet::new();
+        if level < self.max_level {
+            for (patch, tags) in self.levels[level].iter().zip(tags) {
+                assert_eq!(tags.dim(), patch.region.dim);
+                let (oz, oy, ox) = patch.region.origin;
+                for ((z, y, x), _) in tags.indexed_iter().filter(|&(_, &tag)| tag) {
+                    blocks.insert(((oz + z) / coarse_block, (oy + y) / coarse_block, (ox + x) / coarse_block));
+                }
+            }
+        }
+
+        let old = if self.levels.len() > level + 1 { mem::replace(&mut self.levels[level + 1], Vec::new()) } else { Vec::new() };
+        self.levels.truncate(level + 1);
+
+        let r = self.ratio;
+        let mut patches = Vec::with_capacity(blocks.len());
+        for (bz, by, bx) in blocks {
+            let origin = (bz * coarse_block, by * coarse_block, bx * coarse_block);
+            let dim = (coarse_block.min(d - origin.0), coarse_block.min(h - origin.1), coarse_block.min(w - origin.2));
+            let mut patch = Patch3d::new(level + 1, Region3d::new(origin, dim).refine(r), self.ghost);
+            let (oz, oy, ox) = patch.region.origin;
+            let values = Array3::from_shape_fn(patch.region.dim, |(z, y, x)| {
+                let cell = (oz + z, oy + y, ox + x);
+                match old.iter().find(|p| p.region.contains(cell)) {
+                    Some(p) => p.data[p.local((cell.0 as isize, cell.1 as isize, cell.2 as isize))],
+                    None => self.value_at(level, ((cell.0 / r) as isize, (cell.1 / r) as isize, (cell.2 / r) as isize)),
+                }
+            });
+            patch.interior_mut().assign(&values);
+            patches.push(patch);
+        }
+        if !patches.is_empty() {
+            self.levels.push(patches);
+        }
+    }
+
+    /// Fill the ghost cells of all patches of a level.
+    pub fn fill_ghosts(&mut self, level: usize) {
+        let g = self.ghost;
+        let mut values = Vec::new();
+        for (p, patch) in self.levels[level].iter().enumerate() {
+            let (d, h, w) = patch.region.dim;
+            let (oz, oy, ox) = patch.region.origin;
+            for ((k, j, i), _) in patch.data.indexed_iter() {
+                if k < g || j < g || i < g || k >= d + g || j >= h + g || i >= w + g {
+                    let cell = ((oz + k) as isize - g as isize, (oy + j) as isize - g as isize, (ox + i) as isize - g as isize);
+                    values.push((p, (k, j, i), self.value_at(level, cell)));
+                }
+            }
+        }
+        for (p, index, value) in values {
+            self.levels[level][p].data[index] = value;
+        }
+    }
+
+    /// Overwrite the coarse cells covered by patches of `level` with the average of their children.
+    pub fn average_down(&mut self, level: usize) {
+        assert!(level > 0);
+        let r = self.ratio;
+        let scale = T::one() / T::new(r * r * r);
+        let mut values = Vec::new();
+        for patch in &self.levels[level] {
+            let coarse = patch.region.coarsen(r);
+            let (o, end) = (patch.region.origin, coarse.end());
+            let interior = patch.interior();
+            for z in coarse.origin.0..end.0 {
+                for y in coarse.origin.1..end.1 {
+                    for x in coarse.origin.2..end.2 {
+                        let (fz, fy, fx) = ((z * r - o.0) as isize, (y * r - o.1) as isize, (x * r - o.2) as isize);
+                        let r = r as isize;
+                        let sum = interior.slice(s![fz..fz + r, fy..fy + r, fx..fx + r]).scalar_sum();
+                        values.push(((z, y, x), sum * scale));
+                    }
+                }
+            }
+        }
+        for (cell, value) in values {
+            let p = self.find(level - 1, cell).expect("patches aren't properly nested");
+            let patch = &mut self.levels[level - 1][p];
+            let index = patch.local((cell.0 as isize, cell.1 as isize, cell.2 as isize));
+            patch.data[index] = value;
+        }
+    }
+
+    /// Correct coarse cells next to the patches of `level` for the flux mismatch at their interface.
+    ///
+    /// `coarse` and `fine` contain the fluxes used in the last update of each
+    /// patch of `level - 1` and `level`, integrated over the faces and positive
+    /// along the axes. The update is assumed to be `q -= timestep / volume * (outflow - inflow)`.
+    pub fn reflux(&mut self, level: usize, coarse: &[Staggered3d<T>], fine: &[Staggered3d<T>], timestep: T) {
+        assert!(level > 0);
+        assert_eq!(coarse.len(), self.levels[level - 1].len());
+        assert_eq!(fine.len(), self.levels[level].len());
+        let r = self.ratio;
+        let dim = array_3d(self.level_dim(level - 1));
+        let factor = timestep / T::new(self.level_spacing(level - 1).powi(3));
+
+        // face on the coarse level, normal axis, offset of the coarse cell along the normal, sum of fine fluxes, sign of the correction
+        let mut corrections = Vec::new();
+        for (patch, flux) in self.levels[level].iter().zip(fine) {
+            let region = patch.region.coarsen(r);
+            let (origin, end, fine_dim) = (array_3d(region.origin), array_3d(region.end()), array_3d(patch.region.dim));
+            let components = { let (fz, fy, fx) = flux.split(); [fz, fy, fx] };
+            for axis in 0..3 {
+                let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+                for i in origin[a]..end[a] {
+                    for j in origin[b]..end[b] {
+                        let fine_flux = |row: usize| {
+                            let mut sum = T::zero();
+                            for k in 0..r {
+                                for l in 0..r {
+                                    let mut index = [row; 3];
+                                    index[a] = (i - origin[a]) * r + k;
+                                    index[b] = (j - origin[b]) * r + l;
+                                    sum = sum + components[axis][tuple_3d(index)];
+                                }
+                            }
+                            sum
+                        };
+                        let mut lower = [origin[axis]; 3];
+                        lower[a] = i;
+                        lower[b] = j;
+                        let mut upper = lower;
+                        upper[axis] = end[axis];
+                        corrections.push((lower, axis, -1, fine_flux(0), -T::one()));
+                        corrections.push((upper, axis, 0, fine_flux(fine_dim[axis]), T::one()));
+                    }
+                }
+            }
+        }
+
+        for (face, axis, offset, sum, sign) in corrections {
+            let z = face[axis] as isize + offset;
+            if z < 0 || z >= dim[axis] as isize {
+                continue;
+            }
+            let mut cell = face;
+            cell[axis] = z as usize;
+            if self.find(level, (cell[0] * r, cell[1] * r, cell[2] * r)).is_some() {
+                continue;
+            }
+            let p = self.find(level - 1, tuple_3d(cell)).expect("patches aren't properly nested");
+            let patch = &mut self.levels[level - 1][p];
+            let o = array_3d(patch.region.origin);
+            let flux = {
+                let (cz, cy, cx) = coarse[p].split();
+                [cz, cy, cx][axis][(face[0] - o[0], face[1] - o[1], face[2] - o[2])]
+            };
+            let index = patch.local((cell[0] as isize, cell[1] as isize, cell[2] as isize));
+            patch.data[index] = patch.data[index] + sign * factor * (sum - flux);
+        }
+    }
+}
+
+fn array_3d((z, y, x): (usize, usize, usize)) -> [usize; 3] {
+    [z, y, x]
+}
+
+fn tuple_3d([z, y, x]: [usize; 3]) -> (usize, usize, usize) {
+    (z, y, x)
+}
+
+/// Tag cells with a vorticity magnitude above `threshold`.
+pub fn tag_vorticity<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>, threshold: T) -> Array2<bool> {
+    let (dy, dx) = grid.spacing();
+    let area = T::new(dy * dx);
+    vorticity::cell_vorticity_2d(grid, velocity).mapv(|w| (w / area).abs() > threshold)
+}
+
+/// Tag cells within `cells` cells of the zero level set of `phi`.
+pub fn tag_interface<T: Real>(grid: &Grid2d, phi: ArrayView2<T>, cells: T) -> Array2<bool> {
+    let (dy, dx) = grid.spacing();
+    let band = cells * T::new(dy.max(dx));
+    phi.mapv(|phi| phi.abs() < band)
+}
+
+/// Tag cells with a vorticity magnitude above `threshold`, `spacing` is the cell spacing of the grid.
+pub fn tag_vorticity_3d<T: Real>(grid: &Grid3d, velocity: &Staggered3d<T>, spacing: f64, threshold: T) -> Array3<bool> {
+    let area = T::new(spacing * spacing);
+    vorticity::cell_vorticity_3d(grid, velocity).mapv(|(z, y, x)| (z * z + y * y + x * x).sqrt() / area > threshold)
+}
+
+/// Tag cells within `cells` cells of the zero level set of `phi`, `spacing` is the cell spacing of the grid.
+pub fn tag_interface_3d<T: Real>(phi: ArrayView3<T>, spacing: f64, cells: T) -> Array3<bool> {
+    let band = cells * T::new(spacing);
+    phi.mapv(|phi| phi.abs() < band)
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::{Manifold2d, Manifold3d};
+    use super::*;
+
+    #[test]
+    fn refined_hierarchy() {
+        let mut hierarchy = Hierarchy::new(Grid2d::new((16, 16)));
+        hierarchy.level_mut(0)[0].interior_mut().assign(&Array2::from_shape_fn((16, 16), |(y, x)| y as f64 + 0.1 * x as f64));
+
+        // circle around the center
+        let phi = Array2::from_shape_fn((16, 16), |(y, x)| ((y as f64 - 5.5).powi(2) + (x as f64 - 5.5).powi(2)).sqrt() - 0.5);
+        let tags = tag_interface(hierarchy.base(), phi.view(), 1.0);
+        hierarchy.regrid(0, &[tags]);
+        assert_eq!(hierarchy.num_levels(), 2);
+        assert_eq!(hierarchy.level(1).len(), 1);
+        let region = hierarchy.level(1)[0].region;
+        assert_eq!(region, Region2d::new((8, 8), (8, 8)));
+        assert_eq!(hierarchy.level(1)[0].grid.spacing(), (0.5, 0.5));
+
+        // piecewise constant prolongation and ghost cells from the coarse level
+        hierarchy.fill_ghosts(1);
+        {
+            let patch = &hierarchy.level(1)[0];
+            assert_eq!(patch.interior()[(0, 1)], 4.0 + 0.1 * 4.0);
+            assert_eq!(patch.data()[(1, 2)], 3.0 + 0.1 * 4.0);
+        }
+
+        // coarse cells covered by the patch
+        hierarchy.level_mut(1)[0].interior_mut().fill(1.0);
+        hierarchy.average_down(1);
+        assert_eq!(hierarchy.level(0)[0].interior()[(5, 5)], 1.0);
+        assert_eq!(hierarchy.level(0)[0].interior()[(3, 5)], 3.0 + 0.1 * 5.0);
+
+        // upward flux of the fine patch through its lower and upper boundary
+        let coarse = <Grid2d as Manifold2d<f64>>::new_simplex_1(&hierarchy.level(0)[0].grid);
+        let mut fine = <Grid2d as Manifold2d<f64>>::new_simplex_1(&hierarchy.level(1)[0].grid);
+        fine.split_mut().0.fill(1.0);
+        hierarchy.reflux(1, &[coarse], &[fine], 0.5);
+        let interior = hierarchy.level(0)[0].interior();
+        assert_eq!(interior[(3, 5)], 2.5);
+        assert_eq!(interior[(8, 5)], 9.5);
+        assert_eq!(interior[(6, 3)], 6.0 + 0.1 * 3.0);
+
+        // removing the tags removes the level
+        let tags = Array2::from_elem((16, 16), false);
+        hierarchy.regrid(0, &[tags]);
+        assert_eq!(hierarchy.num_levels(), 1);
+    }
+
+    #[test]
+    fn refined_hierarchy_3d() {
+        let value = |z: usize, y: usize, x: usize| (100 * z + 10 * y + x) as f64;
+        let mut hierarchy = Hierarchy3d::new(Grid3d::new((16, 16, 16)));
+        hierarchy.level_mut(0)[0].interior_mut().assign(&Array3::from_shape_fn((16, 16, 16), |(z, y, x)| value(z, y, x)));
+
+        // sphere around the center
+        let phi = Array3::from_shape_fn((16, 16, 16), |(z, y, x)| {
+            ((z as f64 - 5.5).powi(2) + (y as f64 - 5.5).powi(2) + (x as f64 - 5.5).powi(2)).sqrt() - 0.5
+        });
+        let tags = tag_interface_3d(phi.view(), hierarchy.level_spacing(0), 1.0);
+        hierarchy.regrid(0, &[tags]);
+        assert_eq!(hierarchy.num_levels(), 2);
+        assert_eq!(hierarchy.level(1).len(), 1);
+        assert_eq!(hierarchy.level(1)[0].region, Region3d::new((8, 8, 8), (8, 8, 8)));
+        assert_eq!(hierarchy.level_spacing(1), 0.5);
+
+        // piecewise constant prolongation and ghost cells from the coarse level
+        hierarchy.fill_ghosts(1);
+        {
+            let patch = &hierarchy.level(1)[0];
+            assert_eq!(patch.interior()[(0, 0, 1)], value(4, 4, 4));
+            assert_eq!(patch.data()[(1, 2, 2)], value(3, 4, 4));
+        }
+
+        // coarse cells covered by the patch
+        hierarchy.level_mut(1)[0].interior_mut().fill(1.0);
+        hierarchy.average_down(1);
+        assert_eq!(hierarchy.level(0)[0].interior()[(5, 5, 5)], 1.0);
+        assert_eq!(hierarchy.level(0)[0].interior()[(3, 5, 5)], value(3, 5, 5));
+
+        // flux of the fine patch along z through its lower and upper boundary
+        let coarse = <Grid3d as Manifold3d<f64>>::new_simplex_2(&hierarchy.level(0)[0].grid);
+        let mut fine = <Grid3d as Manifold3d<f64>>::new_simplex_2(&hierarchy.level(1)[0].grid);
+        fine.split_mut().0.fill(1.0);
+        hierarchy.reflux(1, &[coarse], &[fine], 0.5);
+        let interior = hierarchy.level(0)[0].interior();
+        assert_eq!(interior[(3, 5, 5)], value(3, 5, 5) - 2.0);
+        assert_eq!(interior[(8, 5, 5)], value(8, 5, 5) + 2.0);
+        assert_eq!(interior[(6, 3, 5)], value(6, 3, 5));
+        assert_eq!(interior[(6, 5, 8)], value(6, 5, 8));
+
+        // removing the tags removes the level
+        let tags = Array3::from_elem((16, 16, 16), false);
+        hierarchy.regrid(0, &[tags]);
+        assert_eq!(hierarchy.num_levels(), 1);
+    }
+}
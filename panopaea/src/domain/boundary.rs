@@ -0,0 +1,104 @@
+use error::{Error, Result};
+
+/// Condition imposed at one side of a grid domain.
+///
+/// Pressure is Dirichlet for open boundaries, periodic for periodic boundaries
+/// and Neumann (zero flux) otherwise.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryCondition {
+    /// Open boundary, the pressure vanishes outside of the domain.
+    Dirichlet,
+    /// Prescribed flux, the normal velocity at the boundary is kept as is (inflow).
+    Neumann,
+    /// Wraps around to the opposite side, both sides need to be periodic.
+    Periodic,
+    /// Solid wall, tangential velocity is unconstrained.
+    FreeSlip,
+    /// Solid wall, tangential velocity vanishes at the wall.
+    NoSlip,
+}
+
+impl BoundaryCondition {
+    /// Normal velocity vanishes at the boundary.
+    pub fn is_wall(self) -> bool {
+        match self {
+            BoundaryCondition::FreeSlip | BoundaryCondition::NoSlip => true,
+            _ => false,
+        }
+    }
+}
+
+/// Boundary conditions at the lower and upper side of one axis.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AxisBoundary {
+    pub lower: BoundaryCondition,
+    pub upper: BoundaryCondition,
+}
+
+impl AxisBoundary {
+    pub fn new(lower: BoundaryCondition, upper: BoundaryCondition) -> Self {
+        AxisBoundary::try_new(lower, upper).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Fails if only one side is periodic.
+    pub fn try_new(lower: BoundaryCondition, upper: BoundaryCondition) -> Result<Self> {
+        let boundary = AxisBoundary { lower, upper };
+        boundary.check()?;
+        Ok(boundary)
+    }
+
+    /// Same condition on both sides.
+    pub fn uniform(condition: BoundaryCondition) -> Self {
+        AxisBoundary::new(condition, condition)
+    }
+
+    pub fn periodic() -> Self {
+        AxisBoundary::uniform(BoundaryCondition::Periodic)
+    }
+
+    pub fn is_periodic(&self) -> bool {
+        self.lower == BoundaryCondition::Periodic
+    }
+
+    /// Periodic conditions are only allowed on both sides of an axis.
+    pub fn is_consistent(&self) -> bool {
+        (self.lower == BoundaryCondition::Periodic) == (self.upper == BoundaryCondition::Periodic)
+    }
+
+    /// `Err` if the boundary isn't consistent, e.g. after deserialization.
+    pub fn check(&self) -> Result<()> {
+        if self.is_consistent() {
+            Ok(())
+        } else {
+            Err(Error::InvalidParameter(format!("periodic boundaries need to be paired: {:?}", self)))
+        }
+    }
+}
+
+/// Closed domain with free-slip walls.
+impl Default for AxisBoundary {
+    fn default() -> Self {
+        AxisBoundary::uniform(BoundaryCondition::FreeSlip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axis_boundary_periodic_pairs() {
+        assert!(AxisBoundary::try_new(BoundaryCondition::Periodic, BoundaryCondition::Periodic).is_ok());
+        assert!(AxisBoundary::try_new(BoundaryCondition::Dirichlet, BoundaryCondition::NoSlip).is_ok());
+
+        for &(lower, upper) in &[(BoundaryCondition::Periodic, BoundaryCondition::FreeSlip),
+                                 (BoundaryCondition::Neumann, BoundaryCondition::Periodic)] {
+            match AxisBoundary::try_new(lower, upper) {
+                Err(Error::InvalidParameter(_)) => (),
+                other => panic!("unexpected result {:?}", other),
+            }
+            let boundary = AxisBoundary { lower, upper };
+            assert!(!boundary.is_consistent() && boundary.check().is_err());
+        }
+    }
+}
@@ -1,12 +1,69 @@
+use error::{Error, Result};
+use super::boundary::AxisBoundary;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Grid2d {
     dim: (usize, usize), // (y, x)
+    boundary: [AxisBoundary; 2], // [y, x]
+    spacing: (f64, f64), // (dy, dx)
 }
 
 impl Grid2d {
+    /// Closed grid with free-slip walls.
     pub fn new(dim: (usize, usize)) -> Self {
-        Grid2d { dim: dim }
+        Grid2d::with_boundary(dim, Default::default())
+    }
+
+    /// Grid with boundary conditions for the `[y, x]` axes.
+    pub fn with_boundary(dim: (usize, usize), boundary: [AxisBoundary; 2]) -> Self {
+        Grid2d::try_with_boundary(dim, boundary).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Fails for inconsistent periodic boundaries.
+    pub fn try_with_boundary(dim: (usize, usize), boundary: [AxisBoundary; 2]) -> Result<Self> {
+        for b in &boundary {
+            b.check()?;
+        }
+        Ok(Grid2d { dim: dim, boundary: boundary, spacing: (1.0, 1.0) })
+    }
+
+    /// Grid with cell spacing `(dy, dx)`, unit spacing by default.
+    pub fn with_spacing(self, spacing: (f64, f64)) -> Self {
+        self.try_with_spacing(spacing).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Fails for non-positive spacings.
+    pub fn try_with_spacing(self, spacing: (f64, f64)) -> Result<Self> {
+        if !(spacing.0 > 0.0 && spacing.1 > 0.0) {
+            return Err(Error::InvalidParameter(format!("grid spacing needs to be positive: {:?}", spacing)));
+        }
+        Ok(Grid2d { spacing: spacing, ..self })
+    }
+
+    pub fn dim(&self) -> (usize, usize) {
+        self.dim
+    }
+
+    /// Boundary conditions of the `[y, x]` axes.
+    pub fn boundary(&self) -> [AxisBoundary; 2] {
+        self.boundary
+    }
+
+    /// Cell spacing `(dy, dx)`.
+    pub fn spacing(&self) -> (f64, f64) {
+        self.spacing
+    }
+}
+
+/// Doubly periodic 2d grid (torus) with equal number of vertices, faces and edges per direction.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct PeriodicGrid2d {
+    dim: (usize, usize), // (y, x)
+}
+
+impl PeriodicGrid2d {
+    pub fn new(dim: (usize, usize)) -> Self {
+        PeriodicGrid2d { dim: dim }
     }
 
     pub fn dim(&self) -> (usize, usize) {
@@ -14,7 +71,73 @@ impl Grid2d {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Grid3d {
     dim: (usize, usize, usize), // (z, y, x)
+    boundary: [AxisBoundary; 3], // [z, y, x]
+}
+
+impl Grid3d {
+    /// Closed grid with free-slip walls.
+    pub fn new(dim: (usize, usize, usize)) -> Self {
+        Grid3d::with_boundary(dim, Default::default())
+    }
+
+    /// Grid with boundary conditions for the `[z, y, x]` axes.
+    pub fn with_boundary(dim: (usize, usize, usize), boundary: [AxisBoundary; 3]) -> Self {
+        Grid3d::try_with_boundary(dim, boundary).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Fails for inconsistent periodic boundaries.
+    pub fn try_with_boundary(dim: (usize, usize, usize), boundary: [AxisBoundary; 3]) -> Result<Self> {
+        for b in &boundary {
+            b.check()?;
+        }
+        Ok(Grid3d { dim: dim, boundary: boundary })
+    }
+
+    pub fn dim(&self) -> (usize, usize, usize) {
+        self.dim
+    }
+
+    /// Boundary conditions of the `[z, y, x]` axes.
+    pub fn boundary(&self) -> [AxisBoundary; 3] {
+        self.boundary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::BoundaryCondition;
+    use super::*;
+
+    #[test]
+    fn grid_inconsistent_boundary() {
+        // e.g. from a deserialized scene description
+        let broken = AxisBoundary { lower: BoundaryCondition::Periodic, upper: BoundaryCondition::NoSlip };
+
+        match Grid2d::try_with_boundary((4, 4), [AxisBoundary::default(), broken]) {
+            Err(Error::InvalidParameter(_)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match Grid3d::try_with_boundary((4, 4, 4), [broken, AxisBoundary::default(), AxisBoundary::default()]) {
+            Err(Error::InvalidParameter(_)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(Grid2d::try_with_boundary((4, 4), [AxisBoundary::periodic(); 2]).is_ok());
+        assert!(Grid3d::try_with_boundary((4, 4, 4), [AxisBoundary::periodic(); 3]).is_ok());
+    }
+
+    #[test]
+    fn grid_spacing() {
+        for &spacing in &[(0.0, 1.0), (1.0, -0.5), (::std::f64::NAN, 1.0)] {
+            match Grid2d::new((4, 4)).try_with_spacing(spacing) {
+                Err(Error::InvalidParameter(_)) => (),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        let grid = Grid2d::new((4, 4)).try_with_spacing((0.5, 2.0)).unwrap();
+        assert_eq!(grid.spacing(), (0.5, 2.0));
+        assert_eq!(grid.dim(), (4, 4));
+    }
 }
@@ -0,0 +1,280 @@
+//! Interpolation of grid fields
+//!
+//! Fields are sampled at `index + offset` depending on their location in the
+//! grid cells: nodes, cell centers or the faces of staggered velocities.
+//! Interpolation is separable along the axes. Periodic axes wrap around, other
+//! axes are clamped to the outermost samples.
+//!
+//! 2d positions are given in world units `(y, x)` and scaled by the grid
+//! spacing, 3d grids have unit spacing and positions `(z, y, x)`.
+//!
+//! 3d fields are accessed through `Field3d`, which is implemented by dense
+//! arrays and the sparse tiled storage of `domain::sparse`.
+//!
+//! References:
+//!     [FSJ01] Ronald Fedkiw, Jos Stam and Henrik Wann Jensen, 2001,
+//!             Visual simulation of smoke,
+//!             In Proceedings of SIGGRAPH 2001, ACM, 15-22
+
+use dec::grid::{Staggered2d, Staggered3d};
+use math::interp::{catmull_rom, linear, monotone_cubic};
+use math::Real;
+use ndarray::{ArrayBase, ArrayView2, Data, Ix3};
+use std::cmp;
+
+use super::grid::{Grid2d, Grid3d};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Bi- and trilinear interpolation of the closest samples.
+    Linear,
+    /// Catmull-Rom splines through four samples per axis.
+    CatmullRom,
+    /// Cubic splines limited to the range of the two closest samples.
+    ///
+    /// Ref: [FSJ01] Sec. 4
+    MonotoneCubic,
+}
+
+impl Interpolation {
+    fn interpolate<T: Real>(self, a: [T; 4], s: T) -> T {
+        match self {
+            Interpolation::Linear => linear(a[1], a[2], s),
+            Interpolation::CatmullRom => catmull_rom(a[0], a[1], a[2], a[3], s),
+            Interpolation::MonotoneCubic => monotone_cubic(a[0], a[1], a[2], a[3], s),
+        }
+    }
+}
+
+/// Sample location of 2d fields.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Centering2d {
+    Node,
+    Cell,
+    /// Vertical velocity components of a `Staggered2d`.
+    Vertical,
+    /// Horizontal velocity components of a `Staggered2d`.
+    Horizontal,
+}
+
+impl Centering2d {
+    /// Position of the sample `(0, 0)` in grid units.
+    pub fn offset<T: Real>(self) -> (T, T) {
+        let half = T::new(0.5);
+        match self {
+            Centering2d::Node => (T::zero(), T::zero()),
+            Centering2d::Cell => (half, half),
+            Centering2d::Vertical => (T::zero(), half),
+            Centering2d::Horizontal => (half, T::zero()),
+        }
+    }
+}
+
+/// Sample location of 3d fields.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Centering3d {
+    Node,
+    Cell,
+    /// Face normal components of a `Staggered3d` along `z`, `y` and `x`.
+    FaceZ,
+    FaceY,
+    FaceX,
+}
+
+impl Centering3d {
+    /// Position of the sample `(0, 0, 0)` in grid units.
+    pub fn offset<T: Real>(self) -> (T, T, T) {
+        let (zero, half) = (T::zero(), T::new(0.5));
+        match self {
+            Centering3d::Node => (zero, zero, zero),
+            Centering3d::Cell => (half, half, half),
+            Centering3d::FaceZ => (zero, half, half),
+            Centering3d::FaceY => (half, zero, half),
+            Centering3d::FaceX => (half, half, zero),
+        }
+    }
+}
+
+/// Read access to the samples of a 3d field.
+pub trait Field3d<T> {
+    /// Number of samples `(z, y, x)`.
+    fn dim(&self) -> (usize, usize, usize);
+
+    /// Sample at the index `(z, y, x)`.
+    fn at(&self, index: (usize, usize, usize)) -> T;
+}
+
+impl<T: Copy, S: Data<Elem = T>> Field3d<T> for ArrayBase<S, Ix3> {
+    fn dim(&self) -> (usize, usize, usize) {
+        ArrayBase::dim(self)
+    }
+
+    fn at(&self, index: (usize, usize, usize)) -> T {
+        self[index]
+    }
+}
+
+impl<'a, T, F: Field3d<T> + ?Sized> Field3d<T> for &'a F {
+    fn dim(&self) -> (usize, usize, usize) {
+        (**self).dim()
+    }
+
+    fn at(&self, index: (usize, usize, usize)) -> T {
+        (**self).at(index)
+    }
+}
+
+/// Indices of the four samples around `p` and the interpolation weight between the middle two.
+///
+/// `p` is given relative to the first sample. Periodic fields repeat after `cells` samples.
+fn stencil<T: Real>(periodic: bool, cells: usize, samples: usize, p: T) -> ([usize; 4], T) {
+    if periodic {
+        let len = T::new(cells);
+        let p = p - len * (p / len).floor();
+        let i = cmp::min(p.floor().to_usize().unwrap(), cells - 1);
+        let wrap = |offset: isize| ((i + cells) as isize + offset) as usize % cells;
+        ([wrap(-1), i, wrap(1), wrap(2)], p - T::new(i))
+    } else {
+        let last = samples - 1;
+        let p = p.max(T::zero()).min(T::new(last));
+        let i = cmp::min(p.floor().to_usize().unwrap(), last.saturating_sub(1));
+        let clamp = |offset: isize| cmp::max(0, cmp::min(last as isize, i as isize + offset)) as usize;
+        ([clamp(-1), i, clamp(1), clamp(2)], p - T::new(i))
+    }
+}
+
+/// Interpolate a 2d field at a world position.
+pub fn sample_2d<T: Real>(
+    grid: &Grid2d,
+    field: ArrayView2<T>,
+    centering: Centering2d,
+    interpolation: Interpolation,
+    pos: (T, T),
+) -> T {
+    let (h, w) = grid.dim();
+    let (dy, dx) = grid.spacing();
+    let [by, bx] = grid.boundary();
+    let offset = centering.offset::<T>();
+    let (ys, t) = stencil(by.is_periodic(), h, field.dim().0, pos.0 / T::new(dy) - offset.0);
+    let (xs, s) = stencil(bx.is_periodic(), w, field.dim().1, pos.1 / T::new(dx) - offset.1);
+
+    let mut rows = [T::zero(); 4];
+    for j in 0..4 {
+        let row = [field[(ys[j], xs[0])], field[(ys[j], xs[1])], field[(ys[j], xs[2])], field[(ys[j], xs[3])]];
+        rows[j] = interpolation.interpolate(row, s);
+    }
+    interpolation.interpolate(rows, t)
+}
+
+/// Interpolate a 3d field at a position in grid units.
+pub fn sample_3d<T: Real, F: Field3d<T>>(
+    grid: &Grid3d,
+    field: F,
+    centering: Centering3d,
+    interpolation: Interpolation,
+    pos: (T, T, T),
+) -> T {
+    let (d, h, w) = grid.dim();
+    let [bz, by, bx] = grid.boundary();
+    let offset = centering.offset::<T>();
+    let (zs, u) = stencil(bz.is_periodic(), d, field.dim().0, pos.0 - offset.0);
+    let (ys, t) = stencil(by.is_periodic(), h, field.dim().1, pos.1 - offset.1);
+    let (xs, s) = stencil(bx.is_periodic(), w, field.dim().2, pos.2 - offset.2);
+
+    let mut layers = [T::zero(); 4];
+    for k in 0..4 {
+        let mut rows = [T::zero(); 4];
+        for j in 0..4 {
+            let (z, y) = (zs[k], ys[j]);
+            let row = [field.at((z, y, xs[0])), field.at((z, y, xs[1])), field.at((z, y, xs[2])), field.at((z, y, xs[3]))];
+            rows[j] = interpolation.interpolate(row, s);
+        }
+        layers[k] = interpolation.interpolate(rows, t);
+    }
+    interpolation.interpolate(layers, u)
+}
+
+/// Velocity `(y, x)` in world units of a dual 1-form.
+///
+/// The staggered values are integrated along the edges and divided by the spacing.
+pub fn velocity_2d<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>, interpolation: Interpolation, pos: (T, T)) -> (T, T) {
+    let (dy, dx) = grid.spacing();
+    let (vertical, horizontal) = velocity.split();
+    (
+        sample_2d(grid, vertical, Centering2d::Vertical, interpolation, pos) / T::new(dy),
+        sample_2d(grid, horizontal, Centering2d::Horizontal, interpolation, pos) / T::new(dx),
+    )
+}
+
+/// Velocity `(z, y, x)` of face centered normal components.
+pub fn velocity_3d<T: Real>(grid: &Grid3d, velocity: &Staggered3d<T>, interpolation: Interpolation, pos: (T, T, T)) -> (T, T, T) {
+    let (vz, vy, vx) = velocity.split();
+    (
+        sample_3d(grid, vz, Centering3d::FaceZ, interpolation, pos),
+        sample_3d(grid, vy, Centering3d::FaceY, interpolation, pos),
+        sample_3d(grid, vx, Centering3d::FaceX, interpolation, pos),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::Manifold2d;
+    use domain::AxisBoundary;
+    use ndarray::{Array1, Array2, Array3};
+    use super::*;
+
+    const SCHEMES: [Interpolation; 3] = [Interpolation::Linear, Interpolation::CatmullRom, Interpolation::MonotoneCubic];
+
+    #[test]
+    fn field_interpolation() {
+        // linear fields are reproduced by all schemes
+        let grid = Grid2d::new((6, 8)).with_spacing((0.5, 0.25));
+        let nodes = Array2::from_shape_fn((7, 9), |(y, x)| 2.0 * y as f64 * 0.5 - x as f64 * 0.25);
+        let cells = Array2::from_shape_fn((6, 8), |(y, x)| 2.0 * (y as f64 + 0.5) * 0.5 - (x as f64 + 0.5) * 0.25);
+        for &scheme in &SCHEMES {
+            for &pos in &[(1.3, 0.7), (0.9, 1.1), (2.2, 0.4)] {
+                let expected = 2.0 * pos.0 - pos.1;
+                assert!((sample_2d(&grid, nodes.view(), Centering2d::Node, scheme, pos) - expected).abs() < 1.0e-12);
+                assert!((sample_2d(&grid, cells.view(), Centering2d::Cell, scheme, pos) - expected).abs() < 1.0e-12);
+            }
+            // clamped outside of the samples
+            assert!((sample_2d(&grid, nodes.view(), Centering2d::Node, scheme, (-1.0, 0.5)) - -0.5).abs() < 1.0e-12);
+        }
+
+        // uniform flow
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().0.fill(0.5);
+        velocity.split_mut().1.fill(0.5);
+        let v = velocity_2d(&grid, &velocity, Interpolation::CatmullRom, (1.0, 1.0));
+        assert!((v.0 - 1.0).abs() < 1.0e-12 && (v.1 - 2.0).abs() < 1.0e-12);
+
+        // periodic axes wrap around
+        let periodic = Grid2d::with_boundary((4, 4), [AxisBoundary::periodic(), AxisBoundary::periodic()]);
+        let field = Array2::from_shape_fn((4, 4), |(_, x)| if x == 0 { 1.0f64 } else { 0.0 });
+        for &scheme in &SCHEMES {
+            let a = sample_2d(&periodic, field.view(), Centering2d::Cell, scheme, (1.0, 0.2));
+            let b = sample_2d(&periodic, field.view(), Centering2d::Cell, scheme, (1.0, 4.2));
+            assert!((a - b).abs() < 1.0e-12);
+        }
+
+        // catmull-rom overshoots at steps, the monotone spline doesn't
+        let step = Array1::from_vec(vec![0.0, 0.0, 1.0, 1.0, 1.0]).into_shape((1, 5)).unwrap();
+        let grid = Grid2d::new((0, 4));
+        let overshoot = |scheme| (0..40).map(|i| {
+            sample_2d(&grid, step.view(), Centering2d::Node, scheme, (0.0, 0.1 * i as f64))
+        }).fold(0.0f64, |max, v| max.max(v - 1.0).max(-v));
+        assert!(overshoot(Interpolation::CatmullRom) > 0.01);
+        assert_eq!(overshoot(Interpolation::MonotoneCubic), 0.0);
+
+        let grid = Grid3d::new((4, 4, 5));
+        let field = Array3::from_shape_fn((4, 4, 5), |(z, y, x)| z as f64 + 2.0 * y as f64 + 3.0 * x as f64 + 3.0);
+        for &scheme in &SCHEMES {
+            let value = sample_3d(&grid, field.view(), Centering3d::Cell, scheme, (1.7, 2.1, 3.3));
+            assert!((value - (1.7 + 2.0 * 2.1 + 3.0 * 3.3)).abs() < 1.0e-12);
+        }
+        let mut velocity = Staggered3d::faces(grid.dim(), 0.0);
+        velocity.split_mut().2.fill(1.5);
+        let v = velocity_3d(&grid, &velocity, Interpolation::Linear, (1.0, 1.0, 1.0));
+        assert_eq!(v, (0.0, 0.0, 1.5));
+    }
+}
@@ -1,5 +1,8 @@
 
+use cgmath::{InnerSpace, Vector3};
+use math::Real;
 use particle::{Particles, Property};
+use std::collections::HashMap;
 
 type Edges = Particles;
 type Faces = Particles;
@@ -93,3 +96,124 @@ impl Mesh {
         self.vertices.write_property::<T>()
     }
 }
+
+/// Triangle mesh with edge-list connectivity.
+///
+/// Edges are oriented from the lower to the higher vertex index, faces
+/// follow the winding order of their vertices.
+/// The measures of the circumcentric dual are computed on construction.
+pub struct TriangleMesh<T> {
+    positions: Vec<Vector3<T>>,
+    edges: Vec<[usize; 2]>,
+    faces: Vec<[usize; 3]>,
+    face_edges: Vec<[(usize, bool); 3]>,
+
+    vertex_dual_areas: Vec<T>,
+    edge_cotan_weights: Vec<T>,
+    face_areas: Vec<T>,
+}
+
+impl<T: Real> TriangleMesh<T> {
+    pub fn new(positions: Vec<Vector3<T>>, faces: Vec<[usize; 3]>) -> Self {
+        let mut edge_ids = HashMap::new();
+        let mut edges = Vec::new();
+        let mut face_edges = Vec::with_capacity(faces.len());
+
+        for face in &faces {
+            let mut face_edge = [(0, true); 3];
+            for i in 0..3 {
+                let (v0, v1) = (face[i], face[(i+1) % 3]);
+                let key = if v0 < v1 { [v0, v1] } else { [v1, v0] };
+                let id = *edge_ids.entry(key).or_insert(edges.len());
+                if id == edges.len() {
+                    edges.push(key);
+                }
+                face_edge[i] = (id, v0 < v1);
+            }
+            face_edges.push(face_edge);
+        }
+
+        let mut vertex_dual_areas = vec![T::zero(); positions.len()];
+        let mut edge_cotan_weights = vec![T::zero(); edges.len()];
+        let mut face_areas = Vec::with_capacity(faces.len());
+
+        let half = T::new(0.5);
+        let eighth = T::new(0.125);
+
+        for (face, face_edge) in faces.iter().zip(face_edges.iter()) {
+            let p = [positions[face[0]], positions[face[1]], positions[face[2]]];
+            face_areas.push(half * (p[1] - p[0]).cross(p[2] - p[0]).magnitude());
+
+            for i in 0..3 {
+                // edge (i, i+1) is opposite to vertex i+2
+                let (a, b, c) = (i, (i+1) % 3, (i+2) % 3);
+                let cot = cotan(p[a] - p[c], p[b] - p[c]);
+                edge_cotan_weights[face_edge[a].0] += half * cot;
+
+                let len2 = (p[b] - p[a]).magnitude2();
+                vertex_dual_areas[face[a]] += eighth * len2 * cot;
+                vertex_dual_areas[face[b]] += eighth * len2 * cot;
+            }
+        }
+
+        TriangleMesh {
+            positions,
+            edges,
+            faces,
+            face_edges,
+            vertex_dual_areas,
+            edge_cotan_weights,
+            face_areas,
+        }
+    }
+
+    pub fn num_vertices(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn num_edges(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn num_faces(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn positions(&self) -> &[Vector3<T>] {
+        &self.positions
+    }
+
+    /// Vertices of each edge.
+    pub fn edges(&self) -> &[[usize; 2]] {
+        &self.edges
+    }
+
+    /// Vertices of each face.
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    /// Edges of each face and whether their orientation agrees with the face.
+    pub fn face_edges(&self) -> &[[(usize, bool); 3]] {
+        &self.face_edges
+    }
+
+    /// Area of the circumcentric dual cell of each vertex.
+    pub fn vertex_dual_areas(&self) -> &[T] {
+        &self.vertex_dual_areas
+    }
+
+    /// Ratio of dual to primal edge length (cotan weights).
+    pub fn edge_cotan_weights(&self) -> &[T] {
+        &self.edge_cotan_weights
+    }
+
+    pub fn face_areas(&self) -> &[T] {
+        &self.face_areas
+    }
+}
+
+/// Cotangent of the angle between two vectors.
+fn cotan<T: Real>(u: Vector3<T>, v: Vector3<T>) -> T {
+    u.dot(v) / u.cross(v).magnitude()
+}
@@ -1,5 +1,14 @@
 
+pub mod amr;
+pub mod boundary;
 pub mod grid;
+pub mod interp;
 pub mod mesh;
+pub mod resample;
+pub mod sparse;
 
-pub use self::grid::{Grid2d, Grid3d};
\ No newline at end of file
+pub use self::boundary::{AxisBoundary, BoundaryCondition};
+pub use self::grid::{Grid2d, Grid3d, PeriodicGrid2d};
+pub use self::interp::{Centering2d, Centering3d, Field3d, Interpolation};
+pub use self::mesh::TriangleMesh;
+pub use self::sparse::SparseField3d;
\ No newline at end of file
@@ -0,0 +1,306 @@
+//! Transfer of fields between grids of different resolution
+//!
+//! Both grids are assumed to cover the same domain, sample positions are
+//! mapped relative to the grid extents. Used for multigrid transfers and to
+//! restart simulations at a different resolution.
+//!
+//! Interpolating variants sample the source field at the destination sample
+//! positions with any `Interpolation` scheme. Conservative variants average the
+//! source over the destination cells instead:
+//!
+//! - Cell fields keep their integral `∫ f dA`.
+//! - Staggered velocities keep the flux through each destination face. Values
+//!   are averaged along the faces and interpolated linearly across them, for
+//!   integer resolution ratios (restriction and prolongation) divergence-free
+//!   fields remain divergence-free.
+//!
+//! Staggered values are rescaled to the destination spacing: 2d velocities
+//! are integrated along the edges, 3d velocities are given in grid units.
+
+use dec::grid::{Staggered2d, Staggered3d};
+use dec::manifold::Manifold2d;
+use math::Real;
+use ndarray::{Array, Array2, Array3, ArrayView, ArrayView2, ArrayView3, Axis, RemoveAxis};
+
+use super::grid::{Grid2d, Grid3d};
+use super::interp::{sample_2d, sample_3d, Centering2d, Centering3d, Field3d, Interpolation};
+
+/// Samples of a field along one axis with `cells` cells.
+fn samples(cells: usize, cell_centered: bool) -> usize {
+    if cell_centered { cells } else { cells + 1 }
+}
+
+/// Position of the destination sample `i` in source grid units.
+fn map_position<T: Real>(i: usize, offset: T, src: usize, dst: usize) -> T {
+    (T::new(i) + offset) * T::new(src) / T::new(dst)
+}
+
+/// Interpolate a 2d field at the sample positions of the destination grid.
+pub fn resample_2d<T: Real>(
+    src: &Grid2d,
+    field: ArrayView2<T>,
+    dst: &Grid2d,
+    centering: Centering2d,
+    interpolation: Interpolation,
+) -> Array2<T> {
+    let ((h0, w0), (h1, w1)) = (src.dim(), dst.dim());
+    let (dy, dx) = src.spacing();
+    let offset = centering.offset::<T>();
+    let shape = (samples(h1, offset.0 > T::zero()), samples(w1, offset.1 > T::zero()));
+    Array2::from_shape_fn(shape, |(y, x)| {
+        let pos = (
+            map_position(y, offset.0, h0, h1) * T::new(dy),
+            map_position(x, offset.1, w0, w1) * T::new(dx),
+        );
+        sample_2d(src, field, centering, interpolation, pos)
+    })
+}
+
+/// Interpolate a 3d field at the sample positions of the destination grid.
+pub fn resample_3d<T: Real, F: Field3d<T>>(
+    src: &Grid3d,
+    field: F,
+    dst: &Grid3d,
+    centering: Centering3d,
+    interpolation: Interpolation,
+) -> Array3<T> {
+    let ((d0, h0, w0), (d1, h1, w1)) = (src.dim(), dst.dim());
+    let offset = centering.offset::<T>();
+    let shape = (
+        samples(d1, offset.0 > T::zero()),
+        samples(h1, offset.1 > T::zero()),
+        samples(w1, offset.2 > T::zero()),
+    );
+    Array3::from_shape_fn(shape, |(z, y, x)| {
+        let pos = (
+            map_position(z, offset.0, d0, d1),
+            map_position(y, offset.1, h0, h1),
+            map_position(x, offset.2, w0, w1),
+        );
+        sample_3d(src, &field, centering, interpolation, pos)
+    })
+}
+
+/// Interpolate a 2d velocity at the edges of the destination grid.
+pub fn resample_staggered_2d<T: Real>(
+    src: &Grid2d,
+    velocity: &Staggered2d<T>,
+    dst: &Grid2d,
+    interpolation: Interpolation,
+) -> Staggered2d<T> {
+    let (vertical, horizontal) = velocity.split();
+    let vertical = resample_2d(src, vertical, dst, Centering2d::Vertical, interpolation);
+    let horizontal = resample_2d(src, horizontal, dst, Centering2d::Horizontal, interpolation);
+    staggered_2d(src, dst, vertical, horizontal)
+}
+
+/// Interpolate a 3d velocity at the faces of the destination grid.
+pub fn resample_staggered_3d<T: Real>(
+    src: &Grid3d,
+    velocity: &Staggered3d<T>,
+    dst: &Grid3d,
+    interpolation: Interpolation,
+) -> Staggered3d<T> {
+    check_faces(src, velocity);
+    let (vz, vy, vx) = velocity.split();
+    staggered_3d(src, dst, [
+        resample_3d(src, vz, dst, Centering3d::FaceZ, interpolation),
+        resample_3d(src, vy, dst, Centering3d::FaceY, interpolation),
+        resample_3d(src, vx, dst, Centering3d::FaceX, interpolation),
+    ])
+}
+
+/// Average a cell-centered 2d field over the destination cells.
+pub fn resample_conservative_2d<T: Real>(src: &Grid2d, field: ArrayView2<T>, dst: &Grid2d) -> Array2<T> {
+    let ((h0, w0), (h1, w1)) = (src.dim(), dst.dim());
+    assert_eq!(field.dim(), (h0, w0), "expected cell-centered field");
+    let field = transform_axis(field, 0, &overlap_weights(h0, h1));
+    transform_axis(field.view(), 1, &overlap_weights(w0, w1))
+}
+
+/// Average a cell-centered 3d field over the destination cells.
+pub fn resample_conservative_3d<T: Real>(src: &Grid3d, field: ArrayView3<T>, dst: &Grid3d) -> Array3<T> {
+    let ((d0, h0, w0), (d1, h1, w1)) = (src.dim(), dst.dim());
+    assert_eq!(field.dim(), (d0, h0, w0), "expected cell-centered field");
+    let field = transform_axis(field, 0, &overlap_weights(d0, d1));
+    let field = transform_axis(field.view(), 1, &overlap_weights(h0, h1));
+    transform_axis(field.view(), 2, &overlap_weights(w0, w1))
+}
+
+/// Transfer the fluxes of a 2d velocity to the edges of the destination grid.
+pub fn resample_staggered_conservative_2d<T: Real>(
+    src: &Grid2d,
+    velocity: &Staggered2d<T>,
+    dst: &Grid2d,
+) -> Staggered2d<T> {
+    let ((h0, w0), (h1, w1)) = (src.dim(), dst.dim());
+    let (vertical, horizontal) = velocity.split();
+    let vertical = transform_axis(vertical, 0, &node_weights(h0, h1));
+    let vertical = transform_axis(vertical.view(), 1, &overlap_weights(w0, w1));
+    let horizontal = transform_axis(horizontal, 0, &overlap_weights(h0, h1));
+    let horizontal = transform_axis(horizontal.view(), 1, &node_weights(w0, w1));
+    staggered_2d(src, dst, vertical, horizontal)
+}
+
+/// Transfer the fluxes of a 3d velocity to the faces of the destination grid.
+pub fn resample_staggered_conservative_3d<T: Real>(
+    src: &Grid3d,
+    velocity: &Staggered3d<T>,
+    dst: &Grid3d,
+) -> Staggered3d<T> {
+    check_faces(src, velocity);
+    let ((d0, h0, w0), (d1, h1, w1)) = (src.dim(), dst.dim());
+    let weights = [
+        (node_weights(d0, d1), overlap_weights(d0, d1)),
+        (node_weights(h0, h1), overlap_weights(h0, h1)),
+        (node_weights(w0, w1), overlap_weights(w0, w1)),
+    ];
+    let (vz, vy, vx) = velocity.split();
+    let mut components = Vec::with_capacity(3);
+    for (normal, component) in [vz, vy, vx].iter().enumerate() {
+        let mut field = component.to_owned();
+        for axis in 0..3 {
+            let (ref nodes, ref cells) = weights[axis];
+            field = transform_axis(field.view(), axis, if axis == normal { nodes } else { cells });
+        }
+        components.push(field);
+    }
+    let vx = components.pop().unwrap();
+    let vy = components.pop().unwrap();
+    let vz = components.pop().unwrap();
+    staggered_3d(src, dst, [vz, vy, vx])
+}
+
+/// Weights of the source cells overlapping each destination cell, normalized to one.
+fn overlap_weights<T: Real>(src: usize, dst: usize) -> Vec<Vec<(usize, T)>> {
+    assert!(src > 0 && dst > 0, "grids need at least one cell per axis");
+    let scale = src as f64 / dst as f64;
+    (0..dst).map(|j| {
+        let (a, b) = (j as f64 * scale, (j + 1) as f64 * scale);
+        let end = (b.ceil() as usize).min(src);
+        (a.floor() as usize..end)
+            .map(|i| (i, T::new((b.min(i as f64 + 1.0) - a.max(i as f64)) / scale)))
+            .filter(|&(_, w)| w > T::zero())
+            .collect()
+    }).collect()
+}
+
+/// Linear interpolation weights of the source nodes at the destination nodes.
+fn node_weights<T: Real>(src: usize, dst: usize) -> Vec<Vec<(usize, T)>> {
+    assert!(src > 0 && dst > 0, "grids need at least one cell per axis");
+    (0..dst + 1).map(|j| {
+        let p = (j * src) as f64 / dst as f64;
+        let i = (p.floor() as usize).min(src - 1);
+        let s = p - i as f64;
+        vec![(i, T::new(1.0 - s)), (i + 1, T::new(s))]
+    }).collect()
+}
+
+/// Linear combination of source slices along `axis` for each destination slice.
+fn transform_axis<T, D>(field: ArrayView<T, D>, axis: usize, weights: &[Vec<(usize, T)>]) -> Array<T, D>
+    where T: Real, D: RemoveAxis
+{
+    let mut dim = field.raw_dim();
+    dim[axis] = weights.len();
+    let mut result = Array::zeros(dim);
+    for (j, row) in weights.iter().enumerate() {
+        let mut slice = result.subview_mut(Axis(axis), j);
+        for &(i, w) in row {
+            slice.scaled_add(w, &field.subview(Axis(axis), i));
+        }
+    }
+    result
+}
+
+fn check_faces<T>(grid: &Grid3d, velocity: &Staggered3d<T>) {
+    let (d, h, w) = grid.dim();
+    assert_eq!(velocity.shape()[0], (d+1, h, w), "expected face storage");
+}
+
+/// Velocity on the destination edges, rescaled to its spacing.
+fn staggered_2d<T: Real>(src: &Grid2d, dst: &Grid2d, vertical: Array2<T>, horizontal: Array2<T>) -> Staggered2d<T> {
+    let ((dy0, dx0), (dy1, dx1)) = (src.spacing(), dst.spacing());
+    let mut velocity = <Grid2d as Manifold2d<T>>::new_simplex_1(dst);
+    {
+        let (mut v, mut h) = velocity.split_mut();
+        v.zip_mut_with(&vertical, |v, &s| *v = s * T::new(dy1 / dy0));
+        h.zip_mut_with(&horizontal, |h, &s| *h = s * T::new(dx1 / dx0));
+    }
+    velocity
+}
+
+/// Velocity on the destination faces, rescaled to its grid units.
+fn staggered_3d<T: Real>(src: &Grid3d, dst: &Grid3d, components: [Array3<T>; 3]) -> Staggered3d<T> {
+    let ((d0, h0, w0), (d1, h1, w1)) = (src.dim(), dst.dim());
+    let scale = [d1 as f64 / d0 as f64, h1 as f64 / h0 as f64, w1 as f64 / w0 as f64];
+    let mut velocity = Staggered3d::faces(dst.dim(), T::zero());
+    {
+        let (vz, vy, vx) = velocity.split_mut();
+        for ((out, values), &scale) in [vz, vy, vx].iter_mut().zip(components.iter()).zip(scale.iter()) {
+            out.zip_mut_with(values, |v, &s| *v = s * T::new(scale));
+        }
+    }
+    velocity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn divergence(grid: &Grid2d, velocity: &Staggered2d<f64>) -> f64 {
+        let mut primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(grid);
+        let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(grid);
+        grid.hodge_1_dual(&mut primal, velocity);
+        grid.derivative_1_primal(&mut divergence, &primal);
+        divergence.fold(0.0, |max, &div| max.max(div.abs()))
+    }
+
+    #[test]
+    fn restriction_and_prolongation() {
+        let fine = Grid2d::new((16, 12)).with_spacing((0.5, 0.5));
+        let coarse = Grid2d::new((8, 6));
+
+        // cell averages keep the integral
+        let field = Array2::from_shape_fn((16, 12), |(y, x)| ((y * 12 + x) as f64 * 0.7).sin());
+        let restricted = resample_conservative_2d(&fine, field.view(), &coarse);
+        let prolongated = resample_conservative_2d(&coarse, restricted.view(), &fine);
+        assert!((field.scalar_sum() * 0.25 - restricted.scalar_sum()).abs() < 1.0e-12);
+        assert!((prolongated.scalar_sum() - field.scalar_sum()).abs() < 1.0e-12);
+        assert!((restricted[(1, 2)] - field.slice(s![2..4, 4..6]).scalar_sum() * 0.25).abs() < 1.0e-12);
+
+        // linear fields are reproduced when interpolating inside the source samples
+        let linear = Array2::from_shape_fn((16, 12), |(y, x)| 2.0 * (y as f64 + 0.5) * 0.5 - (x as f64 + 0.5) * 0.5);
+        let sampled = resample_2d(&fine, linear.view(), &coarse, Centering2d::Cell, Interpolation::Linear);
+        for ((y, x), &v) in sampled.indexed_iter() {
+            assert!((v - (2.0 * (y as f64 + 0.5) - (x as f64 + 0.5))).abs() < 1.0e-12);
+        }
+
+        // divergence-free velocity from a stream function
+        let stream = Array2::from_shape_fn((17, 13), |(y, x)| (0.3 * y as f64).sin() * (0.4 * x as f64).cos());
+        let mut edges = <Grid2d as Manifold2d<f64>>::new_simplex_1(&fine);
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&fine);
+        fine.derivative_0_primal(&mut edges, &stream);
+        fine.hodge_1_primal(&mut velocity, &edges);
+        assert!(divergence(&fine, &velocity) < 1.0e-12);
+
+        let restricted = resample_staggered_conservative_2d(&fine, &velocity, &coarse);
+        assert!(divergence(&coarse, &restricted) < 1.0e-12);
+        let prolongated = resample_staggered_conservative_2d(&coarse, &restricted, &fine);
+        assert!(divergence(&fine, &prolongated) < 1.0e-12);
+
+        // uniform flow keeps its velocity
+        let mut uniform = <Grid2d as Manifold2d<f64>>::new_simplex_1(&fine);
+        uniform.split_mut().1.fill(0.5);
+        let resampled = resample_staggered_2d(&fine, &uniform, &coarse, Interpolation::Linear);
+        assert!(resampled.split().1.iter().all(|&v| (v - 1.0).abs() < 1.0e-12));
+
+        // 3d velocities are given in grid units
+        let (src, dst) = (Grid3d::new((4, 4, 4)), Grid3d::new((8, 8, 8)));
+        let mut velocity = Staggered3d::faces(src.dim(), 0.0f64);
+        velocity.split_mut().0.fill(1.0);
+        let resampled = resample_staggered_conservative_3d(&src, &velocity, &dst);
+        assert!(resampled.split().0.iter().all(|&v| (v - 2.0).abs() < 1.0e-12));
+        let density = Array3::from_elem((4, 4, 4), 1.0);
+        assert_eq!(resample_conservative_3d(&src, density.view(), &dst).scalar_sum(), 512.0);
+    }
+}
@@ -0,0 +1,211 @@
+//! Sparse tiled storage of 3d fields
+//!
+//! Fields of localized phenomena (smoke plumes, liquid sheets) are mostly
+//! constant. `SparseField3d` splits the sample domain into cubic tiles of
+//! `TILE_SIZE³` samples and only allocates tiles containing values different
+//! from the background, similar to the leaf level of a VDB tree. Unallocated
+//! tiles read as background value.
+//!
+//! A dense table maps tile coordinates to allocated tiles, which costs one
+//! index per `TILE_SIZE³` samples. Fields implement `Field3d` and can be
+//! interpolated like dense arrays.
+//!
+//! References:
+//!     [Mus13] Ken Museth, 2013,
+//!             VDB: High-resolution sparse volumes with dynamic topology,
+//!             ACM Trans. Graph. 32, 3, Article 27 (July 2013)
+
+use math::Real;
+use ndarray::{Array3, ArrayView3};
+use std::usize;
+
+use super::interp::Field3d;
+
+/// Number of samples per tile along each axis.
+pub const TILE_SIZE: usize = 8;
+const TILE_LEN: usize = TILE_SIZE * TILE_SIZE * TILE_SIZE;
+const EMPTY: usize = usize::MAX;
+
+#[derive(Clone, Debug)]
+pub struct SparseField3d<T> {
+    dim: (usize, usize, usize),
+    tiles_dim: (usize, usize, usize),
+    background: T,
+    /// Index into `tiles` for each tile, `EMPTY` if unallocated.
+    table: Vec<usize>,
+    tiles: Vec<Vec<T>>,
+    /// Tile coordinates of each allocated tile.
+    coords: Vec<(usize, usize, usize)>,
+}
+
+impl<T: Real> SparseField3d<T> {
+    /// Field with `dim` samples `(z, y, x)`, all set to the background value.
+    pub fn new(dim: (usize, usize, usize), background: T) -> Self {
+        let tiles = |n: usize| (n + TILE_SIZE - 1) / TILE_SIZE;
+        let tiles_dim = (tiles(dim.0), tiles(dim.1), tiles(dim.2));
+        SparseField3d {
+            dim,
+            tiles_dim,
+            background,
+            table: vec![EMPTY; tiles_dim.0 * tiles_dim.1 * tiles_dim.2],
+            tiles: Vec::new(),
+            coords: Vec::new(),
+        }
+    }
+
+    /// Sparse copy of a dense field, tiles within `tolerance` of the background aren't allocated.
+    pub fn from_dense(field: ArrayView3<T>, background: T, tolerance: T) -> Self {
+        let mut sparse = SparseField3d::new(field.dim(), background);
+        for (index, &value) in field.indexed_iter() {
+            if (value - background).abs() > tolerance {
+                *sparse.get_mut(index) = value;
+            }
+        }
+        sparse
+    }
+
+    pub fn to_dense(&self) -> Array3<T> {
+        Array3::from_shape_fn(self.dim, |index| self.get(index))
+    }
+
+    pub fn dim(&self) -> (usize, usize, usize) {
+        self.dim
+    }
+
+    pub fn background(&self) -> T {
+        self.background
+    }
+
+    /// Number of allocated tiles.
+    pub fn num_tiles(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Number of stored samples, including the unused parts of tiles at the border.
+    pub fn num_allocated(&self) -> usize {
+        self.tiles.len() * TILE_LEN
+    }
+
+    /// Tile index and offset inside the tile of a sample.
+    fn locate(&self, (z, y, x): (usize, usize, usize)) -> (usize, usize) {
+        assert!(z < self.dim.0 && y < self.dim.1 && x < self.dim.2,
+            "index {:?} out of bounds {:?}", (z, y, x), self.dim);
+        let (tz, ty, tx) = (z / TILE_SIZE, y / TILE_SIZE, x / TILE_SIZE);
+        let (oz, oy, ox) = (z % TILE_SIZE, y % TILE_SIZE, x % TILE_SIZE);
+        let tile = (tz * self.tiles_dim.1 + ty) * self.tiles_dim.2 + tx;
+        (tile, (oz * TILE_SIZE + oy) * TILE_SIZE + ox)
+    }
+
+    pub fn get(&self, index: (usize, usize, usize)) -> T {
+        let (tile, offset) = self.locate(index);
+        match self.table[tile] {
+            EMPTY => self.background,
+            slot => self.tiles[slot][offset],
+        }
+    }
+
+    /// Mutable sample, allocates the tile if required.
+    pub fn get_mut(&mut self, index: (usize, usize, usize)) -> &mut T {
+        let (tile, offset) = self.locate(index);
+        if self.table[tile] == EMPTY {
+            let (ty, tx) = (self.tiles_dim.1, self.tiles_dim.2);
+            self.table[tile] = self.tiles.len();
+            self.tiles.push(vec![self.background; TILE_LEN]);
+            self.coords.push((tile / (ty * tx), (tile / tx) % ty, tile % tx));
+        }
+        &mut self.tiles[self.table[tile]][offset]
+    }
+
+    /// Set a sample, setting unallocated samples to the background doesn't allocate.
+    pub fn set(&mut self, index: (usize, usize, usize), value: T) {
+        let (tile, _) = self.locate(index);
+        if self.table[tile] == EMPTY && value == self.background {
+            return;
+        }
+        *self.get_mut(index) = value;
+    }
+
+    /// Call `f` with index and value of all samples in allocated tiles.
+    pub fn for_each_active<F: FnMut((usize, usize, usize), T)>(&self, mut f: F) {
+        for (tile, &(tz, ty, tx)) in self.tiles.iter().zip(self.coords.iter()) {
+            for (offset, &value) in tile.iter().enumerate() {
+                let (oz, oy, ox) = (offset / (TILE_SIZE * TILE_SIZE), (offset / TILE_SIZE) % TILE_SIZE, offset % TILE_SIZE);
+                let index = (tz * TILE_SIZE + oz, ty * TILE_SIZE + oy, tx * TILE_SIZE + ox);
+                if index.0 < self.dim.0 && index.1 < self.dim.1 && index.2 < self.dim.2 {
+                    f(index, value);
+                }
+            }
+        }
+    }
+
+    /// Release tiles with all samples within `tolerance` of the background.
+    pub fn prune(&mut self, tolerance: T) {
+        let background = self.background;
+        let mut slot = 0;
+        while slot < self.tiles.len() {
+            if self.tiles[slot].iter().all(|&v| (v - background).abs() <= tolerance) {
+                let (tz, ty, tx) = self.coords[slot];
+                self.table[(tz * self.tiles_dim.1 + ty) * self.tiles_dim.2 + tx] = EMPTY;
+                self.tiles.swap_remove(slot);
+                self.coords.swap_remove(slot);
+                if slot < self.tiles.len() {
+                    // the last tile moved into the released slot
+                    let (tz, ty, tx) = self.coords[slot];
+                    self.table[(tz * self.tiles_dim.1 + ty) * self.tiles_dim.2 + tx] = slot;
+                }
+            } else {
+                slot += 1;
+            }
+        }
+    }
+}
+
+impl<T: Real> Field3d<T> for SparseField3d<T> {
+    fn dim(&self) -> (usize, usize, usize) {
+        self.dim
+    }
+
+    fn at(&self, index: (usize, usize, usize)) -> T {
+        self.get(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::{Centering3d, Grid3d, Interpolation};
+    use domain::interp::sample_3d;
+    use super::*;
+
+    #[test]
+    fn sparse_plume() {
+        // plume in a small part of the domain
+        let grid = Grid3d::new((40, 64, 64));
+        let dense = Array3::from_shape_fn(grid.dim(), |(z, y, x)| {
+            let r2 = (y as f64 - 30.0).powi(2) + (x as f64 - 20.0).powi(2);
+            if r2 < 16.0 && z < 20 { 1.0 - r2 / 16.0 + 0.01 * z as f64 } else { 0.0 }
+        });
+        let mut sparse = SparseField3d::from_dense(dense.view(), 0.0, 0.0);
+        assert!(sparse.num_allocated() < dense.len() / 10);
+        assert_eq!(sparse.to_dense(), dense);
+
+        for &scheme in &[Interpolation::Linear, Interpolation::CatmullRom] {
+            for &pos in &[(3.2, 29.5, 20.7), (19.9, 33.1, 17.4), (30.0, 10.0, 50.0)] {
+                let a = sample_3d(&grid, dense.view(), Centering3d::Cell, scheme, pos);
+                let b = sample_3d(&grid, &sparse, Centering3d::Cell, scheme, pos);
+                assert_eq!(a, b);
+            }
+        }
+
+        // clearing the plume releases all tiles
+        let mut active = Vec::new();
+        sparse.for_each_active(|index, value| if value != 0.0 { active.push(index) });
+        assert_eq!(active.len(), dense.iter().filter(|&&v| v != 0.0).count());
+        for index in active {
+            sparse.set(index, 0.0);
+        }
+        sparse.set((39, 63, 63), 0.0);
+        sparse.prune(0.0);
+        assert_eq!(sparse.num_tiles(), 0);
+        assert_eq!(sparse.get((5, 30, 20)), 0.0);
+    }
+}
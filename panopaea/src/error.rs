@@ -0,0 +1,87 @@
+//! Errors of the fallible setup functions
+//!
+//! Most operators assume consistent inputs and panic otherwise. Setup paths
+//! which depend on user input (scene descriptions, checkpoints, particle sets)
+//! report failures as `Error` instead, panicking variants of the constructors
+//! remain available for hard-coded setups.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Array dimensions don't match the domain.
+    ShapeMismatch {
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+    /// Invalid parameter of a domain or solver, e.g. a non-positive grid spacing.
+    InvalidParameter(String),
+    /// Combination of features without an implementation.
+    Unsupported(&'static str),
+    /// Particles aren't sorted by the cell keys of a grid.
+    UnsortedParticles,
+    Io(io::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    pub fn shape_mismatch<E: AsRef<[usize]>, F: AsRef<[usize]>>(expected: E, found: F) -> Self {
+        Error::ShapeMismatch {
+            expected: expected.as_ref().to_vec(),
+            found: found.as_ref().to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::ShapeMismatch { ref expected, ref found } => {
+                write!(f, "shape mismatch: expected {:?}, found {:?}", expected, found)
+            }
+            Error::InvalidParameter(ref msg) => write!(f, "invalid parameter: {}", msg),
+            Error::Unsupported(what) => write!(f, "unsupported: {}", what),
+            Error::UnsortedParticles => write!(f, "particles aren't sorted by cell"),
+            Error::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ShapeMismatch { .. } => "shape mismatch",
+            Error::InvalidParameter(ref msg) => msg,
+            Error::Unsupported(what) => what,
+            Error::UnsortedParticles => "unsorted particles",
+            Error::Io(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// Errors in `io` functions are reported as `InvalidData`.
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Finite-time Lyapunov exponents
+//!
+//! Lagrangian coherent structures show up as ridges of the FTLE field
+//! `σ(x) = ln(sqrt(λ_max(C))) / |T|`, where `C = ∇Φᵀ ∇Φ` is the right
+//! Cauchy-Green tensor of the flow map `Φ` over the integration time `T`.
+//! Forward integration reveals repelling, backward integration (negative
+//! duration) attracting structures.
+//!
+//! The flow map is computed by advecting a regular lattice of tracers through
+//! a `FlowField`, e.g. a `FlowSequence2d` of simulation output, its gradient
+//! by finite differences over the lattice.
+//!
+//! References:
+//!     [Hal01] George Haller, 2001,
+//!             Distinguished material surfaces and coherent structures in three-dimensional fluid flows,
+//!             Physica D 149 (4), 248-277
+//!     [SLM05] Shawn C. Shadden, Francois Lekien and Jerrold E. Marsden, 2005,
+//!             Definition and properties of Lagrangian coherent structures from finite-time Lyapunov exponents in two-dimensional aperiodic flows,
+//!             Physica D 212 (3-4), 271-304
+
+use math::{Dim, Real, VectorN};
+use math::integration::Integrator;
+use math::vector_n::{vec2, vec3};
+use ndarray::{Array2, Array3};
+use tracers::{FlowField, Tracers};
+use typenum::{U2, U3};
+
+/// Advect the tracers from `start` over `duration` in steps of at most `step`.
+fn advect_lattice<T, N, F, I>(tracers: &mut Tracers<T, N>, field: &F, integrator: &I, duration: T, step: T)
+    where T: Real,
+          N: Dim<T>,
+          F: FlowField<T, N>,
+          I: Integrator<T> + Sync,
+{
+    assert!(step > T::zero(), "step size needs to be positive");
+    let steps = (duration.abs() / step).ceil().to_usize().unwrap().max(1);
+    let dt = duration / T::new(steps);
+    for _ in 0..steps {
+        tracers.advect(field, integrator, dt);
+    }
+}
+
+/// Flow map of a lattice of `dim = (h, w)` seeds at `origin + (i dx, j dy)`, indexed `(j, i)`.
+pub fn flow_map_2d<T, F, I>(
+    field: &F,
+    integrator: &I,
+    origin: VectorN<T, U2>,
+    spacing: VectorN<T, U2>,
+    dim: (usize, usize),
+    start: T,
+    duration: T,
+    step: T,
+) -> Array2<VectorN<T, U2>>
+    where T: Real,
+          F: FlowField<T, U2>,
+          I: Integrator<T> + Sync,
+{
+    let (h, w) = dim;
+    let seeds = (0..h)
+        .flat_map(|j| (0..w).map(move |i| (j, i)))
+        .map(|(j, i)| origin.clone() + vec2(T::new(i) * spacing[0], T::new(j) * spacing[1]))
+        .collect();
+    let mut tracers = Tracers::new(seeds, start);
+    advect_lattice(&mut tracers, field, integrator, duration, step);
+    Array2::from_shape_vec(dim, tracers.positions).unwrap()
+}
+
+/// Flow map of a lattice of `dim = (d, h, w)` seeds at `origin + (i dx, j dy, k dz)`, indexed `(k, j, i)`.
+pub fn flow_map_3d<T, F, I>(
+    field: &F,
+    integrator: &I,
+    origin: VectorN<T, U3>,
+    spacing: VectorN<T, U3>,
+    dim: (usize, usize, usize),
+    start: T,
+    duration: T,
+    step: T,
+) -> Array3<VectorN<T, U3>>
+    where T: Real,
+          F: FlowField<T, U3>,
+          I: Integrator<T> + Sync,
+{
+    let (d, h, w) = dim;
+    let seeds = (0..d)
+        .flat_map(|k| (0..h).flat_map(move |j| (0..w).map(move |i| (k, j, i))))
+        .map(|(k, j, i)| origin.clone() + vec3(T::new(i) * spacing[0], T::new(j) * spacing[1], T::new(k) * spacing[2]))
+        .collect();
+    let mut tracers = Tracers::new(seeds, start);
+    advect_lattice(&mut tracers, field, integrator, duration, step);
+    Array3::from_shape_vec(dim, tracers.positions).unwrap()
+}
+
+/// Derivative along a lattice axis, central differences inside and one-sided at the ends.
+fn difference<T, F>(sample: F, index: usize, len: usize, spacing: T) -> T
+    where T: Real, F: Fn(usize) -> T
+{
+    assert!(len > 1, "flow map needs at least two seeds per axis");
+    if index == 0 {
+        (sample(1) - sample(0)) / spacing
+    } else if index == len - 1 {
+        (sample(len - 1) - sample(len - 2)) / spacing
+    } else {
+        (sample(index + 1) - sample(index - 1)) / (T::new(2.0) * spacing)
+    }
+}
+
+/// FTLE from the largest eigenvalue of the Cauchy-Green tensor.
+fn exponent<T: Real>(lambda_max: T, duration: T) -> T {
+    lambda_max.max(T::min_positive_value()).ln() / (T::new(2.0) * duration.abs())
+}
+
+/// FTLE field of a 2d flow map with lattice `spacing` over `duration`.
+pub fn ftle_2d<T: Real>(flow_map: &Array2<VectorN<T, U2>>, spacing: VectorN<T, U2>, duration: T) -> Array2<T> {
+    let (h, w) = flow_map.dim();
+    Array2::from_shape_fn((h, w), |(j, i)| {
+        // `f[a][b] = ∂Φ_a/∂X_b`
+        let mut f = [[T::zero(); 2]; 2];
+        for a in 0..2 {
+            f[a][0] = difference(|i| flow_map[(j, i)][a], i, w, spacing[0]);
+            f[a][1] = difference(|j| flow_map[(j, i)][a], j, h, spacing[1]);
+        }
+        let c = cauchy_green_2(&f);
+        // largest eigenvalue of the symmetric 2x2 tensor
+        let mean = (c[0][0] + c[1][1]) * T::new(0.5);
+        let radius = ((c[0][0] - c[1][1]) * T::new(0.5)).hypot(c[0][1]);
+        exponent(mean + radius, duration)
+    })
+}
+
+/// FTLE field of a 3d flow map with lattice `spacing` over `duration`.
+pub fn ftle_3d<T: Real>(flow_map: &Array3<VectorN<T, U3>>, spacing: VectorN<T, U3>, duration: T) -> Array3<T> {
+    let (d, h, w) = flow_map.dim();
+    Array3::from_shape_fn((d, h, w), |(k, j, i)| {
+        let mut f = [[T::zero(); 3]; 3];
+        for a in 0..3 {
+            f[a][0] = difference(|i| flow_map[(k, j, i)][a], i, w, spacing[0]);
+            f[a][1] = difference(|j| flow_map[(k, j, i)][a], j, h, spacing[1]);
+            f[a][2] = difference(|k| flow_map[(k, j, i)][a], k, d, spacing[2]);
+        }
+        exponent(max_eigenvalue_symmetric_3(&cauchy_green_3(&f)), duration)
+    })
+}
+
+/// Right Cauchy-Green tensor `Fᵀ F` of a 2d deformation gradient.
+fn cauchy_green_2<T: Real>(f: &[[T; 2]; 2]) -> [[T; 2]; 2] {
+    let mut c = [[T::zero(); 2]; 2];
+    for a in 0..2 {
+        for b in 0..2 {
+            c[a][b] = f[0][a] * f[0][b] + f[1][a] * f[1][b];
+        }
+    }
+    c
+}
+
+/// Right Cauchy-Green tensor `Fᵀ F` of a 3d deformation gradient.
+fn cauchy_green_3<T: Real>(f: &[[T; 3]; 3]) -> [[T; 3]; 3] {
+    let mut c = [[T::zero(); 3]; 3];
+    for a in 0..3 {
+        for b in 0..3 {
+            c[a][b] = f[0][a] * f[0][b] + f[1][a] * f[1][b] + f[2][a] * f[2][b];
+        }
+    }
+    c
+}
+
+/// Largest eigenvalue of a symmetric 3x3 matrix, trigonometric solution of the characteristic polynomial.
+fn max_eigenvalue_symmetric_3<T: Real>(m: &[[T; 3]; 3]) -> T {
+    let off = m[0][1] * m[0][1] + m[0][2] * m[0][2] + m[1][2] * m[1][2];
+    let q = (m[0][0] + m[1][1] + m[2][2]) / T::new(3.0);
+    let (d0, d1, d2) = (m[0][0] - q, m[1][1] - q, m[2][2] - q);
+    let p = ((d0 * d0 + d1 * d1 + d2 * d2 + T::new(2.0) * off) / T::new(6.0)).sqrt();
+    if p <= T::zero() {
+        return q;
+    }
+
+    // `B = (M - qI) / p`, eigenvalues `q + 2p cos(φ + 2πk/3)`
+    let b = [[d0 / p, m[0][1] / p, m[0][2] / p], [m[0][1] / p, d1 / p, m[1][2] / p], [m[0][2] / p, m[1][2] / p, d2 / p]];
+    let det = b[0][0] * (b[1][1] * b[2][2] - b[1][2] * b[2][1])
+            - b[0][1] * (b[1][0] * b[2][2] - b[1][2] * b[2][0])
+            + b[0][2] * (b[1][0] * b[2][1] - b[1][1] * b[2][0]);
+    let r = (det * T::new(0.5)).max(-T::one()).min(T::one());
+    q + T::new(2.0) * p * (r.acos() / T::new(3.0)).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use math::integration::Rk4;
+    use super::*;
+
+    #[test]
+    fn saddle_flows() {
+        // hyperbolic saddle stretching along `x` at unit rate
+        let saddle = |_: f64, p: &VectorN<f64, U2>| vec2(p[0], -p[1]);
+        let flow_map = flow_map_2d(&saddle, &Rk4, vec2(-1.0, -1.0), vec2(0.25, 0.25), (9, 9), 0.0, 2.0, 0.01);
+        let end = flow_map[(4, 8)];
+        assert!((end[0] - 2.0f64.exp()).abs() < 1.0e-6 && end[1].abs() < 1.0e-12);
+        for &sigma in ftle_2d(&flow_map, vec2(0.25, 0.25), 2.0).iter() {
+            assert!((sigma - 1.0).abs() < 1.0e-6, "{}", sigma);
+        }
+
+        // backward integration sees the contracting direction
+        let flow_map = flow_map_2d(&saddle, &Rk4, vec2(-1.0, -1.0), vec2(0.25, 0.25), (5, 5), 0.0, -1.0, 0.01);
+        for &sigma in ftle_2d(&flow_map, vec2(0.25, 0.25), -1.0).iter() {
+            assert!((sigma - 1.0).abs() < 1.0e-6, "{}", sigma);
+        }
+
+        // volume preserving 3d saddle with a rotation in the contracting plane
+        let flow = |_: f64, p: &VectorN<f64, U3>| vec3(p[0], -0.5 * p[1] - p[2], p[1] - 0.5 * p[2]);
+        let spacing = vec3(0.5, 0.5, 0.5);
+        let flow_map = flow_map_3d(&flow, &Rk4, vec3(-1.0, -1.0, -1.0), spacing, (5, 5, 5), 0.0, 1.5, 0.01);
+        for &sigma in ftle_3d(&flow_map, spacing, 1.5).iter() {
+            assert!((sigma - 1.0).abs() < 1.0e-6, "{}", sigma);
+        }
+    }
+}
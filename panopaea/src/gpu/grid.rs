@@ -0,0 +1,810 @@
+//! Pressure projection and advection on the GPU
+//!
+//! Mirrors the CPU solvers for `Grid2d` and `Grid3d`: the velocity is a dual
+//! 1-form in the staggered layout of `Staggered2d`/`Staggered3d` faces, scalar
+//! fields are stored at the cell centers. 2d grids are handled as 3d grids of
+//! depth one without a z component.
+//!
+//! The pressure poisson equation is solved by Jacobi preconditioned conjugate
+//! gradient with the same stencil as the dual laplacian of the CPU projection:
+//! walls and inflow boundaries are closed, open boundaries have zero ghost
+//! pressure and periodic boundaries wrap around. The iteration runs on the
+//! device, only the maximum residual is read back every few iterations to
+//! check for convergence.
+//!
+//! Advection is semi-Lagrangian with a midpoint backtrace and trilinear
+//! sampling, clamped at the boundary and wrapped on periodic axes, in grid
+//! units like `advection`.
+
+use domain::{AxisBoundary, BoundaryCondition, Grid2d, Grid3d};
+use math::LinearView;
+use ndarray::{Array, Dimension};
+use std::mem;
+use super::{float, Buffer, Commands, Context, Kernel, Reduction};
+
+/// Conjugate gradient iterations between convergence checks.
+const CHECK_INTERVAL: usize = 8;
+
+/// Slots of the conjugate gradient scalars.
+const SLOT_SIGMA: u32 = 0;
+const SLOT_CURVATURE: u32 = 1;
+const SLOT_SIGMA_NEW: u32 = 2;
+const SLOT_RESIDUAL: u32 = 3;
+
+/// Parameter block and helpers shared by the grid kernels, axes are ordered `(x, y, z)`.
+const GRID: &str = "
+struct Params {
+    count: u32,
+    nx: u32, ny: u32, nz: u32,
+    offset_x: u32, offset_y: u32, offset_z: u32, axes: u32,
+    lower_x: u32, upper_x: u32, lower_y: u32, upper_y: u32,
+    lower_z: u32, upper_z: u32, _pad0: u32, _pad1: u32,
+    weight_x: f32, weight_y: f32, weight_z: f32, timestep: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+
+const DIRICHLET: u32 = 0u;
+const NEUMANN: u32 = 1u;
+const PERIODIC: u32 = 2u;
+const FREE_SLIP: u32 = 3u;
+const NO_SLIP: u32 = 4u;
+
+fn cells() -> vec3<u32> { return vec3<u32>(params.nx, params.ny, params.nz); }
+fn lower(a: u32) -> u32 { return vec3<u32>(params.lower_x, params.lower_y, params.lower_z)[a]; }
+fn upper(a: u32) -> u32 { return vec3<u32>(params.upper_x, params.upper_y, params.upper_z)[a]; }
+fn weight(a: u32) -> f32 { return vec3<f32>(params.weight_x, params.weight_y, params.weight_z)[a]; }
+fn offset(a: u32) -> u32 { return vec3<u32>(params.offset_x, params.offset_y, params.offset_z)[a]; }
+
+fn unit(a: u32) -> vec3<u32> {
+    var e = vec3<u32>(0u);
+    e[a] = 1u;
+    return e;
+}
+
+fn coords(index: u32, dim: vec3<u32>) -> vec3<u32> {
+    return vec3<u32>(index % dim.x, (index / dim.x) % dim.y, index / (dim.x * dim.y));
+}
+
+fn index_of(p: vec3<u32>, dim: vec3<u32>) -> u32 {
+    return (p.z * dim.y + p.y) * dim.x + p.x;
+}
+
+// samples of the velocity component along axis `a`
+fn samples(a: u32) -> vec3<u32> {
+    return cells() + unit(a);
+}
+
+// first velocity sample of component `a` and the sample points `(x, y, z)`
+fn velocity_component(index: u32) -> vec4<u32> {
+    for (var a = 0u; a < params.axes; a = a + 1u) {
+        let dim = samples(a);
+        let len = dim.x * dim.y * dim.z;
+        if (index >= offset(a) && index < offset(a) + len) {
+            return vec4<u32>(coords(index - offset(a), dim), a);
+        }
+    }
+    return vec4<u32>(0u, 0u, 0u, 3u);
+}
+";
+
+/// Divergence, gradient and conjugate gradient kernels of the pressure projection.
+const PROJECTION: &str = "
+@group(0) @binding(1) var<storage, read_write> velocity: array<f32>;
+@group(0) @binding(2) var<storage, read_write> pressure: array<f32>;
+@group(0) @binding(3) var<storage, read_write> rhs: array<f32>;
+@group(0) @binding(4) var<storage, read_write> residual: array<f32>;
+@group(0) @binding(5) var<storage, read_write> search: array<f32>;
+@group(0) @binding(6) var<storage, read_write> auxiliary: array<f32>;
+@group(0) @binding(7) var<storage, read_write> precond: array<f32>;
+@group(0) @binding(8) var<storage, read_write> scalars: array<f32>;
+
+// neighbor cell across the lower or upper face along `a`,
+// `-1` for closed faces and `-2` for zero ghost pressure
+fn neighbor(p: vec3<u32>, a: u32, upper_side: bool) -> i32 {
+    let n = cells()[a];
+    var q = p;
+    if (!upper_side) {
+        if (p[a] > 0u) {
+            q[a] = p[a] - 1u;
+        } else if (lower(a) == PERIODIC) {
+            q[a] = n - 1u;
+        } else if (lower(a) == DIRICHLET) {
+            return -2;
+        } else {
+            return -1;
+        }
+    } else {
+        if (p[a] + 1u < n) {
+            q[a] = p[a] + 1u;
+        } else if (upper(a) == PERIODIC) {
+            q[a] = 0u;
+        } else if (upper(a) == DIRICHLET) {
+            return -2;
+        } else {
+            return -1;
+        }
+    }
+    return i32(index_of(q, cells()));
+}
+
+fn inverse_diagonal(p: vec3<u32>) -> f32 {
+    var diagonal = 0.0;
+    for (var a = 0u; a < params.axes; a = a + 1u) {
+        if (neighbor(p, a, false) != -1) { diagonal = diagonal + weight(a); }
+        if (neighbor(p, a, true) != -1) { diagonal = diagonal + weight(a); }
+    }
+    if (diagonal > 0.0) { return 1.0 / diagonal; }
+    return 0.0;
+}
+
+// negative laplacian of the search direction
+fn laplacian(c: u32) -> f32 {
+    let p = coords(c, cells());
+    var sum = 0.0;
+    for (var a = 0u; a < params.axes; a = a + 1u) {
+        let lo = neighbor(p, a, false);
+        let hi = neighbor(p, a, true);
+        if (lo != -1) { sum = sum + weight(a) * search[c]; }
+        if (lo >= 0) { sum = sum - weight(a) * search[u32(lo)]; }
+        if (hi != -1) { sum = sum + weight(a) * search[c]; }
+        if (hi >= 0) { sum = sum - weight(a) * search[u32(hi)]; }
+    }
+    return sum;
+}
+
+// rhs = -div(u) / dt
+@compute @workgroup_size(256)
+fn divergence(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let c = thread_index(wid, num_groups, lid);
+    if (c >= params.count) { return; }
+    let p = coords(c, cells());
+    var div = 0.0;
+    for (var a = 0u; a < params.axes; a = a + 1u) {
+        let dim = samples(a);
+        let lo = velocity[offset(a) + index_of(p, dim)];
+        let hi = velocity[offset(a) + index_of(p + unit(a), dim)];
+        div = div + weight(a) * (hi - lo);
+    }
+    rhs[c] = -div / params.timestep;
+}
+
+// zero initial guess
+@compute @workgroup_size(256)
+fn init(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let c = thread_index(wid, num_groups, lid);
+    if (c >= params.count) { return; }
+    let z = rhs[c] * inverse_diagonal(coords(c, cells()));
+    pressure[c] = 0.0;
+    residual[c] = rhs[c];
+    precond[c] = z;
+    search[c] = z;
+}
+
+@compute @workgroup_size(256)
+fn apply(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let c = thread_index(wid, num_groups, lid);
+    if (c >= params.count) { return; }
+    auxiliary[c] = laplacian(c);
+}
+
+@compute @workgroup_size(256)
+fn update(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let c = thread_index(wid, num_groups, lid);
+    if (c >= params.count) { return; }
+    var alpha = 0.0;
+    if (scalars[1] != 0.0) { alpha = scalars[0] / scalars[1]; }
+    pressure[c] = pressure[c] + alpha * search[c];
+    residual[c] = residual[c] - alpha * auxiliary[c];
+    precond[c] = residual[c] * inverse_diagonal(coords(c, cells()));
+}
+
+@compute @workgroup_size(256)
+fn direction(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let c = thread_index(wid, num_groups, lid);
+    if (c >= params.count) { return; }
+    var beta = 0.0;
+    if (scalars[0] != 0.0) { beta = scalars[2] / scalars[0]; }
+    search[c] = precond[c] + beta * search[c];
+}
+
+@compute @workgroup_size(256)
+fn shift(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    if (thread_index(wid, num_groups, lid) == 0u) {
+        scalars[0] = scalars[2];
+    }
+}
+
+// u = u - dt * grad(p), closed boundary faces are untouched
+@compute @workgroup_size(256)
+fn gradient(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    if (i >= params.count) { return; }
+    let point = velocity_component(i);
+    let a = point.w;
+    if (a >= params.axes) { return; }
+    let n = cells()[a];
+    var q = point.xyz;
+    let face = q[a];
+
+    var hi = 0.0;
+    var lo = 0.0;
+    if (face > 0u && face < n) {
+        hi = pressure[index_of(q, cells())];
+        q[a] = face - 1u;
+        lo = pressure[index_of(q, cells())];
+    } else if ((face == 0u && lower(a) == PERIODIC) || (face == n && upper(a) == PERIODIC)) {
+        q[a] = 0u;
+        hi = pressure[index_of(q, cells())];
+        q[a] = n - 1u;
+        lo = pressure[index_of(q, cells())];
+    } else if (face == 0u && lower(a) == DIRICHLET) {
+        hi = pressure[index_of(q, cells())];
+    } else if (face == n && upper(a) == DIRICHLET) {
+        q[a] = n - 1u;
+        lo = pressure[index_of(q, cells())];
+    }
+    velocity[i] = velocity[i] - params.timestep * (hi - lo);
+}
+";
+
+/// Semi-Lagrangian advection of cell centered scalars and staggered velocities.
+const ADVECTION: &str = "
+@group(0) @binding(1) var<storage, read_write> velocity: array<f32>;
+@group(0) @binding(2) var<storage, read_write> src: array<f32>;
+@group(0) @binding(3) var<storage, read_write> dst: array<f32>;
+
+struct Stencil {
+    i0: u32,
+    i1: u32,
+    t: f32,
+}
+
+// interpolation stencil along one axis, see `advection::stencil_axis`
+fn stencil_axis(a: u32, num_samples: u32, p: f32) -> Stencil {
+    let n = cells()[a];
+    if (lower(a) == PERIODIC) {
+        let len = f32(n);
+        let q = p - len * floor(p / len);
+        let i0 = min(u32(floor(q)), n - 1u);
+        return Stencil(i0, (i0 + 1u) % n, q - f32(i0));
+    }
+    let q = clamp(p, 0.0, f32(num_samples - 1u));
+    let i0 = u32(floor(q));
+    return Stencil(i0, min(i0 + 1u, num_samples - 1u), q - f32(i0));
+}
+
+struct Trilinear {
+    base: u32,
+    dim: vec3<u32>,
+    x: Stencil,
+    y: Stencil,
+    z: Stencil,
+}
+
+// trilinear stencil of a field with `dim` samples located at `index + shift`
+fn trilinear(base: u32, dim: vec3<u32>, shift: vec3<f32>, pos: vec3<f32>) -> Trilinear {
+    let p = pos - shift;
+    return Trilinear(base, dim, stencil_axis(0u, dim.x, p.x), stencil_axis(1u, dim.y, p.y), stencil_axis(2u, dim.z, p.z));
+}
+
+fn corner_index(s: Trilinear, corner: u32) -> u32 {
+    let x = select(s.x.i0, s.x.i1, (corner & 1u) != 0u);
+    let y = select(s.y.i0, s.y.i1, (corner & 2u) != 0u);
+    let z = select(s.z.i0, s.z.i1, (corner & 4u) != 0u);
+    return s.base + index_of(vec3<u32>(x, y, z), s.dim);
+}
+
+fn corner_weight(s: Trilinear, corner: u32) -> f32 {
+    let wx = select(1.0 - s.x.t, s.x.t, (corner & 1u) != 0u);
+    let wy = select(1.0 - s.y.t, s.y.t, (corner & 2u) != 0u);
+    let wz = select(1.0 - s.z.t, s.z.t, (corner & 4u) != 0u);
+    return wx * wy * wz;
+}
+
+// sample points of velocity component `a` are offset by half a cell except along `a`
+fn component_shift(a: u32) -> vec3<f32> {
+    var shift = vec3<f32>(0.5);
+    shift[a] = 0.0;
+    return shift;
+}
+
+// tangential velocities vanish at no-slip walls
+fn no_slip(a: u32, pos: vec3<f32>) -> f32 {
+    var scale = 1.0;
+    for (var b = 0u; b < params.axes; b = b + 1u) {
+        if (b == a) { continue; }
+        if (lower(b) == NO_SLIP) { scale = min(scale, pos[b] / 0.5); }
+        if (upper(b) == NO_SLIP) { scale = min(scale, (f32(cells()[b]) - pos[b]) / 0.5); }
+    }
+    return max(scale, 0.0);
+}
+
+fn sample_velocity(pos: vec3<f32>) -> vec3<f32> {
+    var vel = vec3<f32>(0.0);
+    for (var a = 0u; a < params.axes; a = a + 1u) {
+        let s = trilinear(offset(a), samples(a), component_shift(a), pos);
+        var v = 0.0;
+        for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+            v = v + corner_weight(s, corner) * velocity[corner_index(s, corner)];
+        }
+        vel[a] = v * no_slip(a, pos);
+    }
+    return vel;
+}
+
+fn sample_src(s: Trilinear) -> f32 {
+    var v = 0.0;
+    for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+        v = v + corner_weight(s, corner) * src[corner_index(s, corner)];
+    }
+    return v;
+}
+
+// midpoint rule, see `advection::backtrace`
+fn backtrace(pos: vec3<f32>) -> vec3<f32> {
+    let dt = params.timestep;
+    let mid = pos - 0.5 * dt * sample_velocity(pos);
+    return pos - dt * sample_velocity(mid);
+}
+
+@compute @workgroup_size(256)
+fn advect_scalar(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let c = thread_index(wid, num_groups, lid);
+    if (c >= params.count) { return; }
+    let shift = vec3<f32>(0.5);
+    let pos = backtrace(vec3<f32>(coords(c, cells())) + shift);
+    dst[c] = sample_src(trilinear(0u, cells(), shift, pos));
+}
+
+@compute @workgroup_size(256)
+fn advect_velocity(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    if (i >= params.count) { return; }
+    let point = velocity_component(i);
+    let a = point.w;
+    if (a >= params.axes) { return; }
+    let shift = component_shift(a);
+    let pos = backtrace(vec3<f32>(point.xyz) + shift);
+    dst[i] = sample_src(trilinear(offset(a), samples(a), shift, pos));
+}
+";
+
+/// Grid dimensions, boundary conditions and stencil weights of the kernels, axes ordered `(x, y, z)`.
+#[derive(Copy, Clone, Debug)]
+struct Layout {
+    cells: [usize; 3],
+    offsets: [usize; 3],
+    axes: usize,
+    boundary: [AxisBoundary; 3],
+    weights: [f32; 3],
+}
+
+impl Layout {
+    fn num_cells(&self) -> usize {
+        self.cells[0] * self.cells[1] * self.cells[2]
+    }
+
+    fn num_velocity(&self) -> usize {
+        let [nx, ny, nz] = self.cells;
+        let len = [(nx + 1) * ny * nz, nx * (ny + 1) * nz, nx * ny * (nz + 1)];
+        len[..self.axes].iter().sum()
+    }
+
+    /// Parameter block following the thread count.
+    fn params(&self, timestep: f32) -> Vec<u32> {
+        let [nx, ny, nz] = self.cells;
+        let [ox, oy, oz] = self.offsets;
+        let [bx, by, bz] = self.boundary;
+        vec![
+            nx as u32, ny as u32, nz as u32,
+            ox as u32, oy as u32, oz as u32, self.axes as u32,
+            code(bx.lower), code(bx.upper), code(by.lower), code(by.upper),
+            code(bz.lower), code(bz.upper), 0, 0,
+            float(self.weights[0]), float(self.weights[1]), float(self.weights[2]), float(timestep),
+        ]
+    }
+}
+
+/// Boundary condition constants of the kernels.
+fn code(condition: BoundaryCondition) -> u32 {
+    match condition {
+        BoundaryCondition::Dirichlet => 0,
+        BoundaryCondition::Neumann => 1,
+        BoundaryCondition::Periodic => 2,
+        BoundaryCondition::FreeSlip => 3,
+        BoundaryCondition::NoSlip => 4,
+    }
+}
+
+/// Pressure projection and advection of grid fields on the device.
+///
+/// The velocity stays on the device between the steps and is transferred with
+/// `upload_velocity` and `download_velocity`, cell centered scalars are
+/// uploaded into separate buffers.
+pub struct GridSolver<'a> {
+    context: &'a Context,
+    layout: Layout,
+    /// Shape of the cell centered fields, `(y, x)` or `(z, y, x)`.
+    shape: Vec<usize>,
+
+    velocity: Buffer<f32>,
+    velocity_temp: Buffer<f32>,
+    pressure: Buffer<f32>,
+    scalar_temp: Buffer<f32>,
+
+    // conjugate gradient
+    rhs: Buffer<f32>,
+    residual: Buffer<f32>,
+    search: Buffer<f32>,
+    auxiliary: Buffer<f32>,
+    precond: Buffer<f32>,
+    scalars: Buffer<f32>,
+    reduction: Reduction,
+
+    divergence: Kernel,
+    init: Kernel,
+    apply: Kernel,
+    update: Kernel,
+    direction: Kernel,
+    shift: Kernel,
+    gradient: Kernel,
+    advect_scalar: Kernel,
+    advect_velocity: Kernel,
+
+    pub max_iterations: usize,
+    pub threshold: f32,
+}
+
+impl<'a> GridSolver<'a> {
+    /// Solver for a 2d grid, the velocity layout is the one of `Staggered2d`.
+    pub fn new_2d(context: &'a Context, grid: &Grid2d, max_iterations: usize, threshold: f32) -> Self {
+        let (h, w) = grid.dim();
+        let (dy, dx) = grid.spacing();
+        let [by, bx] = grid.boundary();
+        let layout = Layout {
+            cells: [w, h, 1],
+            // (vertical, horizontal)
+            offsets: [(h + 1) * w, 0, 0],
+            axes: 2,
+            boundary: [bx, by, AxisBoundary::default()],
+            weights: [(1.0 / (dx * dx)) as f32, (1.0 / (dy * dy)) as f32, 0.0],
+        };
+        GridSolver::new(context, layout, vec![h, w], max_iterations, threshold)
+    }
+
+    /// Solver for a 3d grid, the velocity layout is the one of `Staggered3d` faces.
+    pub fn new_3d(context: &'a Context, grid: &Grid3d, max_iterations: usize, threshold: f32) -> Self {
+        let (d, h, w) = grid.dim();
+        let [bz, by, bx] = grid.boundary();
+        let layout = Layout {
+            cells: [w, h, d],
+            // (z, y, x) normals
+            offsets: [(d + 1) * h * w + d * (h + 1) * w, (d + 1) * h * w, 0],
+            axes: 3,
+            boundary: [bx, by, bz],
+            weights: [1.0; 3],
+        };
+        GridSolver::new(context, layout, vec![d, h, w], max_iterations, threshold)
+    }
+
+    fn new(context: &'a Context, layout: Layout, shape: Vec<usize>, max_iterations: usize, threshold: f32) -> Self {
+        let (cells, edges) = (layout.num_cells(), layout.num_velocity());
+        let projection = context.shader("projection", &format!("{}{}", GRID, PROJECTION));
+        let advection = context.shader("advection", &format!("{}{}", GRID, ADVECTION));
+        GridSolver {
+            context,
+            layout,
+            shape,
+            velocity: Buffer::new(context, edges),
+            velocity_temp: Buffer::new(context, edges),
+            pressure: Buffer::new(context, cells),
+            scalar_temp: Buffer::new(context, cells),
+            rhs: Buffer::new(context, cells),
+            residual: Buffer::new(context, cells),
+            search: Buffer::new(context, cells),
+            auxiliary: Buffer::new(context, cells),
+            precond: Buffer::new(context, cells),
+            scalars: Buffer::new(context, 4),
+            reduction: Reduction::new(context, cells),
+            divergence: Kernel::new(context, &projection, "divergence", 8),
+            init: Kernel::new(context, &projection, "init", 8),
+            apply: Kernel::new(context, &projection, "apply", 8),
+            update: Kernel::new(context, &projection, "update", 8),
+            direction: Kernel::new(context, &projection, "direction", 8),
+            shift: Kernel::new(context, &projection, "shift", 8),
+            gradient: Kernel::new(context, &projection, "gradient", 8),
+            advect_scalar: Kernel::new(context, &advection, "advect_scalar", 3),
+            advect_velocity: Kernel::new(context, &advection, "advect_velocity", 3),
+            max_iterations,
+            threshold,
+        }
+    }
+
+    /// Velocity as dual 1-form on the device.
+    pub fn velocity(&self) -> &Buffer<f32> {
+        &self.velocity
+    }
+
+    /// Pressure of the last projection, `u = u - dt ∇p`.
+    pub fn pressure(&self) -> &Buffer<f32> {
+        &self.pressure
+    }
+
+    pub fn upload_velocity<V: LinearView<Elem = f32>>(&self, velocity: &V) {
+        let data = velocity.view_linear().to_vec();
+        self.velocity.upload(self.context, &data);
+    }
+
+    pub fn download_velocity<V: LinearView<Elem = f32>>(&self, velocity: &mut V) {
+        let data = self.velocity.download(self.context);
+        assert_eq!(data.len(), velocity.view_linear().len(), "velocity layout mismatch");
+        for (dst, src) in velocity.view_linear_mut().iter_mut().zip(data) {
+            *dst = src;
+        }
+    }
+
+    /// New device buffer holding a cell centered field.
+    pub fn upload_scalar<D: Dimension>(&self, field: &Array<f32, D>) -> Buffer<f32> {
+        assert_eq!(field.shape(), &self.shape[..], "field shape mismatch");
+        let data = field.iter().cloned().collect::<Vec<_>>();
+        Buffer::from_slice(self.context, &data)
+    }
+
+    pub fn download_scalar<D: Dimension>(&self, buffer: &Buffer<f32>, field: &mut Array<f32, D>) {
+        assert_eq!(field.shape(), &self.shape[..], "field shape mismatch");
+        for (dst, src) in field.iter_mut().zip(buffer.download(self.context)) {
+            *dst = src;
+        }
+    }
+
+    pub fn download_pressure<D: Dimension>(&self, pressure: &mut Array<f32, D>) {
+        self.download_scalar(&self.pressure, pressure)
+    }
+
+    /// Make the velocity on the device divergence-free.
+    ///
+    /// Returns the number of conjugate gradient iterations, which are run in
+    /// batches between the convergence checks.
+    pub fn project(&mut self, timestep: f32) -> usize {
+        let cells = self.layout.num_cells();
+        let params = self.layout.params(timestep);
+
+        let mut commands = Commands::new(self.context);
+        commands.dispatch(&self.divergence, cells, &params, &self.projection_buffers());
+        commands.dispatch(&self.init, cells, &params, &self.projection_buffers());
+        self.reduction.dot(&mut commands, &self.residual, &self.precond, &self.scalars, SLOT_SIGMA);
+        self.reduction.max_abs(&mut commands, &self.residual, &self.scalars, SLOT_RESIDUAL);
+        commands.submit();
+        if self.residual_error() < self.threshold {
+            return 0;
+        }
+
+        let mut iterations = 0;
+        while iterations < self.max_iterations {
+            let batch = CHECK_INTERVAL.min(self.max_iterations - iterations);
+            let mut commands = Commands::new(self.context);
+            for _ in 0..batch {
+                commands.dispatch(&self.apply, cells, &params, &self.projection_buffers());
+                self.reduction.dot(&mut commands, &self.search, &self.auxiliary, &self.scalars, SLOT_CURVATURE);
+                commands.dispatch(&self.update, cells, &params, &self.projection_buffers());
+                self.reduction.dot(&mut commands, &self.residual, &self.precond, &self.scalars, SLOT_SIGMA_NEW);
+                commands.dispatch(&self.direction, cells, &params, &self.projection_buffers());
+                commands.dispatch(&self.shift, 1, &params, &self.projection_buffers());
+            }
+            self.reduction.max_abs(&mut commands, &self.residual, &self.scalars, SLOT_RESIDUAL);
+            commands.submit();
+
+            iterations += batch;
+            if self.residual_error() < self.threshold {
+                break;
+            }
+        }
+
+        let mut commands = Commands::new(self.context);
+        commands.dispatch(&self.gradient, self.layout.num_velocity(), &params, &self.projection_buffers());
+        commands.submit();
+        iterations
+    }
+
+    fn projection_buffers(&self) -> [&::wgpu::Buffer; 8] {
+        [
+            self.velocity.raw(), self.pressure.raw(), self.rhs.raw(), self.residual.raw(),
+            self.search.raw(), self.auxiliary.raw(), self.precond.raw(), self.scalars.raw(),
+        ]
+    }
+
+    /// Maximum norm of the conjugate gradient residual.
+    fn residual_error(&self) -> f32 {
+        self.scalars.download(self.context)[SLOT_RESIDUAL as usize]
+    }
+
+    /// Advect the velocity on the device by itself.
+    pub fn advect_velocity(&mut self, timestep: f32) {
+        let mut commands = Commands::new(self.context);
+        let buffers = [self.velocity.raw(), self.velocity.raw(), self.velocity_temp.raw()];
+        commands.dispatch(&self.advect_velocity, self.layout.num_velocity(), &self.layout.params(timestep), &buffers);
+        commands.submit();
+        mem::swap(&mut self.velocity, &mut self.velocity_temp);
+    }
+
+    /// Advect a cell centered field by the velocity on the device.
+    pub fn advect_scalar(&mut self, field: &mut Buffer<f32>, timestep: f32) {
+        assert_eq!(field.len(), self.layout.num_cells(), "field shape mismatch");
+        let mut commands = Commands::new(self.context);
+        let buffers = [self.velocity.raw(), field.raw(), self.scalar_temp.raw()];
+        commands.dispatch(&self.advect_scalar, self.layout.num_cells(), &self.layout.params(timestep), &buffers);
+        commands.submit();
+        mem::swap(field, &mut self.scalar_temp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use advection::{self, Scheme};
+    use dec::manifold::Manifold2d;
+    use dec::projection::Projection;
+    use domain::{AxisBoundary, BoundaryCondition, Grid2d, Grid3d};
+    use math::LinearView;
+    use ndarray::{Array2, Array3};
+    use super::*;
+
+    fn velocity_2d(grid: &Grid2d) -> ::dec::grid::Staggered2d<f64> {
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(grid);
+        {
+            let (mut vy, mut vx) = velocity.split_mut();
+            for ((y, x), v) in vy.indexed_iter_mut() {
+                *v = ((y * 7 + x) as f64 * 0.37).sin();
+            }
+            for ((y, x), v) in vx.indexed_iter_mut() {
+                *v = ((y * 5 + x) as f64 * 0.53).cos();
+            }
+        }
+        ::dec::grid::enforce_boundary_2d(grid, &mut velocity);
+        velocity
+    }
+
+    fn to_f32<V: LinearView<Elem = f64>, W: LinearView<Elem = f32>>(src: &V, dst: &mut W) {
+        for (dst, &src) in dst.view_linear_mut().iter_mut().zip(src.view_linear().iter()) {
+            *dst = src as f32;
+        }
+    }
+
+    #[test]
+    fn projection_2d() {
+        let context = match Context::new() {
+            Some(context) => context,
+            None => return, // no GPU available
+        };
+
+        let boundaries = [
+            [AxisBoundary::default(), AxisBoundary::new(BoundaryCondition::NoSlip, BoundaryCondition::FreeSlip)],
+            [AxisBoundary::uniform(BoundaryCondition::Dirichlet), AxisBoundary::periodic()],
+        ];
+        for &boundary in &boundaries {
+            let grid = Grid2d::with_boundary((12, 10), boundary).with_spacing((0.5, 0.25));
+            let mut velocity = velocity_2d(&grid);
+            let mut pressure = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+
+            let mut gpu_velocity = <Grid2d as Manifold2d<f32>>::new_simplex_1(&grid);
+            to_f32(&velocity, &mut gpu_velocity);
+            let mut solver = GridSolver::new_2d(&context, &grid, 500, 1.0e-3);
+            solver.upload_velocity(&gpu_velocity);
+            assert!(solver.project(0.1) > 0);
+            solver.download_velocity(&mut gpu_velocity);
+
+            // the divergence-free part is unique, both solvers use the same stencil
+            Projection::new(&grid, 500, 1.0e-10).project(&mut velocity, &mut pressure, 0.1);
+            for (&cpu, &gpu) in velocity.view_linear().iter().zip(gpu_velocity.view_linear().iter()) {
+                assert!((cpu - gpu as f64).abs() < 1.0e-3, "{} {}", cpu, gpu);
+            }
+        }
+    }
+
+    #[test]
+    fn projection_3d() {
+        let context = match Context::new() {
+            Some(context) => context,
+            None => return,
+        };
+
+        let boundary = [AxisBoundary::periodic(), AxisBoundary::default(), AxisBoundary::uniform(BoundaryCondition::Dirichlet)];
+        let grid = Grid3d::with_boundary((6, 5, 4), boundary);
+        let mut velocity = <Grid3d as ::dec::manifold::Manifold3d<f32>>::new_simplex_2(&grid);
+        for (i, v) in velocity.view_linear_mut().iter_mut().enumerate() {
+            *v = (i as f32 * 0.41).sin();
+        }
+        ::dec::grid::enforce_boundary_3d(&grid, &mut velocity);
+
+        let mut solver = GridSolver::new_3d(&context, &grid, 500, 1.0e-4);
+        solver.upload_velocity(&velocity);
+        solver.project(0.1);
+        solver.download_velocity(&mut velocity);
+
+        // divergence of the faces, unit spacing
+        let (fz, fy, fx) = velocity.split();
+        let mut max_div = 0.0f32;
+        for z in 0..6 {
+            for y in 0..5 {
+                for x in 0..4 {
+                    let div = fz[(z + 1, y, x)] - fz[(z, y, x)]
+                            + fy[(z, y + 1, x)] - fy[(z, y, x)]
+                            + fx[(z, y, x + 1)] - fx[(z, y, x)];
+                    max_div = max_div.max(div.abs());
+                }
+            }
+        }
+        assert!(max_div < 1.0e-3, "{}", max_div);
+
+        // periodic boundary faces stay in sync
+        for y in 0..5 {
+            for x in 0..4 {
+                assert!((fz[(0, y, x)] - fz[(6, y, x)]).abs() < 1.0e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn advection_2d() {
+        let context = match Context::new() {
+            Some(context) => context,
+            None => return,
+        };
+
+        let boundary = [AxisBoundary::new(BoundaryCondition::NoSlip, BoundaryCondition::FreeSlip), AxisBoundary::periodic()];
+        let grid = Grid2d::with_boundary((10, 12), boundary);
+        let velocity = velocity_2d(&grid);
+        let src = Array2::from_shape_fn((10, 12), |(y, x)| ((y * 3 + x) as f64 * 0.3).sin());
+
+        let mut velocity_f32 = <Grid2d as Manifold2d<f32>>::new_simplex_1(&grid);
+        to_f32(&velocity, &mut velocity_f32);
+        let mut solver = GridSolver::new_2d(&context, &grid, 0, 0.0);
+        solver.upload_velocity(&velocity_f32);
+        let mut field = solver.upload_scalar(&src.mapv(|v| v as f32));
+        solver.advect_scalar(&mut field, 0.7);
+        solver.advect_velocity(0.7);
+
+        let mut scalar = Array2::zeros((10, 12));
+        let mut advected = <Grid2d as Manifold2d<f32>>::new_simplex_1(&grid);
+        solver.download_scalar(&field, &mut scalar);
+        solver.download_velocity(&mut advected);
+
+        let mut expected = Array2::zeros((10, 12));
+        advection::advect_scalar(&grid, Scheme::SemiLagrangian, &mut expected, &src, &velocity, 0.7);
+        for (&cpu, &gpu) in expected.iter().zip(scalar.iter()) {
+            assert!((cpu - gpu as f64).abs() < 1.0e-4, "{} {}", cpu, gpu);
+        }
+
+        let mut expected = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        advection::advect_velocity(&grid, Scheme::SemiLagrangian, &mut expected, &velocity, &velocity, 0.7);
+        for (&cpu, &gpu) in expected.view_linear().iter().zip(advected.view_linear().iter()) {
+            assert!((cpu - gpu as f64).abs() < 1.0e-4, "{} {}", cpu, gpu);
+        }
+    }
+
+    #[test]
+    fn advection_3d_uniform() {
+        let context = match Context::new() {
+            Some(context) => context,
+            None => return,
+        };
+
+        // uniform flow along x through a periodic box shifts the field by whole cells
+        let boundary = [AxisBoundary::default(), AxisBoundary::default(), AxisBoundary::periodic()];
+        let grid = Grid3d::with_boundary((3, 4, 8), boundary);
+        let mut velocity = <Grid3d as ::dec::manifold::Manifold3d<f32>>::new_simplex_2(&grid);
+        velocity.split_mut().2.fill(1.0);
+        let src = Array3::from_shape_fn((3, 4, 8), |(z, y, x)| (x + 10 * y + 100 * z) as f32);
+
+        let mut solver = GridSolver::new_3d(&context, &grid, 0, 0.0);
+        solver.upload_velocity(&velocity);
+        let mut field = solver.upload_scalar(&src);
+        solver.advect_scalar(&mut field, 2.0);
+
+        let mut dst = Array3::zeros((3, 4, 8));
+        solver.download_scalar(&field, &mut dst);
+        for ((z, y, x), &v) in dst.indexed_iter() {
+            assert!((v - src[(z, y, (x + 6) % 8)]).abs() < 1.0e-4, "{:?} {}", (z, y, x), v);
+        }
+    }
+}
@@ -0,0 +1,408 @@
+//! GPU compute backend
+//!
+//! Solvers running as compute shaders through wgpu, enabled by the `gpu`
+//! feature. Fields live in device buffers and are transferred explicitly with
+//! `upload` and `download`, keeping them on the device over several steps
+//! avoids the transfers in between. Device computations use single precision.
+//!
+//! Kernels are launched with a uniform parameter block at binding 0, the first
+//! parameter is the number of threads. Storage buffers follow at the next
+//! bindings in the order of the kernel's arguments.
+
+pub mod grid;
+pub mod sph;
+
+use bytemuck::{self, Pod};
+use pollster;
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::sync::mpsc;
+use wgpu;
+use wgpu::util::DeviceExt;
+
+/// Threads per workgroup of all kernels.
+const WORKGROUP_SIZE: usize = 256;
+
+/// Maximum number of workgroups along one dispatch dimension.
+const MAX_GROUPS: usize = 65535;
+
+/// Thread index of the kernels, workgroups are laid out in 2d for large dispatches.
+const PRELUDE: &str = "
+fn thread_index(wid: vec3<u32>, num_groups: vec3<u32>, lid: vec3<u32>) -> u32 {
+    return (wid.y * num_groups.x + wid.x) * 256u + lid.x;
+}
+";
+
+/// Partial reductions per workgroup and the final reduction into a scalar slot.
+const REDUCE: &str = "
+struct Params {
+    count: u32,
+    op: u32,
+    slot: u32,
+    _pad: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> a: array<f32>;
+@group(0) @binding(2) var<storage, read_write> b: array<f32>;
+@group(0) @binding(3) var<storage, read_write> partials: array<f32>;
+@group(0) @binding(4) var<storage, read_write> scalars: array<f32>;
+
+var<workgroup> shared_values: array<f32, 256>;
+
+fn combine(x: f32, y: f32) -> f32 {
+    if (params.op == 0u) { return x + y; }
+    return max(x, y);
+}
+
+fn reduce_workgroup(lid: u32) {
+    for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+        workgroupBarrier();
+        if (lid < stride) {
+            shared_values[lid] = combine(shared_values[lid], shared_values[lid + stride]);
+        }
+    }
+    workgroupBarrier();
+}
+
+// `a . b` (op 0) or `max |a|` (op 1) per workgroup
+@compute @workgroup_size(256)
+fn partial(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    var value = 0.0;
+    if (i < params.count) {
+        if (params.op == 0u) { value = a[i] * b[i]; } else { value = abs(a[i]); }
+    }
+    shared_values[lid.x] = value;
+    reduce_workgroup(lid.x);
+    if (lid.x == 0u) {
+        partials[wid.y * num_groups.x + wid.x] = shared_values[0];
+    }
+}
+
+// single workgroup combining `count` partial results into `scalars[slot]`
+@compute @workgroup_size(256)
+fn total(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    var value = 0.0;
+    for (var i = lid.x; i < params.count; i = i + 256u) {
+        value = combine(value, partials[i]);
+    }
+    shared_values[lid.x] = value;
+    reduce_workgroup(lid.x);
+    if (lid.x == 0u) {
+        scalars[params.slot] = shared_values[0];
+    }
+}
+";
+
+/// Device and command queue shared by the GPU solvers.
+pub struct Context {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl Context {
+    /// Context on the first high performance adapter, `None` without a compute capable GPU.
+    pub fn new() -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))?;
+        let descriptor = wgpu::DeviceDescriptor {
+            label: Some("panopaea"),
+            required_features: wgpu::Features::empty(),
+            // large grids and particle sets need the full buffer sizes of the adapter
+            required_limits: adapter.limits(),
+        };
+        let (device, queue) = pollster::block_on(adapter.request_device(&descriptor, None)).ok()?;
+        Some(Context { device, queue })
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// Block until all submitted work is finished.
+    pub fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    /// Compile a compute shader, prefixed with the common helper functions.
+    fn shader(&self, label: &str, source: &str) -> wgpu::ShaderModule {
+        self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(format!("{}{}", PRELUDE, source))),
+        })
+    }
+}
+
+/// Typed storage buffer on the device.
+///
+/// Buffers always hold at least one element, empty buffers can't be bound.
+pub struct Buffer<T> {
+    buffer: wgpu::Buffer,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> Buffer<T> {
+    /// Zero initialized buffer.
+    pub fn new(context: &Context, len: usize) -> Self {
+        let buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: Self::size(len),
+            usage: Self::usage(),
+            mapped_at_creation: false,
+        });
+        Buffer { buffer, len, _marker: PhantomData }
+    }
+
+    pub fn from_slice(context: &Context, data: &[T]) -> Self {
+        if data.is_empty() {
+            return Buffer::new(context, 0);
+        }
+        let buffer = context.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(data),
+            usage: Self::usage(),
+        });
+        Buffer { buffer, len: data.len(), _marker: PhantomData }
+    }
+
+    fn size(len: usize) -> wgpu::BufferAddress {
+        (len.max(1) * ::std::mem::size_of::<T>()) as wgpu::BufferAddress
+    }
+
+    fn usage() -> wgpu::BufferUsages {
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn raw(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Copy host data into the buffer, the length has to match.
+    pub fn upload(&self, context: &Context, data: &[T]) {
+        assert_eq!(data.len(), self.len, "buffer length mismatch");
+        if !data.is_empty() {
+            context.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(data));
+        }
+    }
+
+    /// Copy the buffer contents to the host, waits for all submitted work.
+    pub fn download(&self, context: &Context) -> Vec<T> {
+        if self.len == 0 {
+            return Vec::new();
+        }
+        let size = (self.len * ::std::mem::size_of::<T>()) as wgpu::BufferAddress;
+        let staging = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("download"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &staging, 0, size);
+        context.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+        context.wait();
+        receiver.recv().unwrap().expect("failed to map the download buffer");
+
+        let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging.unmap();
+        data
+    }
+
+    /// Copy the contents of another buffer of the same length.
+    pub fn copy_from(&self, context: &Context, src: &Buffer<T>) {
+        assert_eq!(src.len, self.len, "buffer length mismatch");
+        let mut encoder = context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&src.buffer, 0, &self.buffer, 0, Self::size(self.len));
+        context.queue.submit(Some(encoder.finish()));
+    }
+}
+
+/// Compute shader entry point, see the module documentation for the bindings.
+struct Kernel {
+    pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    num_buffers: usize,
+}
+
+impl Kernel {
+    fn new(context: &Context, module: &wgpu::ShaderModule, entry: &str, num_buffers: usize) -> Self {
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        for binding in 1..num_buffers + 1 {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: binding as u32,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+        }
+
+        let device = &context.device;
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(entry),
+            entries: &entries,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(entry),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry),
+            layout: Some(&pipeline_layout),
+            module,
+            entry_point: entry,
+        });
+        Kernel { pipeline, layout, num_buffers }
+    }
+}
+
+/// Kernel launches recorded into one command buffer.
+struct Commands<'a> {
+    context: &'a Context,
+    encoder: wgpu::CommandEncoder,
+}
+
+impl<'a> Commands<'a> {
+    fn new(context: &'a Context) -> Self {
+        let encoder = context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        Commands { context, encoder }
+    }
+
+    /// Launch `threads` threads of the kernel, `params` follow the thread count in the parameter block.
+    fn dispatch(&mut self, kernel: &Kernel, threads: usize, params: &[u32], buffers: &[&wgpu::Buffer]) {
+        if threads > 0 {
+            self.launch(kernel, threads, params, buffers, groups(threads));
+        }
+    }
+
+    /// Launch a single workgroup, `count` is passed as thread count.
+    fn dispatch_single(&mut self, kernel: &Kernel, count: usize, params: &[u32], buffers: &[&wgpu::Buffer]) {
+        self.launch(kernel, count, params, buffers, (1, 1));
+    }
+
+    fn launch(&mut self, kernel: &Kernel, count: usize, params: &[u32], buffers: &[&wgpu::Buffer], (x, y): (u32, u32)) {
+        assert_eq!(buffers.len(), kernel.num_buffers, "kernel argument mismatch");
+
+        // uniform blocks are padded to 16 bytes
+        let mut block = Vec::with_capacity(params.len() + 4);
+        block.push(count as u32);
+        block.extend_from_slice(params);
+        while block.len() % 4 != 0 {
+            block.push(0);
+        }
+        let device = &self.context.device;
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&block),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let mut entries = vec![wgpu::BindGroupEntry { binding: 0, resource: uniform.as_entire_binding() }];
+        for (i, buffer) in buffers.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry { binding: i as u32 + 1, resource: buffer.as_entire_binding() });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &kernel.layout,
+            entries: &entries,
+        });
+
+        let mut pass = self.encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
+        pass.set_pipeline(&kernel.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(x, y, 1);
+    }
+
+    fn submit(self) {
+        self.context.queue.submit(Some(self.encoder.finish()));
+    }
+}
+
+/// Workgroups `(x, y)` covering the threads.
+fn groups(threads: usize) -> (u32, u32) {
+    let groups = (threads + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+    let x = groups.min(MAX_GROUPS);
+    (x as u32, ((groups + x - 1) / x) as u32)
+}
+
+/// Bit pattern of a float parameter.
+fn float(x: f32) -> u32 {
+    x.to_bits()
+}
+
+/// Dot products and maximum norms of device vectors.
+struct Reduction {
+    partial: Kernel,
+    total: Kernel,
+    partials: Buffer<f32>,
+}
+
+/// Reduction operators of the `REDUCE` kernels.
+const OP_DOT: u32 = 0;
+const OP_MAX_ABS: u32 = 1;
+
+impl Reduction {
+    /// Reductions of vectors with up to `len` elements.
+    fn new(context: &Context, len: usize) -> Self {
+        let module = context.shader("reduce", REDUCE);
+        let (x, y) = groups(len);
+        Reduction {
+            partial: Kernel::new(context, &module, "partial", 4),
+            total: Kernel::new(context, &module, "total", 4),
+            partials: Buffer::new(context, (x * y) as usize),
+        }
+    }
+
+    /// `scalars[slot] = a . b`
+    fn dot(&self, commands: &mut Commands, a: &Buffer<f32>, b: &Buffer<f32>, scalars: &Buffer<f32>, slot: u32) {
+        self.reduce(commands, OP_DOT, a, b, scalars, slot);
+    }
+
+    /// `scalars[slot] = max |a|`
+    fn max_abs(&self, commands: &mut Commands, a: &Buffer<f32>, scalars: &Buffer<f32>, slot: u32) {
+        self.reduce(commands, OP_MAX_ABS, a, a, scalars, slot);
+    }
+
+    fn reduce(&self, commands: &mut Commands, op: u32, a: &Buffer<f32>, b: &Buffer<f32>, scalars: &Buffer<f32>, slot: u32) {
+        let (x, y) = groups(a.len());
+        let buffers = [a.raw(), b.raw(), self.partials.raw(), scalars.raw()];
+        commands.dispatch(&self.partial, a.len(), &[op, slot], &buffers);
+        // one workgroup, the thread count is the number of partial results
+        commands.dispatch_single(&self.total, (x * y) as usize, &[op, slot], &buffers);
+    }
+}
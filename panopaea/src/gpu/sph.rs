@@ -0,0 +1,613 @@
+//! Weakly compressible SPH on the GPU
+//!
+//! The neighborhood grid is built by a counting sort [Gre10]: each particle
+//! atomically increments the counter of its cell and keeps the returned rank,
+//! an exclusive scan over the counters gives the first particle of each cell
+//! and the particles are scattered into cell order. Particles outside of the
+//! grid are sorted behind the last cell and don't interact. Boundary particles
+//! are sorted the same way into a second grid.
+//!
+//! Densities, Tait pressures, pressure and viscosity forces and the symplectic
+//! Euler step are computed per particle over the 3x3 neighboring cells, with
+//! the same kernel and force terms as `sph::wcsph::Wcsph`.
+//!
+//! References:
+//!     [Gre10] Simon Green, 2010,
+//!             Particle simulation using CUDA,
+//!             NVIDIA CUDA SDK whitepaper
+//!     [BT07] Markus Becker and Matthias Teschner, 2007,
+//!            Weakly compressible SPH for free surface flows,
+//!            In Proceedings of the 2007 ACM SIGGRAPH/Eurographics symposium on Computer animation (SCA '07),
+//!            Eurographics Association, Aire-la-Ville, Switzerland, Switzerland, 209-217
+//!     [AIA12] Nadir Akinci, Markus Ihmsen, Gizem Akinci, Barbara Solenthaler, and Matthias Teschner, 2012,
+//!             Versatile rigid-fluid coupling for incompressible SPH,
+//!             ACM Trans. Graph. 31, 4, Article 62
+
+use error::{Error, Result};
+use math::VectorN;
+use math::vector_n::vec2;
+use particle::Particles;
+use sph::kernel::SmoothingKernel;
+use sph::property::*;
+use sph::solver::{Diagnostics, Pipeline, Solver};
+use sph::wcsph;
+use std::f32;
+use typenum::U2;
+use super::{float, Buffer, Commands, Context, Kernel};
+
+/// Parameter block and grid helpers shared by the particle kernels.
+const SPH_COMMON: &str = "
+struct Params {
+    count: u32,
+    nx: u32,
+    ny: u32,
+    _pad0: u32,
+    cell_size: f32,
+    support: f32,
+    w_const: f32,
+    rest_density: f32,
+    stiffness: f32,
+    exponent: f32,
+    viscous: f32,
+    eta: f32,
+    gravity_x: f32,
+    gravity_y: f32,
+    timestep: f32,
+    _pad1: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+
+fn num_cells() -> u32 {
+    return params.nx * params.ny;
+}
+
+fn cell_of(pos: vec2<f32>) -> vec2<i32> {
+    return vec2<i32>(floor(pos / params.cell_size));
+}
+
+fn inside(c: vec2<i32>) -> bool {
+    return c.x >= 0 && c.y >= 0 && c.x < i32(params.nx) && c.y < i32(params.ny);
+}
+
+// row-major cell key, `num_cells` outside of the grid
+fn cell_key(pos: vec2<f32>) -> u32 {
+    let c = cell_of(pos);
+    if (!inside(c)) { return num_cells(); }
+    return u32(c.y) * params.nx + u32(c.x);
+}
+";
+
+/// Counting sort of points into the cells of the grid.
+const SORT: &str = "
+@group(0) @binding(1) var<storage, read_write> points_in: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> aux_in: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read_write> keys: array<vec2<u32>>;
+@group(0) @binding(4) var<storage, read_write> counts: array<atomic<u32>>;
+@group(0) @binding(5) var<storage, read_write> starts: array<u32>;
+@group(0) @binding(6) var<storage, read_write> points_out: array<vec4<f32>>;
+@group(0) @binding(7) var<storage, read_write> aux_out: array<vec4<f32>>;
+@group(0) @binding(8) var<storage, read_write> order: array<u32>;
+
+var<workgroup> chunk: array<u32, 256>;
+var<workgroup> carry: u32;
+
+@compute @workgroup_size(256)
+fn clear(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    if (i >= params.count) { return; }
+    atomicStore(&counts[i], 0u);
+}
+
+// cell key and rank of each point within its cell
+@compute @workgroup_size(256)
+fn count(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    if (i >= params.count) { return; }
+    let key = cell_key(points_in[i].xy);
+    keys[i] = vec2<u32>(key, atomicAdd(&counts[key], 1u));
+}
+
+// exclusive scan of the `count` counters by a single workgroup, in chunks of 256
+@compute @workgroup_size(256)
+fn scan(@builtin(local_invocation_id) lid: vec3<u32>) {
+    let n = params.count;
+    if (lid.x == 0u) { carry = 0u; }
+    workgroupBarrier();
+
+    let num_chunks = (n + 255u) / 256u;
+    for (var k = 0u; k < num_chunks; k = k + 1u) {
+        let i = k * 256u + lid.x;
+        var value = 0u;
+        if (i < n) { value = atomicLoad(&counts[i]); }
+        chunk[lid.x] = value;
+        workgroupBarrier();
+
+        // inclusive scan of the chunk
+        for (var stride = 1u; stride < 256u; stride = stride * 2u) {
+            var sum = chunk[lid.x];
+            if (lid.x >= stride) { sum = sum + chunk[lid.x - stride]; }
+            workgroupBarrier();
+            chunk[lid.x] = sum;
+            workgroupBarrier();
+        }
+
+        let base = carry;
+        if (i < n) { starts[i] = base + chunk[lid.x] - value; }
+        workgroupBarrier();
+        if (lid.x == 255u) { carry = base + chunk[255]; }
+        workgroupBarrier();
+    }
+
+    if (lid.x == 0u) { starts[n] = carry; }
+}
+
+@compute @workgroup_size(256)
+fn scatter(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    if (i >= params.count) { return; }
+    let key = keys[i];
+    let j = starts[key.x] + key.y;
+    points_out[j] = points_in[i];
+    aux_out[j] = aux_in[i];
+    order[j] = i;
+}
+";
+
+/// Density, pressure, forces and time integration of the sorted particles.
+const FLUID: &str = "
+// positions and velocities
+@group(0) @binding(1) var<storage, read_write> state: array<vec4<f32>>;
+// masses and densities of the last step
+@group(0) @binding(2) var<storage, read_write> aux: array<vec4<f32>>;
+// densities and pressures
+@group(0) @binding(3) var<storage, read_write> fluid: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read_write> starts: array<u32>;
+// positions and volumes
+@group(0) @binding(5) var<storage, read_write> boundary: array<vec4<f32>>;
+@group(0) @binding(6) var<storage, read_write> boundary_starts: array<u32>;
+@group(0) @binding(7) var<storage, read_write> accel: array<vec2<f32>>;
+
+// cubic spline, see `CubicSpline::new_2d`
+fn kernel_w(r: f32) -> f32 {
+    let q = r / params.support;
+    if (q >= 1.0) { return 0.0; }
+    if (q <= 0.5) { return params.w_const * (6.0 * (q * q * q - q * q) + 1.0); }
+    let s = 1.0 - q;
+    return params.w_const * 2.0 * s * s * s;
+}
+
+// `∇W = r grad_w(|r|)`
+fn kernel_grad(r: f32) -> f32 {
+    let h = params.support;
+    let q = r / h;
+    if (q >= 1.0) { return 0.0; }
+    if (q <= 0.5) { return params.w_const * (18.0 * q - 12.0) / (h * h); }
+    let s = 1.0 - q;
+    return -params.w_const * 6.0 * s * s / q / (h * h);
+}
+
+// Tait equation clamped to non-negative pressures, [BT07] Eq. 7
+fn tait(density: f32) -> f32 {
+    return max(params.stiffness * (pow(density / params.rest_density, params.exponent) - 1.0), 0.0);
+}
+
+// density summation including the particle itself and the boundary, [AIA12] Eq. 6
+@compute @workgroup_size(256)
+fn density(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    if (i >= params.count) { return; }
+    let pos = state[i].xy;
+    let cell = cell_of(pos);
+    if (!inside(cell)) {
+        // particles outside of the grid keep their density
+        fluid[i] = vec2<f32>(aux[i].y, tait(aux[i].y));
+        return;
+    }
+
+    var d = 0.0;
+    for (var dy = -1; dy <= 1; dy = dy + 1) {
+        for (var dx = -1; dx <= 1; dx = dx + 1) {
+            let c = cell + vec2<i32>(dx, dy);
+            if (!inside(c)) { continue; }
+            let key = u32(c.y) * params.nx + u32(c.x);
+            for (var j = starts[key]; j < starts[key + 1u]; j = j + 1u) {
+                d = d + aux[j].x * kernel_w(distance(pos, state[j].xy));
+            }
+            for (var b = boundary_starts[key]; b < boundary_starts[key + 1u]; b = b + 1u) {
+                d = d + params.rest_density * boundary[b].z * kernel_w(distance(pos, boundary[b].xy));
+            }
+        }
+    }
+    fluid[i] = vec2<f32>(d, tait(d));
+}
+
+// gravity, symmetric pressure forces, laminar viscosity and boundary pressure forces,
+// [BT07] Eq. 6, [AIA12] Eq. 10
+@compute @workgroup_size(256)
+fn forces(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    if (i >= params.count) { return; }
+    var a = vec2<f32>(params.gravity_x, params.gravity_y);
+    let pos = state[i].xy;
+    let vel = state[i].zw;
+    let cell = cell_of(pos);
+    if (!inside(cell)) {
+        accel[i] = a;
+        return;
+    }
+    let pressure_i = fluid[i].y / (fluid[i].x * fluid[i].x);
+
+    for (var dy = -1; dy <= 1; dy = dy + 1) {
+        for (var dx = -1; dx <= 1; dx = dx + 1) {
+            let c = cell + vec2<i32>(dx, dy);
+            if (!inside(c)) { continue; }
+            let key = u32(c.y) * params.nx + u32(c.x);
+            for (var j = starts[key]; j < starts[key + 1u]; j = j + 1u) {
+                if (j == i) { continue; }
+                let r = pos - state[j].xy;
+                let dist = length(r);
+                let grad = r * kernel_grad(dist);
+                let mass = aux[j].x;
+                let density_j = fluid[j].x;
+
+                let pressure_j = fluid[j].y / (density_j * density_j);
+                a = a - grad * (mass * (pressure_i + pressure_j));
+
+                let v = vel - state[j].zw;
+                a = a + grad * (params.viscous * mass / density_j * dot(v, r) / (dist * dist + params.eta));
+            }
+            for (var b = boundary_starts[key]; b < boundary_starts[key + 1u]; b = b + 1u) {
+                let grad = (pos - boundary[b].xy) * kernel_grad(distance(pos, boundary[b].xy));
+                a = a - grad * (params.rest_density * boundary[b].z * pressure_i);
+            }
+        }
+    }
+    accel[i] = a;
+}
+
+// symplectic Euler
+@compute @workgroup_size(256)
+fn integrate(@builtin(workgroup_id) wid: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+    let i = thread_index(wid, num_groups, lid);
+    if (i >= params.count) { return; }
+    let vel = state[i].zw + accel[i] * params.timestep;
+    state[i] = vec4<f32>(state[i].xy + vel * params.timestep, vel);
+}
+";
+
+/// Buffers of a point set sorted into the grid.
+struct Sorted {
+    points: Buffer<[f32; 4]>,
+    aux: Buffer<[f32; 4]>,
+    starts: Buffer<u32>,
+    order: Buffer<u32>,
+}
+
+/// Weakly compressible SPH solver for 2d domains running on the GPU.
+///
+/// Particles are uploaded, sorted and advanced on the device each step and
+/// downloaded again in the cell order of their initial positions, all properties and attributes of the
+/// particles are reordered accordingly. Only the laminar viscosity, gravity
+/// and the static boundary of the `Pipeline` are supported, the neighbor grid
+/// of the pipeline isn't rebuilt. Other stages are rejected when the solver is
+/// built and ignored if added later. Computations use single precision.
+///
+/// Ref: [Gre10], [BT07], [AIA12]
+pub struct Wcsph<'a> {
+    context: &'a Context,
+    /// Grid, kernel, boundary and non-pressure forces.
+    pipeline: Pipeline<f32>,
+
+    pub rest_density: f32,
+    /// Stiffness `B` of the Tait equation.
+    pub stiffness: f32,
+    /// Exponent `γ` of the Tait equation.
+    pub exponent: f32,
+
+    clear: Kernel,
+    count: Kernel,
+    scan: Kernel,
+    scatter: Kernel,
+    density: Kernel,
+    forces: Kernel,
+    integrate: Kernel,
+}
+
+impl<'a> Wcsph<'a> {
+    /// Solver for the domain `[0, num_cells * smoothing_radius]`, see `sph::wcsph::Wcsph::new`.
+    pub fn new(context: &'a Context, num_cells: VectorN<usize, U2>, smoothing_radius: f32, rest_density: f32) -> Self {
+        Wcsph::with_pipeline(context, Pipeline::new(num_cells, smoothing_radius), rest_density)
+            .expect("default pipeline is supported on the GPU")
+    }
+
+    /// Solver running a configured pipeline, fails for stages which aren't supported on the GPU.
+    pub fn with_pipeline(context: &'a Context, pipeline: Pipeline<f32>, rest_density: f32) -> Result<Self> {
+        check_pipeline(&pipeline)?;
+        let sort = context.shader("sort", &format!("{}{}", SPH_COMMON, SORT));
+        let fluid = context.shader("fluid", &format!("{}{}", SPH_COMMON, FLUID));
+        let mut solver = Wcsph {
+            context,
+            pipeline,
+            rest_density,
+            stiffness: 0.0,
+            exponent: 7.0,
+            clear: Kernel::new(context, &sort, "clear", 8),
+            count: Kernel::new(context, &sort, "count", 8),
+            scan: Kernel::new(context, &sort, "scan", 8),
+            scatter: Kernel::new(context, &sort, "scatter", 8),
+            density: Kernel::new(context, &fluid, "density", 7),
+            forces: Kernel::new(context, &fluid, "forces", 7),
+            integrate: Kernel::new(context, &fluid, "integrate", 7),
+        };
+        solver.set_speed_of_sound(10.0);
+        Ok(solver)
+    }
+
+    /// Set the stiffness to `ρ0 c^2 / γ` for the numerical speed of sound `c`.
+    pub fn set_speed_of_sound(&mut self, speed: f32) {
+        self.stiffness = self.rest_density * speed * speed / self.exponent;
+    }
+
+    /// Numerical speed of sound `c = sqrt(B γ / ρ0)`, required for the timestep restriction.
+    pub fn speed_of_sound(&self) -> f32 {
+        (self.stiffness * self.exponent / self.rest_density).sqrt()
+    }
+
+    /// Parameter block following the thread count.
+    fn params(&self, timestep: f32) -> Vec<u32> {
+        let pipeline = &self.pipeline;
+        let num_cells = pipeline.grid().num_cells();
+        let h = pipeline.kernel().support();
+        vec![
+            num_cells[0] as u32, num_cells[1] as u32, 0,
+            float(pipeline.grid().cell_size()),
+            float(h),
+            float(40.0 / (7.0 * f32::consts::PI) / (h * h)),
+            float(self.rest_density),
+            float(self.stiffness),
+            float(self.exponent),
+            // `2 (d + 2) ν` and regularization of the distance
+            float(8.0 * pipeline.viscosity),
+            float(0.01 * h * h),
+            float(pipeline.gravity[0]),
+            float(pipeline.gravity[1]),
+            float(timestep),
+            0,
+        ]
+    }
+
+    /// Counting sort of points with auxiliary data into the grid.
+    fn sort(&self, commands: &mut Commands, params: &[u32], points: &[[f32; 4]], aux: &[[f32; 4]]) -> Sorted {
+        let context = self.context;
+        let num_cells = params[0] as usize * params[1] as usize;
+        let (points_in, aux_in) = (Buffer::from_slice(context, points), Buffer::from_slice(context, aux));
+        let keys = Buffer::<[u32; 2]>::new(context, points.len());
+        let counts = Buffer::<u32>::new(context, num_cells + 1);
+        let sorted = Sorted {
+            points: Buffer::new(context, points.len()),
+            aux: Buffer::new(context, points.len()),
+            starts: Buffer::new(context, num_cells + 2),
+            order: Buffer::new(context, points.len()),
+        };
+
+        let buffers = [
+            points_in.raw(), aux_in.raw(), keys.raw(), counts.raw(),
+            sorted.starts.raw(), sorted.points.raw(), sorted.aux.raw(), sorted.order.raw(),
+        ];
+        commands.dispatch(&self.clear, num_cells + 1, params, &buffers);
+        commands.dispatch(&self.count, points.len(), params, &buffers);
+        commands.dispatch_single(&self.scan, num_cells + 1, params, &buffers);
+        commands.dispatch(&self.scatter, points.len(), params, &buffers);
+        sorted
+    }
+
+    /// Advance the simulation by one timestep.
+    ///
+    /// Requires the properties registered by `init`.
+    pub fn step(&mut self, particles: &mut Particles, timestep: f32) {
+        let num_particles = particles.num_particles();
+        if num_particles == 0 {
+            return;
+        }
+
+        let points = {
+            let (positions, velocities) = (
+                particles.read_property::<Position<f32, U2>>(),
+                particles.read_property::<Velocity<f32, U2>>(),
+            );
+            positions.iter().zip(velocities).map(|(x, v)| [x[0], x[1], v[0], v[1]]).collect::<Vec<_>>()
+        };
+        let aux = {
+            let (masses, densities) = (particles.read_property::<Mass<f32>>(), particles.read_property::<Density<f32>>());
+            masses.iter().zip(densities).map(|(&m, &d)| [m, d, 0.0, 0.0]).collect::<Vec<_>>()
+        };
+        let boundary = {
+            let boundary = self.pipeline.boundary();
+            boundary.positions().iter().zip(boundary.volumes()).map(|(x, &v)| [x[0], x[1], v, 0.0]).collect::<Vec<_>>()
+        };
+
+        let context = self.context;
+        let params = self.params(timestep);
+        let mut commands = Commands::new(context);
+        let fluid = self.sort(&mut commands, &params, &points, &aux);
+        let solid = self.sort(&mut commands, &params, &boundary, &boundary);
+        let densities = Buffer::<[f32; 2]>::new(context, num_particles);
+        let accels = Buffer::<[f32; 2]>::new(context, num_particles);
+
+        let buffers = [
+            fluid.points.raw(), fluid.aux.raw(), densities.raw(), fluid.starts.raw(),
+            solid.points.raw(), solid.starts.raw(), accels.raw(),
+        ];
+        commands.dispatch(&self.density, num_particles, &params, &buffers);
+        commands.dispatch(&self.forces, num_particles, &params, &buffers);
+        commands.dispatch(&self.integrate, num_particles, &params, &buffers);
+        commands.submit();
+
+        let order = fluid.order.download(context).into_iter().map(|i| i as usize).collect::<Vec<_>>();
+        particles.reorder(&order);
+
+        for (pos, state) in particles.write_property::<Position<f32, U2>>().iter_mut().zip(fluid.points.download(context)) {
+            *pos = vec2(state[0], state[1]);
+        }
+        for (vel, state) in particles.write_property::<Velocity<f32, U2>>().iter_mut().zip(fluid.points.download(context)) {
+            *vel = vec2(state[2], state[3]);
+        }
+        let densities = densities.download(context);
+        for (density, values) in particles.write_property::<Density<f32>>().iter_mut().zip(&densities) {
+            *density = values[0];
+        }
+        for (pressure, values) in particles.write_property::<Pressure<f32>>().iter_mut().zip(&densities) {
+            *pressure = values[1];
+        }
+        for (accel, a) in particles.write_property::<Acceleration<f32, U2>>().iter_mut().zip(accels.download(context)) {
+            *accel = vec2(a[0], a[1]);
+        }
+    }
+}
+
+/// Fails for stages of the pipeline which aren't supported on the GPU.
+fn check_pipeline(pipeline: &Pipeline<f32>) -> Result<()> {
+    if !pipeline.bodies.is_empty() {
+        return Err(Error::Unsupported("rigid bodies on the GPU"));
+    }
+    if !pipeline.forces.is_empty() {
+        return Err(Error::Unsupported("force terms on the GPU"));
+    }
+    if !pipeline.colliders.is_empty() {
+        return Err(Error::Unsupported("colliders on the GPU"));
+    }
+    if pipeline.surface_tension.is_some() || pipeline.artificial_viscosity.is_some() || pipeline.xsph.is_some() {
+        return Err(Error::Unsupported("surface tension, artificial viscosity and XSPH on the GPU"));
+    }
+    Ok(())
+}
+
+impl<'a> Solver<f32> for Wcsph<'a> {
+    fn init(&self, particles: &mut Particles) {
+        wcsph::init::<f32, U2>(particles);
+    }
+
+    fn step(&mut self, particles: &mut Particles, timestep: f32) {
+        Wcsph::step(self, particles, timestep);
+    }
+
+    fn pipeline(&self) -> &Pipeline<f32> {
+        &self.pipeline
+    }
+
+    fn pipeline_mut(&mut self) -> &mut Pipeline<f32> {
+        &mut self.pipeline
+    }
+
+    fn diagnostics(&self, particles: &Particles) -> Diagnostics<f32> {
+        Diagnostics::measure(particles, self.rest_density)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sph::boundary::BoundarySampler;
+    use sph::wcsph::Wcsph as CpuWcsph;
+    use super::*;
+
+    fn particles(solver: &Solver<f32>) -> Particles {
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let mut particles = Particles::new();
+        solver.init(&mut particles);
+        particles.add_attribute::<usize>("id");
+
+        let mut positions = Vec::new();
+        for y in 0..12 {
+            for x in 0..10 {
+                positions.push(vec2(1.0 + x as f32 * spacing, 0.55 + y as f32 * spacing));
+            }
+        }
+        let masses = vec![rest_density * spacing * spacing; positions.len()];
+        let ids = (0..positions.len()).collect::<Vec<_>>();
+        particles.add_particles(positions.len())
+                 .with::<Position<f32, U2>>(&positions)
+                 .with::<Mass<f32>>(&masses)
+                 .with_attribute("id", &ids);
+        particles
+    }
+
+    #[test]
+    fn wcsph_matches_cpu() {
+        let context = match Context::new() {
+            Some(context) => context,
+            None => return, // no GPU available
+        };
+
+        let boundary = BoundarySampler::new(0.05).rectangle(vec2(0.5, 0.5), vec2(3.5, 3.5));
+        let mut cpu = CpuWcsph::new(vec2(20, 20), 0.2, 1000.0);
+        cpu.pipeline.set_boundary(boundary.clone());
+        let mut pipeline = Pipeline::new(vec2(20, 20), 0.2);
+        pipeline.set_boundary(boundary);
+        let mut gpu = Wcsph::with_pipeline(&context, pipeline, 1000.0).unwrap();
+
+        let (mut cpu_particles, mut gpu_particles) = (particles(&cpu), particles(&gpu));
+        for _ in 0..5 {
+            cpu.step(&mut cpu_particles, 1.0e-3);
+            gpu.step(&mut gpu_particles, 1.0e-3);
+        }
+
+        // particles are sorted by cell on both sides, but not in the same order within the cells
+        let mut index = vec![0; gpu_particles.num_particles()];
+        for (i, &id) in cpu_particles.read_attribute::<usize>("id").iter().enumerate() {
+            index[id] = i;
+        }
+        let (cpu_positions, cpu_densities) = (
+            cpu_particles.read_property::<Position<f32, U2>>(),
+            cpu_particles.read_property::<Density<f32>>(),
+        );
+        let (gpu_positions, gpu_densities) = (
+            gpu_particles.read_property::<Position<f32, U2>>(),
+            gpu_particles.read_property::<Density<f32>>(),
+        );
+        for (j, &id) in gpu_particles.read_attribute::<usize>("id").iter().enumerate() {
+            let i = index[id];
+            assert!((cpu_positions[i][0] - gpu_positions[j][0]).abs() < 1.0e-4 && (cpu_positions[i][1] - gpu_positions[j][1]).abs() < 1.0e-4, "{:?} {:?}", cpu_positions[i], gpu_positions[j]);
+            assert!((cpu_densities[i] - gpu_densities[j]).abs() < 1.0e-3 * 1000.0, "{} {}", cpu_densities[i], gpu_densities[j]);
+        }
+
+        // particles are stored in the cell order of the start of the step, a step
+        // without motion keeps it
+        gpu.step(&mut gpu_particles, 0.0);
+        let gpu_positions = gpu_particles.read_property::<Position<f32, U2>>();
+        let keys = gpu_positions.iter().map(|pos| gpu.pipeline().grid().get_coords(pos).map(|c| c[1] * 20 + c[0]).unwrap_or(400)).collect::<Vec<_>>();
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn wcsph_empty() {
+        let context = match Context::new() {
+            Some(context) => context,
+            None => return,
+        };
+
+        let mut solver = Wcsph::new(&context, vec2(4, 4), 0.2, 1000.0);
+        let mut particles = Particles::new();
+        solver.init(&mut particles);
+        solver.step(&mut particles, 1.0e-3);
+        assert_eq!(particles.num_particles(), 0);
+    }
+
+    #[test]
+    fn wcsph_unsupported() {
+        use collision::Collider;
+        use implicit::Implicit;
+
+        let context = match Context::new() {
+            Some(context) => context,
+            None => return,
+        };
+
+        let mut pipeline = Pipeline::new(vec2(4, 4), 0.2);
+        pipeline.colliders.push(Collider::new(Implicit::Sphere { center: vec2(0.4, 0.4), radius: 0.1 }));
+        match Wcsph::with_pipeline(&context, pipeline, 1000.0) {
+            Err(Error::Unsupported(_)) => (),
+            _ => panic!("colliders are rejected"),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Simulation checkpoints
+//!
+//! Grids, staggered fields and solver states implement `Serialize` and
+//! `Deserialize`. Particle properties are identified by type and can't be
+//! serialized generically, `ParticleState` stores the selected properties and
+//! attributes by name instead.
+
+use bincode;
+use particle::{Particles, Property};
+use rmp_serde;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Encoding of checkpoint files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Compact binary encoding, not portable across crate versions.
+    Bincode,
+    /// MessagePack, readable from other languages.
+    MessagePack,
+}
+
+fn invalid_data<E: Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+pub fn write_checkpoint<W, S>(writer: &mut W, format: Format, state: &S) -> io::Result<()>
+    where W: Write,
+          S: Serialize,
+{
+    match format {
+        Format::Bincode => bincode::serialize_into(writer, state, bincode::Infinite).map_err(invalid_data),
+        Format::MessagePack => rmp_serde::encode::write(writer, state).map_err(invalid_data),
+    }
+}
+
+pub fn read_checkpoint<R, S>(reader: &mut R, format: Format) -> io::Result<S>
+    where R: Read,
+          S: DeserializeOwned,
+{
+    match format {
+        Format::Bincode => bincode::deserialize_from(reader, bincode::Infinite).map_err(invalid_data),
+        Format::MessagePack => rmp_serde::decode::from_read(reader).map_err(invalid_data),
+    }
+}
+
+pub fn save_checkpoint<P, S>(path: P, format: Format, state: &S) -> io::Result<()>
+    where P: AsRef<Path>,
+          S: Serialize,
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    write_checkpoint(&mut file, format, state)?;
+    file.flush()
+}
+
+pub fn load_checkpoint<P, S>(path: P, format: Format) -> io::Result<S>
+    where P: AsRef<Path>,
+          S: DeserializeOwned,
+{
+    let mut file = BufReader::new(File::open(path)?);
+    read_checkpoint(&mut file, format)
+}
+
+/// Serializable copy of particle properties and attributes.
+///
+/// Each entry is encoded with bincode independently of the checkpoint format.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ParticleState {
+    num_particles: usize,
+    properties: BTreeMap<String, Vec<u8>>,
+    attributes: BTreeMap<String, Vec<u8>>,
+}
+
+impl ParticleState {
+    pub fn new(particles: &Particles) -> Self {
+        ParticleState {
+            num_particles: particles.num_particles(),
+            properties: BTreeMap::new(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.num_particles
+    }
+
+    /// Store a property under the given name.
+    pub fn store_property<T>(&mut self, name: &str, particles: &Particles)
+        where T: Property,
+              T::Subtype: Serialize,
+    {
+        let values = particles.read_property::<T>();
+        assert_eq!(values.len(), self.num_particles);
+        let data = bincode::serialize(values, bincode::Infinite).unwrap();
+        self.properties.insert(name.to_owned(), data);
+    }
+
+    pub fn store_attribute<T>(&mut self, name: &str, particles: &Particles)
+        where T: Clone + Default + Send + Sync + Serialize + 'static
+    {
+        let values = particles.read_attribute::<T>(name);
+        assert_eq!(values.len(), self.num_particles);
+        let data = bincode::serialize(values, bincode::Infinite).unwrap();
+        self.attributes.insert(name.to_owned(), data);
+    }
+
+    /// Replace all particles by the stored number of particles with default values.
+    ///
+    /// Registered properties and attributes are kept, load the stored values afterwards.
+    pub fn reset(&self, particles: &mut Particles) {
+        let keep = vec![false; particles.num_particles()];
+        particles.compact(&keep);
+        particles.add_particles(self.num_particles);
+    }
+
+    /// Load a stored property, the particle count has to match.
+    ///
+    /// Returns `false` if no property with this name has been stored.
+    pub fn load_property<T>(&self, name: &str, particles: &mut Particles) -> bool
+        where T: Property,
+              T::Subtype: DeserializeOwned,
+    {
+        assert_eq!(particles.num_particles(), self.num_particles);
+        let data = if let Some(data) = self.properties.get(name) { data } else { return false };
+        let values: Vec<T::Subtype> = bincode::deserialize(data).expect("Corrupted property");
+        particles.write_property::<T>().clone_from_slice(&values);
+        true
+    }
+
+    pub fn load_attribute<T>(&self, name: &str, particles: &mut Particles) -> bool
+        where T: Clone + Default + Send + Sync + DeserializeOwned + 'static
+    {
+        assert_eq!(particles.num_particles(), self.num_particles);
+        let data = if let Some(data) = self.attributes.get(name) { data } else { return false };
+        let values: Vec<T> = bincode::deserialize(data).expect("Corrupted attribute");
+        particles.add_attribute::<T>(name);
+        particles.write_attribute::<T>(name).clone_from_slice(&values);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::Grid2d;
+    use math::vector_n::vec2;
+    use solvers::smoke::{Smoke, SmokeState};
+    use sph::property::{Position, Velocity};
+    use sph::wcsph;
+    use typenum::U2;
+    use super::*;
+
+    #[test]
+    fn checkpoint_roundtrip() {
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+        particles.add_attribute::<u32>("id");
+        particles.add_particles(2)
+                 .with::<Position<f64, U2>>(&[vec2(1.0, 2.0), vec2(3.0, 4.0)])
+                 .with::<Velocity<f64, U2>>(&[vec2(0.5, 0.0), vec2(0.0, 0.5)])
+                 .with_attribute::<u32>("id", &[7, 9]);
+
+        let mut state = ParticleState::new(&particles);
+        state.store_property::<Position<f64, U2>>("position", &particles);
+        state.store_property::<Velocity<f64, U2>>("velocity", &particles);
+        state.store_attribute::<u32>("id", &particles);
+
+        let grid = Grid2d::new((4, 3)).with_spacing((0.5, 0.25));
+        let mut smoke = Smoke::<f64>::new(&grid);
+        smoke.density[(1, 2)] = 1.0;
+        smoke.velocity.split_mut().0[(2, 1)] = 3.0;
+
+        for &format in &[Format::Bincode, Format::MessagePack] {
+            let mut data = Vec::new();
+            write_checkpoint(&mut data, format, &(&state, grid, smoke.state())).unwrap();
+            let (loaded, loaded_grid, smoke_state): (ParticleState, Grid2d, SmokeState<f64>) =
+                read_checkpoint(&mut &data[..], format).unwrap();
+
+            let mut restored = Particles::new();
+            wcsph::init::<f64, U2>(&mut restored);
+            loaded.reset(&mut restored);
+            assert!(loaded.load_property::<Position<f64, U2>>("position", &mut restored));
+            assert!(loaded.load_property::<Velocity<f64, U2>>("velocity", &mut restored));
+            assert!(loaded.load_attribute::<u32>("id", &mut restored));
+            assert!(!loaded.load_property::<Position<f64, U2>>("missing", &mut restored));
+
+            assert_eq!(restored.num_particles(), 2);
+            let positions = restored.read_property::<Position<f64, U2>>();
+            assert_eq!(Into::<[f64; 2]>::into(positions[1]), [3.0, 4.0]);
+            let velocities = restored.read_property::<Velocity<f64, U2>>();
+            assert_eq!(Into::<[f64; 2]>::into(velocities[0]), [0.5, 0.0]);
+            assert_eq!(restored.read_attribute::<u32>("id"), &[7, 9]);
+
+            assert_eq!(loaded_grid.spacing(), (0.5, 0.25));
+            let mut resumed = Smoke::<f64>::new(&loaded_grid);
+            resumed.restore(smoke_state).unwrap();
+            assert_eq!(resumed.density, smoke.density);
+            assert_eq!(resumed.velocity.split().0[(2, 1)], 3.0);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Image export of 2d fields
+//!
+//! Scalar fields are mapped to colors for PNG export or stored as floating
+//! point values in uncompressed OpenEXR files. Velocity fields are shown by
+//! direction (hue) and speed (value).
+//!
+//! Fields are indexed by `(y, x)` with `y` pointing upwards, rows are flipped
+//! when writing images.
+//!
+//! References:
+//!     [Mor09] Kenneth Moreland, 2009,
+//!             Diverging color maps for scientific visualization,
+//!             In Advances in Visual Computing (ISVC 2009), Springer, 92-103
+//!     [EXR] Industrial Light & Magic, The OpenEXR File Layout,
+//!           https://www.openexr.com/documentation/openexrfilelayout.pdf
+
+use dec::grid::Staggered2d;
+use domain::Grid2d;
+use image;
+use math::Real;
+use ndarray::{Array2, ArrayView2};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Mapping of normalized values to colors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Grayscale,
+    /// Diverging blue-white-red map, suited for signed fields like vorticity or level sets.
+    ///
+    /// Ref: [Mor09]
+    Coolwarm,
+    /// Perceptually uniform sequential map.
+    Viridis,
+}
+
+impl Colormap {
+    /// Color of a value in `[0, 1]`, values outside are clamped.
+    pub fn color(&self, t: f64) -> [u8; 3] {
+        const GRAYSCALE: &[[f64; 3]] = &[[0.0, 0.0, 0.0], [255.0, 255.0, 255.0]];
+        const COOLWARM: &[[f64; 3]] = &[[59.0, 76.0, 192.0], [221.0, 221.0, 221.0], [180.0, 4.0, 38.0]];
+        const VIRIDIS: &[[f64; 3]] = &[
+            [68.0, 1.0, 84.0], [59.0, 82.0, 139.0], [33.0, 145.0, 140.0], [94.0, 201.0, 98.0], [253.0, 231.0, 37.0],
+        ];
+
+        let points = match *self {
+            Colormap::Grayscale => GRAYSCALE,
+            Colormap::Coolwarm => COOLWARM,
+            Colormap::Viridis => VIRIDIS,
+        };
+
+        // piecewise linear interpolation between the control points
+        let t = if t.is_nan() { 0.0 } else { t.max(0.0).min(1.0) };
+        let t = t * (points.len() - 1) as f64;
+        let i = (t.floor() as usize).min(points.len() - 2);
+        let s = t - i as f64;
+        let mut color = [0; 3];
+        for c in 0..3 {
+            color[c] = ((1.0 - s) * points[i][c] + s * points[i+1][c]).round() as u8;
+        }
+        color
+    }
+}
+
+/// Fully saturated color with hue in degrees and value in `[0, 1]`.
+fn hsv(hue: f64, value: f64) -> [u8; 3] {
+    let hue = hue % 360.0;
+    let hue = if hue < 0.0 { hue + 360.0 } else { hue };
+    let h = (hue / 60.0).min(5.999_999);
+    let x = value * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as usize {
+        0 => (value, x, 0.0),
+        1 => (x, value, 0.0),
+        2 => (0.0, value, x),
+        3 => (0.0, x, value),
+        4 => (x, 0.0, value),
+        _ => (value, 0.0, x),
+    };
+    [(r * 255.0).round() as u8, (g * 255.0).round() as u8, (b * 255.0).round() as u8]
+}
+
+/// Map a scalar field to colors.
+///
+/// Values are normalized by the given `(min, max)` range or the range of the field.
+pub fn colorize<T: Real>(field: ArrayView2<T>, range: Option<(T, T)>, colormap: Colormap) -> Array2<[u8; 3]> {
+    let (min, max) = range.unwrap_or_else(|| {
+        field.fold((T::infinity(), T::neg_infinity()), |(min, max), &v| (min.min(v), max.max(v)))
+    });
+    let (min, max) = (min.to_f64().unwrap(), max.to_f64().unwrap());
+    let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+    field.map(|&v| colormap.color((v.to_f64().unwrap() - min) * scale))
+}
+
+/// Map the cell centered velocity of a dual 1-form to colors.
+///
+/// The hue is the angle of the flow direction with red for `+x`, the value
+/// encodes the speed relative to `max_speed` or the maximum speed of the field.
+pub fn velocity_colors<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>, max_speed: Option<T>) -> Array2<[u8; 3]> {
+    let (dy, dx) = grid.spacing();
+    let (vertical, horizontal) = velocity.split();
+    let velocities = Array2::from_shape_fn(grid.dim(), |(y, x)| {
+        let vy = (vertical[(y, x)] + vertical[(y+1, x)]).to_f64().unwrap() / (2.0 * dy);
+        let vx = (horizontal[(y, x)] + horizontal[(y, x+1)]).to_f64().unwrap() / (2.0 * dx);
+        (vx, vy)
+    });
+
+    let max_speed = max_speed.map(|s| s.to_f64().unwrap()).unwrap_or_else(|| {
+        velocities.fold(0.0, |max: f64, &(vx, vy)| max.max(vx.hypot(vy)))
+    });
+    let scale = if max_speed > 0.0 { 1.0 / max_speed } else { 0.0 };
+
+    velocities.map(|&(vx, vy)| {
+        hsv(vy.atan2(vx).to_degrees(), (vx.hypot(vy) * scale).min(1.0))
+    })
+}
+
+/// Save colors as RGB PNG.
+pub fn save_png<P: AsRef<Path>>(path: P, colors: &Array2<[u8; 3]>) -> io::Result<()> {
+    let (height, width) = colors.dim();
+    let mut buffer = image::ImageBuffer::new(width as u32, height as u32);
+    for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+        *pixel = image::Rgb(colors[(height - 1 - y as usize, x as usize)]);
+    }
+
+    let mut file = File::create(path)?;
+    image::ImageRgb8(buffer).save(&mut file, image::PNG)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+}
+
+/// Write a scalar field as single channel (`Y`) 32 bit float OpenEXR image.
+///
+/// Ref: [EXR]
+pub fn write_exr<W: Write, T: Real>(w: &mut W, field: ArrayView2<T>) -> io::Result<()> {
+    fn attribute(header: &mut Vec<u8>, name: &str, ty: &str, value: &[u8]) {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(ty.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&i32_bytes(value.len() as i32));
+        header.extend_from_slice(value);
+    }
+
+    let (height, width) = field.dim();
+    let window = [0, 0, width as i32 - 1, height as i32 - 1].iter()
+        .flat_map(|&v| i32_bytes(v).to_vec())
+        .collect::<Vec<_>>();
+
+    // channel `Y`: float pixels, linear flag, reserved, x and y sampling
+    let mut channels = vec![b'Y', 0];
+    channels.extend_from_slice(&i32_bytes(2));
+    channels.extend_from_slice(&[0, 0, 0, 0]);
+    channels.extend_from_slice(&i32_bytes(1));
+    channels.extend_from_slice(&i32_bytes(1));
+    channels.push(0);
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(&mut header, "compression", "compression", &[0]);
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &f32_bytes(1.0));
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &f32_bytes(1.0));
+    header.push(0);
+    w.write_all(&header)?;
+
+    // offset table, one uncompressed scanline per chunk
+    let line_size = 8 + 4 * width;
+    let table_end = header.len() + 8 * height;
+    for line in 0..height {
+        w.write_all(&u64_bytes((table_end + line * line_size) as u64))?;
+    }
+
+    for line in 0..height {
+        w.write_all(&i32_bytes(line as i32))?;
+        w.write_all(&i32_bytes(4 * width as i32))?;
+        for &v in field.row(height - 1 - line) {
+            w.write_all(&f32_bytes(v.to_f32().unwrap()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Save a scalar field as OpenEXR image.
+pub fn save_exr<P: AsRef<Path>, T: Real>(path: P, field: ArrayView2<T>) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_exr(&mut file, field)?;
+    file.flush()
+}
+
+fn i32_bytes(v: i32) -> [u8; 4] {
+    u32_bytes(v as u32)
+}
+
+fn u32_bytes(v: u32) -> [u8; 4] {
+    [v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]
+}
+
+fn u64_bytes(v: u64) -> [u8; 8] {
+    let (lo, hi) = (u32_bytes(v as u32), u32_bytes((v >> 32) as u32));
+    [lo[0], lo[1], lo[2], lo[3], hi[0], hi[1], hi[2], hi[3]]
+}
+
+fn f32_bytes(v: f32) -> [u8; 4] {
+    u32_bytes(v.to_bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::Manifold2d;
+    use super::*;
+
+    #[test]
+    fn field_images() {
+        assert_eq!(Colormap::Grayscale.color(-1.0), [0, 0, 0]);
+        assert_eq!(Colormap::Grayscale.color(0.5), [128, 128, 128]);
+        assert_eq!(Colormap::Coolwarm.color(0.5), [221, 221, 221]);
+        assert_eq!(Colormap::Viridis.color(1.0), [253, 231, 37]);
+
+        let field = Array2::from_shape_fn((2, 3), |(y, x)| (y * 3 + x) as f64);
+        let colors = colorize(field.view(), None, Colormap::Grayscale);
+        assert_eq!(colors[(0, 0)], [0, 0, 0]);
+        assert_eq!(colors[(1, 2)], [255, 255, 255]);
+        let colors = colorize(field.view(), Some((0.0, 10.0)), Colormap::Grayscale);
+        assert_eq!(colors[(1, 2)], [128, 128, 128]);
+
+        // uniform flow along `+x` and `+y`
+        let grid = Grid2d::new((2, 2));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().1.fill(1.0);
+        assert_eq!(velocity_colors(&grid, &velocity, None)[(0, 0)], [255, 0, 0]);
+        assert_eq!(velocity_colors(&grid, &velocity, Some(2.0))[(1, 1)], [128, 0, 0]);
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().0.fill(1.0);
+        assert_eq!(velocity_colors(&grid, &velocity, None)[(0, 0)], [128, 255, 0]);
+
+        let mut exr = Vec::new();
+        write_exr(&mut exr, field.view()).unwrap();
+        assert_eq!(&exr[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        // header, offset table and two scanlines of three floats
+        let data = 2 * 8 + 2 * (8 + 12);
+        let header = exr.len() - data;
+        assert_eq!(&exr[header - 1..header], &[0]);
+        // the first scanline is the top row
+        let first = header + 2 * 8;
+        assert_eq!(&exr[first..first + 4], &i32_bytes(0));
+        assert_eq!(&exr[first + 8..first + 12], &f32_bytes(3.0));
+        assert_eq!(&exr[exr.len() - 4..], &f32_bytes(2.0));
+    }
+}
@@ -0,0 +1,461 @@
+//! Triangle mesh import and export
+//!
+//! Supports Wavefront OBJ and Stanford PLY files. Polygons are triangulated
+//! as fans on import, additional attributes like texture coordinates are ignored.
+//! PLY files are read in ASCII and binary encoding, written as ASCII.
+//!
+//! References:
+//!     [PLY] Greg Turk, 1994, The PLY Polygon File Format,
+//!           http://paulbourke.net/dataformats/ply/
+
+use cgmath::{InnerSpace, Vector3};
+use domain::TriangleMesh;
+use math::Real;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Indexed triangle list as stored in mesh files.
+#[derive(Clone, Debug)]
+pub struct MeshData<T> {
+    pub positions: Vec<Vector3<T>>,
+    pub faces: Vec<[usize; 3]>,
+}
+
+/// Defects found by `MeshData::validate`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Defect {
+    /// Face with repeated vertices or zero area.
+    DegenerateFace(usize),
+    /// Edge used by a single face, the mesh has a hole or boundary.
+    OpenEdge(usize, usize),
+    /// Edge shared by more than two faces.
+    NonManifoldEdge(usize, usize),
+    /// Neighboring faces traverse their shared edge in the same direction.
+    InconsistentOrientation(usize, usize),
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<T: Real> MeshData<T> {
+    pub fn from_mesh(mesh: &TriangleMesh<T>) -> Self {
+        MeshData {
+            positions: mesh.positions().to_vec(),
+            faces: mesh.faces().to_vec(),
+        }
+    }
+
+    /// Build the mesh with its dual measures for the DEC operators.
+    pub fn into_mesh(self) -> TriangleMesh<T> {
+        TriangleMesh::new(self.positions, self.faces)
+    }
+
+    /// Unit normal of each face following the winding order, zero for degenerate faces.
+    pub fn face_normals(&self) -> Vec<Vector3<T>> {
+        self.faces.iter().map(|f| {
+            let p = [self.positions[f[0]], self.positions[f[1]], self.positions[f[2]]];
+            let normal = (p[1] - p[0]).cross(p[2] - p[0]);
+            let length = normal.magnitude();
+            if length > T::zero() { normal / length } else { normal }
+        }).collect()
+    }
+
+    /// Area weighted vertex normals.
+    pub fn vertex_normals(&self) -> Vec<Vector3<T>> {
+        let mut normals = vec![Vector3::new(T::zero(), T::zero(), T::zero()); self.positions.len()];
+        for f in &self.faces {
+            let p = [self.positions[f[0]], self.positions[f[1]], self.positions[f[2]]];
+            // the cross product is proportional to the face area
+            let normal = (p[1] - p[0]).cross(p[2] - p[0]);
+            for &v in f {
+                normals[v] = normals[v] + normal;
+            }
+        }
+        for normal in &mut normals {
+            let length = normal.magnitude();
+            if length > T::zero() {
+                *normal = *normal / length;
+            }
+        }
+        normals
+    }
+
+    /// Check for degenerate faces and non-manifold or inconsistently oriented edges.
+    ///
+    /// Closed, consistently oriented meshes without defects are suited as obstacles.
+    pub fn validate(&self) -> Vec<Defect> {
+        let mut defects = Vec::new();
+
+        // faces using each directed edge
+        let mut edges = HashMap::new();
+        for (i, f) in self.faces.iter().enumerate() {
+            let p = [self.positions[f[0]], self.positions[f[1]], self.positions[f[2]]];
+            if f[0] == f[1] || f[1] == f[2] || f[2] == f[0] || (p[1] - p[0]).cross(p[2] - p[0]).magnitude2() == T::zero() {
+                defects.push(Defect::DegenerateFace(i));
+                continue;
+            }
+            for k in 0..3 {
+                *edges.entry((f[k], f[(k+1) % 3])).or_insert(0) += 1;
+            }
+        }
+
+        let mut undirected = edges.keys()
+            .map(|&(a, b)| (a.min(b), a.max(b)))
+            .collect::<Vec<_>>();
+        undirected.sort();
+        undirected.dedup();
+        for (a, b) in undirected {
+            let forward = edges.get(&(a, b)).cloned().unwrap_or(0);
+            let backward = edges.get(&(b, a)).cloned().unwrap_or(0);
+            match (forward, backward) {
+                (1, 1) => (),
+                (1, 0) | (0, 1) => defects.push(Defect::OpenEdge(a, b)),
+                (2, 0) | (0, 2) => defects.push(Defect::InconsistentOrientation(a, b)),
+                _ => defects.push(Defect::NonManifoldEdge(a, b)),
+            }
+        }
+        defects
+    }
+
+    fn check_indices(&self) -> io::Result<()> {
+        let num_vertices = self.positions.len();
+        match self.faces.iter().flat_map(|f| f.iter()).find(|&&v| v >= num_vertices) {
+            Some(v) => Err(invalid_data(format!("vertex index {} out of range ({} vertices)", v, num_vertices))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Split a polygon into a triangle fan.
+fn triangulate(polygon: &[usize], faces: &mut Vec<[usize; 3]>) {
+    for i in 1..polygon.len().saturating_sub(1) {
+        faces.push([polygon[0], polygon[i], polygon[i+1]]);
+    }
+}
+
+fn parse<V: ::std::str::FromStr>(token: Option<&str>) -> io::Result<V> {
+    token.and_then(|t| t.parse().ok())
+         .ok_or_else(|| invalid_data(format!("unexpected token {:?}", token)))
+}
+
+/// Read an OBJ file, only vertices and faces are considered.
+pub fn read_obj<R: BufRead, T: Real>(reader: R) -> io::Result<MeshData<T>> {
+    let mut mesh = MeshData { positions: Vec::new(), faces: Vec::new() };
+    let mut polygon = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let x: f64 = parse(tokens.next())?;
+                let y: f64 = parse(tokens.next())?;
+                let z: f64 = parse(tokens.next())?;
+                mesh.positions.push(Vector3::new(T::new(x), T::new(y), T::new(z)));
+            }
+            Some("f") => {
+                polygon.clear();
+                for token in tokens {
+                    // `v`, `v/vt`, `v//vn` or `v/vt/vn`, 1-based or negative (relative) indices
+                    let index: isize = parse(token.split('/').next())?;
+                    let index = if index < 0 { mesh.positions.len() as isize + index } else { index - 1 };
+                    if index < 0 {
+                        return Err(invalid_data(format!("invalid vertex index in `{}`", line)));
+                    }
+                    polygon.push(index as usize);
+                }
+                triangulate(&polygon, &mut mesh.faces);
+            }
+            _ => (),
+        }
+    }
+    mesh.check_indices()?;
+    Ok(mesh)
+}
+
+fn to_f64<T: Real>(v: Vector3<T>) -> [f64; 3] {
+    [v.x.to_f64().unwrap(), v.y.to_f64().unwrap(), v.z.to_f64().unwrap()]
+}
+
+/// Write an OBJ file with vertex normals.
+pub fn write_obj<W: Write, T: Real>(w: &mut W, mesh: &MeshData<T>) -> io::Result<()> {
+    for p in &mesh.positions {
+        let p = to_f64(*p);
+        writeln!(w, "v {} {} {}", p[0], p[1], p[2])?;
+    }
+    for n in mesh.vertex_normals() {
+        let n = to_f64(n);
+        writeln!(w, "vn {} {} {}", n[0], n[1], n[2])?;
+    }
+    for f in &mesh.faces {
+        writeln!(w, "f {0}//{0} {1}//{1} {2}//{2}", f[0] + 1, f[1] + 1, f[2] + 1)?;
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Debug)]
+enum PlyProperty {
+    Scalar { name: String, ty: String },
+    List { name: String, count: String, ty: String },
+}
+
+#[derive(Clone, Debug)]
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+fn ply_type_size(ty: &str) -> io::Result<usize> {
+    match ty {
+        "char" | "uchar" | "int8" | "uint8" => Ok(1),
+        "short" | "ushort" | "int16" | "uint16" => Ok(2),
+        "int" | "uint" | "int32" | "uint32" | "float" | "float32" => Ok(4),
+        "double" | "float64" => Ok(8),
+        _ => Err(invalid_data(format!("unknown PLY type `{}`", ty))),
+    }
+}
+
+/// Source of PLY values, either whitespace separated tokens or binary data.
+enum PlyValues<'a, R: 'a> {
+    Ascii(::std::str::SplitWhitespace<'a>),
+    Binary { reader: &'a mut R, big_endian: bool },
+}
+
+impl<'a, R: Read> PlyValues<'a, R> {
+    fn next(&mut self, ty: &str) -> io::Result<f64> {
+        match *self {
+            PlyValues::Ascii(ref mut tokens) => parse(tokens.next()),
+            PlyValues::Binary { ref mut reader, big_endian } => {
+                let size = ply_type_size(ty)?;
+                let mut bytes = [0u8; 8];
+                reader.read_exact(&mut bytes[..size])?;
+                if big_endian {
+                    bytes[..size].reverse();
+                }
+                let bits = bytes.iter().rev().fold(0u64, |bits, &b| (bits << 8) | b as u64);
+                Ok(match ty {
+                    "char" | "int8" => bits as u8 as i8 as f64,
+                    "uchar" | "uint8" => bits as u8 as f64,
+                    "short" | "int16" => bits as u16 as i16 as f64,
+                    "ushort" | "uint16" => bits as u16 as f64,
+                    "int" | "int32" => bits as u32 as i32 as f64,
+                    "uint" | "uint32" => bits as u32 as f64,
+                    "float" | "float32" => f32::from_bits(bits as u32) as f64,
+                    _ => f64::from_bits(bits),
+                })
+            }
+        }
+    }
+}
+
+/// Read a PLY file with `vertex` (`x`, `y`, `z`) and `face` (`vertex_indices`) elements.
+///
+/// Ref: [PLY]
+pub fn read_ply<R: BufRead, T: Real>(mut reader: R) -> io::Result<MeshData<T>> {
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid_data("missing PLY magic number".to_owned()));
+    }
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("unexpected end of PLY header".to_owned()));
+        }
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.get(0).cloned() {
+            Some("format") => format = match tokens.get(1).cloned() {
+                Some("ascii") => Some(PlyFormat::Ascii),
+                Some("binary_little_endian") => Some(PlyFormat::BinaryLittleEndian),
+                Some("binary_big_endian") => Some(PlyFormat::BinaryBigEndian),
+                _ => return Err(invalid_data(format!("unknown PLY format `{}`", line.trim()))),
+            },
+            Some("element") if tokens.len() == 3 => elements.push(PlyElement {
+                name: tokens[1].to_owned(),
+                count: parse(Some(tokens[2]))?,
+                properties: Vec::new(),
+            }),
+            Some("property") => {
+                let property = match tokens.len() {
+                    3 => PlyProperty::Scalar { name: tokens[2].to_owned(), ty: tokens[1].to_owned() },
+                    5 if tokens[1] == "list" => PlyProperty::List {
+                        name: tokens[4].to_owned(),
+                        count: tokens[2].to_owned(),
+                        ty: tokens[3].to_owned(),
+                    },
+                    _ => return Err(invalid_data(format!("invalid PLY property `{}`", line.trim()))),
+                };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(invalid_data("PLY property without element".to_owned())),
+                }
+            }
+            Some("end_header") => break,
+            _ => (), // comments and object info
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("missing PLY format".to_owned()))?;
+    let mut body = String::new();
+    let mut values = match format {
+        PlyFormat::Ascii => {
+            reader.read_to_string(&mut body)?;
+            PlyValues::Ascii(body.split_whitespace())
+        }
+        PlyFormat::BinaryLittleEndian => PlyValues::Binary { reader: &mut reader, big_endian: false },
+        PlyFormat::BinaryBigEndian => PlyValues::Binary { reader: &mut reader, big_endian: true },
+    };
+
+    let mut mesh = MeshData { positions: Vec::new(), faces: Vec::new() };
+    let mut polygon = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut position = [0.0; 3];
+            for property in &element.properties {
+                match *property {
+                    PlyProperty::Scalar { ref name, ref ty } => {
+                        let value = values.next(ty)?;
+                        if element.name == "vertex" {
+                            match name.as_str() {
+                                "x" => position[0] = value,
+                                "y" => position[1] = value,
+                                "z" => position[2] = value,
+                                _ => (),
+                            }
+                        }
+                    }
+                    PlyProperty::List { ref name, ref count, ref ty } => {
+                        let count = values.next(count)? as usize;
+                        polygon.clear();
+                        for _ in 0..count {
+                            polygon.push(values.next(ty)? as usize);
+                        }
+                        if element.name == "face" && (name.as_str() == "vertex_indices" || name.as_str() == "vertex_index") {
+                            triangulate(&polygon, &mut mesh.faces);
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                mesh.positions.push(Vector3::new(T::new(position[0]), T::new(position[1]), T::new(position[2])));
+            }
+        }
+    }
+    mesh.check_indices()?;
+    Ok(mesh)
+}
+
+/// Write an ASCII PLY file with vertex normals.
+pub fn write_ply<W: Write, T: Real>(w: &mut W, mesh: &MeshData<T>) -> io::Result<()> {
+    writeln!(w, "ply\nformat ascii 1.0")?;
+    writeln!(w, "element vertex {}", mesh.positions.len())?;
+    writeln!(w, "property double x\nproperty double y\nproperty double z")?;
+    writeln!(w, "property double nx\nproperty double ny\nproperty double nz")?;
+    writeln!(w, "element face {}", mesh.faces.len())?;
+    writeln!(w, "property list uchar int vertex_indices\nend_header")?;
+    for (p, n) in mesh.positions.iter().zip(mesh.vertex_normals()) {
+        let (p, n) = (to_f64(*p), to_f64(n));
+        writeln!(w, "{} {} {} {} {} {}", p[0], p[1], p[2], n[0], n[1], n[2])?;
+    }
+    for f in &mesh.faces {
+        writeln!(w, "3 {} {} {}", f[0], f[1], f[2])?;
+    }
+    Ok(())
+}
+
+fn is_ply<P: AsRef<Path>>(path: &P) -> bool {
+    path.as_ref().extension().and_then(|ext| ext.to_str()).map_or(false, |ext| ext.to_lowercase() == "ply")
+}
+
+/// Load an OBJ or PLY (`.ply` extension) file.
+pub fn load<P: AsRef<Path>, T: Real>(path: P) -> io::Result<MeshData<T>> {
+    let ply = is_ply(&path);
+    let file = BufReader::new(File::open(path)?);
+    if ply { read_ply(file) } else { read_obj(file) }
+}
+
+/// Save as OBJ or PLY (`.ply` extension) file.
+pub fn save<P: AsRef<Path>, T: Real>(path: P, mesh: &MeshData<T>) -> io::Result<()> {
+    let ply = is_ply(&path);
+    let mut file = BufWriter::new(File::create(path)?);
+    if ply { write_ply(&mut file, mesh)? } else { write_obj(&mut file, mesh)? }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE_OBJ: &str = "
+        # unit cube with quad faces
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        v 0 0 1
+        v 1 0 1
+        v 1 1 1
+        v 0 1 1
+        f 1 4 3 2
+        f 5 6 7 8
+        f 1 2 6 5
+        f 2/1 3/2 7/3 6/4
+        f 3//1 4//1 8//1 7//1
+        f -4 -1 -5 -8
+    ";
+
+    #[test]
+    fn mesh_roundtrip() {
+        let cube = read_obj::<_, f64>(CUBE_OBJ.as_bytes()).unwrap();
+        assert_eq!(cube.positions.len(), 8);
+        assert_eq!(cube.faces.len(), 12);
+        assert!(cube.validate().is_empty());
+
+        // normals point outwards
+        let center = Vector3::new(0.5, 0.5, 0.5);
+        for (p, n) in cube.positions.iter().zip(cube.vertex_normals()) {
+            assert!((n.magnitude() - 1.0).abs() < 1.0e-12);
+            assert!(n.dot(*p - center) > 0.0);
+        }
+
+        let mut obj = Vec::new();
+        write_obj(&mut obj, &cube).unwrap();
+        let mut ply = Vec::new();
+        write_ply(&mut ply, &cube).unwrap();
+        for mesh in &[read_obj::<_, f64>(&obj[..]).unwrap(), read_ply::<_, f64>(&ply[..]).unwrap()] {
+            assert_eq!(mesh.positions, cube.positions);
+            assert_eq!(mesh.faces, cube.faces);
+        }
+
+        // binary little endian, single triangle with an additional property
+        let mut binary = b"ply\nformat binary_little_endian 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n".to_vec();
+        for &(x, y) in &[(0.0f32, 0.0f32), (1.0, 0.0), (0.0, 1.0)] {
+            for &v in &[x, y, 0.0] {
+                let bits = v.to_bits();
+                binary.extend_from_slice(&[bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8]);
+            }
+            binary.push(255);
+        }
+        binary.extend_from_slice(&[3, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0]);
+        let triangle = read_ply::<_, f64>(&binary[..]).unwrap();
+        assert_eq!(triangle.positions[1], Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(triangle.faces, vec![[0, 1, 2]]);
+        assert_eq!(triangle.validate(), vec![Defect::OpenEdge(0, 1), Defect::OpenEdge(0, 2), Defect::OpenEdge(1, 2)]);
+        assert_eq!(triangle.face_normals()[0], Vector3::new(0.0, 0.0, 1.0));
+
+        assert!(read_obj::<_, f64>("v 0 0 0\nf 1 2 3\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,10 @@
+//! File import and export
+
+pub mod checkpoint;
+pub mod image;
+pub mod mesh;
+pub mod scene;
+pub mod usd;
+#[cfg(feature = "vdb")]
+pub mod vdb;
+pub mod vtk;
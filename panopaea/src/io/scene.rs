@@ -0,0 +1,551 @@
+//! Declarative scene descriptions
+//!
+//! A scene file describes the domain, the solver with its parameters, emitters,
+//! obstacles and the output schedule. Scenes are written in RON or JSON and
+//! construct the corresponding 2d solver, experiments can be set up without
+//! recompiling.
+//!
+//! Positions and sizes are given in world units as `(x, y)` pairs, the domain
+//! covers `[0, cells * spacing]`. Grid cell counts and boundaries follow the
+//! `(y, x)` order of `Grid2d`.
+//!
+//! ```text
+//! (
+//!     domain: (cells: (32, 32), spacing: 0.05),
+//!     solver: Wcsph((particle_spacing: Some(0.025), viscosity: 0.01)),
+//!     emitters: [(shape: Box(min: (0.2, 1.0), max: (0.4, 1.2)), rate: 2000.0)],
+//!     obstacles: [Circle(center: (0.8, 0.4), radius: 0.1)],
+//!     output: (frame_rate: 30.0, end_time: 4.0, directory: "dam"),
+//! )
+//! ```
+
+use advection::Scheme;
+use domain::{AxisBoundary, Grid2d};
+use error;
+use math::VectorN;
+use math::vector_n::vec2;
+use particle::Particles;
+use ron;
+use serde_json;
+use solvers::flip::{self, Flip, Transfer};
+use solvers::smoke::{Combustion, Smoke};
+use solvers::timestep::TimestepController;
+use sph::boundary::BoundarySampler;
+use sph::dfsph::Dfsph;
+use sph::emitter::{Emitter, Shape};
+use sph::property::Mass;
+use sph::wcsph::{self, Wcsph};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use typenum::U2;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub domain: Domain,
+    pub solver: SolverConfig,
+    #[serde(default)]
+    pub emitters: Vec<EmitterConfig>,
+    /// Static solids, only represented by the SPH solvers as boundary particles.
+    #[serde(default)]
+    pub obstacles: Vec<ShapeConfig>,
+    #[serde(default)]
+    pub timestep: TimestepConfig,
+    #[serde(default)]
+    pub output: Output,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Domain {
+    /// Number of grid cells `(y, x)`.
+    pub cells: (usize, usize),
+    /// Edge length of the grid cells.
+    pub spacing: f64,
+    /// Boundary conditions of the `[y, x]` axes, walls also bound the SPH domains.
+    #[serde(default)]
+    pub boundary: [AxisBoundary; 2],
+}
+
+impl Domain {
+    /// Extent `(x, y)` of the domain.
+    pub fn size(&self) -> (f64, f64) {
+        (self.cells.1 as f64 * self.spacing, self.cells.0 as f64 * self.spacing)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SolverConfig {
+    Smoke(SmokeConfig),
+    Flip(FlipConfig),
+    Wcsph(SphConfig),
+    Dfsph(SphConfig),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmokeConfig {
+    pub scheme: Scheme,
+    pub density_weight: f64,
+    pub temperature_lift: f64,
+    pub ambient_temperature: f64,
+    pub vorticity_confinement: f64,
+    pub viscosity: f64,
+    pub temperature_diffusivity: f64,
+    /// Burn the fuel of the sources, `None` disables fire.
+    pub combustion: Option<Combustion<f64>>,
+}
+
+impl Default for SmokeConfig {
+    fn default() -> Self {
+        SmokeConfig {
+            scheme: Scheme::MacCormack,
+            density_weight: 0.1,
+            temperature_lift: 1.0,
+            ambient_temperature: 0.0,
+            vorticity_confinement: 0.2,
+            viscosity: 0.0,
+            temperature_diffusivity: 0.0,
+            combustion: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlipConfig {
+    /// Spacing of emitted particles, defaults to half the cell spacing.
+    pub particle_spacing: Option<f64>,
+    pub flip_ratio: f64,
+    pub transfer: Transfer,
+    pub gravity: f64,
+    pub viscosity: f64,
+}
+
+impl Default for FlipConfig {
+    fn default() -> Self {
+        FlipConfig {
+            particle_spacing: None,
+            flip_ratio: 0.95,
+            transfer: Transfer::Flip,
+            gravity: -9.81,
+            viscosity: 0.0,
+        }
+    }
+}
+
+/// Parameters shared by the SPH solvers.
+///
+/// The smoothing radius is twice the particle spacing.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SphConfig {
+    /// Spacing of emitted particles, defaults to half the cell spacing.
+    pub particle_spacing: Option<f64>,
+    pub rest_density: f64,
+    pub viscosity: f64,
+    pub gravity: (f64, f64),
+    /// Numerical speed of sound, only used by `Wcsph`.
+    pub speed_of_sound: f64,
+}
+
+impl Default for SphConfig {
+    fn default() -> Self {
+        SphConfig {
+            particle_spacing: None,
+            rest_density: 1000.0,
+            viscosity: 1.0e-3,
+            gravity: (0.0, -9.81),
+            speed_of_sound: 10.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ShapeConfig {
+    Box { min: (f64, f64), max: (f64, f64) },
+    Circle { center: (f64, f64), radius: f64 },
+}
+
+impl ShapeConfig {
+    /// Emitter shape with positions divided by `scale`.
+    fn shape(&self, scale: f64) -> Shape<f64, U2> {
+        match *self {
+            ShapeConfig::Box { min, max } => Shape::Box {
+                min: vec2(min.0, min.1) / scale,
+                max: vec2(max.0, max.1) / scale,
+            },
+            ShapeConfig::Circle { center, radius } => Shape::Sphere {
+                center: vec2(center.0, center.1) / scale,
+                radius: radius / scale,
+            },
+        }
+    }
+
+    fn boundary(&self, sampler: &BoundarySampler<f64>) -> Vec<VectorN<f64, U2>> {
+        match *self {
+            ShapeConfig::Box { min, max } => sampler.rectangle(vec2(min.0, min.1), vec2(max.0, max.1)),
+            ShapeConfig::Circle { center, radius } => sampler.circle(vec2(center.0, center.1), radius),
+        }
+    }
+}
+
+/// Particle emitter or smoke source.
+///
+/// Particle solvers emit `rate` particles per second. Smoke sources set density,
+/// temperature and fuel of the cells inside the shape at every step.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EmitterConfig {
+    pub shape: ShapeConfig,
+    #[serde(default)]
+    pub rate: f64,
+    #[serde(default)]
+    pub velocity: (f64, f64),
+    #[serde(default = "default_source_density")]
+    pub density: f64,
+    #[serde(default)]
+    pub temperature: f64,
+    #[serde(default)]
+    pub fuel: f64,
+}
+
+fn default_source_density() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimestepConfig {
+    pub min: f64,
+    pub max: f64,
+    /// Courant number of the adaptive timestep.
+    pub courant: f64,
+}
+
+impl Default for TimestepConfig {
+    fn default() -> Self {
+        TimestepConfig {
+            min: 1.0e-5,
+            max: 1.0e-2,
+            courant: 0.4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Output {
+    /// Frames per second of simulated time.
+    pub frame_rate: f64,
+    pub end_time: f64,
+    pub directory: String,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            frame_rate: 30.0,
+            end_time: 10.0,
+            directory: "output".to_owned(),
+        }
+    }
+}
+
+impl Output {
+    pub fn num_frames(&self) -> usize {
+        (self.end_time * self.frame_rate).ceil() as usize
+    }
+
+    pub fn frame_duration(&self) -> f64 {
+        1.0 / self.frame_rate
+    }
+
+    /// File of a frame in the output directory, e.g. `output/frame_00042.vtp`.
+    pub fn frame_path(&self, frame: usize, extension: &str) -> PathBuf {
+        Path::new(&self.directory).join(format!("frame_{:05}.{}", frame, extension))
+    }
+}
+
+impl SceneDescription {
+    /// Fails for inconsistent boundaries or a non-positive spacing.
+    pub fn grid(&self) -> error::Result<Grid2d> {
+        let spacing = self.domain.spacing;
+        Grid2d::try_with_boundary(self.domain.cells, self.domain.boundary)?.try_with_spacing((spacing, spacing))
+    }
+
+    /// Spacing of emitted particles in world units.
+    pub fn particle_spacing(&self) -> f64 {
+        let spacing = match self.solver {
+            SolverConfig::Smoke(_) => None,
+            SolverConfig::Flip(ref config) => config.particle_spacing,
+            SolverConfig::Wcsph(ref config) | SolverConfig::Dfsph(ref config) => config.particle_spacing,
+        };
+        spacing.unwrap_or(0.5 * self.domain.spacing)
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub fn parse_ron(source: &str) -> io::Result<SceneDescription> {
+    ron::de::from_str(source).map_err(|err| invalid_data(err.to_string()))
+}
+
+pub fn parse_json(source: &str) -> io::Result<SceneDescription> {
+    serde_json::from_str(source).map_err(|err| invalid_data(err.to_string()))
+}
+
+/// Load a scene file, `.ron` files are parsed as RON and all others as JSON.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SceneDescription> {
+    let path = path.as_ref();
+    let mut source = String::new();
+    File::open(path)?.read_to_string(&mut source)?;
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+    if extension == "ron" {
+        parse_ron(&source)
+    } else {
+        parse_json(&source)
+    }
+}
+
+pub enum Solver<'a> {
+    Smoke(Smoke<'a, f64>),
+    Flip(Flip<'a, f64>),
+    Wcsph(Wcsph<f64>),
+    Dfsph(Dfsph<f64>),
+}
+
+/// Solver constructed from a scene description with its emitters.
+///
+/// FLIP particles are stored in grid units, emitter shapes and velocities are
+/// converted from world units. Obstacles are ignored by the grid solvers.
+pub struct Simulation<'a> {
+    pub solver: Solver<'a>,
+    pub emitters: Vec<Emitter<f64, U2>>,
+    pub controller: TimestepController<f64>,
+    sources: Vec<EmitterConfig>,
+    spacing: f64,
+    smoothing_radius: f64,
+}
+
+impl<'a> Simulation<'a> {
+    /// Construct the solver on the grid of the scene, see `SceneDescription::grid`.
+    ///
+    /// Registers the particle properties required by the solver.
+    pub fn new(scene: &SceneDescription, grid: &'a Grid2d, particles: &mut Particles) -> Self {
+        let spacing = scene.domain.spacing;
+        let particle_spacing = scene.particle_spacing();
+        let smoothing_radius = 2.0 * particle_spacing;
+
+        let mut controller = TimestepController::new(scene.timestep.min, scene.timestep.max);
+        controller.courant = scene.timestep.courant;
+
+        // particle emitters in solver units with mass `ρ0 s^2`
+        let emitters = |scale: f64, mass: f64| {
+            scene.emitters.iter().map(|config| {
+                let mut emitter = Emitter::new(config.shape.shape(scale), config.rate, particle_spacing / scale, mass);
+                emitter.velocity = vec2(config.velocity.0, config.velocity.1) / scale;
+                emitter
+            }).collect::<Vec<_>>()
+        };
+
+        let (solver, emitters) = match scene.solver {
+            SolverConfig::Smoke(ref config) => {
+                let mut smoke = Smoke::new(grid);
+                smoke.scheme = config.scheme;
+                smoke.density_weight = config.density_weight;
+                smoke.temperature_lift = config.temperature_lift;
+                smoke.ambient_temperature = config.ambient_temperature;
+                smoke.vorticity_confinement = config.vorticity_confinement;
+                smoke.viscosity = config.viscosity;
+                smoke.temperature_diffusivity = config.temperature_diffusivity;
+                smoke.combustion = config.combustion;
+                (Solver::Smoke(smoke), Vec::new())
+            }
+            SolverConfig::Flip(ref config) => {
+                flip::init::<f64>(particles);
+                particles.add_property::<Mass<f64>>();
+                let mut flip = Flip::new(grid);
+                flip.flip_ratio = config.flip_ratio;
+                flip.transfer = config.transfer;
+                flip.gravity = config.gravity;
+                flip.viscosity = config.viscosity;
+                (Solver::Flip(flip), emitters(spacing, particle_spacing * particle_spacing))
+            }
+            SolverConfig::Wcsph(ref config) => {
+                wcsph::init::<f64, U2>(particles);
+                let mut solver = Wcsph::new(sph_cells(scene, smoothing_radius), smoothing_radius, config.rest_density);
+                solver.set_speed_of_sound(config.speed_of_sound);
+                solver.pipeline.viscosity = config.viscosity;
+                solver.pipeline.gravity = vec2(config.gravity.0, config.gravity.1);
+                solver.pipeline.set_boundary(sph_boundary(scene, particle_spacing));
+                let mass = config.rest_density * particle_spacing * particle_spacing;
+                (Solver::Wcsph(solver), emitters(1.0, mass))
+            }
+            SolverConfig::Dfsph(ref config) => {
+                wcsph::init::<f64, U2>(particles);
+                let mut solver = Dfsph::new(sph_cells(scene, smoothing_radius), smoothing_radius, config.rest_density);
+                solver.pipeline.viscosity = config.viscosity;
+                solver.pipeline.gravity = vec2(config.gravity.0, config.gravity.1);
+                solver.pipeline.set_boundary(sph_boundary(scene, particle_spacing));
+                let mass = config.rest_density * particle_spacing * particle_spacing;
+                (Solver::Dfsph(solver), emitters(1.0, mass))
+            }
+        };
+
+        let sources = match solver {
+            Solver::Smoke(_) => scene.emitters.clone(),
+            _ => Vec::new(),
+        };
+
+        Simulation {
+            solver,
+            emitters,
+            controller,
+            sources,
+            spacing,
+            smoothing_radius,
+        }
+    }
+
+    /// Adaptive timestep of the current state.
+    pub fn timestep(&self, particles: &Particles) -> f64 {
+        match self.solver {
+            Solver::Smoke(ref smoke) => self.controller.staggered(smoke.grid(), &smoke.velocity, 0.0),
+            Solver::Flip(ref flip) => self.controller.staggered(flip.grid(), &flip.velocity, 0.0),
+            Solver::Wcsph(ref solver) => {
+                self.controller.particles(particles, self.smoothing_radius, solver.speed_of_sound(), solver.pipeline.viscosity)
+            }
+            Solver::Dfsph(ref solver) => {
+                self.controller.particles(particles, self.smoothing_radius, 0.0, solver.pipeline.viscosity)
+            }
+        }
+    }
+
+    /// Emit particles or apply smoke sources and advance the solver by one timestep.
+    pub fn step(&mut self, particles: &mut Particles, timestep: f64) {
+        for emitter in &mut self.emitters {
+            emitter.emit(particles, timestep);
+        }
+
+        match self.solver {
+            Solver::Smoke(ref mut smoke) => {
+                let spacing = self.spacing;
+                for source in &self.sources {
+                    let shape = source.shape.shape(spacing);
+                    let (h, w) = smoke.density.dim();
+                    for y in 0..h {
+                        for x in 0..w {
+                            if shape.contains(&vec2(x as f64 + 0.5, y as f64 + 0.5)) {
+                                smoke.density[(y, x)] = source.density;
+                                smoke.temperature[(y, x)] = source.temperature;
+                                smoke.fuel[(y, x)] = source.fuel;
+                            }
+                        }
+                    }
+                }
+                smoke.step(timestep);
+            }
+            Solver::Flip(ref mut flip) => flip.step(particles, timestep),
+            Solver::Wcsph(ref mut solver) => solver.step(particles, timestep),
+            Solver::Dfsph(ref mut solver) => solver.step(particles, timestep),
+        }
+    }
+
+    /// Advance by `duration` with adaptive substeps, returns the number of steps.
+    pub fn advance(&mut self, particles: &mut Particles, duration: f64) -> usize {
+        let mut remaining = duration;
+        let mut num_steps = 0;
+        while remaining > 0.0 {
+            let timestep = self.timestep(particles);
+            let timestep = self.controller.substep(timestep, remaining);
+            self.step(particles, timestep);
+            remaining -= timestep;
+            num_steps += 1;
+        }
+        num_steps
+    }
+}
+
+/// Neighborhood grid covering the domain including boundary particles on the upper walls.
+fn sph_cells(scene: &SceneDescription, smoothing_radius: f64) -> VectorN<usize, U2> {
+    let (width, height) = scene.domain.size();
+    vec2(
+        (width / smoothing_radius).ceil() as usize + 1,
+        (height / smoothing_radius).ceil() as usize + 1,
+    )
+}
+
+/// Boundary particles of the domain walls and obstacles.
+fn sph_boundary(scene: &SceneDescription, particle_spacing: f64) -> Vec<VectorN<f64, U2>> {
+    let sampler = BoundarySampler::new(particle_spacing);
+    let (width, height) = scene.domain.size();
+    let (y_axis, x_axis) = (scene.domain.boundary[0], scene.domain.boundary[1]);
+
+    let mut positions = Vec::new();
+    let walls = [
+        (y_axis.lower, vec2(0.0, 0.0), vec2(width, 0.0)),
+        (y_axis.upper, vec2(0.0, height), vec2(width, height)),
+        (x_axis.lower, vec2(0.0, 0.0), vec2(0.0, height)),
+        (x_axis.upper, vec2(width, 0.0), vec2(width, height)),
+    ];
+    for &(condition, a, b) in &walls {
+        if condition.is_wall() {
+            positions.extend(sampler.segment(a, b));
+        }
+    }
+    for obstacle in &scene.obstacles {
+        positions.extend(obstacle.boundary(&sampler));
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use sph::property::Position;
+    use super::*;
+
+    #[test]
+    fn scene_setup() {
+        let scene = parse_ron(r#"(
+            domain: (cells: (10, 10), spacing: 0.1),
+            solver: Wcsph((particle_spacing: Some(0.05), viscosity: 0.01)),
+            emitters: [(shape: Box(min: (0.2, 0.6), max: (0.4, 0.8)), rate: 2000.0, velocity: (1.0, 0.0))],
+            obstacles: [Circle(center: (0.5, 0.3), radius: 0.1)],
+            output: (frame_rate: 50.0, end_time: 1.0, directory: "dam"),
+        )"#).unwrap();
+        assert_eq!(scene.output.num_frames(), 50);
+        assert_eq!(scene.output.frame_path(3, "vtp"), Path::new("dam/frame_00003.vtp"));
+
+        let grid = scene.grid().unwrap();
+        let mut particles = Particles::new();
+        let mut simulation = Simulation::new(&scene, &grid, &mut particles);
+        assert!(simulation.advance(&mut particles, scene.output.frame_duration()) > 1);
+        assert!(particles.num_particles() > 0);
+        for p in particles.read_property::<Position<f64, U2>>() {
+            assert!(p[0] > 0.0 && p[0] < 1.0 && p[1] > 0.0 && p[1] < 1.0, "{:?}", p);
+        }
+
+        let scene = parse_json(r#"{
+            "domain": { "cells": [16, 8], "spacing": 0.5 },
+            "solver": { "Smoke": { "vorticity_confinement": 0.0 } },
+            "emitters": [{ "shape": { "Box": { "min": [1.0, 0.0], "max": [3.0, 1.0] } }, "temperature": 1.0 }]
+        }"#).unwrap();
+        let grid = scene.grid().unwrap();
+        assert_eq!(grid.dim(), (16, 8));
+        assert_eq!(grid.spacing(), (0.5, 0.5));
+
+        let invalid = parse_json(r#"{ "domain": { "cells": [4, 4], "spacing": 0.0 }, "solver": { "Smoke": {} } }"#).unwrap();
+        assert!(invalid.grid().is_err());
+
+        let mut simulation = Simulation::new(&scene, &grid, &mut particles);
+        simulation.step(&mut particles, 0.1);
+        match simulation.solver {
+            Solver::Smoke(ref smoke) => {
+                assert!(smoke.density[(0, 3)] > 0.0);
+                assert_eq!(smoke.density[(10, 3)], 0.0);
+            }
+            _ => panic!("expected smoke solver"),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! USD export of particle and mesh sequences
+//!
+//! Time-sampled particle positions and surface meshes are collected frame by
+//! frame into a `UsdStage` and written as a single ASCII layer (`.usda`),
+//! which can be loaded or referenced by DCC tools supporting USD. Particles
+//! become `Points` prims, meshes `Mesh` prims with time-sampled topology as
+//! reconstructed surfaces change their connectivity every frame.
+//!
+//! Prims are placed below a root `Xform`, the stage uses a `Z` up axis like
+//! the 3d solvers. Samples are kept in memory until the stage is written.
+//!
+//! References:
+//!     [USD] Pixar, Universal Scene Description,
+//!           https://graphics.pixar.com/usd/docs/index.html
+
+use domain::TriangleMesh;
+use math::{Dim, Real, VectorN};
+use particle::Particles;
+use sph::property::Position;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Geometry {
+    Points,
+    Mesh,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Sample {
+    points: Vec<[f64; 3]>,
+    velocities: Option<Vec<[f64; 3]>>,
+    faces: Vec<[usize; 3]>,
+}
+
+#[derive(Clone, Debug)]
+struct Prim {
+    geometry: Geometry,
+    /// Constant point width of particles.
+    width: Option<f64>,
+    samples: BTreeMap<usize, Sample>,
+}
+
+/// Time-sampled particles and meshes of a simulation, indexed by frame.
+pub struct UsdStage {
+    root: String,
+    frames_per_second: f64,
+    prims: BTreeMap<String, Prim>,
+}
+
+/// Vectors padded to 3 components.
+fn pad<T: Real, N: Dim<T>>(values: &[VectorN<T, N>]) -> Vec<[f64; 3]> {
+    values.iter().map(|v| {
+        let mut p = [0.0; 3];
+        for i in 0..v.len().min(3) {
+            p[i] = v[i].to_f64().unwrap();
+        }
+        p
+    }).collect()
+}
+
+/// Panics if `name` isn't a valid prim name.
+fn check_name(name: &str) {
+    let letter = |c: char| (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_';
+    let valid = name.chars().next().map_or(false, &letter)
+        && name.chars().all(|c| letter(c) || (c >= '0' && c <= '9'));
+    assert!(valid, "invalid prim name `{}`", name);
+}
+
+impl UsdStage {
+    pub fn new(frames_per_second: f64) -> Self {
+        assert!(frames_per_second > 0.0, "frame rate must be positive");
+        UsdStage {
+            root: "Fluid".to_string(),
+            frames_per_second,
+            prims: BTreeMap::new(),
+        }
+    }
+
+    /// Name of the root prim, `Fluid` by default.
+    pub fn with_root(self, root: &str) -> Self {
+        check_name(root);
+        UsdStage { root: root.to_string(), ..self }
+    }
+
+    fn sample(&mut self, name: &str, geometry: Geometry, frame: usize) -> &mut Sample {
+        check_name(name);
+        let prim = self.prims.entry(name.to_string()).or_insert_with(|| Prim {
+            geometry,
+            width: None,
+            samples: BTreeMap::new(),
+        });
+        assert_eq!(prim.geometry, geometry, "prim `{}` has a different type", name);
+        prim.samples.entry(frame).or_insert_with(Sample::default)
+    }
+
+    /// Add point positions of a frame.
+    pub fn points<T: Real, N: Dim<T>>(&mut self, name: &str, frame: usize, positions: &[VectorN<T, N>]) -> &mut Self {
+        self.sample(name, Geometry::Points, frame).points = pad(positions);
+        self
+    }
+
+    /// Add point velocities of a frame, used for motion blur.
+    pub fn velocities<T: Real, N: Dim<T>>(&mut self, name: &str, frame: usize, velocities: &[VectorN<T, N>]) -> &mut Self {
+        self.sample(name, Geometry::Points, frame).velocities = Some(pad(velocities));
+        self
+    }
+
+    /// Add particle positions of a frame.
+    pub fn particles<T, N>(&mut self, name: &str, frame: usize, particles: &Particles) -> &mut Self
+        where T: Real + 'static,
+              N: Dim<T> + 'static,
+    {
+        self.points(name, frame, particles.read_property::<Position<T, N>>())
+    }
+
+    /// Diameter of all points of a particle prim.
+    pub fn point_width(&mut self, name: &str, width: f64) -> &mut Self {
+        let prim = self.prims.get_mut(name).expect("unknown prim");
+        assert_eq!(prim.geometry, Geometry::Points, "prim `{}` has no points", name);
+        prim.width = Some(width);
+        self
+    }
+
+    /// Add a triangle mesh of a frame, e.g. the reconstructed surface.
+    pub fn mesh<T: Real>(&mut self, name: &str, frame: usize, mesh: &TriangleMesh<T>) -> &mut Self {
+        let sample = self.sample(name, Geometry::Mesh, frame);
+        sample.points = mesh.positions().iter()
+            .map(|p| [p.x.to_f64().unwrap(), p.y.to_f64().unwrap(), p.z.to_f64().unwrap()])
+            .collect();
+        sample.faces = mesh.faces().to_vec();
+        self
+    }
+
+    /// First and last frame with samples.
+    pub fn frame_range(&self) -> Option<(usize, usize)> {
+        let first = self.prims.values().filter_map(|prim| prim.samples.keys().next()).min();
+        let last = self.prims.values().filter_map(|prim| prim.samples.keys().next_back()).max();
+        match (first, last) {
+            (Some(&first), Some(&last)) => Some((first, last)),
+            _ => None,
+        }
+    }
+
+    /// Write the stage as USD ASCII layer.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (start, end) = self.frame_range().unwrap_or((0, 0));
+        writeln!(w, "#usda 1.0")?;
+        writeln!(w, "(")?;
+        writeln!(w, "    defaultPrim = \"{}\"", self.root)?;
+        writeln!(w, "    startTimeCode = {}", start)?;
+        writeln!(w, "    endTimeCode = {}", end)?;
+        writeln!(w, "    framesPerSecond = {}", self.frames_per_second)?;
+        writeln!(w, "    timeCodesPerSecond = {}", self.frames_per_second)?;
+        writeln!(w, "    upAxis = \"Z\"")?;
+        writeln!(w, ")")?;
+        writeln!(w, "")?;
+        writeln!(w, "def Xform \"{}\"", self.root)?;
+        writeln!(w, "{{")?;
+        for (name, prim) in &self.prims {
+            match prim.geometry {
+                Geometry::Points => {
+                    writeln!(w, "    def Points \"{}\"", name)?;
+                    writeln!(w, "    {{")?;
+                    write_samples(w, "point3f[] points", prim, |s| Some(vec3s(&s.points)))?;
+                    if prim.samples.values().any(|s| s.velocities.is_some()) {
+                        write_samples(w, "vector3f[] velocities", prim, |s| s.velocities.as_ref().map(|v| vec3s(v)))?;
+                    }
+                    if let Some(width) = prim.width {
+                        writeln!(w, "        float[] widths = [{}] (", width)?;
+                        writeln!(w, "            interpolation = \"constant\"")?;
+                        writeln!(w, "        )")?;
+                    }
+                }
+                Geometry::Mesh => {
+                    writeln!(w, "    def Mesh \"{}\"", name)?;
+                    writeln!(w, "    {{")?;
+                    write_samples(w, "int[] faceVertexCounts", prim, |s| {
+                        Some(list(s.faces.iter().map(|_| 3)))
+                    })?;
+                    write_samples(w, "int[] faceVertexIndices", prim, |s| {
+                        Some(list(s.faces.iter().flat_map(|f| f.iter().cloned())))
+                    })?;
+                    write_samples(w, "point3f[] points", prim, |s| Some(vec3s(&s.points)))?;
+                    writeln!(w, "        uniform token orientation = \"rightHanded\"")?;
+                    writeln!(w, "        uniform token subdivisionScheme = \"none\"")?;
+                }
+            }
+            writeln!(w, "    }}")?;
+        }
+        writeln!(w, "}}")
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+}
+
+/// Attribute with a value per frame, frames without value are skipped.
+fn write_samples<W, F>(w: &mut W, attribute: &str, prim: &Prim, mut value: F) -> io::Result<()>
+    where W: Write,
+          F: FnMut(&Sample) -> Option<String>,
+{
+    writeln!(w, "        {}.timeSamples = {{", attribute)?;
+    for (frame, sample) in &prim.samples {
+        if let Some(value) = value(sample) {
+            writeln!(w, "            {}: {},", frame, value)?;
+        }
+    }
+    writeln!(w, "        }}")
+}
+
+fn list<V: ToString, I: Iterator<Item = V>>(values: I) -> String {
+    format!("[{}]", values.map(|v| v.to_string()).collect::<Vec<_>>().join(", "))
+}
+
+fn vec3s(values: &[[f64; 3]]) -> String {
+    list(values.iter().map(|v| format!("({}, {}, {})", v[0], v[1], v[2])))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+    use math::vector_n::vec3;
+    use typenum::U3;
+    use super::*;
+
+    #[test]
+    fn usd_export() {
+        let mut stage = UsdStage::new(24.0);
+        let velocities = vec![vec3(0.0, 0.0, 1.0); 2];
+        for frame in 1..3 {
+            let t = frame as f64;
+            stage.points("particles", frame, &[vec3(0.0, 0.0, t), vec3(1.0, 0.5, t)])
+                 .velocities("particles", frame, &velocities);
+        }
+        stage.point_width("particles", 0.1);
+
+        let mesh = TriangleMesh::new(
+            vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)],
+            vec![[0, 1, 2]]);
+        stage.mesh("surface", 2, &mesh);
+        assert_eq!(stage.frame_range(), Some((1, 2)));
+
+        let mut usda = Vec::new();
+        stage.write(&mut usda).unwrap();
+        let usda = String::from_utf8(usda).unwrap();
+        assert!(usda.starts_with("#usda 1.0\n"));
+        assert!(usda.contains("startTimeCode = 1\n    endTimeCode = 2\n"));
+        assert!(usda.contains("def Points \"particles\""));
+        assert!(usda.contains("            2: [(0, 0, 2), (1, 0.5, 2)],\n"));
+        assert!(usda.contains("vector3f[] velocities.timeSamples"));
+        assert!(usda.contains("float[] widths = [0.1]"));
+        assert!(usda.contains("int[] faceVertexIndices.timeSamples = {\n            2: [0, 1, 2],\n"));
+        assert_eq!(usda.matches('{').count(), usda.matches('}').count());
+
+        // particles read from a particle set
+        let mut particles = Particles::new();
+        particles.add_property::<Position<f64, U3>>();
+        particles.add_particles(1).with::<Position<f64, U3>>(&[vec3(1.0, 2.0, 3.0)]);
+        let mut stage = UsdStage::new(30.0).with_root("Cache");
+        stage.particles::<f64, U3>("water", 0, &particles);
+        let mut usda = Vec::new();
+        stage.write(&mut usda).unwrap();
+        assert!(String::from_utf8(usda).unwrap().contains("0: [(1, 2, 3)],"));
+    }
+}
@@ -0,0 +1,519 @@
+//! OpenVDB file export
+//!
+//! Writes 3d fields as sparse volumes (`.vdb`) readable by Houdini, Blender
+//! and other tools built on the OpenVDB library, without depending on it.
+//! Scalar fields are stored as `FloatGrid`, velocities as `Vec3SGrid` with the
+//! default tree configuration `5_4_3`. Data is written uncompressed.
+//!
+//! Grid axes `(z, y, x)` map to VDB coordinates `(x, y, z)`. Cell `(0, 0, 0)`
+//! of the grid is the voxel `(0, 0, 0)`, placed at `origin + voxel_size / 2`.
+//!
+//! Only leaf nodes containing active voxels are stored, regions without
+//! active voxels are represented by tiles of the internal nodes.
+//!
+//! References:
+//!     [Mus13] Ken Museth, 2013,
+//!             VDB: High-resolution sparse volumes with dynamic topology,
+//!             ACM Trans. Graph. 32, 3, Article 27 (July 2013)
+
+use dec::grid::Staggered3d;
+use domain::Field3d;
+use math::rng::Pcg32;
+use math::Real;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAGIC: i64 = 0x56444220;
+const FILE_VERSION: u32 = 224;
+const LIBRARY_VERSION: (u32, u32) = (5, 0);
+
+/// Per-node compression metadata: all values stored, no mask compression.
+const NO_MASK_AND_ALL_VALS: u8 = 6;
+
+/// `log2` of the node dimensions from the root to the leaves.
+const LOG2_INTERNAL_2: usize = 5;
+const LOG2_INTERNAL_1: usize = 4;
+const LOG2_LEAF: usize = 3;
+
+const LEAF_DIM: usize = 1 << LOG2_LEAF;
+const LEAF_LEN: usize = 1 << (3 * LOG2_LEAF);
+/// Number of voxels covered by a child of an internal node along each axis.
+const INTERNAL_1_TOTAL: usize = LOG2_INTERNAL_1 + LOG2_LEAF;
+const INTERNAL_2_TOTAL: usize = LOG2_INTERNAL_2 + INTERNAL_1_TOTAL;
+
+const CLASS_FOG_VOLUME: &'static str = "fog volume";
+const CLASS_LEVEL_SET: &'static str = "level set";
+const CLASS_UNKNOWN: &'static str = "unknown";
+
+/// Contents of a leaf sized region.
+enum Region {
+    /// Inactive tile with a value different from the background.
+    Tile(Vec<f32>),
+    Leaf {
+        mask: [u64; LEAF_LEN / 64],
+        /// Values ordered `x` slowest, interleaved components.
+        values: Vec<f32>,
+    },
+}
+
+struct VdbGrid {
+    name: String,
+    class: &'static str,
+    vector_type: Option<&'static str>,
+    components: usize,
+    background: Vec<f32>,
+    /// Regions by leaf origin `(x, y, z)`.
+    regions: BTreeMap<[i32; 3], Region>,
+}
+
+impl VdbGrid {
+    /// Collect the regions of a field with `dim` cells `(z, y, x)`.
+    ///
+    /// `sample` writes the components of a cell and returns whether it's active.
+    fn new<S>(name: &str, class: &'static str, dim: (usize, usize, usize), background: Vec<f32>, mut sample: S) -> Self
+        where S: FnMut((usize, usize, usize), &mut [f32]) -> bool
+    {
+        let components = background.len();
+        let (d, h, w) = dim;
+        let tiles = |n: usize| (n + LEAF_DIM - 1) / LEAF_DIM;
+        let mut regions = BTreeMap::new();
+
+        for tx in 0..tiles(w) {
+            for ty in 0..tiles(h) {
+                for tz in 0..tiles(d) {
+                    let mut mask = [0; LEAF_LEN / 64];
+                    let mut values = Vec::with_capacity(LEAF_LEN * components);
+                    for i in 0..LEAF_LEN {
+                        let (x, y, z) = (tx * LEAF_DIM + (i >> (2 * LOG2_LEAF)), ty * LEAF_DIM + ((i >> LOG2_LEAF) & (LEAF_DIM - 1)), tz * LEAF_DIM + (i & (LEAF_DIM - 1)));
+                        let start = values.len();
+                        values.extend_from_slice(&background);
+                        if z < d && y < h && x < w && sample((z, y, x), &mut values[start..]) {
+                            mask[i / 64] |= 1 << (i % 64);
+                        }
+                    }
+
+                    let origin = [(tx * LEAF_DIM) as i32, (ty * LEAF_DIM) as i32, (tz * LEAF_DIM) as i32];
+                    let uniform = values.chunks(components).all(|v| v == &values[..components]);
+                    if mask.iter().all(|&m| m == 0) && uniform {
+                        if values[..components] != background[..] {
+                            regions.insert(origin, Region::Tile(values[..components].to_vec()));
+                        }
+                    } else {
+                        regions.insert(origin, Region::Leaf { mask, values });
+                    }
+                }
+            }
+        }
+
+        VdbGrid {
+            name: name.to_string(),
+            class,
+            vector_type: None,
+            components,
+            background,
+            regions,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        if self.components == 3 { "Tree_vec3s_5_4_3" } else { "Tree_float_5_4_3" }
+    }
+
+    fn num_leaves(&self) -> usize {
+        self.regions.values().filter(|r| match **r { Region::Leaf { .. } => true, _ => false }).count()
+    }
+}
+
+/// Collection of volumes written into a single `.vdb` file.
+pub struct VdbFile {
+    grids: Vec<VdbGrid>,
+    voxel_size: f64,
+    /// World position of the grid corner `(z, y, x)`.
+    origin: (f64, f64, f64),
+}
+
+impl VdbFile {
+    /// 3d grids have unit spacing.
+    pub fn new() -> Self {
+        VdbFile {
+            grids: Vec::new(),
+            voxel_size: 1.0,
+            origin: (0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn with_voxel_size(self, voxel_size: f64) -> Self {
+        assert!(voxel_size > 0.0, "voxel size must be positive");
+        VdbFile { voxel_size, ..self }
+    }
+
+    /// Position of the first grid vertex `(z, y, x)`.
+    pub fn with_origin(self, origin: (f64, f64, f64)) -> Self {
+        VdbFile { origin, ..self }
+    }
+
+    pub fn num_grids(&self) -> usize {
+        self.grids.len()
+    }
+
+    fn push(mut self, grid: VdbGrid) -> Self {
+        assert!(self.grids.iter().all(|g| g.name != grid.name), "duplicate grid name `{}`", grid.name);
+        self.grids.push(grid);
+        self
+    }
+
+    /// Add a cell-centered density, cells above `threshold` are active.
+    pub fn fog_volume<T: Real, F: Field3d<T>>(self, name: &str, field: F, threshold: T) -> Self {
+        let grid = VdbGrid::new(name, CLASS_FOG_VOLUME, field.dim(), vec![0.0], |index, value| {
+            let v = field.at(index);
+            if v > threshold {
+                value[0] = v.to_f32().unwrap();
+                true
+            } else {
+                false
+            }
+        });
+        self.push(grid)
+    }
+
+    /// Add a cell-centered signed distance in grid units, negative inside.
+    ///
+    /// Cells closer than `half_width` to the surface are active, distances are
+    /// clamped to the band and scaled by the voxel size when written.
+    pub fn level_set<T: Real, F: Field3d<T>>(self, name: &str, phi: F, half_width: T) -> Self {
+        let band = half_width.to_f32().unwrap();
+        let grid = VdbGrid::new(name, CLASS_LEVEL_SET, phi.dim(), vec![band], |index, value| {
+            let v = phi.at(index).to_f32().unwrap();
+            value[0] = v.max(-band).min(band);
+            v.abs() < band
+        });
+        self.push(grid)
+    }
+
+    /// Add a velocity field stored as face fluxes, averaged to the cell centers.
+    ///
+    /// Cells with non-zero velocity are active.
+    pub fn staggered_3d<T: Real>(self, name: &str, velocity: &Staggered3d<T>) -> Self {
+        let (d, h, w) = velocity.dim();
+        assert_eq!(velocity.shape()[0], (d+1, h, w), "expected face storage");
+
+        let (vz, vy, vx) = velocity.split();
+        let half = T::new(0.5);
+        let mut grid = VdbGrid::new(name, CLASS_UNKNOWN, (d, h, w), vec![0.0; 3], |(z, y, x), value| {
+            value[0] = ((vx[(z, y, x)] + vx[(z, y, x+1)]) * half).to_f32().unwrap();
+            value[1] = ((vy[(z, y, x)] + vy[(z, y+1, x)]) * half).to_f32().unwrap();
+            value[2] = ((vz[(z, y, x)] + vz[(z+1, y, x)]) * half).to_f32().unwrap();
+            value.iter().any(|&v| v != 0.0)
+        });
+        grid.vector_type = Some("contravariant relative");
+        self.push(grid)
+    }
+
+    /// Write the file header and all grids.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf = Vec::new();
+        put_i64(&mut buf, MAGIC);
+        put_u32(&mut buf, FILE_VERSION);
+        put_u32(&mut buf, LIBRARY_VERSION.0);
+        put_u32(&mut buf, LIBRARY_VERSION.1);
+        buf.push(1); // grid offsets
+        buf.extend_from_slice(uuid().as_bytes());
+
+        // file metadata
+        put_u32(&mut buf, 0);
+
+        put_u32(&mut buf, self.grids.len() as u32);
+        for grid in &self.grids {
+            self.write_grid(&mut buf, grid);
+        }
+        w.write_all(&buf)
+    }
+
+    fn write_grid(&self, buf: &mut Vec<u8>, grid: &VdbGrid) {
+        // grid descriptor, stream positions are patched after writing the grid
+        put_string(buf, &grid.name);
+        put_string(buf, grid.type_name());
+        put_string(buf, "");
+        let positions = buf.len();
+        buf.extend_from_slice(&[0; 24]);
+
+        let grid_pos = buf.len();
+        put_u32(buf, 0); // compression
+
+        let mut metadata = vec![("class", Meta::String(grid.class)), ("name", Meta::String(&grid.name))];
+        if let Some(vector_type) = grid.vector_type {
+            metadata.push(("vector_type", Meta::String(vector_type)));
+        }
+        metadata.push(("is_local_space", Meta::Bool(false)));
+        put_u32(buf, metadata.len() as u32);
+        for &(name, ref value) in &metadata {
+            put_string(buf, name);
+            match *value {
+                Meta::String(s) => {
+                    put_string(buf, "string");
+                    put_string(buf, s);
+                }
+                Meta::Bool(b) => {
+                    put_string(buf, "bool");
+                    put_u32(buf, 1);
+                    buf.push(b as u8);
+                }
+            }
+        }
+
+        // transform
+        let size = self.voxel_size;
+        let half = 0.5 * size;
+        let (oz, oy, ox) = self.origin;
+        put_string(buf, "UniformScaleTranslateMap");
+        for v in &[
+            [ox + half, oy + half, oz + half], // translation
+            [size; 3], // scale
+            [size; 3], // voxel size
+            [1.0 / size; 3], // inverse scale
+            [1.0 / (size * size); 3], // inverse squared scale
+            [0.5 / size; 3], // inverse twice scale
+        ] {
+            for &c in v {
+                put_f64(buf, c);
+            }
+        }
+
+        // level sets are stored in world units
+        let scale = if grid.class == CLASS_LEVEL_SET { size as f32 } else { 1.0 };
+        let background = grid.background.iter().map(|&v| v * scale).collect::<Vec<_>>();
+        let zero = vec![0.0; grid.components];
+
+        // group regions into internal nodes, keys are the child offsets
+        let mut root = BTreeMap::new();
+        for (origin, region) in &grid.regions {
+            let node_2 = node_origin(origin, INTERNAL_2_TOTAL);
+            let slot_2 = child_offset(origin, LOG2_INTERNAL_2, INTERNAL_1_TOTAL);
+            let slot_1 = child_offset(origin, LOG2_INTERNAL_1, LOG2_LEAF);
+            root.entry(node_2).or_insert_with(BTreeMap::new)
+                .entry(slot_2).or_insert_with(BTreeMap::new)
+                .insert(slot_1, region);
+        }
+
+        // topology
+        put_u32(buf, 1); // buffer count
+        put_f32s(buf, &background, 1.0);
+        put_u32(buf, 0); // root tiles
+        put_u32(buf, root.len() as u32);
+        for (origin, children) in &root {
+            for &c in origin {
+                put_u32(buf, c as u32);
+            }
+
+            let mut values = Vec::with_capacity(grid.components << (3 * LOG2_INTERNAL_2));
+            for slot in 0..1 << (3 * LOG2_INTERNAL_2) {
+                values.extend_from_slice(if children.contains_key(&slot) { &zero } else { &background });
+            }
+            put_mask(buf, children.keys().cloned(), LOG2_INTERNAL_2);
+            put_mask(buf, None, LOG2_INTERNAL_2);
+            buf.push(NO_MASK_AND_ALL_VALS);
+            put_f32s(buf, &values, 1.0);
+
+            for regions in children.values() {
+                let leaves = regions.iter()
+                    .filter(|&(_, r)| match **r { Region::Leaf { .. } => true, _ => false })
+                    .map(|(&slot, _)| slot);
+                let mut values = Vec::with_capacity(grid.components << (3 * LOG2_INTERNAL_1));
+                for slot in 0..1 << (3 * LOG2_INTERNAL_1) {
+                    match regions.get(&slot) {
+                        Some(&&Region::Tile(ref tile)) => values.extend(tile.iter().map(|&v| v * scale)),
+                        Some(&&Region::Leaf { .. }) => values.extend_from_slice(&zero),
+                        None => values.extend_from_slice(&background),
+                    }
+                }
+                put_mask(buf, leaves, LOG2_INTERNAL_1);
+                put_mask(buf, None, LOG2_INTERNAL_1);
+                buf.push(NO_MASK_AND_ALL_VALS);
+                put_f32s(buf, &values, 1.0);
+
+                for region in regions.values() {
+                    if let Region::Leaf { ref mask, .. } = **region {
+                        put_u64s(buf, mask);
+                    }
+                }
+            }
+        }
+
+        // leaf buffers in the same order as the topology
+        let block_pos = buf.len();
+        for children in root.values() {
+            for regions in children.values() {
+                for region in regions.values() {
+                    if let Region::Leaf { ref mask, ref values } = **region {
+                        put_u64s(buf, mask);
+                        buf.push(NO_MASK_AND_ALL_VALS);
+                        put_f32s(buf, values, scale);
+                    }
+                }
+            }
+        }
+        let end_pos = buf.len();
+
+        let mut pos = Vec::with_capacity(24);
+        for &p in &[grid_pos, block_pos, end_pos] {
+            put_i64(&mut pos, p as i64);
+        }
+        buf[positions..positions+24].copy_from_slice(&pos);
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)
+    }
+}
+
+enum Meta<'a> {
+    String(&'a str),
+    Bool(bool),
+}
+
+/// Origin of the node of size `2^total` containing the leaf at `origin`.
+fn node_origin(origin: &[i32; 3], total: usize) -> [i32; 3] {
+    let mask = !((1 << total) - 1);
+    [origin[0] & mask, origin[1] & mask, origin[2] & mask]
+}
+
+/// Offset of the child containing `origin` in its parent node, `x` slowest.
+fn child_offset(origin: &[i32; 3], log2_dim: usize, child_total: usize) -> usize {
+    let dim = (1 << log2_dim) - 1;
+    let axis = |c: i32| (c as usize >> child_total) & dim;
+    (axis(origin[0]) << (2 * log2_dim)) + (axis(origin[1]) << log2_dim) + axis(origin[2])
+}
+
+/// Random UUID (version 4) identifying the file.
+fn uuid() -> String {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() ^ (time.subsec_nanos() as u64) << 32)
+        .unwrap_or(0);
+    let mut rng = Pcg32::new(seed);
+    let (a, b, c, d) = (rng.next_u32(), rng.next_u32(), rng.next_u32(), rng.next_u32());
+    format!("{:08x}-{:04x}-4{:03x}-{:04x}-{:04x}{:08x}",
+        a, b >> 16, b & 0xfff, 0x8000 | (c >> 16) & 0x3fff, c & 0xffff, d)
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    for i in 0..4 {
+        buf.push((v >> (8 * i)) as u8);
+    }
+}
+
+fn put_u64(buf: &mut Vec<u8>, v: u64) {
+    for i in 0..8 {
+        buf.push((v >> (8 * i)) as u8);
+    }
+}
+
+fn put_u64s(buf: &mut Vec<u8>, values: &[u64]) {
+    for &v in values {
+        put_u64(buf, v);
+    }
+}
+
+fn put_i64(buf: &mut Vec<u8>, v: i64) {
+    put_u64(buf, v as u64);
+}
+
+fn put_f64(buf: &mut Vec<u8>, v: f64) {
+    put_u64(buf, v.to_bits());
+}
+
+fn put_f32s(buf: &mut Vec<u8>, values: &[f32], scale: f32) {
+    for &v in values {
+        put_u32(buf, (v * scale).to_bits());
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &str) {
+    put_u32(buf, s.len() as u32);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Node mask with the given bits set.
+fn put_mask<I: IntoIterator<Item = usize>>(buf: &mut Vec<u8>, bits: I, log2_dim: usize) {
+    let mut words = vec![0u64; (1 << (3 * log2_dim)) / 64];
+    for bit in bits {
+        words[bit / 64] |= 1 << (bit % 64);
+    }
+    put_u64s(buf, &words);
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array3;
+    use super::*;
+
+    fn read_u32(data: &[u8], pos: usize) -> u32 {
+        (0..4).fold(0, |v, i| v | (data[pos + i] as u32) << (8 * i))
+    }
+
+    fn read_u64(data: &[u8], pos: usize) -> u64 {
+        (0..8).fold(0, |v, i| v | (data[pos + i] as u64) << (8 * i))
+    }
+
+    #[test]
+    fn vdb_export() {
+        // small plume and a sphere
+        let dim = (40, 24, 24);
+        let density = Array3::from_shape_fn(dim, |(z, y, x)| if z < 8 && y < 4 && x < 4 { 1.0 } else { 0.0 });
+        let phi = Array3::from_shape_fn(dim, |(z, y, x)| {
+            ((z as f64 - 20.0).powi(2) + (y as f64 - 12.0).powi(2) + (x as f64 - 12.0).powi(2)).sqrt() - 10.0
+        });
+        let mut velocity = Staggered3d::faces(dim, 0.0f32);
+        velocity.split_mut().2[(1, 1, 8)] = 1.0;
+
+        let file = VdbFile::new()
+            .with_voxel_size(0.1)
+            .fog_volume("density", density.view(), 0.0)
+            .level_set("surface", &phi, 3.0)
+            .staggered_3d("velocity", &velocity);
+
+        let mut data = Vec::new();
+        file.write(&mut data).unwrap();
+        assert_eq!(read_u64(&data, 0), MAGIC as u64);
+        assert_eq!(read_u32(&data, 8), FILE_VERSION);
+        assert_eq!(data[20], 1);
+        assert_eq!(data[21 + 8], b'-');
+
+        // grids are stored back to back with consistent stream positions
+        let mut pos = 21 + 36;
+        assert_eq!(read_u32(&data, pos), 0);
+        assert_eq!(read_u32(&data, pos + 4), 3);
+        pos += 8;
+        let leaf_size = |components: usize| 64 + 1 + 4 * components * LEAF_LEN;
+        for &(name, ty, components, leaves) in &[
+            ("density", "Tree_float_5_4_3", 1, 1),
+            ("surface", "Tree_float_5_4_3", 1, file.grids[1].num_leaves()),
+            ("velocity", "Tree_vec3s_5_4_3", 3, 2),
+        ] {
+            let mut strings = Vec::new();
+            for _ in 0..3 {
+                let len = read_u32(&data, pos) as usize;
+                strings.push(String::from_utf8(data[pos+4..pos+4+len].to_vec()).unwrap());
+                pos += 4 + len;
+            }
+            assert_eq!(strings, [name, ty, ""]);
+            let (grid_pos, block_pos, end_pos) = (read_u64(&data, pos), read_u64(&data, pos + 8), read_u64(&data, pos + 16));
+            assert_eq!(grid_pos as usize, pos + 24);
+            assert_eq!((end_pos - block_pos) as usize, leaves * leaf_size(components));
+            pos = end_pos as usize;
+        }
+        assert_eq!(pos, data.len());
+
+        // the narrow band covers a shell around the sphere, deep inside is a tile
+        let surface = &file.grids[1];
+        assert!(surface.num_leaves() < surface.regions.len());
+        match surface.regions[&[8, 8, 16]] {
+            Region::Tile(ref value) => assert_eq!(value, &[-3.0]),
+            _ => panic!("expected a tile inside the sphere"),
+        }
+    }
+}
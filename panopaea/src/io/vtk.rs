@@ -0,0 +1,547 @@
+//! VTK file export
+//!
+//! Grid fields are exported as image data (`.vti`), particles, polylines and
+//! triangle meshes as poly data (`.vtp`) or unstructured grids (`.vtu`). Both can also
+//! be written in the legacy format (`.vtk`). All files are written as ASCII.
+//!
+//! Grid axes are ordered `(z, y, x)` as everywhere else in the crate, VTK
+//! stores `x` fastest which matches the row-major memory order.
+//!
+//! References:
+//!     [VTK] Kitware, File Formats for VTK Version 4.2,
+//!           https://www.vtk.org/wp-content/uploads/2015/04/file-formats.pdf
+
+use dec::grid::{Staggered2d, Staggered3d};
+use domain::{Grid2d, Grid3d, TriangleMesh};
+use math::{Dim, Real, VectorN};
+use ndarray::{ArrayBase, Data, Dimension};
+use particle::Particles;
+use sph::property::Position;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Named data array with interleaved components.
+struct DataArray {
+    name: String,
+    components: usize,
+    values: Vec<f64>,
+}
+
+impl DataArray {
+    fn scalars<T, I>(name: &str, values: I) -> Self
+        where T: Real,
+              I: IntoIterator<Item = T>,
+    {
+        DataArray {
+            name: name.to_string(),
+            components: 1,
+            values: values.into_iter().map(|v| v.to_f64().unwrap()).collect(),
+        }
+    }
+
+    /// Vectors padded to 3 components.
+    fn vectors<T, N>(name: &str, values: &[VectorN<T, N>]) -> Self
+        where T: Real,
+              N: Dim<T>,
+    {
+        let mut data = Vec::with_capacity(3 * values.len());
+        for v in values {
+            data.extend((0..3).map(|i| if i < v.len() { v[i].to_f64().unwrap() } else { 0.0 }));
+        }
+        DataArray { name: name.to_string(), components: 3, values: data }
+    }
+
+    fn write_xml<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        xml_array(w, Some(&self.name), "Float64", self.components, &self.values)
+    }
+
+    fn write_legacy<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match self.components {
+            1 => writeln!(w, "SCALARS {} double 1\nLOOKUP_TABLE default", self.name)?,
+            3 => writeln!(w, "VECTORS {} double", self.name)?,
+            n => writeln!(w, "FIELD FieldData 1\n{} {} {} double", self.name, n, self.values.len() / n)?,
+        }
+        write_values(w, self.components, &self.values)
+    }
+}
+
+fn write_values<W: Write, V: Display>(w: &mut W, components: usize, values: &[V]) -> io::Result<()> {
+    for chunk in values.chunks(components.max(1)) {
+        for (i, v) in chunk.iter().enumerate() {
+            if i > 0 {
+                write!(w, " ")?;
+            }
+            write!(w, "{}", v)?;
+        }
+        writeln!(w, "")?;
+    }
+    Ok(())
+}
+
+fn xml_array<W: Write, V: Display>(w: &mut W, name: Option<&str>, ty: &str, components: usize, values: &[V]) -> io::Result<()> {
+    write!(w, "<DataArray type=\"{}\"", ty)?;
+    if let Some(name) = name {
+        write!(w, " Name=\"{}\"", name)?;
+    }
+    writeln!(w, " NumberOfComponents=\"{}\" format=\"ascii\">", components)?;
+    write_values(w, components, values)?;
+    writeln!(w, "</DataArray>")
+}
+
+fn xml_header<W: Write>(w: &mut W, ty: &str) -> io::Result<()> {
+    writeln!(w, "<?xml version=\"1.0\"?>")?;
+    writeln!(w, "<VTKFile type=\"{}\" version=\"0.1\" byte_order=\"LittleEndian\">", ty)
+}
+
+fn legacy_header<W: Write>(w: &mut W, dataset: &str) -> io::Result<()> {
+    writeln!(w, "# vtk DataFile Version 3.0")?;
+    writeln!(w, "panopaea")?;
+    writeln!(w, "ASCII")?;
+    writeln!(w, "DATASET {}", dataset)
+}
+
+fn create<P: AsRef<Path>>(path: P) -> io::Result<BufWriter<File>> {
+    File::create(path).map(BufWriter::new)
+}
+
+fn extension<P: AsRef<Path>>(path: &P) -> Option<String> {
+    path.as_ref().extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase())
+}
+
+/// Uniform grid with data on the vertices (points) and cells.
+pub struct ImageData {
+    /// Number of cells `(z, y, x)`, 2d grids have a single layer of points.
+    cells: (usize, usize, usize),
+    /// Cell spacing `(dz, dy, dx)`.
+    spacing: (f64, f64, f64),
+    origin: (f64, f64, f64),
+    is_2d: bool,
+    point_data: Vec<DataArray>,
+    cell_data: Vec<DataArray>,
+}
+
+impl ImageData {
+    pub fn from_grid_2d(grid: &Grid2d) -> Self {
+        let (h, w) = grid.dim();
+        let (dy, dx) = grid.spacing();
+        ImageData {
+            cells: (0, h, w),
+            spacing: (1.0, dy, dx),
+            origin: (0.0, 0.0, 0.0),
+            is_2d: true,
+            point_data: Vec::new(),
+            cell_data: Vec::new(),
+        }
+    }
+
+    /// 3d grids have unit spacing.
+    pub fn from_grid_3d(grid: &Grid3d) -> Self {
+        ImageData {
+            cells: grid.dim(),
+            spacing: (1.0, 1.0, 1.0),
+            origin: (0.0, 0.0, 0.0),
+            is_2d: false,
+            point_data: Vec::new(),
+            cell_data: Vec::new(),
+        }
+    }
+
+    /// Position of the first grid vertex `(z, y, x)`.
+    pub fn with_origin(self, origin: (f64, f64, f64)) -> Self {
+        ImageData { origin: origin, ..self }
+    }
+
+    fn point_shape(&self) -> Vec<usize> {
+        let (d, h, w) = self.cells;
+        if self.is_2d { vec![h + 1, w + 1] } else { vec![d + 1, h + 1, w + 1] }
+    }
+
+    fn cell_shape(&self) -> Vec<usize> {
+        let (d, h, w) = self.cells;
+        if self.is_2d { vec![h, w] } else { vec![d, h, w] }
+    }
+
+    /// Add a scalar field sampled at the grid vertices.
+    pub fn point_scalars<T, S, D>(mut self, name: &str, field: &ArrayBase<S, D>) -> Self
+        where T: Real,
+              S: Data<Elem = T>,
+              D: Dimension,
+    {
+        assert_eq!(field.shape(), &self.point_shape()[..]);
+        self.point_data.push(DataArray::scalars(name, field.iter().cloned()));
+        self
+    }
+
+    /// Add a scalar field sampled at the cell centers.
+    pub fn cell_scalars<T, S, D>(mut self, name: &str, field: &ArrayBase<S, D>) -> Self
+        where T: Real,
+              S: Data<Elem = T>,
+              D: Dimension,
+    {
+        assert_eq!(field.shape(), &self.cell_shape()[..]);
+        self.cell_data.push(DataArray::scalars(name, field.iter().cloned()));
+        self
+    }
+
+    /// Add a velocity field stored as dual 1-form, averaged to the cell centers.
+    pub fn staggered_2d<T: Real>(mut self, name: &str, velocity: &Staggered2d<T>) -> Self {
+        assert!(self.is_2d);
+        let (_, h, w) = self.cells;
+        assert_eq!(velocity.dim(), (h, w));
+
+        let (_, dy, dx) = self.spacing;
+        let (vertical, horizontal) = velocity.split();
+        let mut values = Vec::with_capacity(3 * h * w);
+        for y in 0..h {
+            for x in 0..w {
+                let vy = (vertical[(y, x)] + vertical[(y+1, x)]).to_f64().unwrap() / (2.0 * dy);
+                let vx = (horizontal[(y, x)] + horizontal[(y, x+1)]).to_f64().unwrap() / (2.0 * dx);
+                values.extend_from_slice(&[vx, vy, 0.0]);
+            }
+        }
+        self.cell_data.push(DataArray { name: name.to_string(), components: 3, values });
+        self
+    }
+
+    /// Add a velocity field stored as face fluxes, averaged to the cell centers.
+    pub fn staggered_3d<T: Real>(mut self, name: &str, velocity: &Staggered3d<T>) -> Self {
+        assert!(!self.is_2d);
+        let (d, h, w) = self.cells;
+        assert_eq!(velocity.dim(), (d, h, w));
+        assert_eq!(velocity.shape()[0], (d+1, h, w), "expected face storage");
+
+        let (vz, vy, vx) = velocity.split();
+        let mut values = Vec::with_capacity(3 * d * h * w);
+        for z in 0..d {
+            for y in 0..h {
+                for x in 0..w {
+                    let half = T::new(0.5);
+                    values.extend_from_slice(&[
+                        ((vx[(z, y, x)] + vx[(z, y, x+1)]) * half).to_f64().unwrap(),
+                        ((vy[(z, y, x)] + vy[(z, y+1, x)]) * half).to_f64().unwrap(),
+                        ((vz[(z, y, x)] + vz[(z+1, y, x)]) * half).to_f64().unwrap(),
+                    ]);
+                }
+            }
+        }
+        self.cell_data.push(DataArray { name: name.to_string(), components: 3, values });
+        self
+    }
+
+    /// Write as XML image data (`.vti`).
+    pub fn write_xml<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (d, h, w_) = self.cells;
+        let extent = format!("0 {} 0 {} 0 {}", w_, h, d);
+        xml_header(w, "ImageData")?;
+        writeln!(w, "<ImageData WholeExtent=\"{}\" Origin=\"{} {} {}\" Spacing=\"{} {} {}\">",
+            extent, self.origin.2, self.origin.1, self.origin.0, self.spacing.2, self.spacing.1, self.spacing.0)?;
+        writeln!(w, "<Piece Extent=\"{}\">", extent)?;
+        writeln!(w, "<PointData>")?;
+        for data in &self.point_data {
+            data.write_xml(w)?;
+        }
+        writeln!(w, "</PointData>")?;
+        writeln!(w, "<CellData>")?;
+        for data in &self.cell_data {
+            data.write_xml(w)?;
+        }
+        writeln!(w, "</CellData>")?;
+        writeln!(w, "</Piece>\n</ImageData>\n</VTKFile>")
+    }
+
+    /// Write as legacy structured points (`.vtk`).
+    pub fn write_legacy<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (d, h, w_) = self.cells;
+        legacy_header(w, "STRUCTURED_POINTS")?;
+        writeln!(w, "DIMENSIONS {} {} {}", w_ + 1, h + 1, d + 1)?;
+        writeln!(w, "ORIGIN {} {} {}", self.origin.2, self.origin.1, self.origin.0)?;
+        writeln!(w, "SPACING {} {} {}", self.spacing.2, self.spacing.1, self.spacing.0)?;
+        if !self.point_data.is_empty() {
+            writeln!(w, "POINT_DATA {}", self.point_shape().iter().product::<usize>())?;
+            for data in &self.point_data {
+                data.write_legacy(w)?;
+            }
+        }
+        if !self.cell_data.is_empty() {
+            writeln!(w, "CELL_DATA {}", self.cell_shape().iter().product::<usize>())?;
+            for data in &self.cell_data {
+                data.write_legacy(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Save to a file, `.vtk` files are written in the legacy format.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let legacy = extension(&path).map_or(false, |ext| ext == "vtk");
+        let mut file = create(path)?;
+        if legacy { self.write_legacy(&mut file) } else { self.write_xml(&mut file) }
+    }
+}
+
+/// Points with vertex and triangle cells.
+pub struct PolyData {
+    points: Vec<f64>,
+    vertices: Vec<usize>,
+    lines: Vec<Vec<usize>>,
+    triangles: Vec<[usize; 3]>,
+    point_data: Vec<DataArray>,
+}
+
+impl PolyData {
+    /// Particle positions as vertex cells.
+    pub fn particles<T, N>(particles: &Particles) -> Self
+        where T: Real + 'static,
+              N: Dim<T> + 'static,
+    {
+        let positions = particles.read_property::<Position<T, N>>();
+        PolyData {
+            points: DataArray::vectors("", positions).values,
+            vertices: (0..positions.len()).collect(),
+            lines: Vec::new(),
+            triangles: Vec::new(),
+            point_data: Vec::new(),
+        }
+    }
+
+    /// Triangle mesh surface.
+    pub fn mesh<T: Real>(mesh: &TriangleMesh<T>) -> Self {
+        let mut points = Vec::with_capacity(3 * mesh.num_vertices());
+        for p in mesh.positions() {
+            points.extend_from_slice(&[p.x.to_f64().unwrap(), p.y.to_f64().unwrap(), p.z.to_f64().unwrap()]);
+        }
+        PolyData {
+            points,
+            vertices: Vec::new(),
+            lines: Vec::new(),
+            triangles: mesh.faces().to_vec(),
+            point_data: Vec::new(),
+        }
+    }
+
+    /// Polylines through the given points, e.g. streamlines or particle trails.
+    ///
+    /// Points are numbered consecutively over all lines.
+    pub fn polylines<T, N>(lines: &[Vec<VectorN<T, N>>]) -> Self
+        where T: Real,
+              N: Dim<T>,
+    {
+        let mut points = Vec::new();
+        let mut connectivity = Vec::with_capacity(lines.len());
+        for line in lines {
+            let start = points.len() / 3;
+            points.extend(DataArray::vectors("", line).values);
+            connectivity.push((start..start + line.len()).collect());
+        }
+        PolyData {
+            points,
+            vertices: Vec::new(),
+            lines: connectivity,
+            triangles: Vec::new(),
+            point_data: Vec::new(),
+        }
+    }
+
+    pub fn num_points(&self) -> usize {
+        self.points.len() / 3
+    }
+
+    /// Add scalar values per point, e.g. particle densities.
+    pub fn point_scalars<T: Real>(mut self, name: &str, values: &[T]) -> Self {
+        assert_eq!(values.len(), self.num_points());
+        self.point_data.push(DataArray::scalars(name, values.iter().cloned()));
+        self
+    }
+
+    /// Add vectors per point, e.g. particle velocities.
+    pub fn point_vectors<T: Real, N: Dim<T>>(mut self, name: &str, values: &[VectorN<T, N>]) -> Self {
+        assert_eq!(values.len(), self.num_points());
+        self.point_data.push(DataArray::vectors(name, values));
+        self
+    }
+
+    fn write_point_data<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "<PointData>")?;
+        for data in &self.point_data {
+            data.write_xml(w)?;
+        }
+        writeln!(w, "</PointData>")?;
+        writeln!(w, "<Points>")?;
+        xml_array(w, None, "Float64", 3, &self.points)?;
+        writeln!(w, "</Points>")
+    }
+
+    fn write_cells<W: Write>(w: &mut W, connectivity: &[usize], offsets: &[usize]) -> io::Result<()> {
+        xml_array(w, Some("connectivity"), "Int64", 1, connectivity)?;
+        xml_array(w, Some("offsets"), "Int64", 1, offsets)
+    }
+
+    /// Write as XML poly data (`.vtp`).
+    pub fn write_xml<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        xml_header(w, "PolyData")?;
+        writeln!(w, "<PolyData>")?;
+        writeln!(w, "<Piece NumberOfPoints=\"{}\" NumberOfVerts=\"{}\" NumberOfLines=\"{}\" NumberOfStrips=\"0\" NumberOfPolys=\"{}\">",
+            self.num_points(), self.vertices.len(), self.lines.len(), self.triangles.len())?;
+        self.write_point_data(w)?;
+
+        writeln!(w, "<Verts>")?;
+        let offsets = (1..self.vertices.len()+1).collect::<Vec<_>>();
+        Self::write_cells(w, &self.vertices, &offsets)?;
+        writeln!(w, "</Verts>")?;
+
+        writeln!(w, "<Lines>")?;
+        let connectivity = self.lines.iter().flat_map(|l| l.iter().cloned()).collect::<Vec<_>>();
+        let offsets = self.lines.iter().scan(0, |end, l| { *end += l.len(); Some(*end) }).collect::<Vec<_>>();
+        Self::write_cells(w, &connectivity, &offsets)?;
+        writeln!(w, "</Lines>")?;
+
+        writeln!(w, "<Polys>")?;
+        let connectivity = self.triangles.iter().flat_map(|t| t.iter().cloned()).collect::<Vec<_>>();
+        let offsets = (1..self.triangles.len()+1).map(|i| 3 * i).collect::<Vec<_>>();
+        Self::write_cells(w, &connectivity, &offsets)?;
+        writeln!(w, "</Polys>")?;
+
+        writeln!(w, "</Piece>\n</PolyData>\n</VTKFile>")
+    }
+
+    /// Write as XML unstructured grid (`.vtu`).
+    pub fn write_unstructured<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        const VTK_VERTEX: u8 = 1;
+        const VTK_POLY_LINE: u8 = 4;
+        const VTK_TRIANGLE: u8 = 5;
+
+        xml_header(w, "UnstructuredGrid")?;
+        writeln!(w, "<UnstructuredGrid>")?;
+        writeln!(w, "<Piece NumberOfPoints=\"{}\" NumberOfCells=\"{}\">",
+            self.num_points(), self.vertices.len() + self.lines.len() + self.triangles.len())?;
+        self.write_point_data(w)?;
+
+        let mut connectivity = self.vertices.clone();
+        let mut offsets = (1..self.vertices.len()+1).collect::<Vec<_>>();
+        let mut types = vec![VTK_VERTEX; self.vertices.len()];
+        for line in &self.lines {
+            connectivity.extend(line.iter().cloned());
+            offsets.push(connectivity.len());
+            types.push(VTK_POLY_LINE);
+        }
+        for t in &self.triangles {
+            connectivity.extend(t.iter().cloned());
+            offsets.push(connectivity.len());
+            types.push(VTK_TRIANGLE);
+        }
+
+        writeln!(w, "<Cells>")?;
+        Self::write_cells(w, &connectivity, &offsets)?;
+        xml_array(w, Some("types"), "UInt8", 1, &types)?;
+        writeln!(w, "</Cells>")?;
+
+        writeln!(w, "</Piece>\n</UnstructuredGrid>\n</VTKFile>")
+    }
+
+    /// Write as legacy poly data (`.vtk`).
+    pub fn write_legacy<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        legacy_header(w, "POLYDATA")?;
+        writeln!(w, "POINTS {} double", self.num_points())?;
+        write_values(w, 3, &self.points)?;
+        if !self.vertices.is_empty() {
+            writeln!(w, "VERTICES {} {}", self.vertices.len(), 2 * self.vertices.len())?;
+            for v in &self.vertices {
+                writeln!(w, "1 {}", v)?;
+            }
+        }
+        if !self.lines.is_empty() {
+            let size = self.lines.iter().map(|l| l.len() + 1).sum::<usize>();
+            writeln!(w, "LINES {} {}", self.lines.len(), size)?;
+            for line in &self.lines {
+                write!(w, "{}", line.len())?;
+                for v in line {
+                    write!(w, " {}", v)?;
+                }
+                writeln!(w, "")?;
+            }
+        }
+        if !self.triangles.is_empty() {
+            writeln!(w, "POLYGONS {} {}", self.triangles.len(), 4 * self.triangles.len())?;
+            for t in &self.triangles {
+                writeln!(w, "3 {} {} {}", t[0], t[1], t[2])?;
+            }
+        }
+        if !self.point_data.is_empty() {
+            writeln!(w, "POINT_DATA {}", self.num_points())?;
+            for data in &self.point_data {
+                data.write_legacy(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Save to a file, the format is chosen by the extension (`.vtp`, `.vtu` or legacy `.vtk`).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let ext = extension(&path);
+        let mut file = create(path)?;
+        match ext.as_ref().map(|ext| ext.as_str()) {
+            Some("vtk") => self.write_legacy(&mut file),
+            Some("vtu") => self.write_unstructured(&mut file),
+            _ => self.write_xml(&mut file),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::Manifold2d;
+    use math::vector_n::vec2;
+    use ndarray::Array2;
+    use sph::property::Velocity;
+    use sph::wcsph;
+    use typenum::U2;
+    use super::*;
+
+    #[test]
+    fn vtk_export() {
+        let grid = Grid2d::new((2, 3)).with_spacing((0.5, 0.5));
+        let phi = Array2::from_shape_fn((3, 4), |(y, x)| (y * 4 + x) as f64);
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().1.fill(1.0);
+        let image = ImageData::from_grid_2d(&grid)
+            .point_scalars("phi", &phi)
+            .staggered_2d("velocity", &velocity);
+
+        let mut xml = Vec::new();
+        image.write_xml(&mut xml).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains("WholeExtent=\"0 3 0 2 0 0\""));
+        assert!(xml.contains("Spacing=\"0.5 0.5 1\""));
+        assert!(xml.contains("\n11\n</DataArray>"));
+        assert!(xml.contains("\n2 0 0\n"));
+
+        let mut legacy = Vec::new();
+        image.write_legacy(&mut legacy).unwrap();
+        let legacy = String::from_utf8(legacy).unwrap();
+        assert!(legacy.contains("DIMENSIONS 4 3 1\n"));
+        assert!(legacy.contains("POINT_DATA 12\nSCALARS phi double 1\n"));
+        assert!(legacy.contains("CELL_DATA 6\nVECTORS velocity double\n"));
+
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+        particles.add_particles(2)
+                 .with::<Position<f64, U2>>(&[vec2(1.0, 2.0), vec2(3.0, 4.0)])
+                 .with::<Velocity<f64, U2>>(&[vec2(0.5, 0.0), vec2(0.0, 0.5)]);
+        let poly = PolyData::particles::<f64, U2>(&particles)
+            .point_vectors("velocity", particles.read_property::<Velocity<f64, U2>>());
+
+        let mut vtp = Vec::new();
+        poly.write_xml(&mut vtp).unwrap();
+        let vtp = String::from_utf8(vtp).unwrap();
+        assert!(vtp.contains("NumberOfPoints=\"2\" NumberOfVerts=\"2\""));
+        assert!(vtp.contains("\n1 2 0\n3 4 0\n"));
+
+        let mut vtu = Vec::new();
+        poly.write_unstructured(&mut vtu).unwrap();
+        let vtu = String::from_utf8(vtu).unwrap();
+        assert!(vtu.contains("NumberOfCells=\"2\""));
+        assert!(vtu.contains("Name=\"types\" NumberOfComponents=\"1\" format=\"ascii\">\n1\n1\n"));
+    }
+}
@@ -0,0 +1,592 @@
+//! Level set representation of interfaces
+//!
+//! The signed distance is stored at the cell centers of a grid, negative
+//! values are inside. Distances are measured in grid units.
+//!
+//! Surface tension of liquids is derived from the curvature of the level set,
+//! either as pressure jump across the free surface for the ghost fluid
+//! projection [KFL00] or smeared out over a few cells as continuum surface
+//! force [BKZ92].
+//!
+//! References:
+//!     [Zha05] Hongkai Zhao, 2005,
+//!             A fast sweeping method for eikonal equations,
+//!             Mathematics of Computation 74, 250 (2005), 603-627
+//!     [KFL00] Myungjoo Kang, Ronald Fedkiw, and Xu-Dong Liu, 2000,
+//!             A boundary condition capturing method for multiphase incompressible flow,
+//!             Journal of Scientific Computing 15 (3), 323-360
+//!     [BKZ92] Jeremiah U. Brackbill, Douglas B. Kothe, and Charles Zemach, 1992,
+//!             A continuum method for modeling surface tension,
+//!             Journal of Computational Physics 100 (2), 335-354
+
+use advection::{self, Scheme};
+use dec::grid::Staggered2d;
+use dec::manifold::Manifold2d;
+use domain::{Grid2d, Grid3d};
+use math::{self, Real};
+use ndarray::{Array2, Array3};
+use std::cmp;
+
+/// Signed distance field on the faces of a 2d grid.
+#[derive(Clone, Debug)]
+pub struct LevelSet2d<T> {
+    pub phi: Array2<T>,
+}
+
+/// Signed distance field on the cells of a 3d grid.
+#[derive(Clone, Debug)]
+pub struct LevelSet3d<T> {
+    pub phi: Array3<T>,
+}
+
+/// Index of the neighbor in direction `dir`, clamped to the grid.
+fn offset(i: usize, dir: isize, len: usize) -> usize {
+    cmp::min(cmp::max(i as isize + dir, 0) as usize, len - 1)
+}
+
+/// Fraction of the grid spacing from the cell to the interface along one axis, if crossed.
+fn crossing<T: Real>(phi: T, neighbors: &[T]) -> Option<T> {
+    neighbors.iter()
+        .filter(|&&n| phi * n < T::zero())
+        .map(|&n| phi / (phi - n))
+        .fold(None, |min, theta| Some(min.map_or(theta, |min: T| min.min(theta))))
+}
+
+/// Initial distance of cells next to the interface, `None` for cells away from it.
+fn interface_distance<T: Real>(phi: T, axes: &[[T; 2]]) -> Option<T> {
+    if phi == T::zero() {
+        return Some(T::zero());
+    }
+
+    let inv_sq = axes.iter()
+        .filter_map(|neighbors| crossing(phi, neighbors))
+        .fold(None, |sum, theta| Some(sum.unwrap_or(T::zero()) + T::one() / (theta * theta)));
+    inv_sq.map(|inv_sq| T::one() / inv_sq.sqrt())
+}
+
+/// Godunov upwind solution of `|grad d| = 1` given the smallest neighbor distance per axis.
+fn solve_eikonal<T: Real>(neighbors: &mut [T]) -> T {
+    neighbors.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut d = neighbors[0] + T::one();
+    for n in 2..(neighbors.len() + 1) {
+        if d <= neighbors[n - 1] {
+            break;
+        }
+        // solve sum_i (d - a_i)^2 = 1 for the n smallest values
+        let count = T::new(n);
+        let sum = neighbors[..n].iter().fold(T::zero(), |sum, &a| sum + a);
+        let sum_sq = neighbors[..n].iter().fold(T::zero(), |sum, &a| sum + a * a);
+        let discriminant = sum * sum - count * (sum_sq - T::one());
+        d = (sum + discriminant.max(T::zero()).sqrt()) / count;
+    }
+    d
+}
+
+/// Mean curvature from gradient `g` and hessian `hess`: `div(grad phi / |grad phi|)`.
+fn curvature<T: Real>(g: &[T], hess: &[&[T]]) -> T {
+    let norm_sq = g.iter().fold(T::zero(), |sum, &g| sum + g * g);
+    if norm_sq <= T::zero() {
+        return T::zero();
+    }
+
+    let trace = (0..g.len()).fold(T::zero(), |sum, i| sum + hess[i][i]);
+    let mut quadratic = T::zero();
+    for i in 0..g.len() {
+        for j in 0..g.len() {
+            quadratic = quadratic + g[i] * hess[i][j] * g[j];
+        }
+    }
+    (norm_sq * trace - quadratic) / (norm_sq * norm_sq.sqrt())
+}
+
+fn normalize<T: Real>(g: &mut [T]) {
+    let norm = g.iter().fold(T::zero(), |sum, &g| sum + g * g).sqrt();
+    if norm > T::zero() {
+        for g in g.iter_mut() {
+            *g = *g / norm;
+        }
+    }
+}
+
+impl<T: Real> LevelSet2d<T> {
+    /// Evaluate an implicit function at the face centers `(y, x)`.
+    pub fn from_fn<F>(grid: &Grid2d, func: F) -> Self
+        where F: Fn((T, T)) -> T
+    {
+        let half = T::new(0.5);
+        LevelSet2d {
+            phi: Array2::from_shape_fn(grid.dim(), |(y, x)| func((T::new(y) + half, T::new(x) + half))),
+        }
+    }
+
+    pub fn dim(&self) -> (usize, usize) {
+        self.phi.dim()
+    }
+
+    /// Interpolated value at an arbitrary position, wrapping around periodic sides of the grid.
+    pub fn sample(&self, grid: &Grid2d, pos: (T, T)) -> T {
+        assert_eq!(grid.dim(), self.dim(), "grid dimension mismatch");
+        advection::sample(grid, self.phi.view(), advection::offset_center(), pos)
+    }
+
+    pub fn is_inside(&self, idx: (usize, usize)) -> bool {
+        self.phi[idx] < T::zero()
+    }
+
+    fn at(&self, (y, x): (usize, usize), (dy, dx): (isize, isize)) -> T {
+        let (h, w) = self.dim();
+        self.phi[(offset(y, dy, h), offset(x, dx, w))]
+    }
+
+    /// Central difference gradient `(y, x)`.
+    pub fn gradient(&self, idx: (usize, usize)) -> (T, T) {
+        let half = T::new(0.5);
+        (half * (self.at(idx, (1, 0)) - self.at(idx, (-1, 0))),
+         half * (self.at(idx, (0, 1)) - self.at(idx, (0, -1))))
+    }
+
+    /// Interface normal `(y, x)` pointing outwards.
+    pub fn normal(&self, idx: (usize, usize)) -> (T, T) {
+        let g = self.gradient(idx);
+        let mut n = [g.0, g.1];
+        normalize(&mut n);
+        (n[0], n[1])
+    }
+
+    /// Curvature of the level set through the cell center, positive for convex regions.
+    pub fn curvature(&self, idx: (usize, usize)) -> T {
+        let (g_y, g_x) = self.gradient(idx);
+        let center = self.at(idx, (0, 0));
+        let two = T::new(2.0);
+        let quarter = T::new(0.25);
+
+        let h_yy = self.at(idx, (1, 0)) - two * center + self.at(idx, (-1, 0));
+        let h_xx = self.at(idx, (0, 1)) - two * center + self.at(idx, (0, -1));
+        let h_xy = quarter * (self.at(idx, (1, 1)) - self.at(idx, (1, -1)) - self.at(idx, (-1, 1)) + self.at(idx, (-1, -1)));
+
+        curvature(&[g_y, g_x], &[&[h_yy, h_xy], &[h_xy, h_xx]])
+    }
+
+    /// Restore the signed distance property using the fast sweeping method.
+    ///
+    /// The interface position is preserved by initializing the cells next to it
+    /// with linearly interpolated distances.
+    pub fn reinitialize(&mut self, iterations: usize) {
+        let (h, w) = self.dim();
+        let far = T::new(h + w);
+
+        let mut frozen = Array2::from_elem((h, w), false);
+        let mut dist = Array2::from_elem((h, w), far);
+        for y in 0..h {
+            for x in 0..w {
+                let idx = (y, x);
+                let axes = [
+                    [self.at(idx, (-1, 0)), self.at(idx, (1, 0))],
+                    [self.at(idx, (0, -1)), self.at(idx, (0, 1))],
+                ];
+                if let Some(d) = interface_distance(self.phi[idx], &axes) {
+                    dist[idx] = d;
+                    frozen[idx] = true;
+                }
+            }
+        }
+
+        let sweeps = [(false, false), (true, false), (false, true), (true, true)];
+        for _ in 0..iterations {
+            for &(rev_y, rev_x) in &sweeps {
+                for j in 0..h {
+                    let y = if rev_y { h - 1 - j } else { j };
+                    for i in 0..w {
+                        let x = if rev_x { w - 1 - i } else { i };
+                        if frozen[(y, x)] { continue; }
+
+                        let mut neighbors = [
+                            dist[(offset(y, -1, h), x)].min(dist[(offset(y, 1, h), x)]),
+                            dist[(y, offset(x, -1, w))].min(dist[(y, offset(x, 1, w))]),
+                        ];
+                        let d = solve_eikonal(&mut neighbors);
+                        if d < dist[(y, x)] {
+                            dist[(y, x)] = d;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (phi, &d) in self.phi.iter_mut().zip(dist.iter()) {
+            *phi = if *phi < T::zero() { -d } else { d };
+        }
+    }
+
+    /// Transport the level set with the velocity field.
+    pub fn advect(&mut self, grid: &Grid2d, scheme: Scheme, velocity: &Staggered2d<T>, timestep: T) {
+        let src = self.phi.clone();
+        advection::advect_scalar(grid, scheme, &mut self.phi, &src, velocity, timestep);
+    }
+}
+
+/// Fraction of a line segment with negative level set values, assuming linear variation.
+fn fraction_inside<T: Real>(phi0: T, phi1: T) -> T {
+    match (phi0 < T::zero(), phi1 < T::zero()) {
+        (true, true) => T::one(),
+        (false, false) => T::zero(),
+        (true, false) => phi0 / (phi0 - phi1),
+        (false, true) => phi1 / (phi1 - phi0),
+    }
+}
+
+/// Fraction of each grid edge not covered by the solid described by the level set.
+///
+/// Used as edge weights for the variational pressure projection.
+pub fn fluid_fractions<T: Real>(grid: &Grid2d, solid: &LevelSet2d<T>) -> Staggered2d<T> {
+    let mut fractions = <Grid2d as Manifold2d<T>>::new_simplex_1(grid);
+    {
+        let vertex = |y: usize, x: usize| solid.sample(grid, (T::new(y), T::new(x)));
+        let (mut vertical, mut horizontal) = fractions.split_mut();
+        for ((y, x), f) in vertical.indexed_iter_mut() {
+            *f = T::one() - fraction_inside(vertex(y, x), vertex(y, x + 1));
+        }
+        for ((y, x), f) in horizontal.indexed_iter_mut() {
+            *f = T::one() - fraction_inside(vertex(y, x), vertex(y + 1, x));
+        }
+    }
+    fractions
+}
+
+/// Smallest liquid fraction of an edge for the ghost fluid weights, avoids huge weights
+/// when the surface passes close to a pressure sample.
+const MIN_GHOST_FRACTION: f64 = 1.0e-3;
+
+/// Ghost fluid weight of an edge between two pressure samples, `0` in the air.
+fn ghost_weight<T: Real>(phi0: T, phi1: T) -> T {
+    match (phi0 < T::zero(), phi1 < T::zero()) {
+        (true, true) => T::one(),
+        (false, false) => T::zero(),
+        _ => T::one() / fraction_inside(phi0, phi1).max(T::new(MIN_GHOST_FRACTION)),
+    }
+}
+
+/// Free surface boundary of the liquid described by the level set, see `Projection::free_surface`.
+///
+/// Returns the liquid mask of the faces (`1` inside) and the ghost fluid weights
+/// of the edges. Edges crossing the surface scale the pressure gradient by `1/θ`
+/// with `θ` the liquid fraction between the two pressure samples, placing the
+/// `p = 0` condition on the interface instead of the air cell centers. Boundary
+/// edges of non-periodic axes only see the adjacent face.
+pub fn free_surface<T: Real>(grid: &Grid2d, liquid: &LevelSet2d<T>) -> (Array2<T>, Staggered2d<T>) {
+    let (h, w) = grid.dim();
+    let [by, bx] = grid.boundary();
+    let mask = liquid.phi.mapv(|phi| if phi < T::zero() { T::one() } else { T::zero() });
+
+    // faces on both sides of an edge at `i` along an axis of length `len`
+    let sides = |i: usize, len: usize, periodic: bool| -> (usize, usize) {
+        match (i, periodic) {
+            (0, true) => (len - 1, 0),
+            (0, false) => (0, 0),
+            (i, true) if i == len => (len - 1, 0),
+            (i, false) if i == len => (len - 1, len - 1),
+            (i, _) => (i - 1, i),
+        }
+    };
+
+    let mut weights = <Grid2d as Manifold2d<T>>::new_simplex_1(grid);
+    {
+        let (mut vertical, mut horizontal) = weights.split_mut();
+        for ((y, x), weight) in vertical.indexed_iter_mut() {
+            let (y0, y1) = sides(y, h, by.is_periodic());
+            *weight = ghost_weight(liquid.phi[(y0, x)], liquid.phi[(y1, x)]);
+        }
+        for ((y, x), weight) in horizontal.indexed_iter_mut() {
+            let (x0, x1) = sides(x, w, bx.is_periodic());
+            *weight = ghost_weight(liquid.phi[(y, x0)], liquid.phi[(y, x1)]);
+        }
+    }
+    (mask, weights)
+}
+
+/// Half width of the smeared interface of the continuum surface force in cells.
+const CSF_WIDTH: f64 = 1.5;
+
+/// Curvature in world units, limited to the resolution of the grid.
+fn limited_curvature<T: Real>(liquid: &LevelSet2d<T>, idx: (usize, usize), dx: T) -> T {
+    liquid.curvature(idx).max(-T::one()).min(T::one()) / dx
+}
+
+/// Surface tension pressure jump `σκ` at the faces, see `Projection::surface_pressure`.
+///
+/// The curvature is interpolated at the closest point on the interface and
+/// limited to one over the cell size, as smaller features can't be resolved
+/// by the grid. Assumes square cells.
+///
+/// Ref: [KFL00]
+pub fn surface_tension_pressure<T: Real>(grid: &Grid2d, liquid: &LevelSet2d<T>, coefficient: T) -> Array2<T> {
+    let dx = T::new(grid.spacing().1);
+    let half = T::new(0.5);
+    let curvature = Array2::from_shape_fn(liquid.dim(), |idx| limited_curvature(liquid, idx, dx));
+    let cells = Grid2d::new(liquid.dim());
+    Array2::from_shape_fn(liquid.dim(), |(y, x)| {
+        let (phi, (n_y, n_x)) = (liquid.phi[(y, x)], liquid.normal((y, x)));
+        let closest = (T::new(y) + half - phi * n_y, T::new(x) + half - phi * n_x);
+        coefficient * advection::sample(&cells, curvature.view(), advection::offset_center(), closest)
+    })
+}
+
+/// Surface tension accelerations `-σκ δ(φ) ∇φ / ρ` on the edges of the grid.
+///
+/// Continuum surface force with the interface smeared over 1.5 cells on each
+/// side, to be added to the velocity before the projection. Boundary edges are
+/// left at zero. Assumes square cells.
+///
+/// Ref: [BKZ92]
+pub fn surface_tension_force<T: Real>(grid: &Grid2d, liquid: &LevelSet2d<T>, coefficient: T, density: T) -> Staggered2d<T> {
+    let dx = T::new(grid.spacing().1);
+    let width = T::new(CSF_WIDTH);
+    let pi = T::new(::std::f64::consts::PI);
+    // smoothed dirac delta in grid units
+    let delta = |phi: T| if phi.abs() < width {
+        (T::one() + (pi * phi / width).cos()) / (T::new(2.0) * width)
+    } else {
+        T::zero()
+    };
+    let force = |a: (usize, usize), b: (usize, usize)| {
+        let (phi0, phi1) = (liquid.phi[a], liquid.phi[b]);
+        let phi = T::new(0.5) * (phi0 + phi1);
+        let curvature = T::new(0.5) * (limited_curvature(liquid, a, dx) + limited_curvature(liquid, b, dx));
+        -coefficient * curvature * delta(phi) * (phi1 - phi0) / (dx * density)
+    };
+
+    let mut accel = <Grid2d as Manifold2d<T>>::new_simplex_1(grid);
+    {
+        let (mut vertical, mut horizontal) = accel.split_mut();
+        for ((y, x), a) in vertical.slice_mut(s![1..-1, ..]).indexed_iter_mut() {
+            *a = force((y, x), (y + 1, x));
+        }
+        for ((y, x), a) in horizontal.slice_mut(s![.., 1..-1]).indexed_iter_mut() {
+            *a = force((y, x), (y, x + 1));
+        }
+    }
+    accel
+}
+
+impl<T: Real> LevelSet3d<T> {
+    /// Evaluate an implicit function at the cell centers `(z, y, x)`.
+    pub fn from_fn<F>(grid: &Grid3d, func: F) -> Self
+        where F: Fn((T, T, T)) -> T
+    {
+        let half = T::new(0.5);
+        LevelSet3d {
+            phi: Array3::from_shape_fn(grid.dim(), |(z, y, x)| {
+                func((T::new(z) + half, T::new(y) + half, T::new(x) + half))
+            }),
+        }
+    }
+
+    pub fn dim(&self) -> (usize, usize, usize) {
+        self.phi.dim()
+    }
+
+    /// Trilinear interpolation at a position `(z, y, x)`, clamped to the cell centers.
+    pub fn sample(&self, (z, y, x): (T, T, T)) -> T {
+        let (d, h, w) = self.dim();
+        let axis = |p: T, len: usize| {
+            let p = (p - T::new(0.5)).max(T::zero()).min(T::new(len - 1));
+            let i0 = p.floor().to_usize().unwrap();
+            (i0, cmp::min(i0 + 1, len - 1), p - T::new(i0))
+        };
+        let (z0, z1, u) = axis(z, d);
+        let (y0, y1, t) = axis(y, h);
+        let (x0, x1, s) = axis(x, w);
+        let phi = &self.phi;
+        math::trilinear(
+            phi[(z0, y0, x0)], phi[(z0, y0, x1)], phi[(z0, y1, x0)], phi[(z0, y1, x1)],
+            phi[(z1, y0, x0)], phi[(z1, y0, x1)], phi[(z1, y1, x0)], phi[(z1, y1, x1)],
+            s, t, u,
+        )
+    }
+
+    pub fn is_inside(&self, idx: (usize, usize, usize)) -> bool {
+        self.phi[idx] < T::zero()
+    }
+
+    fn at(&self, (z, y, x): (usize, usize, usize), (dz, dy, dx): (isize, isize, isize)) -> T {
+        let (d, h, w) = self.dim();
+        self.phi[(offset(z, dz, d), offset(y, dy, h), offset(x, dx, w))]
+    }
+
+    /// Central difference gradient `(z, y, x)`.
+    pub fn gradient(&self, idx: (usize, usize, usize)) -> (T, T, T) {
+        let half = T::new(0.5);
+        (half * (self.at(idx, (1, 0, 0)) - self.at(idx, (-1, 0, 0))),
+         half * (self.at(idx, (0, 1, 0)) - self.at(idx, (0, -1, 0))),
+         half * (self.at(idx, (0, 0, 1)) - self.at(idx, (0, 0, -1))))
+    }
+
+    /// Interface normal `(z, y, x)` pointing outwards.
+    pub fn normal(&self, idx: (usize, usize, usize)) -> (T, T, T) {
+        let g = self.gradient(idx);
+        let mut n = [g.0, g.1, g.2];
+        normalize(&mut n);
+        (n[0], n[1], n[2])
+    }
+
+    /// Mean curvature (sum of the principal curvatures) of the level set through the cell center.
+    pub fn curvature(&self, idx: (usize, usize, usize)) -> T {
+        let g = self.gradient(idx);
+        let center = self.at(idx, (0, 0, 0));
+        let two = T::new(2.0);
+        let quarter = T::new(0.25);
+
+        let second = |dir: (isize, isize, isize)| {
+            self.at(idx, dir) - two * center + self.at(idx, (-dir.0, -dir.1, -dir.2))
+        };
+        let mixed = |a: (isize, isize, isize), b: (isize, isize, isize)| {
+            quarter * (self.at(idx, (a.0 + b.0, a.1 + b.1, a.2 + b.2))
+                     - self.at(idx, (a.0 - b.0, a.1 - b.1, a.2 - b.2))
+                     - self.at(idx, (b.0 - a.0, b.1 - a.1, b.2 - a.2))
+                     + self.at(idx, (-a.0 - b.0, -a.1 - b.1, -a.2 - b.2)))
+        };
+
+        let (ez, ey, ex) = ((1, 0, 0), (0, 1, 0), (0, 0, 1));
+        let (h_zy, h_zx, h_yx) = (mixed(ez, ey), mixed(ez, ex), mixed(ey, ex));
+        curvature(&[g.0, g.1, g.2], &[
+            &[second(ez), h_zy, h_zx],
+            &[h_zy, second(ey), h_yx],
+            &[h_zx, h_yx, second(ex)],
+        ])
+    }
+
+    /// Restore the signed distance property using the fast sweeping method.
+    pub fn reinitialize(&mut self, iterations: usize) {
+        let (d, h, w) = self.dim();
+        let far = T::new(d + h + w);
+
+        let mut frozen = Array3::from_elem((d, h, w), false);
+        let mut dist = Array3::from_elem((d, h, w), far);
+        for z in 0..d {
+            for y in 0..h {
+                for x in 0..w {
+                    let idx = (z, y, x);
+                    let axes = [
+                        [self.at(idx, (-1, 0, 0)), self.at(idx, (1, 0, 0))],
+                        [self.at(idx, (0, -1, 0)), self.at(idx, (0, 1, 0))],
+                        [self.at(idx, (0, 0, -1)), self.at(idx, (0, 0, 1))],
+                    ];
+                    if let Some(dist_interface) = interface_distance(self.phi[idx], &axes) {
+                        dist[idx] = dist_interface;
+                        frozen[idx] = true;
+                    }
+                }
+            }
+        }
+
+        for _ in 0..iterations {
+            for sweep in 0..8 {
+                let (rev_z, rev_y, rev_x) = (sweep & 4 != 0, sweep & 2 != 0, sweep & 1 != 0);
+                for k in 0..d {
+                    let z = if rev_z { d - 1 - k } else { k };
+                    for j in 0..h {
+                        let y = if rev_y { h - 1 - j } else { j };
+                        for i in 0..w {
+                            let x = if rev_x { w - 1 - i } else { i };
+                            if frozen[(z, y, x)] { continue; }
+
+                            let mut neighbors = [
+                                dist[(offset(z, -1, d), y, x)].min(dist[(offset(z, 1, d), y, x)]),
+                                dist[(z, offset(y, -1, h), x)].min(dist[(z, offset(y, 1, h), x)]),
+                                dist[(z, y, offset(x, -1, w))].min(dist[(z, y, offset(x, 1, w))]),
+                            ];
+                            let dist_new = solve_eikonal(&mut neighbors);
+                            if dist_new < dist[(z, y, x)] {
+                                dist[(z, y, x)] = dist_new;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for (phi, &d) in self.phi.iter_mut().zip(dist.iter()) {
+            *phi = if *phi < T::zero() { -d } else { d };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circle_reinitialize() {
+        let grid = Grid2d::new((32, 32));
+        let radius = 8.0;
+        let distance = |(y, x): (f64, f64)| ((y - 16.0).powi(2) + (x - 16.0).powi(2)).sqrt() - radius;
+
+        // scaled distance, zero level set is unchanged
+        let mut level_set = LevelSet2d::from_fn(&grid, |pos| 3.0 * distance(pos));
+        level_set.reinitialize(2);
+
+        for ((y, x), &phi) in level_set.phi.indexed_iter() {
+            let exact = distance((y as f64 + 0.5, x as f64 + 0.5));
+            assert!((phi - exact).abs() < 0.75, "{} {}", phi, exact);
+        }
+
+        let idx = (16, 16 + 8);
+        assert!(!level_set.is_inside(idx));
+        let normal = level_set.normal(idx);
+        assert!(normal.1 > 0.95);
+        assert!((level_set.curvature(idx) - 1.0 / radius).abs() < 0.05);
+    }
+
+    #[test]
+    fn periodic_sample() {
+        use domain::AxisBoundary;
+
+        let closed = Grid2d::new((4, 6));
+        let periodic = Grid2d::with_boundary((4, 6), [AxisBoundary::default(), AxisBoundary::periodic()]);
+        let level_set = LevelSet2d::from_fn(&closed, |(_, x): (f64, f64)| x);
+
+        // clamped to the first cell center or interpolated towards the last one across the periodic side
+        assert!((level_set.sample(&closed, (2.0, 0.0)) - 0.5).abs() < 1.0e-10);
+        assert!((level_set.sample(&periodic, (2.0, 0.0)) - 3.0).abs() < 1.0e-10);
+        assert!((level_set.sample(&periodic, (2.0, 0.25)) - 1.75).abs() < 1.0e-10);
+    }
+
+    #[test]
+    fn sphere_reinitialize() {
+        let grid = Grid3d::new((16, 16, 16));
+        let radius = 5.0;
+        let distance = |(z, y, x): (f64, f64, f64)| {
+            ((z - 8.0).powi(2) + (y - 8.0).powi(2) + (x - 8.0).powi(2)).sqrt() - radius
+        };
+
+        let mut level_set = LevelSet3d::from_fn(&grid, |pos| 0.5 * distance(pos));
+        level_set.reinitialize(2);
+
+        for ((z, y, x), &phi) in level_set.phi.indexed_iter() {
+            let exact = distance((z as f64 + 0.5, y as f64 + 0.5, x as f64 + 0.5));
+            assert!((phi - exact).abs() < 1.0, "{} {}", phi, exact);
+        }
+
+        // mean curvature of a sphere is 2/r
+        assert!((level_set.curvature((8, 8, 13)) - 2.0 / radius).abs() < 0.1);
+    }
+
+    #[test]
+    fn surface_tension_droplet() {
+        let grid = Grid2d::new((32, 32)).with_spacing((0.5, 0.5));
+        let radius = 8.0;
+        let liquid = LevelSet2d::from_fn(&grid, |(y, x): (f64, f64)| ((y - 16.0).powi(2) + (x - 16.0).powi(2)).sqrt() - radius);
+
+        // jump `σκ` with the curvature in world units
+        let pressure = surface_tension_pressure(&grid, &liquid, 2.0);
+        assert!((pressure[(16, 24)] - 2.0 / (0.5 * 8.0)).abs() < 0.02);
+
+        // continuum force pulls the surface towards the center
+        let accel = surface_tension_force(&grid, &liquid, 2.0, 1.0);
+        let (vertical, horizontal) = accel.split();
+        assert!(vertical[(24, 16)] < 0.0 && vertical[(8, 16)] > 0.0);
+        assert!(horizontal[(16, 24)] < 0.0 && horizontal[(16, 8)] > 0.0);
+        assert_eq!(vertical[(16, 16)], 0.0);
+    }
+}
@@ -16,20 +16,58 @@ extern crate typenum;
 extern crate cgmath;
 extern crate specs;
 extern crate sprs;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate bincode;
+extern crate rmp_serde;
+extern crate image;
+extern crate ron;
+extern crate serde_json;
+#[cfg(feature = "mpi")]
+extern crate mpi;
+#[cfg(feature = "gpu")]
+extern crate wgpu;
+#[cfg(feature = "gpu")]
+extern crate pollster;
+#[cfg(feature = "gpu")]
+extern crate bytemuck;
 
+pub mod advection;
+pub mod analysis;
 pub mod cg;
+pub mod collision;
 pub mod dec;
+pub mod distributed;
 pub mod domain;
+pub mod error;
+pub mod ftle;
+#[cfg(feature = "gpu")]
+pub mod gpu;
 pub mod grid;
+pub mod implicit;
+pub mod io;
+pub mod levelset;
 pub mod math;
+pub mod mesh;
+pub mod nbody;
+pub mod obstacle;
 pub mod ocean;
+pub mod operator;
 pub mod particle;
 pub mod pbd;
 pub mod pcg;
+pub mod sampling;
 pub mod scene;
 pub mod solver;
+pub mod solvers;
 pub mod sparse;
 pub mod sph;
+pub mod tracers;
+pub mod tree;
+pub mod turbulence;
+pub mod validation;
+pub mod vorticity;
 
 pub use grid::*;
 pub use scene::*;
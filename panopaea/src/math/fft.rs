@@ -0,0 +1,309 @@
+//! Fast Fourier transforms of ndarray fields
+//!
+//! Thin layer over `rustfft` for multidimensional fields. A `Planner` caches
+//! the 1d plans per length, `ComplexFft` and `RealFft` hold the plans of all
+//! axes for a fixed shape and can be reused for every transform of that shape.
+//! Multidimensional transforms are computed as batches of 1d transforms along
+//! each axis (`transform_axis`).
+//!
+//! Conventions:
+//!  - Forward transforms use the kernel `e^{-2πi jk/n}`, inverse transforms `e^{+2πi jk/n}`.
+//!  - Coefficients are stored in FFT order, index `k` corresponds to the
+//!    frequency `k` for `k <= n/2` and `k - n` above.
+//!  - Real fields of shape `(.., n)` have spectra of shape `(.., n/2 + 1)`,
+//!    the remaining coefficients follow from Hermitian symmetry.
+//!  - Scaling is selected by `Normalization`, see its variants.
+//!
+//! References:
+//!     [FJ05] Matteo Frigo, Steven G. Johnson, 2005,
+//!            The design and implementation of FFTW3,
+//!            Proceedings of the IEEE 93 (2), 216–231
+
+use fft::{FFT, FFTnum, FFTplanner, Length};
+use math::Real;
+use ndarray::{Array, ArrayView, ArrayViewMut, Axis, IntoDimension, Ix1, Ix2, Ix3, RemoveAxis};
+use num::complex::Complex;
+
+use std::sync::Arc;
+
+/// Scaling of forward and inverse transforms over `n` samples.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// No scaling, `inverse(forward(x)) = n x` like the raw `rustfft` plans.
+    None,
+    /// Inverse scaled by `1/n`, forward coefficients are plain sums over the samples.
+    Backward,
+    /// Forward scaled by `1/n`, coefficients are mean amplitudes of the modes.
+    Forward,
+    /// Both scaled by `1/sqrt(n)`, the transform is unitary and preserves energy.
+    Ortho,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Normalization::Backward
+    }
+}
+
+impl Normalization {
+    fn factor<T: Real>(self, n: usize, inverse: bool) -> T {
+        let n = T::new(n);
+        match (self, inverse) {
+            (Normalization::Backward, true) | (Normalization::Forward, false) => n.recip(),
+            (Normalization::Ortho, _) => n.sqrt().recip(),
+            _ => T::one(),
+        }
+    }
+}
+
+/// Cache of 1d plans, shared by all transforms created from it.
+pub struct Planner<T: FFTnum> {
+    forward: FFTplanner<T>,
+    inverse: FFTplanner<T>,
+}
+
+impl<T: FFTnum> Planner<T> {
+    pub fn new() -> Self {
+        Planner {
+            forward: FFTplanner::new(false),
+            inverse: FFTplanner::new(true),
+        }
+    }
+
+    /// Unnormalized 1d plan, cached per length.
+    pub fn plan(&mut self, len: usize, inverse: bool) -> Arc<FFT<T>> {
+        if inverse {
+            self.inverse.plan_fft(len)
+        } else {
+            self.forward.plan_fft(len)
+        }
+    }
+}
+
+/// Unnormalized batched 1d transforms of all lanes of `data` along `axis`, in place.
+pub fn transform_axis<T, D>(plan: &FFT<T>, mut data: ArrayViewMut<Complex<T>, D>, axis: Axis)
+    where T: FFTnum, D: RemoveAxis
+{
+    let n = data.len_of(axis);
+    assert_eq!(plan.len(), n, "plan length mismatch");
+    if data.len() == 0 {
+        return;
+    }
+
+    // gather lanes into contiguous memory
+    let lanes = data.len() / n;
+    let zero = Complex::new(T::zero(), T::zero());
+    let mut input = vec![zero; n * lanes];
+    let mut output = vec![zero; n * lanes];
+    for i in 0..n {
+        for (lane, &v) in data.subview(axis, i).iter().enumerate() {
+            input[lane * n + i] = v;
+        }
+    }
+
+    plan.process_multi(&mut input, &mut output);
+
+    for i in 0..n {
+        for (lane, v) in data.subview_mut(axis, i).iter_mut().enumerate() {
+            *v = output[lane * n + i];
+        }
+    }
+}
+
+fn scale<T: Real, D: RemoveAxis>(data: &mut ArrayViewMut<Complex<T>, D>, factor: T) {
+    if factor != T::one() {
+        data.map_inplace(|v| *v = v.scale(factor));
+    }
+}
+
+/// Complex to complex transform of fields with a fixed shape.
+pub struct ComplexFft<T: FFTnum, D> {
+    shape: D,
+    normalization: Normalization,
+    forward: Vec<Arc<FFT<T>>>,
+    inverse: Vec<Arc<FFT<T>>>,
+}
+
+pub type Fft1d<T> = ComplexFft<T, Ix1>;
+pub type Fft2d<T> = ComplexFft<T, Ix2>;
+pub type Fft3d<T> = ComplexFft<T, Ix3>;
+
+impl<T: Real + FFTnum, D: RemoveAxis> ComplexFft<T, D> {
+    pub fn new<Sh>(planner: &mut Planner<T>, shape: Sh, normalization: Normalization) -> Self
+        where Sh: IntoDimension<Dim = D>
+    {
+        let shape = shape.into_dimension();
+        ComplexFft {
+            forward: shape.slice().iter().map(|&n| planner.plan(n, false)).collect(),
+            inverse: shape.slice().iter().map(|&n| planner.plan(n, true)).collect(),
+            shape,
+            normalization,
+        }
+    }
+
+    pub fn shape(&self) -> D {
+        self.shape.clone()
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Forward transform in place.
+    pub fn forward(&self, data: ArrayViewMut<Complex<T>, D>) {
+        self.transform(data, false);
+    }
+
+    /// Inverse transform in place.
+    pub fn inverse(&self, data: ArrayViewMut<Complex<T>, D>) {
+        self.transform(data, true);
+    }
+
+    fn transform(&self, mut data: ArrayViewMut<Complex<T>, D>, inverse: bool) {
+        assert_eq!(data.raw_dim(), self.shape, "shape mismatch");
+        let plans = if inverse { &self.inverse } else { &self.forward };
+        for (axis, plan) in plans.iter().enumerate() {
+            transform_axis(&**plan, data.view_mut(), Axis(axis));
+        }
+        scale(&mut data, self.normalization.factor(self.shape.size(), inverse));
+    }
+}
+
+/// Real to complex transform of fields with a fixed shape.
+///
+/// The last axis of the spectrum only stores the non-negative frequencies.
+pub struct RealFft<T: FFTnum, D> {
+    shape: D,
+    normalization: Normalization,
+    forward: Vec<Arc<FFT<T>>>,
+    inverse: Vec<Arc<FFT<T>>>,
+}
+
+pub type RealFft1d<T> = RealFft<T, Ix1>;
+pub type RealFft2d<T> = RealFft<T, Ix2>;
+pub type RealFft3d<T> = RealFft<T, Ix3>;
+
+impl<T: Real + FFTnum, D: RemoveAxis> RealFft<T, D> {
+    pub fn new<Sh>(planner: &mut Planner<T>, shape: Sh, normalization: Normalization) -> Self
+        where Sh: IntoDimension<Dim = D>
+    {
+        let shape = shape.into_dimension();
+        RealFft {
+            forward: shape.slice().iter().map(|&n| planner.plan(n, false)).collect(),
+            inverse: shape.slice().iter().map(|&n| planner.plan(n, true)).collect(),
+            shape,
+            normalization,
+        }
+    }
+
+    /// Shape of the real fields.
+    pub fn shape(&self) -> D {
+        self.shape.clone()
+    }
+
+    /// Shape of the spectra, `n/2 + 1` coefficients along the last axis.
+    pub fn spectrum_dim(&self) -> D {
+        let mut dim = self.shape.clone();
+        let last = dim.ndim() - 1;
+        dim[last] = dim[last] / 2 + 1;
+        dim
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Spectrum of a real field.
+    pub fn forward(&self, input: ArrayView<T, D>, mut output: ArrayViewMut<Complex<T>, D>) {
+        assert_eq!(input.raw_dim(), self.shape, "shape mismatch");
+        assert_eq!(output.raw_dim(), self.spectrum_dim(), "spectrum shape mismatch");
+        let last = self.shape.ndim() - 1;
+
+        let mut full = input.map(|&v| Complex::new(v, T::zero()));
+        transform_axis(&*self.forward[last], full.view_mut(), Axis(last));
+        for k in 0..output.len_of(Axis(last)) {
+            output.subview_mut(Axis(last), k).assign(&full.subview(Axis(last), k));
+        }
+        for axis in 0..last {
+            transform_axis(&*self.forward[axis], output.view_mut(), Axis(axis));
+        }
+        scale(&mut output, self.normalization.factor(self.shape.size(), false));
+    }
+
+    /// Real field of a spectrum, imaginary parts violating the symmetry are dropped.
+    pub fn inverse(&self, input: ArrayView<Complex<T>, D>, mut output: ArrayViewMut<T, D>) {
+        assert_eq!(input.raw_dim(), self.spectrum_dim(), "spectrum shape mismatch");
+        assert_eq!(output.raw_dim(), self.shape, "shape mismatch");
+        let last = self.shape.ndim() - 1;
+        let n = self.shape[last];
+
+        let mut spectrum = input.to_owned();
+        for axis in 0..last {
+            transform_axis(&*self.inverse[axis], spectrum.view_mut(), Axis(axis));
+        }
+
+        // the other axes are spatial again, the last axis is Hermitian per lane
+        let half = spectrum.len_of(Axis(last));
+        let mut full = Array::from_elem(self.shape.clone(), Complex::new(T::zero(), T::zero()));
+        for k in 0..n {
+            let mut lane = full.subview_mut(Axis(last), k);
+            if k < half {
+                lane.assign(&spectrum.subview(Axis(last), k));
+            } else {
+                lane.zip_mut_with(&spectrum.subview(Axis(last), n - k), |v, s| *v = s.conj());
+            }
+        }
+        transform_axis(&*self.inverse[last], full.view_mut(), Axis(last));
+
+        let factor = self.normalization.factor(self.shape.size(), true);
+        output.zip_mut_with(&full, |v, c| *v = c.re * factor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array1, Array3};
+    use std::f64::consts::PI;
+    use super::*;
+
+    #[test]
+    fn real_and_complex_transforms() {
+        let mut planner = Planner::<f64>::new();
+
+        // single mode, amplitudes with forward normalization
+        let n = 16;
+        let signal = Array1::from_shape_fn(n, |i| 1.0 + 2.0 * (2.0 * PI * 3.0 * i as f64 / n as f64).cos());
+        let fft = RealFft1d::new(&mut planner, n, Normalization::Forward);
+        let mut spectrum = Array1::from_elem(fft.spectrum_dim(), Complex::new(0.0, 0.0));
+        fft.forward(signal.view(), spectrum.view_mut());
+        assert_eq!(spectrum.len(), n / 2 + 1);
+        for (k, c) in spectrum.iter().enumerate() {
+            let expected = match k { 0 => 1.0, 3 => 1.0, _ => 0.0 };
+            assert!((c.re - expected).abs() < 1e-12 && c.im.abs() < 1e-12, "{}: {}", k, c);
+        }
+
+        // round trip of an odd sized 3d field matches the complex transform
+        let dim = (4, 6, 5);
+        let field = Array3::from_shape_fn(dim, |(z, y, x)| ((z * 7 + y * 3 + x * x) % 5) as f64 - 1.5);
+        let real = RealFft3d::new(&mut planner, dim, Normalization::Ortho);
+        let complex = Fft3d::new(&mut planner, dim, Normalization::Ortho);
+        let mut spectrum = Array3::from_elem(real.spectrum_dim(), Complex::new(0.0, 0.0));
+        real.forward(field.view(), spectrum.view_mut());
+
+        let mut reference = field.map(|&v| Complex::new(v, 0.0));
+        complex.forward(reference.view_mut());
+        for ((z, y, x), c) in spectrum.indexed_iter() {
+            assert!((c - reference[(z, y, x)]).norm() < 1e-12);
+        }
+
+        // unitary transform preserves energy
+        let energy = field.iter().map(|v| v * v).sum::<f64>();
+        assert!((reference.iter().map(|c| c.norm_sqr()).sum::<f64>() - energy).abs() < 1e-10);
+
+        let mut result = Array3::zeros(dim);
+        real.inverse(spectrum.view(), result.view_mut());
+        for (a, b) in result.iter().zip(field.iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+}
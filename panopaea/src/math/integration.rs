@@ -1,4 +1,18 @@
+//! Numerical integration
+//!
+//! Quadrature rules and one-step integrators for ordinary differential
+//! equations. Integrators advance states `x` with `dx/dt = f(t, x)` and
+//! second order systems like particle positions and velocities.
+//!
+//! References:
+//!     [HLW06] Ernst Hairer, Christian Lubich and Gerhard Wanner, 2006,
+//!             Geometric numerical integration,
+//!             Springer Series in Computational Mathematics 31
+//!     [Bri15] Robert Bridson, 2015,
+//!             Fluid simulation for computer graphics, Second Edition,
+//!             CRC Press
 
+use std::ops::{Add, Mul};
 use super::Real;
 
 pub fn trapezoidal_quadrature<F, T>(interval: (T, T), substeps: usize, func: F) -> T
@@ -17,3 +31,175 @@ where
 
     substep_length * integral
 }
+
+/// States which can be integrated: scalars, vectors or combinations of them.
+pub trait State<T>: Clone + Add<Output = Self> + Mul<T, Output = Self> { }
+impl<T, X> State<T> for X where X: Clone + Add<Output = X> + Mul<T, Output = X> { }
+
+/// Explicit one-step integrator.
+pub trait Integrator<T: Real> {
+    /// Advance `dx/dt = f(t, x)` from `time` by `timestep`.
+    fn step<X, F>(&self, x: &X, time: T, timestep: T, f: F) -> X
+        where X: State<T>,
+              F: Fn(T, &X) -> X;
+
+    /// Advance position and velocity of `d²x/dt² = a(t, x, v)`.
+    ///
+    /// Integrates the first order system of position and velocity by default.
+    fn step_second_order<X, F>(&self, x: &X, v: &X, time: T, timestep: T, accel: F) -> (X, X)
+        where X: State<T>,
+              F: Fn(T, &X, &X) -> X,
+    {
+        let state = Pair(x.clone(), v.clone());
+        let Pair(x, v) = self.step(&state, time, timestep, |t, s: &Pair<X>| Pair(s.1.clone(), accel(t, &s.0, &s.1)));
+        (x, v)
+    }
+}
+
+/// Forward Euler, first order.
+#[derive(Copy, Clone, Debug)]
+pub struct ExplicitEuler;
+
+impl<T: Real> Integrator<T> for ExplicitEuler {
+    fn step<X, F>(&self, x: &X, time: T, timestep: T, f: F) -> X
+        where X: State<T>,
+              F: Fn(T, &X) -> X,
+    {
+        x.clone() + f(time, x) * timestep
+    }
+}
+
+/// Midpoint rule, second order Runge-Kutta.
+#[derive(Copy, Clone, Debug)]
+pub struct Rk2;
+
+impl<T: Real> Integrator<T> for Rk2 {
+    fn step<X, F>(&self, x: &X, time: T, timestep: T, f: F) -> X
+        where X: State<T>,
+              F: Fn(T, &X) -> X,
+    {
+        let half = T::new(0.5) * timestep;
+        let k1 = f(time, x);
+        let k2 = f(time + half, &(x.clone() + k1 * half));
+        x.clone() + k2 * timestep
+    }
+}
+
+/// Ralston's third order Runge-Kutta.
+///
+/// Ref: [Bri15] Sec. 3.1
+#[derive(Copy, Clone, Debug)]
+pub struct Rk3;
+
+impl<T: Real> Integrator<T> for Rk3 {
+    fn step<X, F>(&self, x: &X, time: T, timestep: T, f: F) -> X
+        where X: State<T>,
+              F: Fn(T, &X) -> X,
+    {
+        let (half, three_quarter) = (T::new(0.5) * timestep, T::new(0.75) * timestep);
+        let k1 = f(time, x);
+        let k2 = f(time + half, &(x.clone() + k1.clone() * half));
+        let k3 = f(time + three_quarter, &(x.clone() + k2.clone() * three_quarter));
+        let ninth = timestep / T::new(9.0);
+        x.clone() + k1 * (T::new(2.0) * ninth) + k2 * (T::new(3.0) * ninth) + k3 * (T::new(4.0) * ninth)
+    }
+}
+
+/// Classical fourth order Runge-Kutta.
+#[derive(Copy, Clone, Debug)]
+pub struct Rk4;
+
+impl<T: Real> Integrator<T> for Rk4 {
+    fn step<X, F>(&self, x: &X, time: T, timestep: T, f: F) -> X
+        where X: State<T>,
+              F: Fn(T, &X) -> X,
+    {
+        let half = T::new(0.5) * timestep;
+        let k1 = f(time, x);
+        let k2 = f(time + half, &(x.clone() + k1.clone() * half));
+        let k3 = f(time + half, &(x.clone() + k2.clone() * half));
+        let k4 = f(time + timestep, &(x.clone() + k3.clone() * timestep));
+        let sixth = timestep / T::new(6.0);
+        x.clone() + (k1 + k2 * T::new(2.0) + k3 * T::new(2.0) + k4) * sixth
+    }
+}
+
+/// Semi-implicit Euler, positions are advanced with the updated velocities.
+///
+/// First order but symplectic: the energy of conservative systems stays
+/// bounded instead of drifting. First order systems fall back to forward Euler.
+///
+/// Ref: [HLW06] Sec. VI.3
+#[derive(Copy, Clone, Debug)]
+pub struct SymplecticEuler;
+
+impl<T: Real> Integrator<T> for SymplecticEuler {
+    fn step<X, F>(&self, x: &X, time: T, timestep: T, f: F) -> X
+        where X: State<T>,
+              F: Fn(T, &X) -> X,
+    {
+        ExplicitEuler.step(x, time, timestep, f)
+    }
+
+    fn step_second_order<X, F>(&self, x: &X, v: &X, time: T, timestep: T, accel: F) -> (X, X)
+        where X: State<T>,
+              F: Fn(T, &X, &X) -> X,
+    {
+        let v = v.clone() + accel(time, x, v) * timestep;
+        (x.clone() + v.clone() * timestep, v)
+    }
+}
+
+/// Position and velocity as a combined first order state.
+#[derive(Clone)]
+struct Pair<X>(X, X);
+
+impl<X: Add<Output = X>> Add for Pair<X> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Pair(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl<T: Copy, X: Mul<T, Output = X>> Mul<T> for Pair<X> {
+    type Output = Self;
+    fn mul(self, rhs: T) -> Self {
+        Pair(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Error of integrating `dx/dt = -x` until `t = 1`.
+    fn decay_error<I: Integrator<f64>>(integrator: &I, steps: usize) -> f64 {
+        let dt = 1.0 / steps as f64;
+        let x = (0..steps).fold(1.0, |x, i| integrator.step(&x, i as f64 * dt, dt, |_, x: &f64| -*x));
+        (x - (-1.0f64).exp()).abs()
+    }
+
+    /// Energy of the harmonic oscillator `d²x/dt² = -x` after `steps` steps.
+    fn oscillator_energy<I: Integrator<f64>>(integrator: &I, steps: usize, dt: f64) -> f64 {
+        let (x, v) = (0..steps).fold((1.0, 0.0), |(x, v), i| {
+            integrator.step_second_order(&x, &v, i as f64 * dt, dt, |_, x: &f64, _: &f64| -*x)
+        });
+        0.5 * (x * x + v * v)
+    }
+
+    #[test]
+    fn integrators() {
+        fn order<I: Integrator<f64>>(integrator: &I) -> f64 {
+            (decay_error(integrator, 20) / decay_error(integrator, 40)).log2()
+        }
+        assert!((order(&ExplicitEuler) - 1.0).abs() < 0.1);
+        assert!((order(&Rk2) - 2.0).abs() < 0.1);
+        assert!((order(&Rk3) - 3.0).abs() < 0.1);
+        assert!((order(&Rk4) - 4.0).abs() < 0.1);
+
+        // forward euler gains energy, the symplectic variant stays close to `0.5`
+        assert!(oscillator_energy(&ExplicitEuler, 1000, 0.05) > 1.0);
+        assert!((oscillator_energy(&SymplecticEuler, 1000, 0.05) - 0.5).abs() < 0.05);
+        assert!((oscillator_energy(&Rk4, 1000, 0.05) - 0.5).abs() < 1.0e-4);
+    }
+}
@@ -1,3 +1,9 @@
+//! Scalar interpolation kernels
+//!
+//! References:
+//!     [FSJ01] Ronald Fedkiw, Jos Stam and Henrik Wann Jensen, 2001,
+//!             Visual simulation of smoke,
+//!             In Proceedings of SIGGRAPH 2001, ACM, 15-22
 
 use super::Real;
 
@@ -16,3 +22,28 @@ pub fn trilinear<S: Real>(
 ) -> S {
     linear(bilinear(a000, a001, a010, a011, s, t), bilinear(a100, a101, a110, a111, s, t), u)
 }
+
+/// Cubic Hermite interpolation between `a1` and `a2` with the slopes `d1` and `d2`.
+fn hermite<S: Real>(a1: S, a2: S, d1: S, d2: S, s: S) -> S {
+    let delta = a2 - a1;
+    let (s2, s3) = (s * s, s * s * s);
+    let (two, three) = (S::new(2.0), S::new(3.0));
+    a1 + d1 * s + (three * delta - two * d1 - d2) * s2 + (d1 + d2 - two * delta) * s3
+}
+
+/// Catmull-Rom spline between `a1` and `a2`, may overshoot the samples.
+pub fn catmull_rom<S: Real>(a0: S, a1: S, a2: S, a3: S, s: S) -> S {
+    let half = S::new(0.5);
+    hermite(a1, a2, half * (a2 - a0), half * (a3 - a1), s)
+}
+
+/// Monotone cubic interpolation between `a1` and `a2`.
+///
+/// Slopes with a different sign than the interval difference are set to zero,
+/// which avoids overshooting at discontinuities. Ref: [FSJ01] Eq. 5
+pub fn monotone_cubic<S: Real>(a0: S, a1: S, a2: S, a3: S, s: S) -> S {
+    let half = S::new(0.5);
+    let delta = a2 - a1;
+    let limit = |d: S| if delta == S::zero() || d.signum() != delta.signum() { S::zero() } else { d };
+    hermite(a1, a2, limit(half * (a2 - a0)), limit(half * (a3 - a1)), s)
+}
@@ -6,12 +6,15 @@ use num;
 use generic_array::ArrayLength;
 use rand;
 
+pub mod fft;
 pub mod integration;
 pub mod interp;
+pub mod rng;
+pub mod simd;
 pub mod vector_n;
 pub mod wavelet;
 
-pub use self::interp::{linear, bilinear, trilinear};
+pub use self::interp::{linear, bilinear, trilinear, catmull_rom, monotone_cubic};
 pub use self::vector_n::VectorN;
 
 pub fn vec2<N: na::Scalar>(x: N, y: N) -> na::Vector2<N> {
@@ -0,0 +1,199 @@
+//! SIMD paths for element-wise kernels
+//!
+//! With the `simd` feature enabled, `f32` and `f64` slices are processed with
+//! AVX on x86 targets if the CPU supports it (detected at runtime). All other
+//! cases use plain loops. Results of both paths agree up to rounding.
+
+use math::Real;
+use ndarray::LinalgScalar;
+use rayon::prelude::*;
+
+/// Elements per task of the parallel variants, multiple of all vector widths.
+const CHUNK_SIZE: usize = 4096;
+
+/// `dst = factor * src`
+pub fn scale<T: LinalgScalar>(dst: &mut [T], src: &[T], factor: T) {
+    assert_eq!(dst.len(), src.len());
+
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if avx::scale(dst, src, factor) {
+            return;
+        }
+    }
+
+    for (dst, &src) in dst.iter_mut().zip(src.iter()) {
+        *dst = src * factor;
+    }
+}
+
+/// Parallel `scale` over chunks of the slices.
+pub fn par_scale<T: LinalgScalar + Send + Sync>(dst: &mut [T], src: &[T], factor: T) {
+    assert_eq!(dst.len(), src.len());
+    dst.par_chunks_mut(CHUNK_SIZE)
+        .zip(src.par_chunks(CHUNK_SIZE))
+        .for_each(|(dst, src)| scale(dst, src, factor));
+}
+
+/// Evaluate the cubic spline `w(q)` in place for `q = r / h`, see `sph::kernel::CubicSpline`.
+///
+/// `w(q) = c (6 (q³ - q²) + 1)` for `q <= 0.5`, `c 2 (1 - q)³` for `0.5 < q < 1`, zero otherwise.
+pub fn cubic_spline<T: Real>(values: &mut [T], factor: T) {
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if avx::cubic_spline(values, factor) {
+            return;
+        }
+    }
+
+    let (half, one, two, six) = (T::new(0.5), T::one(), T::new(2.0), T::new(6.0));
+    for q in values.iter_mut() {
+        let t = (one - *q).max(T::zero());
+        *q = factor * if *q <= half {
+            six * *q * *q * (*q - one) + one
+        } else {
+            two * t * t * t
+        };
+    }
+}
+
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+mod avx {
+    use math::Real;
+    use ndarray::LinalgScalar;
+    use std::any::TypeId;
+    use std::slice;
+
+    /// Only valid if `T` and `U` are the same type.
+    unsafe fn cast<T: Copy, U: Copy>(value: T) -> U {
+        *(&value as *const T as *const U)
+    }
+
+    fn cast_slice<T: 'static, U: 'static>(values: &[T]) -> Option<&[U]> {
+        if TypeId::of::<T>() == TypeId::of::<U>() {
+            Some(unsafe { slice::from_raw_parts(values.as_ptr() as *const U, values.len()) })
+        } else {
+            None
+        }
+    }
+
+    fn cast_slice_mut<T: 'static, U: 'static>(values: &mut [T]) -> Option<&mut [U]> {
+        if TypeId::of::<T>() == TypeId::of::<U>() {
+            Some(unsafe { slice::from_raw_parts_mut(values.as_mut_ptr() as *mut U, values.len()) })
+        } else {
+            None
+        }
+    }
+
+    /// Returns `false` if no SIMD path is available.
+    pub fn scale<T: LinalgScalar>(dst: &mut [T], src: &[T], factor: T) -> bool {
+        if !is_x86_feature_detected!("avx") {
+            return false;
+        }
+        if let (Some(dst), Some(src)) = (cast_slice_mut::<T, f64>(dst), cast_slice::<T, f64>(src)) {
+            unsafe { wide_f64::scale(dst, src, cast(factor)) };
+            return true;
+        }
+        if let (Some(dst), Some(src)) = (cast_slice_mut::<T, f32>(dst), cast_slice::<T, f32>(src)) {
+            unsafe { wide_f32::scale(dst, src, cast(factor)) };
+            return true;
+        }
+        false
+    }
+
+    /// Returns `false` if no SIMD path is available.
+    pub fn cubic_spline<T: Real>(values: &mut [T], factor: T) -> bool {
+        if !is_x86_feature_detected!("avx") {
+            return false;
+        }
+        if let Some(values) = cast_slice_mut::<T, f64>(values) {
+            unsafe { wide_f64::cubic_spline(values, cast(factor)) };
+            return true;
+        }
+        if let Some(values) = cast_slice_mut::<T, f32>(values) {
+            unsafe { wide_f32::cubic_spline(values, cast(factor)) };
+            return true;
+        }
+        false
+    }
+
+    macro_rules! avx_kernels {
+        ($module:ident, $t:ty, $lanes:expr,
+         $load:ident, $store:ident, $set1:ident, $mul:ident, $add:ident, $sub:ident,
+         $max:ident, $cmp:ident, $blend:ident) => {
+            mod $module {
+                #[cfg(target_arch = "x86")]
+                use std::arch::x86::*;
+                #[cfg(target_arch = "x86_64")]
+                use std::arch::x86_64::*;
+
+                #[target_feature(enable = "avx")]
+                pub unsafe fn scale(dst: &mut [$t], src: &[$t], factor: $t) {
+                    let n = dst.len() / $lanes * $lanes;
+                    let f = $set1(factor);
+                    let mut i = 0;
+                    while i < n {
+                        let x = $load(src.as_ptr().offset(i as isize));
+                        $store(dst.as_mut_ptr().offset(i as isize), $mul(x, f));
+                        i += $lanes;
+                    }
+                    for i in n..dst.len() {
+                        dst[i] = src[i] * factor;
+                    }
+                }
+
+                #[target_feature(enable = "avx")]
+                pub unsafe fn cubic_spline(values: &mut [$t], factor: $t) {
+                    let n = values.len() / $lanes * $lanes;
+                    let (zero, half, one) = ($set1(0.0), $set1(0.5), $set1(1.0));
+                    let (two, six, f) = ($set1(2.0), $set1(6.0), $set1(factor));
+                    let mut i = 0;
+                    while i < n {
+                        let q = $load(values.as_ptr().offset(i as isize));
+                        let inner = $add($mul($mul(six, $mul(q, q)), $sub(q, one)), one);
+                        let t = $max($sub(one, q), zero);
+                        let outer = $mul(two, $mul(t, $mul(t, t)));
+                        let w = $blend(outer, inner, $cmp(q, half, _CMP_LE_OQ));
+                        $store(values.as_mut_ptr().offset(i as isize), $mul(f, w));
+                        i += $lanes;
+                    }
+                    for q in &mut values[n..] {
+                        let t = (1.0 - *q).max(0.0);
+                        *q = factor * if *q <= 0.5 { 6.0 * *q * *q * (*q - 1.0) + 1.0 } else { 2.0 * t * t * t };
+                    }
+                }
+            }
+        }
+    }
+
+    avx_kernels!(wide_f64, f64, 4,
+        _mm256_loadu_pd, _mm256_storeu_pd, _mm256_set1_pd, _mm256_mul_pd, _mm256_add_pd, _mm256_sub_pd,
+        _mm256_max_pd, _mm256_cmp_pd, _mm256_blendv_pd);
+    avx_kernels!(wide_f32, f32, 8,
+        _mm256_loadu_ps, _mm256_storeu_ps, _mm256_set1_ps, _mm256_mul_ps, _mm256_add_ps, _mm256_sub_ps,
+        _mm256_max_ps, _mm256_cmp_ps, _mm256_blendv_ps);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn element_wise_kernels() {
+        // odd lengths exercise the scalar remainder
+        let src: Vec<f64> = (0..1037).map(|i| (i as f64 * 0.37).sin()).collect();
+        let mut dst = vec![0.0; src.len()];
+        par_scale(&mut dst, &src, 3.0);
+        for (&d, &s) in dst.iter().zip(src.iter()) {
+            assert_eq!(d, 3.0 * s);
+        }
+
+        let q: Vec<f32> = (0..23).map(|i| i as f32 * 0.05).collect();
+        let mut w = q.clone();
+        cubic_spline(&mut w, 2.0);
+        for (&w, &q) in w.iter().zip(q.iter()) {
+            let expected = if q <= 0.5 { 6.0 * (q * q * q - q * q) + 1.0 } else if q < 1.0 { 2.0 * (1.0 - q).powi(3) } else { 0.0 };
+            assert!((w - 2.0 * expected).abs() < 1.0e-5);
+        }
+    }
+}
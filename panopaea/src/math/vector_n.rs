@@ -4,6 +4,10 @@ use generic_array::GenericArray;
 use generic_array::typenum::{U2, U3};
 use std::ops::{Add, AddAssign, Deref, DerefMut, Sub, SubAssign, Mul, Div, DivAssign, Rem};
 use num::Zero;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, SeqAccess, Visitor};
+use std::fmt;
+use std::marker::PhantomData;
 
 use super::{Dim, Real};
 
@@ -195,6 +199,43 @@ impl<S, N: Dim<S>> DerefMut for VectorN<S, N> {
     }
 }
 
+/// Serialized as tuple of `N` elements.
+impl<S: Serialize, N: Dim<S>> Serialize for VectorN<S, N> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        use serde::ser::SerializeTuple;
+        let mut tuple = serializer.serialize_tuple(N::to_usize())?;
+        for elem in self.iter() {
+            tuple.serialize_element(elem)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de, S: Deserialize<'de> + Clone, N: Dim<S>> Deserialize<'de> for VectorN<S, N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct VectorVisitor<S, N>(PhantomData<(S, N)>);
+
+        impl<'de, S: Deserialize<'de> + Clone, N: Dim<S>> Visitor<'de> for VectorVisitor<S, N> {
+            type Value = VectorN<S, N>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a vector with {} elements", N::to_usize())
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let len = N::to_usize();
+                let mut elems = Vec::with_capacity(len);
+                for i in 0..len {
+                    elems.push(seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i, &self))?);
+                }
+                Ok(VectorN(GenericArray::clone_from_slice(&elems)))
+            }
+        }
+
+        deserializer.deserialize_tuple(N::to_usize(), VectorVisitor(PhantomData))
+    }
+}
+
 impl<S: Copy> Into<[S; 2]> for VectorN<S, U2> {
     fn into(self) -> [S; 2] {
         [self[0], self[1]]
@@ -204,3 +245,7 @@ impl<S: Copy> Into<[S; 2]> for VectorN<S, U2> {
 pub fn vec2<S: Clone>(x: S, y: S) -> VectorN<S, U2> {
     VectorN(GenericArray::clone_from_slice(&[x, y]))
 }
+
+pub fn vec3<S: Clone>(x: S, y: S, z: S) -> VectorN<S, U3> {
+    VectorN(GenericArray::clone_from_slice(&[x, y, z]))
+}
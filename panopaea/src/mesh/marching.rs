@@ -0,0 +1,258 @@
+//! Iso-surface extraction from sampled scalar fields
+//!
+//! Samples are located at the grid nodes `index * spacing`. Each cell is
+//! processed independently: the iso-contour of every cell face is computed
+//! with marching squares and the resulting segments on the cube surface are
+//! joined to closed loops, which are triangulated as fans. This produces the
+//! same configurations as the classic case tables of marching cubes.
+//!
+//! Ambiguous faces are resolved by the mean of the corner values. As both
+//! neighboring cells see the same face values, the resulting surfaces are closed.
+//! Segments and triangles are oriented with the normals pointing towards increasing
+//! values, i.e. outwards for level sets with negative inside.
+//!
+//! References:
+//!     [LC87] William E. Lorensen and Harvey E. Cline, 1987,
+//!            Marching cubes: A high resolution 3D surface construction algorithm,
+//!            In Proceedings of the 14th annual conference on Computer graphics and interactive techniques (SIGGRAPH '87),
+//!            ACM, New York, NY, USA, 163-169
+
+use cgmath::{Vector2, Vector3};
+use domain::TriangleMesh;
+use math::Real;
+use ndarray::{ArrayView2, ArrayView3};
+use std::collections::HashMap;
+
+/// Corners of the cube faces in counterclockwise order seen from outside.
+///
+/// Corner `i` is located at `(i & 1, (i >> 1) & 1, i >> 2)`.
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2], [1, 3, 7, 5], // x
+    [0, 1, 5, 4], [2, 6, 7, 3], // y
+    [0, 2, 3, 1], [4, 5, 7, 6], // z
+];
+
+/// Iso-contour of a 2d field.
+pub struct Contour<T> {
+    pub vertices: Vec<Vector2<T>>,
+    /// Oriented segments with increasing values on the right side.
+    pub segments: Vec<[usize; 2]>,
+}
+
+/// Vertices on grid edges, shared between adjacent cells.
+struct EdgeVertices<V> {
+    vertices: Vec<V>,
+    indices: HashMap<(usize, usize), usize>,
+}
+
+impl<V> EdgeVertices<V> {
+    fn new() -> Self {
+        EdgeVertices {
+            vertices: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Vertex on the edge between the nodes `a` and `b`, created on first access.
+    fn get<F>(&mut self, a: usize, b: usize, position: F) -> usize
+        where F: FnOnce() -> V
+    {
+        let vertices = &mut self.vertices;
+        *self.indices.entry((a.min(b), a.max(b))).or_insert_with(|| {
+            vertices.push(position());
+            vertices.len() - 1
+        })
+    }
+}
+
+/// Oriented iso segments of a face with values at the corners in counterclockwise order.
+///
+/// `crossings[k]` is the vertex on the edge from corner `k` to `k+1`, if any.
+/// Segments start where the boundary enters the region above the iso value.
+fn face_segments<T: Real>(values: &[T; 4], crossings: &[Option<usize>; 4], iso: T, segments: &mut Vec<(usize, usize)>) {
+    // (starts, vertex) in counterclockwise order
+    let mut boundary = [(false, 0); 4];
+    let mut num_crossings = 0;
+    for k in 0..4 {
+        if let Some(vertex) = crossings[k] {
+            boundary[num_crossings] = (values[(k+1) % 4] >= iso, vertex);
+            num_crossings += 1;
+        }
+    }
+
+    match num_crossings {
+        0 => (),
+        2 => {
+            let (start, end) = if boundary[0].0 { (0, 1) } else { (1, 0) };
+            segments.push((boundary[start].1, boundary[end].1));
+        }
+        4 => {
+            // saddle, either the regions above or below the iso value are connected
+            let mean = (values[0] + values[1] + values[2] + values[3]) / T::new(4.0);
+            let offset = if mean >= iso { 3 } else { 1 };
+            for k in 0..4 {
+                if boundary[k].0 {
+                    segments.push((boundary[k].1, boundary[(k + offset) % 4].1));
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn crossing<T: Real>(iso: T, a: T, b: T) -> T {
+    (iso - a) / (b - a)
+}
+
+/// Extract the iso-contour of a field sampled on the nodes of a 2d grid, indexed by `(y, x)`.
+pub fn marching_squares<T: Real>(field: ArrayView2<T>, spacing: T, iso: T) -> Contour<T> {
+    let (h, w) = field.dim();
+    let node = |(y, x): (usize, usize)| Vector2::new(T::new(x), T::new(y)) * spacing;
+
+    let mut vertices = EdgeVertices::new();
+    let mut segments = Vec::new();
+    for y in 0..h.saturating_sub(1) {
+        for x in 0..w.saturating_sub(1) {
+            let corners = [(y, x), (y, x+1), (y+1, x+1), (y+1, x)];
+            let values = [field[corners[0]], field[corners[1]], field[corners[2]], field[corners[3]]];
+
+            let mut crossings = [None; 4];
+            for k in 0..4 {
+                let (a, b) = (corners[k], corners[(k+1) % 4]);
+                let (va, vb) = (values[k], values[(k+1) % 4]);
+                if (va >= iso) != (vb >= iso) {
+                    crossings[k] = Some(vertices.get(a.0 * w + a.1, b.0 * w + b.1, || {
+                        let t = crossing(iso, va, vb);
+                        node(a) + (node(b) - node(a)) * t
+                    }));
+                }
+            }
+
+            face_segments(&values, &crossings, iso, &mut segments);
+        }
+    }
+
+    Contour {
+        vertices: vertices.vertices,
+        segments: segments.into_iter().map(|(a, b)| [a, b]).collect(),
+    }
+}
+
+/// Extract the iso-surface of a field sampled on the nodes of a 3d grid, indexed by `(z, y, x)`.
+pub fn marching_cubes<T: Real>(field: ArrayView3<T>, spacing: T, iso: T) -> TriangleMesh<T> {
+    let (d, h, w) = field.dim();
+    let node = |(z, y, x): (usize, usize, usize)| Vector3::new(T::new(x), T::new(y), T::new(z)) * spacing;
+    let id = |(z, y, x): (usize, usize, usize)| (z * h + y) * w + x;
+
+    let mut vertices = EdgeVertices::new();
+    let mut faces = Vec::new();
+    let mut segments = Vec::with_capacity(12);
+    let mut next = HashMap::new();
+    for z in 0..d.saturating_sub(1) {
+        for y in 0..h.saturating_sub(1) {
+            for x in 0..w.saturating_sub(1) {
+                let corner = |i: usize| (z + (i >> 2), y + ((i >> 1) & 1), x + (i & 1));
+                let mut values = [T::zero(); 8];
+                for i in 0..8 {
+                    values[i] = field[corner(i)];
+                }
+
+                // segments on the cube faces
+                segments.clear();
+                for face in &CUBE_FACES {
+                    let face_values = [values[face[0]], values[face[1]], values[face[2]], values[face[3]]];
+                    let mut crossings = [None; 4];
+                    for k in 0..4 {
+                        let (a, b) = (face[k], face[(k+1) % 4]);
+                        let (va, vb) = (values[a], values[b]);
+                        if (va >= iso) != (vb >= iso) {
+                            let (a, b) = (corner(a), corner(b));
+                            crossings[k] = Some(vertices.get(id(a), id(b), || {
+                                let t = crossing(iso, va, vb);
+                                node(a) + (node(b) - node(a)) * t
+                            }));
+                        }
+                    }
+                    face_segments(&face_values, &crossings, iso, &mut segments);
+                }
+
+                // each crossing starts a segment on one face and ends one on the adjacent face
+                next.clear();
+                next.extend(segments.iter().cloned());
+                loop {
+                    let first = match next.keys().next() {
+                        Some(&first) => first,
+                        None => break,
+                    };
+                    let mut loop_vertices = vec![first];
+                    let mut current = next.remove(&first).unwrap();
+                    while current != first {
+                        loop_vertices.push(current);
+                        current = next.remove(&current).unwrap();
+                    }
+
+                    for i in 1..loop_vertices.len()-1 {
+                        faces.push([loop_vertices[0], loop_vertices[i+1], loop_vertices[i]]);
+                    }
+                }
+            }
+        }
+    }
+
+    TriangleMesh::new(vertices.vertices, faces)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+    use ndarray::{Array2, Array3};
+    use std::collections::HashSet;
+    use super::*;
+
+    #[test]
+    fn marching_circle_sphere() {
+        let (spacing, radius) = (0.1, 0.62);
+
+        // distance fields, increasing outwards
+        let circle = Array2::from_shape_fn((16, 16), |(y, x)| {
+            Vector2::new(x as f64 * spacing - 0.75, y as f64 * spacing - 0.75).magnitude()
+        });
+        let contour = marching_squares(circle.view(), spacing, radius);
+        assert!(!contour.segments.is_empty());
+        for v in &contour.vertices {
+            assert!(((*v - Vector2::new(0.75, 0.75)).magnitude() - radius).abs() < 0.01);
+        }
+        // closed, every vertex starts and ends exactly one segment
+        let starts = contour.segments.iter().map(|s| s[0]).collect::<HashSet<_>>();
+        let ends = contour.segments.iter().map(|s| s[1]).collect::<HashSet<_>>();
+        assert_eq!(starts.len(), contour.segments.len());
+        assert_eq!(starts, ends);
+        // normals point outwards
+        for s in &contour.segments {
+            let (a, b) = (contour.vertices[s[0]], contour.vertices[s[1]]);
+            let normal = Vector2::new(b.y - a.y, a.x - b.x);
+            assert!(normal.dot(a + b - Vector2::new(1.5, 1.5)) > 0.0);
+        }
+
+        let sphere = Array3::from_shape_fn((16, 16, 16), |(z, y, x)| {
+            Vector3::new(x as f64 * spacing - 0.75, y as f64 * spacing - 0.75, z as f64 * spacing - 0.75).magnitude()
+        });
+        let mesh = marching_cubes(sphere.view(), spacing, radius);
+        assert!(mesh.num_faces() > 0);
+        let center = Vector3::new(0.75, 0.75, 0.75);
+        for v in mesh.positions() {
+            assert!(((*v - center).magnitude() - radius).abs() < 0.01);
+        }
+        // closed and consistently oriented, every edge is shared by two faces
+        assert_eq!(3 * mesh.num_faces(), 2 * mesh.num_edges());
+        let mut oriented = HashSet::new();
+        for f in mesh.faces() {
+            for i in 0..3 {
+                assert!(oriented.insert((f[i], f[(i+1) % 3])));
+            }
+            let p = [mesh.positions()[f[0]], mesh.positions()[f[1]], mesh.positions()[f[2]]];
+            let normal = (p[1] - p[0]).cross(p[2] - p[0]);
+            assert!(normal.dot(p[0] + p[1] + p[2] - center * 3.0) > 0.0);
+        }
+    }
+}
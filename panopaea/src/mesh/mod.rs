@@ -0,0 +1,5 @@
+//! Surface reconstruction and conversion
+
+pub mod marching;
+pub mod rasterize;
+pub mod sdf;
@@ -0,0 +1,231 @@
+//! Rasterization of SPH particles to scalar fields
+//!
+//! The color field `c(x) = Σ m_j / ρ_j W(x - x_j)` is one inside the fluid and
+//! drops to zero at the surface, its iso-contour at 0.5 approximates the
+//! liquid surface. Isotropic kernels produce bumpy surfaces for flat fluid
+//! regions. Anisotropic kernels are stretched along the principal axes of the
+//! particle neighborhood, which smooths thin sheets and flat surfaces.
+//!
+//! Fields are sampled on the nodes `index * spacing`, indexed by `(y, x)`.
+//!
+//! References:
+//!     [YT13] Jihun Yu and Greg Turk, 2013,
+//!            Reconstructing surfaces of particle-based fluids using anisotropic kernels,
+//!            ACM Trans. Graph. 32, 1, Article 5
+
+use cgmath::MetricSpace;
+use math::{Real, VectorN};
+use math::vector_n::vec2;
+use ndarray::Array2;
+use particle::Particles;
+use sph::grid::BoundedGrid;
+use sph::kernel::SmoothingKernel;
+use sph::property::{Density, Mass, Position};
+use typenum::U2;
+
+/// Parameters of the anisotropic kernels.
+#[derive(Copy, Clone, Debug)]
+pub struct Anisotropy<T> {
+    /// Laplacian smoothing `λ` of the kernel centers, between 0 and 1.
+    pub smoothing: T,
+    /// Maximum ratio `k_r` of the covariance eigenvalues.
+    pub max_stretch: T,
+    /// Particles with fewer neighbors keep isotropic kernels.
+    pub min_neighbors: usize,
+}
+
+impl<T: Real> Anisotropy<T> {
+    pub fn new() -> Self {
+        Anisotropy {
+            smoothing: T::new(0.9),
+            max_stretch: T::new(4.0),
+            min_neighbors: 6,
+        }
+    }
+}
+
+/// Add `value * weight(x - center)` to all nodes `x` within `extent` of the center.
+fn splat<T, F>(field: &mut Array2<T>, spacing: T, center: VectorN<T, U2>, extent: T, value: T, weight: F)
+    where T: Real,
+          F: Fn(VectorN<T, U2>) -> T,
+{
+    let (h, w) = field.dim();
+    let range = |c: T, n: usize| {
+        let lower = ((c - extent) / spacing).ceil().max(T::zero());
+        let upper = ((c + extent) / spacing).floor().min(T::new(n as f64 - 1.0));
+        if upper < lower {
+            0..0
+        } else {
+            lower.to_usize().unwrap()..upper.to_usize().unwrap() + 1
+        }
+    };
+
+    for y in range(center[1], h) {
+        for x in range(center[0], w) {
+            let node = vec2(T::new(x as f64), T::new(y as f64)) * spacing;
+            field[(y, x)] += value * weight(node - center);
+        }
+    }
+}
+
+/// Rasterize the color field with isotropic kernels.
+///
+/// The field is reset before rasterization.
+pub fn color_field<T, K>(particles: &Particles, kernel: &K, spacing: T, field: &mut Array2<T>)
+    where T: Real + 'static,
+          K: SmoothingKernel<T>,
+{
+    let (positions, densities, masses) = (
+        particles.read_property::<Position<T, U2>>(),
+        particles.read_property::<Density<T>>(),
+        particles.read_property::<Mass<T>>(),
+    );
+
+    field.fill(T::zero());
+    for i in 0..positions.len() {
+        splat(field, spacing, positions[i], kernel.support(), masses[i] / densities[i], |r| {
+            kernel.w((r[0] * r[0] + r[1] * r[1]).sqrt())
+        });
+    }
+}
+
+/// Rasterize the color field with anisotropic kernels.
+///
+/// Each kernel `W(A r)` is shaped by the weighted covariance of the neighboring
+/// particles. Eigenvalues are clamped to `max_stretch` and normalized to unit
+/// determinant instead of using the scaling constants of [YT13], so each kernel
+/// still integrates to one. The grid ranges have to be constructed for the
+/// current particle positions with cell size of the kernel support.
+///
+/// Ref: [YT13] Sec. 3.2, 3.3
+pub fn anisotropic_color_field<T, K>(
+    particles: &Particles,
+    kernel: &K,
+    grid: &BoundedGrid<T, U2>,
+    params: Anisotropy<T>,
+    spacing: T,
+    field: &mut Array2<T>,
+)
+    where T: Real + 'static,
+          K: SmoothingKernel<T>,
+{
+    let (positions, densities, masses) = (
+        particles.read_property::<Position<T, U2>>(),
+        particles.read_property::<Density<T>>(),
+        particles.read_property::<Mass<T>>(),
+    );
+
+    let h = kernel.support();
+    field.fill(T::zero());
+    for i in 0..positions.len() {
+        let pos = positions[i];
+        let volume = masses[i] / densities[i];
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else {
+            splat(field, spacing, pos, h, volume, |r| kernel.w((r[0] * r[0] + r[1] * r[1]).sqrt()));
+            continue;
+        };
+
+        // weighted mean, Ref: [YT13] Eq. 6
+        let mut num_neighbors = 0;
+        let mut sum = T::zero();
+        let mut mean = vec2(T::zero(), T::zero());
+        grid.for_each_neighbor(cell, 1, |j| {
+            let weight = T::one() - (pos.distance(positions[j]) / h).powi(3);
+            if weight <= T::zero() { return }
+            num_neighbors += 1;
+            sum += weight;
+            mean += positions[j] * weight;
+        });
+        mean = mean / sum;
+
+        // weighted covariance, Ref: [YT13] Eq. 5
+        let mut covariance = [T::zero(); 3];
+        grid.for_each_neighbor(cell, 1, |j| {
+            let weight = T::one() - (pos.distance(positions[j]) / h).powi(3);
+            if weight <= T::zero() { return }
+            let r = positions[j] - mean;
+            covariance[0] += weight * r[0] * r[0];
+            covariance[1] += weight * r[0] * r[1];
+            covariance[2] += weight * r[1] * r[1];
+        });
+
+        // symmetric 2x2 eigen decomposition, `major` along `(cos θ, sin θ)`
+        let (a, b, c) = (covariance[0] / sum, covariance[1] / sum, covariance[2] / sum);
+        let half = T::new(0.5);
+        let radius = ((half * (a - c)).powi(2) + b * b).sqrt();
+        let major = half * (a + c) + radius;
+        let minor = (half * (a + c) - radius).max(major / params.max_stretch);
+
+        let center = pos * (T::one() - params.smoothing) + mean * params.smoothing;
+        if num_neighbors < params.min_neighbors || major <= T::zero() {
+            splat(field, spacing, center, h, volume, |r| kernel.w((r[0] * r[0] + r[1] * r[1]).sqrt()));
+            continue;
+        }
+
+        // A = R diag(s / major, s / minor) R^T with det A = 1
+        let scale = (major * minor).sqrt();
+        let theta = half * (T::new(2.0) * b).atan2(a - c);
+        let (sin, cos) = theta.sin_cos();
+        let (stretch_major, stretch_minor) = (scale / major, scale / minor);
+        splat(field, spacing, center, h / stretch_major, volume, |r| {
+            let u = (cos * r[0] + sin * r[1]) * stretch_major;
+            let v = (cos * r[1] - sin * r[0]) * stretch_minor;
+            kernel.w((u * u + v * v).sqrt())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mesh::marching::marching_squares;
+    use sph::kernel::CubicSpline;
+    use sph::sort::ParticleSort;
+    use sph::{self, wcsph};
+    use super::*;
+
+    #[test]
+    fn rasterize_block() {
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+        {
+            let mut positions = Vec::new();
+            for y in 0..10 {
+                for x in 0..10 {
+                    positions.push(vec2(1.05 + x as f64 * spacing, 1.05 + y as f64 * spacing));
+                }
+            }
+            let masses = vec![rest_density * spacing * spacing; positions.len()];
+            let densities = vec![rest_density; positions.len()];
+            particles.add_particles(positions.len())
+                     .with::<Position<f64, U2>>(&positions)
+                     .with::<Mass<f64>>(&masses)
+                     .with::<Density<f64>>(&densities);
+        }
+
+        let kernel = CubicSpline::new_2d(2.0 * spacing);
+        let mut grid = BoundedGrid::new(vec2(15, 15), 2.0 * spacing);
+        sph::sort_particles(&mut particles, &mut grid, &mut ParticleSort::new());
+
+        let cell_size = 0.025;
+        let mut isotropic = Array2::zeros((121, 121));
+        let mut anisotropic = Array2::zeros((121, 121));
+        color_field(&particles, &kernel, cell_size, &mut isotropic);
+        anisotropic_color_field(&particles, &kernel, &grid, Anisotropy::new(), cell_size, &mut anisotropic);
+
+        for field in &[isotropic, anisotropic] {
+            // kernels integrate to the particle volumes
+            let volume = field.scalar_sum() * cell_size * cell_size;
+            assert!((volume - 1.0).abs() < 0.02, "{}", volume);
+            assert!((field[(60, 60)] - 1.0).abs() < 0.05, "{}", field[(60, 60)]);
+
+            // surface close to the block outline
+            let contour = marching_squares(field.view(), cell_size, 0.5);
+            assert!(!contour.segments.is_empty());
+            for v in &contour.vertices {
+                let dist = (v.x - 1.5).abs().max((v.y - 1.5).abs());
+                assert!(dist > 0.4 && dist < 0.6, "{:?}", v);
+            }
+        }
+    }
+}
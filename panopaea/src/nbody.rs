@@ -0,0 +1,400 @@
+//! Fast summation of pairwise interactions
+//!
+//! N-body style sums `u(x) = Σ K(x - y_j) s_j` over all sources, e.g. the
+//! Biot-Savart law of vortex particle methods, gravitation or long-range
+//! corrections of SPH, cost `O(N²)` for all particles with direct summation.
+//!
+//! The Barnes-Hut scheme of [BH86] sorts the sources into a quadtree (2d) or
+//! octree (3d), see `tree`, and replaces distant cells by an expansion around
+//! their center of weight: the total strength (monopole) and the first moment
+//! `Σ s_j (y_j - c)` of the strengths (dipole). A cell of edge length `s` is
+//! accepted if `s < θ d` for the distance `d` from the target to the cell, which
+//! also bounds the error for centers of weight close to the cell boundary,
+//! reducing the cost to `O(N log N)`. `θ = 0` falls back to direct summation, values around `0.5`
+//! give errors below a percent. The dipole term is required for sources of
+//! both signs like point vortices, their strengths cancel in the monopole.
+//!
+//! Higher order expansions and cell-cell interactions of the fast multipole
+//! method [GR87] are left out.
+//!
+//! References:
+//!     [BH86] Josh Barnes and Piet Hut, 1986,
+//!            A hierarchical O(N log N) force-calculation algorithm,
+//!            Nature 324, 446-449
+//!     [GR87] Leslie Greengard and Vladimir Rokhlin, 1987,
+//!            A fast algorithm for particle simulations,
+//!            Journal of Computational Physics 73 (2), 325-348
+
+use cgmath::InnerSpace;
+use math::{Dim, Real, VectorN};
+use math::vector_n::{vec2, vec3};
+use num::Zero;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+use std::ops::{Add, Mul};
+use tree::Orthtree;
+use typenum::{U2, U3};
+
+/// Pairwise interaction of a source with a target.
+pub trait Kernel<T: Real, N: Dim<T>>: Sync {
+    type Strength: Clone + Send + Sync + Zero + Add<Output = Self::Strength> + Mul<T, Output = Self::Strength>;
+    type Output: Clone + Send + Zero + Add<Output = Self::Output>;
+
+    /// Nonnegative weight of a source for the expansion centers.
+    fn weight(&self, strength: &Self::Strength) -> T;
+
+    /// Contribution of a source at `offset = target - source`.
+    ///
+    /// Needs to vanish for coincident points to exclude self-interactions.
+    fn evaluate(&self, offset: &VectorN<T, N>, strength: &Self::Strength) -> Self::Output;
+
+    /// Contribution of a dipole at `offset = target - center`, `-Σ_d ∂_d K(offset) moments[d]`.
+    ///
+    /// `moments[d] = Σ_j s_j (y_j - c)_d` is the first moment of the source strengths
+    /// along axis `d`, the first order term of the expansion of `Σ_j K(offset - (y_j - c)) s_j`.
+    fn evaluate_dipole(&self, offset: &VectorN<T, N>, moments: &[Self::Strength]) -> Self::Output;
+}
+
+/// Gravitational acceleration of point masses, softened by `softening` (Plummer).
+#[derive(Copy, Clone, Debug)]
+pub struct Gravity<T> {
+    pub constant: T,
+    pub softening: T,
+}
+
+impl<T: Real, N: Dim<T>> Kernel<T, N> for Gravity<T>
+    where VectorN<T, N>: Copy
+{
+    type Strength = T;
+    type Output = VectorN<T, N>;
+
+    fn weight(&self, mass: &T) -> T {
+        mass.abs()
+    }
+
+    fn evaluate(&self, offset: &VectorN<T, N>, mass: &T) -> VectorN<T, N> {
+        let r2 = offset.clone().magnitude2() + self.softening * self.softening;
+        if r2 <= T::zero() {
+            return VectorN::zero();
+        }
+        offset.clone() * (-self.constant * *mass / (r2 * r2.sqrt()))
+    }
+
+    fn evaluate_dipole(&self, offset: &VectorN<T, N>, moments: &[T]) -> VectorN<T, N> {
+        let r2 = offset.clone().magnitude2() + self.softening * self.softening;
+        if r2 <= T::zero() {
+            return VectorN::zero();
+        }
+        // `G (M / s³ - 3 r (r·M) / s⁵)`
+        let mut moment = VectorN::<T, N>::zero();
+        for (d, &m) in moments.iter().enumerate() {
+            moment[d] = m;
+        }
+        let r3 = r2 * r2.sqrt();
+        let projection = offset.clone().dot(moment.clone());
+        moment * (self.constant / r3) - offset.clone() * (T::new(3.0) * self.constant * projection / (r3 * r2))
+    }
+}
+
+/// Velocity induced by 2d point vortices of given circulation, with a regularized core of radius `core`.
+#[derive(Copy, Clone, Debug)]
+pub struct BiotSavart2d<T> {
+    pub core: T,
+}
+
+impl<T: Real> Kernel<T, U2> for BiotSavart2d<T> {
+    type Strength = T;
+    type Output = VectorN<T, U2>;
+
+    fn weight(&self, circulation: &T) -> T {
+        circulation.abs()
+    }
+
+    fn evaluate(&self, offset: &VectorN<T, U2>, circulation: &T) -> VectorN<T, U2> {
+        let r2 = offset.clone().magnitude2() + self.core * self.core;
+        if r2 <= T::zero() {
+            return VectorN::zero();
+        }
+        let scale = *circulation / (T::new(2.0 * PI) * r2);
+        vec2(-offset[1] * scale, offset[0] * scale)
+    }
+
+    fn evaluate_dipole(&self, offset: &VectorN<T, U2>, moments: &[T]) -> VectorN<T, U2> {
+        let r2 = offset.clone().magnitude2() + self.core * self.core;
+        if r2 <= T::zero() {
+            return VectorN::zero();
+        }
+        // `-(M^⊥ / s² - 2 (r·M) r^⊥ / s⁴) / 2π`
+        let scale = T::one() / (T::new(2.0 * PI) * r2);
+        let projection = T::new(2.0) * (offset[0] * moments[0] + offset[1] * moments[1]) / r2;
+        vec2(
+            (moments[1] - projection * offset[1]) * scale,
+            (projection * offset[0] - moments[0]) * scale,
+        )
+    }
+}
+
+/// Velocity induced by 3d vortex particles of given vector strength, with a regularized core of radius `core`.
+#[derive(Copy, Clone, Debug)]
+pub struct BiotSavart3d<T> {
+    pub core: T,
+}
+
+impl<T: Real> Kernel<T, U3> for BiotSavart3d<T> {
+    type Strength = VectorN<T, U3>;
+    type Output = VectorN<T, U3>;
+
+    fn weight(&self, strength: &VectorN<T, U3>) -> T {
+        strength.magnitude()
+    }
+
+    fn evaluate(&self, offset: &VectorN<T, U3>, strength: &VectorN<T, U3>) -> VectorN<T, U3> {
+        let r2 = offset.clone().magnitude2() + self.core * self.core;
+        if r2 <= T::zero() {
+            return VectorN::zero();
+        }
+        let scale = T::one() / (T::new(4.0 * PI) * r2 * r2.sqrt());
+        // `α × r`
+        let (a, r) = (strength, offset);
+        vec3(
+            (a[1] * r[2] - a[2] * r[1]) * scale,
+            (a[2] * r[0] - a[0] * r[2]) * scale,
+            (a[0] * r[1] - a[1] * r[0]) * scale,
+        )
+    }
+
+    fn evaluate_dipole(&self, offset: &VectorN<T, U3>, moments: &[VectorN<T, U3>]) -> VectorN<T, U3> {
+        let r2 = offset.clone().magnitude2() + self.core * self.core;
+        if r2 <= T::zero() {
+            return VectorN::zero();
+        }
+        // `-(Σ_d M_d × e_d / s³ - 3 (Σ_d r_d M_d) × r / s⁵) / 4π`
+        let scale = T::one() / (T::new(4.0 * PI) * r2 * r2.sqrt());
+        let m = moments;
+        let a = vec3(
+            m[2][1] - m[1][2],
+            m[0][2] - m[2][0],
+            m[1][0] - m[0][1],
+        );
+        let b = m[0].clone() * offset[0] + m[1].clone() * offset[1] + m[2].clone() * offset[2];
+        let r = offset;
+        let k = T::new(3.0) / r2;
+        vec3(
+            ((b[1] * r[2] - b[2] * r[1]) * k - a[0]) * scale,
+            ((b[2] * r[0] - b[0] * r[2]) * k - a[1]) * scale,
+            ((b[0] * r[1] - b[1] * r[0]) * k - a[2]) * scale,
+        )
+    }
+}
+
+/// Sum over all sources at each target, `O(N M)` reference for `BarnesHut`.
+pub fn direct<T, N, K>(kernel: &K, positions: &[VectorN<T, N>], strengths: &[K::Strength], targets: &[VectorN<T, N>]) -> Vec<K::Output>
+    where T: Real,
+          N: Dim<T>,
+          K: Kernel<T, N>,
+{
+    assert_eq!(positions.len(), strengths.len(), "source count mismatch");
+    targets
+        .par_iter()
+        .map(|target| {
+            positions.iter().zip(strengths.iter()).fold(K::Output::zero(), |sum, (position, strength)| {
+                sum + kernel.evaluate(&(target.clone() - position.clone()), strength)
+            })
+        })
+        .collect()
+}
+
+/// Barnes-Hut tree over a set of sources.
+pub struct BarnesHut<T: Real, N: Dim<T>, K: Kernel<T, N>> {
+    pub kernel: K,
+    /// Opening angle, cells with `size < θ distance` are approximated.
+    pub theta: T,
+    tree: Orthtree<T, N>,
+    /// Strengths in tree order.
+    strengths: Vec<K::Strength>,
+    /// Total strength and center of weight of each tree node.
+    moments: Vec<(K::Strength, VectorN<T, N>)>,
+    /// First moments of the strengths around the center of weight, `N` per tree node.
+    dipoles: Vec<K::Strength>,
+}
+
+impl<T, N, K> BarnesHut<T, N, K>
+    where T: Real,
+          N: Dim<T>,
+          K: Kernel<T, N>,
+{
+    pub fn new(kernel: K, theta: T) -> Self {
+        BarnesHut {
+            kernel,
+            theta,
+            tree: Orthtree::new(),
+            strengths: Vec::new(),
+            moments: Vec::new(),
+            dipoles: Vec::new(),
+        }
+    }
+
+    /// Maximum number of sources per leaf, 8 by default.
+    pub fn with_leaf_size(mut self, leaf_size: usize) -> Self {
+        self.tree = self.tree.with_leaf_size(leaf_size);
+        self
+    }
+
+    pub fn tree(&self) -> &Orthtree<T, N> {
+        &self.tree
+    }
+
+    /// Rebuild the tree for a new set of sources.
+    pub fn build(&mut self, positions: &[VectorN<T, N>], strengths: &[K::Strength]) {
+        assert_eq!(positions.len(), strengths.len(), "source count mismatch");
+        self.tree.build(positions);
+        self.strengths = self.tree.order().iter().map(|&i| strengths[i].clone()).collect();
+        self.moments = self.tree.nodes().iter().map(|node| (K::Strength::zero(), node.center.clone())).collect();
+        self.dipoles = vec![K::Strength::zero(); self.moments.len() * N::to_usize()];
+        if !self.moments.is_empty() {
+            self.accumulate(0);
+        }
+    }
+
+    /// Total strength, weighted position sum and total weight of a node.
+    fn accumulate(&mut self, index: usize) -> (K::Strength, VectorN<T, N>, T) {
+        let mut strength = K::Strength::zero();
+        let mut moment = VectorN::zero();
+        let mut weight = T::zero();
+        let (children, start, end) = {
+            let node = &self.tree.nodes()[index];
+            (node.child_nodes(), node.start, node.end)
+        };
+        match children.clone() {
+            Some(children) => for child in children {
+                let (s, m, w) = self.accumulate(child);
+                strength = strength + s;
+                moment = moment + m;
+                weight = weight + w;
+            },
+            None => for i in start..end {
+                let w = self.kernel.weight(&self.strengths[i]);
+                strength = strength + self.strengths[i].clone();
+                moment = moment + self.tree.points()[i].clone() * w;
+                weight = weight + w;
+            },
+        }
+
+        if weight > T::zero() {
+            self.moments[index].1 = moment.clone() / weight;
+        }
+        self.moments[index].0 = strength.clone();
+
+        // first moments around the center, shifted from the children
+        let dim = N::to_usize();
+        let center = self.moments[index].1.clone();
+        for d in 0..dim {
+            let mut dipole = K::Strength::zero();
+            match children.clone() {
+                Some(children) => for child in children {
+                    let (ref s, ref c) = self.moments[child];
+                    dipole = dipole + self.dipoles[child * dim + d].clone() + s.clone() * (c[d] - center[d]);
+                },
+                None => for i in start..end {
+                    dipole = dipole + self.strengths[i].clone() * (self.tree.points()[i][d] - center[d]);
+                },
+            }
+            self.dipoles[index * dim + d] = dipole;
+        }
+        (strength, moment, weight)
+    }
+
+    /// Approximate sum over all sources at a target.
+    pub fn evaluate(&self, target: &VectorN<T, N>) -> K::Output {
+        let mut sum = K::Output::zero();
+        let nodes = self.tree.nodes();
+        if nodes.is_empty() {
+            return sum;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &nodes[index];
+            if node.is_empty() {
+                continue;
+            }
+            match node.child_nodes() {
+                Some(children) => {
+                    let (ref strength, ref centroid) = self.moments[index];
+                    let offset = target.clone() - centroid.clone();
+                    if node.size * node.size < self.theta * self.theta * node.bounds().distance2(target) {
+                        let dim = N::to_usize();
+                        let dipole = &self.dipoles[index * dim..(index + 1) * dim];
+                        sum = sum + self.kernel.evaluate(&offset, strength) + self.kernel.evaluate_dipole(&offset, dipole);
+                    } else {
+                        stack.extend(children);
+                    }
+                }
+                None => for i in node.start..node.end {
+                    let offset = target.clone() - self.tree.points()[i].clone();
+                    sum = sum + self.kernel.evaluate(&offset, &self.strengths[i]);
+                },
+            }
+        }
+        sum
+    }
+
+    /// Approximate sums at all targets in parallel.
+    pub fn evaluate_all(&self, targets: &[VectorN<T, N>]) -> Vec<K::Output> {
+        targets.par_iter().map(|target| self.evaluate(target)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::rng::{Pcg32, DEFAULT_SEED};
+    use rand::Rng;
+    use super::*;
+
+    fn max_error<N: Dim<f64>>(approx: &[VectorN<f64, N>], exact: &[VectorN<f64, N>]) -> f64
+        where VectorN<f64, N>: Copy
+    {
+        let scale = exact.iter().fold(0.0f64, |m, v| m.max(v.clone().magnitude()));
+        approx.iter().zip(exact).fold(0.0f64, |m, (a, e)| m.max((a.clone() - e.clone()).magnitude())) / scale
+    }
+
+    #[test]
+    fn barnes_hut_matches_direct() {
+        let mut rng = Pcg32::new(DEFAULT_SEED);
+
+        // 2d point vortices of both signs
+        let positions = (0..800).map(|_| vec2(rng.gen::<f64>(), rng.gen::<f64>() * 0.5)).collect::<Vec<_>>();
+        let circulations = (0..800).map(|_| rng.gen::<f64>() - 0.5).collect::<Vec<_>>();
+        let kernel = BiotSavart2d { core: 0.01 };
+        let exact = direct(&kernel, &positions, &circulations, &positions);
+
+        let mut tree = BarnesHut::new(kernel, 0.0);
+        tree.build(&positions, &circulations);
+        assert!(max_error(&tree.evaluate_all(&positions), &exact) < 1.0e-12);
+
+        tree.theta = 0.5;
+        assert!(max_error(&tree.evaluate_all(&positions), &exact) < 1.0e-2);
+
+        // 3d vortex particles with strengths of all directions
+        let positions = (0..600).map(|_| vec3(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>())).collect::<Vec<_>>();
+        let strengths = (0..600).map(|_| vec3(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5)).collect::<Vec<_>>();
+        let kernel = BiotSavart3d { core: 0.02 };
+        let exact = direct(&kernel, &positions, &strengths, &positions);
+
+        let mut tree = BarnesHut::new(kernel, 0.5);
+        tree.build(&positions, &strengths);
+        assert!(max_error(&tree.evaluate_all(&positions), &exact) < 1.0e-2);
+
+        // 3d gravity with coincident masses
+        let mut positions = (0..600).map(|_| vec3(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>())).collect::<Vec<_>>();
+        positions.extend(vec![vec3(0.5, 0.5, 0.5); 20]);
+        let masses = vec![1.0; positions.len()];
+        let kernel = Gravity { constant: 1.0, softening: 0.05 };
+        let exact = direct(&kernel, &positions, &masses, &positions);
+
+        let mut tree = BarnesHut::new(kernel, 0.5).with_leaf_size(4);
+        tree.build(&positions, &masses);
+        assert!(tree.tree().nodes().len() > 1);
+        assert!(max_error(&tree.evaluate_all(&positions), &exact) < 1.0e-2);
+    }
+}
@@ -0,0 +1,268 @@
+//! Moving rigid obstacles in grid simulations
+//!
+//! Obstacles are rigid bodies with an analytic shape. Each step their signed
+//! distance is rasterized into the fluid fractions of the variational pressure
+//! projection and their velocity is prescribed on the covered part of the
+//! edges, so paddles and propellers push the fluid. In return, the pressure
+//! acting on the covered part of the edges sums up to forces and torques on
+//! the bodies.
+//!
+//! Positions are given in world units `(x, y)` like for the SPH rigid bodies.
+//!
+//! References:
+//!     [BBB07] Christopher Batty, Florence Bertails, and Robert Bridson, 2007,
+//!             A fast variational framework for accurate solid-fluid coupling,
+//!             ACM Trans. Graph. 26, 3, Article 100 (July 2007)
+
+use cgmath::InnerSpace;
+use dec::grid::Staggered2d;
+use dec::manifold::Manifold2d;
+use domain::interp::Centering2d;
+use domain::Grid2d;
+use levelset::{self, LevelSet2d};
+use math::{Real, VectorN};
+use math::vector_n::vec2;
+use ndarray::{ArrayView2, ArrayViewMut2};
+use sph::rigid::RigidBody;
+use typenum::U2;
+
+#[derive(Copy, Clone, Debug)]
+pub enum ObstacleShape<T: Real> {
+    Circle { radius: T },
+    /// Box centered around the center of mass.
+    Rectangle { half_size: VectorN<T, U2> },
+}
+
+impl<T: Real> ObstacleShape<T> {
+    /// Signed distance to a point relative to the center of mass at zero rotation.
+    pub fn distance(&self, p: VectorN<T, U2>) -> T {
+        match *self {
+            ObstacleShape::Circle { radius } => p.magnitude() - radius,
+            ObstacleShape::Rectangle { half_size } => {
+                let q = vec2(p[0].abs() - half_size[0], p[1].abs() - half_size[1]);
+                let outside = vec2(q[0].max(T::zero()), q[1].max(T::zero())).magnitude();
+                outside + q[0].max(q[1]).min(T::zero())
+            }
+        }
+    }
+
+    pub fn area(&self) -> T {
+        match *self {
+            ObstacleShape::Circle { radius } => T::new(::std::f64::consts::PI) * radius * radius,
+            ObstacleShape::Rectangle { half_size } => T::new(4.0) * half_size[0] * half_size[1],
+        }
+    }
+
+    /// Moment of inertia of a solid body with uniform density.
+    pub fn inertia(&self, mass: T) -> T {
+        match *self {
+            ObstacleShape::Circle { radius } => T::new(0.5) * mass * radius * radius,
+            ObstacleShape::Rectangle { half_size } => {
+                mass * (half_size[0] * half_size[0] + half_size[1] * half_size[1]) / T::new(3.0)
+            }
+        }
+    }
+}
+
+/// Rigid obstacle, the surface samples of the body are unused.
+#[derive(Clone, Debug)]
+pub struct Obstacle<T: Real> {
+    pub shape: ObstacleShape<T>,
+    pub body: RigidBody<T>,
+}
+
+impl<T: Real> Obstacle<T> {
+    /// Free obstacle at rest with uniform density.
+    pub fn new(shape: ObstacleShape<T>, position: VectorN<T, U2>, density: T) -> Self {
+        let mass = density * shape.area();
+        Obstacle {
+            shape,
+            body: RigidBody::new(mass, shape.inertia(mass), position, Vec::new()),
+        }
+    }
+
+    /// Obstacle moving with a prescribed velocity, unaffected by the fluid.
+    pub fn kinematic(shape: ObstacleShape<T>, position: VectorN<T, U2>, velocity: VectorN<T, U2>, angular_velocity: T) -> Self {
+        let mut obstacle = Obstacle::new(shape, position, T::one());
+        obstacle.body.kinematic = true;
+        obstacle.body.velocity = velocity;
+        obstacle.body.angular_velocity = angular_velocity;
+        obstacle
+    }
+
+    /// Signed distance to a point in world space.
+    pub fn distance(&self, point: &VectorN<T, U2>) -> T {
+        let r = *point - self.body.position;
+        let (sin, cos) = self.body.rotation.sin_cos();
+        self.shape.distance(vec2(cos * r[0] + sin * r[1], cos * r[1] - sin * r[0]))
+    }
+}
+
+/// Index of the obstacle closest to a point.
+fn closest<T: Real>(obstacles: &[Obstacle<T>], point: &VectorN<T, U2>) -> Option<(usize, T)> {
+    obstacles.iter().enumerate().fold(None, |closest, (i, obstacle)| {
+        let distance = obstacle.distance(point);
+        match closest {
+            Some((_, min)) if min <= distance => closest,
+            _ => Some((i, distance)),
+        }
+    })
+}
+
+/// World position of a grid sample.
+fn sample_position<T: Real>(grid: &Grid2d, centering: Centering2d, (y, x): (usize, usize)) -> VectorN<T, U2> {
+    let (dy, dx) = grid.spacing();
+    let offset = centering.offset::<T>();
+    vec2((T::new(x) + offset.1) * T::new(dx), (T::new(y) + offset.0) * T::new(dy))
+}
+
+/// Fraction of each edge open to the fluid, see `Projection::fluid_fractions`.
+pub fn fluid_fractions<T: Real>(grid: &Grid2d, obstacles: &[Obstacle<T>]) -> Staggered2d<T> {
+    if obstacles.is_empty() {
+        let mut fractions = <Grid2d as Manifold2d<T>>::new_simplex_1(grid);
+        fractions.split_mut().0.fill(T::one());
+        fractions.split_mut().1.fill(T::one());
+        return fractions;
+    }
+
+    // distances in units of the finer spacing
+    let (dy, dx) = grid.spacing();
+    let (dy, dx, h) = (T::new(dy), T::new(dx), T::new(dy.min(dx)));
+    let solid = LevelSet2d::from_fn(grid, |(y, x)| {
+        closest(obstacles, &vec2(x * dx, y * dy)).unwrap().1 / h
+    });
+    levelset::fluid_fractions(grid, &solid)
+}
+
+/// Velocity of the closest obstacle on each edge, see `Projection::solid_velocity`.
+pub fn solid_velocity<T: Real>(grid: &Grid2d, obstacles: &[Obstacle<T>]) -> Staggered2d<T> {
+    let mut velocity = <Grid2d as Manifold2d<T>>::new_simplex_1(grid);
+    let (dy, dx) = grid.spacing();
+    {
+        let (vertical, horizontal) = velocity.split_mut();
+        solid_velocity_component(grid, obstacles, vertical, Centering2d::Vertical, 1, T::new(dy));
+        solid_velocity_component(grid, obstacles, horizontal, Centering2d::Horizontal, 0, T::new(dx));
+    }
+    velocity
+}
+
+fn solid_velocity_component<T: Real>(
+    grid: &Grid2d,
+    obstacles: &[Obstacle<T>],
+    mut edges: ArrayViewMut2<T>,
+    centering: Centering2d,
+    axis: usize,
+    spacing: T,
+) {
+    for (idx, v) in edges.indexed_iter_mut() {
+        let pos = sample_position(grid, centering, idx);
+        if let Some((i, _)) = closest(obstacles, &pos) {
+            *v = obstacles[i].body.velocity_at(&pos)[axis] * spacing;
+        }
+    }
+}
+
+/// Accumulate the pressure forces `-∫ ∇p` over the solid volume on the obstacles.
+///
+/// Each partially covered edge contributes `ρ (1 - F) g A / l` to the closest
+/// obstacle, with the cell area `A`, edge length `l` and the pressure gradient `g`
+/// of the last projection (see `Projection::pressure_gradient`).
+///
+/// Ref: [BBB07] Sec. 5
+pub fn pressure_forces<T: Real>(
+    grid: &Grid2d,
+    obstacles: &mut [Obstacle<T>],
+    fractions: &Staggered2d<T>,
+    gradient: &Staggered2d<T>,
+    fluid_density: T,
+) {
+    let (dy, dx) = grid.spacing();
+    let (fractions_vertical, fractions_horizontal) = fractions.split();
+    let (gradient_vertical, gradient_horizontal) = gradient.split();
+    let (vertical, horizontal) = (fluid_density * T::new(dx), fluid_density * T::new(dy));
+    pressure_forces_component(grid, obstacles, fractions_vertical, gradient_vertical, Centering2d::Vertical, 1, vertical);
+    pressure_forces_component(grid, obstacles, fractions_horizontal, gradient_horizontal, Centering2d::Horizontal, 0, horizontal);
+}
+
+fn pressure_forces_component<T: Real>(
+    grid: &Grid2d,
+    obstacles: &mut [Obstacle<T>],
+    fractions: ArrayView2<T>,
+    gradient: ArrayView2<T>,
+    centering: Centering2d,
+    axis: usize,
+    scale: T,
+) {
+    for (idx, &fraction) in fractions.indexed_iter() {
+        if fraction >= T::one() {
+            continue;
+        }
+        let pos = sample_position(grid, centering, idx);
+        if let Some((i, _)) = closest(obstacles, &pos) {
+            let mut force = vec2(T::zero(), T::zero());
+            force[axis] = scale * (T::one() - fraction) * gradient[idx];
+            obstacles[i].body.apply_force(force, &pos);
+        }
+    }
+}
+
+/// Integrate the obstacles with the accumulated forces.
+pub fn advance<T: Real>(obstacles: &mut [Obstacle<T>], gravity: VectorN<T, U2>, timestep: T) {
+    for obstacle in obstacles {
+        obstacle.body.integrate(gravity, timestep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::projection::Projection;
+    use domain::AxisBoundary;
+    use super::*;
+
+    #[test]
+    fn moving_obstacles() {
+        // paddle moving right pushes the fluid in a closed box
+        let grid = Grid2d::new((16, 16));
+        let paddle = Obstacle::kinematic(
+            ObstacleShape::Rectangle { half_size: vec2(1.0, 3.0) },
+            vec2(6.0, 8.0), vec2(1.0, 0.0), 0.0);
+        let obstacles = vec![paddle];
+        let mut projection = Projection::new(&grid, 500, 1.0e-8);
+        projection.fluid_fractions = Some(fluid_fractions(&grid, &obstacles));
+        projection.solid_velocity = Some(solid_velocity(&grid, &obstacles));
+
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut pressure = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        projection.project(&mut velocity, &mut pressure, 0.1);
+        {
+            let (_, horizontal) = velocity.split();
+            assert!(horizontal[(8, 6)] == 1.0);
+            assert!(horizontal[(8, 9)] > 0.1);
+            // flow returns around the paddle
+            assert!(horizontal[(1, 6)] < 0.0);
+        }
+
+        // uniform flow in a periodic channel pushes a free body downstream
+        let grid = Grid2d::with_boundary((16, 16), [AxisBoundary::default(), AxisBoundary::periodic()]);
+        let mut obstacles = vec![Obstacle::new(ObstacleShape::Circle { radius: 3.0 }, vec2(8.0, 8.0), 1.0)];
+        let mut projection = Projection::new(&grid, 500, 1.0e-8);
+        let fractions = fluid_fractions(&grid, &obstacles);
+        projection.fluid_fractions = Some(fractions.clone());
+        projection.solid_velocity = Some(solid_velocity(&grid, &obstacles));
+
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut pressure = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        velocity.split_mut().1.fill(1.0);
+        projection.project(&mut velocity, &mut pressure, 0.1);
+
+        let mut gradient = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        projection.pressure_gradient(&mut gradient, &pressure);
+        pressure_forces(&grid, &mut obstacles, &fractions, &gradient, 1.0);
+        let (force, torque) = obstacles[0].body.force();
+        assert!(force[0] > 0.0);
+        assert!(force[1].abs() < 1.0e-3 * force[0] && torque.abs() < 1.0e-3 * force[0]);
+
+        advance(&mut obstacles, vec2(0.0, 0.0), 0.1);
+        assert!(obstacles[0].body.velocity[0] > 0.0);
+    }
+}
@@ -5,7 +5,8 @@ use math::{integration, Real};
 use ndarray::{Array2, ArrayView2, ArrayViewMut2, Axis};
 use num::Zero;
 use num::complex::Complex;
-use rand;
+use math::rng::Pcg32;
+use rand::Rng;
 use rand::distributions::normal;
 
 use std::f32::consts::PI;
@@ -86,10 +87,13 @@ pub struct Parameters<T> {
     pub domain_size: T,
 }
 
+/// Random amplitudes and phases are drawn from per-cell streams of `rng`,
+/// the spectrum only depends on the seed.
 pub fn build_height_spectrum<S, T>(
     parameters: &Parameters<T>,
     spectrum: &S,
-    resolution: usize) -> (Array2<Complex<T>>, Array2<T>)
+    resolution: usize,
+    rng: &Pcg32) -> (Array2<Complex<T>>, Array2<T>)
 where
     S: Spectrum<T>,
     T: Real
@@ -110,7 +114,8 @@ where
                 pi * x / parameters.domain_size,
                 pi * y / parameters.domain_size,
             );
-            sample_spectrum(parameters, spectrum, k)
+            let mut rng = rng.stream((j * resolution + i) as u64);
+            sample_spectrum(parameters, spectrum, k, &mut rng)
         };
 
         *height_spectrum = sample.0;
@@ -120,13 +125,15 @@ where
     (height_spectrum, omega)
 }
 
-fn sample_spectrum<S, T>(
+fn sample_spectrum<S, T, R>(
     parameters: &Parameters<T>,
     spectrum: &S,
-    pos: cgmath::Vector2<T>) -> (Complex<T>, T)
+    pos: cgmath::Vector2<T>,
+    rng: &mut R) -> (Complex<T>, T)
 where
     S: Spectrum<T>,
-    T: Real
+    T: Real,
+    R: Rng
 {
     if pos.magnitude() < T::default_epsilon() {
         return (Complex::new(T::zero(), T::zero()), T::zero());
@@ -139,8 +146,8 @@ where
     let spreading = directional_spreading(parameters, omega, theta, directional_base_donelan_banner);
     let sample = spectrum.evaluate(omega);
 
-    let normal::StandardNormal(z) = rand::random();
-    let phase = T::new(2.0 * PI) * rand::random::<T>();
+    let normal::StandardNormal(z) = rng.gen();
+    let phase = T::new(2.0 * PI) * rng.gen::<T>();
 
     let amplitude = T::new(z as f32) * (T::new(2.0) * spreading * sample * grad_k.powi(2) * grad_omega / pos.magnitude()).sqrt();
 
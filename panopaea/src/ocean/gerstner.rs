@@ -0,0 +1,243 @@
+//! Gerstner waves
+//!
+//! Superposition of trochoidal waves: surface points move on circles, which
+//! sharpens the crests and flattens the troughs compared to sine waves. The
+//! surface point starting at `x0` on the mean water level moves to
+//!
+//! ```text
+//! x = x0 - Σ (k_i / |k_i|) q_i sin(θ_i),  y = Σ A_i cos(θ_i),  θ_i = k_i·x0 - ω_i t + φ_i
+//! ```
+//!
+//! with horizontal amplitudes `q_i = λ Q_i / |k_i|`. The surface doesn't loop
+//! as long as the choppiness `λ` times the sum of the steepness `Q_i` stays
+//! below one. Waves follow the deep water dispersion `ω = sqrt(g |k|)`.
+//!
+//! Horizontal positions are `(x, z)` pairs, the height points along `y`.
+//!
+//! References:
+//!     [Tes01] Jerry Tessendorf, 2001,
+//!             Simulating ocean water,
+//!             SIGGRAPH 2001 Course Notes
+//!     [FK04] Mark Finch, 2004,
+//!            Effective water simulation from physical models,
+//!            GPU Gems, Chapter 1
+
+use cgmath::{vec2, vec3, InnerSpace, Vector2, Vector3};
+use math::Real;
+use ndarray::Array2;
+use rand::Rng;
+
+use std::f64::consts::PI;
+
+use super::tessendorf::WaveSpectrum;
+
+/// Fixed point iterations for inverting the horizontal displacement.
+const HEIGHT_ITERATIONS: usize = 16;
+
+#[derive(Copy, Clone, Debug)]
+pub struct GerstnerWave<T> {
+    /// Propagation direction in radians, counterclockwise from `+x` towards `+z`.
+    pub direction: T,
+    pub wave_number: T,
+    pub amplitude: T,
+    /// Steepness `Q` in `[0, 1]`, a single wave with `Q = 1` forms a cusp.
+    pub steepness: T,
+    pub phase: T,
+}
+
+impl<T: Real> GerstnerWave<T> {
+    /// Wave with the maximal steepness `Q = k A` for which points move on circles.
+    pub fn new(direction: T, wavelength: T, amplitude: T) -> Self {
+        let wave_number = T::new(2.0 * PI) / wavelength;
+        GerstnerWave {
+            direction,
+            wave_number,
+            amplitude,
+            steepness: (wave_number * amplitude).min(T::one()),
+            phase: T::zero(),
+        }
+    }
+
+    fn wave_vector(&self) -> Vector2<T> {
+        vec2(self.direction.cos(), self.direction.sin()) * self.wave_number
+    }
+}
+
+pub struct GerstnerField<T> {
+    pub waves: Vec<GerstnerWave<T>>,
+    /// Scale `λ` of the horizontal displacement.
+    pub choppiness: T,
+    pub gravity: T, // [m/s^2]
+}
+
+impl<T: Real> GerstnerField<T> {
+    pub fn new(gravity: T) -> Self {
+        GerstnerField {
+            waves: Vec::new(),
+            choppiness: T::one(),
+            gravity,
+        }
+    }
+
+    /// Sample waves from a directional spectrum.
+    ///
+    /// Wave vectors are drawn uniformly from the annulus between `k_min` and
+    /// `k_max` with random phases, the amplitudes match the spectral energy
+    /// of the area around each sample. The total steepness is set to `0.5`.
+    pub fn from_spectrum<S, R>(spectrum: &S, num_waves: usize, (k_min, k_max): (T, T), gravity: T, rng: &mut R) -> Self
+        where S: WaveSpectrum<T>,
+              R: Rng,
+    {
+        let two_pi = T::new(2.0 * PI);
+        let area = T::new(PI) * (k_max * k_max - k_min * k_min) / T::new(num_waves as f64);
+
+        let mut field = GerstnerField::new(gravity);
+        for _ in 0..num_waves {
+            let wave_number = (k_min * k_min + rng.gen::<T>() * (k_max * k_max - k_min * k_min)).sqrt();
+            let direction = two_pi * rng.gen::<T>();
+            let k = vec2(direction.cos(), direction.sin()) * wave_number;
+            field.waves.push(GerstnerWave {
+                direction,
+                wave_number,
+                amplitude: (T::new(2.0) * spectrum.density(k) * area).sqrt(),
+                steepness: T::zero(),
+                phase: two_pi * rng.gen::<T>(),
+            });
+        }
+        field.set_steepness(T::new(0.5));
+        field
+    }
+
+    /// Distribute the total steepness evenly over all waves.
+    ///
+    /// Ref: [FK04] Eq. 9
+    pub fn set_steepness(&mut self, total: T) {
+        let steepness = total / T::new(self.waves.len() as f64);
+        for wave in &mut self.waves {
+            wave.steepness = steepness;
+        }
+    }
+
+    fn omega(&self, wave: &GerstnerWave<T>) -> T {
+        (self.gravity * wave.wave_number).sqrt()
+    }
+
+    fn angle(&self, wave: &GerstnerWave<T>, position: Vector2<T>, time: T) -> T {
+        wave.wave_vector().dot(position) - self.omega(wave) * time + wave.phase
+    }
+
+    /// Displacement of the surface point at rest position `(x, z)`.
+    ///
+    /// Ref: [Tes01] Eq. 6, 9
+    pub fn displacement(&self, position: Vector2<T>, time: T) -> Vector3<T> {
+        let mut displacement = vec3(T::zero(), T::zero(), T::zero());
+        for wave in &self.waves {
+            let (sin, cos) = self.angle(wave, position, time).sin_cos();
+            let horizontal = self.choppiness * wave.steepness / wave.wave_number * sin;
+            displacement.x -= wave.direction.cos() * horizontal;
+            displacement.y += wave.amplitude * cos;
+            displacement.z -= wave.direction.sin() * horizontal;
+        }
+        displacement
+    }
+
+    /// Velocity of the surface point at rest position `(x, z)`.
+    pub fn surface_velocity(&self, position: Vector2<T>, time: T) -> Vector3<T> {
+        let mut velocity = vec3(T::zero(), T::zero(), T::zero());
+        for wave in &self.waves {
+            let omega = self.omega(wave);
+            let (sin, cos) = self.angle(wave, position, time).sin_cos();
+            let horizontal = self.choppiness * wave.steepness / wave.wave_number * omega * cos;
+            velocity.x += wave.direction.cos() * horizontal;
+            velocity.y += wave.amplitude * omega * sin;
+            velocity.z += wave.direction.sin() * horizontal;
+        }
+        velocity
+    }
+
+    /// Surface height above the horizontal position `(x, z)`.
+    ///
+    /// The rest position of the surface point is found by fixed point iteration,
+    /// which converges for surfaces without loops.
+    pub fn height(&self, position: Vector2<T>, time: T) -> T {
+        let mut rest = position;
+        for _ in 0..HEIGHT_ITERATIONS {
+            let displacement = self.displacement(rest, time);
+            rest = position - vec2(displacement.x, displacement.z);
+        }
+        self.displacement(rest, time).y
+    }
+
+    /// Fluid velocity of linear wave theory at `(x, y, z)` below the mean water level `y = 0`.
+    ///
+    /// Orbital velocities decay with `e^{|k| y}`, suited for seeding particles
+    /// below the surface or for inflow boundaries.
+    pub fn velocity(&self, position: Vector3<T>, time: T) -> Vector3<T> {
+        let horizontal = vec2(position.x, position.z);
+        let mut velocity = vec3(T::zero(), T::zero(), T::zero());
+        for wave in &self.waves {
+            let omega = self.omega(wave);
+            let decay = (wave.wave_number * position.y.min(T::zero())).exp();
+            let (sin, cos) = self.angle(wave, horizontal, time).sin_cos();
+            let speed = wave.amplitude * omega * decay;
+            velocity.x += wave.direction.cos() * speed * cos;
+            velocity.y += speed * sin;
+            velocity.z += wave.direction.sin() * speed * cos;
+        }
+        velocity
+    }
+
+    /// Displacements of the rest positions `(x, z) = (i, j) * spacing`, indexed by `(j, i)`.
+    pub fn displacement_field(&self, dim: (usize, usize), spacing: T, time: T) -> Array2<Vector3<T>> {
+        Array2::from_shape_fn(dim, |(j, i)| {
+            self.displacement(vec2(T::new(i as f64), T::new(j as f64)) * spacing, time)
+        })
+    }
+
+    /// Surface velocities of the rest positions `(x, z) = (i, j) * spacing`, indexed by `(j, i)`.
+    pub fn velocity_field(&self, dim: (usize, usize), spacing: T, time: T) -> Array2<Vector3<T>> {
+        Array2::from_shape_fn(dim, |(j, i)| {
+            self.surface_velocity(vec2(T::new(i as f64), T::new(j as f64)) * spacing, time)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ocean::tessendorf::Phillips;
+    use rand::{SeedableRng, XorShiftRng};
+    use super::*;
+
+    #[test]
+    fn gerstner_waves() {
+        let gravity = 9.81;
+        let mut field = GerstnerField::new(gravity);
+        field.waves.push(GerstnerWave::new(0.5, 10.0, 0.5));
+        let omega = (gravity * 2.0 * PI / 10.0).sqrt();
+
+        // points move on circles with the orbital speed `A ω`
+        let displacements = field.displacement_field((4, 8), 1.3, 0.7);
+        let velocities = field.velocity_field((4, 8), 1.3, 0.7);
+        for (d, v) in displacements.iter().zip(velocities.iter()) {
+            assert!((d.magnitude() - 0.5).abs() < 1.0e-9);
+            assert!((v.magnitude() - 0.5 * omega).abs() < 1.0e-9);
+            assert!(d.dot(*v).abs() < 1.0e-9);
+        }
+        assert!((field.velocity(vec3(0.3, 0.0, 0.1), 0.7).magnitude() - 0.5 * omega).abs() < 1.0e-9);
+        assert!(field.velocity(vec3(0.3, -10.0, 0.1), 0.7).magnitude() < 0.01 * 0.5 * omega);
+
+        let mut rng = XorShiftRng::from_seed([4, 3, 2, 1]);
+        let spectrum = Phillips { amplitude: 1.0e-3, wind_speed: 8.0, wind_direction: 0.0, gravity, suppression: 0.1 };
+        let field = GerstnerField::from_spectrum(&spectrum, 64, (0.05, 5.0), gravity, &mut rng);
+        let total = field.waves.iter().fold(0.0, |sum, w| sum + w.steepness);
+        assert!((total - 0.5).abs() < 1.0e-9);
+        assert!(field.waves.iter().any(|w| w.amplitude > 0.0));
+
+        // heights of displaced surface points
+        for &(x, z) in &[(0.0, 0.0), (3.2, -1.5), (10.0, 7.5)] {
+            let d = field.displacement(vec2(x, z), 1.5);
+            let height = field.height(vec2(x + d.x, z + d.z), 1.5);
+            assert!((height - d.y).abs() < 1.0e-4, "{} {}", height, d.y);
+        }
+    }
+}
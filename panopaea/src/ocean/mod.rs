@@ -7,5 +7,10 @@
 //!                In Proceedings of the 2015 Symposium on Digital Production (DigiPro '15),
 //!                Stephen Spencer (Ed.). ACM, New York, NY, USA, 29-39,
 //!                DOI=http://dx.doi.org/10.1145/2791261.2791267
+//!     [Tes01] Jerry Tessendorf, 2001,
+//!             Simulating ocean water,
+//!             SIGGRAPH 2001 Course Notes
 
 pub mod empirical;
+pub mod gerstner;
+pub mod tessendorf;
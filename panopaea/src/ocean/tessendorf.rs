@@ -0,0 +1,308 @@
+//! FFT ocean synthesis
+//!
+//! The surface is a sum of linear waves with random Fourier amplitudes drawn
+//! from a wave spectrum. Each amplitude rotates with the deep water dispersion
+//! relation `ω = sqrt(g |k|)`, heights, horizontal displacements and slopes of
+//! a time are evaluated with inverse FFTs. The surface tiles periodically.
+//!
+//! Fields are indexed by `(z, x)` over a square patch with `resolution` samples
+//! per side, the height points along `y`.
+//!
+//! References:
+//!     [Tes01] Jerry Tessendorf, 2001,
+//!             Simulating ocean water,
+//!             SIGGRAPH 2001 Course Notes
+
+use cgmath::{vec2, InnerSpace, Vector2, Vector3};
+use fft;
+use math::Real;
+use math::fft::{Fft2d, Normalization, Planner};
+use ndarray::Array2;
+use num::complex::Complex;
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+
+use std::f64::consts::PI;
+
+use super::empirical::Spectrum;
+
+/// Directional spectral density over the wave vector.
+///
+/// The density is per unit area of wave vectors, the mean square height of
+/// the surface is the integral over all wave vectors.
+pub trait WaveSpectrum<T: Real>: Sync {
+    fn density(&self, k: Vector2<T>) -> T;
+}
+
+/// Phillips spectrum for wind driven waves.
+///
+/// Ref: [Tes01] Eq. 40, 41
+pub struct Phillips<T> {
+    /// Scale `A` of the spectrum.
+    pub amplitude: T,
+    pub wind_speed: T, // [m/s]
+    /// Wind direction in radians, counterclockwise from `+x` towards `+z`.
+    pub wind_direction: T,
+    pub gravity: T, // [m/s^2]
+    /// Waves shorter than this length `l` are suppressed.
+    pub suppression: T, // [m]
+}
+
+impl<T: Real> WaveSpectrum<T> for Phillips<T> {
+    fn density(&self, k: Vector2<T>) -> T {
+        let k_len = k.magnitude();
+        if k_len < T::default_epsilon() {
+            return T::zero();
+        }
+
+        // largest waves for the wind speed
+        let length = self.wind_speed * self.wind_speed / self.gravity;
+        let wind = vec2(self.wind_direction.cos(), self.wind_direction.sin());
+        let alignment = (k / k_len).dot(wind);
+
+        self.amplitude * (-T::one() / (k_len * length).powi(2)).exp() / k_len.powi(4)
+            * alignment * alignment
+            * (-(k_len * self.suppression).powi(2)).exp()
+    }
+}
+
+/// Frequency spectrum like JONSWAP with `cos^2` directional spreading around the wind.
+///
+/// Converts `S(ω)` to wave vectors with `E(k) = S(ω) D(θ) (dω/dk) / |k|`.
+pub struct Directional<T, S> {
+    pub spectrum: S,
+    /// Wind direction in radians, counterclockwise from `+x` towards `+z`.
+    pub wind_direction: T,
+    pub gravity: T, // [m/s^2]
+}
+
+impl<T: Real, S: Spectrum<T>> WaveSpectrum<T> for Directional<T, S> {
+    fn density(&self, k: Vector2<T>) -> T {
+        let k_len = k.magnitude();
+        if k_len < T::default_epsilon() {
+            return T::zero();
+        }
+
+        let alignment = k.y.atan2(k.x) - self.wind_direction;
+        let spreading = T::new(2.0 / PI) * alignment.cos().max(T::zero()).powi(2);
+        let omega = (self.gravity * k_len).sqrt();
+        let grad_omega = self.gravity / (T::new(2.0) * omega);
+
+        self.spectrum.evaluate(omega) * spreading * grad_omega / k_len
+    }
+}
+
+/// Wave number of an FFT index, the upper half maps to negative wave numbers.
+fn wave_number<T: Real>(index: usize, resolution: usize, size: T) -> T {
+    let n = if index < resolution / 2 { index as isize } else { index as isize - resolution as isize };
+    T::new(2.0 * PI * n as f64) / size
+}
+
+/// Periodic ocean patch synthesized from a wave spectrum.
+pub struct FftOcean<T: fft::FFTnum> {
+    resolution: usize,
+    size: T,
+    gravity: T,
+
+    /// Initial amplitudes `h0(k)`.
+    amplitudes: Array2<Complex<T>>,
+    omega: Array2<T>,
+
+    /// Scale `λ` of the horizontal displacement.
+    pub choppiness: T,
+
+    /// Surface height.
+    pub height: Array2<T>,
+    /// Horizontal displacement `(x, z)` of the surface points.
+    pub displacement: Array2<Vector2<T>>,
+    /// Normals of the undisplaced heightfield.
+    pub normals: Array2<Vector3<T>>,
+
+    plan: Fft2d<T>,
+    spectrum: Array2<Complex<T>>,
+    data: Array2<Complex<T>>,
+}
+
+impl<T> FftOcean<T> where T: Real + fft::FFTnum {
+    /// Draw random amplitudes for a square patch with side length `size`.
+    ///
+    /// Ref: [Tes01] Eq. 42
+    pub fn new<S, R>(resolution: usize, size: T, gravity: T, spectrum: &S, rng: &mut R) -> Self
+        where S: WaveSpectrum<T>,
+              R: Rng,
+    {
+        assert!(resolution % 2 == 0, "Resolution has to be even");
+        let zero = Complex::new(T::zero(), T::zero());
+        let delta_k = T::new(2.0 * PI) / size;
+        let nyquist = resolution / 2;
+
+        // Nyquist modes have no conjugate partner and would leave imaginary parts.
+        let mut amplitudes = Array2::from_elem((resolution, resolution), zero);
+        for ((j, i), amplitude) in amplitudes.indexed_iter_mut() {
+            if j == nyquist || i == nyquist || (j, i) == (0, 0) {
+                continue;
+            }
+            let k = vec2(wave_number(i, resolution, size), wave_number(j, resolution, size));
+            let scale = (T::new(0.5) * spectrum.density(k)).sqrt() * delta_k;
+            let StandardNormal(re) = rng.gen();
+            let StandardNormal(im) = rng.gen();
+            *amplitude = Complex::new(T::new(re) * scale, T::new(im) * scale);
+        }
+
+        let omega = Array2::from_shape_fn((resolution, resolution), |(j, i)| {
+            let k = vec2(wave_number(i, resolution, size), wave_number(j, resolution, size));
+            (gravity * k.magnitude()).sqrt()
+        });
+
+        FftOcean {
+            resolution,
+            size,
+            gravity,
+
+            amplitudes,
+            omega,
+
+            choppiness: T::one(),
+
+            height: Array2::zeros((resolution, resolution)),
+            displacement: Array2::from_elem((resolution, resolution), vec2(T::zero(), T::zero())),
+            normals: Array2::from_elem((resolution, resolution), Vector3::new(T::zero(), T::one(), T::zero())),
+
+            plan: Fft2d::new(&mut Planner::new(), (resolution, resolution), Normalization::None),
+            spectrum: Array2::from_elem((resolution, resolution), zero),
+            data: Array2::from_elem((resolution, resolution), zero),
+        }
+    }
+
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    /// Side length of the patch.
+    pub fn size(&self) -> T {
+        self.size
+    }
+
+    pub fn gravity(&self) -> T {
+        self.gravity
+    }
+
+    /// Evaluate height, displacement and normals at the given time.
+    ///
+    /// Ref: [Tes01] Eq. 43, 44, 29
+    pub fn evaluate(&mut self, time: T) {
+        let n = self.resolution;
+
+        // h(k, t) = h0(k) e^{-iωt} + conj(h0(-k)) e^{iωt}
+        {
+            let amplitudes = &self.amplitudes;
+            par_azip!(
+                index (j, i),
+                mut spectrum (&mut self.spectrum),
+                omega (&self.omega)
+            in {
+                let (sin, cos) = (omega * time).sin_cos();
+                let conjugate = amplitudes[((n - j) % n, (n - i) % n)].conj();
+                *spectrum = amplitudes[(j, i)] * Complex::new(cos, -sin) + conjugate * Complex::new(cos, sin);
+            });
+        }
+
+        let i = Complex::new(T::zero(), T::one());
+        let direction = |k: Vector2<T>| {
+            let len = k.magnitude();
+            if len < T::default_epsilon() { vec2(T::zero(), T::zero()) } else { k / len }
+        };
+
+        self.transform(|_| Complex::new(T::one(), T::zero()));
+        for (height, value) in self.height.iter_mut().zip(self.data.iter()) {
+            *height = value.re;
+        }
+
+        let choppiness = self.choppiness;
+        self.transform(|k| -i * direction(k).x * choppiness);
+        for (displacement, value) in self.displacement.iter_mut().zip(self.data.iter()) {
+            displacement.x = value.re;
+        }
+        self.transform(|k| -i * direction(k).y * choppiness);
+        for (displacement, value) in self.displacement.iter_mut().zip(self.data.iter()) {
+            displacement.y = value.re;
+        }
+
+        // slopes, the x component is kept in the normal until the z slope is known
+        self.transform(|k| i * k.x);
+        for (normal, value) in self.normals.iter_mut().zip(self.data.iter()) {
+            normal.x = -value.re;
+        }
+        self.transform(|k| i * k.y);
+        for (normal, value) in self.normals.iter_mut().zip(self.data.iter()) {
+            *normal = Vector3::new(normal.x, T::one(), -value.re).normalize();
+        }
+    }
+
+    /// Multiply the current spectrum by `factor(k)` and transform into `self.data`.
+    fn transform<F>(&mut self, factor: F)
+        where F: Fn(Vector2<T>) -> Complex<T> + Sync
+    {
+        let (n, size) = (self.resolution, self.size);
+        par_azip!(
+            index (j, i),
+            mut data (&mut self.data),
+            spectrum (&self.spectrum)
+        in {
+            let k = vec2(wave_number(i, n, size), wave_number(j, n, size));
+            *data = factor(k) * spectrum;
+        });
+        self.plan.inverse(self.data.view_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, XorShiftRng};
+    use super::*;
+
+    /// Single wave travelling along `+x`.
+    struct Mode(f64);
+
+    impl WaveSpectrum<f64> for Mode {
+        fn density(&self, k: Vector2<f64>) -> f64 {
+            if (k.x - self.0).abs() < 1.0e-6 && k.y.abs() < 1.0e-6 { 1.0 } else { 0.0 }
+        }
+    }
+
+    #[test]
+    fn fft_ocean_waves() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let (n, size, gravity) = (16, 32.0, 9.81);
+        let k = 2.0 * PI * 2.0 / size;
+        let mut ocean = FftOcean::new(n, size, gravity, &Mode(k), &mut rng);
+        ocean.evaluate(0.0);
+        let initial = ocean.height.clone();
+        let amplitude = ocean.height[(0, 0)].hypot(ocean.displacement[(0, 0)].x);
+        assert!(amplitude > 0.0);
+
+        // the wave moves one sample to the right in `dx k / ω`
+        let dx = size / n as f64;
+        ocean.evaluate(dx * k / (gravity * k).sqrt());
+        for j in 0..n {
+            for i in 0..n {
+                assert!((ocean.height[(j, (i + 1) % n)] - initial[(j, i)]).abs() < 1.0e-6 * amplitude);
+                // circular orbits of the surface points
+                let radius = ocean.height[(j, i)].hypot(ocean.displacement[(j, i)].x);
+                assert!((radius - amplitude).abs() < 1.0e-6 * amplitude);
+                assert!(ocean.displacement[(j, i)].y.abs() < 1.0e-6 * amplitude);
+                assert!((ocean.normals[(j, i)].magnitude() - 1.0).abs() < 1.0e-6);
+            }
+        }
+
+        // random phases and heights with zero mean
+        let spectrum = Phillips { amplitude: 1.0, wind_speed: 10.0, wind_direction: 0.3, gravity, suppression: 0.1 };
+        let mut ocean = FftOcean::new(32, 100.0, gravity, &spectrum, &mut rng);
+        ocean.evaluate(2.5);
+        let mean = ocean.height.scalar_sum() / (32.0 * 32.0);
+        let max = ocean.height.iter().fold(0.0f64, |max, h| max.max(h.abs()));
+        assert!(max > 0.0);
+        assert!(mean.abs() < 1.0e-9 * max);
+        assert!(ocean.normals.iter().all(|n| n.y > 0.0));
+    }
+}
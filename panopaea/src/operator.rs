@@ -0,0 +1,223 @@
+//! Linear operators
+//!
+//! Common interface of assembled matrices and matrix-free operators for the
+//! iterative solvers in `pcg`. Sparse and diagonal matrices are used by
+//! reference, DEC operator chains are composed from closures applying the
+//! derivatives and hodge stars of a manifold without assembling them.
+//!
+//! Plain closures `FnMut(&mut L, &L)` are treated as symmetric operators of
+//! unknown size, which covers the operators passed to the solvers so far.
+//! `MatrixFree` additionally provides the transpose and dimensions.
+
+use math::LinearView;
+use sparse::{DiagonalMatrix, SparseMatrix};
+use ndarray::LinalgScalar;
+
+/// Linear map `A: X -> Y`.
+pub trait LinearOperator<X, Y = X> {
+    /// `(rows, columns)` of the operator, `None` if unknown.
+    fn dims(&self) -> Option<(usize, usize)>;
+
+    /// `dst = A src`
+    fn apply(&mut self, dst: &mut Y, src: &X);
+
+    /// `dst = Aᵀ src`
+    fn apply_transpose(&mut self, dst: &mut X, src: &Y);
+}
+
+impl<'a, A, X, Y> LinearOperator<X, Y> for &'a SparseMatrix<A>
+where
+    A: LinalgScalar + Send + Sync,
+    X: LinearView<Elem = A>,
+    Y: LinearView<Elem = A>,
+{
+    fn dims(&self) -> Option<(usize, usize)> {
+        Some(self.dim())
+    }
+
+    fn apply(&mut self, dst: &mut Y, src: &X) {
+        self.mul_vec(dst.view_linear_mut(), src.view_linear());
+    }
+
+    fn apply_transpose(&mut self, dst: &mut X, src: &Y) {
+        self.mul_vec_transpose(dst.view_linear_mut(), src.view_linear());
+    }
+}
+
+impl<'a, A, L> LinearOperator<L> for &'a DiagonalMatrix<A>
+where
+    A: LinalgScalar + Send + Sync,
+    L: LinearView<Elem = A>,
+{
+    fn dims(&self) -> Option<(usize, usize)> {
+        Some((self.dim(), self.dim()))
+    }
+
+    fn apply(&mut self, dst: &mut L, src: &L) {
+        self.mul_vec(dst.view_linear_mut(), src.view_linear());
+    }
+
+    fn apply_transpose(&mut self, dst: &mut L, src: &L) {
+        self.mul_vec(dst.view_linear_mut(), src.view_linear());
+    }
+}
+
+/// Symmetric operator, the transpose is the operator itself.
+impl<L, F> LinearOperator<L> for F
+    where F: FnMut(&mut L, &L)
+{
+    fn dims(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    fn apply(&mut self, dst: &mut L, src: &L) {
+        self(dst, src)
+    }
+
+    fn apply_transpose(&mut self, dst: &mut L, src: &L) {
+        self(dst, src)
+    }
+}
+
+/// Matrix-free operator defined by closures for the operator and its transpose.
+pub struct MatrixFree<F, G> {
+    dims: (usize, usize),
+    apply: F,
+    transpose: G,
+}
+
+impl<F, G> MatrixFree<F, G> {
+    pub fn new(dims: (usize, usize), apply: F, transpose: G) -> Self {
+        MatrixFree { dims, apply, transpose }
+    }
+}
+
+impl<X, Y, F, G> LinearOperator<X, Y> for MatrixFree<F, G>
+where
+    F: FnMut(&mut Y, &X),
+    G: FnMut(&mut X, &Y),
+{
+    fn dims(&self) -> Option<(usize, usize)> {
+        Some(self.dims)
+    }
+
+    fn apply(&mut self, dst: &mut Y, src: &X) {
+        (self.apply)(dst, src)
+    }
+
+    fn apply_transpose(&mut self, dst: &mut X, src: &Y) {
+        (self.transpose)(dst, src)
+    }
+}
+
+/// Product `A B` of two operators, `B: X -> Z` is applied first.
+///
+/// The intermediate result is stored in `buffer`.
+pub struct Compose<A, B, Z> {
+    pub outer: A,
+    pub inner: B,
+    buffer: Z,
+}
+
+impl<A, B, Z> Compose<A, B, Z> {
+    pub fn new(outer: A, inner: B, buffer: Z) -> Self {
+        Compose { outer, inner, buffer }
+    }
+}
+
+impl<X, Y, Z, A, B> LinearOperator<X, Y> for Compose<A, B, Z>
+where
+    A: LinearOperator<Z, Y>,
+    B: LinearOperator<X, Z>,
+{
+    fn dims(&self) -> Option<(usize, usize)> {
+        match (self.outer.dims(), self.inner.dims()) {
+            (Some((rows, inner_rows)), Some((inner_cols, cols))) => {
+                assert_eq!(inner_rows, inner_cols, "Operator dimensions don't match");
+                Some((rows, cols))
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&mut self, dst: &mut Y, src: &X) {
+        self.inner.apply(&mut self.buffer, src);
+        self.outer.apply(dst, &self.buffer);
+    }
+
+    /// `(A B)ᵀ = Bᵀ Aᵀ`
+    fn apply_transpose(&mut self, dst: &mut X, src: &Y) {
+        self.outer.apply_transpose(&mut self.buffer, src);
+        self.inner.apply_transpose(dst, &self.buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::grid::Staggered2d;
+    use dec::manifold::{Boundary, Laplacian, Manifold2d};
+    use domain::Grid2d;
+    use math::LinearViewReal;
+    use ndarray::Array2;
+    use pcg;
+    use super::*;
+
+    #[test]
+    fn matrix_free_laplacian() {
+        // `d1 ★1 d0 ★2` composed from the DEC operators and as assembled matrix,
+        // the transposes of the factors fall back to their matrices
+        let grid = Grid2d::new((6, 7));
+        let matrix = Laplacian::new(&grid, Boundary::Neumann).matrix_2();
+        let gradient_matrix = &grid.derivative_0_dual_matrix() * &grid.hodge_2_primal_matrix();
+        let divergence_matrix = &grid.derivative_1_primal_matrix() * &grid.hodge_1_dual_matrix();
+
+        let gradient = MatrixFree::new(gradient_matrix.dim(),
+            |edges: &mut Staggered2d<f64>, faces: &Array2<f64>| {
+                let mut dual = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+                grid.hodge_2_primal(&mut dual, faces);
+                grid.derivative_0_dual(edges, &dual);
+            },
+            |faces: &mut Array2<f64>, edges: &Staggered2d<f64>| (&gradient_matrix).apply_transpose(faces, edges));
+        let divergence = MatrixFree::new(divergence_matrix.dim(),
+            |faces: &mut Array2<f64>, edges: &Staggered2d<f64>| {
+                let mut primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+                grid.hodge_1_dual(&mut primal, edges);
+                grid.derivative_1_primal(faces, &primal);
+            },
+            |edges: &mut Staggered2d<f64>, faces: &Array2<f64>| (&divergence_matrix).apply_transpose(edges, faces));
+        let mut laplacian = Compose::new(divergence, gradient, <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid));
+        assert_eq!(laplacian.dims(), Some(matrix.dim()));
+
+        let x = Array2::from_shape_fn((6, 7), |(y, x)| ((y * 7 + x) as f64 * 0.37).sin());
+        let (mut a, mut b) = (Array2::zeros((6, 7)), Array2::zeros((6, 7)));
+        laplacian.apply(&mut a, &x);
+        (&matrix).apply(&mut b, &x);
+        b.scaled_add(-1.0, &a);
+        assert!(b.norm_max() < 1.0e-12);
+
+        laplacian.apply_transpose(&mut a, &x);
+        (&matrix.transpose()).apply(&mut b, &x);
+        b.scaled_add(-1.0, &a);
+        assert!(b.norm_max() < 1.0e-12);
+
+        // solvers accept matrices and matrix-free operators
+        let mut rhs = Array2::zeros((6, 7));
+        (&matrix).apply(&mut rhs, &x);
+        let mut solutions = Vec::new();
+        let (mut residual, mut auxiliary, mut search) = (rhs.clone(), rhs.clone(), rhs.clone());
+        let mut solution = Array2::zeros((6, 7));
+        pcg::precond_conjugate_gradient(&(), &mut solution, &rhs, 200, 1.0e-10,
+            &mut residual, &mut auxiliary, &mut search, &matrix);
+        solutions.push(solution.clone());
+        pcg::precond_conjugate_gradient(&(), &mut solution, &rhs, 200, 1.0e-10,
+            &mut residual, &mut auxiliary, &mut search, laplacian);
+        solutions.push(solution);
+
+        for solution in &solutions {
+            let mut check = Array2::zeros((6, 7));
+            (&matrix).apply(&mut check, solution);
+            check.scaled_add(-1.0, &rhs);
+            assert!(check.norm_max() < 1.0e-8);
+        }
+    }
+}
@@ -12,9 +12,25 @@ pub trait Property: Send + Sync + 'static {
     fn new() -> Self::Subtype;
 }
 
+/// Property for user-registered attributes, identified by name instead of type.
+pub struct Attribute<T>(PhantomData<T>);
+impl<T> Property for Attribute<T>
+where
+    T: Clone + Default + Send + Sync + 'static
+{
+    type Subtype = T;
+    fn new() -> T {
+        T::default()
+    }
+}
+
+/// Structure-of-arrays particle storage.
+///
+/// Properties are identified by type, attributes by name. All arrays have the same length.
 pub struct Particles {
     num_particles: usize,
     properties: HashMap<TypeId, Box<Storage>>,
+    attributes: HashMap<String, Box<Storage>>,
 }
 
 impl Particles {
@@ -22,6 +38,7 @@ impl Particles {
         Particles {
             num_particles: 0,
             properties: HashMap::new(),
+            attributes: HashMap::new(),
         }
     }
 
@@ -58,10 +75,74 @@ impl Particles {
                        .expect("Unregisted property")
     }
 
+    pub fn add_attribute<T>(&mut self, name: &str)
+        where T: Clone + Default + Send + Sync + 'static
+    {
+        let num_particles = self.num_particles;
+        let attribute = self.attributes.entry(name.to_owned()).or_insert_with(|| {
+            Box::new((vec![T::default(); num_particles], PhantomData::<Attribute<T>>))
+        });
+        assert!(attribute.is::<VecStorage<Attribute<T>>>(), "Attribute `{}` registered with different type", name);
+    }
+
+    pub fn read_attribute<T>(&self, name: &str) -> &[T]
+        where T: Clone + Default + Send + Sync + 'static
+    {
+        self.get_attribute::<T>(name).as_slice()
+    }
+
+    pub fn write_attribute<T>(&mut self, name: &str) -> &mut [T]
+        where T: Clone + Default + Send + Sync + 'static
+    {
+        self.get_attribute_mut::<T>(name).as_mut_slice()
+    }
+
+    fn get_attribute<T>(&self, name: &str) -> &Vec<T>
+        where T: Clone + Default + Send + Sync + 'static
+    {
+        self.attributes.get(name)
+                       .and_then(|attribute| attribute.downcast_ref::<VecStorage<Attribute<T>>>())
+                       .map(|&(ref vec, _)| vec)
+                       .expect("Unregisted attribute")
+    }
+
+    fn get_attribute_mut<T>(&mut self, name: &str) -> &mut Vec<T>
+        where T: Clone + Default + Send + Sync + 'static
+    {
+        self.attributes.get_mut(name)
+                       .and_then(|attribute| attribute.downcast_mut::<VecStorage<Attribute<T>>>())
+                       .map(|&mut (ref mut vec, _)| vec)
+                       .expect("Unregisted attribute")
+    }
+
     pub fn reserve(&mut self, additional: usize) {
-        for (_, property) in &mut self.properties {
-            property.reserve(additional);
+        for storage in self.properties.values_mut().chain(self.attributes.values_mut()) {
+            storage.reserve(additional);
+        }
+    }
+
+    /// Swap two particles in all properties and attributes.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        for storage in self.properties.values_mut().chain(self.attributes.values_mut()) {
+            storage.swap(a, b);
+        }
+    }
+
+    /// Reorder all properties and attributes, particle `i` afterwards is the previous particle `order[i]`.
+    pub fn reorder(&mut self, order: &[usize]) {
+        assert_eq!(order.len(), self.num_particles);
+        for storage in self.properties.values_mut().chain(self.attributes.values_mut()) {
+            storage.permute(order);
+        }
+    }
+
+    /// Remove all particles not marked to keep, preserving the order of the remaining particles.
+    pub fn compact(&mut self, keep: &[bool]) {
+        assert_eq!(keep.len(), self.num_particles);
+        for storage in self.properties.values_mut().chain(self.attributes.values_mut()) {
+            storage.retain(keep);
         }
+        self.num_particles = keep.iter().filter(|&&k| k).count();
     }
 
     pub fn add_particles(&mut self, additional: usize) -> Builder {
@@ -110,16 +191,29 @@ impl<'a> Builder<'a> {
 
         self
     }
+
+    pub fn with_attribute<T>(&mut self, name: &str, values: &[T]) -> &mut Self
+        where T: Clone + Default + Send + Sync + 'static
+    {
+        let num_particles = self.0.num_particles;
+        {
+            let storage = self.0.get_attribute_mut::<T>(name);
+            debug_assert_eq!(values.len(), num_particles - storage.len());
+            storage.extend_from_slice(values);
+        }
+
+        self
+    }
 }
 
 impl<'a> Drop for Builder<'a> {
     fn drop(&mut self) {
-        // fill remaining properties with default values
+        // fill remaining properties and attributes with default values
         let num_particles = self.0.num_particles;
-        for (_, property) in &mut self.0.properties {
-            let remaining_particles = num_particles - property.len();
+        for storage in self.0.properties.values_mut().chain(self.0.attributes.values_mut()) {
+            let remaining_particles = num_particles - storage.len();
             if remaining_particles > 0 {
-                property.fill(remaining_particles);
+                storage.fill(remaining_particles);
             }
         }
     }
@@ -134,12 +228,28 @@ impl<'a> Processor<'a> {
     pub fn write_property<T: Property>(&self) -> &mut [T::Subtype] {
         unsafe { self.0.get_property_mut::<T>().as_mut_slice() }
     }
+
+    pub fn read_attribute<T>(&self, name: &str) -> &[T]
+        where T: Clone + Default + Send + Sync + 'static
+    {
+        self.0.get_attribute::<T>(name).as_slice()
+    }
+
+    /// Attributes are borrowed from the processor, only one of them can be written at a time.
+    pub fn write_attribute<T>(&mut self, name: &str) -> &mut [T]
+        where T: Clone + Default + Send + Sync + 'static
+    {
+        self.0.get_attribute_mut::<T>(name).as_mut_slice()
+    }
 }
 
 pub trait Storage : mopa::Any + Send + Sync {
     fn len(&self) -> usize;
     fn reserve(&mut self, additional: usize);
     fn fill(&mut self, additional: usize);
+    fn swap(&mut self, a: usize, b: usize);
+    fn permute(&mut self, order: &[usize]);
+    fn retain(&mut self, keep: &[bool]);
 }
 
 mopafy!(Storage);
@@ -161,8 +271,57 @@ where
     fn fill(&mut self, additional: usize) {
         self.0.extend_from_slice(&vec![T::new(); additional])
     }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.0.swap(a, b);
+    }
+
+    fn permute(&mut self, order: &[usize]) {
+        let permuted = order.iter().map(|&i| self.0[i].clone()).collect();
+        self.0 = permuted;
+    }
+
+    fn retain(&mut self, keep: &[bool]) {
+        let mut i = 0;
+        self.0.retain(|_| { i += 1; keep[i-1] });
+    }
 }
 
 impl scene::Component for Particles {
     type Storage = scene::Storage<Particles>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Id;
+    impl Property for Id {
+        type Subtype = usize;
+        fn new() -> usize { 0 }
+    }
+
+    #[test]
+    fn reorder_compact() {
+        let mut particles = Particles::new();
+        particles.add_property::<Id>();
+        particles.add_attribute::<f32>("temperature");
+        particles.add_particles(4)
+                 .with::<Id>(&[0, 1, 2, 3])
+                 .with_attribute::<f32>("temperature", &[10.0, 11.0, 12.0, 13.0]);
+
+        particles.reorder(&[2, 0, 3, 1]);
+        assert_eq!(particles.read_property::<Id>(), &[2, 0, 3, 1]);
+        assert_eq!(particles.read_attribute::<f32>("temperature"), &[12.0, 10.0, 13.0, 11.0]);
+
+        particles.swap(0, 3);
+        particles.compact(&[true, false, true, false]);
+        assert_eq!(particles.num_particles(), 2);
+        assert_eq!(particles.read_property::<Id>(), &[1, 3]);
+        assert_eq!(particles.read_attribute::<f32>("temperature"), &[11.0, 13.0]);
+
+        // new particles get default attribute values
+        particles.add_particles(1).with::<Id>(&[4]);
+        assert_eq!(particles.read_attribute::<f32>("temperature"), &[11.0, 13.0, 0.0]);
+    }
+}
@@ -8,7 +8,7 @@ use self::property::*;
 
 use sph::grid::BoundedGrid;
 use sph::property::*;
-use sph::kernel::{self, Kernel};
+use sph::kernel::{self, SmoothingKernel};
 use particle::{Particles, Processor};
 use typenum::U2;
 use math::{Real, Dim, VectorN};
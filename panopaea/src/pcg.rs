@@ -1,5 +1,6 @@
 
 use math::{LinearView, LinearViewReal, Real};
+use operator::LinearOperator;
 
 pub trait Preconditioner<L> {
     fn apply(&self, dst: &mut L, src: &L);
@@ -24,7 +25,7 @@ pub fn precond_conjugate_gradient<L, O, P, T>(
 ) where P: Preconditioner<L>,
         T: Real,
         L: LinearViewReal<T>,
-        O: FnMut(&mut L, &L),
+        O: LinearOperator<L>,
 { 
     // Conjugate gradient
 
@@ -48,7 +49,7 @@ pub fn precond_conjugate_gradient<L, O, P, T>(
         'iter: for i in 0..max_iterations {
             // println!("residual: {:#?}", &residual.view_linear());
             // println!("search: {:#?}", &search.view_linear());
-            a(&mut auxiliary, search); // apply_sparse_matrix(auxiliary, search, diag, plus_x, plus_y, timestep);
+            a.apply(&mut auxiliary, search); // apply_sparse_matrix(auxiliary, search, diag, plus_x, plus_y, timestep);
             // println!("aux: {:#?}", &auxiliary.view_linear());
             let alpha = sigma/auxiliary.dot_linear(search);
             
@@ -80,3 +81,213 @@ pub fn precond_conjugate_gradient<L, O, P, T>(
         }
     }
 }
+
+/// Preconditioned stabilized bi-conjugate gradient method (BiCGSTAB).
+///
+/// Solves non-symmetric systems, the preconditioner is applied from the right.
+/// Temporary buffers are cloned from `b`. The iteration restarts with the
+/// current residual as shadow residual if both become orthogonal.
+pub fn precond_bicgstab<L, O, P, T>(
+    preconditioner: &P,
+    x: &mut L,
+    b: &L,
+    max_iterations: usize,
+    threshold: T,
+    mut a: O,
+) where P: Preconditioner<L>,
+        T: Real,
+        L: LinearViewReal<T> + Clone,
+        O: LinearOperator<L>,
+{
+    // initial guess
+    x.view_linear_mut().fill(T::zero());
+
+    // early out
+    if b.norm_max() < threshold {
+        return;
+    }
+
+    let mut residual = b.clone();
+    let mut shadow = b.clone();
+    let mut search = b.clone();
+    let mut auxiliary = b.clone();
+    let mut v = b.clone();
+    let mut t = b.clone();
+    search.view_linear_mut().fill(T::zero());
+    v.view_linear_mut().fill(T::zero());
+
+    let (mut rho, mut alpha, mut omega) = (T::one(), T::one(), T::one());
+
+    for _ in 0..max_iterations {
+        let mut rho_new = shadow.dot_linear(&residual);
+        if rho_new == T::zero() {
+            // breakdown, e.g. identity rows of fixed values are solved exactly
+            shadow.view_linear_mut().assign(&residual.view_linear());
+            rho_new = shadow.dot_linear(&residual);
+            if rho_new == T::zero() {
+                return;
+            }
+            rho = T::one();
+            alpha = T::one();
+            omega = T::one();
+            search.view_linear_mut().fill(T::zero());
+            v.view_linear_mut().fill(T::zero());
+        }
+
+        // p = r + beta * (p - omega * v)
+        let beta = (rho_new / rho) * (alpha / omega);
+        {
+            let mut search = search.view_linear_mut();
+            let residual = residual.view_linear();
+            let v = v.view_linear();
+            for i in 0..search.len() {
+                search[i] = residual[i] + beta * (search[i] - omega * v[i]);
+            }
+        }
+
+        preconditioner.apply(&mut auxiliary, &search);
+        a.apply(&mut v, &auxiliary);
+        alpha = rho_new / shadow.dot_linear(&v);
+        x.view_linear_mut().scaled_add(alpha, &auxiliary.view_linear());
+
+        // s = r - alpha * v
+        residual.view_linear_mut().scaled_add(-alpha, &v.view_linear());
+        if residual.norm_max() < threshold {
+            return;
+        }
+
+        preconditioner.apply(&mut auxiliary, &residual);
+        a.apply(&mut t, &auxiliary);
+        let tt = t.dot_linear(&t);
+        if tt == T::zero() {
+            return;
+        }
+        omega = t.dot_linear(&residual) / tt;
+        x.view_linear_mut().scaled_add(omega, &auxiliary.view_linear());
+
+        // r = s - omega * t
+        residual.view_linear_mut().scaled_add(-omega, &t.view_linear());
+        if residual.norm_max() < threshold || omega == T::zero() {
+            return;
+        }
+
+        rho = rho_new;
+    }
+}
+
+/// Restarted generalized minimal residual method, GMRES(m).
+///
+/// Solves non-symmetric systems, the preconditioner is applied from the right.
+/// `restart` is the dimension of the krylov subspace built before restarting.
+/// The solver stops if the euclidean norm of the residual drops below the threshold
+/// or after `max_iterations` inner iterations.
+pub fn precond_gmres<L, O, P, T>(
+    preconditioner: &P,
+    x: &mut L,
+    b: &L,
+    restart: usize,
+    max_iterations: usize,
+    threshold: T,
+    mut a: O,
+) where P: Preconditioner<L>,
+        T: Real,
+        L: LinearViewReal<T> + Clone,
+        O: LinearOperator<L>,
+{
+    assert!(restart > 0);
+
+    // initial guess
+    x.view_linear_mut().fill(T::zero());
+
+    let mut basis = vec![b.clone(); restart + 1];
+    let mut auxiliary = b.clone();
+    let mut correction = b.clone();
+
+    // hessenberg matrix (column major), givens rotations and rhs of the least squares problem
+    let mut hessenberg = vec![vec![T::zero(); restart + 1]; restart];
+    let mut rotations = vec![(T::one(), T::zero()); restart];
+    let mut g = vec![T::zero(); restart + 1];
+
+    let mut iterations = 0;
+    while iterations < max_iterations {
+        // r = b - A x
+        a.apply(&mut auxiliary, x);
+        basis[0].view_linear_mut().assign(&b.view_linear());
+        basis[0].view_linear_mut().scaled_add(-T::one(), &auxiliary.view_linear());
+
+        let beta = basis[0].dot_linear(&basis[0]).sqrt();
+        if beta < threshold {
+            return;
+        }
+        basis[0].view_linear_mut().map_inplace(|v| *v = *v / beta);
+
+        for v in g.iter_mut() { *v = T::zero(); }
+        g[0] = beta;
+
+        let mut k = 0;
+        while k < restart && iterations < max_iterations {
+            iterations += 1;
+
+            // arnoldi step with modified gram-schmidt
+            let (krylov, next) = basis.split_at_mut(k + 1);
+            let w = &mut next[0];
+            preconditioner.apply(&mut auxiliary, &krylov[k]);
+            a.apply(&mut *w, &auxiliary);
+
+            let h = &mut hessenberg[k];
+            for (i, v) in krylov.iter().enumerate() {
+                h[i] = w.dot_linear(v);
+                w.view_linear_mut().scaled_add(-h[i], &v.view_linear());
+            }
+            h[k + 1] = w.dot_linear(&*w).sqrt();
+            if h[k + 1] != T::zero() {
+                let norm = h[k + 1];
+                w.view_linear_mut().map_inplace(|v| *v = *v / norm);
+            }
+
+            // apply previous rotations to the new column
+            for i in 0..k {
+                let (c, s) = rotations[i];
+                let (h0, h1) = (h[i], h[i + 1]);
+                h[i] = c * h0 + s * h1;
+                h[i + 1] = -s * h0 + c * h1;
+            }
+
+            // eliminate the subdiagonal element
+            let denom = (h[k] * h[k] + h[k + 1] * h[k + 1]).sqrt();
+            let (c, s) = if denom == T::zero() { (T::one(), T::zero()) } else { (h[k] / denom, h[k + 1] / denom) };
+            rotations[k] = (c, s);
+            h[k] = denom;
+            h[k + 1] = T::zero();
+            g[k + 1] = -s * g[k];
+            g[k] = c * g[k];
+
+            k += 1;
+            if g[k].abs() < threshold {
+                break;
+            }
+        }
+
+        // solve the upper triangular system H y = g
+        let mut y = vec![T::zero(); k];
+        for i in (0..k).rev() {
+            let mut sum = g[i];
+            for j in (i + 1)..k {
+                sum = sum - hessenberg[j][i] * y[j];
+            }
+            y[i] = sum / hessenberg[i][i];
+        }
+
+        // x += M^-1 V y
+        correction.view_linear_mut().fill(T::zero());
+        for (v, &y) in basis.iter().zip(y.iter()) {
+            correction.view_linear_mut().scaled_add(y, &v.view_linear());
+        }
+        preconditioner.apply(&mut auxiliary, &correction);
+        x.view_linear_mut().scaled_add(T::one(), &auxiliary.view_linear());
+
+        if g[k].abs() < threshold {
+            return;
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Poisson-disk sampling
+//!
+//! Blue noise point sets with a minimum distance between all samples, used
+//! for initial particle blocks and irregular boundary particles. Compared to
+//! lattices, Poisson-disk samples avoid aligned particle layers and the
+//! resulting anisotropic artifacts in SPH.
+//!
+//! Boxes are filled with Bridson's algorithm, which grows the sample set from
+//! active samples and terminates with a maximal set. Triangle meshes are
+//! sampled by dart throwing with area weighted candidates.
+//!
+//! A radius `r` results in an average spacing slightly larger than `r`, half
+//! the smoothing radius of the fluid is a common choice for SPH particles.
+//!
+//! References:
+//!     [Bri07] Robert Bridson, 2007,
+//!             Fast Poisson disk sampling in arbitrary dimensions,
+//!             In ACM SIGGRAPH 2007 Sketches, Article 22
+
+use cgmath::MetricSpace;
+use math::{Dim, Real, VectorN};
+use rand::Rng;
+use sph::emitter::Shape;
+use std::collections::HashMap;
+use typenum::U3;
+
+pub struct PoissonDisk<T> {
+    /// Minimum distance between two samples.
+    pub radius: T,
+    /// Candidates `k` per active sample before it's retired.
+    pub attempts: usize,
+}
+
+impl<T: Real> PoissonDisk<T> {
+    pub fn new(radius: T) -> Self {
+        PoissonDisk {
+            radius,
+            attempts: 30,
+        }
+    }
+
+    /// Maximal sample set of an axis aligned box.
+    ///
+    /// Ref: [Bri07]
+    pub fn sample_box<N, R>(&self, min: VectorN<T, N>, max: VectorN<T, N>, rng: &mut R) -> Vec<VectorN<T, N>>
+        where N: Dim<T>,
+              R: Rng,
+    {
+        let dim = N::to_usize();
+        let radius = self.radius;
+
+        // background grid with at most one sample per cell
+        let cell_size = radius / T::new(dim as f64).sqrt();
+        let cells = (0..dim)
+            .map(|d| ((max[d] - min[d]) / cell_size).ceil().to_usize().unwrap().max(1))
+            .collect::<Vec<_>>();
+        let mut grid = vec![None; cells.iter().product()];
+        let cell_of = |p: &VectorN<T, N>| {
+            (0..dim).map(|d| {
+                let c = ((p[d] - min[d]) / cell_size).floor().to_isize().unwrap();
+                c.max(0).min(cells[d] as isize - 1)
+            }).collect::<Vec<_>>()
+        };
+        let flatten = |cell: &[isize]| {
+            (0..dim).fold(0, |index, d| index * cells[d] + cell[d] as usize)
+        };
+
+        let mut samples: Vec<VectorN<T, N>> = Vec::new();
+        let mut active = Vec::new();
+
+        let mut first = VectorN::from_elem(T::zero());
+        for d in 0..dim {
+            first[d] = min[d] + rng.gen::<T>() * (max[d] - min[d]);
+        }
+        grid[flatten(&cell_of(&first))] = Some(0);
+        samples.push(first);
+        active.push(0);
+
+        // neighbor cells within `radius` are at most two cells away
+        let num_offsets = 5usize.pow(dim as u32);
+        while !active.is_empty() {
+            let slot = rng.gen_range(0, active.len());
+            let center = samples[active[slot]].clone();
+
+            let mut found = false;
+            for _ in 0..self.attempts {
+                let candidate = center.clone() + annulus_offset::<T, N, R>(radius, rng);
+                if (0..dim).any(|d| candidate[d] < min[d] || candidate[d] > max[d]) {
+                    continue;
+                }
+
+                let cell = cell_of(&candidate);
+                let mut neighbor = vec![0; dim];
+                let far = (0..num_offsets).all(|offset| {
+                    let mut rest = offset;
+                    for d in 0..dim {
+                        neighbor[d] = cell[d] + (rest % 5) as isize - 2;
+                        rest /= 5;
+                        if neighbor[d] < 0 || neighbor[d] >= cells[d] as isize {
+                            return true;
+                        }
+                    }
+                    match grid[flatten(&neighbor)] {
+                        Some(j) => distance2(&samples[j], &candidate) >= radius * radius,
+                        None => true,
+                    }
+                });
+
+                if far {
+                    grid[flatten(&cell)] = Some(samples.len());
+                    active.push(samples.len());
+                    samples.push(candidate);
+                    found = true;
+                    break;
+                }
+            }
+
+            if !found {
+                active.swap_remove(slot);
+            }
+        }
+        samples
+    }
+
+    /// Samples inside a shape, filling its bounding box and rejecting outside samples.
+    pub fn sample_shape<N, R>(&self, shape: &Shape<T, N>, rng: &mut R) -> Vec<VectorN<T, N>>
+        where N: Dim<T>,
+              R: Rng,
+    {
+        let (min, max) = shape.bounds();
+        let mut samples = self.sample_box(min, max, rng);
+        samples.retain(|p| shape.contains(p));
+        samples
+    }
+
+    /// Samples on the surface of a triangle mesh.
+    ///
+    /// Candidates are drawn with probability proportional to the triangle
+    /// areas, about `attempts` candidates per area `r^2` are tested.
+    pub fn sample_mesh<R>(&self, vertices: &[VectorN<T, U3>], triangles: &[[usize; 3]], rng: &mut R) -> Vec<VectorN<T, U3>>
+        where R: Rng,
+    {
+        let radius = self.radius;
+
+        // cumulative areas for choosing triangles
+        let mut cumulative = Vec::with_capacity(triangles.len());
+        let mut total = T::zero();
+        for t in triangles {
+            let (a, b, c) = (vertices[t[0]], vertices[t[1]], vertices[t[2]]);
+            total += triangle_area(a, b, c);
+            cumulative.push(total);
+        }
+        if total <= T::zero() {
+            return Vec::new();
+        }
+
+        let cell_size = radius;
+        let cell_of = |p: &VectorN<T, U3>| {
+            [
+                (p[0] / cell_size).floor().to_i64().unwrap(),
+                (p[1] / cell_size).floor().to_i64().unwrap(),
+                (p[2] / cell_size).floor().to_i64().unwrap(),
+            ]
+        };
+
+        let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        let mut samples: Vec<VectorN<T, U3>> = Vec::new();
+        let num_candidates = self.attempts * (total / (radius * radius)).ceil().to_usize().unwrap();
+        for _ in 0..num_candidates {
+            let target = rng.gen::<T>() * total;
+            let index = match cumulative.binary_search_by(|area| area.partial_cmp(&target).unwrap()) {
+                Ok(i) | Err(i) => i.min(triangles.len() - 1),
+            };
+            let t = triangles[index];
+            let (a, b, c) = (vertices[t[0]], vertices[t[1]], vertices[t[2]]);
+
+            // uniform point in the triangle
+            let (u, v) = (rng.gen::<T>().sqrt(), rng.gen::<T>());
+            let candidate = a * (T::one() - u) + b * (u * (T::one() - v)) + c * (u * v);
+
+            let cell = cell_of(&candidate);
+            let mut far = true;
+            'search: for z in -1..2 {
+                for y in -1..2 {
+                    for x in -1..2 {
+                        if let Some(bucket) = grid.get(&[cell[0] + x, cell[1] + y, cell[2] + z]) {
+                            if bucket.iter().any(|&j| samples[j].distance2(candidate) < radius * radius) {
+                                far = false;
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+
+            if far {
+                grid.entry(cell).or_insert_with(Vec::new).push(samples.len());
+                samples.push(candidate);
+            }
+        }
+        samples
+    }
+}
+
+/// Uniform random offset with length between `radius` and `2 radius`.
+fn annulus_offset<T, N, R>(radius: T, rng: &mut R) -> VectorN<T, N>
+    where T: Real,
+          N: Dim<T>,
+          R: Rng,
+{
+    let two = T::new(2.0);
+    loop {
+        let mut offset: VectorN<T, N> = VectorN::from_elem(T::zero());
+        for d in 0..N::to_usize() {
+            offset[d] = (two * rng.gen::<T>() - T::one()) * two * radius;
+        }
+        let len2 = distance2(&offset, &VectorN::from_elem(T::zero()));
+        if len2 >= radius * radius && len2 <= T::new(4.0) * radius * radius {
+            return offset;
+        }
+    }
+}
+
+fn distance2<T: Real, N: Dim<T>>(a: &VectorN<T, N>, b: &VectorN<T, N>) -> T {
+    (0..N::to_usize()).fold(T::zero(), |dist, d| dist + (a[d] - b[d]).powi(2))
+}
+
+fn triangle_area<T: Real>(a: VectorN<T, U3>, b: VectorN<T, U3>, c: VectorN<T, U3>) -> T {
+    let (u, v) = (b - a, c - a);
+    let cross = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    T::new(0.5) * (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use math::vector_n::{vec2, vec3};
+    use rand::{SeedableRng, XorShiftRng};
+    use super::*;
+
+    fn min_distance<N: Dim<f64>>(samples: &[VectorN<f64, N>]) -> f64 {
+        let mut min = ::std::f64::INFINITY;
+        for i in 0..samples.len() {
+            for j in 0..i {
+                min = min.min(distance2(&samples[i], &samples[j]).sqrt());
+            }
+        }
+        min
+    }
+
+    #[test]
+    fn poisson_disk() {
+        let mut rng = XorShiftRng::from_seed([5, 6, 7, 8]);
+        let sampler = PoissonDisk::new(0.05);
+        let samples = sampler.sample_box(vec2(0.0, 0.0), vec2(1.0, 1.0), &mut rng);
+        assert!(samples.len() > 200, "{}", samples.len());
+        assert!(min_distance(&samples) >= 0.05);
+        assert!(samples.iter().all(|p| p.iter().all(|&c| c >= 0.0 && c <= 1.0)));
+        // maximal set, no gaps larger than the radius
+        for y in 0..20 {
+            for x in 0..20 {
+                let p = vec2(0.025 + 0.05 * x as f64, 0.025 + 0.05 * y as f64);
+                assert!(samples.iter().any(|s| s.distance(p) < 0.1));
+            }
+        }
+
+        let samples = PoissonDisk::new(0.2).sample_box(vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0), &mut rng);
+        assert!(samples.len() > 20);
+        assert!(min_distance(&samples) >= 0.2);
+
+        let disk = Shape::Sphere { center: vec2(0.5, 0.5), radius: 0.3 };
+        let samples = sampler.sample_shape(&disk, &mut rng);
+        assert!(samples.iter().all(|p| disk.contains(p)));
+
+        // unit square in the `xy` plane
+        let vertices = [vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0), vec3(0.0, 1.0, 0.0)];
+        let samples = PoissonDisk::new(0.1).sample_mesh(&vertices, &[[0, 1, 2], [0, 2, 3]], &mut rng);
+        assert!(samples.len() > 40, "{}", samples.len());
+        assert!(min_distance(&samples) >= 0.1);
+        assert!(samples.iter().all(|p| p[2] == 0.0 && p[0] >= 0.0 && p[0] <= 1.0 && p[1] >= 0.0 && p[1] <= 1.0));
+    }
+}
@@ -0,0 +1,210 @@
+//! Simulation diagnostics
+//!
+//! Global quantities of a grid simulation, measured after each step to detect
+//! blowups and check conservation: kinetic energy, divergence of the velocity,
+//! total mass of a scalar field, total circulation and the CFL number of the
+//! last timestep.
+//!
+//! A `Monitor` stage appended to a `Pipeline` measures the state and passes the
+//! values to a `DiagnosticsSink`, e.g. a closure or a `CsvWriter`.
+
+use dec::grid::Staggered2d;
+use dec::manifold::Manifold2d;
+use domain::Grid2d;
+use math::Real;
+use ndarray::Array2;
+use std::io::{self, Write};
+use vorticity;
+use super::pipeline::{GridState, Stage};
+
+/// Quantities of a single step.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Diagnostics<T> {
+    /// Number of the step, starting at one.
+    pub step: usize,
+    /// Simulated time at the end of the step.
+    pub time: T,
+    /// `1/2 ∫ |u|² dA` for unit fluid density.
+    pub kinetic_energy: T,
+    /// Maximum absolute divergence of the velocity.
+    pub divergence_max: T,
+    /// Root mean square of the divergence over all cells.
+    pub divergence_rms: T,
+    /// Integral of the mass field, zero if none is monitored.
+    pub mass: T,
+    /// Integral of the vorticity over the interior vertices.
+    pub circulation: T,
+    /// Largest fraction of a cell crossed by the velocity in one timestep.
+    pub cfl: T,
+}
+
+impl<T: Real> Diagnostics<T> {
+    /// Measure the fields of a step with the given timestep, `step` and `time` are left at zero.
+    pub fn measure(grid: &Grid2d, velocity: &Staggered2d<T>, mass: Option<&Array2<T>>, timestep: T) -> Self {
+        let (dy, dx) = grid.spacing();
+        let (dy, dx) = (T::new(dy), T::new(dx));
+        let area = dy * dx;
+        let (vertical, horizontal) = velocity.split();
+
+        // velocities are integrated along the edges
+        let energy = vertical.fold(T::zero(), |sum, &v| sum + (v / dy).powi(2))
+            + horizontal.fold(T::zero(), |sum, &v| sum + (v / dx).powi(2));
+        let cfl = vertical.fold(T::zero(), |max, &v| max.max(v.abs() / (dy * dy)))
+            .max(horizontal.fold(T::zero(), |max, &v| max.max(v.abs() / (dx * dx))));
+
+        let mut velocity_primal = grid.new_simplex_1();
+        let mut divergence = grid.new_simplex_2();
+        grid.hodge_1_dual(&mut velocity_primal, velocity);
+        grid.derivative_1_primal(&mut divergence, &velocity_primal);
+        divergence.mapv_inplace(|div| div / area);
+        let divergence_max = divergence.fold(T::zero(), |max, &div| max.max(div.abs()));
+        let divergence_rms = (divergence.fold(T::zero(), |sum, &div| sum + div * div)
+            / T::new(divergence.len().max(1))).sqrt();
+
+        Diagnostics {
+            step: 0,
+            time: T::zero(),
+            kinetic_energy: T::new(0.5) * area * energy,
+            divergence_max,
+            divergence_rms,
+            mass: mass.map_or(T::zero(), |field| field.scalar_sum() * area),
+            circulation: vorticity::curl_2d(grid, velocity).scalar_sum(),
+            cfl: cfl * timestep,
+        }
+    }
+
+    /// `false` if any quantity is NaN or infinite, usually caused by an unstable simulation.
+    pub fn is_finite(&self) -> bool {
+        [self.time, self.kinetic_energy, self.divergence_max, self.divergence_rms,
+         self.mass, self.circulation, self.cfl]
+            .iter()
+            .all(|v| v.is_finite())
+    }
+}
+
+/// Receiver of the diagnostics of each step.
+pub trait DiagnosticsSink<T> {
+    fn record(&mut self, diagnostics: &Diagnostics<T>) -> io::Result<()>;
+}
+
+impl<T, F> DiagnosticsSink<T> for F
+    where F: FnMut(&Diagnostics<T>)
+{
+    fn record(&mut self, diagnostics: &Diagnostics<T>) -> io::Result<()> {
+        self(diagnostics);
+        Ok(())
+    }
+}
+
+/// Writes one line per step with a header line before the first step.
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    header: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W) -> Self {
+        CsvWriter { writer, header: false }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<T: Real, W: Write> DiagnosticsSink<T> for CsvWriter<W> {
+    fn record(&mut self, d: &Diagnostics<T>) -> io::Result<()> {
+        if !self.header {
+            writeln!(self.writer, "step,time,kinetic_energy,divergence_max,divergence_rms,mass,circulation,cfl")?;
+            self.header = true;
+        }
+        let values = [d.time, d.kinetic_energy, d.divergence_max, d.divergence_rms, d.mass, d.circulation, d.cfl];
+        write!(self.writer, "{}", d.step)?;
+        for v in &values {
+            write!(self.writer, ",{}", v.to_f64().unwrap())?;
+        }
+        writeln!(self.writer)
+    }
+}
+
+/// Stage measuring the state, usually the last stage of a pipeline.
+///
+/// Panics if the sink fails to record a step.
+pub struct Monitor<T, S> {
+    pub sink: S,
+    /// Scalar field measured as mass, e.g. `density`.
+    pub mass: Option<&'static str>,
+    step: usize,
+    time: T,
+}
+
+impl<T: Real, S: DiagnosticsSink<T>> Monitor<T, S> {
+    pub fn new(sink: S) -> Self {
+        Monitor {
+            sink,
+            mass: None,
+            step: 0,
+            time: T::zero(),
+        }
+    }
+
+    pub fn with_mass(self, field: &'static str) -> Self {
+        Monitor { mass: Some(field), ..self }
+    }
+}
+
+impl<T: Real, S: DiagnosticsSink<T>> Stage<T> for Monitor<T, S> {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        self.step += 1;
+        self.time += timestep;
+
+        let mass = self.mass.map(|field| state.scalar(field));
+        let diagnostics = Diagnostics {
+            step: self.step,
+            time: self.time,
+            ..Diagnostics::measure(state.grid, &state.velocity, mass, timestep)
+        };
+        self.sink.record(&diagnostics).expect("failed to record diagnostics");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solvers::pipeline::{GridSolver, Pipeline};
+    use std::cell::RefCell;
+    use super::*;
+
+    #[test]
+    fn monitor_smoke_pipeline() {
+        let grid = Grid2d::new((24, 16));
+        let records = RefCell::new(Vec::new());
+        {
+            let mut pipeline = Pipeline::<f64>::smoke(&grid);
+            pipeline.state.scalar_mut("density")[(3, 8)] = 1.0;
+            pipeline.state.scalar_mut("temperature")[(3, 8)] = 4.0;
+            pipeline.with(Monitor::new(|d: &Diagnostics<f64>| records.borrow_mut().push(*d)).with_mass("density"));
+            for _ in 0..4 {
+                pipeline.step(0.1);
+            }
+        }
+
+        let records = records.into_inner();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[3].step, 4);
+        assert!((records[3].time - 0.4).abs() < 1.0e-12);
+        for d in &records {
+            assert!(d.is_finite());
+            assert!(d.kinetic_energy > 0.0);
+            assert!(d.divergence_max < 1.0e-2);
+            assert!(d.mass > 0.0);
+        }
+
+        let mut csv = CsvWriter::new(Vec::new());
+        for d in &records {
+            csv.record(d).unwrap();
+        }
+        let csv = String::from_utf8(csv.into_inner()).unwrap();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.starts_with("step,time,kinetic_energy"));
+    }
+}
@@ -0,0 +1,395 @@
+//! FLIP/PIC and APIC particle-grid solver
+//!
+//! Particles carry the velocity, the grid is only used for the pressure projection.
+//! Particle positions are given in grid units, `[0]` is the horizontal and `[1]`
+//! the vertical component. The whole domain is treated as fluid, particles
+//! wrap around periodic boundaries.
+//!
+//! References:
+//!     [ZB05] Yongning Zhu and Robert Bridson, 2005,
+//!            Animating sand as a fluid,
+//!            ACM Trans. Graph. 24, 3 (July 2005), 965-972
+//!     [JSS+15] Chenfanfu Jiang, Craig Schroeder, Andrew Selle, Joseph Teran, and Alexey Stomakhin, 2015,
+//!              The affine particle-in-cell method,
+//!              ACM Trans. Graph. 34, 4 (July 2015), 51:1-51:10
+
+use advection::{self, offset_horizontal, offset_vertical};
+use collision::{self, Collider};
+use dec::grid::{self, Staggered2d};
+use dec::manifold::Manifold2d;
+use dec::projection::Projection;
+use dec::viscosity::Viscosity;
+use domain::Grid2d;
+use math::{LinearView, Real};
+use math::integration::Rk2;
+use math::vector_n::vec2;
+use ndarray::{Array2, ArrayView2, ArrayViewMut2};
+use particle::{Particles, Property};
+use rayon::prelude::*;
+use sph::property::{Position, Velocity};
+use typenum::U2;
+
+/// Affine velocity matrix `C` of a particle used by APIC.
+///
+/// `c[i][j]` is the derivative of the velocity component `i` along axis `j`.
+pub struct AffineVelocity<T: Real>(pub [[T; 2]; 2]);
+impl<T: Real> Property for AffineVelocity<T> {
+    type Subtype = [[T; 2]; 2];
+    fn new() -> Self::Subtype {
+        [[T::zero(); 2]; 2]
+    }
+}
+
+pub fn init<T: Real>(particles: &mut Particles) {
+    particles.add_property::<Position<T, U2>>();
+    particles.add_property::<Velocity<T, U2>>();
+    particles.add_property::<AffineVelocity<T>>();
+}
+
+/// Particle-grid transfer scheme.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Transfer {
+    /// Blend of PIC and FLIP, see `Flip::flip_ratio`.
+    Flip,
+    /// Affine particle-in-cell [JSS+15].
+    Apic,
+}
+
+pub struct Flip<'a, T: Real> {
+    grid: &'a Grid2d,
+    projection: Projection<'a, T, Grid2d>,
+    viscosity_solver: Viscosity<'a, T>,
+
+    pub velocity: Staggered2d<T>,
+    pub pressure: Array2<T>,
+
+    /// Blend factor between PIC (`0`) and FLIP (`1`).
+    pub flip_ratio: T,
+    pub transfer: Transfer,
+    /// Vertical acceleration.
+    pub gravity: T,
+    /// Kinematic viscosity, diffused implicitly if positive.
+    pub viscosity: T,
+    /// Static obstacles for the particles, positions in grid units `(x, y)`.
+    pub colliders: Vec<Collider<T, U2>>,
+
+    velocity_old: Staggered2d<T>,
+    weights: Staggered2d<T>,
+}
+
+impl<'a, T: Real> Flip<'a, T> {
+    pub fn new(grid: &'a Grid2d) -> Self {
+        Flip {
+            grid,
+            projection: Projection::new(grid, 500, T::new(1.0e-5)),
+            viscosity_solver: Viscosity::new(grid, 500, T::new(1.0e-5)),
+
+            velocity: grid.new_simplex_1(),
+            pressure: grid.new_simplex_2(),
+
+            flip_ratio: T::new(0.95),
+            transfer: Transfer::Flip,
+            gravity: T::new(-9.81),
+            viscosity: T::zero(),
+            colliders: Vec::new(),
+
+            velocity_old: grid.new_simplex_1(),
+            weights: grid.new_simplex_1(),
+        }
+    }
+
+    pub fn grid(&self) -> &Grid2d {
+        self.grid
+    }
+
+    /// Pressure solver settings.
+    pub fn projection_mut(&mut self) -> &mut Projection<'a, T, Grid2d> {
+        &mut self.projection
+    }
+
+    /// Viscosity solver settings.
+    pub fn viscosity_mut(&mut self) -> &mut Viscosity<'a, T> {
+        &mut self.viscosity_solver
+    }
+
+    /// Advance particles and grid by one timestep.
+    pub fn step(&mut self, particles: &mut Particles, timestep: T) {
+        self.particles_to_grid(particles);
+        self.velocity_old.view_linear_mut().assign(&self.velocity.view_linear());
+
+        self.apply_gravity(timestep);
+        self.enforce_boundary();
+        if self.viscosity > T::zero() {
+            self.viscosity_solver.diffuse(&mut self.velocity, self.viscosity, timestep);
+        }
+        self.projection.project(&mut self.velocity, &mut self.pressure, timestep);
+
+        self.grid_to_particles(particles);
+        self.advect_particles(particles, timestep);
+    }
+
+    /// Splat particle velocities onto the grid using bilinear weights.
+    pub fn particles_to_grid(&mut self, particles: &Particles) {
+        let positions = particles.read_property::<Position<T, U2>>();
+        let velocities = particles.read_property::<Velocity<T, U2>>();
+        let affine = particles.read_property::<AffineVelocity<T>>();
+        let apic = self.transfer == Transfer::Apic;
+
+        self.velocity.view_linear_mut().fill(T::zero());
+        self.weights.view_linear_mut().fill(T::zero());
+
+        {
+            let (mut vertical, mut horizontal) = self.velocity.split_mut();
+            let (mut weight_vertical, mut weight_horizontal) = self.weights.split_mut();
+            for ((pos, vel), c) in positions.iter().zip(velocities.iter()).zip(affine.iter()) {
+                let pos = (pos[1], pos[0]);
+
+                // affine velocity contribution at the grid node
+                let value = |component: usize, node: (T, T)| {
+                    if apic {
+                        vel[component] + c[component][0] * (node.1 - pos.1) + c[component][1] * (node.0 - pos.0)
+                    } else {
+                        vel[component]
+                    }
+                };
+
+                splat(self.grid, &mut vertical, &mut weight_vertical, offset_vertical(), pos, |node| value(1, node));
+                splat(self.grid, &mut horizontal, &mut weight_horizontal, offset_horizontal(), pos, |node| value(0, node));
+            }
+        }
+
+        par_azip!(mut v (self.velocity.view_linear_mut()), weight (self.weights.view_linear()) in {
+            if weight > T::zero() { *v = *v / weight; }
+        });
+
+        // duplicated boundary samples of periodic axes aren't splatted to
+        let (h, w) = self.grid.dim();
+        let [by, bx] = self.grid.boundary();
+        let (mut vertical, mut horizontal) = self.velocity.split_mut();
+        if by.is_periodic() {
+            let first = vertical.row(0).to_owned();
+            vertical.row_mut(h).assign(&first);
+        }
+        if bx.is_periodic() {
+            let first = horizontal.column(0).to_owned();
+            horizontal.column_mut(w).assign(&first);
+        }
+    }
+
+    /// Update particle velocities from the grid.
+    pub fn grid_to_particles(&self, particles: &mut Particles) {
+        match self.transfer {
+            Transfer::Flip => self.grid_to_particles_flip(particles),
+            Transfer::Apic => self.grid_to_particles_apic(particles),
+        }
+    }
+
+    /// Blend of PIC and FLIP velocity updates.
+    fn grid_to_particles_flip(&self, particles: &mut Particles) {
+        let (grid, velocity, velocity_old) = (self.grid, &self.velocity, &self.velocity_old);
+        let ratio = self.flip_ratio;
+
+        particles.run(|p| {
+            let positions = p.read_property::<Position<T, U2>>();
+            let velocities = p.write_property::<Velocity<T, U2>>();
+
+            velocities.par_iter_mut().zip(positions.par_iter()).for_each(|(vel, pos)| {
+                let pos = (pos[1], pos[0]);
+                let new = advection::sample_velocity(grid, velocity, pos);
+                let old = advection::sample_velocity(grid, velocity_old, pos);
+
+                let flip = (vel[0] + new.1 - old.1, vel[1] + new.0 - old.0);
+                vel[0] = ratio * flip.0 + (T::one() - ratio) * new.1;
+                vel[1] = ratio * flip.1 + (T::one() - ratio) * new.0;
+            });
+        });
+    }
+
+    /// Interpolated velocity and its gradient as affine velocity matrix.
+    fn grid_to_particles_apic(&self, particles: &mut Particles) {
+        let grid = self.grid;
+        let (vertical, horizontal) = self.velocity.split();
+
+        particles.run(|p| {
+            let positions = p.read_property::<Position<T, U2>>();
+            let velocities = p.write_property::<Velocity<T, U2>>();
+            let affine = p.write_property::<AffineVelocity<T>>();
+
+            velocities.par_iter_mut().zip(affine.par_iter_mut()).zip(positions.par_iter()).for_each(|((vel, c), pos)| {
+                let pos = (pos[1], pos[0]);
+
+                vel[0] = advection::sample(grid, horizontal, offset_horizontal(), pos);
+                vel[1] = advection::sample(grid, vertical, offset_vertical(), pos);
+
+                let (dy, dx) = gradient(grid, horizontal, offset_horizontal(), pos);
+                c[0] = [dx, dy];
+                let (dy, dx) = gradient(grid, vertical, offset_vertical(), pos);
+                c[1] = [dx, dy];
+            });
+        });
+    }
+
+    /// Move particles through the grid velocity field (midpoint rule).
+    ///
+    /// Collisions with the colliders are resolved along the straight path of the step,
+    /// particles leaving the domain are clamped to the boundary or wrapped around periodic axes.
+    pub fn advect_particles(&self, particles: &mut Particles, timestep: T) {
+        let (grid, velocity, colliders) = (self.grid, &self.velocity, &self.colliders);
+        let (h, w) = grid.dim();
+        let [by, bx] = grid.boundary();
+
+        particles.run(|p| {
+            let (positions, velocities) = (
+                p.write_property::<Position<T, U2>>(),
+                p.write_property::<Velocity<T, U2>>(),
+            );
+            positions.par_iter_mut().zip(velocities.par_iter_mut()).for_each(|(pos, vel)| {
+                let start = *pos;
+                let end = advection::trace(&Rk2, grid, velocity, (pos[1], pos[0]), timestep);
+                *pos = vec2(end.1, end.0);
+                collision::resolve_all(colliders, &start, pos, vel);
+                pos[0] = confine(bx.is_periodic(), pos[0], T::new(w));
+                pos[1] = confine(by.is_periodic(), pos[1], T::new(h));
+            });
+        });
+    }
+
+    fn apply_gravity(&mut self, timestep: T) {
+        let (mut vertical, _) = self.velocity.split_mut();
+        let gravity = self.gravity;
+        vertical.slice_mut(s![1..-1, ..]).map_inplace(|v| *v += timestep * gravity);
+    }
+
+    /// Boundary conditions of the grid for the normal velocity components.
+    fn enforce_boundary(&mut self) {
+        grid::enforce_boundary_2d(self.grid, &mut self.velocity);
+    }
+}
+
+/// Wrap a coordinate around a periodic axis of length `len` or clamp it to the domain.
+fn confine<T: Real>(periodic: bool, p: T, len: T) -> T {
+    if periodic {
+        p - len * (p / len).floor()
+    } else {
+        p.max(T::zero()).min(len)
+    }
+}
+
+/// Image of a node coordinate closest to the particle on a periodic axis of length `len`.
+fn closest_image<T: Real>(periodic: bool, node: T, pos: T, len: T) -> T {
+    let half = T::new(0.5) * len;
+    if !periodic {
+        node
+    } else if node - pos > half {
+        node - len
+    } else if pos - node > half {
+        node + len
+    } else {
+        node
+    }
+}
+
+/// Bilinear interpolation weights `(y, x, weight, d/dy, d/dx)` of a position.
+fn weights<T: Real>(grid: &Grid2d, dim: (usize, usize), offset: (T, T), pos: (T, T)) -> [(usize, usize, T, T, T); 4] {
+    let (y0, x0, y1, x1, s, t) = advection::stencil(grid, dim, offset, pos);
+    let one = T::one();
+    [
+        (y0, x0, (one - s) * (one - t), -(one - s), -(one - t)),
+        (y0, x1, s * (one - t), -s, one - t),
+        (y1, x0, (one - s) * t, one - s, -t),
+        (y1, x1, s * t, s, t),
+    ]
+}
+
+/// Accumulate weighted node values onto the interpolation stencil of a position.
+fn splat<T, F>(grid: &Grid2d, field: &mut ArrayViewMut2<T>, weights_sum: &mut ArrayViewMut2<T>, offset: (T, T), pos: (T, T), value: F)
+    where T: Real, F: Fn((T, T)) -> T
+{
+    let (h, w) = grid.dim();
+    let [by, bx] = grid.boundary();
+    for &(y, x, weight, _, _) in &weights(grid, field.dim(), offset, pos) {
+        let node = (
+            closest_image(by.is_periodic(), T::new(y) + offset.0, pos.0, T::new(h)),
+            closest_image(bx.is_periodic(), T::new(x) + offset.1, pos.1, T::new(w)),
+        );
+        field[(y, x)] += weight * value(node);
+        weights_sum[(y, x)] += weight;
+    }
+}
+
+/// Gradient `(d/dy, d/dx)` of the bilinear interpolation at a position.
+fn gradient<T: Real>(grid: &Grid2d, field: ArrayView2<T>, offset: (T, T), pos: (T, T)) -> (T, T) {
+    weights(grid, field.dim(), offset, pos).iter().fold((T::zero(), T::zero()), |(gy, gx), &(y, x, _, dy, dx)| {
+        (gy + dy * field[(y, x)], gx + dx * field[(y, x)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use math::VectorN;
+    use super::*;
+
+    #[test]
+    fn flip_hydrostatic() {
+        let grid = Grid2d::new((8, 8));
+        let mut particles = Particles::new();
+        init::<f64>(&mut particles);
+
+        // 2x2 particles per cell at rest
+        let positions = (0..16 * 16).map(|i| {
+            let mut pos = VectorN::from_elem(0.0);
+            pos[0] = 0.25 + 0.5 * (i % 16) as f64;
+            pos[1] = 0.25 + 0.5 * (i / 16) as f64;
+            pos
+        }).collect::<Vec<_>>();
+        particles.add_particles(positions.len()).with::<Position<f64, U2>>(&positions);
+
+        for &ratio in &[0.0, 0.95] {
+            let mut flip = Flip::new(&grid);
+            flip.flip_ratio = ratio;
+            flip.step(&mut particles, 0.05);
+            flip.step(&mut particles, 0.05);
+
+            // gravity is balanced by the pressure in a closed, filled domain
+            for vel in particles.read_property::<Velocity<f64, U2>>() {
+                assert!(vel[0].abs() < 1.0e-2 && vel[1].abs() < 1.0e-2);
+            }
+        }
+    }
+
+    #[test]
+    fn apic_affine_transfer() {
+        let grid = Grid2d::new((6, 6));
+        let mut particles = Particles::new();
+        init::<f64>(&mut particles);
+
+        let positions = (0..12 * 12).map(|i| {
+            let mut pos = VectorN::from_elem(0.0);
+            pos[0] = 0.25 + 0.5 * (i % 12) as f64;
+            pos[1] = 0.25 + 0.5 * (i / 12) as f64;
+            pos
+        }).collect::<Vec<_>>();
+        particles.add_particles(positions.len()).with::<Position<f64, U2>>(&positions);
+
+        let mut apic = Flip::new(&grid);
+        apic.transfer = Transfer::Apic;
+        for ((_, x), v) in apic.velocity.split_mut().1.indexed_iter_mut() {
+            *v = 0.5 * x as f64 + 1.0;
+        }
+        let reference = apic.velocity.clone();
+
+        // affine fields are transferred exactly in both directions
+        apic.grid_to_particles(&mut particles);
+        for (pos, (vel, c)) in particles.read_property::<Position<f64, U2>>().iter().zip(
+            particles.read_property::<Velocity<f64, U2>>().iter().zip(particles.read_property::<AffineVelocity<f64>>()))
+        {
+            assert!((vel[0] - (0.5 * pos[0] + 1.0)).abs() < 1.0e-10);
+            assert!((c[0][0] - 0.5).abs() < 1.0e-10 && c[0][1].abs() < 1.0e-10);
+        }
+
+        apic.particles_to_grid(&particles);
+        for (&v, &r) in apic.velocity.view_linear().iter().zip(reference.view_linear().iter()) {
+            assert!((v - r).abs() < 1.0e-10);
+        }
+    }
+}
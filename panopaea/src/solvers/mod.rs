@@ -0,0 +1,8 @@
+//! Complete simulation drivers built from the individual building blocks.
+
+pub mod diagnostics;
+pub mod flip;
+pub mod pipeline;
+pub mod smoke;
+pub mod stream;
+pub mod timestep;
@@ -0,0 +1,361 @@
+//! Grid solvers composed of pluggable stages
+//!
+//! A `Pipeline` advances a `GridState` by running its stages in order each
+//! timestep. The built-in stages cover the steps of the smoke solver
+//! (advection, buoyancy, vorticity confinement, boundary conditions,
+//! viscosity, scalar diffusion and pressure projection), custom stages are
+//! implemented with the `Stage` trait or as closures. Stages can be replaced
+//! at any time, e.g. swapping the first stage of `Pipeline::smoke` for a
+//! semi-Lagrangian `Advection` to compare advection schemes.
+
+use advection::{self, Scheme};
+use dec::diffusion::Diffusion;
+use dec::grid::{self, Staggered2d};
+use dec::manifold::{Boundary, Manifold2d};
+use dec::pool::FieldPool;
+use dec::projection::Projection;
+use dec::viscosity::Viscosity;
+use domain::Grid2d;
+use math::Real;
+use ndarray::Array2;
+use std::collections::BTreeMap;
+use std::mem;
+use vorticity;
+
+/// Eulerian solver on a 2d grid.
+pub trait GridSolver<T: Real> {
+    fn grid(&self) -> &Grid2d;
+
+    /// Velocity as dual 1-form.
+    fn velocity(&self) -> &Staggered2d<T>;
+
+    /// Advance the simulation by one timestep.
+    fn step(&mut self, timestep: T);
+}
+
+/// Fields of a grid simulation shared by all stages.
+pub struct GridState<'a, T: Real> {
+    pub grid: &'a Grid2d,
+    pub velocity: Staggered2d<T>,
+    /// Pressure of the last projection.
+    pub pressure: Array2<T>,
+    /// Named scalar fields stored at the face centers, advected with the velocity.
+    pub scalars: BTreeMap<&'static str, Array2<T>>,
+    /// Temporary fields shared by the stages.
+    pub pool: FieldPool<'a, T, Grid2d>,
+}
+
+impl<'a, T: Real> GridState<'a, T> {
+    /// Fluid at rest without scalar fields.
+    pub fn new(grid: &'a Grid2d) -> Self {
+        GridState {
+            grid,
+            velocity: grid.new_simplex_1(),
+            pressure: grid.new_simplex_2(),
+            scalars: BTreeMap::new(),
+            pool: FieldPool::new(grid),
+        }
+    }
+
+    /// Add a scalar field initialized to zero, existing fields are kept.
+    pub fn add_scalar(&mut self, name: &'static str) -> &mut Array2<T> {
+        let grid = self.grid;
+        self.scalars.entry(name).or_insert_with(|| grid.new_simplex_2())
+    }
+
+    /// Panics if the field doesn't exist.
+    pub fn scalar(&self, name: &str) -> &Array2<T> {
+        self.scalars.get(name).unwrap_or_else(|| panic!("missing scalar field `{}`", name))
+    }
+
+    /// Panics if the field doesn't exist.
+    pub fn scalar_mut(&mut self, name: &str) -> &mut Array2<T> {
+        self.scalars.get_mut(name).unwrap_or_else(|| panic!("missing scalar field `{}`", name))
+    }
+
+    /// Exchange the velocity with an updated one without copying.
+    pub fn swap_velocity(&mut self, velocity: &mut Staggered2d<T>) {
+        mem::swap(&mut self.velocity, velocity);
+    }
+
+    /// Exchange a scalar field with an updated one without copying, panics if the field doesn't exist.
+    pub fn swap_scalar(&mut self, name: &str, field: &mut Array2<T>) {
+        mem::swap(self.scalar_mut(name), field);
+    }
+}
+
+/// Step of a pipeline modifying the state.
+pub trait Stage<T: Real> {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T);
+}
+
+impl<T: Real, F> Stage<T> for F
+    where F: FnMut(&mut GridState<T>, T)
+{
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        self(state, timestep)
+    }
+}
+
+/// Stages run in order on a shared state.
+pub struct Pipeline<'a, T: Real> {
+    pub state: GridState<'a, T>,
+    pub stages: Vec<Box<Stage<T> + 'a>>,
+}
+
+impl<'a, T: Real> Pipeline<'a, T> {
+    /// Empty pipeline, stages need to be added.
+    pub fn new(grid: &'a Grid2d) -> Self {
+        Pipeline {
+            state: GridState::new(grid),
+            stages: Vec::new(),
+        }
+    }
+
+    /// Same steps and default parameters as `Smoke`, without combustion and obstacles.
+    ///
+    /// Scalar fields are `density` and `temperature`.
+    pub fn smoke(grid: &'a Grid2d) -> Self {
+        let mut pipeline = Pipeline::new(grid);
+        pipeline.state.add_scalar("density");
+        pipeline.state.add_scalar("temperature");
+        pipeline
+            .with(Advection::new(Scheme::MacCormack))
+            .with(Buoyancy::default())
+            .with(VorticityConfinement(T::new(0.2)))
+            .with(BoundaryConditions)
+            .with(PressureProjection::new(grid));
+        pipeline
+    }
+
+    /// Append a stage.
+    pub fn with<S: Stage<T> + 'a>(&mut self, stage: S) -> &mut Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+}
+
+impl<'a, T: Real> GridSolver<T> for Pipeline<'a, T> {
+    fn grid(&self) -> &Grid2d {
+        self.state.grid
+    }
+
+    fn velocity(&self) -> &Staggered2d<T> {
+        &self.state.velocity
+    }
+
+    fn step(&mut self, timestep: T) {
+        for stage in &mut self.stages {
+            stage.apply(&mut self.state, timestep);
+        }
+    }
+}
+
+/// Advect all scalar fields and the velocity with the velocity at the start of the stage.
+///
+/// Advected fields are written into buffers of the pool and swapped in, the
+/// temporary fields of the MacCormack scheme are taken from the pool as well.
+#[derive(Copy, Clone, Debug)]
+pub struct Advection {
+    pub scheme: Scheme,
+}
+
+impl Advection {
+    pub fn new(scheme: Scheme) -> Self {
+        Advection { scheme }
+    }
+}
+
+impl<T: Real> Stage<T> for Advection {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        let grid = state.grid;
+        let (mut scalar, mut forward, mut backward) = (state.pool.take_2(), state.pool.take_2(), state.pool.take_2());
+        for field in state.scalars.values_mut() {
+            advection::advect_scalar_with(grid, self.scheme, &mut scalar, field, &state.velocity, timestep,
+                                          (&mut forward, &mut backward));
+            mem::swap(field, &mut scalar);
+        }
+        state.pool.recycle_2(scalar);
+        state.pool.recycle_2(forward);
+        state.pool.recycle_2(backward);
+
+        let (mut velocity, mut forward, mut backward) = (state.pool.take_1(), state.pool.take_1(), state.pool.take_1());
+        advection::advect_velocity_with(grid, self.scheme, &mut velocity, &state.velocity, &state.velocity, timestep,
+                                        (&mut forward, &mut backward));
+        state.swap_velocity(&mut velocity);
+        state.pool.recycle_1(velocity);
+        state.pool.recycle_1(forward);
+        state.pool.recycle_1(backward);
+    }
+}
+
+/// Boussinesq buoyancy on the vertical velocity components, see `Smoke`.
+///
+/// Requires the scalar fields `density` and `temperature`.
+#[derive(Copy, Clone, Debug)]
+pub struct Buoyancy<T> {
+    /// Downward force caused by the smoke density.
+    pub density_weight: T,
+    /// Upward force caused by the temperature difference to the ambient temperature.
+    pub temperature_lift: T,
+    pub ambient_temperature: T,
+}
+
+impl<T: Real> Default for Buoyancy<T> {
+    fn default() -> Self {
+        Buoyancy {
+            density_weight: T::new(0.1),
+            temperature_lift: T::one(),
+            ambient_temperature: T::zero(),
+        }
+    }
+}
+
+impl<T: Real> Stage<T> for Buoyancy<T> {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        let (alpha, beta, ambient) = (self.density_weight, self.temperature_lift, self.ambient_temperature);
+        let half = T::new(0.5);
+        let (density, temperature) = (&state.scalars["density"], &state.scalars["temperature"]);
+        let force = |y: usize, x: usize| {
+            beta * (temperature[(y, x)] - ambient) - alpha * density[(y, x)]
+        };
+
+        let (mut vertical, _) = state.velocity.split_mut();
+        let (h, w) = density.dim();
+        for y in 1..h {
+            for x in 0..w {
+                vertical[(y, x)] += timestep * half * (force(y - 1, x) + force(y, x));
+            }
+        }
+    }
+}
+
+/// Vorticity confinement with the given strength.
+#[derive(Copy, Clone, Debug)]
+pub struct VorticityConfinement<T>(pub T);
+
+impl<T: Real> Stage<T> for VorticityConfinement<T> {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        vorticity::confinement_2d(state.grid, &mut state.velocity, self.0, timestep);
+    }
+}
+
+/// Boundary conditions of the grid for the normal velocity components.
+#[derive(Copy, Clone, Debug)]
+pub struct BoundaryConditions;
+
+impl<T: Real> Stage<T> for BoundaryConditions {
+    fn apply(&mut self, state: &mut GridState<T>, _: T) {
+        grid::enforce_boundary_2d(state.grid, &mut state.velocity);
+    }
+}
+
+/// Implicit diffusion of the velocity with a kinematic viscosity.
+pub struct ViscousDiffusion<'a, T: Real> {
+    pub solver: Viscosity<'a, T>,
+    pub viscosity: T,
+}
+
+impl<'a, T: Real> ViscousDiffusion<'a, T> {
+    pub fn new(grid: &'a Grid2d, viscosity: T) -> Self {
+        ViscousDiffusion {
+            solver: Viscosity::new(grid, 500, T::new(1.0e-4)),
+            viscosity,
+        }
+    }
+}
+
+impl<'a, T: Real> Stage<T> for ViscousDiffusion<'a, T> {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        self.solver.diffuse(&mut state.velocity, self.viscosity, timestep);
+    }
+}
+
+/// Implicit diffusion of a scalar field relative to an ambient value, conserving the total amount.
+pub struct ScalarDiffusion<'a, T: Real> {
+    pub field: &'static str,
+    pub ambient: T,
+    pub diffusion: Diffusion<'a, T, Grid2d>,
+}
+
+impl<'a, T: Real> ScalarDiffusion<'a, T> {
+    pub fn new(grid: &'a Grid2d, field: &'static str, diffusivity: T) -> Self {
+        ScalarDiffusion {
+            field,
+            ambient: T::zero(),
+            diffusion: Diffusion::new(grid, Boundary::Neumann, diffusivity),
+        }
+    }
+}
+
+impl<'a, T: Real> Stage<T> for ScalarDiffusion<'a, T> {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        let ambient = self.ambient;
+        let field = state.scalar_mut(self.field);
+        field.mapv_inplace(|v| v - ambient);
+        self.diffusion.diffuse_2(field, timestep);
+        field.mapv_inplace(|v| v + ambient);
+    }
+}
+
+/// Pressure projection, solver settings and solid coupling are available on the projection.
+pub struct PressureProjection<'a, T: Real> {
+    pub projection: Projection<'a, T, Grid2d>,
+    /// Scalar field with the fluid density of each cell, unit density if `None`.
+    pub fluid_density: Option<&'static str>,
+}
+
+impl<'a, T: Real> PressureProjection<'a, T> {
+    pub fn new(grid: &'a Grid2d) -> Self {
+        PressureProjection {
+            projection: Projection::new(grid, 500, T::new(1.0e-4)),
+            fluid_density: None,
+        }
+    }
+}
+
+impl<'a, T: Real> Stage<T> for PressureProjection<'a, T> {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        if let Some(field) = self.fluid_density {
+            self.projection.edge_density = Some(grid::average_to_edges_2d(state.grid, state.scalar(field)));
+        }
+        self.projection.project(&mut state.velocity, &mut state.pressure, timestep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::{LinearView, LinearViewReal};
+    use solvers::smoke::Smoke;
+    use super::*;
+
+    #[test]
+    fn smoke_pipeline() {
+        // default pipeline reproduces the smoke solver
+        let grid = Grid2d::new((24, 16));
+        let mut smoke = Smoke::<f64>::new(&grid);
+        let mut pipeline = Pipeline::smoke(&grid);
+        for y in 2..5 {
+            for x in 6..10 {
+                smoke.density[(y, x)] = 1.0;
+                smoke.temperature[(y, x)] = 4.0;
+                pipeline.state.scalar_mut("density")[(y, x)] = 1.0;
+                pipeline.state.scalar_mut("temperature")[(y, x)] = 4.0;
+            }
+        }
+        for _ in 0..5 {
+            smoke.step(0.1);
+            pipeline.step(0.1);
+        }
+        let mut difference = smoke.velocity.clone();
+        difference.view_linear_mut().zip_mut_with(&pipeline.velocity().view_linear(), |a, &b| *a -= b);
+        assert!(difference.norm_max() < 1.0e-12);
+
+        // swapping stages from user code
+        pipeline.stages[0] = Box::new(Advection::new(Scheme::SemiLagrangian));
+        pipeline.with(|state: &mut GridState<f64>, _: f64| state.scalar_mut("density").fill(0.0));
+        pipeline.step(0.1);
+        assert!(pipeline.state.scalar("density").iter().all(|&d| d == 0.0));
+        assert!(pipeline.velocity().norm_max() > 0.0);
+    }
+}
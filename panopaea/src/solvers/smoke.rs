@@ -0,0 +1,426 @@
+//! Smoke simulation on a staggered grid
+//!
+//! Density and temperature are stored at the face centers, the velocity as
+//! dual 1-form on the edges. Gravity points towards negative `y`, buoyancy
+//! follows the Boussinesq approximation with forces linear in the density and
+//! the temperature difference to the ambient air.
+//!
+//! Fire is modeled by a fuel field, which burns above the ignition
+//! temperature. Burning fuel releases heat and smoke and expands, which is
+//! enforced as divergence of the velocity by the projection. Closed domains
+//! can't absorb the expansion, fire requires open boundaries.
+//!
+//! Moving obstacles are coupled through the pressure projection. Gravity
+//! doesn't act on them, as the ambient air isn't simulated with hydrostatic
+//! pressure.
+//!
+//! References:
+//!     [FSJ01] Ronald Fedkiw, Jos Stam and Henrik Wann Jensen, 2001,
+//!             Visual simulation of smoke,
+//!             In Proceedings of SIGGRAPH 2001, ACM, 15-22
+//!     [NFJ02] Duc Quang Nguyen, Ronald Fedkiw and Henrik Wann Jensen, 2002,
+//!             Physically based modeling and animation of fire,
+//!             ACM Trans. Graph. 21, 3 (July 2002), 721-728
+
+use advection::{self, Scheme};
+use dec::diffusion::Diffusion;
+use dec::grid::{self, Staggered2d};
+use dec::manifold::{Boundary, Manifold2d};
+use dec::projection::Projection;
+use dec::viscosity::Viscosity;
+use domain::Grid2d;
+use error::{Error, Result};
+use math::Real;
+use math::vector_n::vec2;
+use ndarray::Array2;
+use obstacle::{self, Obstacle};
+use std::mem;
+use vorticity;
+use super::pipeline::GridSolver;
+
+/// Simulation state of a smoke solver for checkpoints.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SmokeState<T> {
+    pub velocity: Staggered2d<T>,
+    pub pressure: Array2<T>,
+    pub density: Array2<T>,
+    pub temperature: Array2<T>,
+    pub fuel: Array2<T>,
+}
+
+/// Parameters of the combustion model.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Combustion<T> {
+    /// Fuel only burns in cells with a higher temperature.
+    pub ignition_temperature: T,
+    /// Burned fuel per second.
+    pub burn_rate: T,
+    /// Temperature increase per burned fuel.
+    pub heat_release: T,
+    /// Smoke density produced per burned fuel.
+    pub smoke_yield: T,
+    /// Volume expansion per burned fuel.
+    ///
+    /// Ref: [NFJ02] Sec. 3
+    pub expansion: T,
+}
+
+impl<T: Real> Default for Combustion<T> {
+    fn default() -> Self {
+        Combustion {
+            ignition_temperature: T::one(),
+            burn_rate: T::one(),
+            heat_release: T::new(5.0),
+            smoke_yield: T::new(0.5),
+            expansion: T::new(0.5),
+        }
+    }
+}
+
+pub struct Smoke<'a, T: Real> {
+    grid: &'a Grid2d,
+    projection: Projection<'a, T, Grid2d>,
+    viscosity_solver: Viscosity<'a, T>,
+    diffusion: Diffusion<'a, T, Grid2d>,
+
+    pub velocity: Staggered2d<T>,
+    pub pressure: Array2<T>,
+    pub density: Array2<T>,
+    pub temperature: Array2<T>,
+    /// Unburned fuel, only used with a combustion model.
+    pub fuel: Array2<T>,
+
+    pub scheme: Scheme,
+    /// Downward force caused by the smoke density.
+    pub density_weight: T,
+    /// Upward force caused by the temperature difference to the ambient temperature.
+    pub temperature_lift: T,
+    pub ambient_temperature: T,
+    /// Strength of the vorticity confinement.
+    pub vorticity_confinement: T,
+    /// Kinematic viscosity, diffused implicitly if positive.
+    pub viscosity: T,
+    /// Thermal diffusivity of the temperature relative to the ambient temperature.
+    pub temperature_diffusivity: T,
+    pub combustion: Option<Combustion<T>>,
+    /// Rigid obstacles, replacing the fluid fractions and solid velocities of the projection.
+    pub obstacles: Vec<Obstacle<T>>,
+    /// Density of the fluid for the forces on the obstacles.
+    pub fluid_density: T,
+
+    velocity_temp: Staggered2d<T>,
+    scalar_temp: Array2<T>,
+    // temporaries of the MacCormack scheme
+    velocity_scratch: (Staggered2d<T>, Staggered2d<T>),
+    scalar_scratch: (Array2<T>, Array2<T>),
+}
+
+impl<'a, T: Real> Smoke<'a, T> {
+    pub fn new(grid: &'a Grid2d) -> Self {
+        Smoke {
+            grid,
+            projection: Projection::new(grid, 500, T::new(1.0e-4)),
+            viscosity_solver: Viscosity::new(grid, 500, T::new(1.0e-4)),
+            diffusion: Diffusion::new(grid, Boundary::Neumann, T::zero()),
+
+            velocity: grid.new_simplex_1(),
+            pressure: grid.new_simplex_2(),
+            density: grid.new_simplex_2(),
+            temperature: grid.new_simplex_2(),
+            fuel: grid.new_simplex_2(),
+
+            scheme: Scheme::MacCormack,
+            density_weight: T::new(0.1),
+            temperature_lift: T::one(),
+            ambient_temperature: T::zero(),
+            vorticity_confinement: T::new(0.2),
+            viscosity: T::zero(),
+            temperature_diffusivity: T::zero(),
+            combustion: None,
+            obstacles: Vec::new(),
+            fluid_density: T::one(),
+
+            velocity_temp: grid.new_simplex_1(),
+            scalar_temp: grid.new_simplex_2(),
+            velocity_scratch: (grid.new_simplex_1(), grid.new_simplex_1()),
+            scalar_scratch: (grid.new_simplex_2(), grid.new_simplex_2()),
+        }
+    }
+
+    pub fn grid(&self) -> &Grid2d {
+        self.grid
+    }
+
+    /// Pressure solver settings.
+    pub fn projection_mut(&mut self) -> &mut Projection<'a, T, Grid2d> {
+        &mut self.projection
+    }
+
+    /// Viscosity solver settings.
+    pub fn viscosity_mut(&mut self) -> &mut Viscosity<'a, T> {
+        &mut self.viscosity_solver
+    }
+
+    /// Copy of the current simulation state.
+    pub fn state(&self) -> SmokeState<T> {
+        SmokeState {
+            velocity: self.velocity.clone(),
+            pressure: self.pressure.clone(),
+            density: self.density.clone(),
+            temperature: self.temperature.clone(),
+            fuel: self.fuel.clone(),
+        }
+    }
+
+    /// Resume from a state of a solver on a grid with the same dimensions.
+    ///
+    /// Fails if the dimensions of any field don't match, the solver is left unchanged.
+    pub fn restore(&mut self, state: SmokeState<T>) -> Result<()> {
+        check_dim(state.velocity.dim(), self.grid.dim())?;
+        for field in &[&state.pressure, &state.density, &state.temperature, &state.fuel] {
+            check_dim(field.dim(), self.density.dim())?;
+        }
+        self.velocity = state.velocity;
+        self.pressure = state.pressure;
+        self.density = state.density;
+        self.temperature = state.temperature;
+        self.fuel = state.fuel;
+        Ok(())
+    }
+
+    /// Advance the simulation by one timestep.
+    pub fn step(&mut self, timestep: T) {
+        self.advect(timestep);
+        if let Some(combustion) = self.combustion {
+            self.burn(combustion, timestep);
+        }
+        self.apply_buoyancy(timestep);
+        if self.vorticity_confinement > T::zero() {
+            vorticity::confinement_2d(self.grid, &mut self.velocity, self.vorticity_confinement, timestep);
+        }
+        self.enforce_boundary();
+        if self.viscosity > T::zero() {
+            self.viscosity_solver.diffuse(&mut self.velocity, self.viscosity, timestep);
+        }
+        if self.obstacles.is_empty() {
+            self.projection.project(&mut self.velocity, &mut self.pressure, timestep);
+        } else {
+            self.project_obstacles(timestep);
+        }
+    }
+
+    /// Projection with the current obstacles, which are advanced afterwards by the pressure forces.
+    fn project_obstacles(&mut self, timestep: T) {
+        self.projection.solid_velocity = Some(obstacle::solid_velocity(self.grid, &self.obstacles));
+        self.projection.fluid_fractions = Some(obstacle::fluid_fractions(self.grid, &self.obstacles));
+        self.projection.project(&mut self.velocity, &mut self.pressure, timestep);
+
+        self.projection.pressure_gradient(&mut self.velocity_temp, &self.pressure);
+        let fractions = self.projection.fluid_fractions.as_ref().expect("fluid fractions of the obstacles");
+        obstacle::pressure_forces(self.grid, &mut self.obstacles, fractions, &self.velocity_temp, self.fluid_density);
+        obstacle::advance(&mut self.obstacles, vec2(T::zero(), T::zero()), timestep);
+    }
+
+    fn advect(&mut self, timestep: T) {
+        let scratch = (&mut self.scalar_scratch.0, &mut self.scalar_scratch.1);
+        advection::advect_scalar_with(self.grid, self.scheme, &mut self.scalar_temp, &self.density, &self.velocity, timestep, scratch);
+        mem::swap(&mut self.density, &mut self.scalar_temp);
+
+        let scratch = (&mut self.scalar_scratch.0, &mut self.scalar_scratch.1);
+        advection::advect_scalar_with(self.grid, self.scheme, &mut self.scalar_temp, &self.temperature, &self.velocity, timestep, scratch);
+        mem::swap(&mut self.temperature, &mut self.scalar_temp);
+        if self.temperature_diffusivity > T::zero() {
+            let ambient = self.ambient_temperature;
+            self.diffusion.diffusivity = self.temperature_diffusivity;
+            self.temperature.mapv_inplace(|t| t - ambient);
+            self.diffusion.diffuse_2(&mut self.temperature, timestep);
+            self.temperature.mapv_inplace(|t| t + ambient);
+        }
+
+        if self.combustion.is_some() {
+            let scratch = (&mut self.scalar_scratch.0, &mut self.scalar_scratch.1);
+            advection::advect_scalar_with(self.grid, self.scheme, &mut self.scalar_temp, &self.fuel, &self.velocity, timestep, scratch);
+            mem::swap(&mut self.fuel, &mut self.scalar_temp);
+        }
+
+        let scratch = (&mut self.velocity_scratch.0, &mut self.velocity_scratch.1);
+        advection::advect_velocity_with(self.grid, self.scheme, &mut self.velocity_temp, &self.velocity, &self.velocity, timestep, scratch);
+        mem::swap(&mut self.velocity, &mut self.velocity_temp);
+    }
+
+    /// Burn fuel in cells above the ignition temperature and set the resulting expansion.
+    ///
+    /// Ref: [NFJ02] Sec. 3, 4
+    fn burn(&mut self, combustion: Combustion<T>, timestep: T) {
+        let grid = self.grid;
+        let (dy, dx) = grid.spacing();
+        let area = T::new(dy * dx);
+        let source = self.projection.divergence_source.get_or_insert_with(|| grid.new_simplex_2());
+
+        let cells = self.fuel.iter_mut()
+            .zip(self.temperature.iter_mut())
+            .zip(self.density.iter_mut())
+            .zip(source.iter_mut());
+        for (((fuel, temperature), density), source) in cells {
+            *source = T::zero();
+            if *temperature < combustion.ignition_temperature || *fuel <= T::zero() {
+                continue;
+            }
+            let burned = fuel.min(combustion.burn_rate * timestep);
+            *fuel -= burned;
+            *temperature += combustion.heat_release * burned;
+            *density += combustion.smoke_yield * burned;
+            // divergence integrated over the cell
+            *source = combustion.expansion * burned / timestep * area;
+        }
+    }
+
+    /// Buoyancy force acting on the vertical velocity components.
+    ///
+    /// Ref: [FSJ01] Eq. 8
+    fn apply_buoyancy(&mut self, timestep: T) {
+        let (alpha, beta, ambient) = (self.density_weight, self.temperature_lift, self.ambient_temperature);
+        let half = T::new(0.5);
+        let (density, temperature) = (&self.density, &self.temperature);
+        let force = |y: usize, x: usize| {
+            beta * (temperature[(y, x)] - ambient) - alpha * density[(y, x)]
+        };
+
+        let (mut vertical, _) = self.velocity.split_mut();
+        let (h, w) = density.dim();
+        for y in 1..h {
+            for x in 0..w {
+                vertical[(y, x)] += timestep * half * (force(y - 1, x) + force(y, x));
+            }
+        }
+    }
+
+    /// Boundary conditions of the grid for the normal velocity components.
+    fn enforce_boundary(&mut self) {
+        grid::enforce_boundary_2d(self.grid, &mut self.velocity);
+    }
+}
+
+impl<'a, T: Real> GridSolver<T> for Smoke<'a, T> {
+    fn grid(&self) -> &Grid2d {
+        self.grid
+    }
+
+    fn velocity(&self) -> &Staggered2d<T> {
+        &self.velocity
+    }
+
+    fn step(&mut self, timestep: T) {
+        Smoke::step(self, timestep);
+    }
+}
+
+fn check_dim(found: (usize, usize), expected: (usize, usize)) -> Result<()> {
+    if found == expected {
+        Ok(())
+    } else {
+        Err(Error::shape_mismatch([expected.0, expected.1], [found.0, found.1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::Manifold2d;
+    use domain::{AxisBoundary, BoundaryCondition};
+    use math::{LinearView, LinearViewReal};
+    use super::*;
+
+    #[test]
+    fn smoke_rises() {
+        let grid = Grid2d::new((24, 16));
+        let mut smoke = Smoke::<f64>::new(&grid);
+        for y in 2..5 {
+            for x in 6..10 {
+                smoke.density[(y, x)] = 1.0;
+                smoke.temperature[(y, x)] = 4.0;
+            }
+        }
+        let mass = smoke.density.scalar_sum();
+
+        for _ in 0..10 {
+            smoke.step(0.1);
+        }
+
+        // divergence free
+        let mut velocity_primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_1_dual(&mut velocity_primal, &smoke.velocity);
+        grid.derivative_1_primal(&mut divergence, &velocity_primal);
+        assert!(divergence.norm_max() < 1.0e-3);
+
+        // hot smoke moves upwards
+        let center = smoke.density.indexed_iter().fold(0.0, |sum, ((y, _), &d)| sum + y as f64 * d) / smoke.density.scalar_sum();
+        assert!(center > 3.0);
+        assert!(smoke.density.scalar_sum() > 0.5 * mass);
+    }
+
+    #[test]
+    fn fuel_burns() {
+        let boundary = [AxisBoundary::uniform(BoundaryCondition::Dirichlet); 2];
+        let grid = Grid2d::with_boundary((16, 16), boundary);
+        let mut smoke = Smoke::<f64>::new(&grid);
+        smoke.vorticity_confinement = 0.0;
+        smoke.combustion = Some(Combustion::default());
+        for y in 6..10 {
+            for x in 6..10 {
+                smoke.fuel[(y, x)] = 1.0;
+                smoke.temperature[(y, x)] = 2.0;
+            }
+        }
+        smoke.step(0.1);
+
+        // a tenth of the fuel burned, releasing heat and smoke
+        assert!((smoke.fuel[(7, 7)] - 0.9).abs() < 1.0e-10);
+        assert!(smoke.temperature[(7, 7)] > 2.0 && smoke.density[(7, 7)] > 0.0);
+
+        // burning cells expand
+        let mut velocity_primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_1_dual(&mut velocity_primal, &smoke.velocity);
+        grid.derivative_1_primal(&mut divergence, &velocity_primal);
+        assert!((divergence[(7, 7)] - 0.5).abs() < 1.0e-2, "{}", divergence[(7, 7)]);
+        assert!(divergence[(1, 1)].abs() < 1.0e-2);
+    }
+
+    #[test]
+    fn restore_mismatched_state() {
+        let grid = Grid2d::new((8, 6));
+        let mut smoke = Smoke::<f64>::new(&grid);
+        smoke.density[(2, 2)] = 1.0;
+        smoke.velocity.split_mut().0[(3, 3)] = 0.5;
+
+        // state of a solver on a different grid
+        let larger = Grid2d::new((8, 7));
+        match smoke.restore(Smoke::<f64>::new(&larger).state()) {
+            Err(Error::ShapeMismatch { expected, found }) => {
+                assert_eq!(expected, vec![8, 6]);
+                assert_eq!(found, vec![8, 7]);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // single mismatching field, no field is restored
+        let mut state = smoke.state();
+        state.velocity.view_linear_mut().fill(1.0);
+        state.density.fill(2.0);
+        state.fuel = Array2::zeros((3, 3));
+        match smoke.restore(state) {
+            Err(Error::ShapeMismatch { .. }) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(smoke.density[(2, 2)], 1.0);
+        assert_eq!(smoke.density.scalar_sum(), 1.0);
+        assert_eq!(smoke.velocity.view_linear().scalar_sum(), 0.5);
+        assert_eq!(smoke.fuel.dim(), (8, 6));
+
+        let mut state = smoke.state();
+        state.density.fill(2.0);
+        smoke.restore(state).unwrap();
+        assert_eq!(smoke.density.scalar_sum(), 96.0);
+    }
+}
@@ -0,0 +1,179 @@
+//! Vorticity-stream function solver
+//!
+//! Incompressible 2d flow in vorticity/stream function variables. The scalar
+//! vorticity `ω` is a primal 0-form on the grid vertices and advected with the flow.
+//! The stream function `ψ` solves `Δψ = ω` with `ψ = 0` on the closed boundary,
+//! using the 0-form laplacian of the grid.
+//!
+//! The velocity is recovered as the dual 1-form `★d ψ` and is divergence-free
+//! by construction (`d ★̃★ d = -d d = 0`), no pressure projection is required.
+//! Walls are free-slip, periodic boundaries aren't supported.
+
+use advection::Scheme;
+use dec::grid::Staggered2d;
+use dec::manifold::{Boundary, Laplacian, Manifold2d};
+use dec::ops;
+use domain::Grid2d;
+use error::{Error, Result};
+use math::{LinearView, Real};
+use ndarray::Array2;
+use pcg;
+use sparse::{Jacobi, SparseMatrix};
+
+pub struct StreamFunction<'a, T: Real> {
+    grid: &'a Grid2d,
+    laplacian: SparseMatrix<T>,
+    preconditioner: Jacobi<T>,
+
+    pub vorticity: Array2<T>,
+    pub stream: Array2<T>,
+    pub velocity: Staggered2d<T>,
+
+    pub scheme: Scheme,
+    pub max_iterations: usize,
+    pub threshold: T,
+
+    // conjugate gradient
+    rhs: Array2<T>,
+    residual: Array2<T>,
+    auxiliary: Array2<T>,
+    search: Array2<T>,
+
+    vorticity_temp: Array2<T>,
+}
+
+impl<'a, T: Real> StreamFunction<'a, T> {
+    pub fn new(grid: &'a Grid2d) -> Self {
+        StreamFunction::try_new(grid).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Fails for grids with periodic boundaries.
+    pub fn try_new(grid: &'a Grid2d) -> Result<Self> {
+        if grid.boundary().iter().any(|b| b.is_periodic()) {
+            return Err(Error::Unsupported("periodic boundaries in the stream function solver"));
+        }
+
+        let laplacian = Laplacian::new(grid, Boundary::Dirichlet).matrix_0();
+        let preconditioner = Jacobi::new(&laplacian)?;
+        Ok(StreamFunction {
+            grid,
+            laplacian,
+            preconditioner,
+
+            vorticity: grid.new_simplex_0(),
+            stream: grid.new_simplex_0(),
+            velocity: grid.new_simplex_1(),
+
+            scheme: Scheme::MacCormack,
+            max_iterations: 500,
+            threshold: T::new(1.0e-6),
+
+            rhs: grid.new_simplex_0(),
+            residual: grid.new_simplex_0(),
+            auxiliary: grid.new_simplex_0(),
+            search: grid.new_simplex_0(),
+
+            vorticity_temp: grid.new_simplex_0(),
+        })
+    }
+
+    pub fn grid(&self) -> &Grid2d {
+        self.grid
+    }
+
+    /// Initialize the vorticity from a velocity field and solve for the stream function.
+    ///
+    /// Only the rotational part of the velocity is retained.
+    pub fn set_velocity(&mut self, velocity: &Staggered2d<T>) {
+        ops::curl(self.grid, &mut self.vorticity, velocity);
+        self.clear_boundary();
+        self.solve();
+    }
+
+    /// Advance the simulation by one timestep.
+    pub fn step(&mut self, timestep: T) {
+        self.scheme.advect_field(
+            self.grid,
+            self.vorticity_temp.view_mut(),
+            self.vorticity.view(),
+            (T::zero(), T::zero()),
+            &self.velocity,
+            timestep);
+        self.vorticity.assign(&self.vorticity_temp);
+        self.clear_boundary();
+        self.solve();
+    }
+
+    /// Solve for the stream function and update the velocity.
+    pub fn solve(&mut self) {
+        // `-Δψ = -ω`, the boundary rows of the laplacian are identity rows
+        self.rhs.zip_mut_with(&self.vorticity, |rhs, &w| *rhs = -w);
+        pcg::precond_conjugate_gradient(
+            &self.preconditioner, &mut self.stream, &self.rhs,
+            self.max_iterations, self.threshold,
+            &mut self.residual, &mut self.auxiliary, &mut self.search,
+            &self.laplacian);
+
+        let mut edges = self.grid.new_simplex_1();
+        self.grid.derivative_0_primal(&mut edges, &self.stream);
+        self.grid.hodge_1_primal(&mut self.velocity, &edges);
+    }
+
+    /// Vorticity vanishes at the free-slip walls.
+    fn clear_boundary(&mut self) {
+        let (h, w) = self.grid.dim();
+        for ((y, x), v) in self.vorticity.indexed_iter_mut() {
+            if y == 0 || y == h || x == 0 || x == w {
+                *v = T::zero();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::AxisBoundary;
+    use math::LinearViewReal;
+    use std::f64::consts::PI;
+    use super::*;
+
+    #[test]
+    fn stationary_vortex() {
+        let grid = Grid2d::new((16, 16));
+        let mut solver = StreamFunction::<f64>::new(&grid);
+        solver.threshold = 1.0e-10;
+
+        // `ψ = sin(πx/16) sin(πy/16)` is a steady solution of the euler equations
+        let k = PI / 16.0;
+        let stream = Array2::from_shape_fn((17, 17), |(y, x)| (k * x as f64).sin() * (k * y as f64).sin());
+        solver.vorticity.zip_mut_with(&stream, |w, &s| *w = -2.0 * k * k * s);
+        solver.solve();
+
+        let error = solver.stream.iter().zip(stream.iter()).fold(0.0f64, |e, (a, b)| e.max((a - b).abs()));
+        assert!(error < 1.0e-2, "{}", error);
+
+        for _ in 0..5 {
+            solver.step(0.5);
+        }
+
+        let mut velocity_primal = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        let mut divergence = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+        grid.hodge_1_dual(&mut velocity_primal, &solver.velocity);
+        grid.derivative_1_primal(&mut divergence, &velocity_primal);
+        assert!(divergence.norm_max() < 1.0e-10);
+
+        let error = solver.stream.iter().zip(stream.iter()).fold(0.0f64, |e, (a, b)| e.max((a - b).abs()));
+        assert!(error < 5.0e-2, "{}", error);
+    }
+
+    #[test]
+    fn periodic_grid_unsupported() {
+        let grid = Grid2d::with_boundary((16, 16), [AxisBoundary::default(), AxisBoundary::periodic()]);
+        match StreamFunction::<f64>::try_new(&grid) {
+            Err(Error::Unsupported(_)) => (),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("periodic grid accepted"),
+        }
+        assert!(StreamFunction::<f64>::try_new(&Grid2d::new((16, 16))).is_ok());
+    }
+}
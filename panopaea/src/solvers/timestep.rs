@@ -0,0 +1,129 @@
+//! Adaptive timestep control
+//!
+//! The timestep is limited by the CFL condition, the maximum acceleration
+//! and the diffusion limit of explicit viscosity, then clamped to user bounds.
+//!
+//! References:
+//!     [Mon92] Joe J. Monaghan, 1992,
+//!             Smoothed particle hydrodynamics,
+//!             Annual Review of Astronomy and Astrophysics 30, 543-574
+//!     [BT07] Markus Becker and Matthias Teschner, 2007,
+//!            Weakly compressible SPH for free surface flows,
+//!            In Proceedings of the 2007 ACM SIGGRAPH/Eurographics symposium on Computer animation (SCA '07),
+//!            Eurographics Association, Aire-la-Ville, Switzerland, Switzerland, 209-217
+
+use cgmath::InnerSpace;
+use dec::grid::Staggered2d;
+use domain::Grid2d;
+use math::{Real, VectorN};
+use particle::Particles;
+use sph::property::{Acceleration, Velocity};
+use typenum::U2;
+
+pub struct TimestepController<T> {
+    /// Courant number `λ_v`, `dt <= λ_v h / |v|`.
+    pub courant: T,
+    /// Force factor `λ_f`, `dt <= λ_f sqrt(h / |a|)`.
+    pub force_factor: T,
+    /// Diffusion factor `λ_ν`, `dt <= λ_ν h^2 / ν`.
+    pub diffusion_factor: T,
+
+    pub min_timestep: T,
+    pub max_timestep: T,
+}
+
+impl<T: Real> TimestepController<T> {
+    pub fn new(min_timestep: T, max_timestep: T) -> Self {
+        TimestepController {
+            courant: T::new(0.4),
+            force_factor: T::new(0.25),
+            diffusion_factor: T::new(0.125),
+
+            min_timestep,
+            max_timestep,
+        }
+    }
+
+    /// Timestep for a spacing `h` and the maximum velocity, acceleration and diffusivity.
+    ///
+    /// Ref: [Mon92] Sec. 10.3, [BT07] Eq. 9
+    pub fn timestep(&self, spacing: T, max_velocity: T, max_acceleration: T, diffusivity: T) -> T {
+        let mut timestep = self.max_timestep;
+        if max_velocity > T::zero() {
+            timestep = timestep.min(self.courant * spacing / max_velocity);
+        }
+        if max_acceleration > T::zero() {
+            timestep = timestep.min(self.force_factor * (spacing / max_acceleration).sqrt());
+        }
+        if diffusivity > T::zero() {
+            timestep = timestep.min(self.diffusion_factor * spacing * spacing / diffusivity);
+        }
+        timestep.max(self.min_timestep)
+    }
+
+    /// Timestep for SPH particles with velocity and acceleration properties.
+    ///
+    /// Weakly compressible solvers need to include their numerical speed of sound, which
+    /// is added to the particle velocities. Accelerations are those of the last step.
+    pub fn particles(&self, particles: &Particles, smoothing_radius: T, speed_of_sound: T, viscosity: T) -> T {
+        let max_norm = |values: &[VectorN<T, U2>]| {
+            values.iter().fold(T::zero(), |max, v| max.max(v.magnitude()))
+        };
+
+        let max_velocity = max_norm(particles.read_property::<Velocity<T, U2>>());
+        let max_acceleration = max_norm(particles.read_property::<Acceleration<T, U2>>());
+        self.timestep(smoothing_radius, speed_of_sound + max_velocity, max_acceleration, viscosity)
+    }
+
+    /// Timestep for a velocity field on a staggered grid.
+    ///
+    /// The velocity is stored as dual 1-form, integrated along the edges.
+    pub fn staggered(&self, grid: &Grid2d, velocity: &Staggered2d<T>, viscosity: T) -> T {
+        let (dy, dx) = grid.spacing();
+        let (dy, dx) = (T::new(dy), T::new(dx));
+        let (vertical, horizontal) = velocity.split();
+
+        let max_y = vertical.fold(T::zero(), |max, &v| max.max(v.abs() / dy));
+        let max_x = horizontal.fold(T::zero(), |max, &v| max.max(v.abs() / dx));
+        self.timestep(dy.min(dx), max_y.max(max_x), T::zero(), viscosity)
+    }
+
+    /// Shorten the timestep to hit the end of a frame with `remaining` time left.
+    ///
+    /// Splits the remainder evenly instead of leaving a tiny last substep.
+    pub fn substep(&self, timestep: T, remaining: T) -> T {
+        if remaining <= timestep {
+            remaining
+        } else if remaining < T::new(2.0) * timestep {
+            T::new(0.5) * remaining
+        } else {
+            timestep
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::Manifold2d;
+    use super::*;
+
+    #[test]
+    fn timestep_limits() {
+        let controller = TimestepController::new(1.0e-5f64, 1.0e-2);
+        assert_eq!(controller.timestep(0.1, 0.0, 0.0, 0.0), 1.0e-2);
+        assert!((controller.timestep(0.1, 10.0, 0.0, 0.0) - 4.0e-3).abs() < 1.0e-12);
+        assert!((controller.timestep(0.1, 10.0, 1000.0, 0.0) - 2.5e-3).abs() < 1.0e-12);
+        assert!((controller.timestep(0.1, 10.0, 1000.0, 1.0) - 1.25e-3).abs() < 1.0e-12);
+        assert_eq!(controller.timestep(0.1, 1.0e6, 0.0, 0.0), 1.0e-5);
+
+        let grid = Grid2d::new((4, 4)).with_spacing((0.5, 0.5));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().1.fill(2.0);
+        let unbounded = TimestepController::new(1.0e-5, 1.0);
+        assert!((unbounded.staggered(&grid, &velocity, 0.0) - 0.05).abs() < 1.0e-12);
+        assert_eq!(controller.staggered(&grid, &velocity, 0.0), 1.0e-2);
+
+        assert_eq!(controller.substep(0.01, 0.015), 0.0075);
+        assert_eq!(controller.substep(0.01, 0.005), 0.005);
+    }
+}
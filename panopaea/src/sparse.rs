@@ -1,6 +1,10 @@
 
+use error::{Error, Result};
+use math::{LinearView, Real};
 use ndarray::{Array, ArrayView, ArrayViewMut, Ix1, Ix2, LinalgScalar, Zip};
-use std::ops::{Index, IndexMut, Mul};
+use std::ops::{Add, Index, IndexMut, Mul};
+
+pub use pcg::Preconditioner;
 
 /// Diagonal matrix
 pub struct DiagonalMatrix<A> {
@@ -15,6 +19,10 @@ impl<A: LinalgScalar> DiagonalMatrix<A> {
             dim: dim,
         }
     }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
 }
 
 impl<A: LinalgScalar + Send + Sync> DiagonalMatrix<A> {
@@ -87,6 +95,27 @@ impl<A> SparseMatrix<A> {
         self.data.reserve(additional);
         self.col_indices.reserve(additional);
     }
+
+    /// Column indices and values of the stored elements of a row.
+    pub fn row(&self, row: usize) -> (&[usize], &[A]) {
+        let start = self.row_indices[row];
+        let end = self.row_indices[row + 1];
+        (&self.col_indices[start..end], &self.data[start..end])
+    }
+}
+
+impl<A: LinalgScalar> SparseMatrix<A> {
+    /// Diagonal elements of a square matrix.
+    pub fn diagonal(&self) -> Vec<A> {
+        debug_assert_eq!(self.dim.0, self.dim.1);
+        (0..self.dim.0).map(|i| {
+            let (cols, values) = self.row(i);
+            match cols.binary_search(&i) {
+                Ok(pos) => values[pos],
+                Err(_) => A::zero(),
+            }
+        }).collect()
+    }
 }
 
 impl<A> SparseMatrix<A>
@@ -116,15 +145,149 @@ impl<A> SparseMatrix<A>
         }
     }
 
+    pub fn transpose(&self) -> SparseMatrix<A> {
+        let mut row_indices = vec![0; self.dim.1 + 1];
+        for &j in &self.col_indices {
+            row_indices[j + 1] += 1;
+        }
+        for j in 0..self.dim.1 {
+            row_indices[j + 1] += row_indices[j];
+        }
+
+        let mut next = row_indices.clone();
+        let mut data = vec![A::zero(); self.data.len()];
+        let mut col_indices = vec![0; self.col_indices.len()];
+
+        // rows are traversed in order, resulting in sorted columns
+        for i in 0..self.dim.0 {
+            let (cols, values) = self.row(i);
+            for (&j, &value) in cols.iter().zip(values.iter()) {
+                data[next[j]] = value;
+                col_indices[next[j]] = i;
+                next[j] += 1;
+            }
+        }
+
+        SparseMatrix {
+            data,
+            col_indices,
+            row_indices,
+            dim: (self.dim.1, self.dim.0),
+        }
+    }
+}
+
+impl<A> SparseMatrix<A>
+    where A: LinalgScalar + Send + Sync
+{
     pub fn mul_vec(&self, mut b: ArrayViewMut<A, Ix1>, x: ArrayView<A, Ix1>) {
-        for i in 0 .. b.len() {
-            let start = self.row_indices[i];
-            let end = self.row_indices[i + 1];
+        debug_assert_eq!(b.len(), self.dim.0);
+        debug_assert_eq!(x.len(), self.dim.1);
 
-            b[i] = self.col_indices[start..end].iter()
-                        .enumerate()
-                        .fold(A::zero(), |acc, (k, &j)| acc + self.data[start + k] * x[j]);
+        par_azip!(index i, mut b in {
+            let (cols, values) = self.row(i);
+            *b = cols.iter()
+                     .zip(values.iter())
+                     .fold(A::zero(), |acc, (&j, &value)| acc + value * x[j]);
+        });
+    }
+
+    /// `b = Aᵀ x` without assembling the transposed matrix.
+    pub fn mul_vec_transpose(&self, mut b: ArrayViewMut<A, Ix1>, x: ArrayView<A, Ix1>) {
+        debug_assert_eq!(b.len(), self.dim.1);
+        debug_assert_eq!(x.len(), self.dim.0);
+
+        b.fill(A::zero());
+        for i in 0..self.dim.0 {
+            let (cols, values) = self.row(i);
+            for (&j, &value) in cols.iter().zip(values.iter()) {
+                b[j] = b[j] + value * x[i];
+            }
+        }
+    }
+}
+
+/// Sparse matrix in coordinate format.
+///
+/// Used for assembling matrices, elements can be pushed in arbitrary order.
+/// Duplicate entries are summed up on conversion.
+#[derive(Debug)]
+pub struct TripletMatrix<A> {
+    data: Vec<A>,
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+    dim: (usize, usize),
+}
+
+impl<A> TripletMatrix<A> {
+    pub fn new(dim: (usize, usize)) -> Self {
+        Self::with_capacity(dim, 0)
+    }
+
+    pub fn with_capacity(dim: (usize, usize), capacity: usize) -> Self {
+        TripletMatrix {
+            data: Vec::with_capacity(capacity),
+            rows: Vec::with_capacity(capacity),
+            cols: Vec::with_capacity(capacity),
+            dim: dim,
+        }
+    }
+
+    pub fn dim(&self) -> (usize, usize) {
+        self.dim
+    }
+
+    pub fn push(&mut self, index: (usize, usize), elem: A) {
+        debug_assert!(index.0 < self.dim.0 && index.1 < self.dim.1,
+            "Element index out of bounds");
+        self.rows.push(index.0);
+        self.cols.push(index.1);
+        self.data.push(elem);
+    }
+}
+
+impl<A: LinalgScalar> TripletMatrix<A> {
+    /// Convert into compressed sparse row format.
+    pub fn to_sparse(&self) -> SparseMatrix<A> {
+        // bucket elements by row
+        let mut offsets = vec![0; self.dim.0 + 1];
+        for &i in &self.rows {
+            offsets[i + 1] += 1;
+        }
+        for i in 0..self.dim.0 {
+            offsets[i + 1] += offsets[i];
         }
+
+        let mut order = vec![0; self.data.len()];
+        {
+            let mut next = offsets.clone();
+            for (k, &i) in self.rows.iter().enumerate() {
+                order[next[i]] = k;
+                next[i] += 1;
+            }
+        }
+
+        // sort each row by column and merge duplicates
+        let mut matrix = SparseMatrix::new(self.dim);
+        matrix.reserve(self.data.len());
+        for i in 0..self.dim.0 {
+            let row = &mut order[offsets[i]..offsets[i + 1]];
+            row.sort_by_key(|&k| self.cols[k]);
+
+            let row_start = matrix.data.len();
+            for &k in row.iter() {
+                if matrix.data.len() > row_start && matrix.col_indices[matrix.col_indices.len() - 1] == self.cols[k] {
+                    let last = matrix.data.len() - 1;
+                    matrix.data[last] = matrix.data[last] + self.data[k];
+                } else {
+                    matrix.data.push(self.data[k]);
+                    matrix.col_indices.push(self.cols[k]);
+                }
+            }
+            matrix.row_indices[i + 1] = matrix.data.len();
+        }
+
+        matrix
     }
 }
 
@@ -153,3 +316,744 @@ impl<A> Mul for DiagonalMatrix<A>
         self
     }
 }
+
+/// Sparse matrix product.
+impl<'a, 'b, A> Mul<&'b SparseMatrix<A>> for &'a SparseMatrix<A>
+    where A: LinalgScalar
+{
+    type Output = SparseMatrix<A>;
+    fn mul(self, rhs: &'b SparseMatrix<A>) -> SparseMatrix<A> {
+        assert_eq!(self.dim.1, rhs.dim.0, "Matrix dimensions don't match");
+
+        let mut matrix = SparseMatrix::new((self.dim.0, rhs.dim.1));
+        matrix.reserve(self.data.len().max(rhs.data.len()));
+
+        // dense accumulator for the current row
+        let mut accum = vec![A::zero(); rhs.dim.1];
+        let mut occupied = vec![false; rhs.dim.1];
+        let mut row_cols = Vec::new();
+
+        for i in 0..self.dim.0 {
+            let (cols, values) = self.row(i);
+            for (&k, &a) in cols.iter().zip(values.iter()) {
+                let (rhs_cols, rhs_values) = rhs.row(k);
+                for (&j, &b) in rhs_cols.iter().zip(rhs_values.iter()) {
+                    if !occupied[j] {
+                        occupied[j] = true;
+                        row_cols.push(j);
+                    }
+                    accum[j] = accum[j] + a * b;
+                }
+            }
+
+            row_cols.sort();
+            for &j in &row_cols {
+                matrix.data.push(accum[j]);
+                matrix.col_indices.push(j);
+                accum[j] = A::zero();
+                occupied[j] = false;
+            }
+            row_cols.clear();
+            matrix.row_indices[i + 1] = matrix.data.len();
+        }
+
+        matrix
+    }
+}
+
+/// Sparse matrix sum.
+impl<'a, 'b, A> Add<&'b SparseMatrix<A>> for &'a SparseMatrix<A>
+    where A: LinalgScalar
+{
+    type Output = SparseMatrix<A>;
+    fn add(self, rhs: &'b SparseMatrix<A>) -> SparseMatrix<A> {
+        assert_eq!(self.dim, rhs.dim, "Matrix dimensions don't match");
+
+        let mut matrix = SparseMatrix::new(self.dim);
+        matrix.reserve(self.data.len() + rhs.data.len());
+
+        for i in 0..self.dim.0 {
+            let (cols_a, values_a) = self.row(i);
+            let (cols_b, values_b) = rhs.row(i);
+            let (mut a, mut b) = (0, 0);
+            while a < cols_a.len() || b < cols_b.len() {
+                let col_a = cols_a.get(a).cloned().unwrap_or(usize::max_value());
+                let col_b = cols_b.get(b).cloned().unwrap_or(usize::max_value());
+                if col_a == col_b {
+                    matrix.data.push(values_a[a] + values_b[b]);
+                    matrix.col_indices.push(col_a);
+                    a += 1;
+                    b += 1;
+                } else if col_a < col_b {
+                    matrix.data.push(values_a[a]);
+                    matrix.col_indices.push(col_a);
+                    a += 1;
+                } else {
+                    matrix.data.push(values_b[b]);
+                    matrix.col_indices.push(col_b);
+                    b += 1;
+                }
+            }
+            matrix.row_indices[i + 1] = matrix.data.len();
+        }
+
+        matrix
+    }
+}
+
+/// Scales the rows of the sparse matrix.
+impl<'a, 'b, A> Mul<&'b SparseMatrix<A>> for &'a DiagonalMatrix<A>
+    where A: LinalgScalar
+{
+    type Output = SparseMatrix<A>;
+    fn mul(self, rhs: &'b SparseMatrix<A>) -> SparseMatrix<A> {
+        assert_eq!(self.dim, rhs.dim.0, "Matrix dimensions don't match");
+
+        let mut data = rhs.data.clone();
+        for i in 0..rhs.dim.0 {
+            for value in &mut data[rhs.row_indices[i]..rhs.row_indices[i + 1]] {
+                *value = self[i] * *value;
+            }
+        }
+
+        SparseMatrix {
+            data,
+            col_indices: rhs.col_indices.clone(),
+            row_indices: rhs.row_indices.clone(),
+            dim: rhs.dim,
+        }
+    }
+}
+
+/// Scales the columns of the sparse matrix.
+impl<'a, 'b, A> Mul<&'b DiagonalMatrix<A>> for &'a SparseMatrix<A>
+    where A: LinalgScalar
+{
+    type Output = SparseMatrix<A>;
+    fn mul(self, rhs: &'b DiagonalMatrix<A>) -> SparseMatrix<A> {
+        assert_eq!(self.dim.1, rhs.dim, "Matrix dimensions don't match");
+
+        SparseMatrix {
+            data: self.data.iter().zip(self.col_indices.iter()).map(|(&value, &j)| value * rhs[j]).collect(),
+            col_indices: self.col_indices.clone(),
+            row_indices: self.row_indices.clone(),
+            dim: self.dim,
+        }
+    }
+}
+
+/// Jacobi (diagonal) preconditioner.
+pub struct Jacobi<A> {
+    inv_diag: Vec<A>,
+}
+
+impl<A: Real> Jacobi<A> {
+    /// Fails if a diagonal element is zero or not stored.
+    pub fn new(matrix: &SparseMatrix<A>) -> Result<Self> {
+        let diagonal = matrix.diagonal();
+        if let Some(row) = diagonal.iter().position(|&d| d == A::zero()) {
+            return Err(Error::InvalidParameter(format!("zero diagonal element in row {}", row)));
+        }
+        Ok(Jacobi {
+            inv_diag: diagonal.into_iter().map(|d| A::one() / d).collect(),
+        })
+    }
+}
+
+impl<A: Real, L: LinearView<Elem = A>> Preconditioner<L> for Jacobi<A> {
+    fn apply(&self, dst: &mut L, src: &L) {
+        let inv_diag: ArrayView<A, Ix1> = self.inv_diag.as_slice().into();
+        par_azip!(mut dst (dst.view_linear_mut()), src (src.view_linear()), inv_diag in { *dst = src * inv_diag });
+    }
+}
+
+/// Incomplete Cholesky factorization with zero fill-in, IC(0).
+///
+/// Only the lower triangular part of the symmetric matrix is read. If the
+/// factorization breaks down on a non-positive pivot, it is repeated for the
+/// diagonally shifted matrix `A + α diag(A)` with increasing `α` [Man80].
+///
+/// Ref: [Man80] T. A. Manteuffel, 1980,
+///              An incomplete factorization technique for positive definite linear systems,
+///              Mathematics of Computation 34 (150), 473-497
+pub struct IncompleteCholesky<A> {
+    /// Lower triangular factor, the diagonal is the last element of each row.
+    lower: SparseMatrix<A>,
+}
+
+impl<A: Real> IncompleteCholesky<A> {
+    /// Fails if a diagonal element is not stored or not positive.
+    pub fn new(matrix: &SparseMatrix<A>) -> Result<Self> {
+        let n = matrix.dim().0;
+        let mut lower = SparseMatrix::new((n, n));
+
+        // copy sparsity pattern of the lower triangular part, diagonal last
+        for i in 0..n {
+            let (cols, values) = matrix.row(i);
+            let diag = match cols.binary_search(&i) {
+                Ok(pos) if values[pos] > A::zero() => pos,
+                Ok(_) => return Err(Error::InvalidParameter(format!("non-positive diagonal element in row {}", i))),
+                Err(_) => return Err(Error::InvalidParameter(format!("missing diagonal element in row {}", i))),
+            };
+            lower.data.extend_from_slice(&values[..diag + 1]);
+            lower.col_indices.extend_from_slice(&cols[..diag + 1]);
+            lower.row_indices[i + 1] = lower.data.len();
+        }
+
+        let values = lower.data.clone();
+        let mut shift = A::zero();
+        while !Self::factorize(&mut lower, shift) {
+            shift = if shift == A::zero() { A::new(1.0e-3) } else { shift * A::new(2.0) };
+            lower.data.copy_from_slice(&values);
+        }
+
+        Ok(IncompleteCholesky { lower })
+    }
+
+    /// Factorize in place with the diagonal scaled by `1 + shift`, fails on breakdown.
+    fn factorize(lower: &mut SparseMatrix<A>, shift: A) -> bool {
+        let n = lower.dim().0;
+        for i in 0..n {
+            let start = lower.row_indices[i];
+            let end = lower.row_indices[i + 1];
+
+            for idx in start..end {
+                let j = lower.col_indices[idx];
+
+                // sum_k<j L[i, k] * L[j, k]
+                let sum = {
+                    let (cols_i, values_i) = lower.row(i);
+                    let (cols_j, values_j) = lower.row(j);
+                    sparse_dot(cols_i, values_i, cols_j, values_j, j)
+                };
+
+                if j < i {
+                    let diag_j = lower.data[lower.row_indices[j + 1] - 1];
+                    lower.data[idx] = (lower.data[idx] - sum) / diag_j;
+                } else {
+                    let diag = lower.data[idx] * (A::one() + shift) - sum;
+                    if !(diag > A::zero()) {
+                        return false;
+                    }
+                    lower.data[idx] = diag.sqrt();
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Dot product of two sorted sparse rows, considering only columns less than `limit`.
+fn sparse_dot<A: Real>(cols_a: &[usize], values_a: &[A], cols_b: &[usize], values_b: &[A], limit: usize) -> A {
+    let (mut a, mut b) = (0, 0);
+    let mut sum = A::zero();
+    while a < cols_a.len() && b < cols_b.len() && cols_a[a] < limit && cols_b[b] < limit {
+        if cols_a[a] == cols_b[b] {
+            sum += values_a[a] * values_b[b];
+            a += 1;
+            b += 1;
+        } else if cols_a[a] < cols_b[b] {
+            a += 1;
+        } else {
+            b += 1;
+        }
+    }
+    sum
+}
+
+impl<A: Real, L: LinearView<Elem = A>> Preconditioner<L> for IncompleteCholesky<A> {
+    fn apply(&self, dst: &mut L, src: &L) {
+        let mut dst = dst.view_linear_mut();
+        let src = src.view_linear();
+        let n = self.lower.dim().0;
+
+        // L y = r
+        for i in 0..n {
+            let (cols, values) = self.lower.row(i);
+            let last = cols.len() - 1;
+            let mut t = src[i];
+            for k in 0..last {
+                t -= values[k] * dst[cols[k]];
+            }
+            dst[i] = t / values[last];
+        }
+
+        // L^T z = y
+        for i in (0..n).rev() {
+            let (cols, values) = self.lower.row(i);
+            let last = cols.len() - 1;
+            dst[i] = dst[i] / values[last];
+            let z = dst[i];
+            for k in 0..last {
+                dst[cols[k]] -= values[k] * z;
+            }
+        }
+    }
+}
+
+/// Symmetric successive over-relaxation preconditioner.
+pub struct Ssor<'a, A: 'a> {
+    matrix: &'a SparseMatrix<A>,
+    diag: Vec<A>,
+    omega: A,
+}
+
+impl<'a, A: Real> Ssor<'a, A> {
+    /// `omega` in (0, 2), `omega = 1` corresponds to symmetric Gauss-Seidel.
+    pub fn new(matrix: &'a SparseMatrix<A>, omega: A) -> Self {
+        Ssor {
+            matrix,
+            diag: matrix.diagonal(),
+            omega,
+        }
+    }
+}
+
+impl<'a, A: Real, L: LinearView<Elem = A>> Preconditioner<L> for Ssor<'a, A> {
+    fn apply(&self, dst: &mut L, src: &L) {
+        let mut dst = dst.view_linear_mut();
+        let src = src.view_linear();
+        let n = self.matrix.dim().0;
+        let omega = self.omega;
+
+        // (D/w + L) y = r
+        for i in 0..n {
+            let (cols, values) = self.matrix.row(i);
+            let mut t = src[i];
+            for (&j, &a) in cols.iter().zip(values.iter()) {
+                if j >= i { break; }
+                t -= a * dst[j];
+            }
+            dst[i] = t * omega / self.diag[i];
+        }
+
+        // y = D/w y
+        for i in 0..n {
+            dst[i] = dst[i] * self.diag[i] / omega;
+        }
+
+        // (D/w + U) z = y
+        for i in (0..n).rev() {
+            let (cols, values) = self.matrix.row(i);
+            let mut t = dst[i];
+            for (&j, &a) in cols.iter().zip(values.iter()) {
+                if j > i { t -= a * dst[j]; }
+            }
+            dst[i] = t * omega / self.diag[i];
+        }
+
+        let scale = (A::new(2.0) - omega) / omega;
+        for i in 0..n {
+            dst[i] = dst[i] * scale;
+        }
+    }
+}
+
+/// Symbolic sparse Cholesky factorization.
+///
+/// Only depends on the sparsity pattern of the lower triangular part and
+/// can be reused for factorizing matrices with the same pattern.
+#[derive(Clone, Debug)]
+pub struct SymbolicCholesky {
+    dim: usize,
+    /// Elimination tree.
+    parent: Vec<Option<usize>>,
+    /// Strictly lower triangular pattern of `L` in row format.
+    lower_rows: Vec<usize>,
+    lower_cols: Vec<usize>,
+    /// Pattern of `L^T` in row format, diagonal elements come first.
+    upper_rows: Vec<usize>,
+    upper_cols: Vec<usize>,
+}
+
+impl SymbolicCholesky {
+    pub fn new<A>(matrix: &SparseMatrix<A>) -> Self {
+        let n = matrix.dim().0;
+        assert_eq!(n, matrix.dim().1, "Matrix must be square");
+
+        // elimination tree
+        let mut parent = vec![None; n];
+        let mut ancestor = vec![None; n];
+        for k in 0..n {
+            let (cols, _) = matrix.row(k);
+            for &j in cols {
+                if j >= k { break; }
+                let mut i = j;
+                loop {
+                    let next = ancestor[i];
+                    ancestor[i] = Some(k);
+                    match next {
+                        Some(next) if next < k => i = next,
+                        Some(_) => break,
+                        None => { parent[i] = Some(k); break; }
+                    }
+                }
+            }
+        }
+
+        // row patterns of L, obtained by walking up the elimination tree
+        let mut mark = vec![usize::max_value(); n];
+        let mut counts = vec![1; n];
+        let mut lower_rows = vec![0; n + 1];
+        let mut lower_cols = Vec::new();
+        for k in 0..n {
+            mark[k] = k;
+            let start = lower_cols.len();
+            let (cols, _) = matrix.row(k);
+            for &j in cols {
+                if j >= k { break; }
+                let mut i = j;
+                while mark[i] != k {
+                    mark[i] = k;
+                    lower_cols.push(i);
+                    i = parent[i].expect("Broken elimination tree");
+                }
+            }
+
+            // ascending order is a topological order of the elimination tree
+            lower_cols[start..].sort();
+            for &j in &lower_cols[start..] {
+                counts[j] += 1;
+            }
+            lower_rows[k + 1] = lower_cols.len();
+        }
+
+        // transposed pattern
+        let mut upper_rows = vec![0; n + 1];
+        for j in 0..n {
+            upper_rows[j + 1] = upper_rows[j] + counts[j];
+        }
+        let mut next = upper_rows.clone();
+        let mut upper_cols = vec![0; upper_rows[n]];
+        for k in 0..n {
+            for &j in &lower_cols[lower_rows[k]..lower_rows[k + 1]] {
+                upper_cols[next[j]] = k;
+                next[j] += 1;
+            }
+            upper_cols[next[k]] = k;
+            next[k] += 1;
+        }
+
+        SymbolicCholesky {
+            dim: n,
+            parent,
+            lower_rows,
+            lower_cols,
+            upper_rows,
+            upper_cols,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Parent of each column in the elimination tree.
+    pub fn elimination_tree(&self) -> &[Option<usize>] {
+        &self.parent
+    }
+
+    /// Number of non-zero elements of the factor `L`.
+    pub fn nnz(&self) -> usize {
+        self.upper_cols.len()
+    }
+}
+
+/// Sparse Cholesky factorization `A = L L^T` of a symmetric positive definite matrix.
+///
+/// Only the lower triangular part of the matrix is read.
+pub struct Cholesky<A> {
+    symbolic: SymbolicCholesky,
+    /// `L^T` in row format.
+    upper: SparseMatrix<A>,
+}
+
+impl<A: Real> Cholesky<A> {
+    /// Returns `None` if the matrix isn't positive definite.
+    pub fn new(matrix: &SparseMatrix<A>) -> Option<Self> {
+        Self::with_symbolic(SymbolicCholesky::new(matrix), matrix)
+    }
+
+    /// Factorize a matrix with a precomputed symbolic factorization.
+    pub fn with_symbolic(symbolic: SymbolicCholesky, matrix: &SparseMatrix<A>) -> Option<Self> {
+        let upper = SparseMatrix {
+            data: vec![A::zero(); symbolic.nnz()],
+            col_indices: symbolic.upper_cols.clone(),
+            row_indices: symbolic.upper_rows.clone(),
+            dim: (symbolic.dim, symbolic.dim),
+        };
+
+        let mut cholesky = Cholesky { symbolic, upper };
+        if cholesky.factorize(matrix) {
+            Some(cholesky)
+        } else {
+            None
+        }
+    }
+
+    pub fn symbolic(&self) -> &SymbolicCholesky {
+        &self.symbolic
+    }
+
+    /// Recompute the numeric factorization for a matrix with the same sparsity pattern.
+    ///
+    /// Returns `false` if the matrix isn't positive definite.
+    pub fn factorize(&mut self, matrix: &SparseMatrix<A>) -> bool {
+        let n = self.symbolic.dim;
+        debug_assert_eq!(matrix.dim(), (n, n));
+
+        let upper = &mut self.upper;
+        let mut x = vec![A::zero(); n];
+        let mut next = upper.row_indices[..n].to_vec();
+
+        // up-looking factorization, computes one row of L at a time
+        for k in 0..n {
+            let (cols, values) = matrix.row(k);
+            for (&j, &value) in cols.iter().zip(values.iter()) {
+                if j > k { break; }
+                x[j] = value;
+            }
+
+            let mut diag = x[k];
+            x[k] = A::zero();
+
+            let lower = &self.symbolic.lower_cols[self.symbolic.lower_rows[k]..self.symbolic.lower_rows[k + 1]];
+            for &j in lower {
+                let start = upper.row_indices[j];
+                let l_kj = x[j] / upper.data[start];
+                x[j] = A::zero();
+                for p in (start + 1)..next[j] {
+                    x[upper.col_indices[p]] -= upper.data[p] * l_kj;
+                }
+                diag -= l_kj * l_kj;
+                upper.data[next[j]] = l_kj;
+                next[j] += 1;
+            }
+
+            if diag <= A::zero() {
+                return false;
+            }
+            upper.data[next[k]] = diag.sqrt();
+            next[k] += 1;
+        }
+
+        true
+    }
+
+    /// Solve `A x = b` using the factorization.
+    pub fn solve<L: LinearView<Elem = A>>(&self, x: &mut L, b: &L) {
+        let mut x = x.view_linear_mut();
+        x.assign(&b.view_linear());
+        let n = self.symbolic.dim;
+
+        // L y = b
+        for j in 0..n {
+            let (cols, values) = self.upper.row(j);
+            x[j] = x[j] / values[0];
+            let y = x[j];
+            for (&i, &l) in cols[1..].iter().zip(values[1..].iter()) {
+                x[i] -= l * y;
+            }
+        }
+
+        // L^T x = y
+        for j in (0..n).rev() {
+            let (cols, values) = self.upper.row(j);
+            let mut t = x[j];
+            for (&i, &l) in cols[1..].iter().zip(values[1..].iter()) {
+                t -= l * x[i];
+            }
+            x[j] = t / values[0];
+        }
+    }
+}
+
+impl<A: Real, L: LinearView<Elem = A>> Preconditioner<L> for Cholesky<A> {
+    fn apply(&self, dst: &mut L, src: &L) {
+        self.solve(dst, src);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::LinearViewReal;
+    use ndarray::Array1;
+    use pcg;
+    use super::*;
+
+    /// 1d poisson matrix with dirichlet boundary.
+    fn poisson_1d(n: usize) -> SparseMatrix<f64> {
+        let mut matrix = SparseMatrix::new((n, n));
+        for i in 0..n {
+            if i > 0 { matrix.insert((i, i-1), -1.0); }
+            matrix.insert((i, i), 2.0);
+            if i < n-1 { matrix.insert((i, i+1), -1.0); }
+        }
+        matrix
+    }
+
+    #[test]
+    fn triplet_conversion() {
+        let mut triplets = TripletMatrix::new((3, 4));
+        triplets.push((2, 3), 1.0);
+        triplets.push((0, 1), 2.0);
+        triplets.push((2, 0), 3.0);
+        triplets.push((0, 1), 4.0);
+        triplets.push((1, 2), 5.0);
+
+        let matrix = triplets.to_sparse();
+        assert_eq!(matrix.row(0), (&[1][..], &[6.0][..]));
+        assert_eq!(matrix.row(1), (&[2][..], &[5.0][..]));
+        assert_eq!(matrix.row(2), (&[0, 3][..], &[3.0, 1.0][..]));
+
+        let transposed = matrix.transpose();
+        assert_eq!(transposed.dim(), (4, 3));
+        assert_eq!(transposed.row(0), (&[2][..], &[3.0][..]));
+        assert_eq!(transposed.row(1), (&[0][..], &[6.0][..]));
+        assert_eq!(transposed.row(2), (&[1][..], &[5.0][..]));
+        assert_eq!(transposed.row(3), (&[2][..], &[1.0][..]));
+
+        let x = Array1::from_vec(vec![1.0, 2.0, 3.0, 4.0]);
+        let mut b = Array1::zeros(3);
+        matrix.mul_vec(b.view_mut(), x.view());
+        assert_eq!(b, Array1::from_vec(vec![12.0, 15.0, 7.0]));
+    }
+
+    fn solve<P: Preconditioner<Array1<f64>>>(matrix: &SparseMatrix<f64>, precond: &P) {
+        let n = matrix.dim().0;
+        let b = Array1::from_elem(n, 1.0);
+        let mut x = Array1::zeros(n);
+        let (mut residual, mut auxiliary, mut search) = (Array1::zeros(n), Array1::zeros(n), Array1::zeros(n));
+
+        pcg::precond_conjugate_gradient(
+            precond, &mut x, &b,
+            2 * n, 1.0e-10,
+            &mut residual, &mut auxiliary, &mut search,
+            matrix);
+
+        let mut ax = Array1::zeros(n);
+        matrix.mul_vec(ax.view_mut(), x.view());
+        ax.scaled_add(-1.0, &b);
+        assert!(ax.norm_max() < 1.0e-8);
+    }
+
+    #[test]
+    fn incomplete_cholesky_tridiagonal() {
+        // IC(0) is exact for tridiagonal matrices
+        let matrix = poisson_1d(16);
+        let precond = IncompleteCholesky::new(&matrix).unwrap();
+
+        let x = Array1::from_shape_fn(16, |i| (i as f64 * 0.37).sin());
+        let mut b = Array1::zeros(16);
+        let mut y = Array1::zeros(16);
+        matrix.mul_vec(b.view_mut(), x.view());
+        precond.apply(&mut y, &b);
+
+        y.scaled_add(-1.0, &x);
+        assert!(y.norm_max() < 1.0e-8);
+    }
+
+    #[test]
+    fn preconditioner_pivots() {
+        // missing and non-positive diagonal elements
+        let mut matrix = SparseMatrix::new((2, 2));
+        matrix.insert((0, 0), 1.0);
+        matrix.insert((1, 0), 0.5);
+        matrix.insert((0, 1), 0.5);
+        assert!(Jacobi::new(&matrix).is_err());
+        assert!(IncompleteCholesky::new(&matrix).is_err());
+        matrix.insert((1, 1), -1.0);
+        assert!(Jacobi::new(&matrix).is_ok());
+        assert!(IncompleteCholesky::new(&matrix).is_err());
+
+        // breakdown of the unshifted factorization, `1 - 2² < 0`
+        let mut matrix = SparseMatrix::new((2, 2));
+        matrix.insert((0, 0), 1.0);
+        matrix.insert((1, 0), 2.0);
+        matrix.insert((0, 1), 2.0);
+        matrix.insert((1, 1), 1.0);
+        let precond = IncompleteCholesky::new(&matrix).unwrap();
+        let mut y = Array1::<f64>::zeros(2);
+        precond.apply(&mut y, &Array1::from_vec(vec![1.0, 1.0]));
+        assert!(y.iter().all(|y| y.is_finite()));
+    }
+
+    #[test]
+    fn nonsymmetric_solvers() {
+        // 1d advection-diffusion
+        let n = 32;
+        let mut triplets = TripletMatrix::new((n, n));
+        for i in 0..n {
+            if i > 0 { triplets.push((i, i-1), -1.5); }
+            triplets.push((i, i), 2.0);
+            if i < n-1 { triplets.push((i, i+1), -0.5); }
+        }
+        let matrix = triplets.to_sparse();
+        let b = Array1::from_shape_fn(n, |i| (i as f64 * 0.37).sin());
+
+        let check = |x: &Array1<f64>| {
+            let mut ax = Array1::zeros(n);
+            matrix.mul_vec(ax.view_mut(), x.view());
+            ax.scaled_add(-1.0, &b);
+            assert!(ax.norm_max() < 1.0e-8);
+        };
+
+        let mut x = Array1::zeros(n);
+        pcg::precond_bicgstab(&Jacobi::new(&matrix).unwrap(), &mut x, &b, 4 * n, 1.0e-10, &matrix);
+        check(&x);
+
+        pcg::precond_gmres(&(), &mut x, &b, 16, 16 * n, 1.0e-10, &matrix);
+        check(&x);
+    }
+
+    #[test]
+    fn cholesky_poisson_2d() {
+        let (h, w) = (6, 7);
+        let n = h * w;
+        let poisson = |scale: f64| {
+            let mut triplets = TripletMatrix::new((n, n));
+            for y in 0..h {
+                for x in 0..w {
+                    let i = y * w + x;
+                    triplets.push((i, i), 4.0 * scale);
+                    if x > 0 { triplets.push((i, i - 1), -scale); }
+                    if x < w-1 { triplets.push((i, i + 1), -scale); }
+                    if y > 0 { triplets.push((i, i - w), -scale); }
+                    if y < h-1 { triplets.push((i, i + w), -scale); }
+                }
+            }
+            triplets.to_sparse()
+        };
+
+        let b = Array1::from_shape_fn(n, |i| (i as f64 * 0.37).sin());
+        let mut x = Array1::zeros(n);
+        let check = |matrix: &SparseMatrix<f64>, x: &Array1<f64>| {
+            let mut ax = Array1::zeros(n);
+            matrix.mul_vec(ax.view_mut(), x.view());
+            ax.scaled_add(-1.0, &b);
+            assert!(ax.norm_max() < 1.0e-10);
+        };
+
+        let matrix = poisson(1.0);
+        let mut cholesky = Cholesky::new(&matrix).unwrap();
+        cholesky.solve(&mut x, &b);
+        check(&matrix, &x);
+
+        // reuse symbolic factorization
+        let matrix = poisson(2.5);
+        assert!(cholesky.factorize(&matrix));
+        cholesky.solve(&mut x, &b);
+        check(&matrix, &x);
+
+        assert!(Cholesky::new(&poisson(-1.0)).is_none());
+    }
+
+    #[test]
+    fn preconditioned_cg() {
+        let matrix = poisson_1d(32);
+        solve(&matrix, &());
+        solve(&matrix, &Jacobi::new(&matrix).unwrap());
+        solve(&matrix, &IncompleteCholesky::new(&matrix).unwrap());
+        solve(&matrix, &Ssor::new(&matrix, 1.2));
+    }
+}
@@ -0,0 +1,349 @@
+//! Boundary handling with sampled boundary particles
+//!
+//! Solid walls and obstacles are represented by static particles sampled on
+//! their surface. Each boundary particle `b` contributes like a fluid particle
+//! with mass `Ψ_b = ρ0 V_b`, where the volume `V_b = 1 / Σ_k W_bk` is estimated
+//! from the neighboring boundary particles. This corrects for irregular and
+//! one-layered samplings of the boundary.
+//!
+//! References:
+//!     [AIA12] Nadir Akinci, Markus Ihmsen, Gizem Akinci, Barbara Solenthaler, and Matthias Teschner, 2012,
+//!             Versatile rigid-fluid coupling for incompressible SPH,
+//!             ACM Trans. Graph. 31, 4, Article 62
+
+use cgmath::MetricSpace;
+use math::{Real, VectorN};
+use math::vector_n::vec2;
+use particle::Processor;
+use typenum::{U2, U3};
+
+use super::grid::BoundedGrid;
+use super::kernel::SmoothingKernel;
+use super::property::{Acceleration, Density, Position, Pressure};
+use super::rigid::RigidBody;
+use super::sort::ParticleSort;
+
+/// Boundary particles sorted into their own grid.
+///
+/// Particles sampled on rigid bodies move with the body velocity, static particles are at rest.
+pub struct BoundaryParticles<T: Real> {
+    positions: Vec<VectorN<T, U2>>,
+    volumes: Vec<T>,
+    velocities: Vec<VectorN<T, U2>>,
+    bodies: Vec<Option<usize>>,
+    grid: BoundedGrid<T, U2>,
+}
+
+impl<T: Real> BoundaryParticles<T> {
+    /// Boundary without any particles.
+    pub fn empty(num_cells: VectorN<usize, U2>, cell_size: T) -> Self {
+        BoundaryParticles {
+            positions: Vec::new(),
+            volumes: Vec::new(),
+            velocities: Vec::new(),
+            bodies: Vec::new(),
+            grid: BoundedGrid::new(num_cells, cell_size),
+        }
+    }
+
+    /// Sort the boundary particles and compute their volumes.
+    ///
+    /// The grid should match the grid of the fluid particles, particles outside of the grid are ignored.
+    ///
+    /// Ref: [AIA12] Eq. 4
+    pub fn new<K>(positions: Vec<VectorN<T, U2>>, kernel: &K, num_cells: VectorN<usize, U2>, cell_size: T) -> Self
+        where K: SmoothingKernel<T>
+    {
+        let velocities = vec![VectorN::from_elem(T::zero()); positions.len()];
+        let bodies = vec![None; positions.len()];
+        Self::build(positions, velocities, bodies, kernel, num_cells, cell_size)
+    }
+
+    /// Static boundary particles combined with the current samples of rigid bodies.
+    pub fn with_bodies<K>(
+        mut positions: Vec<VectorN<T, U2>>,
+        rigid_bodies: &[RigidBody<T>],
+        kernel: &K,
+        num_cells: VectorN<usize, U2>,
+        cell_size: T,
+    ) -> Self
+        where K: SmoothingKernel<T>
+    {
+        let mut velocities = vec![VectorN::from_elem(T::zero()); positions.len()];
+        let mut bodies = vec![None; positions.len()];
+        for (i, body) in rigid_bodies.iter().enumerate() {
+            for sample in body.samples() {
+                velocities.push(body.velocity_at(&sample));
+                bodies.push(Some(i));
+                positions.push(sample);
+            }
+        }
+        Self::build(positions, velocities, bodies, kernel, num_cells, cell_size)
+    }
+
+    fn build<K>(
+        mut positions: Vec<VectorN<T, U2>>,
+        mut velocities: Vec<VectorN<T, U2>>,
+        mut bodies: Vec<Option<usize>>,
+        kernel: &K,
+        num_cells: VectorN<usize, U2>,
+        cell_size: T,
+    ) -> Self
+        where K: SmoothingKernel<T>
+    {
+        let mut grid = BoundedGrid::new(num_cells, cell_size);
+
+        let mut sort = ParticleSort::new();
+        sort.sort(&positions, grid.num_keys(), |pos| grid.get_key(pos));
+        sort.permute(&mut positions);
+        sort.permute(&mut velocities);
+        sort.permute(&mut bodies);
+        let num_inside = positions.iter().take_while(|pos| grid.get_cell(pos).is_some()).count();
+        positions.truncate(num_inside);
+        velocities.truncate(num_inside);
+        bodies.truncate(num_inside);
+
+        grid.construct_ranges(&positions).expect("boundary particles are sorted by cell");
+
+        let volumes = positions.iter().map(|pos| {
+            let cell = grid.get_cell(pos).unwrap();
+            let mut sum = T::zero();
+            grid.for_each_neighbor(cell, 1, |k| sum += kernel.w(pos.distance(positions[k])));
+            T::one() / sum
+        }).collect();
+
+        BoundaryParticles { positions, volumes, velocities, bodies, grid }
+    }
+
+    pub fn positions(&self) -> &[VectorN<T, U2>] {
+        &self.positions
+    }
+
+    pub fn volumes(&self) -> &[T] {
+        &self.volumes
+    }
+
+    pub fn velocities(&self) -> &[VectorN<T, U2>] {
+        &self.velocities
+    }
+
+    /// Index of the rigid body each particle belongs to, `None` for static particles.
+    pub fn bodies(&self) -> &[Option<usize>] {
+        &self.bodies
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Apply function to the position and volume of each boundary particle close to a position.
+    pub fn for_each_neighbor<F>(&self, position: &VectorN<T, U2>, mut fnc: F)
+        where F: FnMut(&VectorN<T, U2>, T)
+    {
+        if self.is_empty() {
+            return;
+        }
+        if let Some(cell) = self.grid.get_cell(position) {
+            self.grid.for_each_neighbor(cell, 1, |b| fnc(&self.positions[b], self.volumes[b]));
+        }
+    }
+
+    /// Apply function to the index of each boundary particle close to a position.
+    pub fn for_each_neighbor_index<F>(&self, position: &VectorN<T, U2>, fnc: F)
+        where F: FnMut(usize)
+    {
+        if self.is_empty() {
+            return;
+        }
+        if let Some(cell) = self.grid.get_cell(position) {
+            self.grid.for_each_neighbor(cell, 1, fnc);
+        }
+    }
+}
+
+/// Add the density contribution `Σ ρ0 V_b W_ib` of the boundary to the fluid particles.
+///
+/// Ref: [AIA12] Eq. 6
+pub fn boundary_density<T, K>(p: &Processor, kernel: &K, boundary: &BoundaryParticles<T>, rest_density: T)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (densities, positions) = (
+        p.write_property::<Density<T>>(),
+        p.read_property::<Position<T, U2>>(),
+    );
+
+    par_azip!(mut density (densities), pos (positions) in {
+        boundary.for_each_neighbor(&pos, |b, volume| {
+            *density += rest_density * volume * kernel.w(pos.distance(*b));
+        });
+    });
+}
+
+/// Add the pressure forces `-Ψ_b p_i / ρ_i^2 ∇W_ib` of the boundary to the fluid accelerations.
+///
+/// Ref: [AIA12] Eq. 10
+pub fn boundary_pressure_forces<T, K>(p: &Processor, kernel: &K, boundary: &BoundaryParticles<T>, rest_density: T)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (accels, positions, densities, pressures) = (
+        p.write_property::<Acceleration<T, U2>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Pressure<T>>(),
+    );
+
+    par_azip!(mut accel (accels), pos (positions), density (densities), pressure (pressures) in {
+        let pressure_i = pressure / (density * density);
+        boundary.for_each_neighbor(&pos, |b, volume| {
+            let grad = (pos - *b) * kernel.grad_w(pos.distance(*b));
+            *accel -= grad * (rest_density * volume * pressure_i);
+        });
+    });
+}
+
+/// Samples boundary particles on the surface of shapes with a fixed spacing.
+///
+/// Spacings between half and the full fluid particle spacing are common.
+pub struct BoundarySampler<T: Real> {
+    pub spacing: T,
+}
+
+impl<T: Real> BoundarySampler<T> {
+    pub fn new(spacing: T) -> Self {
+        BoundarySampler { spacing }
+    }
+
+    /// Number of intervals for sampling a distance.
+    fn intervals(&self, length: T) -> usize {
+        (length / self.spacing).round().to_usize().unwrap().max(1)
+    }
+
+    /// Line segment including both end points.
+    pub fn segment(&self, a: VectorN<T, U2>, b: VectorN<T, U2>) -> Vec<VectorN<T, U2>> {
+        let n = self.intervals(a.distance(b));
+        (0..n+1).map(|i| {
+            let t = T::new(i as f64) / T::new(n as f64);
+            a + (b - a) * t
+        }).collect()
+    }
+
+    /// Connected line segments, closed polylines also connect the last and first point.
+    pub fn polyline(&self, points: &[VectorN<T, U2>], closed: bool) -> Vec<VectorN<T, U2>> {
+        let mut samples = Vec::new();
+        let num_segments = if closed { points.len() } else { points.len().saturating_sub(1) };
+        for i in 0..num_segments {
+            let segment = self.segment(points[i], points[(i + 1) % points.len()]);
+            // skip the end points, which are shared with the next segment
+            let end = segment.len() - 1;
+            samples.extend_from_slice(&segment[..end]);
+        }
+        if !closed {
+            samples.extend(points.last().cloned());
+        }
+        samples
+    }
+
+    /// Outline of an axis aligned box.
+    pub fn rectangle(&self, min: VectorN<T, U2>, max: VectorN<T, U2>) -> Vec<VectorN<T, U2>> {
+        let corners = [min, vec2(max[0], min[1]), max, vec2(min[0], max[1])];
+        self.polyline(&corners, true)
+    }
+
+    /// Circle outline.
+    pub fn circle(&self, center: VectorN<T, U2>, radius: T) -> Vec<VectorN<T, U2>> {
+        let two_pi = T::new(2.0 * ::std::f64::consts::PI);
+        let n = self.intervals(two_pi * radius);
+        (0..n).map(|i| {
+            let phi = two_pi * T::new(i as f64) / T::new(n as f64);
+            center + vec2(phi.cos(), phi.sin()) * radius
+        }).collect()
+    }
+
+    /// Triangle surface including its edges.
+    pub fn triangle(&self, a: VectorN<T, U3>, b: VectorN<T, U3>, c: VectorN<T, U3>) -> Vec<VectorN<T, U3>> {
+        // barycentric lattice along the two edges starting at `a`
+        let n = self.intervals(a.distance(b).max(a.distance(c)).max(b.distance(c)));
+        let mut samples = Vec::new();
+        for i in 0..n+1 {
+            for j in 0..n+1-i {
+                let u = T::new(i as f64) / T::new(n as f64);
+                let v = T::new(j as f64) / T::new(n as f64);
+                samples.push(a + (b - a) * u + (c - a) * v);
+            }
+        }
+        samples
+    }
+
+    /// Surface of a triangle mesh, shared edges and vertices are sampled once per triangle.
+    pub fn mesh(&self, vertices: &[VectorN<T, U3>], triangles: &[[usize; 3]]) -> Vec<VectorN<T, U3>> {
+        triangles.iter().flat_map(|t| self.triangle(vertices[t[0]], vertices[t[1]], vertices[t[2]])).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use particle::Particles;
+    use sph::kernel::CubicSpline;
+    use sph::property::{Mass, Velocity};
+    use sph::wcsph::{self, Wcsph};
+    use super::*;
+
+    #[test]
+    fn boundary_density_deficiency() {
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+        {
+            let mut positions = Vec::new();
+            for y in 0..10 {
+                for x in 0..20 {
+                    positions.push(vec2(1.0 + x as f64 * spacing, 1.1 + y as f64 * spacing));
+                }
+            }
+            let masses = vec![rest_density * spacing * spacing; positions.len()];
+            particles.add_particles(positions.len())
+                     .with::<Position<f64, U2>>(&positions)
+                     .with::<Mass<f64>>(&masses);
+        }
+
+        // two layered floor below the fluid
+        let sampler = BoundarySampler::new(spacing);
+        let mut floor = sampler.segment(vec2(0.2, 1.0), vec2(3.8, 1.0));
+        floor.extend(sampler.segment(vec2(0.2, 0.9), vec2(3.8, 0.9)));
+
+        let mut solver = Wcsph::new(vec2(20, 20), 2.0 * spacing, rest_density);
+        solver.pipeline.gravity = vec2(0.0, 0.0);
+        solver.pipeline.set_boundary(floor);
+        assert_eq!(solver.pipeline.boundary().len(), 74);
+        solver.step(&mut particles, 1.0e-4);
+
+        // without boundary the density drops to about 840
+        let positions = particles.read_property::<Position<f64, U2>>();
+        let i = (0..positions.len()).find(|&i| positions[i].distance(vec2(1.9, 1.1)) < 1.0e-3).unwrap();
+        let density = particles.read_property::<Density<f64>>()[i];
+        assert!((density - rest_density).abs() < 0.05 * rest_density, "{}", density);
+
+        // pushed away from the floor
+        assert!(particles.read_property::<Velocity<f64, U2>>()[i][1] > 0.0);
+    }
+
+    #[test]
+    fn sampler_spacing() {
+        let sampler = BoundarySampler::new(0.1);
+        assert_eq!(sampler.rectangle(vec2(0.0, 0.0), vec2(1.0, 0.5)).len(), 30);
+        assert_eq!(sampler.polyline(&[vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0)], false).len(), 21);
+
+        let kernel = CubicSpline::new_2d(0.2);
+        let circle = BoundaryParticles::new(sampler.circle(vec2(1.0, 1.0), 0.5), &kernel, vec2(10, 10), 0.2);
+        assert_eq!(circle.len(), 31);
+        // volumes of a regular sampling are uniform
+        let (min, max) = circle.volumes().iter().fold((1.0f64, 0.0f64), |(min, max), &v| (min.min(v), max.max(v)));
+        assert!(max - min < 1.0e-9);
+    }
+}
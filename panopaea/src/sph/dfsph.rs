@@ -0,0 +1,472 @@
+//! Divergence-Free Smoothed Particle Hydrodynamics (DFSPH)
+//!
+//! Enforces incompressibility with two pressure solvers per timestep: the
+//! divergence-free solver removes the velocity divergence `Dρ/Dt` and the
+//! constant density solver corrects the remaining density deviation.
+//! Both use the per-particle factors `α`, which only depend on the positions.
+//!
+//! References:
+//!     [BK15] Jan Bender and Dan Koschier, 2015,
+//!            Divergence-free smoothed particle hydrodynamics,
+//!            In Proceedings of the 14th ACM SIGGRAPH / Eurographics Symposium on Computer Animation (SCA '15),
+//!            ACM, New York, NY, USA, 147-155
+//!     [AIA12] Nadir Akinci, Markus Ihmsen, Gizem Akinci, Barbara Solenthaler, and Matthias Teschner, 2012,
+//!             Versatile rigid-fluid coupling for incompressible SPH,
+//!             ACM Trans. Graph. 31, 4, Article 62
+
+use cgmath::{InnerSpace, MetricSpace};
+use math::{Real, VectorN};
+use num::Zero;
+use particle::{Particles, Processor};
+use typenum::U2;
+
+use super::boundary::BoundaryParticles;
+use super::grid::BoundedGrid;
+use super::isph::PressureSolver;
+use super::kernel::SmoothingKernel;
+use super::property::*;
+use super::solver::{Diagnostics, Fluid, Pipeline, Solver};
+use super::wcsph;
+
+/// Particles with fewer neighbors (excluding themselves) aren't corrected by the divergence solver.
+const MIN_NEIGHBORS: usize = 6;
+
+/// Divergence-free SPH solver for 2d domains.
+///
+/// Uses the properties registered by `wcsph::init`, the pressure property is unused.
+/// Particles with spacing `s` should have a mass of `rest_density * s^2`,
+/// a smoothing radius of `2s` is a common choice.
+pub struct Dfsph<T: Real> {
+    /// Neighborhood, boundary, rigid bodies and non-pressure forces.
+    pub pipeline: Pipeline<T>,
+
+    pub rest_density: T,
+
+    /// Maximum average density error relative to the rest density.
+    pub density_threshold: T,
+    /// Maximum average density change per second relative to the rest density.
+    pub divergence_threshold: T,
+    pub max_iterations: usize,
+
+    /// Iterations of the constant density solver in the last step.
+    pub density_iterations: usize,
+    /// Iterations of the divergence-free solver in the last step.
+    pub divergence_iterations: usize,
+
+    alpha: Vec<T>,
+    neighbors: Vec<usize>,
+    source: Vec<T>,
+    kappa: Vec<T>,
+    /// `κ` summed over all iterations of both solvers, for the forces on rigid bodies.
+    kappa_sum: Vec<T>,
+}
+
+impl<T: Real> Dfsph<T> {
+    /// Solver for the domain `[0, num_cells * smoothing_radius]`.
+    pub fn new(num_cells: VectorN<usize, U2>, smoothing_radius: T, rest_density: T) -> Self {
+        Dfsph {
+            pipeline: Pipeline::new(num_cells, smoothing_radius),
+
+            rest_density,
+
+            density_threshold: T::new(1.0e-3),
+            divergence_threshold: T::new(1.0e-1),
+            max_iterations: 100,
+
+            density_iterations: 0,
+            divergence_iterations: 0,
+
+            alpha: Vec::new(),
+            neighbors: Vec::new(),
+            source: Vec::new(),
+            kappa: Vec::new(),
+            kappa_sum: Vec::new(),
+        }
+    }
+
+    /// Advance the simulation by one timestep.
+    ///
+    /// Ref: [BK15] Alg. 1
+    pub fn step(&mut self, particles: &mut Particles, timestep: T) {
+        self.pipeline.prepare(particles);
+
+        let num_particles = particles.num_particles();
+        self.alpha.resize(num_particles, T::zero());
+        self.neighbors.resize(num_particles, 0);
+        self.source.resize(num_particles, T::zero());
+        self.kappa.resize(num_particles, T::zero());
+        self.kappa_sum.clear();
+        self.kappa_sum.resize(num_particles, T::zero());
+
+        {
+            let pipeline = &self.pipeline;
+            let (grid, kernel, boundary) = (pipeline.grid(), pipeline.kernel(), pipeline.boundary());
+            let (alpha, neighbors) = (&mut self.alpha, &mut self.neighbors);
+            let rest_density = self.rest_density;
+            particles
+                .run(|p| pipeline.densities(p, rest_density))
+                .run(|p| compute_alpha(p, alpha, neighbors, kernel, grid, boundary, rest_density));
+        }
+
+        self.divergence_iterations = self.solve(particles, timestep, Correction::Divergence);
+
+        {
+            let (pipeline, rest_density) = (&mut self.pipeline, self.rest_density);
+            particles.run(|p| {
+                pipeline.forces(p, rest_density);
+                pipeline.accelerate(p, timestep);
+            });
+        }
+
+        self.density_iterations = self.solve(particles, timestep, Correction::Density);
+
+        {
+            // reaction of the pressure corrections, accumulated over the whole step
+            let (pipeline, kappa_sum) = (&mut self.pipeline, &self.kappa_sum);
+            let rest_density = self.rest_density;
+            particles.run(|p| {
+                let densities = p.read_property::<Density<T>>();
+                pipeline.body_reactions(p, rest_density, |i| kappa_sum[i] / densities[i]);
+            });
+        }
+        self.pipeline.step_bodies(timestep);
+
+        let pipeline = &mut self.pipeline;
+        particles.run(|p| pipeline.advect(p, timestep));
+    }
+
+    /// Iterate pressure corrections of the velocities until the average error drops below the threshold.
+    ///
+    /// Returns the number of iterations.
+    ///
+    /// Ref: [BK15] Alg. 2, Alg. 3
+    fn solve(&mut self, particles: &mut Particles, timestep: T, correction: Correction) -> usize {
+        let (grid, kernel, boundary) = (self.pipeline.grid(), self.pipeline.kernel(), self.pipeline.boundary());
+        let (alpha, neighbors) = (&self.alpha, &self.neighbors);
+        let (source, kappa, kappa_sum) = (&mut self.source, &mut self.kappa, &mut self.kappa_sum);
+        let (rest_density, max_iterations) = (self.rest_density, self.max_iterations);
+        let (threshold, min_iterations) = match correction {
+            Correction::Density => (self.density_threshold * rest_density, 2),
+            Correction::Divergence => (self.divergence_threshold * rest_density * timestep, 1),
+        };
+
+        let mut iterations = 0;
+        particles.run(|p| {
+            let num_particles = alpha.len();
+            while iterations < max_iterations {
+                // `ρ* - ρ0` or `dt Dρ/Dt`
+                density_change(p, source, kernel, grid, boundary, rest_density, timestep);
+                {
+                    let densities = p.read_property::<Density<T>>();
+                    par_azip!(mut s (&mut source[..]), density (densities), neighbors (&neighbors[..]) in {
+                        *s = match correction {
+                            Correction::Density => (density + *s - rest_density).max(T::zero()),
+                            Correction::Divergence if neighbors >= MIN_NEIGHBORS => s.max(T::zero()),
+                            Correction::Divergence => T::zero(),
+                        };
+                    });
+                }
+
+                let error = source.iter().fold(T::zero(), |sum, &s| sum + s) / T::new(num_particles.max(1));
+                if iterations >= min_iterations && error <= threshold {
+                    break;
+                }
+
+                par_azip!(mut kappa (&mut kappa[..]), source (&source[..]), alpha (&alpha[..]) in {
+                    *kappa = source * alpha / (timestep * timestep);
+                });
+                apply_pressure(p, kappa, kernel, grid, boundary, rest_density, timestep);
+                par_azip!(mut sum (&mut kappa_sum[..]), kappa (&kappa[..]) in { *sum += kappa; });
+                iterations += 1;
+            }
+        });
+        iterations
+    }
+}
+
+impl<T: Real> Solver<T> for Dfsph<T> {
+    fn init(&self, particles: &mut Particles) {
+        wcsph::init::<T, U2>(particles);
+    }
+
+    fn step(&mut self, particles: &mut Particles, timestep: T) {
+        Dfsph::step(self, particles, timestep);
+    }
+
+    fn pipeline(&self) -> &Pipeline<T> {
+        &self.pipeline
+    }
+
+    fn pipeline_mut(&mut self) -> &mut Pipeline<T> {
+        &mut self.pipeline
+    }
+
+    fn diagnostics(&self, particles: &Particles) -> Diagnostics<T> {
+        Diagnostics {
+            pressure_iterations: self.density_iterations,
+            divergence_iterations: self.divergence_iterations,
+            ..Diagnostics::measure(particles, self.rest_density)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Correction {
+    Density,
+    Divergence,
+}
+
+/// Constant density solver of DFSPH for `Isph`.
+///
+/// Corrects the density deviation without the divergence-free solver,
+/// which allows comparing it with other pressure solvers. The pressures
+/// `p_i = ρ_i Σ κ_i` are summed over all iterations.
+///
+/// Ref: [BK15] Alg. 3
+pub struct DensitySolver<T: Real> {
+    /// Maximum average density error relative to the rest density.
+    pub threshold: T,
+    pub max_iterations: usize,
+
+    alpha: Vec<T>,
+    neighbors: Vec<usize>,
+    source: Vec<T>,
+    kappa: Vec<T>,
+}
+
+impl<T: Real> DensitySolver<T> {
+    pub fn new() -> Self {
+        DensitySolver {
+            threshold: T::new(1.0e-3),
+            max_iterations: 100,
+
+            alpha: Vec::new(),
+            neighbors: Vec::new(),
+            source: Vec::new(),
+            kappa: Vec::new(),
+        }
+    }
+}
+
+impl<T: Real> PressureSolver<T> for DensitySolver<T> {
+    fn solve(&mut self, p: &Processor, fluid: &Fluid<T>, timestep: T) -> usize {
+        let num_particles = p.read_property::<Mass<T>>().len();
+        self.alpha.resize(num_particles, T::zero());
+        self.neighbors.resize(num_particles, 0);
+        self.source.resize(num_particles, T::zero());
+        self.kappa.resize(num_particles, T::zero());
+
+        let (grid, kernel, boundary, rest_density) = (fluid.grid, fluid.kernel, fluid.boundary, fluid.rest_density);
+        let (alpha, source, kappa) = (&mut self.alpha, &mut self.source, &mut self.kappa);
+        compute_alpha(p, alpha, &mut self.neighbors, kernel, grid, boundary, rest_density);
+        for pressure in p.write_property::<Pressure<T>>() {
+            *pressure = T::zero();
+        }
+
+        let threshold = self.threshold * rest_density;
+        let mut iterations = 0;
+        while iterations < self.max_iterations {
+            density_change(p, source, kernel, grid, boundary, rest_density, timestep);
+            {
+                let densities = p.read_property::<Density<T>>();
+                par_azip!(mut s (&mut source[..]), density (densities) in {
+                    *s = (density + *s - rest_density).max(T::zero());
+                });
+            }
+
+            let error = source.iter().fold(T::zero(), |sum, &s| sum + s) / T::new(num_particles.max(1));
+            if iterations >= 2 && error <= threshold {
+                break;
+            }
+
+            par_azip!(mut kappa (&mut kappa[..]), source (&source[..]), alpha (&alpha[..]) in {
+                *kappa = source * alpha / (timestep * timestep);
+            });
+            apply_pressure(p, kappa, kernel, grid, boundary, rest_density, timestep);
+            {
+                let (pressures, densities) = (p.write_property::<Pressure<T>>(), p.read_property::<Density<T>>());
+                par_azip!(mut pressure (pressures), kappa (&kappa[..]), density (densities) in { *pressure += kappa * density; });
+            }
+            iterations += 1;
+        }
+        iterations
+    }
+}
+
+/// Factor `α_i = ρ_i / (|Σ m_j ∇W_ij|^2 + Σ |m_j ∇W_ij|^2)` and number of neighbors.
+///
+/// Boundary particles only contribute to the first sum as they don't move.
+///
+/// Ref: [BK15] Eq. 11
+fn compute_alpha<T, K>(
+    p: &Processor,
+    alpha: &mut [T],
+    neighbors: &mut [usize],
+    kernel: &K,
+    grid: &BoundedGrid<T, U2>,
+    boundary: &BoundaryParticles<T>,
+    rest_density: T,
+)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (positions, densities, masses) = (
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    par_azip!(index i, mut alpha (alpha), mut neighbors (neighbors), pos (positions), density (densities) in {
+        *alpha = T::zero();
+        *neighbors = 0;
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        let mut sum = VectorN::<T, U2>::zero();
+        let mut sum_squared = T::zero();
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let grad = (pos - positions[j]) * (masses[j] * kernel.grad_w(pos.distance(positions[j])));
+            if grad.magnitude2() > T::zero() { *neighbors += 1; }
+            sum += grad;
+            sum_squared += grad.magnitude2();
+        });
+        boundary.for_each_neighbor(&pos, |b, volume| {
+            let grad = (pos - *b) * (rest_density * volume * kernel.grad_w(pos.distance(*b)));
+            if grad.magnitude2() > T::zero() { *neighbors += 1; }
+            sum += grad;
+        });
+
+        let denominator = sum.magnitude2() + sum_squared;
+        if denominator > T::new(1.0e-9) {
+            *alpha = density / denominator;
+        }
+    });
+}
+
+/// Density change `dt Σ m_j (v_i - v_j) ∇W_ij` due to the current velocities.
+///
+/// Boundary particles move with the velocity of their rigid body.
+pub fn density_change<T, K>(
+    p: &Processor,
+    change: &mut [T],
+    kernel: &K,
+    grid: &BoundedGrid<T, U2>,
+    boundary: &BoundaryParticles<T>,
+    rest_density: T,
+    timestep: T,
+)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (positions, velocities, masses) = (
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Velocity<T, U2>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    let (boundary_positions, boundary_velocities, volumes) = (boundary.positions(), boundary.velocities(), boundary.volumes());
+
+    par_azip!(index i, mut change (change), pos (positions), vel (velocities) in {
+        *change = T::zero();
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        let mut sum = T::zero();
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let grad = (pos - positions[j]) * kernel.grad_w(pos.distance(positions[j]));
+            sum += masses[j] * (vel - velocities[j]).dot(grad);
+        });
+        boundary.for_each_neighbor_index(&pos, |b| {
+            let grad = (pos - boundary_positions[b]) * kernel.grad_w(pos.distance(boundary_positions[b]));
+            sum += rest_density * volumes[b] * (vel - boundary_velocities[b]).dot(grad);
+        });
+        *change = timestep * sum;
+    });
+}
+
+/// Velocity update `v_i -= dt Σ m_j (κ_i / ρ_i + κ_j / ρ_j) ∇W_ij`.
+///
+/// Boundary particles `b` only contribute `dt Ψ_b κ_i / ρ_i ∇W_ib`.
+///
+/// Ref: [BK15] Eq. 9
+fn apply_pressure<T, K>(
+    p: &Processor,
+    kappa: &[T],
+    kernel: &K,
+    grid: &BoundedGrid<T, U2>,
+    boundary: &BoundaryParticles<T>,
+    rest_density: T,
+    timestep: T,
+)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (positions, velocities, densities, masses) = (
+        p.read_property::<Position<T, U2>>(),
+        p.write_property::<Velocity<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    par_azip!(index i, mut vel (velocities), pos (positions), density (densities) in {
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+        let kappa_i = kappa[i] / density;
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let grad = (pos - positions[j]) * kernel.grad_w(pos.distance(positions[j]));
+            *vel -= grad * (timestep * masses[j] * (kappa_i + kappa[j] / densities[j]));
+        });
+        boundary.for_each_neighbor(&pos, |b, volume| {
+            let grad = (pos - *b) * kernel.grad_w(pos.distance(*b));
+            *vel -= grad * (timestep * rest_density * volume * kappa_i);
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use math::vector_n::vec2;
+    use super::*;
+
+    #[test]
+    fn dfsph_compression() {
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+
+        // slightly compressed block moving towards its center
+        let c = 1.25 + 7.5 * 0.95 * spacing;
+        let center = vec2(c, c);
+        {
+            let mut positions = Vec::new();
+            let mut velocities = Vec::new();
+            for y in 0..16 {
+                for x in 0..16 {
+                    let pos = vec2(1.25 + x as f64 * 0.95 * spacing, 1.25 + y as f64 * 0.95 * spacing);
+                    positions.push(pos);
+                    velocities.push((center - pos) * 0.5);
+                }
+            }
+            let masses = vec![rest_density * spacing * spacing; positions.len()];
+            particles.add_particles(positions.len())
+                     .with::<Position<f64, U2>>(&positions)
+                     .with::<Velocity<f64, U2>>(&velocities)
+                     .with::<Mass<f64>>(&masses);
+        }
+
+        let mut solver = Dfsph::new(vec2(20, 20), 2.0 * spacing, rest_density);
+        solver.pipeline.gravity = vec2(0.0, 0.0);
+        solver.pipeline.viscosity = 0.0;
+        solver.step(&mut particles, 1.0e-3);
+
+        assert!(solver.density_iterations >= 2 && solver.density_iterations < solver.max_iterations);
+        assert!(solver.divergence_iterations < solver.max_iterations);
+
+        // pressure forces are symmetric, the momentum is preserved
+        let momentum = particles.read_property::<Velocity<f64, U2>>().iter()
+            .zip(particles.read_property::<Mass<f64>>().iter())
+            .fold(vec2(0.0, 0.0), |sum, (&v, &m)| sum + v * m);
+        assert!(momentum.magnitude() < 1.0e-8, "{:?}", momentum);
+    }
+}
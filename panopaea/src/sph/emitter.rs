@@ -0,0 +1,240 @@
+//! Particle sources and sinks
+//!
+//! Emitters inject particles inside a shape at a given rate, sinks remove
+//! particles entering a shape or crossing a plane. Emitted particles get
+//! position, velocity and mass, all other properties are default initialized.
+
+use math::{Dim, Real, VectorN};
+use particle::Particles;
+use math::rng::Pcg32;
+use rand::Rng;
+
+use super::property::{Mass, Position, Velocity};
+
+/// Maximum number of rejected candidates per poisson-disk sample.
+const MAX_ATTEMPTS: usize = 30;
+
+/// Region of space, disks are 2d spheres.
+#[derive(Clone, Debug)]
+pub enum Shape<T: Real, N: Dim<T>> {
+    Box { min: VectorN<T, N>, max: VectorN<T, N> },
+    Sphere { center: VectorN<T, N>, radius: T },
+}
+
+impl<T: Real, N: Dim<T>> Shape<T, N> {
+    pub fn contains(&self, position: &VectorN<T, N>) -> bool {
+        match *self {
+            Shape::Box { ref min, ref max } => {
+                (0..N::to_usize()).all(|d| min[d] <= position[d] && position[d] <= max[d])
+            }
+            Shape::Sphere { ref center, radius } => {
+                distance2(center, position) <= radius * radius
+            }
+        }
+    }
+
+    /// Axis aligned bounding box.
+    pub fn bounds(&self) -> (VectorN<T, N>, VectorN<T, N>) {
+        match *self {
+            Shape::Box { ref min, ref max } => (min.clone(), max.clone()),
+            Shape::Sphere { ref center, radius } => {
+                let mut min = center.clone();
+                let mut max = center.clone();
+                for d in 0..N::to_usize() {
+                    min[d] -= radius;
+                    max[d] += radius;
+                }
+                (min, max)
+            }
+        }
+    }
+}
+
+fn distance2<T: Real, N: Dim<T>>(a: &VectorN<T, N>, b: &VectorN<T, N>) -> T {
+    (0..N::to_usize()).fold(T::zero(), |dist, d| dist + (a[d] - b[d]).powi(2))
+}
+
+/// Distribution of the emitted particles.
+#[derive(Copy, Clone, Debug)]
+pub enum Sampling<T> {
+    /// Random cells of a lattice with the emitter spacing, offset by `jitter * spacing`.
+    Jittered { jitter: T },
+    /// Dart throwing, particles of a batch are at least one spacing apart.
+    PoissonDisk,
+}
+
+pub struct Emitter<T: Real, N: Dim<T>> {
+    pub shape: Shape<T, N>,
+    pub sampling: Sampling<T>,
+    /// Emitted particles per second.
+    pub rate: T,
+    pub spacing: T,
+    pub mass: T,
+    pub velocity: VectorN<T, N>,
+
+    /// Fractional particles carried over to the next step.
+    accumulator: T,
+    /// Sampling jitter, part of the solver state for reproducible emission.
+    pub rng: Pcg32,
+}
+
+impl<T: Real, N: Dim<T>> Emitter<T, N> {
+    pub fn new(shape: Shape<T, N>, rate: T, spacing: T, mass: T) -> Self {
+        Emitter {
+            shape,
+            sampling: Sampling::Jittered { jitter: T::new(0.25) },
+            rate,
+            spacing,
+            mass,
+            velocity: VectorN::from_elem(T::zero()),
+
+            accumulator: T::zero(),
+            rng: Pcg32::default(),
+        }
+    }
+
+    /// Emit the particles of one timestep, returns the number of new particles.
+    ///
+    /// Requires the position, velocity and mass properties.
+    pub fn emit(&mut self, particles: &mut Particles, timestep: T) -> usize {
+        self.accumulator += self.rate * timestep;
+        let count = self.accumulator.floor();
+        self.accumulator -= count;
+
+        let positions = self.sample(count.to_usize().unwrap());
+        let num_emitted = positions.len();
+        if num_emitted > 0 {
+            particles.add_particles(num_emitted)
+                     .with::<Position<T, N>>(&positions)
+                     .with::<Velocity<T, N>>(&vec![self.velocity.clone(); num_emitted])
+                     .with::<Mass<T>>(&vec![self.mass; num_emitted]);
+        }
+        num_emitted
+    }
+
+    fn sample(&mut self, count: usize) -> Vec<VectorN<T, N>> {
+        let mut positions: Vec<VectorN<T, N>> = Vec::with_capacity(count);
+        let mut rejected = 0;
+        while positions.len() < count && rejected < MAX_ATTEMPTS * count {
+            let candidate = self.candidate();
+            let accepted = self.shape.contains(&candidate) && match self.sampling {
+                Sampling::Jittered { .. } => true,
+                Sampling::PoissonDisk => {
+                    let min_dist2 = self.spacing * self.spacing;
+                    positions.iter().all(|pos| distance2(pos, &candidate) >= min_dist2)
+                }
+            };
+
+            if accepted {
+                positions.push(candidate);
+            } else {
+                rejected += 1;
+            }
+        }
+        positions
+    }
+
+    /// Random position inside the bounding box of the shape.
+    fn candidate(&mut self) -> VectorN<T, N> {
+        let (min, max) = self.shape.bounds();
+        let sampling = self.sampling;
+        let mut pos = VectorN::from_elem(T::zero());
+        for d in 0..N::to_usize() {
+            let u = self.rng.gen::<T>();
+            pos[d] = match sampling {
+                Sampling::Jittered { jitter } => {
+                    let cells = ((max[d] - min[d]) / self.spacing).floor().max(T::one());
+                    let cell = (u * cells).floor();
+                    let offset = T::new(0.5) + jitter * (self.rng.gen::<T>() - T::new(0.5));
+                    min[d] + (cell + offset) * self.spacing
+                }
+                Sampling::PoissonDisk => min[d] + u * (max[d] - min[d]),
+            };
+        }
+        pos
+    }
+}
+
+/// Removes particles inside a shape.
+pub struct Sink<T: Real, N: Dim<T>> {
+    pub shape: Shape<T, N>,
+}
+
+impl<T: Real, N: Dim<T>> Sink<T, N> {
+    /// Returns the number of removed particles.
+    pub fn apply(&self, particles: &mut Particles) -> usize {
+        remove_particles(particles, |pos: &VectorN<T, N>| self.shape.contains(pos))
+    }
+}
+
+/// Removes particles in front of a plane.
+pub struct KillPlane<T: Real, N: Dim<T>> {
+    pub point: VectorN<T, N>,
+    pub normal: VectorN<T, N>,
+}
+
+impl<T: Real, N: Dim<T>> KillPlane<T, N> {
+    /// Returns the number of removed particles.
+    pub fn apply(&self, particles: &mut Particles) -> usize {
+        remove_particles(particles, |pos: &VectorN<T, N>| {
+            let dist = (0..N::to_usize()).fold(T::zero(), |dist, d| dist + (pos[d] - self.point[d]) * self.normal[d]);
+            dist > T::zero()
+        })
+    }
+}
+
+fn remove_particles<T, N, F>(particles: &mut Particles, remove: F) -> usize
+    where T: Real,
+          N: Dim<T>,
+          F: Fn(&VectorN<T, N>) -> bool,
+{
+    let keep = particles.read_property::<Position<T, N>>().iter().map(|pos| !remove(pos)).collect::<Vec<_>>();
+    let num_removed = keep.iter().filter(|&&k| !k).count();
+    if num_removed > 0 {
+        particles.compact(&keep);
+    }
+    num_removed
+}
+
+#[cfg(test)]
+mod tests {
+    use math::vector_n::vec2;
+    use sph::wcsph;
+    use typenum::U2;
+    use super::*;
+
+    #[test]
+    fn emitter_and_sinks() {
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+
+        let disk = Shape::Sphere { center: vec2(0.0, 0.0), radius: 1.0 };
+        let mut emitter = Emitter::new(disk, 250.0, 0.05, 1.0);
+        emitter.sampling = Sampling::PoissonDisk;
+        emitter.velocity = vec2(1.0, 0.0);
+
+        // fractional particles accumulate over steps
+        for _ in 0..10 {
+            emitter.emit(&mut particles, 0.0125);
+        }
+        assert_eq!(particles.num_particles(), 31);
+        {
+            let positions = particles.read_property::<Position<f64, U2>>();
+            for (i, a) in positions.iter().enumerate() {
+                assert!(a[0] * a[0] + a[1] * a[1] <= 1.0);
+                for b in &positions[i+1..] {
+                    assert!(distance2(a, b) >= 0.05 * 0.05);
+                }
+            }
+        }
+
+        let plane = KillPlane { point: vec2(0.5, 0.0), normal: vec2(1.0, 0.0) };
+        let removed = plane.apply(&mut particles);
+        assert_eq!(particles.num_particles(), 31 - removed);
+        assert!(particles.read_property::<Position<f64, U2>>().iter().all(|pos| pos[0] <= 0.5));
+
+        let sink = Sink { shape: Shape::Box { min: vec2(-2.0, -2.0), max: vec2(2.0, 2.0) } };
+        sink.apply(&mut particles);
+        assert_eq!(particles.num_particles(), 0);
+    }
+}
@@ -1,72 +1,174 @@
 
 //! Bounded Unfiform Grid
 
-use generic_array::typenum::U2;
+use cgmath::MetricSpace;
+use error::{Error, Result};
+use generic_array::typenum::{U2, U3, Unsigned};
 use math::{Dim, Real, VectorN};
+use rayon::prelude::*;
 use std::usize;
 use std::cmp;
+use super::neighbor::NeighborSearch;
 
-pub struct BoundedGrid<S: Real, N: Dim<usize> + Dim<(usize, usize)>> {
+/// Mapping of cell coordinates to keys, which define the memory order of the cells.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CellOrder {
+    /// Row-major, `x` varies fastest.
+    RowMajor,
+    /// Z-order curve, neighboring cells are mostly close in memory.
+    ///
+    /// Improves cache locality of neighbor loops for large particle counts.
+    /// The number of keys is rounded up to the next power of two per axis.
+    Morton,
+}
+
+pub struct BoundedGrid<S: Real, N: Dim<S> + Dim<usize> + Dim<(usize, usize)>> {
     num_cells: VectorN<usize, N>,
     cell_size: S,
+    order: CellOrder,
 
     cell_ranges: Vec<(usize, usize)>,
+
+    /// Positions of the last `NeighborSearch::build`.
+    positions: Vec<VectorN<S, N>>,
+    /// Index into `positions` of each particle in cell order.
+    particles: Vec<usize>,
 }
 
-impl<S> BoundedGrid<S, U2>
-    where S: Real
+impl<S, N> BoundedGrid<S, N>
+    where S: Real,
+          N: Dim<S> + Dim<usize> + Dim<(usize, usize)>,
 {
-    pub fn new(num_cells: VectorN<usize, U2>, cell_size: S) -> Self {
-        let ranges = vec![(0, 0); num_cells[0] * num_cells[1]];
+    pub fn new(num_cells: VectorN<usize, N>, cell_size: S) -> Self {
+        let total: usize = num_cells.iter().product();
         BoundedGrid {
             num_cells: num_cells,
             cell_size: cell_size,
-            cell_ranges: ranges,
+            order: CellOrder::RowMajor,
+            cell_ranges: vec![(0, 0); total],
+            positions: Vec::new(),
+            particles: Vec::new(),
         }
     }
 
-    pub fn get_key(&self, position: &VectorN<S, U2>) -> usize {
-        if let Some((x, y)) = self.get_cell(position) {
-            x + y * self.num_cells[0]
+    /// Grid with the given cell order, row-major by default.
+    pub fn with_order(mut self, order: CellOrder) -> Self {
+        self.order = order;
+        let num_keys: usize = match order {
+            CellOrder::RowMajor => self.num_cells.iter().product(),
+            CellOrder::Morton => 1 << (Self::morton_bits(&self.num_cells) * self.num_cells.len()),
+        };
+        self.cell_ranges = vec![(0, 0); num_keys];
+        self
+    }
+
+    pub fn order(&self) -> CellOrder {
+        self.order
+    }
+
+    pub fn num_cells(&self) -> &VectorN<usize, N> {
+        &self.num_cells
+    }
+
+    pub fn cell_size(&self) -> S {
+        self.cell_size
+    }
+
+    /// Number of cell keys, keys of positions inside the grid are smaller.
+    pub fn num_keys(&self) -> usize {
+        self.cell_ranges.len()
+    }
+
+    /// Cell key according to the cell order, `usize::MAX` for positions outside of the grid.
+    pub fn get_key(&self, position: &VectorN<S, N>) -> usize {
+        if let Some(coords) = self.get_coords(position) {
+            self.coords_key(&coords)
         } else {
             usize::MAX
         }
     }
 
-    pub fn get_cell(&self, position: &VectorN<S, U2>) -> Option<(usize, usize)> {
-        let x: i64 = (position[0] / self.cell_size).floor().to_i64().unwrap();
-        let y: i64 = (position[1] / self.cell_size).floor().to_i64().unwrap();
+    /// Integer cell coordinates of a position.
+    pub fn get_coords(&self, position: &VectorN<S, N>) -> Option<VectorN<usize, N>> {
+        let mut coords = VectorN::from_elem(0);
+        for d in 0..<N as Unsigned>::to_usize() {
+            let c: i64 = (position[d] / self.cell_size).floor().to_i64().unwrap();
+            if c < 0 || c >= self.num_cells[d] as i64 {
+                return None;
+            }
+            coords[d] = c as usize;
+        }
+        Some(coords)
+    }
 
-        if (0 <= x && x < self.num_cells[0] as i64) &&
-           (0 <= y && y < self.num_cells[1] as i64) {
-            Some((x as usize, y as usize))
-        } else {
-            None
+    fn coords_key(&self, coords: &[usize]) -> usize {
+        match self.order {
+            CellOrder::RowMajor => {
+                coords.iter().zip(self.num_cells.iter()).rev().fold(0, |key, (&c, &n)| key * n + c)
+            }
+            CellOrder::Morton => {
+                let dims = coords.len();
+                let mut key = 0;
+                for bit in 0..Self::morton_bits(&self.num_cells) {
+                    for (d, &c) in coords.iter().enumerate() {
+                        key |= ((c >> bit) & 1) << (bit * dims + d);
+                    }
+                }
+                key
+            }
         }
     }
 
+    /// Number of bits required per axis for the morton code.
+    fn morton_bits(num_cells: &[usize]) -> usize {
+        let max = num_cells.iter().cloned().max().unwrap_or(1);
+        (0..).find(|&bits| 1 << bits >= max).unwrap()
+    }
+
+    /// Permutation sorting the positions by cell key, positions outside of the grid are moved to the end.
+    ///
+    /// Reordering the particles accordingly is required before calling `construct_ranges`.
+    pub fn sort_order(&self, positions: &[VectorN<S, N>]) -> Vec<usize> {
+        let mut order = (0..positions.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| self.get_key(&positions[i]));
+        order
+    }
+
     /// Reconstruct cell ranges from _sorted_ particle position.
     ///
+    /// All cells are empty for an empty position slice. Fails if the positions
+    /// aren't sorted by cell key, all cells are empty afterwards.
+    ///
     /// Ref: "Particle Simulation using CUDA", Green, Simon, 2013
-    pub fn construct_ranges(&mut self, positions: &[VectorN<S, U2>]) {
+    pub fn construct_ranges(&mut self, positions: &[VectorN<S, N>]) -> Result<()> {
         // reset ranges
         for cell in &mut self.cell_ranges {
             *cell = (0, 0);
         }
 
-        let mut prev = self.get_key(&positions[0]);
+        let mut prev = match positions.first() {
+            Some(position) => self.get_key(position),
+            None => return Ok(()),
+        };
 
         {
-            if prev >= self.cell_ranges.len() { return; }
+            if prev >= self.cell_ranges.len() { return Ok(()); }
             self.cell_ranges[prev].0 = 0;
         }
 
         for particle in 1..positions.len() {
             let index = self.get_key(&positions[particle]);
 
+            if index < prev {
+                for cell in &mut self.cell_ranges {
+                    *cell = (0, 0);
+                }
+                return Err(Error::UnsortedParticles);
+            }
+
             if index >= self.cell_ranges.len() {
                 self.cell_ranges[prev].1 = particle;
-                return;
+                return Ok(());
             }
 
             if prev != index {
@@ -79,6 +181,57 @@ impl<S> BoundedGrid<S, U2>
         }
 
         self.cell_ranges[prev].1 = positions.len();
+        Ok(())
+    }
+
+    /// Parallel version of `construct_ranges`.
+    ///
+    /// All cells are empty for an empty position slice. Fails if the positions
+    /// aren't sorted by cell key, all cells are empty afterwards.
+    pub fn par_construct_ranges(&mut self, positions: &[VectorN<S, N>]) -> Result<()> {
+        for cell in &mut self.cell_ranges {
+            *cell = (0, 0);
+        }
+
+        let keys = positions.par_iter().map(|pos| self.get_key(pos)).collect::<Vec<_>>();
+        if keys.par_windows(2).any(|pair| pair[1] < pair[0]) {
+            return Err(Error::UnsortedParticles);
+        }
+        let starts = (0..keys.len()).into_par_iter()
+            .filter(|&i| i == 0 || keys[i] != keys[i-1])
+            .collect::<Vec<_>>();
+
+        for (n, &start) in starts.iter().enumerate() {
+            let key = keys[start];
+            if key >= self.cell_ranges.len() {
+                // particles outside of the grid are at the end
+                break;
+            }
+            let end = starts.get(n+1).cloned().unwrap_or(keys.len());
+            self.cell_ranges[key] = (start, end);
+        }
+        Ok(())
+    }
+
+    /// Sort a copy of the positions by cell for the `NeighborSearch` queries.
+    fn build_search(&mut self, positions: &[VectorN<S, N>]) {
+        self.particles = self.sort_order(positions);
+        let sorted = self.particles.iter().map(|&i| positions[i].clone()).collect::<Vec<_>>();
+        self.par_construct_ranges(&sorted).expect("positions are sorted by `sort_order`");
+        self.positions = positions.to_vec();
+    }
+
+    /// Number of neighboring cells to visit for a search radius.
+    fn search_bound(&self, radius: S) -> usize {
+        (radius / self.cell_size).ceil().to_usize().unwrap_or(0)
+    }
+}
+
+impl<S> BoundedGrid<S, U2>
+    where S: Real
+{
+    pub fn get_cell(&self, position: &VectorN<S, U2>) -> Option<(usize, usize)> {
+        self.get_coords(position).map(|c| (c[0], c[1]))
     }
 
     pub fn get_range(&self, cell: (usize, usize)) -> Option<(usize, usize)> {
@@ -94,7 +247,7 @@ impl<S> BoundedGrid<S, U2>
 
     pub unsafe fn get_range_unchecked(&self, cell: (usize, usize)) -> (usize, usize) {
         debug_assert!(cell.0 < self.num_cells[0] && cell.1 < self.num_cells[1]);
-        let index = cell.0 + cell.1 * self.num_cells[0];
+        let index = self.coords_key(&[cell.0, cell.1]);
         self.cell_ranges[index]
     }
 
@@ -115,4 +268,305 @@ impl<S> BoundedGrid<S, U2>
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Build the neighbor lists of all particles in parallel.
+    ///
+    /// Particles outside of the grid have no neighbors.
+    pub fn neighbor_list(&self, positions: &[VectorN<S, U2>], radius: S) -> NeighborList {
+        NeighborList::build(positions.len(), |i, neighbors| {
+            let pos = &positions[i];
+            if let Some(cell) = self.get_cell(pos) {
+                self.for_each_neighbor(cell, 1, |j| {
+                    if pos.distance(positions[j]) < radius {
+                        neighbors.push(j);
+                    }
+                });
+            }
+        })
+    }
+}
+
+impl<S> BoundedGrid<S, U3>
+    where S: Real
+{
+    pub fn get_cell(&self, position: &VectorN<S, U3>) -> Option<(usize, usize, usize)> {
+        self.get_coords(position).map(|c| (c[0], c[1], c[2]))
+    }
+
+    pub fn get_range(&self, cell: (usize, usize, usize)) -> Option<(usize, usize)> {
+        if cell.0 < self.num_cells[0] && cell.1 < self.num_cells[1] && cell.2 < self.num_cells[2] {
+            Some(unsafe { self.get_range_unchecked(cell) })
+        } else {
+            None
+        }
+    }
+
+    pub unsafe fn get_range_unchecked(&self, cell: (usize, usize, usize)) -> (usize, usize) {
+        debug_assert!(cell.0 < self.num_cells[0] && cell.1 < self.num_cells[1] && cell.2 < self.num_cells[2]);
+        let index = self.coords_key(&[cell.0, cell.1, cell.2]);
+        self.cell_ranges[index]
+    }
+
+    /// Apply function to each neighboring (including itself) cell in the grid.
+    pub fn for_each_neighbor<F>(&self, cell: (usize, usize, usize), bound: usize, mut fnc: F)
+        where F: FnMut(usize)
+    {
+        let upper_x = cmp::min(cell.0 + bound+1, self.num_cells[0]);
+        let upper_y = cmp::min(cell.1 + bound+1, self.num_cells[1]);
+        let upper_z = cmp::min(cell.2 + bound+1, self.num_cells[2]);
+
+        for z in cell.2.saturating_sub(bound)..upper_z {
+            for y in cell.1.saturating_sub(bound)..upper_y {
+                for x in cell.0.saturating_sub(bound)..upper_x {
+                    let (start, end) = unsafe { self.get_range_unchecked((x, y, z)) };
+                    assert!(start <= end);
+                    for p in start..end {
+                        fnc(p);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Build the neighbor lists of all particles in parallel.
+    ///
+    /// Particles outside of the grid have no neighbors.
+    pub fn neighbor_list(&self, positions: &[VectorN<S, U3>], radius: S) -> NeighborList {
+        NeighborList::build(positions.len(), |i, neighbors| {
+            let pos = &positions[i];
+            if let Some(cell) = self.get_cell(pos) {
+                self.for_each_neighbor(cell, 1, |j| {
+                    if pos.distance(positions[j]) < radius {
+                        neighbors.push(j);
+                    }
+                });
+            }
+        })
+    }
+}
+
+impl<S: Real> NeighborSearch<S, U2> for BoundedGrid<S, U2> {
+    fn build(&mut self, positions: &[VectorN<S, U2>]) {
+        self.build_search(positions);
+    }
+
+    fn num_particles(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn position(&self, particle: usize) -> &VectorN<S, U2> {
+        &self.positions[particle]
+    }
+
+    /// Particles outside of the grid aren't found.
+    fn for_each_within<F>(&self, position: &VectorN<S, U2>, radius: S, mut fnc: F)
+        where F: FnMut(usize)
+    {
+        if let Some(cell) = self.get_cell(position) {
+            BoundedGrid::<S, U2>::for_each_neighbor(self, cell, self.search_bound(radius), |p| {
+                let j = self.particles[p];
+                if position.distance(self.positions[j]) < radius {
+                    fnc(j);
+                }
+            });
+        }
+    }
+}
+
+impl<S: Real> NeighborSearch<S, U3> for BoundedGrid<S, U3> {
+    fn build(&mut self, positions: &[VectorN<S, U3>]) {
+        self.build_search(positions);
+    }
+
+    fn num_particles(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn position(&self, particle: usize) -> &VectorN<S, U3> {
+        &self.positions[particle]
+    }
+
+    /// Particles outside of the grid aren't found.
+    fn for_each_within<F>(&self, position: &VectorN<S, U3>, radius: S, mut fnc: F)
+        where F: FnMut(usize)
+    {
+        if let Some(cell) = self.get_cell(position) {
+            BoundedGrid::<S, U3>::for_each_neighbor(self, cell, self.search_bound(radius), |p| {
+                let j = self.particles[p];
+                if position.distance(self.positions[j]) < radius {
+                    fnc(j);
+                }
+            });
+        }
+    }
+}
+
+/// Compressed per-particle neighbor lists.
+///
+/// Allows parallel loops over the neighbors of each particle without going through the grid.
+pub struct NeighborList {
+    ranges: Vec<(usize, usize)>,
+    indices: Vec<usize>,
+}
+
+impl NeighborList {
+    /// Collect the neighbors of each particle in parallel, `fnc` pushes the neighbors of particle `i`.
+    pub fn build<F>(num_particles: usize, fnc: F) -> Self
+        where F: Fn(usize, &mut Vec<usize>) + Sync
+    {
+        let lists = (0..num_particles).into_par_iter().map(|i| {
+            let mut neighbors = Vec::new();
+            fnc(i, &mut neighbors);
+            neighbors
+        }).collect::<Vec<_>>();
+
+        let mut ranges = Vec::with_capacity(num_particles);
+        let mut indices = Vec::with_capacity(lists.iter().map(|l| l.len()).sum());
+        for list in lists {
+            let start = indices.len();
+            indices.extend(list);
+            ranges.push((start, indices.len()));
+        }
+
+        NeighborList { ranges, indices }
+    }
+
+    /// Number of particles.
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Neighbor indices of a particle, including the particle itself.
+    pub fn neighbors(&self, particle: usize) -> &[usize] {
+        let (start, end) = self.ranges[particle];
+        &self.indices[start..end]
+    }
+
+    /// Index range of the neighbors of a particle.
+    pub fn range(&self, particle: usize) -> (usize, usize) {
+        self.ranges[particle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_grid_3d() {
+        let cell_size = 0.5;
+        let mut grid = BoundedGrid::<f64, U3>::new(VectorN::from_elem(4), cell_size);
+
+        let mut positions = Vec::new();
+        for z in 0..8 {
+            for y in 0..8 {
+                for x in 0..8 {
+                    let mut pos = VectorN::<f64, U3>::from_elem(0.0);
+                    pos[0] = 0.125 + 0.25 * x as f64;
+                    pos[1] = 0.125 + 0.25 * y as f64;
+                    pos[2] = 0.125 + 0.25 * z as f64;
+                    positions.push(pos);
+                }
+            }
+        }
+        positions.push(VectorN::from_elem(-1.0));
+        positions.sort_by_key(|pos| grid.get_key(pos));
+        grid.construct_ranges(&positions).unwrap();
+
+        assert_eq!(grid.get_cell(&positions[0]), Some((0, 0, 0)));
+        assert_eq!(grid.get_cell(&positions[512]), None);
+
+        let mut count = 0;
+        grid.for_each_neighbor((1, 2, 3), 1, |_| count += 1);
+        assert_eq!(count, 3 * 3 * 2 * 8);
+    }
+    #[test]
+    fn bounded_grid_morton() {
+        let mut grid = BoundedGrid::<f64, U2>::new(VectorN::from_elem(3), 1.0).with_order(CellOrder::Morton);
+        {
+            let key = |x: f64, y: f64| {
+                let mut pos = VectorN::<f64, U2>::from_elem(0.0);
+                pos[0] = x;
+                pos[1] = y;
+                grid.get_key(&pos)
+            };
+            assert_eq!((key(0.5, 0.5), key(1.5, 0.5), key(0.5, 1.5), key(1.5, 1.5)), (0, 1, 2, 3));
+            assert_eq!((key(2.5, 0.5), key(0.5, 2.5), key(2.5, 2.5)), (4, 8, 12));
+        }
+
+        let mut positions = (0..36).map(|i| {
+            let mut pos = VectorN::<f64, U2>::from_elem(0.0);
+            pos[0] = 0.25 + 0.5 * (i % 6) as f64;
+            pos[1] = 0.25 + 0.5 * (i / 6) as f64;
+            pos
+        }).collect::<Vec<_>>();
+        let order = grid.sort_order(&positions);
+        positions = order.iter().map(|&i| positions[i]).collect();
+
+        grid.construct_ranges(&positions).unwrap();
+        let mut count = 0;
+        grid.for_each_neighbor((1, 1), 1, |p| {
+            assert_eq!(grid.get_cell(&positions[p]).map(|(x, y)| x.max(y) <= 2), Some(true));
+            count += 1;
+        });
+        assert_eq!(count, 36);
+    }
+
+    #[test]
+    fn parallel_neighbor_list() {
+        let radius = 0.5;
+        let mut grid = BoundedGrid::<f64, U2>::new(VectorN::from_elem(8), radius);
+        let mut positions = (0..300).map(|i| {
+            let t = i as f64;
+            let mut pos = VectorN::<f64, U2>::from_elem(0.0);
+            pos[0] = 2.0 + 1.9 * (t * 0.71).sin();
+            pos[1] = 2.0 + 1.9 * (t * 0.23).cos();
+            pos
+        }).collect::<Vec<_>>();
+        let order = grid.sort_order(&positions);
+        positions = order.iter().map(|&i| positions[i]).collect();
+
+        grid.construct_ranges(&positions).unwrap();
+        let ranges = grid.cell_ranges.clone();
+        grid.par_construct_ranges(&positions).unwrap();
+        assert_eq!(grid.cell_ranges, ranges);
+
+        // empty particle sets leave all cells empty
+        grid.construct_ranges(&[]).unwrap();
+        assert!(grid.cell_ranges.iter().all(|&range| range == (0, 0)));
+        grid.construct_ranges(&positions).unwrap();
+        grid.par_construct_ranges(&[]).unwrap();
+        assert!(grid.cell_ranges.iter().all(|&range| range == (0, 0)));
+
+        // unsorted particles fail and reset all cells
+        let reversed = positions.iter().rev().cloned().collect::<Vec<_>>();
+        grid.construct_ranges(&positions).unwrap();
+        match grid.construct_ranges(&reversed) {
+            Err(Error::UnsortedParticles) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(grid.cell_ranges.iter().all(|&range| range == (0, 0)));
+        grid.construct_ranges(&positions).unwrap();
+        match grid.par_construct_ranges(&reversed) {
+            Err(Error::UnsortedParticles) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(grid.cell_ranges.iter().all(|&range| range == (0, 0)));
+        grid.par_construct_ranges(&positions).unwrap();
+
+        let neighbors = grid.neighbor_list(&positions, radius);
+        assert_eq!(neighbors.len(), positions.len());
+        for i in 0..positions.len() {
+            let expected = (0..positions.len())
+                .filter(|&j| positions[i].distance(positions[j]) < radius)
+                .collect::<Vec<_>>();
+            let mut found = neighbors.neighbors(i).to_vec();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Unbounded uniform grid with compact hashing
+//!
+//! Only occupied cells are stored, the hash table maps cell coordinates to
+//! the compact list of occupied cells via open addressing (linear probing).
+//! Particles need to be sorted by cell, which keeps the particles of a cell
+//! contiguous in memory.
+//!
+//! References:
+//!     [IAB11] Markus Ihmsen, Nadir Akinci, Markus Becker, and Matthias Teschner, 2011,
+//!             A parallel SPH implementation on multi-core CPUs,
+//!             Computer Graphics Forum 30, 1, 99-112
+//!     [THM03] Matthias Teschner, Bruno Heidelberger, Matthias Müller, Danat Pomerantes, and Markus H. Gross, 2003,
+//!             Optimized spatial hashing for collision detection of deformable objects,
+//!             In Proceedings of Vision, Modeling, Visualization (VMV '03), 47-54
+
+use cgmath::MetricSpace;
+use generic_array::typenum::Unsigned;
+use math::{Dim, Real, VectorN};
+use std::cmp::Ordering;
+use std::usize;
+use super::neighbor::NeighborSearch;
+
+/// Primes of the spatial hash function, [THM03].
+const PRIMES: [i64; 3] = [73856093, 19349663, 83492791];
+const EMPTY: usize = usize::MAX;
+
+pub struct HashedGrid<S: Real, N: Dim<S> + Dim<i64>> {
+    cell_size: S,
+
+    /// Hash table of indices into `cells`.
+    table: Vec<usize>,
+    /// Occupied cells with their particle ranges.
+    cells: Vec<(VectorN<i64, N>, (usize, usize))>,
+
+    /// Positions of the last `NeighborSearch::build`.
+    positions: Vec<VectorN<S, N>>,
+    /// Index into `positions` of each particle in cell order.
+    particles: Vec<usize>,
+}
+
+impl<S, N> HashedGrid<S, N>
+    where S: Real,
+          N: Dim<S> + Dim<i64>,
+{
+    pub fn new(cell_size: S) -> Self {
+        assert!(<N as Unsigned>::to_usize() <= PRIMES.len());
+        HashedGrid {
+            cell_size: cell_size,
+            table: Vec::new(),
+            cells: Vec::new(),
+            positions: Vec::new(),
+            particles: Vec::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> S {
+        self.cell_size
+    }
+
+    /// Number of occupied cells.
+    pub fn num_cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Integer cell coordinates of a position.
+    pub fn get_coords(&self, position: &VectorN<S, N>) -> VectorN<i64, N> {
+        let mut coords = VectorN::from_elem(0);
+        for d in 0..<N as Unsigned>::to_usize() {
+            coords[d] = (position[d] / self.cell_size).floor().to_i64().unwrap();
+        }
+        coords
+    }
+
+    fn hash(&self, coords: &[i64]) -> usize {
+        let hash = coords.iter().zip(PRIMES.iter()).fold(0i64, |hash, (&c, &p)| hash ^ c.wrapping_mul(p));
+        (hash as u64 as usize) & (self.table.len() - 1)
+    }
+
+    /// Cells ordered row-major, `x` varies fastest.
+    fn compare(a: &[i64], b: &[i64]) -> Ordering {
+        a.iter().rev().cmp(b.iter().rev())
+    }
+
+    /// Permutation sorting the positions by cell.
+    ///
+    /// Reordering the particles accordingly is required before calling `construct_ranges`.
+    pub fn sort_order(&self, positions: &[VectorN<S, N>]) -> Vec<usize> {
+        let coords = positions.iter().map(|pos| self.get_coords(pos)).collect::<Vec<_>>();
+        let mut order = (0..positions.len()).collect::<Vec<_>>();
+        order.sort_by(|&i, &j| Self::compare(&coords[i], &coords[j]));
+        order
+    }
+
+    /// Rebuild the occupied cells and the hash table from _sorted_ particle positions.
+    pub fn construct_ranges(&mut self, positions: &[VectorN<S, N>]) {
+        self.cells.clear();
+        for (particle, pos) in positions.iter().enumerate() {
+            let coords = self.get_coords(pos);
+            let new_cell = match self.cells.last() {
+                Some(&(ref last, _)) => {
+                    debug_assert!(Self::compare(last, &coords) != Ordering::Greater, "positions aren't sorted");
+                    Self::compare(last, &coords) != Ordering::Equal
+                }
+                None => true,
+            };
+
+            if new_cell {
+                self.cells.push((coords, (particle, particle + 1)));
+            } else {
+                self.cells.last_mut().unwrap().1 .1 = particle + 1;
+            }
+        }
+
+        // load factor of at most 0.5
+        let size = (2 * self.cells.len()).next_power_of_two();
+        self.table.clear();
+        self.table.resize(size, EMPTY);
+        for cell in 0..self.cells.len() {
+            let mut slot = self.hash(&self.cells[cell].0);
+            while self.table[slot] != EMPTY {
+                slot = (slot + 1) & (size - 1);
+            }
+            self.table[slot] = cell;
+        }
+    }
+
+    /// Particle range of a cell, `None` if the cell is empty.
+    pub fn get_range(&self, coords: &VectorN<i64, N>) -> Option<(usize, usize)> {
+        if self.cells.is_empty() {
+            return None;
+        }
+
+        let mask = self.table.len() - 1;
+        let mut slot = self.hash(coords);
+        loop {
+            let cell = self.table[slot];
+            if cell == EMPTY {
+                return None;
+            }
+            if self.cells[cell].0[..] == coords[..] {
+                return Some(self.cells[cell].1);
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    /// Apply function to each particle in the neighboring cells (including its own) of a position.
+    pub fn for_each_neighbor<F>(&self, position: &VectorN<S, N>, bound: usize, mut fnc: F)
+        where F: FnMut(usize)
+    {
+        let dims = <N as Unsigned>::to_usize();
+        let bound = bound as i64;
+        let center = self.get_coords(position);
+        let mut offset = VectorN::<i64, N>::from_elem(-bound);
+
+        'cells: loop {
+            let mut coords = center.clone();
+            for d in 0..dims {
+                coords[d] += offset[d];
+            }
+            if let Some((start, end)) = self.get_range(&coords) {
+                for p in start..end {
+                    fnc(p);
+                }
+            }
+
+            // next offset
+            for d in 0..dims {
+                if offset[d] < bound {
+                    offset[d] += 1;
+                    continue 'cells;
+                }
+                offset[d] = -bound;
+            }
+            break;
+        }
+    }
+}
+
+
+impl<S, N> NeighborSearch<S, N> for HashedGrid<S, N>
+    where S: Real,
+          N: Dim<S> + Dim<i64>,
+{
+    fn build(&mut self, positions: &[VectorN<S, N>]) {
+        self.particles = self.sort_order(positions);
+        let sorted = self.particles.iter().map(|&i| positions[i].clone()).collect::<Vec<_>>();
+        self.construct_ranges(&sorted);
+        self.positions = positions.to_vec();
+    }
+
+    fn num_particles(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn position(&self, particle: usize) -> &VectorN<S, N> {
+        &self.positions[particle]
+    }
+
+    fn for_each_within<F>(&self, position: &VectorN<S, N>, radius: S, mut fnc: F)
+        where F: FnMut(usize)
+    {
+        let bound = (radius / self.cell_size).ceil().to_usize().unwrap_or(0);
+        HashedGrid::for_each_neighbor(self, position, bound, |p| {
+            let j = self.particles[p];
+            if self.positions[j].clone().distance2(position.clone()) < radius * radius {
+                fnc(j);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::MetricSpace;
+    use generic_array::typenum::U2;
+    use math::vector_n::vec2;
+    use super::*;
+
+    #[test]
+    fn hashed_grid_neighbors() {
+        let radius = 0.3;
+        let mut positions = (0..400).map(|i| {
+            let t = i as f64;
+            vec2(40.0 * (t * 0.37).sin() + 0.5 * (t * 1.3).cos(), -25.0 * (t * 0.11).cos() + (t * 2.1).sin())
+        }).collect::<Vec<_>>();
+        positions.extend((0..100).map(|i| vec2(-1.0e4 + 0.05 * i as f64, 3.0e3)));
+
+        let mut grid = HashedGrid::<f64, U2>::new(radius);
+        let order = grid.sort_order(&positions);
+        positions = order.iter().map(|&i| positions[i]).collect();
+        grid.construct_ranges(&positions);
+
+        for i in 0..positions.len() {
+            let mut found = Vec::new();
+            grid.for_each_neighbor(&positions[i], 1, |j| {
+                if positions[i].distance(positions[j]) < radius { found.push(j); }
+            });
+            found.sort();
+
+            let expected = (0..positions.len())
+                .filter(|&j| positions[i].distance(positions[j]) < radius)
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+        }
+    }
+}
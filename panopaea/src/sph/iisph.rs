@@ -0,0 +1,147 @@
+//! Implicit Incompressible SPH (IISPH)
+//!
+//! Solves the pressure Poisson equation `dt² Σ m_j (a_i - a_j) ∇W_ij = ρ0 - ρ*_i`
+//! for the pressure accelerations `a` with relaxed Jacobi iterations. The
+//! diagonal of the system only depends on the positions, each iteration
+//! evaluates the pressure accelerations and the resulting density change.
+//!
+//! References:
+//!     [ICS14] Markus Ihmsen, Jens Cornelis, Barbara Solenthaler, Christopher Horvath, and Matthias Teschner, 2014,
+//!             Implicit incompressible SPH,
+//!             IEEE Transactions on Visualization and Computer Graphics 20, 3, 426-435
+
+use cgmath::{InnerSpace, MetricSpace};
+use math::{Real, VectorN};
+use num::Zero;
+use particle::Processor;
+use typenum::U2;
+
+use super::dfsph::density_change;
+use super::isph::{pressure_accelerations, PressureSolver};
+use super::kernel::SmoothingKernel;
+use super::property::*;
+use super::solver::Fluid;
+
+/// IISPH pressure solver for `Isph`.
+pub struct Iisph<T: Real> {
+    /// Maximum average density error relative to the rest density.
+    pub threshold: T,
+    pub max_iterations: usize,
+    /// Relaxation factor of the Jacobi iterations.
+    pub omega: T,
+
+    diagonal: Vec<T>,
+    source: Vec<T>,
+    residual: Vec<T>,
+    accels: Vec<VectorN<T, U2>>,
+}
+
+impl<T: Real> Iisph<T> {
+    pub fn new() -> Self {
+        Iisph {
+            threshold: T::new(1.0e-3),
+            max_iterations: 100,
+            omega: T::new(0.5),
+
+            diagonal: Vec::new(),
+            source: Vec::new(),
+            residual: Vec::new(),
+            accels: Vec::new(),
+        }
+    }
+}
+
+impl<T: Real> PressureSolver<T> for Iisph<T> {
+    /// Half of the pressures of the last step serve as initial guess.
+    ///
+    /// Ref: [ICS14] Alg. 2
+    fn solve(&mut self, p: &Processor, fluid: &Fluid<T>, timestep: T) -> usize {
+        let num_particles = p.read_property::<Mass<T>>().len();
+        self.diagonal.resize(num_particles, T::zero());
+        self.source.resize(num_particles, T::zero());
+        self.residual.resize(num_particles, T::zero());
+        self.accels.resize(num_particles, VectorN::zero());
+
+        let (grid, kernel, boundary, rest_density) = (fluid.grid, fluid.kernel, fluid.boundary, fluid.rest_density);
+        let (diagonal, source, residual, accels) = (&mut self.diagonal, &mut self.source, &mut self.residual, &mut self.accels);
+        let (positions, densities, masses) = (
+            p.read_property::<Position<T, U2>>(),
+            p.read_property::<Density<T>>(),
+            p.read_property::<Mass<T>>(),
+        );
+
+        // `ρ0 - ρ_adv` with the density after advecting with the non-pressure velocities
+        density_change(p, source, kernel, grid, boundary, rest_density, timestep);
+        par_azip!(mut s (&mut source[..]), density (densities) in { *s = rest_density - density - *s; });
+
+        // `a_ii = -dt² / ρ_i² (|Σ m_j ∇W_ij + Σ Ψ_b ∇W_ib|² + m_i Σ m_j |∇W_ij|²)`
+        par_azip!(index i, mut diagonal (&mut diagonal[..]), pos (positions), density (densities), mass (masses) in {
+            *diagonal = T::zero();
+            let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+            let mut sum = VectorN::<T, U2>::zero();
+            let mut sum_squared = T::zero();
+            grid.for_each_neighbor(cell, 1, |j| {
+                if j == i { return }
+                let grad = (pos - positions[j]) * kernel.grad_w(pos.distance(positions[j]));
+                sum += grad * masses[j];
+                sum_squared += masses[j] * grad.magnitude2();
+            });
+            boundary.for_each_neighbor(&pos, |b, volume| {
+                sum += (pos - *b) * (rest_density * volume * kernel.grad_w(pos.distance(*b)));
+            });
+            *diagonal = -timestep * timestep / (density * density) * (sum.magnitude2() + mass * sum_squared);
+        });
+
+        {
+            let pressures = p.write_property::<Pressure<T>>();
+            par_azip!(mut pressure (pressures) in { *pressure = *pressure * T::new(0.5); });
+        }
+
+        let (threshold, omega) = (self.threshold * rest_density, self.omega);
+        let mut iterations = 0;
+        loop {
+            pressure_accelerations(p, fluid, accels);
+
+            // `ρ*_i - ρ0` of the current pressures
+            {
+                let accels = &accels[..];
+                par_azip!(index i, mut residual (&mut residual[..]), pos (positions), source (&source[..]) in {
+                    *residual = T::zero();
+                    let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+                    let mut change = T::zero();
+                    grid.for_each_neighbor(cell, 1, |j| {
+                        if j == i { return }
+                        let grad = (pos - positions[j]) * kernel.grad_w(pos.distance(positions[j]));
+                        change += masses[j] * (accels[i] - accels[j]).dot(grad);
+                    });
+                    boundary.for_each_neighbor(&pos, |b, volume| {
+                        let grad = (pos - *b) * kernel.grad_w(pos.distance(*b));
+                        change += rest_density * volume * accels[i].dot(grad);
+                    });
+                    *residual = timestep * timestep * change - source;
+                });
+            }
+
+            let error = residual.iter().fold(T::zero(), |sum, &r| sum + r.max(T::zero())) / T::new(num_particles.max(1));
+            if (iterations >= 2 && error <= threshold) || iterations >= self.max_iterations {
+                break;
+            }
+
+            let pressures = p.write_property::<Pressure<T>>();
+            par_azip!(mut pressure (pressures), diagonal (&diagonal[..]), residual (&residual[..]) in {
+                *pressure = if diagonal < T::zero() {
+                    (*pressure - omega * residual / diagonal).max(T::zero())
+                } else {
+                    T::zero()
+                };
+            });
+            iterations += 1;
+        }
+
+        let velocities = p.write_property::<Velocity<T, U2>>();
+        par_azip!(mut vel (velocities), accel (&accels[..]) in { *vel += accel * timestep; });
+        iterations
+    }
+}
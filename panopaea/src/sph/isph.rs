@@ -0,0 +1,214 @@
+//! Incompressible SPH with exchangeable pressure solvers
+//!
+//! All solvers share the same steps besides the pressure: densities are
+//! computed by summation, non-pressure forces (gravity, viscosity, surface
+//! tension) advance the velocities and a `PressureSolver` corrects the
+//! predicted velocities until the density at the end of the step stays close
+//! to the rest density. The solvers only differ in convergence and cost per
+//! iteration, `Isph::iterations` allows comparing them.
+//!
+//! Available solvers are `Iisph`, `Pcisph` and the constant density solver of
+//! DFSPH (`dfsph::DensitySolver`).
+//!
+//! References:
+//!     [AIA12] Nadir Akinci, Markus Ihmsen, Gizem Akinci, Barbara Solenthaler, and Matthias Teschner, 2012,
+//!             Versatile rigid-fluid coupling for incompressible SPH,
+//!             ACM Trans. Graph. 31, 4, Article 62
+
+use cgmath::MetricSpace;
+use math::{Real, VectorN};
+use num::Zero;
+use particle::{Particles, Processor};
+use typenum::U2;
+
+use super::kernel::SmoothingKernel;
+use super::property::*;
+use super::solver::{Diagnostics, Fluid, Pipeline, Solver};
+use super::wcsph;
+
+/// Enforces incompressibility by correcting the velocities.
+pub trait PressureSolver<T: Real> {
+    /// Add pressure accelerations to the velocities, which already include
+    /// the non-pressure forces of the step.
+    ///
+    /// Densities are computed from the current positions. Solvers store the
+    /// resulting pressures in the `Pressure` property, which determine the
+    /// forces on rigid bodies. Returns the number of iterations.
+    fn solve(&mut self, p: &Processor, fluid: &Fluid<T>, timestep: T) -> usize;
+}
+
+/// Pressure accelerations `a_i = -Σ m_j (p_i / ρ_i^2 + p_j / ρ_j^2) ∇W_ij - Σ Ψ_b p_i / ρ_i^2 ∇W_ib`.
+///
+/// Reads the `Pressure` and `Density` properties.
+///
+/// Ref: [AIA12] Eq. 10
+pub fn pressure_accelerations<T: Real>(p: &Processor, fluid: &Fluid<T>, accels: &mut [VectorN<T, U2>]) {
+    let (positions, densities, pressures, masses) = (
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Pressure<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+    let (grid, kernel, boundary, rest_density) = (fluid.grid, fluid.kernel, fluid.boundary, fluid.rest_density);
+
+    par_azip!(index i, mut accel (accels), pos (positions), density (densities), pressure (pressures) in {
+        *accel = VectorN::zero();
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+        let pressure_i = pressure / (density * density);
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let grad = (pos - positions[j]) * kernel.grad_w(pos.distance(positions[j]));
+            *accel -= grad * (masses[j] * (pressure_i + pressures[j] / (densities[j] * densities[j])));
+        });
+        boundary.for_each_neighbor(&pos, |b, volume| {
+            let grad = (pos - *b) * kernel.grad_w(pos.distance(*b));
+            *accel -= grad * (rest_density * volume * pressure_i);
+        });
+    });
+}
+
+/// Incompressible SPH solver for 2d domains with a pressure solver `S`.
+///
+/// Uses the properties registered by `wcsph::init`.
+/// Particles with spacing `s` should have a mass of `rest_density * s^2`,
+/// a smoothing radius of `2s` is a common choice.
+pub struct Isph<T: Real, S: PressureSolver<T>> {
+    /// Neighborhood, boundary, rigid bodies and non-pressure forces.
+    pub pipeline: Pipeline<T>,
+    pub pressure_solver: S,
+    pub rest_density: T,
+
+    /// Iterations of the pressure solver in the last step.
+    pub iterations: usize,
+}
+
+impl<T: Real, S: PressureSolver<T>> Isph<T, S> {
+    /// Solver for the domain `[0, num_cells * smoothing_radius]`.
+    pub fn new(num_cells: VectorN<usize, U2>, smoothing_radius: T, rest_density: T, pressure_solver: S) -> Self {
+        Isph {
+            pipeline: Pipeline::new(num_cells, smoothing_radius),
+            pressure_solver,
+            rest_density,
+
+            iterations: 0,
+        }
+    }
+
+    /// Advance the simulation by one timestep.
+    pub fn step(&mut self, particles: &mut Particles, timestep: T) {
+        self.pipeline.prepare(particles);
+
+        {
+            let (pipeline, rest_density) = (&mut self.pipeline, self.rest_density);
+            particles.run(|p| {
+                pipeline.densities(p, rest_density);
+                pipeline.forces(p, rest_density);
+                pipeline.accelerate(p, timestep);
+            });
+        }
+
+        {
+            let fluid = self.pipeline.fluid(self.rest_density);
+            let solver = &mut self.pressure_solver;
+            let mut iterations = 0;
+            particles.run(|p| iterations = solver.solve(p, &fluid, timestep));
+            self.iterations = iterations;
+        }
+
+        {
+            let (pipeline, rest_density) = (&mut self.pipeline, self.rest_density);
+            particles.run(|p| {
+                let (densities, pressures) = (p.read_property::<Density<T>>(), p.read_property::<Pressure<T>>());
+                pipeline.body_reactions(p, rest_density, |i| pressures[i] / (densities[i] * densities[i]));
+                pipeline.step_bodies(timestep);
+                pipeline.advect(p, timestep);
+            });
+        }
+    }
+}
+
+impl<T: Real, S: PressureSolver<T>> Solver<T> for Isph<T, S> {
+    fn init(&self, particles: &mut Particles) {
+        wcsph::init::<T, U2>(particles);
+    }
+
+    fn step(&mut self, particles: &mut Particles, timestep: T) {
+        Isph::step(self, particles, timestep);
+    }
+
+    fn pipeline(&self) -> &Pipeline<T> {
+        &self.pipeline
+    }
+
+    fn pipeline_mut(&mut self) -> &mut Pipeline<T> {
+        &mut self.pipeline
+    }
+
+    fn diagnostics(&self, particles: &Particles) -> Diagnostics<T> {
+        Diagnostics {
+            pressure_iterations: self.iterations,
+            ..Diagnostics::measure(particles, self.rest_density)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+    use math::vector_n::vec2;
+    use sph::dfsph::DensitySolver;
+    use sph::iisph::Iisph;
+    use sph::pcisph::Pcisph;
+    use super::*;
+
+    /// Slightly compressed block moving towards its center.
+    fn compressed_block<S: PressureSolver<f64>>(pressure_solver: S) -> (usize, VectorN<f64, U2>) {
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+
+        let c = 1.25 + 7.5 * 0.95 * spacing;
+        let center = vec2(c, c);
+        {
+            let mut positions = Vec::new();
+            let mut velocities = Vec::new();
+            for y in 0..16 {
+                for x in 0..16 {
+                    let pos = vec2(1.25 + x as f64 * 0.95 * spacing, 1.25 + y as f64 * 0.95 * spacing);
+                    positions.push(pos);
+                    velocities.push((center - pos) * 0.5);
+                }
+            }
+            let masses = vec![rest_density * spacing * spacing; positions.len()];
+            particles.add_particles(positions.len())
+                     .with::<Position<f64, U2>>(&positions)
+                     .with::<Velocity<f64, U2>>(&velocities)
+                     .with::<Mass<f64>>(&masses);
+        }
+
+        let mut solver = Isph::new(vec2(20, 20), 2.0 * spacing, rest_density, pressure_solver);
+        solver.pipeline.gravity = vec2(0.0, 0.0);
+        solver.pipeline.viscosity = 0.0;
+        solver.step(&mut particles, 1.0e-3);
+
+        let momentum = particles.read_property::<Velocity<f64, U2>>().iter()
+            .zip(particles.read_property::<Mass<f64>>().iter())
+            .fold(vec2(0.0, 0.0), |sum, (&v, &m)| sum + v * m);
+        (solver.iterations, momentum)
+    }
+
+    #[test]
+    fn pressure_solvers() {
+        // all solvers converge and preserve the momentum with symmetric pressure forces
+        let results = [
+            compressed_block(Iisph::new()),
+            compressed_block(Pcisph::new()),
+            compressed_block(DensitySolver::new()),
+        ];
+        for &(iterations, momentum) in &results {
+            assert!(iterations >= 2 && iterations < 100, "{}", iterations);
+            assert!(momentum.magnitude() < 1.0e-8, "{:?}", momentum);
+        }
+    }
+}
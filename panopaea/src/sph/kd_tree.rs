@@ -0,0 +1,285 @@
+//! k-d tree neighbor search
+//!
+//! Binary space partitioning at the median along the axis of largest extent.
+//! Memory scales with the particle count only, independent of the domain
+//! size and the density variations which make uniform grids wasteful, e.g.
+//! for sprays or particles spread over large empty regions.
+//!
+//! Unlike the grids the tree doesn't need the particles sorted, queries
+//! report indices into the positions passed to `build`.
+//!
+//! References:
+//!     [Ben75] Jon Louis Bentley, 1975,
+//!             Multidimensional binary search trees used for associative searching,
+//!             Communications of the ACM 18 (9), 509-517
+//!     [FBF77] Jerome H. Friedman, Jon Louis Bentley and Raphael Ari Finkel, 1977,
+//!             An algorithm for finding best matches in logarithmic expected time,
+//!             ACM Transactions on Mathematical Software 3 (3), 209-226
+
+use cgmath::MetricSpace;
+use generic_array::typenum::Unsigned;
+use math::{Dim, Real, VectorN};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use super::neighbor::NeighborSearch;
+
+struct KdNode<S> {
+    start: usize,
+    end: usize,
+    axis: usize,
+    split: S,
+    /// Index of the lower child, the upper one follows it. `None` for leaves.
+    children: Option<usize>,
+}
+
+/// Candidate of a k-nearest query, ordered by distance for the max-heap.
+struct Candidate<S> {
+    distance2: S,
+    index: usize,
+}
+
+impl<S: Real> PartialEq for Candidate<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance2 == other.distance2
+    }
+}
+
+impl<S: Real> Eq for Candidate<S> { }
+
+impl<S: Real> PartialOrd for Candidate<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: Real> Ord for Candidate<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance2.partial_cmp(&other.distance2).unwrap_or(Ordering::Equal)
+    }
+}
+
+pub struct KdTree<S: Real, N: Dim<S>> {
+    leaf_size: usize,
+    nodes: Vec<KdNode<S>>,
+    /// Positions in tree order.
+    points: Vec<VectorN<S, N>>,
+    /// Input index of each point in tree order.
+    order: Vec<usize>,
+    /// Tree order index of each input point.
+    rank: Vec<usize>,
+}
+
+impl<S, N> KdTree<S, N>
+    where S: Real,
+          N: Dim<S>,
+{
+    pub fn new() -> Self {
+        KdTree {
+            leaf_size: 8,
+            nodes: Vec::new(),
+            points: Vec::new(),
+            order: Vec::new(),
+            rank: Vec::new(),
+        }
+    }
+
+    /// Tree with at most `leaf_size` particles per leaf, 8 by default.
+    pub fn with_leaf_size(mut self, leaf_size: usize) -> Self {
+        assert!(leaf_size > 0);
+        self.leaf_size = leaf_size;
+        self
+    }
+
+    /// Number of indexed particles.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Rebuild the tree for a new set of positions.
+    pub fn build(&mut self, positions: &[VectorN<S, N>]) {
+        self.nodes.clear();
+        let mut order = (0..positions.len()).collect::<Vec<_>>();
+        if !positions.is_empty() {
+            self.nodes.push(KdNode { start: 0, end: positions.len(), axis: 0, split: S::zero(), children: None });
+            self.subdivide(0, positions, &mut order);
+        }
+        self.points = order.iter().map(|&i| positions[i].clone()).collect();
+        self.rank = vec![0; order.len()];
+        for (r, &i) in order.iter().enumerate() {
+            self.rank[i] = r;
+        }
+        self.order = order;
+    }
+
+    fn subdivide(&mut self, node: usize, positions: &[VectorN<S, N>], order: &mut [usize]) {
+        let (start, end) = (self.nodes[node].start, self.nodes[node].end);
+        if end - start <= self.leaf_size {
+            return;
+        }
+
+        // split at the median along the axis of largest extent
+        let axis = {
+            let range = &mut order[start..end];
+            let axis = (0..<N as Unsigned>::to_usize())
+                .map(|d| {
+                    let (min, max) = range.iter().fold((S::infinity(), S::neg_infinity()), |(min, max), &i| {
+                        (min.min(positions[i][d]), max.max(positions[i][d]))
+                    });
+                    (d, max - min)
+                })
+                .fold((0, -S::one()), |best, (d, extent)| if extent > best.1 { (d, extent) } else { best })
+                .0;
+            range.sort_by(|&a, &b| positions[a][axis].partial_cmp(&positions[b][axis]).unwrap_or(Ordering::Equal));
+            axis
+        };
+        let mid = start + (end - start) / 2;
+
+        let first = self.nodes.len();
+        self.nodes.push(KdNode { start, end: mid, axis: 0, split: S::zero(), children: None });
+        self.nodes.push(KdNode { start: mid, end, axis: 0, split: S::zero(), children: None });
+        self.nodes[node].axis = axis;
+        self.nodes[node].split = positions[order[mid]][axis];
+        self.nodes[node].children = Some(first);
+
+        self.subdivide(first, positions, order);
+        self.subdivide(first + 1, positions, order);
+    }
+
+    /// Apply function to each particle closer than `radius` to a position.
+    pub fn for_each_within<F>(&self, position: &VectorN<S, N>, radius: S, mut fnc: F)
+        where F: FnMut(usize)
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let radius2 = radius * radius;
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            match node.children {
+                Some(lower) => {
+                    // lower child holds coordinates up to `split`, the upper from `split`
+                    let offset = position[node.axis] - node.split;
+                    if offset <= radius {
+                        stack.push(lower);
+                    }
+                    if offset >= -radius {
+                        stack.push(lower + 1);
+                    }
+                }
+                None => for i in node.start..node.end {
+                    if self.points[i].clone().distance2(position.clone()) < radius2 {
+                        fnc(self.order[i]);
+                    }
+                },
+            }
+        }
+    }
+
+    /// The `k` particles closest to a position, ordered by increasing distance.
+    pub fn k_nearest(&self, position: &VectorN<S, N>, k: usize) -> Vec<usize> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        if k > 0 && !self.nodes.is_empty() {
+            self.search_nearest(0, position, k, &mut heap);
+        }
+        heap.into_sorted_vec().into_iter().map(|c| self.order[c.index]).collect()
+    }
+
+    fn search_nearest(&self, node: usize, position: &VectorN<S, N>, k: usize, heap: &mut BinaryHeap<Candidate<S>>) {
+        let node = &self.nodes[node];
+        match node.children {
+            Some(lower) => {
+                let offset = position[node.axis] - node.split;
+                let (near, far) = if offset <= S::zero() { (lower, lower + 1) } else { (lower + 1, lower) };
+                self.search_nearest(near, position, k, heap);
+                if heap.len() < k || offset * offset < heap.peek().unwrap().distance2 {
+                    self.search_nearest(far, position, k, heap);
+                }
+            }
+            None => for i in node.start..node.end {
+                let distance2 = self.points[i].clone().distance2(position.clone());
+                if heap.len() < k {
+                    heap.push(Candidate { distance2, index: i });
+                } else if distance2 < heap.peek().unwrap().distance2 {
+                    heap.pop();
+                    heap.push(Candidate { distance2, index: i });
+                }
+            },
+        }
+    }
+}
+
+
+impl<S, N> NeighborSearch<S, N> for KdTree<S, N>
+    where S: Real,
+          N: Dim<S>,
+{
+    fn build(&mut self, positions: &[VectorN<S, N>]) {
+        KdTree::build(self, positions);
+    }
+
+    fn num_particles(&self) -> usize {
+        self.len()
+    }
+
+    fn position(&self, particle: usize) -> &VectorN<S, N> {
+        &self.points[self.rank[particle]]
+    }
+
+    fn for_each_within<F>(&self, position: &VectorN<S, N>, radius: S, fnc: F)
+        where F: FnMut(usize)
+    {
+        KdTree::for_each_within(self, position, radius, fnc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::MetricSpace;
+    use generic_array::typenum::U3;
+    use math::rng::{Pcg32, DEFAULT_SEED};
+    use math::vector_n::vec3;
+    use rand::Rng;
+    use super::*;
+
+    #[test]
+    fn kd_tree_queries() {
+        let mut rng = Pcg32::new(DEFAULT_SEED);
+        // dense blob and sparse spray
+        let mut positions = (0..400)
+            .map(|_| vec3(rng.gen::<f64>() * 0.5, rng.gen::<f64>() * 0.5, rng.gen::<f64>() * 0.5))
+            .collect::<Vec<_>>();
+        positions.extend((0..100).map(|_| vec3(rng.gen::<f64>() * 100.0, rng.gen::<f64>() * 5.0, rng.gen::<f64>())));
+        positions.extend(vec![vec3(0.25, 0.25, 0.25); 12]);
+
+        let mut tree = KdTree::<f64, U3>::new().with_leaf_size(4);
+        tree.build(&positions);
+        assert_eq!(tree.len(), positions.len());
+
+        let radius = 0.1;
+        for i in 0..positions.len() {
+            let mut found = Vec::new();
+            tree.for_each_within(&positions[i], radius, |j| found.push(j));
+            found.sort();
+            let expected = (0..positions.len())
+                .filter(|&j| positions[i].distance(positions[j]) < radius)
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+        }
+
+        for i in (0..positions.len()).filter(|i| i % 7 == 0) {
+            let nearest = tree.k_nearest(&positions[i], 10);
+            assert_eq!(nearest.len(), 10);
+            let mut distances = (0..positions.len()).map(|j| positions[i].distance(positions[j])).collect::<Vec<_>>();
+            distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for (rank, &j) in nearest.iter().enumerate() {
+                assert_eq!(positions[i].distance(positions[j]), distances[rank]);
+            }
+        }
+        assert_eq!(tree.k_nearest(&positions[0], 1000).len(), positions.len());
+    }
+}
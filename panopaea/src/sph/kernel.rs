@@ -1,15 +1,45 @@
-
 //! Smoothing Kernels
-
-use math::Real;
+//!
+//! All kernels are radially symmetric with compact support `h` and normalized
+//! for 3d (`new`) or 2d (`new_2d`) domains.
+//!
+//! References:
+//!     [Mon05] Joe J. Monaghan, 2005,
+//!             Smoothed particle hydrodynamics,
+//!             Reports on Progress in Physics 68, 1703-1759
+//!     [MFZ97] Joseph P. Morris, Patrick J. Fox, and Yi Zhu, 1997,
+//!             Modeling low Reynolds number incompressible flows using SPH,
+//!             Journal of Computational Physics 136, 214-226
+//!     [Wen95] Holger Wendland, 1995,
+//!             Piecewise polynomial, positive definite and compactly supported radial functions of minimal degree,
+//!             Advances in Computational Mathematics 4, 389-396
+
+use math::{simd, Real};
 use num::cast;
+use std::f64;
+
+pub trait SmoothingKernel<T: Real> {
+    /// Support radius, the kernel vanishes for `radius >= support`.
+    fn support(&self) -> T;
 
-pub trait Kernel<T: Real> {
     fn w(&self, radius: T) -> T;
-    
+
     /// Gradient factor
+    ///
+    /// The gradient with respect to the first particle is `grad_w(|r|) * r` with `r = x_i - x_j`.
     fn grad_w(&self, radius: T) -> T;
     fn laplace_w(&self, radius: T) -> T;
+
+    /// Kernel values `w(r)` for a batch of radii.
+    fn w_batch(&self, out: &mut [T], radii: &[T]) {
+        for (out, &radius) in out.iter_mut().zip(radii.iter()) {
+            *out = self.w(radius);
+        }
+    }
+}
+
+fn real<T: Real>(x: f64) -> T {
+    cast::<f64, T>(x).unwrap()
 }
 
 /// Poly6 kernel function
@@ -17,27 +47,37 @@ pub trait Kernel<T: Real> {
 /// Ref: [MDM03] Sec 3.5
 pub struct Poly6<T: Real> {
     h: T,
+    dim: usize,
     w_const: T,
     grad_w_const: T,
 }
 
 impl<T: Real> Poly6<T> {
     pub fn new(smoothing_radius: T) -> Self {
-        use std::f64;
-        let w_frac = cast::<f64, T>(315.0 / 64.0).unwrap();
-        let grad_w_frac = cast::<f64, T>(-945.0 / 32.0).unwrap();
-        let pi = cast::<f64, T>(f64::consts::PI).unwrap();
-        let h9 = smoothing_radius.powi(9);
+        let w_const = real::<T>(315.0 / (64.0 * f64::consts::PI)) / smoothing_radius.powi(9);
+        Poly6::with_const(smoothing_radius, 3, w_const)
+    }
 
+    pub fn new_2d(smoothing_radius: T) -> Self {
+        let w_const = real::<T>(4.0 / f64::consts::PI) / smoothing_radius.powi(8);
+        Poly6::with_const(smoothing_radius, 2, w_const)
+    }
+
+    fn with_const(smoothing_radius: T, dim: usize, w_const: T) -> Self {
         Poly6 {
             h: smoothing_radius,
-            w_const: w_frac / (pi * h9),
-            grad_w_const: grad_w_frac / (pi * h9),
+            dim,
+            w_const,
+            grad_w_const: real::<T>(-6.0) * w_const,
         }
     }
 }
 
-impl<T: Real> Kernel<T> for Poly6<T> {
+impl<T: Real> SmoothingKernel<T> for Poly6<T> {
+    fn support(&self) -> T {
+        self.h
+    }
+
     fn w(&self, radius: T) -> T {
         debug_assert!(radius.is_sign_positive());
 
@@ -60,8 +100,16 @@ impl<T: Real> Kernel<T> for Poly6<T> {
         self.grad_w_const * diff.powi(2)
     }
 
-    fn laplace_w(&self, _radius: T) -> T {
-        unimplemented!()
+    fn laplace_w(&self, radius: T) -> T {
+        debug_assert!(radius.is_sign_positive());
+
+        if self.h <= radius {
+            return T::zero();
+        }
+
+        let r2 = radius.powi(2);
+        let diff = self.h.powi(2) - r2;
+        self.w_const * diff * (real::<T>(24.0) * r2 - real::<T>(6.0 * self.dim as f64) * diff)
     }
 }
 
@@ -70,27 +118,37 @@ impl<T: Real> Kernel<T> for Poly6<T> {
 /// Ref: [MDM03] Sec 3.5
 pub struct Spiky<T: Real> {
     h: T,
+    dim: usize,
     w_const: T,
     grad_w_const: T,
 }
 
 impl<T: Real> Spiky<T> {
     pub fn new(smoothing_radius: T) -> Self {
-        use std::f64;
-        let w_frac = cast::<f64, T>(15.0).unwrap();
-        let grad_w_frac = cast::<f64, T>(-45.0).unwrap();
-        let pi = cast::<f64, T>(f64::consts::PI).unwrap();
-        let h6 = smoothing_radius.powi(6);
+        let w_const = real::<T>(15.0 / f64::consts::PI) / smoothing_radius.powi(6);
+        Spiky::with_const(smoothing_radius, 3, w_const)
+    }
+
+    pub fn new_2d(smoothing_radius: T) -> Self {
+        let w_const = real::<T>(10.0 / f64::consts::PI) / smoothing_radius.powi(5);
+        Spiky::with_const(smoothing_radius, 2, w_const)
+    }
 
+    fn with_const(smoothing_radius: T, dim: usize, w_const: T) -> Self {
         Spiky {
             h: smoothing_radius,
-            w_const: w_frac / (pi * h6),
-            grad_w_const: grad_w_frac / (pi * h6),
+            dim,
+            w_const,
+            grad_w_const: real::<T>(-3.0) * w_const,
         }
     }
 }
 
-impl<T: Real> Kernel<T> for Spiky<T> {
+impl<T: Real> SmoothingKernel<T> for Spiky<T> {
+    fn support(&self) -> T {
+        self.h
+    }
+
     fn w(&self, radius: T) -> T {
         debug_assert!(radius.is_sign_positive());
 
@@ -114,13 +172,24 @@ impl<T: Real> Kernel<T> for Spiky<T> {
         self.grad_w_const * diff.powi(2) / radius
     }
 
-    fn laplace_w(&self, _radius: T) -> T {
-        unimplemented!()
+    fn laplace_w(&self, radius: T) -> T {
+        debug_assert!(radius.is_sign_positive());
+
+        let eps = cast::<f64, T>(0.00001).unwrap();
+        if self.h <= radius || radius < eps {
+            return T::zero();
+        }
+
+        let diff = self.h - radius;
+        let dim = real::<T>((self.dim - 1) as f64);
+        self.w_const * diff * (real::<T>(6.0) - real::<T>(3.0) * dim * diff / radius)
     }
 }
 
 /// Viscosity kernel function
 ///
+/// Only normalized for 3d domains.
+///
 /// Ref: [MDM03] Sec 3.5
 pub struct Viscosity<T: Real> {
     h: T,
@@ -145,7 +214,11 @@ impl<T: Real> Viscosity<T> {
     }
 }
 
-impl<T: Real> Kernel<T> for Viscosity<T> {
+impl<T: Real> SmoothingKernel<T> for Viscosity<T> {
+    fn support(&self) -> T {
+        self.h
+    }
+
     fn w(&self, radius: T) -> T {
         debug_assert!(radius.is_sign_positive());
 
@@ -156,7 +229,7 @@ impl<T: Real> Kernel<T> for Viscosity<T> {
 
         let two = cast::<f64, T>(2.0).unwrap();
 
-        let fac = 
+        let fac =
             -radius.powi(3) / (two * self.h.powi(3)) +
             (radius / self.h).powi(2) +
             self.h / (two * radius) - T::one();
@@ -164,8 +237,20 @@ impl<T: Real> Kernel<T> for Viscosity<T> {
         self.w_const * fac
     }
 
-    fn grad_w(&self, _radius: T) -> T {
-        unimplemented!()
+    fn grad_w(&self, radius: T) -> T {
+        debug_assert!(radius.is_sign_positive());
+
+        let eps = cast::<f64, T>(0.00001).unwrap();
+        if self.h <= radius || radius < eps {
+            return T::zero();
+        }
+
+        let fac =
+            -real::<T>(1.5) * radius / self.h.powi(3) +
+            real::<T>(2.0) / self.h.powi(2) -
+            self.h / (real::<T>(2.0) * radius.powi(3));
+
+        self.w_const * fac
     }
 
     fn laplace_w(&self, radius: T) -> T {
@@ -177,4 +262,273 @@ impl<T: Real> Kernel<T> for Viscosity<T> {
 
         self.laplace_w_const * (self.h - radius)
     }
-}
\ No newline at end of file
+}
+
+/// Kernel functions given as polynomials in `q = r / h`.
+///
+/// `dw_q(q)` is `dW/dq / q`, which stays finite at the origin.
+trait RadialKernel<T: Real> {
+    fn w_q(&self, q: T) -> T;
+    fn dw_q(&self, q: T) -> T;
+    fn d2w_q(&self, q: T) -> T;
+
+    /// `w_q` in place, zero for `q >= 1`.
+    fn w_q_batch(&self, q: &mut [T]) {
+        for q in q.iter_mut() {
+            *q = if *q >= T::one() { T::zero() } else { self.w_q(*q) };
+        }
+    }
+}
+
+macro_rules! impl_radial_kernel {
+    ($kernel:ident) => {
+        impl<T: Real> SmoothingKernel<T> for $kernel<T> {
+            fn support(&self) -> T {
+                self.h
+            }
+
+            fn w(&self, radius: T) -> T {
+                debug_assert!(radius.is_sign_positive());
+                let q = radius / self.h;
+                if q >= T::one() { T::zero() } else { self.w_q(q) }
+            }
+
+            fn w_batch(&self, out: &mut [T], radii: &[T]) {
+                simd::scale(out, radii, T::one() / self.h);
+                self.w_q_batch(out);
+            }
+
+            fn grad_w(&self, radius: T) -> T {
+                debug_assert!(radius.is_sign_positive());
+                let h = self.h;
+                let q = radius / h;
+                if q >= T::one() { T::zero() } else { self.dw_q(q) / (h * h) }
+            }
+
+            fn laplace_w(&self, radius: T) -> T {
+                debug_assert!(radius.is_sign_positive());
+                let h = self.h;
+                let q = radius / h;
+                if q >= T::one() {
+                    return T::zero();
+                }
+                let dim = real::<T>((self.dim - 1) as f64);
+                (self.d2w_q(q) + dim * self.dw_q(q)) / (h * h)
+            }
+        }
+    }
+}
+
+/// Cubic B-spline kernel
+///
+/// Ref: [Mon05] Sec 2.4, rescaled to the support radius `h`.
+pub struct CubicSpline<T: Real> {
+    h: T,
+    dim: usize,
+    w_const: T,
+}
+
+impl<T: Real> CubicSpline<T> {
+    pub fn new(smoothing_radius: T) -> Self {
+        CubicSpline {
+            h: smoothing_radius,
+            dim: 3,
+            w_const: real::<T>(8.0 / f64::consts::PI) / smoothing_radius.powi(3),
+        }
+    }
+
+    pub fn new_2d(smoothing_radius: T) -> Self {
+        CubicSpline {
+            h: smoothing_radius,
+            dim: 2,
+            w_const: real::<T>(40.0 / (7.0 * f64::consts::PI)) / smoothing_radius.powi(2),
+        }
+    }
+}
+
+impl<T: Real> RadialKernel<T> for CubicSpline<T> {
+    fn w_q(&self, q: T) -> T {
+        if q <= real(0.5) {
+            self.w_const * (real::<T>(6.0) * (q.powi(3) - q.powi(2)) + T::one())
+        } else {
+            self.w_const * real::<T>(2.0) * (T::one() - q).powi(3)
+        }
+    }
+
+    fn dw_q(&self, q: T) -> T {
+        if q <= real(0.5) {
+            self.w_const * (real::<T>(18.0) * q - real::<T>(12.0))
+        } else {
+            -self.w_const * real::<T>(6.0) * (T::one() - q).powi(2) / q
+        }
+    }
+
+    fn d2w_q(&self, q: T) -> T {
+        if q <= real(0.5) {
+            self.w_const * (real::<T>(36.0) * q - real::<T>(12.0))
+        } else {
+            self.w_const * real::<T>(12.0) * (T::one() - q)
+        }
+    }
+
+    fn w_q_batch(&self, q: &mut [T]) {
+        simd::cubic_spline(q, self.w_const);
+    }
+}
+
+impl_radial_kernel!(CubicSpline);
+
+/// Quintic spline kernel
+///
+/// Ref: [MFZ97] Eq. 8, rescaled to the support radius `h`.
+pub struct QuinticSpline<T: Real> {
+    h: T,
+    dim: usize,
+    w_const: T,
+}
+
+impl<T: Real> QuinticSpline<T> {
+    pub fn new(smoothing_radius: T) -> Self {
+        QuinticSpline {
+            h: smoothing_radius,
+            dim: 3,
+            w_const: real::<T>(27.0 / (120.0 * f64::consts::PI)) / smoothing_radius.powi(3),
+        }
+    }
+
+    pub fn new_2d(smoothing_radius: T) -> Self {
+        QuinticSpline {
+            h: smoothing_radius,
+            dim: 2,
+            w_const: real::<T>(63.0 / (478.0 * f64::consts::PI)) / smoothing_radius.powi(2),
+        }
+    }
+
+    /// Sum of `factor * (offset - s)^power` over the active spline segments, `s = 3q`.
+    fn segments(s: T, power: i32, factors: [f64; 3]) -> T {
+        [3.0, 2.0, 1.0].iter().zip(factors.iter()).fold(T::zero(), |sum, (&offset, &factor)| {
+            let d = real::<T>(offset) - s;
+            if d > T::zero() { sum + real::<T>(factor) * d.powi(power) } else { sum }
+        })
+    }
+}
+
+impl<T: Real> RadialKernel<T> for QuinticSpline<T> {
+    fn w_q(&self, q: T) -> T {
+        let s = real::<T>(3.0) * q;
+        self.w_const * Self::segments(s, 5, [1.0, -6.0, 15.0])
+    }
+
+    fn dw_q(&self, q: T) -> T {
+        let s = real::<T>(3.0) * q;
+        if s < real(1.0e-5) {
+            // `dW/ds` vanishes at the origin
+            return self.d2w_q(q);
+        }
+        real::<T>(3.0) * self.w_const * Self::segments(s, 4, [-5.0, 30.0, -75.0]) / q
+    }
+
+    fn d2w_q(&self, q: T) -> T {
+        let s = real::<T>(3.0) * q;
+        real::<T>(9.0) * self.w_const * Self::segments(s, 3, [20.0, -120.0, 300.0])
+    }
+}
+
+impl_radial_kernel!(QuinticSpline);
+
+/// Wendland C2 kernel
+///
+/// Ref: [Wen95]
+pub struct WendlandC2<T: Real> {
+    h: T,
+    dim: usize,
+    w_const: T,
+}
+
+impl<T: Real> WendlandC2<T> {
+    pub fn new(smoothing_radius: T) -> Self {
+        WendlandC2 {
+            h: smoothing_radius,
+            dim: 3,
+            w_const: real::<T>(21.0 / (2.0 * f64::consts::PI)) / smoothing_radius.powi(3),
+        }
+    }
+
+    pub fn new_2d(smoothing_radius: T) -> Self {
+        WendlandC2 {
+            h: smoothing_radius,
+            dim: 2,
+            w_const: real::<T>(7.0 / f64::consts::PI) / smoothing_radius.powi(2),
+        }
+    }
+}
+
+impl<T: Real> RadialKernel<T> for WendlandC2<T> {
+    fn w_q(&self, q: T) -> T {
+        self.w_const * (T::one() - q).powi(4) * (T::one() + real::<T>(4.0) * q)
+    }
+
+    fn dw_q(&self, q: T) -> T {
+        real::<T>(-20.0) * self.w_const * (T::one() - q).powi(3)
+    }
+
+    fn d2w_q(&self, q: T) -> T {
+        real::<T>(-20.0) * self.w_const * (T::one() - q).powi(2) * (T::one() - real::<T>(4.0) * q)
+    }
+}
+
+impl_radial_kernel!(WendlandC2);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check normalization and derivatives against numerical integration and differentiation.
+    fn check_kernel<K: SmoothingKernel<f64>>(kernel: K, dim: usize) {
+        let h = kernel.support();
+        let n = 20000;
+        let dr = h / n as f64;
+
+        let integral = (0..n).fold(0.0, |sum, i| {
+            let r = (i as f64 + 0.5) * dr;
+            let shell = if dim == 2 { 2.0 * f64::consts::PI * r } else { 4.0 * f64::consts::PI * r * r };
+            sum + kernel.w(r) * shell * dr
+        });
+        assert!((integral - 1.0).abs() < 1.0e-4, "{}", integral);
+
+        let eps = 1.0e-5 * h;
+        for &q in &[0.2, 0.45, 0.6, 0.9] {
+            let r = q * h;
+            let dw = (kernel.w(r + eps) - kernel.w(r - eps)) / (2.0 * eps);
+            let d2w = (kernel.w(r + eps) - 2.0 * kernel.w(r) + kernel.w(r - eps)) / (eps * eps);
+            let laplace = d2w + (dim - 1) as f64 * dw / r;
+
+            let scale = kernel.w(0.0) / (h * h);
+            assert!((kernel.grad_w(r) * r - dw).abs() < 1.0e-4 * scale);
+            assert!((kernel.laplace_w(r) - laplace).abs() < 1.0e-2 * scale);
+        }
+        assert_eq!(kernel.w(h), 0.0);
+
+        let radii: Vec<f64> = (0..37).map(|i| i as f64 * h / 32.0).collect();
+        let mut batch = vec![0.0; radii.len()];
+        kernel.w_batch(&mut batch, &radii);
+        for (&w, &r) in batch.iter().zip(radii.iter()) {
+            assert!((w - kernel.w(r)).abs() < 1.0e-12 * kernel.w(0.0));
+        }
+    }
+
+    #[test]
+    fn kernel_normalization() {
+        let h = 0.7;
+        check_kernel(Poly6::new(h), 3);
+        check_kernel(Poly6::new_2d(h), 2);
+        check_kernel(Spiky::new(h), 3);
+        check_kernel(Spiky::new_2d(h), 2);
+        check_kernel(CubicSpline::new(h), 3);
+        check_kernel(CubicSpline::new_2d(h), 2);
+        check_kernel(QuinticSpline::new(h), 3);
+        check_kernel(QuinticSpline::new_2d(h), 2);
+        check_kernel(WendlandC2::new(h), 3);
+        check_kernel(WendlandC2::new_2d(h), 2);
+    }
+}
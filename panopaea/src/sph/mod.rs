@@ -7,13 +7,37 @@
 //!             In Proceedings of the 2003 ACM SIGGRAPH/Eurographics symposium on Computer animation (SCA '03),
 //!             Eurographics Association, Aire-la-Ville, Switzerland, Switzerland, 154-159
 
+pub mod boundary;
+pub mod dfsph;
+pub mod emitter;
 pub mod grid;
+pub mod hash_grid;
+pub mod iisph;
+pub mod isph;
+pub mod kd_tree;
 pub mod kernel;
+pub mod multiphase;
+pub mod neighbor;
+pub mod pcisph;
+pub mod rheology;
+pub mod rigid;
+pub mod solver;
+pub mod sort;
+pub mod surface_tension;
+pub mod viscosity;
 pub mod wcsph;
 
+use cgmath::MetricSpace;
 use math::{Real, Dim};
-use particle::{Processor, Property};
+use math::integration::Integrator;
+use particle::{Particles, Processor, Property};
 use rayon::prelude::*;
+use typenum::U2;
+
+use self::grid::BoundedGrid;
+use self::kernel::SmoothingKernel;
+use self::neighbor::NeighborSearch;
+use self::sort::ParticleSort;
 
 pub mod property {
     //! Common particle properties
@@ -67,6 +91,15 @@ pub mod property {
             T::zero()
         }
     }
+
+    /// Index of the fluid phase for multiphase simulations.
+    pub struct Phase(pub usize);
+    impl Property for Phase {
+        type Subtype = usize;
+        fn new() -> Self::Subtype {
+            0
+        }
+    }
 }
 
 // TODO: move this into Particles to allow reseting all kind of properties
@@ -77,3 +110,93 @@ pub fn reset_acceleration<T, N>(p: &Processor)
     let mut accel = p.write_property::<Acceleration<T, N>>();
     accel.par_iter_mut().for_each(|mut a| *a = Acceleration::<T, N>::new() );
 }
+
+/// Advance positions and velocities with the accelerations of the current step.
+///
+/// Accelerations are held constant over the timestep.
+pub fn integrate<T, N, I>(p: &Processor, integrator: &I, timestep: T)
+    where T: Real + 'static,
+          N: Dim<T>,
+          I: Integrator<T> + Sync,
+{
+    use self::property::{Acceleration, Position, Velocity};
+    let (positions, velocities, accels) = (
+        p.write_property::<Position<T, N>>(),
+        p.write_property::<Velocity<T, N>>(),
+        p.read_property::<Acceleration<T, N>>(),
+    );
+
+    positions.par_iter_mut().zip(velocities.par_iter_mut()).zip(accels.par_iter()).for_each(|((pos, vel), accel)| {
+        let (x, v) = integrator.step_second_order(pos, vel, T::zero(), timestep, |_, _, _| accel.clone());
+        *pos = x;
+        *vel = v;
+    });
+}
+
+/// Sort particles by cell key and rebuild the cell ranges of the grid.
+///
+/// All properties and attributes of the particles are reordered.
+pub fn sort_particles<T, N>(particles: &mut Particles, grid: &mut BoundedGrid<T, N>, sort: &mut ParticleSort)
+    where T: Real,
+          N: Dim<T> + Dim<usize> + Dim<(usize, usize)>,
+{
+    use self::property::Position;
+    sort.sort(particles.read_property::<Position<T, N>>(), grid.num_keys(), |pos| grid.get_key(pos));
+    particles.reorder(sort.order());
+    // an empty particle set, e.g. after sinks removed all particles, leaves all cells empty
+    grid.par_construct_ranges(particles.read_property::<Position<T, N>>())
+        .expect("particles are sorted by cell");
+}
+
+/// Density summation including the particle itself.
+///
+/// Particles outside of the grid keep their density.
+///
+/// Ref: [MDM03] Eq. 3
+pub fn density_summation<T, K>(p: &Processor, kernel: &K, grid: &BoundedGrid<T, U2>)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    use self::property::{Density, Mass, Position};
+    let (densities, positions, masses) = (
+        p.write_property::<Density<T>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    par_azip!(mut density (densities), pos (positions) in {
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        let mut d = T::zero();
+        grid.for_each_neighbor(cell, 1, |j| {
+            d += masses[j] * kernel.w(pos.distance(positions[j]));
+        });
+
+        *density = d;
+    });
+}
+
+/// Density summation including the particle itself, with any neighbor search built from the current positions.
+///
+/// Ref: [MDM03] Eq. 3
+pub fn density_summation_with<T, N, K, G>(p: &Processor, kernel: &K, search: &G)
+    where T: Real + 'static,
+          N: Dim<T>,
+          K: SmoothingKernel<T> + Sync,
+          G: NeighborSearch<T, N>,
+{
+    use self::property::{Density, Mass};
+    let (densities, masses) = (
+        p.write_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    densities.par_iter_mut().enumerate().for_each(|(i, density)| {
+        let pos = search.position(i);
+        let mut d = T::zero();
+        search.for_each_neighbor(i, kernel.support(), |j| {
+            d += masses[j] * kernel.w(pos.clone().distance(search.position(j).clone()));
+        });
+        *density = d;
+    });
+}
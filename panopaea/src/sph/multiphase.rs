@@ -0,0 +1,257 @@
+//! Multiphase fluids with density contrast
+//!
+//! Each particle belongs to a phase with its own rest density, given by the
+//! `Phase` property. Densities are computed from the number density
+//! `δ_i = Σ W_ij` as `ρ_i = m_i δ_i`, which avoids the smoothing of the density
+//! across interfaces of the standard summation and the resulting spurious
+//! interface tension for high density ratios.
+//!
+//! Interface tension is modeled by a continuum surface force on the color
+//! field of each phase, which also acts at free surfaces.
+//!
+//! References:
+//!     [SP08] Barbara Solenthaler and Renato Pajarola, 2008,
+//!            Density contrast SPH interfaces,
+//!            In Proceedings of the 2008 ACM SIGGRAPH/Eurographics Symposium on Computer Animation (SCA '08),
+//!            Eurographics Association, 211-218
+//!     [MSKG05] Matthias Müller, Barbara Solenthaler, Richard Keiser, and Markus Gross, 2005,
+//!              Particle-based fluid-fluid interaction,
+//!              In Proceedings of the 2005 ACM SIGGRAPH/Eurographics Symposium on Computer Animation (SCA '05),
+//!              ACM, 237-244
+
+use cgmath::{InnerSpace, MetricSpace};
+use math::{Real, VectorN};
+use num::Zero;
+use particle::{Particles, Processor};
+use typenum::U2;
+
+use super::boundary::BoundaryParticles;
+use super::grid::BoundedGrid;
+use super::kernel::SmoothingKernel;
+use super::property::*;
+
+/// Register the phase property in addition to the properties of the solver.
+pub fn init(particles: &mut Particles) {
+    particles.add_property::<Phase>();
+}
+
+/// Densities `ρ_i = m_i Σ_j W_ij` including the boundary contribution `ρ0_i Σ_b V_b W_ib`.
+///
+/// Ref: [SP08] Eq. 4, 5
+pub fn density<T, K>(p: &Processor, kernel: &K, grid: &BoundedGrid<T, U2>, boundary: &BoundaryParticles<T>, rest_densities: &[T])
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (densities, positions, masses, phases) = (
+        p.write_property::<Density<T>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Mass<T>>(),
+        p.read_property::<Phase>(),
+    );
+
+    par_azip!(mut density (densities), pos (positions), mass (masses), phase (phases) in {
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        let mut number_density = T::zero();
+        grid.for_each_neighbor(cell, 1, |j| {
+            number_density += kernel.w(pos.distance(positions[j]));
+        });
+
+        let mut boundary_density = T::zero();
+        boundary.for_each_neighbor(&pos, |b, volume| {
+            boundary_density += volume * kernel.w(pos.distance(*b));
+        });
+
+        *density = mass * number_density + rest_densities[phase] * boundary_density;
+    });
+}
+
+/// Tait equation with the rest density of each phase, stiffness `B` is given for `rest_density`.
+///
+/// The speed of sound is the same for all phases.
+pub fn tait_pressure<T>(p: &Processor, rest_densities: &[T], (rest_density, stiffness, exponent): (T, T, T))
+    where T: Real + 'static,
+{
+    let (pressures, densities, phases) = (
+        p.write_property::<Pressure<T>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Phase>(),
+    );
+
+    par_azip!(mut pressure (pressures), density (densities), phase (phases) in {
+        let rest = rest_densities[phase];
+        let stiffness = stiffness * rest / rest_density;
+        *pressure = (stiffness * ((density / rest).powf(exponent) - T::one())).max(T::zero());
+    });
+}
+
+/// Overwrite the accelerations with gravity, pressure forces and laminar viscosity.
+///
+/// Pressure accelerations `-1 / m_i Σ_j (p_i / δ_i^2 + p_j / δ_j^2) ∇W_ij` use the number densities `δ = ρ / m`.
+/// Boundary particles contribute `-Ψ_b p_i / ρ_i^2 ∇W_ib` with the rest density of the particle.
+///
+/// Ref: [SP08] Eq. 8, [AIA12] Eq. 10
+pub fn pressure_viscosity_forces<T, K>(
+    p: &Processor,
+    kernel: &K,
+    grid: &BoundedGrid<T, U2>,
+    boundary: &BoundaryParticles<T>,
+    rest_densities: &[T],
+    viscosity: T,
+    gravity: VectorN<T, U2>,
+)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (accels, positions, velocities, densities, pressures, masses, phases) = (
+        p.write_property::<Acceleration<T, U2>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Velocity<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Pressure<T>>(),
+        p.read_property::<Mass<T>>(),
+        p.read_property::<Phase>(),
+    );
+
+    // `2 (d + 2) ν` and regularization of the distance
+    let viscous = T::new(8.0) * viscosity;
+    let eta = T::new(0.01) * kernel.support().powi(2);
+
+    par_azip!(
+        index i,
+        mut accel (accels),
+        pos (positions),
+        vel (velocities),
+        density (densities),
+        pressure (pressures),
+    in {
+        *accel = gravity;
+        let (mass, phase) = (masses[i], phases[i]);
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+        let number_density = density / mass;
+        let pressure_i = pressure / (number_density * number_density);
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let r = pos - positions[j];
+            let dist = pos.distance(positions[j]);
+            let grad = r * kernel.grad_w(dist);
+
+            let number_density_j = densities[j] / masses[j];
+            let pressure_j = pressures[j] / (number_density_j * number_density_j);
+            *accel -= grad * ((pressure_i + pressure_j) / mass);
+
+            let v = vel - velocities[j];
+            *accel += grad * (viscous * masses[j] / densities[j] * v.dot(r) / (dist * dist + eta));
+        });
+
+        let boundary_pressure = rest_densities[phase] * pressure / (density * density);
+        boundary.for_each_neighbor(&pos, |b, volume| {
+            let grad = (pos - *b) * kernel.grad_w(pos.distance(*b));
+            *accel -= grad * (volume * boundary_pressure);
+        });
+    });
+}
+
+/// Interface tension `a_i = -σ / ρ_i ∇²c_i n_i / |n_i|` on the color field `c` of the own phase.
+///
+/// The color gradient `n_i = Σ_j V_j ∇W_ij` and laplacian only include neighbors of the same phase,
+/// particles with `|n_i| < 0.1 / h` are away from interfaces. `normals` is a buffer with one entry per particle.
+///
+/// Ref: [MSKG05] Sec. 6
+pub fn interface_tension<T, K>(
+    p: &Processor,
+    normals: &mut [VectorN<T, U2>],
+    coefficient: T,
+    kernel: &K,
+    grid: &BoundedGrid<T, U2>,
+)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (accels, positions, densities, masses, phases) = (
+        p.write_property::<Acceleration<T, U2>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+        p.read_property::<Phase>(),
+    );
+
+    par_azip!(index i, mut normal (&mut normals[..]), pos (positions), phase (phases) in {
+        *normal = VectorN::zero();
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i || phases[j] != phase { return }
+            let grad = (pos - positions[j]) * kernel.grad_w(pos.distance(positions[j]));
+            *normal += grad * (masses[j] / densities[j]);
+        });
+    });
+
+    let threshold = T::new(0.1) / kernel.support();
+    let normals = &normals[..];
+
+    par_azip!(index i, mut accel (accels), pos (positions), density (densities), phase (phases) in {
+        let normal = normals[i];
+        let length = normal.magnitude();
+        if length < threshold { return }
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        let mut laplacian = masses[i] / density * kernel.laplace_w(T::zero());
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i || phases[j] != phase { return }
+            laplacian += masses[j] / densities[j] * kernel.laplace_w(pos.distance(positions[j]));
+        });
+
+        *accel -= normal * (coefficient * laplacian / (density * length));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use math::vector_n::vec2;
+    use sph::wcsph::{self, Wcsph};
+    use super::*;
+
+    #[test]
+    fn density_contrast() {
+        // light fluid on top of a fluid with twice the density
+        let (spacing, rest_densities) = (0.1, [1000.0, 500.0]);
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+        init(&mut particles);
+        {
+            let (mut positions, mut masses, mut phases) = (Vec::new(), Vec::new(), Vec::new());
+            for y in 0..20 {
+                for x in 0..20 {
+                    let phase = if y < 10 { 0 } else { 1 };
+                    positions.push(vec2(1.0 + x as f64 * spacing, 1.0 + y as f64 * spacing));
+                    masses.push(rest_densities[phase] * spacing * spacing);
+                    phases.push(phase);
+                }
+            }
+            particles.add_particles(positions.len())
+                     .with::<Position<f64, U2>>(&positions)
+                     .with::<Mass<f64>>(&masses)
+                     .with::<Phase>(&phases);
+        }
+
+        let mut solver = Wcsph::new(vec2(30, 30), 2.0 * spacing, rest_densities[0]);
+        solver.pipeline.gravity = vec2(0.0, 0.0);
+        solver.phase_densities = Some(rest_densities.to_vec());
+        solver.interface_tension = Some(0.1);
+        solver.step(&mut particles, 1.0e-4);
+
+        // both sides of the interface are close to their rest densities
+        let positions = particles.read_property::<Position<f64, U2>>();
+        let densities = particles.read_property::<Density<f64>>();
+        let phases = particles.read_property::<Phase>();
+        for &(y, phase) in &[(9.0, 0), (10.0, 1)] {
+            let center = vec2(1.0 + 9.0 * spacing, 1.0 + y * spacing);
+            let i = (0..positions.len()).find(|&i| positions[i].distance(center) < 1.0e-3).unwrap();
+            assert_eq!(phases[i], phase);
+            let rest = rest_densities[phase];
+            assert!((densities[i] - rest).abs() < 0.05 * rest, "{}", densities[i]);
+        }
+    }
+}
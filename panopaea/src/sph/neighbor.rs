@@ -0,0 +1,99 @@
+//! Neighbor search abstraction
+//!
+//! Common interface of the acceleration structures for fixed radius queries,
+//! implemented by the uniform `BoundedGrid`, the unbounded `HashedGrid` and
+//! the `KdTree`. Solvers written against `NeighborSearch` work with any of them.
+//!
+//! `build` keeps its own copy of the positions in the structure's preferred
+//! order, neighbors are reported as indices into the positions passed to it.
+//! The particles themselves don't need to be sorted, though sorting them by
+//! cell beforehand (`sort_particles`) improves the memory locality.
+
+use math::{Dim, Real, VectorN};
+use rayon::prelude::*;
+use super::grid::NeighborList;
+
+pub trait NeighborSearch<S: Real, N: Dim<S>>: Sync {
+    /// Rebuild the structure for a new set of particle positions.
+    fn build(&mut self, positions: &[VectorN<S, N>]);
+
+    /// Number of particles of the last `build`.
+    fn num_particles(&self) -> usize;
+
+    /// Position of a particle of the last `build`.
+    fn position(&self, particle: usize) -> &VectorN<S, N>;
+
+    /// Apply function to each particle within `radius` of a position.
+    fn for_each_within<F>(&self, position: &VectorN<S, N>, radius: S, fnc: F)
+        where F: FnMut(usize);
+
+    /// Apply function to each particle within `radius` of a particle, including itself.
+    fn for_each_neighbor<F>(&self, particle: usize, radius: S, fnc: F)
+        where F: FnMut(usize)
+    {
+        self.for_each_within(self.position(particle), radius, fnc);
+    }
+
+    /// Apply function to all pairs `(i, j)` of neighboring particles, in parallel over `i`.
+    fn par_for_each_neighbor<F>(&self, radius: S, fnc: F)
+        where F: Fn(usize, usize) + Sync
+    {
+        (0..self.num_particles()).into_par_iter().for_each(|i| {
+            self.for_each_neighbor(i, radius, |j| fnc(i, j));
+        });
+    }
+
+    /// Build the neighbor lists of all particles in parallel.
+    fn par_neighbor_list(&self, radius: S) -> NeighborList {
+        NeighborList::build(self.num_particles(), |i, neighbors| {
+            self.for_each_neighbor(i, radius, |j| neighbors.push(j));
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::MetricSpace;
+    use generic_array::typenum::U2;
+    use math::rng::{Pcg32, DEFAULT_SEED};
+    use math::vector_n::vec2;
+    use rand::Rng;
+    use sph::grid::BoundedGrid;
+    use sph::hash_grid::HashedGrid;
+    use sph::kd_tree::KdTree;
+    use super::*;
+
+    fn sorted_neighbors<G: NeighborSearch<f64, U2>>(search: &G, radius: f64) -> Vec<Vec<usize>> {
+        let list = search.par_neighbor_list(radius);
+        (0..list.len())
+            .map(|i| {
+                let mut neighbors = list.neighbors(i).to_vec();
+                neighbors.sort();
+                neighbors
+            })
+            .collect()
+    }
+
+    #[test]
+    fn neighbor_search_structures_agree() {
+        let mut rng = Pcg32::new(DEFAULT_SEED);
+        let positions = (0..300).map(|_| vec2(rng.gen::<f64>() * 4.0, rng.gen::<f64>() * 2.0)).collect::<Vec<_>>();
+        let radius = 0.3;
+
+        let expected = (0..positions.len())
+            .map(|i| (0..positions.len()).filter(|&j| positions[i].distance(positions[j]) < radius).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut grid = BoundedGrid::<f64, U2>::new(vec2(20, 10), 0.2);
+        NeighborSearch::build(&mut grid, &positions);
+        assert_eq!(sorted_neighbors(&grid, radius), expected);
+
+        let mut hashed = HashedGrid::<f64, U2>::new(0.25);
+        NeighborSearch::build(&mut hashed, &positions);
+        assert_eq!(sorted_neighbors(&hashed, radius), expected);
+
+        let mut tree = KdTree::<f64, U2>::new();
+        NeighborSearch::build(&mut tree, &positions);
+        assert_eq!(sorted_neighbors(&tree, radius), expected);
+    }
+}
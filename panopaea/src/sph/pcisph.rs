@@ -0,0 +1,155 @@
+//! Predictive-Corrective Incompressible SPH (PCISPH)
+//!
+//! Predicts positions and densities with the current pressure accelerations
+//! and increases the pressures proportional to the predicted compression,
+//! until the average density error drops below the threshold. The scaling
+//! factor `δ` is computed for each particle instead of a prototype particle
+//! with a filled neighborhood, which also covers particles at free surfaces
+//! and boundaries.
+//!
+//! References:
+//!     [SP09] Barbara Solenthaler and Renato Pajarola, 2009,
+//!            Predictive-corrective incompressible SPH,
+//!            ACM Trans. Graph. 28, 3, Article 40
+
+use cgmath::{InnerSpace, MetricSpace};
+use math::{Real, VectorN};
+use num::Zero;
+use particle::Processor;
+use typenum::U2;
+
+use super::isph::{pressure_accelerations, PressureSolver};
+use super::kernel::SmoothingKernel;
+use super::property::*;
+use super::solver::Fluid;
+
+/// PCISPH pressure solver for `Isph`.
+pub struct Pcisph<T: Real> {
+    /// Maximum average density error relative to the rest density.
+    pub threshold: T,
+    pub max_iterations: usize,
+
+    delta: Vec<T>,
+    predicted: Vec<VectorN<T, U2>>,
+    errors: Vec<T>,
+    accels: Vec<VectorN<T, U2>>,
+}
+
+impl<T: Real> Pcisph<T> {
+    pub fn new() -> Self {
+        Pcisph {
+            threshold: T::new(1.0e-3),
+            max_iterations: 100,
+
+            delta: Vec::new(),
+            predicted: Vec::new(),
+            errors: Vec::new(),
+            accels: Vec::new(),
+        }
+    }
+}
+
+impl<T: Real> PressureSolver<T> for Pcisph<T> {
+    /// Pressures start at zero each step.
+    ///
+    /// Ref: [SP09] Alg. 2
+    fn solve(&mut self, p: &Processor, fluid: &Fluid<T>, timestep: T) -> usize {
+        let num_particles = p.read_property::<Mass<T>>().len();
+        self.delta.resize(num_particles, T::zero());
+        self.predicted.resize(num_particles, VectorN::zero());
+        self.errors.resize(num_particles, T::zero());
+        self.accels.resize(num_particles, VectorN::zero());
+
+        let (grid, kernel, boundary, rest_density) = (fluid.grid, fluid.kernel, fluid.boundary, fluid.rest_density);
+        let (delta, predicted, errors, accels) = (&mut self.delta, &mut self.predicted, &mut self.errors, &mut self.accels);
+        let (positions, velocities, masses) = (
+            p.read_property::<Position<T, U2>>(),
+            p.read_property::<Velocity<T, U2>>(),
+            p.read_property::<Mass<T>>(),
+        );
+        let (boundary_positions, boundary_velocities, volumes) = (boundary.positions(), boundary.velocities(), boundary.volumes());
+
+        // `δ_i = ρ0² / (2 dt² (|Σ m_j ∇W_ij + Σ Ψ_b ∇W_ib|² + Σ |m_j ∇W_ij|²))`
+        //
+        // Ref: [SP09] Eq. 8
+        par_azip!(index i, mut delta (&mut delta[..]), pos (positions) in {
+            *delta = T::zero();
+            let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+            let mut sum = VectorN::<T, U2>::zero();
+            let mut sum_squared = T::zero();
+            grid.for_each_neighbor(cell, 1, |j| {
+                if j == i { return }
+                let grad = (pos - positions[j]) * (masses[j] * kernel.grad_w(pos.distance(positions[j])));
+                sum += grad;
+                sum_squared += grad.magnitude2();
+            });
+            boundary.for_each_neighbor(&pos, |b, volume| {
+                sum += (pos - *b) * (rest_density * volume * kernel.grad_w(pos.distance(*b)));
+            });
+
+            let denominator = T::new(2.0) * timestep * timestep * (sum.magnitude2() + sum_squared);
+            if denominator > T::zero() {
+                *delta = rest_density * rest_density / denominator;
+            }
+        });
+
+        {
+            let pressures = p.write_property::<Pressure<T>>();
+            par_azip!(mut pressure (pressures) in { *pressure = T::zero(); });
+        }
+        for accel in accels.iter_mut() {
+            *accel = VectorN::zero();
+        }
+
+        let threshold = self.threshold * rest_density;
+        let mut iterations = 0;
+        loop {
+            {
+                let accels = &accels[..];
+                par_azip!(mut predicted (&mut predicted[..]), pos (positions), vel (velocities), accel (accels) in {
+                    *predicted = pos + (vel + accel * timestep) * timestep;
+                });
+            }
+
+            // `ρ*_i - ρ0` at the predicted positions, neighborhoods are kept from the start of the step
+            {
+                let predicted = &predicted[..];
+                par_azip!(index i, mut error (&mut errors[..]), pos (positions) in {
+                    *error = T::zero();
+                    let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+                    let x = predicted[i];
+
+                    let mut density = masses[i] * kernel.w(T::zero());
+                    grid.for_each_neighbor(cell, 1, |j| {
+                        if j == i { return }
+                        density += masses[j] * kernel.w(x.distance(predicted[j]));
+                    });
+                    boundary.for_each_neighbor_index(&pos, |b| {
+                        let boundary_position = boundary_positions[b] + boundary_velocities[b] * timestep;
+                        density += rest_density * volumes[b] * kernel.w(x.distance(boundary_position));
+                    });
+                    *error = (density - rest_density).max(T::zero());
+                });
+            }
+
+            let error = errors.iter().fold(T::zero(), |sum, &e| sum + e) / T::new(num_particles.max(1));
+            if (iterations >= 2 && error <= threshold) || iterations >= self.max_iterations {
+                break;
+            }
+
+            {
+                let pressures = p.write_property::<Pressure<T>>();
+                par_azip!(mut pressure (pressures), delta (&delta[..]), error (&errors[..]) in {
+                    *pressure += delta * error;
+                });
+            }
+            pressure_accelerations(p, fluid, accels);
+            iterations += 1;
+        }
+
+        let velocities = p.write_property::<Velocity<T, U2>>();
+        par_azip!(mut vel (velocities), accel (&accels[..]) in { *vel += accel * timestep; });
+        iterations
+    }
+}
@@ -0,0 +1,269 @@
+//! Rigid bodies coupled with the fluid
+//!
+//! Rigid bodies are represented by boundary particles sampled on their
+//! surface, which move with the body. Fluid particles see them like static
+//! boundary particles, the reaction forces of the pressure are accumulated
+//! into forces and torques acting on the bodies.
+//!
+//! Bodies collide with the walls of the domain, other contacts are not resolved.
+//!
+//! References:
+//!     [AIA12] Nadir Akinci, Markus Ihmsen, Gizem Akinci, Barbara Solenthaler, and Matthias Teschner, 2012,
+//!             Versatile rigid-fluid coupling for incompressible SPH,
+//!             ACM Trans. Graph. 31, 4, Article 62
+
+use cgmath::{InnerSpace, MetricSpace};
+use math::{Real, VectorN};
+use math::integration::{Integrator, SymplecticEuler};
+use math::vector_n::vec2;
+use particle::Processor;
+use typenum::U2;
+
+use super::boundary::{BoundaryParticles, BoundarySampler};
+use super::kernel::SmoothingKernel;
+use super::property::{Mass, Position};
+
+/// Rigid body in 2d with a surface sampled by boundary particles.
+#[derive(Clone, Debug)]
+pub struct RigidBody<T: Real> {
+    pub mass: T,
+    /// Moment of inertia around the center of mass.
+    pub inertia: T,
+    /// Center of mass.
+    pub position: VectorN<T, U2>,
+    /// Counterclockwise rotation in radians.
+    pub rotation: T,
+    pub velocity: VectorN<T, U2>,
+    pub angular_velocity: T,
+    /// Coefficient of restitution for wall collisions.
+    pub restitution: T,
+    /// Kinematic bodies move with their velocities and aren't affected by forces.
+    pub kinematic: bool,
+
+    /// Surface samples relative to the center of mass at zero rotation.
+    samples: Vec<VectorN<T, U2>>,
+    force: VectorN<T, U2>,
+    torque: T,
+}
+
+impl<T: Real> RigidBody<T> {
+    /// Body at rest with the surface samples given in world space.
+    pub fn new(mass: T, inertia: T, position: VectorN<T, U2>, samples: Vec<VectorN<T, U2>>) -> Self {
+        let samples = samples.into_iter().map(|s| s - position).collect();
+        RigidBody {
+            mass,
+            inertia,
+            position,
+            rotation: T::zero(),
+            velocity: vec2(T::zero(), T::zero()),
+            angular_velocity: T::zero(),
+            restitution: T::new(0.5),
+            kinematic: false,
+            samples,
+            force: vec2(T::zero(), T::zero()),
+            torque: T::zero(),
+        }
+    }
+
+    /// Solid disk with uniform density.
+    pub fn circle(center: VectorN<T, U2>, radius: T, density: T, sampler: &BoundarySampler<T>) -> Self {
+        let mass = density * T::new(::std::f64::consts::PI) * radius * radius;
+        let inertia = T::new(0.5) * mass * radius * radius;
+        RigidBody::new(mass, inertia, center, sampler.circle(center, radius))
+    }
+
+    /// Solid axis aligned box with uniform density.
+    pub fn rectangle(min: VectorN<T, U2>, max: VectorN<T, U2>, density: T, sampler: &BoundarySampler<T>) -> Self {
+        let size = max - min;
+        let mass = density * size[0] * size[1];
+        let inertia = mass * (size[0] * size[0] + size[1] * size[1]) / T::new(12.0);
+        RigidBody::new(mass, inertia, (min + max) * T::new(0.5), sampler.rectangle(min, max))
+    }
+
+    /// Current positions of the surface samples.
+    pub fn samples(&self) -> Vec<VectorN<T, U2>> {
+        let (sin, cos) = self.rotation.sin_cos();
+        self.samples.iter().map(|s| {
+            self.position + vec2(cos * s[0] - sin * s[1], sin * s[0] + cos * s[1])
+        }).collect()
+    }
+
+    /// Velocity of the body at a point in world space.
+    pub fn velocity_at(&self, point: &VectorN<T, U2>) -> VectorN<T, U2> {
+        let r = *point - self.position;
+        self.velocity + vec2(-r[1], r[0]) * self.angular_velocity
+    }
+
+    /// Accumulate a force acting at a point in world space until the next `integrate`.
+    pub fn apply_force(&mut self, force: VectorN<T, U2>, point: &VectorN<T, U2>) {
+        self.torque += cross(*point - self.position, force);
+        self.force += force;
+    }
+
+    /// Accumulated force and torque.
+    pub fn force(&self) -> (VectorN<T, U2>, T) {
+        (self.force, self.torque)
+    }
+
+    /// Advance the body with the accumulated forces and gravity, the forces are reset afterwards.
+    pub fn integrate(&mut self, gravity: VectorN<T, U2>, timestep: T) {
+        let (accel, angular_accel) = if self.kinematic {
+            (vec2(T::zero(), T::zero()), T::zero())
+        } else {
+            (self.force / self.mass + gravity, self.torque / self.inertia)
+        };
+
+        let (position, velocity) = SymplecticEuler.step_second_order(
+            &self.position, &self.velocity, T::zero(), timestep, |_, _, _| accel);
+        let (rotation, angular_velocity) = SymplecticEuler.step_second_order(
+            &self.rotation, &self.angular_velocity, T::zero(), timestep, |_, _, _| angular_accel);
+        self.position = position;
+        self.velocity = velocity;
+        self.rotation = rotation;
+        self.angular_velocity = angular_velocity;
+
+        self.force = vec2(T::zero(), T::zero());
+        self.torque = T::zero();
+    }
+
+    /// Push the body out of the walls of the box `[min, max]` and reflect its velocity.
+    ///
+    /// The deepest surface sample behind each wall receives an impulse along the wall normal.
+    pub fn collide_walls(&mut self, min: VectorN<T, U2>, max: VectorN<T, U2>) {
+        if self.kinematic {
+            return;
+        }
+
+        for axis in 0..2 {
+            for &(wall, sign) in &[(min[axis], T::one()), (max[axis], -T::one())] {
+                let deepest = self.samples().into_iter()
+                    .map(|s| ((wall - s[axis]) * sign, s))
+                    .filter(|&(depth, _)| depth > T::zero())
+                    .fold(None, |deepest: Option<(T, VectorN<T, U2>)>, (depth, s)| match deepest {
+                        Some((max, _)) if max >= depth => deepest,
+                        _ => Some((depth, s)),
+                    });
+                let (depth, contact) = if let Some(deepest) = deepest { deepest } else { continue };
+
+                let mut normal = vec2(T::zero(), T::zero());
+                normal[axis] = sign;
+                let r = contact - self.position;
+                let normal_velocity = self.velocity_at(&contact).dot(normal);
+                self.position += normal * depth;
+
+                if normal_velocity < T::zero() {
+                    let rn = cross(r, normal);
+                    let impulse = -(T::one() + self.restitution) * normal_velocity
+                        / (T::one() / self.mass + rn * rn / self.inertia);
+                    self.velocity += normal * (impulse / self.mass);
+                    self.angular_velocity += rn * impulse / self.inertia;
+                }
+            }
+        }
+    }
+}
+
+fn cross<T: Real>(a: VectorN<T, U2>, b: VectorN<T, U2>) -> T {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+/// Accumulate the reaction forces `m_i Ψ_b f_i ∇W_ib` of the fluid on the rigid bodies.
+///
+/// `f_i` is the pressure term of the fluid particle `i`, e.g. `p_i / ρ_i^2` for WCSPH.
+///
+/// Ref: [AIA12] Eq. 11
+pub fn fluid_forces<T, K, F>(
+    p: &Processor,
+    kernel: &K,
+    boundary: &BoundaryParticles<T>,
+    rest_density: T,
+    bodies: &mut [RigidBody<T>],
+    pressure_term: F,
+)
+    where T: Real + 'static,
+          K: SmoothingKernel<T>,
+          F: Fn(usize) -> T,
+{
+    if bodies.is_empty() {
+        return;
+    }
+
+    let (positions, masses) = (
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Mass<T>>(),
+    );
+    let (boundary_positions, volumes, owners) = (boundary.positions(), boundary.volumes(), boundary.bodies());
+
+    for i in 0..positions.len() {
+        let pos = positions[i];
+        let factor = masses[i] * pressure_term(i) * rest_density;
+        if factor == T::zero() {
+            continue;
+        }
+        boundary.for_each_neighbor_index(&pos, |b| {
+            if let Some(k) = owners[b] {
+                let grad = (pos - boundary_positions[b]) * kernel.grad_w(pos.distance(boundary_positions[b]));
+                bodies[k].apply_force(grad * (factor * volumes[b]), &boundary_positions[b]);
+            }
+        });
+    }
+}
+
+/// Integrate all bodies and resolve collisions with the domain walls.
+pub fn step_bodies<T: Real>(bodies: &mut [RigidBody<T>], gravity: VectorN<T, U2>, domain: (VectorN<T, U2>, VectorN<T, U2>), timestep: T) {
+    for body in bodies {
+        body.integrate(gravity, timestep);
+        body.collide_walls(domain.0, domain.1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use particle::Particles;
+    use sph::property::{Mass, Velocity};
+    use sph::wcsph::{self, Wcsph};
+    use super::*;
+
+    #[test]
+    fn rigid_fluid_coupling() {
+        // falling box comes to rest on the floor
+        let sampler = BoundarySampler::new(0.05);
+        let mut bodies = vec![RigidBody::rectangle(vec2(0.4f64, 0.5), vec2(0.8, 0.7), 500.0, &sampler)];
+        bodies[0].restitution = 0.0;
+        bodies[0].rotation = 0.1;
+        for _ in 0..500 {
+            step_bodies(&mut bodies, vec2(0.0, -9.81), (vec2(0.0, 0.0), vec2(2.0, 2.0)), 1.0e-3);
+        }
+        assert!(bodies[0].samples().iter().all(|s| s[1] > -1.0e-3));
+        assert!(bodies[0].velocity[1].abs() < 0.5);
+
+        // compressed fluid pushes the body, momentum is exchanged
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+        {
+            let mut positions = Vec::new();
+            for y in 0..8 {
+                for x in 0..8 {
+                    positions.push(vec2(0.5 + x as f64 * 0.9 * spacing, 0.5 + y as f64 * 0.9 * spacing));
+                }
+            }
+            let masses = vec![rest_density * spacing * spacing; positions.len()];
+            particles.add_particles(positions.len())
+                     .with::<Position<f64, U2>>(&positions)
+                     .with::<Mass<f64>>(&masses);
+        }
+
+        let mut solver = Wcsph::new(vec2(20, 20), 2.0 * spacing, rest_density);
+        solver.pipeline.gravity = vec2(0.0, 0.0);
+        solver.pipeline.bodies.push(RigidBody::circle(vec2(1.4, 0.8), 0.3, 500.0, &BoundarySampler::new(0.5 * spacing)));
+        solver.step(&mut particles, 1.0e-4);
+
+        let body = &solver.pipeline.bodies[0];
+        let momentum = particles.read_property::<Velocity<f64, U2>>().iter()
+            .zip(particles.read_property::<Mass<f64>>().iter())
+            .fold(body.velocity * body.mass, |sum, (&v, &m)| sum + v * m);
+        assert!(body.velocity[0] > 0.0);
+        assert!(momentum.magnitude() < 1.0e-6 * body.mass * body.velocity.magnitude(), "{:?}", momentum);
+    }
+}
@@ -0,0 +1,409 @@
+//! Common solver interface and pipeline
+//!
+//! All SPH solvers share the same skeleton: particles are sorted into the
+//! neighborhood grid, densities are computed by summation including boundary
+//! particles, non-pressure forces advance the velocities, a solver specific
+//! pressure term follows and finally the particles are advected. `Pipeline`
+//! implements the shared stages, solvers only add their pressure computation.
+//!
+//! Non-pressure forces are gravity and laminar viscosity plus the optional
+//! artificial viscosity and surface tension. Additional forces can be
+//! registered as `ForceTerm`s without touching the solvers.
+//!
+//! References:
+//!     [Mon05] Joe J. Monaghan, 2005,
+//!             Smoothed particle hydrodynamics,
+//!             Reports on Progress in Physics 68, 1703-1759
+//!     [AIA12] Nadir Akinci, Markus Ihmsen, Gizem Akinci, Barbara Solenthaler, and Matthias Teschner, 2012,
+//!             Versatile rigid-fluid coupling for incompressible SPH,
+//!             ACM Trans. Graph. 31, 4, Article 62
+
+use cgmath::{InnerSpace, MetricSpace};
+use collision::{self, Collider};
+use math::{Real, VectorN};
+use math::vector_n::vec2;
+use num::Zero;
+use particle::{Particles, Processor};
+use std::marker::PhantomData;
+use typenum::U2;
+
+use super::boundary::{boundary_density, BoundaryParticles};
+use super::grid::BoundedGrid;
+use super::kernel::{CubicSpline, SmoothingKernel};
+use super::property::*;
+use super::rigid::{self, RigidBody};
+use super::sort::ParticleSort;
+use super::surface_tension::{surface_tension_forces, SurfaceTension};
+use super::viscosity::{artificial_viscosity, xsph, ArtificialViscosity};
+use super::{density_summation, sort_particles};
+
+/// SPH solver advancing particles with the properties registered by `init`.
+pub trait Solver<T: Real> {
+    /// Register the particle properties required by the solver.
+    fn init(&self, particles: &mut Particles);
+
+    /// Advance the simulation by one timestep.
+    fn step(&mut self, particles: &mut Particles, timestep: T);
+
+    /// Shared stages and parameters of the non-pressure forces.
+    fn pipeline(&self) -> &Pipeline<T>;
+
+    fn pipeline_mut(&mut self) -> &mut Pipeline<T>;
+
+    /// Statistics of the last step.
+    fn diagnostics(&self, particles: &Particles) -> Diagnostics<T>;
+}
+
+/// Statistics of an SPH simulation after a step.
+#[derive(Copy, Clone, Debug)]
+pub struct Diagnostics<T> {
+    /// Iterations of the pressure solver, zero for WCSPH.
+    pub pressure_iterations: usize,
+    /// Iterations of the divergence-free solver, only used by DFSPH.
+    pub divergence_iterations: usize,
+    /// Average compression `max(ρ - ρ0, 0)` relative to the rest density.
+    pub density_error: T,
+    pub max_velocity: T,
+}
+
+impl<T: Real> Diagnostics<T> {
+    /// Density error and maximum velocity of the particles, without solver iterations.
+    pub fn measure(particles: &Particles, rest_density: T) -> Self {
+        let densities = particles.read_property::<Density<T>>();
+        let velocities = particles.read_property::<Velocity<T, U2>>();
+        let compression = densities.iter().fold(T::zero(), |sum, &density| sum + (density - rest_density).max(T::zero()));
+        Diagnostics {
+            pressure_iterations: 0,
+            divergence_iterations: 0,
+            density_error: compression / (T::new(densities.len().max(1)) * rest_density),
+            max_velocity: velocities.iter().fold(T::zero(), |max, v| max.max(v.magnitude())),
+        }
+    }
+}
+
+/// Solver together with its particles.
+pub struct Simulation<T: Real, S: Solver<T>> {
+    pub solver: S,
+    particles: Particles,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Real, S: Solver<T>> Simulation<T, S> {
+    /// Empty simulation with the properties of the solver registered.
+    pub fn new(solver: S) -> Self {
+        let mut particles = Particles::new();
+        solver.init(&mut particles);
+        Simulation {
+            solver,
+            particles,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn particles(&self) -> &Particles {
+        &self.particles
+    }
+
+    /// Particles for adding or removing particles, the properties of the solver must be kept.
+    pub fn particles_mut(&mut self) -> &mut Particles {
+        &mut self.particles
+    }
+
+    pub fn step(&mut self, timestep: T) {
+        self.solver.step(&mut self.particles, timestep);
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics<T> {
+        self.solver.diagnostics(&self.particles)
+    }
+}
+
+/// Neighborhood data of the fluid for force terms and pressure solvers.
+pub struct Fluid<'a, T: Real + 'a> {
+    pub grid: &'a BoundedGrid<T, U2>,
+    pub kernel: &'a CubicSpline<T>,
+    pub boundary: &'a BoundaryParticles<T>,
+    pub rest_density: T,
+}
+
+/// Additional force acting on the fluid particles.
+pub trait ForceTerm<T: Real>: Send {
+    /// Add accelerations to the `Acceleration` property.
+    fn apply(&mut self, p: &Processor, fluid: &Fluid<T>);
+}
+
+impl<T: Real> ForceTerm<T> for ArtificialViscosity<T> {
+    fn apply(&mut self, p: &Processor, fluid: &Fluid<T>) {
+        artificial_viscosity(p, fluid.kernel, fluid.grid, *self);
+    }
+}
+
+/// Shared stages of the SPH solvers in 2d domains.
+///
+/// Particles are sorted along the cells of a `BoundedGrid` with cell size equal
+/// to the smoothing radius, particles leaving the grid don't interact anymore.
+/// Solid walls are represented by boundary particles, rigid bodies are
+/// sampled the same way each step.
+pub struct Pipeline<T: Real> {
+    grid: BoundedGrid<T, U2>,
+    kernel: CubicSpline<T>,
+    sort: ParticleSort,
+    boundary: BoundaryParticles<T>,
+    static_boundary: Vec<VectorN<T, U2>>,
+
+    /// Kinematic viscosity.
+    pub viscosity: T,
+    pub gravity: VectorN<T, U2>,
+    pub surface_tension: Option<SurfaceTension<T>>,
+    pub artificial_viscosity: Option<ArtificialViscosity<T>>,
+    /// XSPH velocity smoothing factor `ε`.
+    pub xsph: Option<T>,
+    /// Rigid bodies, two-way coupled with the fluid.
+    pub bodies: Vec<RigidBody<T>>,
+    /// Additional forces, applied after the built-in non-pressure forces.
+    pub forces: Vec<Box<ForceTerm<T>>>,
+    /// Static obstacles resolved after advection, positions in world units.
+    pub colliders: Vec<Collider<T, U2>>,
+
+    normals: Vec<VectorN<T, U2>>,
+    corrections: Vec<VectorN<T, U2>>,
+}
+
+impl<T: Real> Pipeline<T> {
+    /// Pipeline for the domain `[0, num_cells * smoothing_radius]`.
+    pub fn new(num_cells: VectorN<usize, U2>, smoothing_radius: T) -> Self {
+        Pipeline {
+            grid: BoundedGrid::new(num_cells, smoothing_radius),
+            kernel: CubicSpline::new_2d(smoothing_radius),
+            sort: ParticleSort::new(),
+            boundary: BoundaryParticles::empty(num_cells, smoothing_radius),
+            static_boundary: Vec::new(),
+
+            viscosity: T::new(1.0e-3),
+            gravity: vec2(T::zero(), T::new(-9.81)),
+            surface_tension: None,
+            artificial_viscosity: None,
+            xsph: None,
+            bodies: Vec::new(),
+            forces: Vec::new(),
+            colliders: Vec::new(),
+
+            normals: Vec::new(),
+            corrections: Vec::new(),
+        }
+    }
+
+    /// Replace the static boundary particles, see `BoundarySampler` for sampling shapes.
+    ///
+    /// Boundary particles contribute to the densities and pressure forces. [AIA12]
+    pub fn set_boundary(&mut self, positions: Vec<VectorN<T, U2>>) {
+        self.boundary = BoundaryParticles::new(positions.clone(), &self.kernel, *self.grid.num_cells(), self.grid.cell_size());
+        self.static_boundary = positions;
+    }
+
+    pub fn boundary(&self) -> &BoundaryParticles<T> {
+        &self.boundary
+    }
+
+    pub fn grid(&self) -> &BoundedGrid<T, U2> {
+        &self.grid
+    }
+
+    pub fn kernel(&self) -> &CubicSpline<T> {
+        &self.kernel
+    }
+
+    pub fn fluid(&self, rest_density: T) -> Fluid<T> {
+        Fluid {
+            grid: &self.grid,
+            kernel: &self.kernel,
+            boundary: &self.boundary,
+            rest_density,
+        }
+    }
+
+    /// Sort the particles and sample the boundary of the rigid bodies at their current positions.
+    pub fn prepare(&mut self, particles: &mut Particles) {
+        sort_particles(particles, &mut self.grid, &mut self.sort);
+        if !self.bodies.is_empty() {
+            self.boundary = BoundaryParticles::with_bodies(
+                self.static_boundary.clone(), &self.bodies,
+                &self.kernel, *self.grid.num_cells(), self.grid.cell_size());
+        }
+
+        let num_particles = particles.num_particles();
+        self.normals.resize(num_particles, VectorN::zero());
+        self.corrections.resize(num_particles, VectorN::zero());
+    }
+
+    /// Density summation including the boundary particles.
+    pub fn densities(&self, p: &Processor, rest_density: T) {
+        density_summation(p, &self.kernel, &self.grid);
+        boundary_density(p, &self.kernel, &self.boundary, rest_density);
+    }
+
+    /// Overwrite the accelerations with all non-pressure forces.
+    pub fn forces(&mut self, p: &Processor, rest_density: T) {
+        laminar_viscosity(p, &self.kernel, &self.grid, self.viscosity, self.gravity);
+        self.add_forces(p, rest_density);
+    }
+
+    /// Add the optional non-pressure forces to the accelerations, excluding gravity and laminar viscosity.
+    pub fn add_forces(&mut self, p: &Processor, rest_density: T) {
+        if let Some(params) = self.artificial_viscosity {
+            artificial_viscosity(p, &self.kernel, &self.grid, params);
+        }
+        if let Some(model) = self.surface_tension {
+            surface_tension_forces(p, model, &mut self.normals, &self.kernel, &self.grid, rest_density);
+        }
+
+        let fluid = Fluid {
+            grid: &self.grid,
+            kernel: &self.kernel,
+            boundary: &self.boundary,
+            rest_density,
+        };
+        for force in &mut self.forces {
+            force.apply(p, &fluid);
+        }
+    }
+
+    /// Update the velocities with the accelerations `v += a dt`.
+    pub fn accelerate(&self, p: &Processor, timestep: T) {
+        let (velocities, accels) = (
+            p.write_property::<Velocity<T, U2>>(),
+            p.read_property::<Acceleration<T, U2>>(),
+        );
+        par_azip!(mut vel (velocities), accel (accels) in { *vel += accel * timestep; });
+    }
+
+    /// Move the particles `x += v dt`, resolve collisions and smooth the velocities.
+    pub fn advect(&mut self, p: &Processor, timestep: T) {
+        if self.colliders.is_empty() {
+            let (positions, velocities) = (
+                p.write_property::<Position<T, U2>>(),
+                p.read_property::<Velocity<T, U2>>(),
+            );
+            par_azip!(mut pos (positions), vel (velocities) in { *pos += vel * timestep; });
+        } else {
+            let (positions, velocities) = (
+                p.write_property::<Position<T, U2>>(),
+                p.write_property::<Velocity<T, U2>>(),
+            );
+            let colliders = &self.colliders;
+            par_azip!(mut pos (positions), mut vel (velocities) in {
+                let start = *pos;
+                *pos += *vel * timestep;
+                collision::resolve_all(colliders, &start, pos, vel);
+            });
+        }
+
+        if let Some(epsilon) = self.xsph {
+            xsph(p, &self.kernel, &self.grid, epsilon, &mut self.corrections);
+        }
+    }
+
+    /// Accumulate the reaction forces of the fluid on the rigid bodies, see `rigid::fluid_forces`.
+    pub fn body_reactions<F>(&mut self, p: &Processor, rest_density: T, pressure_term: F)
+        where F: Fn(usize) -> T
+    {
+        rigid::fluid_forces(p, &self.kernel, &self.boundary, rest_density, &mut self.bodies, pressure_term);
+    }
+
+    /// Integrate the rigid bodies, which collide with the walls of the grid.
+    pub fn step_bodies(&mut self, timestep: T) {
+        let (num_cells, cell_size) = (*self.grid.num_cells(), self.grid.cell_size());
+        let domain = (vec2(T::zero(), T::zero()), vec2(T::new(num_cells[0]) * cell_size, T::new(num_cells[1]) * cell_size));
+        rigid::step_bodies(&mut self.bodies, self.gravity, domain, timestep);
+    }
+}
+
+/// Overwrite the accelerations with gravity and laminar viscosity.
+///
+/// Ref: [Mon05] Eq. 6.5
+fn laminar_viscosity<T, K>(p: &Processor, kernel: &K, grid: &BoundedGrid<T, U2>, viscosity: T, gravity: VectorN<T, U2>)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (accels, positions, velocities, densities, masses) = (
+        p.write_property::<Acceleration<T, U2>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Velocity<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    // `2 (d + 2) ν` and regularization of the distance
+    let viscous = T::new(8.0) * viscosity;
+    let eta = T::new(0.01) * kernel.support().powi(2);
+
+    par_azip!(index i, mut accel (accels), pos (positions), vel (velocities) in {
+        *accel = gravity;
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let r = pos - positions[j];
+            let dist = pos.distance(positions[j]);
+            let grad = r * kernel.grad_w(dist);
+            let v = vel - velocities[j];
+            *accel += grad * (viscous * masses[j] / densities[j] * v.dot(r) / (dist * dist + eta));
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use sph::dfsph::Dfsph;
+    use sph::wcsph::Wcsph;
+    use super::*;
+
+    /// Constant acceleration to the right.
+    struct Wind(f64);
+    impl ForceTerm<f64> for Wind {
+        fn apply(&mut self, p: &Processor, _: &Fluid<f64>) {
+            for accel in p.write_property::<Acceleration<f64, U2>>() {
+                accel[0] += self.0;
+            }
+        }
+    }
+
+    fn run<S: Solver<f64>>(solver: S) -> Diagnostics<f64> {
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let mut simulation = Simulation::new(solver);
+        {
+            let mut positions = Vec::new();
+            for y in 0..10 {
+                for x in 0..10 {
+                    positions.push(vec2(1.0 + x as f64 * spacing, 1.0 + y as f64 * spacing));
+                }
+            }
+            let masses = vec![rest_density * spacing * spacing; positions.len()];
+            simulation.particles_mut().add_particles(positions.len())
+                      .with::<Position<f64, U2>>(&positions)
+                      .with::<Mass<f64>>(&masses);
+        }
+
+        {
+            let pipeline = simulation.solver.pipeline_mut();
+            pipeline.gravity = vec2(0.0, 0.0);
+            pipeline.forces.push(Box::new(Wind(1.0)));
+        }
+        simulation.step(1.0e-3);
+
+        // all particles pushed by the registered force
+        let velocities = simulation.particles().read_property::<Velocity<f64, U2>>();
+        let mean = velocities.iter().fold(0.0, |sum, v| sum + v[0]) / velocities.len() as f64;
+        assert!((mean - 1.0e-3).abs() < 1.0e-6, "{}", mean);
+        simulation.diagnostics()
+    }
+
+    #[test]
+    fn solver_pipeline() {
+        let diagnostics = run(Wcsph::new(vec2(20, 20), 0.2, 1000.0));
+        assert_eq!(diagnostics.pressure_iterations, 0);
+        let diagnostics = run(Dfsph::new(vec2(20, 20), 0.2, 1000.0));
+        assert!(diagnostics.pressure_iterations >= 2);
+        assert!(diagnostics.density_error < 1.0e-2 && diagnostics.max_velocity > 0.0);
+    }
+}
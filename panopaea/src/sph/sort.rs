@@ -0,0 +1,99 @@
+//! Particle reordering by cell key
+//!
+//! Counting sort in `O(n + k)` for `n` particles and `k` cell keys. The
+//! permutation is kept and can be applied in-place to any number of
+//! per-particle arrays, buffers are reused across timesteps.
+
+use rayon::prelude::*;
+use std::cmp;
+
+#[derive(Debug, Default)]
+pub struct ParticleSort {
+    keys: Vec<usize>,
+    counts: Vec<usize>,
+    order: Vec<usize>,
+    visited: Vec<bool>,
+}
+
+impl ParticleSort {
+    pub fn new() -> Self {
+        ParticleSort::default()
+    }
+
+    /// Compute the permutation sorting the items by key.
+    ///
+    /// The sort is stable, keys greater or equal to `num_keys` are moved to the end.
+    pub fn sort<T, F>(&mut self, items: &[T], num_keys: usize, key: F)
+        where T: Sync,
+              F: Fn(&T) -> usize + Sync,
+    {
+        items.par_iter().map(|item| cmp::min(key(item), num_keys)).collect_into(&mut self.keys);
+
+        // bucket offsets, the last bucket holds all invalid keys
+        self.counts.clear();
+        self.counts.resize(num_keys + 2, 0);
+        for &key in &self.keys {
+            self.counts[key + 1] += 1;
+        }
+        for i in 1..self.counts.len() {
+            self.counts[i] += self.counts[i-1];
+        }
+
+        self.order.resize(items.len(), 0);
+        for (i, &key) in self.keys.iter().enumerate() {
+            self.order[self.counts[key]] = i;
+            self.counts[key] += 1;
+        }
+    }
+
+    /// Permutation of the last sort, `sorted[i] = unsorted[order[i]]`.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// Reorder values in-place according to the last sort.
+    pub fn permute<T>(&mut self, values: &mut [T]) {
+        assert_eq!(values.len(), self.order.len());
+
+        self.visited.clear();
+        self.visited.resize(values.len(), false);
+
+        // follow the cycles of the permutation
+        for start in 0..values.len() {
+            if self.visited[start] {
+                continue;
+            }
+
+            let mut i = start;
+            loop {
+                self.visited[i] = true;
+                let next = self.order[i];
+                if next == start {
+                    break;
+                }
+                values.swap(i, next);
+                i = next;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counting_sort() {
+        let keys: [usize; 10] = [3, 1, 7, 0, 3, 9, 1, 2, 3, 0];
+        let mut sort = ParticleSort::new();
+        sort.sort(&keys, 4, |&k| k);
+
+        // stable, invalid keys at the end
+        assert_eq!(sort.order(), &[3, 9, 1, 6, 7, 0, 4, 8, 2, 5]);
+
+        let mut values = keys.iter().enumerate().map(|(i, &k)| (k, i)).collect::<Vec<_>>();
+        let expected = sort.order().iter().map(|&i| values[i]).collect::<Vec<_>>();
+        sort.permute(&mut values);
+        assert_eq!(values, expected);
+    }
+}
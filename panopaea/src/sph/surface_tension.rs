@@ -0,0 +1,207 @@
+//! Surface tension forces
+//!
+//! References:
+//!     [MDM03] Matthias Müller, David Charypar, and Markus Gross, 2003,
+//!             Particle-based fluid simulation for interactive applications,
+//!             In Proceedings of the 2003 ACM SIGGRAPH/Eurographics symposium on Computer animation (SCA '03),
+//!             Eurographics Association, Aire-la-Ville, Switzerland, Switzerland, 154-159
+//!     [AAT13] Nadir Akinci, Gizem Akinci, and Matthias Teschner, 2013,
+//!             Versatile surface tension and adhesion for SPH fluids,
+//!             ACM Trans. Graph. 32, 6, Article 182
+
+use cgmath::{InnerSpace, MetricSpace};
+use math::{Real, VectorN};
+use num::Zero;
+use particle::Processor;
+use std::f64;
+use typenum::U2;
+
+use super::grid::BoundedGrid;
+use super::kernel::SmoothingKernel;
+use super::property::{Acceleration, Density, Mass, Position};
+
+/// Surface tension model with surface tension coefficient.
+#[derive(Copy, Clone, Debug)]
+pub enum SurfaceTension<T> {
+    /// Continuum surface force, acts on particles with a large color field gradient.
+    ///
+    /// Ref: [MDM03] Sec. 4.4
+    Csf { coefficient: T },
+    /// Cohesion and curvature minimization, doesn't require a surface detection.
+    ///
+    /// Ref: [AAT13]
+    Akinci { coefficient: T },
+}
+
+/// Add the surface tension accelerations of the model.
+///
+/// `normals` is a buffer with one entry per particle.
+pub fn surface_tension_forces<T, K>(
+    p: &Processor,
+    model: SurfaceTension<T>,
+    normals: &mut [VectorN<T, U2>],
+    kernel: &K,
+    grid: &BoundedGrid<T, U2>,
+    rest_density: T,
+)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    compute_normals(p, normals, kernel, grid);
+    match model {
+        SurfaceTension::Csf { coefficient } => csf_forces(p, normals, coefficient, kernel, grid),
+        SurfaceTension::Akinci { coefficient } => akinci_forces(p, normals, coefficient, kernel.support(), grid, rest_density),
+    }
+}
+
+/// Color field gradient `n_i = Σ m_j / ρ_j ∇W_ij`.
+///
+/// Ref: [MDM03] Eq. 15
+fn compute_normals<T, K>(p: &Processor, normals: &mut [VectorN<T, U2>], kernel: &K, grid: &BoundedGrid<T, U2>)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (positions, densities, masses) = (
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    par_azip!(index i, mut normal (normals), pos (positions) in {
+        *normal = VectorN::zero();
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let grad = (pos - positions[j]) * kernel.grad_w(pos.distance(positions[j]));
+            *normal += grad * (masses[j] / densities[j]);
+        });
+    });
+}
+
+/// Continuum surface force `a_i = -σ / ρ_i ∇²c_i n_i / |n_i|`.
+///
+/// Particles with `|n_i| < 0.1 / h` are considered interior particles.
+///
+/// Ref: [MDM03] Eq. 19
+fn csf_forces<T, K>(p: &Processor, normals: &[VectorN<T, U2>], coefficient: T, kernel: &K, grid: &BoundedGrid<T, U2>)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (accels, positions, densities, masses) = (
+        p.write_property::<Acceleration<T, U2>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    let threshold = T::new(0.1) / kernel.support();
+
+    par_azip!(index i, mut accel (accels), pos (positions), density (densities), normal (normals) in {
+        let length = normal.magnitude();
+        if length < threshold { return }
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        let mut laplacian = masses[i] / density * kernel.laplace_w(T::zero());
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            laplacian += masses[j] / densities[j] * kernel.laplace_w(pos.distance(positions[j]));
+        });
+
+        *accel -= normal * (coefficient * laplacian / (density * length));
+    });
+}
+
+/// Cohesion spline `C(r)`, rescaled to 2d.
+///
+/// Ref: [AAT13] Eq. 2
+fn cohesion<T: Real>(r: T, h: T) -> T {
+    let c = T::new(32.0 / f64::consts::PI) / h.powi(8);
+    if r > h || r <= T::zero() {
+        T::zero()
+    } else if T::new(2.0) * r > h {
+        c * (h - r).powi(3) * r.powi(3)
+    } else {
+        c * (T::new(2.0) * (h - r).powi(3) * r.powi(3) - h.powi(6) / T::new(64.0))
+    }
+}
+
+/// Cohesion and curvature forces `a_i = -γ Σ K_ij (m_j C(r) r̂ + h (n_i - n_j))`
+/// with symmetric correction `K_ij = 2ρ0 / (ρ_i + ρ_j)`.
+///
+/// Ref: [AAT13] Eq. 1, 5, 6
+fn akinci_forces<T>(p: &Processor, normals: &[VectorN<T, U2>], coefficient: T, h: T, grid: &BoundedGrid<T, U2>, rest_density: T)
+    where T: Real + 'static,
+{
+    let (accels, positions, densities, masses) = (
+        p.write_property::<Acceleration<T, U2>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    par_azip!(index i, mut accel (accels), pos (positions), density (densities), normal (normals) in {
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let r = pos - positions[j];
+            let dist = r.magnitude();
+            if dist <= T::zero() { return }
+
+            let correction = T::new(2.0) * rest_density / (density + densities[j]);
+            let attraction = r * (masses[j] * cohesion(dist, h) / dist);
+            let curvature = (normal - normals[j]) * h;
+            *accel -= (attraction + curvature) * (coefficient * correction);
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use particle::Particles;
+    use sph::wcsph::{self, Wcsph};
+    use sph::property::Velocity;
+    use math::vector_n::vec2;
+    use super::*;
+
+    #[test]
+    fn square_droplet_contracts() {
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let center = vec2(2.0, 2.0);
+
+        for &model in &[SurfaceTension::Csf { coefficient: 1.0 }, SurfaceTension::Akinci { coefficient: 1.0 }] {
+            let mut particles = Particles::new();
+            wcsph::init::<f64, U2>(&mut particles);
+            {
+                let mut positions = Vec::new();
+                for y in 0..10 {
+                    for x in 0..10 {
+                        positions.push(vec2(1.55 + x as f64 * spacing, 1.55 + y as f64 * spacing));
+                    }
+                }
+                let masses = vec![rest_density * spacing * spacing; positions.len()];
+                particles.add_particles(positions.len())
+                         .with::<Position<f64, U2>>(&positions)
+                         .with::<Mass<f64>>(&masses);
+            }
+
+            let mut solver = Wcsph::new(vec2(20, 20), 2.0 * spacing, rest_density);
+            solver.pipeline.gravity = vec2(0.0, 0.0);
+            solver.pipeline.surface_tension = Some(model);
+            solver.step(&mut particles, 1.0e-4);
+
+            // corners are pulled inwards
+            let positions = particles.read_property::<Position<f64, U2>>();
+            let velocities = particles.read_property::<Velocity<f64, U2>>();
+            let corner = (0..positions.len()).find(|&i| positions[i].distance(vec2(1.55, 1.55)) < 1.0e-3).unwrap();
+            assert!((center - positions[corner]).dot(velocities[corner]) > 0.0, "{:?}", model);
+
+            // the akinci model is symmetric and conserves momentum
+            if let SurfaceTension::Akinci { .. } = model {
+                let momentum = velocities.iter().fold(VectorN::<f64, U2>::zero(), |sum, &v| sum + v);
+                assert!(momentum.magnitude() < 1.0e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Artificial viscosity and velocity smoothing
+//!
+//! Both terms damp particle noise and improve stability at the cost of
+//! additional numerical dissipation.
+//!
+//! References:
+//!     [Mon92] Joe J. Monaghan, 1992,
+//!             Smoothed particle hydrodynamics,
+//!             Annual Review of Astronomy and Astrophysics 30, 543-574
+//!     [Mon89] Joe J. Monaghan, 1989,
+//!             On the problem of penetration in particle methods,
+//!             Journal of Computational Physics 82, 1, 1-15
+
+use cgmath::{InnerSpace, MetricSpace};
+use math::{Real, VectorN};
+use num::Zero;
+use particle::Processor;
+use typenum::U2;
+
+use super::grid::BoundedGrid;
+use super::kernel::SmoothingKernel;
+use super::property::{Acceleration, Density, Mass, Position, Velocity};
+
+/// Parameters of the artificial viscosity.
+#[derive(Copy, Clone, Debug)]
+pub struct ArtificialViscosity<T> {
+    /// Viscosity factor `α`, typically between 0.01 and 0.1.
+    pub alpha: T,
+    /// Numerical speed of sound `c`.
+    pub speed_of_sound: T,
+}
+
+/// Add the artificial viscosity `-Σ m_j Π_ij ∇W_ij` to the accelerations.
+///
+/// `Π_ij = -α c μ_ij / ρ_ij` with `μ_ij = h v_ij·r_ij / (|r_ij|^2 + 0.01h^2)` for approaching particles.
+///
+/// Ref: [Mon92] Eq. 4.1
+pub fn artificial_viscosity<T, K>(p: &Processor, kernel: &K, grid: &BoundedGrid<T, U2>, params: ArtificialViscosity<T>)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (accels, positions, velocities, densities, masses) = (
+        p.write_property::<Acceleration<T, U2>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Velocity<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    let h = kernel.support();
+    let eta = T::new(0.01) * h * h;
+    let factor = params.alpha * params.speed_of_sound * h;
+
+    par_azip!(index i, mut accel (accels), pos (positions), vel (velocities), density (densities) in {
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let r = pos - positions[j];
+            let vr = (vel - velocities[j]).dot(r);
+            if vr >= T::zero() { return }
+
+            let dist = pos.distance(positions[j]);
+            let pi = -factor * vr / ((dist * dist + eta) * T::new(0.5) * (density + densities[j]));
+            *accel -= r * (kernel.grad_w(dist) * masses[j] * pi);
+        });
+    });
+}
+
+/// XSPH velocity smoothing `v_i += ε Σ m_j / ρ_ij (v_j - v_i) W_ij`.
+///
+/// Pulls velocities towards the neighborhood average, `ε` between 0 and 1.
+/// `corrections` is a buffer with one entry per particle.
+///
+/// Ref: [Mon89] Eq. 2.6
+pub fn xsph<T, K>(p: &Processor, kernel: &K, grid: &BoundedGrid<T, U2>, epsilon: T, corrections: &mut [VectorN<T, U2>])
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (positions, velocities, densities, masses) = (
+        p.read_property::<Position<T, U2>>(),
+        p.write_property::<Velocity<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    {
+        let velocities = &*velocities;
+        par_azip!(index i, mut correction (&mut *corrections), pos (positions), vel (velocities), density (densities) in {
+            *correction = VectorN::zero();
+            let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+
+            let mut sum = VectorN::<T, U2>::zero();
+            grid.for_each_neighbor(cell, 1, |j| {
+                if j == i { return }
+                let weight = masses[j] * kernel.w(pos.distance(positions[j])) / (T::new(0.5) * (density + densities[j]));
+                sum += (velocities[j] - vel) * weight;
+            });
+            *correction = sum * epsilon;
+        });
+    }
+
+    par_azip!(mut vel (velocities), correction (&*corrections) in { *vel += correction; });
+}
+
+#[cfg(test)]
+mod tests {
+    use math::vector_n::vec2;
+    use particle::Particles;
+    use sph::wcsph;
+    use super::*;
+
+    #[test]
+    fn approaching_pair() {
+        let mut particles = Particles::new();
+        wcsph::init::<f64, U2>(&mut particles);
+        particles.add_particles(2)
+                 .with::<Position<f64, U2>>(&[vec2(1.0, 1.05), vec2(1.1, 1.05)])
+                 .with::<Velocity<f64, U2>>(&[vec2(1.0, 0.0), vec2(-1.0, 0.0)])
+                 .with::<Density<f64>>(&[1000.0, 1000.0])
+                 .with::<Mass<f64>>(&[10.0, 10.0]);
+
+        let kernel = ::sph::kernel::CubicSpline::new_2d(0.2);
+        let mut grid = BoundedGrid::new(vec2(20, 20), 0.2);
+        grid.construct_ranges(particles.read_property::<Position<f64, U2>>()).unwrap();
+
+        let params = ArtificialViscosity { alpha: 0.1, speed_of_sound: 10.0 };
+        particles.run(|p| artificial_viscosity(p, &kernel, &grid, params));
+        {
+            let accels = particles.read_property::<Acceleration<f64, U2>>();
+            assert!(accels[0][0] < 0.0 && accels[1][0] > 0.0);
+            assert!((accels[0][0] + accels[1][0]).abs() < 1.0e-12);
+        }
+
+        let mut corrections = vec![VectorN::zero(); 2];
+        particles.run(|p| xsph(p, &kernel, &grid, 0.5, &mut corrections));
+        let velocities = particles.read_property::<Velocity<f64, U2>>();
+        assert!(velocities[0][0] < 1.0 && velocities[0][0] > 0.0);
+        assert!((velocities[0][0] + velocities[1][0]).abs() < 1.0e-12);
+    }
+}
@@ -1,16 +1,37 @@
 
 //! Weakly Compressible Smoothed Particle Hydrodynamics (WCSPH)
+//!
+//! References:
+//!     [BT07] Markus Becker and Matthias Teschner, 2007,
+//!            Weakly compressible SPH for free surface flows,
+//!            In Proceedings of the 2007 ACM SIGGRAPH/Eurographics symposium on Computer animation (SCA '07),
+//!            Eurographics Association, Aire-la-Ville, Switzerland, Switzerland, 209-217
+//!     [Mon05] Joe J. Monaghan, 2005,
+//!             Smoothed particle hydrodynamics,
+//!             Reports on Progress in Physics 68, 1703-1759
+//!     [AIA12] Nadir Akinci, Markus Ihmsen, Gizem Akinci, Barbara Solenthaler, and Matthias Teschner, 2012,
+//!             Versatile rigid-fluid coupling for incompressible SPH,
+//!             ACM Trans. Graph. 31, 4, Article 62
+//!     [SP08] Barbara Solenthaler and Renato Pajarola, 2008,
+//!            Density contrast SPH interfaces,
+//!            In Proceedings of the 2008 ACM SIGGRAPH/Eurographics Symposium on Computer Animation (SCA '08),
+//!            Eurographics Association, 211-218
 
-use cgmath::MetricSpace;
-use math::{Dim, Real};
+use cgmath::{InnerSpace, MetricSpace};
+use math::{Dim, Real, VectorN};
+use math::integration::SymplecticEuler;
 use particle::{Particles, Processor};
 use rayon::prelude::*;
 use typenum::U2;
 use num::cast;
 
+use super::boundary::boundary_pressure_forces;
 use super::grid::BoundedGrid;
-use super::kernel::{self, Kernel};
+use super::kernel::{self, SmoothingKernel};
+use super::multiphase;
 use super::property::*;
+use super::solver::{Diagnostics, Pipeline, Solver};
+use super::integrate;
 
 pub fn init<T, N>(particles: &mut Particles)
     where T: Real + 'static,
@@ -20,6 +41,7 @@ pub fn init<T, N>(particles: &mut Particles)
     particles.add_property::<Velocity<T, N>>();
     particles.add_property::<Acceleration<T, N>>();
     particles.add_property::<Density<T>>();
+    particles.add_property::<Pressure<T>>();
     particles.add_property::<Mass<T>>();
 }
 
@@ -117,17 +139,238 @@ pub fn calculate_viscosity<T>(p: &Processor, (kernel_size,viscosity, grid): (T,
     });
 }
 
-pub fn integrate_explicit_euler<T>(p: &Processor, timestep: T)
+/// Compute pressure from the density with the Tait equation `p = B ((ρ/ρ0)^γ - 1)`.
+///
+/// Negative pressures are clamped to zero to avoid particle clustering at the free surface.
+///
+/// Ref: [BT07] Eq. 7
+pub fn compute_tait_pressure<T>(p: &Processor, (rest_density, stiffness, exponent): (T, T, T))
     where T: Real + 'static,
 {
-    let (pos, vel, accel) = (
-        p.write_property::<Position<T, U2>>(),
-        p.write_property::<Velocity<T, U2>>(),
-        p.read_property::<Acceleration<T, U2>>(),
+    let (pressures, densities) = (
+        p.write_property::<Pressure<T>>(),
+        p.read_property::<Density<T>>(),
     );
 
-    par_azip!(mut pos (pos), mut vel (vel), accel (accel) in {
-        *vel += accel * timestep;
-        *pos += *vel * timestep;
+    par_azip!(mut pressure (pressures), density (densities) in {
+        *pressure = (stiffness * ((density / rest_density).powf(exponent) - T::one())).max(T::zero());
     });
-}
\ No newline at end of file
+}
+
+/// Symplectic Euler, positions are advanced with the updated velocities.
+pub fn integrate_symplectic_euler<T>(p: &Processor, timestep: T)
+    where T: Real + 'static,
+{
+    integrate::<T, U2, _>(p, &SymplecticEuler, timestep);
+}
+/// Weakly compressible SPH solver for 2d domains.
+///
+/// Densities are computed by summation, pressure follows the Tait equation and
+/// pressure and viscosity forces are evaluated with the cubic spline kernel.
+/// Neighborhood search, boundary particles, rigid bodies and non-pressure
+/// forces are handled by the shared `Pipeline`. Several fluid phases with
+/// different rest densities are supported with `phase_densities`.
+///
+/// Ref: [BT07], [AIA12], [SP08]
+pub struct Wcsph<T: Real> {
+    /// Neighborhood, boundary, rigid bodies and non-pressure forces.
+    pub pipeline: Pipeline<T>,
+
+    pub rest_density: T,
+    /// Stiffness `B` of the Tait equation.
+    pub stiffness: T,
+    /// Exponent `γ` of the Tait equation.
+    pub exponent: T,
+    /// Rest densities indexed by the `Phase` property, see `multiphase::init`.
+    ///
+    /// Particles of each phase should have a mass of `rest_density[phase] * s^2`.
+    pub phase_densities: Option<Vec<T>>,
+    /// Interface tension coefficient between the phases.
+    pub interface_tension: Option<T>,
+
+    normals: Vec<VectorN<T, U2>>,
+}
+
+impl<T: Real> Wcsph<T> {
+    /// Solver for the domain `[0, num_cells * smoothing_radius]`.
+    ///
+    /// Particles with spacing `s` should have a mass of `rest_density * s^2`,
+    /// a smoothing radius of `2s` is a common choice.
+    pub fn new(num_cells: VectorN<usize, U2>, smoothing_radius: T, rest_density: T) -> Self {
+        let mut solver = Wcsph {
+            pipeline: Pipeline::new(num_cells, smoothing_radius),
+            rest_density,
+            stiffness: T::zero(),
+            exponent: T::new(7.0),
+            phase_densities: None,
+            interface_tension: None,
+
+            normals: Vec::new(),
+        };
+        solver.set_speed_of_sound(T::new(10.0));
+        solver
+    }
+
+    /// Set the stiffness to `ρ0 c^2 / γ` for the numerical speed of sound `c`.
+    ///
+    /// The speed of sound should be about 10 times the maximum flow velocity
+    /// to keep density fluctuations around 1%.
+    pub fn set_speed_of_sound(&mut self, speed: T) {
+        self.stiffness = self.rest_density * speed * speed / self.exponent;
+    }
+
+    /// Numerical speed of sound `c = sqrt(B γ / ρ0)`, required for the timestep restriction.
+    pub fn speed_of_sound(&self) -> T {
+        (self.stiffness * self.exponent / self.rest_density).sqrt()
+    }
+
+    /// Advance the simulation by one timestep.
+    ///
+    /// Requires the properties registered by `init`.
+    pub fn step(&mut self, particles: &mut Particles, timestep: T) {
+        self.pipeline.prepare(particles);
+        self.normals.resize(particles.num_particles(), VectorN::from_elem(T::zero()));
+
+        let pipeline = &mut self.pipeline;
+        let normals = &mut self.normals;
+        let rest_density = self.rest_density;
+        let tait = (rest_density, self.stiffness, self.exponent);
+        let (phases, interface_tension) = (self.phase_densities.as_ref().map(|phases| &phases[..]), self.interface_tension);
+        particles.run(|p| {
+            let (viscosity, gravity) = (pipeline.viscosity, pipeline.gravity);
+            if let Some(phases) = phases {
+                let (grid, kernel, boundary) = (pipeline.grid(), pipeline.kernel(), pipeline.boundary());
+                multiphase::density(p, kernel, grid, boundary, phases);
+                multiphase::tait_pressure(p, phases, tait);
+                multiphase::pressure_viscosity_forces(p, kernel, grid, boundary, phases, viscosity, gravity);
+                if let Some(coefficient) = interface_tension {
+                    multiphase::interface_tension(p, normals, coefficient, kernel, grid);
+                }
+            } else {
+                pipeline.densities(p, rest_density);
+                compute_tait_pressure(p, tait);
+                pressure_viscosity_forces(p, pipeline.kernel(), pipeline.grid(), viscosity, gravity);
+                boundary_pressure_forces(p, pipeline.kernel(), pipeline.boundary(), rest_density);
+            }
+            pipeline.add_forces(p, rest_density);
+
+            let (densities, pressures) = (p.read_property::<Density<T>>(), p.read_property::<Pressure<T>>());
+            pipeline.body_reactions(p, rest_density, |i| pressures[i] / (densities[i] * densities[i]));
+
+            pipeline.accelerate(p, timestep);
+            pipeline.advect(p, timestep);
+        });
+        pipeline.step_bodies(timestep);
+    }
+}
+
+impl<T: Real> Solver<T> for Wcsph<T> {
+    fn init(&self, particles: &mut Particles) {
+        init::<T, U2>(particles);
+        if self.phase_densities.is_some() {
+            multiphase::init(particles);
+        }
+    }
+
+    fn step(&mut self, particles: &mut Particles, timestep: T) {
+        Wcsph::step(self, particles, timestep);
+    }
+
+    fn pipeline(&self) -> &Pipeline<T> {
+        &self.pipeline
+    }
+
+    fn pipeline_mut(&mut self) -> &mut Pipeline<T> {
+        &mut self.pipeline
+    }
+
+    fn diagnostics(&self, particles: &Particles) -> Diagnostics<T> {
+        Diagnostics::measure(particles, self.rest_density)
+    }
+}
+
+/// Overwrite the accelerations with gravity, symmetric pressure forces and laminar viscosity.
+///
+/// Ref: [BT07] Eq. 6, [Mon05] Eq. 6.5
+fn pressure_viscosity_forces<T, K>(p: &Processor, kernel: &K, grid: &BoundedGrid<T, U2>, viscosity: T, gravity: VectorN<T, U2>)
+    where T: Real + 'static,
+          K: SmoothingKernel<T> + Sync,
+{
+    let (accels, positions, velocities, densities, pressures, masses) = (
+        p.write_property::<Acceleration<T, U2>>(),
+        p.read_property::<Position<T, U2>>(),
+        p.read_property::<Velocity<T, U2>>(),
+        p.read_property::<Density<T>>(),
+        p.read_property::<Pressure<T>>(),
+        p.read_property::<Mass<T>>(),
+    );
+
+    // `2 (d + 2) ν` and regularization of the distance
+    let viscous = T::new(8.0) * viscosity;
+    let eta = T::new(0.01) * kernel.support().powi(2);
+
+    par_azip!(
+        index i,
+        mut accel (accels),
+        pos (positions),
+        vel (velocities),
+        density (densities),
+        pressure (pressures),
+    in {
+        *accel = gravity;
+        let cell = if let Some(cell) = grid.get_cell(&pos) { cell } else { return };
+        let pressure_i = pressure / (density * density);
+
+        grid.for_each_neighbor(cell, 1, |j| {
+            if j == i { return }
+            let r = pos - positions[j];
+            let dist = pos.distance(positions[j]);
+            let grad = r * kernel.grad_w(dist);
+
+            let pressure_j = pressures[j] / (densities[j] * densities[j]);
+            *accel -= grad * (masses[j] * (pressure_i + pressure_j));
+
+            let v = vel - velocities[j];
+            *accel += grad * (viscous * masses[j] / densities[j] * v.dot(r) / (dist * dist + eta));
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use math::vector_n::vec2;
+    use super::*;
+
+    #[test]
+    fn wcsph_lattice_at_rest() {
+        let (spacing, rest_density) = (0.1, 1000.0);
+        let mut particles = Particles::new();
+        init::<f64, U2>(&mut particles);
+
+        {
+            let mut positions = Vec::new();
+            for y in 0..20 {
+                for x in 0..20 {
+                    positions.push(vec2(1.0 + x as f64 * spacing, 1.0 + y as f64 * spacing));
+                }
+            }
+            let masses = vec![rest_density * spacing * spacing; positions.len()];
+            particles.add_particles(positions.len())
+                     .with::<Position<f64, U2>>(&positions)
+                     .with::<Mass<f64>>(&masses);
+        }
+
+        let mut solver = Wcsph::new(vec2(20, 20), 2.0 * spacing, rest_density);
+        solver.pipeline.gravity = vec2(0.0, 0.0);
+        solver.step(&mut particles, 1.0e-4);
+
+        let center = vec2(1.0 + 9.0 * spacing, 1.0 + 9.0 * spacing);
+        let positions = particles.read_property::<Position<f64, U2>>();
+        let i = (0..positions.len()).find(|&i| positions[i].distance(center) < 1.0e-3).unwrap();
+
+        let density = particles.read_property::<Density<f64>>()[i];
+        assert!((density - rest_density).abs() < 0.05 * rest_density, "{}", density);
+        assert!(particles.read_property::<Pressure<f64>>().iter().all(|&p| p >= 0.0));
+        assert!(particles.read_property::<Velocity<f64, U2>>()[i].magnitude() < 1.0e-6);
+    }
+}
@@ -0,0 +1,273 @@
+//! Tracer particles
+//!
+//! Massless particles following a velocity field, and the integral curves
+//! used to visualize flows:
+//!
+//!  - streamlines: tangent to the velocity at a fixed time,
+//!  - pathlines: trajectory of a single particle through the time-varying field,
+//!  - streaklines: particles continuously released at a seed point.
+//!
+//! Simulation output enters as a `FlowSequence2d`/`FlowSequence3d` of staggered
+//! velocity frames, interpolated in space with `domain::interp` and linearly in
+//! time. Positions are in world units with `[0]` along `x`, `[1]` along `y` and
+//! `[2]` along `z`, curves can be exported with `io::vtk::PolyData::polylines`.
+
+use dec::grid::{Staggered2d, Staggered3d};
+use domain::{Grid2d, Grid3d};
+use domain::interp::{self, Interpolation};
+use math::{Dim, Real, VectorN};
+use math::integration::Integrator;
+use math::vector_n::{vec2, vec3};
+use rayon::prelude::*;
+use typenum::{U2, U3};
+
+/// Time-dependent velocity field `u(t, x)`.
+pub trait FlowField<T: Real, N: Dim<T>>: Sync {
+    fn velocity(&self, time: T, position: &VectorN<T, N>) -> VectorN<T, N>;
+}
+
+/// Analytic fields.
+impl<T, N, F> FlowField<T, N> for F
+    where T: Real,
+          N: Dim<T>,
+          F: Fn(T, &VectorN<T, N>) -> VectorN<T, N> + Sync,
+{
+    fn velocity(&self, time: T, position: &VectorN<T, N>) -> VectorN<T, N> {
+        self(time, position)
+    }
+}
+
+/// Frames enclosing `time` and the weight of the later one, clamped to the recorded times.
+fn frame_weights<T: Real>(times: &[T], time: T) -> (usize, usize, T) {
+    assert!(!times.is_empty(), "empty flow sequence");
+    let last = times.len() - 1;
+    if time <= times[0] {
+        return (0, 0, T::zero());
+    }
+    if time >= times[last] {
+        return (last, last, T::zero());
+    }
+    let next = times.iter().position(|&t| t > time).unwrap();
+    let (t0, t1) = (times[next - 1], times[next]);
+    (next - 1, next, (time - t0) / (t1 - t0))
+}
+
+/// Velocity frames of a 2d simulation at increasing times.
+pub struct FlowSequence2d<'a, T> {
+    grid: &'a Grid2d,
+    times: Vec<T>,
+    frames: Vec<Staggered2d<T>>,
+    pub interpolation: Interpolation,
+}
+
+impl<'a, T: Real> FlowSequence2d<'a, T> {
+    pub fn new(grid: &'a Grid2d, interpolation: Interpolation) -> Self {
+        FlowSequence2d { grid, times: Vec::new(), frames: Vec::new(), interpolation }
+    }
+
+    /// Append the velocity at `time`, which needs to be later than the previous frames.
+    pub fn push(&mut self, time: T, velocity: Staggered2d<T>) {
+        assert!(self.times.last().map_or(true, |&last| time > last), "frames need increasing times");
+        self.times.push(time);
+        self.frames.push(velocity);
+    }
+
+    pub fn times(&self) -> &[T] {
+        &self.times
+    }
+
+    fn sample(&self, frame: usize, position: &VectorN<T, U2>) -> VectorN<T, U2> {
+        let (v, u) = interp::velocity_2d(self.grid, &self.frames[frame], self.interpolation, (position[1], position[0]));
+        vec2(u, v)
+    }
+}
+
+impl<'a, T: Real> FlowField<T, U2> for FlowSequence2d<'a, T> {
+    /// Velocity linearly interpolated between the frames, constant outside of the recorded times.
+    fn velocity(&self, time: T, position: &VectorN<T, U2>) -> VectorN<T, U2> {
+        let (f0, f1, s) = frame_weights(&self.times, time);
+        let v0 = self.sample(f0, position);
+        if f0 == f1 {
+            return v0;
+        }
+        v0 * (T::one() - s) + self.sample(f1, position) * s
+    }
+}
+
+/// Velocity frames of a 3d simulation at increasing times.
+pub struct FlowSequence3d<'a, T> {
+    grid: &'a Grid3d,
+    times: Vec<T>,
+    frames: Vec<Staggered3d<T>>,
+    pub interpolation: Interpolation,
+}
+
+impl<'a, T: Real> FlowSequence3d<'a, T> {
+    pub fn new(grid: &'a Grid3d, interpolation: Interpolation) -> Self {
+        FlowSequence3d { grid, times: Vec::new(), frames: Vec::new(), interpolation }
+    }
+
+    /// Append the velocity at `time`, which needs to be later than the previous frames.
+    pub fn push(&mut self, time: T, velocity: Staggered3d<T>) {
+        assert!(self.times.last().map_or(true, |&last| time > last), "frames need increasing times");
+        self.times.push(time);
+        self.frames.push(velocity);
+    }
+
+    pub fn times(&self) -> &[T] {
+        &self.times
+    }
+
+    fn sample(&self, frame: usize, position: &VectorN<T, U3>) -> VectorN<T, U3> {
+        let pos = (position[2], position[1], position[0]);
+        let (w, v, u) = interp::velocity_3d(self.grid, &self.frames[frame], self.interpolation, pos);
+        vec3(u, v, w)
+    }
+}
+
+impl<'a, T: Real> FlowField<T, U3> for FlowSequence3d<'a, T> {
+    /// Velocity linearly interpolated between the frames, constant outside of the recorded times.
+    fn velocity(&self, time: T, position: &VectorN<T, U3>) -> VectorN<T, U3> {
+        let (f0, f1, s) = frame_weights(&self.times, time);
+        let v0 = self.sample(f0, position);
+        if f0 == f1 {
+            return v0;
+        }
+        v0 * (T::one() - s) + self.sample(f1, position) * s
+    }
+}
+
+/// Number of steps of at most `step` covering `duration` and the resulting step size.
+fn substeps<T: Real>(duration: T, step: T) -> (usize, T) {
+    assert!(step > T::zero(), "step size needs to be positive");
+    let steps = (duration.abs() / step).ceil().to_usize().unwrap().max(1);
+    (steps, duration / T::new(steps))
+}
+
+/// Massless particles advected with a flow field.
+#[derive(Clone, Debug)]
+pub struct Tracers<T: Real, N: Dim<T>> {
+    pub positions: Vec<VectorN<T, N>>,
+    pub time: T,
+}
+
+impl<T: Real, N: Dim<T>> Tracers<T, N> {
+    pub fn new(positions: Vec<VectorN<T, N>>, time: T) -> Self {
+        Tracers { positions, time }
+    }
+
+    /// Advance all particles by `timestep`.
+    pub fn advect<F, I>(&mut self, field: &F, integrator: &I, timestep: T)
+        where F: FlowField<T, N>,
+              I: Integrator<T> + Sync,
+    {
+        let time = self.time;
+        self.positions.par_iter_mut().for_each(|position| {
+            *position = integrator.step(position, time, timestep, |t, p: &VectorN<T, N>| field.velocity(t, p));
+        });
+        self.time = time + timestep;
+    }
+}
+
+/// Streamline from `seed` through the velocity frozen at `time`, `steps` steps of length `step` in time.
+///
+/// Negative steps trace upstream. The curve starts at the seed.
+pub fn streamline<T, N, F, I>(field: &F, integrator: &I, seed: VectorN<T, N>, time: T, step: T, steps: usize) -> Vec<VectorN<T, N>>
+    where T: Real,
+          N: Dim<T>,
+          F: FlowField<T, N>,
+          I: Integrator<T>,
+{
+    let mut line = Vec::with_capacity(steps + 1);
+    line.push(seed);
+    for i in 0..steps {
+        let next = integrator.step(&line[i], time, step, |_, p: &VectorN<T, N>| field.velocity(time, p));
+        line.push(next);
+    }
+    line
+}
+
+/// Trajectory of a particle released at `seed` at time `start` until `end`, with steps of at most `step`.
+pub fn pathline<T, N, F, I>(field: &F, integrator: &I, seed: VectorN<T, N>, start: T, end: T, step: T) -> Vec<VectorN<T, N>>
+    where T: Real,
+          N: Dim<T>,
+          F: FlowField<T, N>,
+          I: Integrator<T>,
+{
+    let (steps, dt) = substeps(end - start, step);
+    let mut line = Vec::with_capacity(steps + 1);
+    line.push(seed);
+    for i in 0..steps {
+        let time = start + dt * T::new(i);
+        let next = integrator.step(&line[i], time, dt, |t, p: &VectorN<T, N>| field.velocity(t, p));
+        line.push(next);
+    }
+    line
+}
+
+/// Particles released at `seed` after every step from `start` until `end`, with steps of at most `step`.
+///
+/// The curve starts at the oldest particle and ends at the seed.
+pub fn streakline<T, N, F, I>(field: &F, integrator: &I, seed: VectorN<T, N>, start: T, end: T, step: T) -> Vec<VectorN<T, N>>
+    where T: Real,
+          N: Dim<T>,
+          F: FlowField<T, N>,
+          I: Integrator<T> + Sync,
+{
+    let (steps, dt) = substeps(end - start, step);
+    let mut tracers = Tracers::new(vec![seed.clone()], start);
+    for _ in 0..steps {
+        tracers.advect(field, integrator, dt);
+        tracers.positions.push(seed.clone());
+    }
+    tracers.positions
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+    use dec::manifold::Manifold2d;
+    use math::integration::Rk4;
+    use super::*;
+
+    #[test]
+    fn rigid_rotation() {
+        // counterclockwise rotation around `(1, 1)` with unit angular velocity
+        let rotation = |_: f64, p: &VectorN<f64, U2>| vec2(1.0 - p[1], p[0] - 1.0);
+        let quarter = ::std::f64::consts::PI / 2.0;
+
+        let line = streamline(&rotation, &Rk4, vec2(1.5, 1.0), 0.0, quarter / 20.0, 20);
+        assert_eq!(line.len(), 21);
+        for p in &line {
+            assert!(((p[0] - 1.0).hypot(p[1] - 1.0) - 0.5).abs() < 1.0e-6);
+        }
+        let end = line[20];
+        assert!((end[0] - 1.0).abs() < 1.0e-6 && (end[1] - 1.5).abs() < 1.0e-6);
+
+        // the same rotation sampled from staggered frames, steady in time
+        let grid = Grid2d::new((8, 8)).with_spacing((0.25, 0.25));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (mut vertical, mut horizontal) = velocity.split_mut();
+            for ((j, i), v) in vertical.indexed_iter_mut() {
+                *v = rotation(0.0, &vec2((i as f64 + 0.5) * 0.25, j as f64 * 0.25))[1] * 0.25;
+            }
+            for ((j, i), u) in horizontal.indexed_iter_mut() {
+                *u = rotation(0.0, &vec2(i as f64 * 0.25, (j as f64 + 0.5) * 0.25))[0] * 0.25;
+            }
+        }
+        let mut sequence = FlowSequence2d::new(&grid, Interpolation::Linear);
+        sequence.push(0.0, velocity.clone());
+        sequence.push(1.0, velocity);
+
+        let path = pathline(&sequence, &Rk4, vec2(1.5, 1.0), 0.0, quarter, 0.05);
+        let end = path[path.len() - 1];
+        assert!((end[0] - 1.0).abs() < 1.0e-6 && (end[1] - 1.5).abs() < 1.0e-6, "{:?}", end);
+
+        // steady flow: streaklines coincide with the pathline, oldest particle first
+        let streak = streakline(&sequence, &Rk4, vec2(1.5, 1.0), 0.0, quarter, 0.05);
+        assert_eq!(streak.len(), path.len());
+        assert!((streak[0] - end).magnitude() < 1.0e-10);
+        assert_eq!((streak[streak.len() - 1] - vec2(1.5, 1.0)).magnitude(), 0.0);
+    }
+}
@@ -0,0 +1,370 @@
+//! Quadtree/octree spatial index
+//!
+//! Hierarchical subdivision of a bounding cube into `2^N` equal children
+//! until each leaf holds at most `leaf_size` points. Unlike the uniform
+//! `sph::grid::BoundedGrid` the resolution adapts to the point density,
+//! which suits clustered distributions, range queries of varying size and
+//! hierarchical algorithms like the Barnes-Hut summation in `nbody`.
+//!
+//! Points are stored in tree order, every node covers a contiguous range
+//! `start..end` of them and `order()` maps back to the input indices.
+//! Children of a node are stored next to each other, the child index has bit
+//! `d` set for the upper half along axis `d`.
+
+use cgmath::MetricSpace;
+use math::{Dim, Real, VectorN};
+use std::ops::Range;
+use typenum::{U2, U3};
+
+/// Nodes aren't subdivided beyond this depth, coincident points end up in a shared leaf.
+const MAX_DEPTH: usize = 32;
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Debug)]
+pub struct Aabb<T: Real, N: Dim<T>> {
+    pub min: VectorN<T, N>,
+    pub max: VectorN<T, N>,
+}
+
+impl<T: Real, N: Dim<T>> Aabb<T, N> {
+    pub fn new(min: VectorN<T, N>, max: VectorN<T, N>) -> Self {
+        Aabb { min, max }
+    }
+
+    /// Smallest box enclosing all points, `None` for an empty set.
+    pub fn from_points(points: &[VectorN<T, N>]) -> Option<Self> {
+        let first = match points.first() {
+            Some(first) => first.clone(),
+            None => return None,
+        };
+        let mut aabb = Aabb::new(first.clone(), first);
+        for p in points {
+            for d in 0..N::to_usize() {
+                aabb.min[d] = aabb.min[d].min(p[d]);
+                aabb.max[d] = aabb.max[d].max(p[d]);
+            }
+        }
+        Some(aabb)
+    }
+
+    pub fn contains(&self, point: &VectorN<T, N>) -> bool {
+        (0..N::to_usize()).all(|d| self.min[d] <= point[d] && point[d] <= self.max[d])
+    }
+
+    pub fn intersects(&self, other: &Aabb<T, N>) -> bool {
+        (0..N::to_usize()).all(|d| self.min[d] <= other.max[d] && other.min[d] <= self.max[d])
+    }
+
+    /// Squared distance of a point to the box, zero inside.
+    pub fn distance2(&self, point: &VectorN<T, N>) -> T {
+        (0..N::to_usize()).fold(T::zero(), |sum, d| {
+            let outside = (self.min[d] - point[d]).max(point[d] - self.max[d]).max(T::zero());
+            sum + outside * outside
+        })
+    }
+}
+
+/// Cubic tree node.
+#[derive(Clone, Debug)]
+pub struct Node<T: Real, N: Dim<T>> {
+    pub center: VectorN<T, N>,
+    /// Edge length.
+    pub size: T,
+    /// Index of the first of the `2^N` children, `None` for leaves.
+    pub children: Option<usize>,
+    /// Range of the points inside in tree order.
+    pub start: usize,
+    pub end: usize,
+}
+
+impl<T: Real, N: Dim<T>> Node<T, N> {
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_none()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Indices of the children.
+    pub fn child_nodes(&self) -> Option<Range<usize>> {
+        self.children.map(|first| first..first + (1 << N::to_usize()))
+    }
+
+    pub fn bounds(&self) -> Aabb<T, N> {
+        let half = self.size * T::new(0.5);
+        let mut aabb = Aabb::new(self.center.clone(), self.center.clone());
+        for d in 0..N::to_usize() {
+            aabb.min[d] = self.center[d] - half;
+            aabb.max[d] = self.center[d] + half;
+        }
+        aabb
+    }
+}
+
+/// Hierarchical spatial index over a set of points.
+pub struct Orthtree<T: Real, N: Dim<T>> {
+    /// Maximum number of points per leaf.
+    pub leaf_size: usize,
+    nodes: Vec<Node<T, N>>,
+    points: Vec<VectorN<T, N>>,
+    order: Vec<usize>,
+}
+
+pub type Quadtree<T> = Orthtree<T, U2>;
+pub type Octree<T> = Orthtree<T, U3>;
+
+impl<T: Real, N: Dim<T>> Orthtree<T, N> {
+    pub fn new() -> Self {
+        Orthtree {
+            leaf_size: 8,
+            nodes: Vec::new(),
+            points: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+
+    pub fn with_leaf_size(mut self, leaf_size: usize) -> Self {
+        assert!(leaf_size > 0, "leaves need to hold at least one point");
+        self.leaf_size = leaf_size;
+        self
+    }
+
+    /// Rebuild the tree for a new set of points.
+    pub fn build(&mut self, positions: &[VectorN<T, N>]) {
+        self.nodes.clear();
+        self.order = (0..positions.len()).collect();
+        let aabb = match Aabb::from_points(positions) {
+            Some(aabb) => aabb,
+            None => {
+                self.points.clear();
+                return;
+            }
+        };
+
+        let extent = (0..N::to_usize()).fold(T::zero(), |extent, d| extent.max(aabb.max[d] - aabb.min[d]));
+        let size = if extent > T::zero() { extent * T::new(1.0 + 1.0e-6) } else { T::one() };
+        self.nodes.push(Node {
+            center: (aabb.min + aabb.max) * T::new(0.5),
+            size,
+            children: None,
+            start: 0,
+            end: positions.len(),
+        });
+
+        let mut order = ::std::mem::replace(&mut self.order, Vec::new());
+        self.subdivide(0, 0, positions, &mut order);
+        self.points = order.iter().map(|&i| positions[i].clone()).collect();
+        self.order = order;
+    }
+
+    fn subdivide(&mut self, node: usize, depth: usize, positions: &[VectorN<T, N>], order: &mut [usize]) {
+        let (start, end) = (self.nodes[node].start, self.nodes[node].end);
+        if end - start <= self.leaf_size || depth >= MAX_DEPTH {
+            return;
+        }
+
+        let dim = N::to_usize();
+        let center = self.nodes[node].center.clone();
+        let quarter = self.nodes[node].size * T::new(0.25);
+        let octant = |p: &VectorN<T, N>| (0..dim).fold(0usize, |o, d| if p[d] >= center[d] { o | (1 << d) } else { o });
+        order[start..end].sort_by_key(|&i| octant(&positions[i]));
+
+        let first = self.nodes.len();
+        let mut child_start = start;
+        for child in 0..(1 << dim) {
+            let child_end = child_start + order[child_start..end].iter().take_while(|&&i| octant(&positions[i]) == child).count();
+            let mut child_center = center.clone();
+            for d in 0..dim {
+                child_center[d] = if child & (1 << d) != 0 { center[d] + quarter } else { center[d] - quarter };
+            }
+            self.nodes.push(Node {
+                center: child_center,
+                size: quarter * T::new(2.0),
+                children: None,
+                start: child_start,
+                end: child_end,
+            });
+            child_start = child_end;
+        }
+        self.nodes[node].children = Some(first);
+
+        for child in first..first + (1 << dim) {
+            self.subdivide(child, depth + 1, positions, order);
+        }
+    }
+
+    /// All nodes, the root first. Empty for an empty point set.
+    pub fn nodes(&self) -> &[Node<T, N>] {
+        &self.nodes
+    }
+
+    /// Points in tree order.
+    pub fn points(&self) -> &[VectorN<T, N>] {
+        &self.points
+    }
+
+    /// Input index of each point in tree order.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// Input indices of the points inside a node.
+    pub fn node_points(&self, node: usize) -> &[usize] {
+        &self.order[self.nodes[node].start..self.nodes[node].end]
+    }
+
+    /// Leaf containing a point, `None` outside of the root.
+    pub fn locate(&self, point: &VectorN<T, N>) -> Option<usize> {
+        if self.nodes.is_empty() || !self.nodes[0].bounds().contains(point) {
+            return None;
+        }
+        let mut node = 0;
+        while let Some(first) = self.nodes[node].children {
+            let center = &self.nodes[node].center;
+            node = first + (0..N::to_usize()).fold(0, |o, d| if point[d] >= center[d] { o | (1 << d) } else { o });
+        }
+        Some(node)
+    }
+
+    /// Visit the nodes whose bounds pass `visit`, descending only into accepted nodes.
+    fn traverse<V, F>(&self, mut visit: V, mut fnc: F)
+        where V: FnMut(&Node<T, N>) -> bool,
+              F: FnMut(usize),
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.is_empty() || !visit(node) {
+                continue;
+            }
+            match node.child_nodes() {
+                Some(children) => stack.extend(children),
+                None => for i in node.start..node.end {
+                    fnc(i);
+                },
+            }
+        }
+    }
+
+    /// Call `fnc` with the input index of every point inside the box.
+    pub fn for_each_in_box<F>(&self, aabb: &Aabb<T, N>, mut fnc: F)
+        where F: FnMut(usize)
+    {
+        self.traverse(|node| node.bounds().intersects(aabb), |i| {
+            if aabb.contains(&self.points[i]) {
+                fnc(self.order[i]);
+            }
+        });
+    }
+
+    /// Call `fnc` with the input index of every point within `radius` of `center`.
+    pub fn for_each_in_radius<F>(&self, center: &VectorN<T, N>, radius: T, mut fnc: F)
+        where F: FnMut(usize)
+    {
+        let radius2 = radius * radius;
+        self.traverse(|node| node.bounds().distance2(center) <= radius2, |i| {
+            if self.points[i].clone().distance2(center.clone()) <= radius2 {
+                fnc(self.order[i]);
+            }
+        });
+    }
+
+    /// Input index of the point closest to `point`, `None` for an empty tree.
+    pub fn nearest(&self, point: &VectorN<T, N>) -> Option<usize> {
+        let mut best: Option<(T, usize)> = None;
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.is_empty() || best.map_or(false, |(min, _)| node.bounds().distance2(point) > min) {
+                continue;
+            }
+            match node.child_nodes() {
+                Some(children) => {
+                    // visit the closest child last, it's popped first
+                    let mut children = children.collect::<Vec<_>>();
+                    children.sort_by(|&a, &b| {
+                        let da = self.nodes[a].bounds().distance2(point);
+                        let db = self.nodes[b].bounds().distance2(point);
+                        db.partial_cmp(&da).unwrap()
+                    });
+                    stack.extend(children);
+                }
+                None => for i in node.start..node.end {
+                    let distance = self.points[i].clone().distance2(point.clone());
+                    if best.map_or(true, |(min, _)| distance < min) {
+                        best = Some((distance, i));
+                    }
+                },
+            }
+        }
+        best.map(|(_, i)| self.order[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+    use math::rng::{Pcg32, DEFAULT_SEED};
+    use math::vector_n::{vec2, vec3};
+    use rand::Rng;
+    use super::*;
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut rng = Pcg32::new(DEFAULT_SEED);
+        // clustered points
+        let positions = (0..500)
+            .map(|i| {
+                let scale = if i % 2 == 0 { 0.05 } else { 1.0 };
+                vec2(rng.gen::<f64>() * scale, rng.gen::<f64>() * scale)
+            })
+            .collect::<Vec<_>>();
+        let mut tree = Quadtree::new().with_leaf_size(4);
+        tree.build(&positions);
+        assert_eq!(tree.node_points(0).len(), 500);
+
+        let query = Aabb::new(vec2(0.02, 0.01), vec2(0.4, 0.03));
+        let mut found = Vec::new();
+        tree.for_each_in_box(&query, |i| found.push(i));
+        found.sort();
+        let expected = (0..500).filter(|&i| query.contains(&positions[i])).collect::<Vec<_>>();
+        assert_eq!(found, expected);
+
+        for &(ref center, radius) in &[(vec2(0.03, 0.02), 0.01), (vec2(0.5, 0.5), 0.2), (vec2(2.0, 2.0), 0.5)] {
+            let mut found = Vec::new();
+            tree.for_each_in_radius(center, radius, |i| found.push(i));
+            found.sort();
+            let expected = (0..500)
+                .filter(|&i| (positions[i] - *center).magnitude() <= radius)
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+        }
+
+        let leaf = tree.locate(&positions[7]).unwrap();
+        assert!(tree.nodes()[leaf].is_leaf() && tree.node_points(leaf).contains(&7));
+        assert!(tree.locate(&vec2(-1.0, 0.0)).is_none());
+
+        // nearest neighbors in 3d
+        let positions = (0..300).map(|_| vec3(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>())).collect::<Vec<_>>();
+        let mut tree = Octree::new();
+        tree.build(&positions);
+        for _ in 0..20 {
+            let point = vec3(rng.gen::<f64>() * 1.5, rng.gen::<f64>(), rng.gen::<f64>() - 0.25);
+            let expected = (0..300)
+                .min_by(|&a, &b| {
+                    let da = (positions[a] - point).magnitude2();
+                    let db = (positions[b] - point).magnitude2();
+                    da.partial_cmp(&db).unwrap()
+                })
+                .unwrap();
+            assert_eq!(tree.nearest(&point), Some(expected));
+        }
+    }
+}
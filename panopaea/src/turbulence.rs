@@ -0,0 +1,362 @@
+//! Procedural turbulence for smoke
+//!
+//! Upsamples a coarse velocity field and adds band-limited detail below the
+//! resolution of the simulation. Detail is the curl of a stream function built
+//! from wavelet noise octaves with Kolmogorov amplitude falloff `k^{-5/6}`. The
+//! noise is looked up at texture coordinates advected with the coarse flow, so
+//! the detail moves along with the smoke instead of sliding through it.
+//!
+//! The stream function lives on the vertices of the fine grid and is
+//! differentiated on the edges, the added detail is exactly divergence-free
+//! in the discrete sense. Stream function values vanish on walls to keep the
+//! boundary fluxes of the coarse field.
+//!
+//! Positions are given in grid units `(y, x)` of the coarse grid.
+//!
+//! References:
+//!     [CD05] Robert L. Cook and Tony DeRose, 2005,
+//!            Wavelet noise,
+//!            ACM Trans. Graph. 24, 3, 803-811
+//!     [BHN07] Robert Bridson, Jim Houriham and Marcus Nordenstam, 2007,
+//!             Curl-noise for procedural fluid flow,
+//!             ACM Trans. Graph. 26, 3, Article 46
+//!     [KTJG08] Theodore Kim, Nils Thürey, Doug James and Markus Gross, 2008,
+//!              Wavelet turbulence for fluid simulation,
+//!              ACM Trans. Graph. 27, 3, Article 50
+
+use advection::{self, Scheme};
+use dec::grid::Staggered2d;
+use domain::Grid2d;
+use math::Real;
+use ndarray::{Array1, Array2, Axis};
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+
+/// Downsampling filter of the quadratic B-spline wavelet, Ref: [CD05] Appendix 1
+const DOWN_COEFFS: [f64; 32] = [
+    0.000334, -0.001528, 0.000410, 0.003545, -0.000938, -0.008233, 0.002172, 0.019120,
+    -0.005040, -0.044412, 0.011655, 0.103311, -0.025936, -0.243780, 0.033979, 0.655340,
+    0.655340, 0.033979, -0.243780, -0.025936, 0.103311, 0.011655, -0.044412, -0.005040,
+    0.019120, 0.002172, -0.008233, -0.000938, 0.003546, 0.000410, -0.001528, 0.000334,
+];
+
+/// Upsampling filter of the quadratic B-spline, Ref: [CD05] Appendix 1
+const UP_COEFFS: [f64; 4] = [0.25, 0.75, 0.75, 0.25];
+
+fn modulo(i: isize, n: usize) -> usize {
+    let n = n as isize;
+    (((i % n) + n) % n) as usize
+}
+
+/// Quadratic B-spline weights and their derivatives for the samples `mid - 1, mid, mid + 1`.
+fn spline_weights<T: Real>(p: T) -> (isize, [T; 3], [T; 3]) {
+    let half = T::new(0.5);
+    let mid = (p - half).ceil();
+    let t = mid - (p - half);
+    let w0 = half * t * t;
+    let w2 = half * (T::one() - t) * (T::one() - t);
+    let weights = [w0, T::one() - w0 - w2, w2];
+    let derivatives = [-t, T::new(2.0) * t - T::one(), T::one() - t];
+    (mid.to_isize().unwrap(), weights, derivatives)
+}
+
+/// Periodic tile of band-limited noise.
+///
+/// Ref: [CD05]
+pub struct WaveletNoise<T> {
+    tile: Array2<T>,
+}
+
+impl<T: Real> WaveletNoise<T> {
+    /// Tile with `size x size` coefficients, normalized to unit variance.
+    ///
+    /// Gaussian noise minus its coarser approximation leaves the detail band
+    /// of the quadratic B-spline wavelet. Ref: [CD05] Sec. 3
+    pub fn new<R: Rng>(size: usize, rng: &mut R) -> Self {
+        assert!(size % 2 == 0, "Noise tile size has to be even");
+        let mut noise = Array2::from_shape_fn((size, size), |_| {
+            let StandardNormal(value) = rng.gen();
+            value
+        });
+
+        // coarse approximation, filtered along both axes
+        let mut coarse = noise.clone();
+        for axis in 0..2 {
+            for mut line in coarse.axis_iter_mut(Axis(1 - axis)) {
+                let input = line.to_owned();
+                let mut down = Array1::<f64>::zeros(size / 2);
+                for i in 0..size / 2 {
+                    let center = 2 * i as isize;
+                    for (k, &a) in DOWN_COEFFS.iter().enumerate() {
+                        down[i] += a * input[modulo(center + k as isize - 16, size)];
+                    }
+                }
+                for i in 0..size {
+                    let mut value = 0.0;
+                    for k in (i / 2)..(i / 2 + 2) {
+                        value += UP_COEFFS[i + 2 - 2 * k] * down[k % (size / 2)];
+                    }
+                    line[i] = value;
+                }
+            }
+        }
+        noise -= &coarse;
+
+        // adding a shifted copy avoids different variances of even and odd samples
+        let offset = size as isize / 2 + 1;
+        let shifted = Array2::from_shape_fn((size, size), |(y, x)| {
+            noise[(modulo(y as isize + offset, size), modulo(x as isize + offset, size))]
+        });
+        noise += &shifted;
+
+        let num_samples = (size * size) as f64;
+        let mean = noise.scalar_sum() / num_samples;
+        let variance = noise.fold(0.0, |sum, &v| sum + (v - mean) * (v - mean)) / num_samples;
+        let scale = 1.0 / variance.sqrt();
+
+        WaveletNoise {
+            tile: noise.map(|&v| T::new((v - mean) * scale)),
+        }
+    }
+
+    /// Number of coefficients along each axis, the noise repeats after this period.
+    pub fn size(&self) -> usize {
+        self.tile.dim().0
+    }
+
+    /// Noise value and gradient `(d/dy, d/dx)` at the position `(y, x)`.
+    pub fn evaluate(&self, pos: (T, T)) -> (T, (T, T)) {
+        let size = self.size();
+        let (my, wy, dy) = spline_weights(pos.0);
+        let (mx, wx, dx) = spline_weights(pos.1);
+
+        let mut value = T::zero();
+        let mut gradient = (T::zero(), T::zero());
+        for j in 0..3 {
+            let y = modulo(my + j as isize - 1, size);
+            for i in 0..3 {
+                let c = self.tile[(y, modulo(mx + i as isize - 1, size))];
+                value += wy[j] * wx[i] * c;
+                gradient.0 += dy[j] * wx[i] * c;
+                gradient.1 += wy[j] * dx[i] * c;
+            }
+        }
+        (value, gradient)
+    }
+
+    /// Divergence-free velocity `(y, x)` from the curl of the noise as stream function.
+    ///
+    /// Ref: [BHN07] Sec. 2
+    pub fn curl(&self, pos: (T, T)) -> (T, T) {
+        let (_, (gy, gx)) = self.evaluate(pos);
+        (-gx, gy)
+    }
+}
+
+/// Turbulence synthesis on a grid refined by an integer factor.
+///
+/// Ref: [KTJG08]
+pub struct WaveletTurbulence<'a, T: Real> {
+    grid: &'a Grid2d,
+    noise: WaveletNoise<T>,
+    scale: usize,
+
+    /// Advected texture coordinates `(y, x)` at the cell centers of the coarse grid.
+    pub texture: (Array2<T>, Array2<T>),
+    /// Velocity of the coarsest detail band relative to the local flow speed.
+    pub strength: T,
+    /// Number of detail bands, each halving the wavelength down to the fine cells.
+    pub octaves: usize,
+    /// Texture coordinates are reset where their Jacobian deviates more from the identity.
+    pub max_distortion: T,
+
+    texture_temp: Array2<T>,
+}
+
+impl<'a, T: Real> WaveletTurbulence<'a, T> {
+    pub fn new<R: Rng>(grid: &'a Grid2d, scale: usize, rng: &mut R) -> Self {
+        let mut octaves = 0;
+        while (2 << octaves) <= scale {
+            octaves += 1;
+        }
+
+        let mut turbulence = WaveletTurbulence {
+            grid,
+            noise: WaveletNoise::new(128, rng),
+            scale,
+
+            texture: (Array2::zeros(grid.dim()), Array2::zeros(grid.dim())),
+            strength: T::new(0.5),
+            octaves: octaves.max(1),
+            max_distortion: T::new(0.5),
+
+            texture_temp: Array2::zeros(grid.dim()),
+        };
+        turbulence.reset_texture();
+        turbulence
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    /// Refined grid with the same extent and boundary conditions.
+    pub fn fine_grid(&self) -> Grid2d {
+        let (h, w) = self.grid.dim();
+        let (dy, dx) = self.grid.spacing();
+        let scale = self.scale as f64;
+        Grid2d::with_boundary((h * self.scale, w * self.scale), self.grid.boundary())
+            .with_spacing((dy / scale, dx / scale))
+    }
+
+    /// Reset the texture coordinates to the cell centers.
+    pub fn reset_texture(&mut self) {
+        let half = T::new(0.5);
+        for ((y, _), t) in self.texture.0.indexed_iter_mut() {
+            *t = T::new(y) + half;
+        }
+        for ((_, x), t) in self.texture.1.indexed_iter_mut() {
+            *t = T::new(x) + half;
+        }
+    }
+
+    /// Advect the texture coordinates with the coarse flow.
+    ///
+    /// Cells with distorted coordinates are reset, which regenerates the detail there.
+    /// Returns the number of reset cells.
+    pub fn advect(&mut self, velocity: &Staggered2d<T>, timestep: T) -> usize {
+        let grid = self.grid;
+        let offset = advection::offset_center();
+        Scheme::SemiLagrangian.advect_field(grid, self.texture_temp.view_mut(), self.texture.0.view(), offset, velocity, timestep);
+        self.texture.0.assign(&self.texture_temp);
+        Scheme::SemiLagrangian.advect_field(grid, self.texture_temp.view_mut(), self.texture.1.view(), offset, velocity, timestep);
+        self.texture.1.assign(&self.texture_temp);
+
+        // central differences of the texture coordinates
+        let (h, w) = grid.dim();
+        let (half, max_distortion) = (T::new(0.5), self.max_distortion);
+        let mut num_reset = 0;
+        for y in 0..h {
+            for x in 0..w {
+                let (y0, y1) = (y.saturating_sub(1), (y + 1).min(h - 1));
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(w - 1));
+                let (ly, lx) = (T::new(y1 - y0).max(T::one()), T::new(x1 - x0).max(T::one()));
+                let jacobian = [
+                    (self.texture.0[(y1, x)] - self.texture.0[(y0, x)]) / ly - T::one(),
+                    (self.texture.0[(y, x1)] - self.texture.0[(y, x0)]) / lx,
+                    (self.texture.1[(y1, x)] - self.texture.1[(y0, x)]) / ly,
+                    (self.texture.1[(y, x1)] - self.texture.1[(y, x0)]) / lx - T::one(),
+                ];
+                if jacobian.iter().any(|d| d.abs() > max_distortion) {
+                    self.texture.0[(y, x)] = T::new(y) + half;
+                    self.texture.1[(y, x)] = T::new(x) + half;
+                    num_reset += 1;
+                }
+            }
+        }
+        num_reset
+    }
+
+    /// Upsampled coarse velocity with added detail, stored on the `fine_grid`.
+    pub fn synthesize(&self, velocity: &Staggered2d<T>, fine: &mut Staggered2d<T>) {
+        let grid = self.grid;
+        let scale = T::new(self.scale);
+        let (h, w) = grid.dim();
+        let [by, bx] = grid.boundary();
+        let (vertical, horizontal) = velocity.split();
+
+        // stream function on the fine vertices in units of the fine edge values
+        let stream = Array2::from_shape_fn((h * self.scale + 1, w * self.scale + 1), |(y, x)| {
+            let wall_y = (y == 0 && by.lower.is_wall()) || (y == h * self.scale && by.upper.is_wall());
+            let wall_x = (x == 0 && bx.lower.is_wall()) || (x == w * self.scale && bx.upper.is_wall());
+            if wall_y || wall_x {
+                return T::zero();
+            }
+
+            let pos = (T::new(y) / scale, T::new(x) / scale);
+            let (vy, vx) = advection::sample_velocity(grid, velocity, pos);
+            let speed = (vy * vy + vx * vx).sqrt() / scale;
+            let offset = advection::offset_center();
+            let texture = (
+                advection::sample(grid, self.texture.0.view(), offset, pos) * scale,
+                advection::sample(grid, self.texture.1.view(), offset, pos) * scale,
+            );
+
+            // octave `i` has a wavelength of `2^i` fine cells
+            let mut sum = T::zero();
+            for i in 0..self.octaves {
+                let wavelength = T::new(1 << i);
+                let weight = T::new(2.0).powf(T::new(-5.0 / 6.0) * T::new(self.octaves - 1 - i));
+                let (value, _) = self.noise.evaluate((texture.0 / wavelength, texture.1 / wavelength));
+                sum += weight * wavelength * value;
+            }
+            self.strength * speed * sum
+        });
+
+        let (mut fine_vertical, mut fine_horizontal) = fine.split_mut();
+        assert_eq!(fine_vertical.dim(), (h * self.scale + 1, w * self.scale));
+        for ((y, x), v) in fine_vertical.indexed_iter_mut() {
+            let pos = (T::new(y) / scale, (T::new(x) + T::new(0.5)) / scale);
+            let base = advection::sample(grid, vertical, advection::offset_vertical(), pos) / scale;
+            *v = base - (stream[(y, x + 1)] - stream[(y, x)]);
+        }
+        for ((y, x), v) in fine_horizontal.indexed_iter_mut() {
+            let pos = ((T::new(y) + T::new(0.5)) / scale, T::new(x) / scale);
+            let base = advection::sample(grid, horizontal, advection::offset_horizontal(), pos) / scale;
+            *v = base + (stream[(y + 1, x)] - stream[(y, x)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dec::manifold::Manifold2d;
+    use domain::{AxisBoundary, BoundaryCondition};
+    use rand::{SeedableRng, XorShiftRng};
+    use super::*;
+
+    #[test]
+    fn wavelet_turbulence() {
+        let mut rng = XorShiftRng::from_seed([3, 1, 4, 1]);
+        let noise = WaveletNoise::<f64>::new(32, &mut rng);
+        let mean = noise.tile.scalar_sum() / (32.0 * 32.0);
+        assert!(mean.abs() < 1.0e-9);
+        let (a, grad_a) = noise.evaluate((0.3, 1.7));
+        let (b, grad_b) = noise.evaluate((32.3, -30.3));
+        assert!((a - b).abs() < 1.0e-9 && (grad_a.0 - grad_b.0).abs() < 1.0e-9);
+        // gradient matches finite differences
+        let (c, _) = noise.evaluate((0.3, 1.7 + 1.0e-6));
+        assert!(((c - a) / 1.0e-6 - grad_a.1).abs() < 1.0e-4);
+
+        let periodic = AxisBoundary { lower: BoundaryCondition::Periodic, upper: BoundaryCondition::Periodic };
+        let grid = Grid2d::with_boundary((8, 8), [periodic, AxisBoundary::default()]);
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        velocity.split_mut().1.fill(1.0);
+
+        let mut turbulence = WaveletTurbulence::new(&grid, 4, &mut rng);
+        assert_eq!(turbulence.octaves, 2);
+        assert_eq!(turbulence.advect(&velocity, 0.25), 0);
+        assert!((turbulence.texture.1[(4, 4)] - 4.25).abs() < 1.0e-9);
+
+        let fine_grid = turbulence.fine_grid();
+        assert_eq!(fine_grid.dim(), (32, 32));
+        let mut fine = <Grid2d as Manifold2d<f64>>::new_simplex_1(&fine_grid);
+        turbulence.synthesize(&velocity, &mut fine);
+
+        let (vertical, horizontal) = fine.split();
+        let mut detail = 0.0f64;
+        for y in 0..32 {
+            for x in 0..32 {
+                let divergence = vertical[(y + 1, x)] - vertical[(y, x)] + horizontal[(y, x + 1)] - horizontal[(y, x)];
+                assert!(divergence.abs() < 1.0e-9, "{}", divergence);
+                detail = detail.max((horizontal[(y, x)] - 0.25).abs());
+            }
+        }
+        assert!(detail > 1.0e-3);
+        // no flux through the walls
+        for y in 0..32 {
+            assert!((horizontal[(y, 0)] - 0.25).abs() < 1.0e-12);
+            assert!((horizontal[(y, 32)] - 0.25).abs() < 1.0e-12);
+        }
+    }
+}
@@ -0,0 +1,623 @@
+//! Validation benchmarks
+//!
+//! Scenarios with analytic or published reference solutions for checking
+//! solvers and their configuration. Each benchmark sets up its domain and
+//! initial state, advances a solver and reports `ErrorNorms` against the
+//! reference:
+//!
+//!  - `TaylorGreen`: decaying vortex array on a periodic domain, analytic.
+//!  - `Poiseuille`: steady channel flow driven by a body force, analytic.
+//!  - `LidDrivenCavity`: steady cavity flow at `Re = 100`, centerline
+//!    velocities of [GGS82].
+//!  - `DamBreak`: collapsing water column for SPH solvers, surge front
+//!    positions of [MM52].
+//!
+//! Grid benchmarks run a default `Pipeline` with `run` or any `GridSolver`
+//! initialized with the benchmark state with `run_solver`.
+//!
+//! `Convergence` repeats a measurement over a sequence of resolutions and
+//! reports the observed order of accuracy, e.g. of the discrete operators
+//! against an analytic field or of a benchmark as a whole.
+//!
+//! References:
+//!     [TG37] Geoffrey I. Taylor and Albert E. Green, 1937,
+//!            Mechanism of the production of small eddies from large ones,
+//!            Proc. R. Soc. Lond. A 158, 499-521
+//!     [GGS82] Urmila Ghia, Kirti N. Ghia and C. T. Shin, 1982,
+//!             High-Re solutions for incompressible flow using the Navier-Stokes equations and a multigrid method,
+//!             Journal of Computational Physics 48, 387-411
+//!     [MM52] J. C. Martin and W. J. Moyce, 1952,
+//!            An experimental study of the collapse of liquid columns on a rigid horizontal plane,
+//!            Phil. Trans. R. Soc. Lond. A 244, 312-324
+
+use advection::Scheme;
+use dec::grid::Staggered2d;
+use dec::manifold::Manifold2d;
+use domain::{AxisBoundary, BoundaryCondition, Grid2d};
+use math::{Real, VectorN};
+use math::vector_n::vec2;
+use ndarray::Array2;
+use particle::Particles;
+use solvers::pipeline::{Advection, BoundaryConditions, GridSolver, GridState, Pipeline,
+                        PressureProjection, Stage, ViscousDiffusion};
+use sph::boundary::BoundarySampler;
+use sph::property::{Mass, Position};
+use sph::solver::Solver;
+use typenum::U2;
+
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Deviation of a simulation from the reference solution.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorNorms<T> {
+    /// Mean absolute error.
+    pub l1: T,
+    /// Root mean square error.
+    pub l2: T,
+    /// Maximum absolute error.
+    pub linf: T,
+}
+
+impl<T: Real> ErrorNorms<T> {
+    /// Norms of a set of pointwise errors, zero if empty.
+    pub fn from_errors<I: IntoIterator<Item = T>>(errors: I) -> Self {
+        let (mut sum, mut sum_squares, mut max, mut count) = (T::zero(), T::zero(), T::zero(), 0);
+        for error in errors {
+            let error = error.abs();
+            sum = sum + error;
+            sum_squares = sum_squares + error * error;
+            max = max.max(error);
+            count += 1;
+        }
+        let n = T::new(count.max(1));
+        ErrorNorms { l1: sum / n, l2: (sum_squares / n).sqrt(), linf: max }
+    }
+
+    /// Errors relative to the norms of the reference solution.
+    pub fn relative(&self, reference: &ErrorNorms<T>) -> Self {
+        ErrorNorms {
+            l1: self.l1 / reference.l1,
+            l2: self.l2 / reference.l2,
+            linf: self.linf / reference.linf,
+        }
+    }
+
+    pub fn get(&self, norm: Norm) -> T {
+        match norm {
+            Norm::L1 => self.l1,
+            Norm::L2 => self.l2,
+            Norm::Linf => self.linf,
+        }
+    }
+}
+
+/// Selects one of the `ErrorNorms`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Norm {
+    L1,
+    L2,
+    Linf,
+}
+
+/// Error norms of a field of cell values against a function of the cell center `(y, x)`.
+pub fn cell_errors<T, F>(grid: &Grid2d, field: &Array2<T>, exact: F) -> ErrorNorms<T>
+    where T: Real, F: Fn(f64, f64) -> f64
+{
+    assert_eq!(field.dim(), grid.dim(), "field dimension mismatch");
+    let (dy, dx) = grid.spacing();
+    ErrorNorms::from_errors(field.indexed_iter().map(|((j, i), &v)| {
+        v - T::new(exact((j as f64 + 0.5) * dy, (i as f64 + 0.5) * dx))
+    }))
+}
+
+/// Velocity `(v, u)` of a function of the position `(y, x)`, integrated along the edges.
+fn sample_velocity<T, F>(grid: &Grid2d, velocity: F) -> Staggered2d<T>
+    where T: Real, F: Fn(f64, f64) -> (f64, f64)
+{
+    let (dy, dx) = grid.spacing();
+    let mut result = <Grid2d as Manifold2d<T>>::new_simplex_1(grid);
+    {
+        let (mut vertical, mut horizontal) = result.split_mut();
+        for ((j, i), v) in vertical.indexed_iter_mut() {
+            *v = T::new(velocity(j as f64 * dy, (i as f64 + 0.5) * dx).0 * dy);
+        }
+        for ((j, i), u) in horizontal.indexed_iter_mut() {
+            *u = T::new(velocity((j as f64 + 0.5) * dy, i as f64 * dx).1 * dx);
+        }
+    }
+    result
+}
+
+/// Error norms of the point velocities `(v, u)` on all edges against a function of `(y, x)`.
+pub fn velocity_errors<T, F>(grid: &Grid2d, velocity: &Staggered2d<T>, exact: F) -> ErrorNorms<T>
+    where T: Real, F: Fn(f64, f64) -> (f64, f64)
+{
+    let (dy, dx) = grid.spacing();
+    let (vertical, horizontal) = velocity.split();
+    let vertical = vertical.indexed_iter().map(|((j, i), &v)| {
+        v / T::new(dy) - T::new(exact(j as f64 * dy, (i as f64 + 0.5) * dx).0)
+    });
+    let horizontal = horizontal.indexed_iter().map(|((j, i), &u)| {
+        u / T::new(dx) - T::new(exact((j as f64 + 0.5) * dy, i as f64 * dx).1)
+    });
+    ErrorNorms::from_errors(vertical.chain(horizontal))
+}
+
+/// Advance a grid solver by `duration` with timesteps of at most `timestep`.
+fn advance<T: Real, S: GridSolver<T>>(solver: &mut S, duration: T, timestep: T) {
+    let steps = (duration / timestep).ceil().to_usize().unwrap().max(1);
+    let timestep = duration / T::new(steps);
+    for _ in 0..steps {
+        solver.step(timestep);
+    }
+}
+
+/// Decaying Taylor–Green vortices on the periodic domain `[0, 2π]²`.
+///
+/// `u = sin x cos y e^{-2νt}`, `v = -cos x sin y e^{-2νt}`.
+#[derive(Copy, Clone, Debug)]
+pub struct TaylorGreen<T> {
+    /// Number of cells per side.
+    pub resolution: usize,
+    pub viscosity: T,
+    pub duration: T,
+    pub timestep: T,
+}
+
+impl<T: Real> TaylorGreen<T> {
+    /// Benchmark with `ν = 0.05` over one time unit at a CFL number of `0.5`.
+    pub fn new(resolution: usize) -> Self {
+        TaylorGreen {
+            resolution,
+            viscosity: T::new(0.05),
+            duration: T::one(),
+            timestep: T::new(PI / resolution as f64),
+        }
+    }
+
+    pub fn grid(&self) -> Grid2d {
+        let spacing = 2.0 * PI / self.resolution as f64;
+        Grid2d::with_boundary((self.resolution, self.resolution), [AxisBoundary::periodic(); 2])
+            .with_spacing((spacing, spacing))
+    }
+
+    /// Point velocity `(v, u)` at position `(y, x)` and time `t`.
+    pub fn exact(&self, (y, x): (f64, f64), time: f64) -> (f64, f64) {
+        let decay = (-2.0 * self.viscosity.to_f64().unwrap() * time).exp();
+        (-x.cos() * y.sin() * decay, x.sin() * y.cos() * decay)
+    }
+
+    /// Analytic velocity as dual 1-form at time `t`.
+    pub fn velocity(&self, grid: &Grid2d, time: T) -> Staggered2d<T> {
+        let time = time.to_f64().unwrap();
+        sample_velocity(grid, |y, x| self.exact((y, x), time))
+    }
+
+    pub fn error(&self, grid: &Grid2d, velocity: &Staggered2d<T>, time: T) -> ErrorNorms<T> {
+        let time = time.to_f64().unwrap();
+        velocity_errors(grid, velocity, |y, x| self.exact((y, x), time))
+    }
+
+    /// Errors at the end of the benchmark for a solver starting from `velocity(grid, 0)`.
+    pub fn run_solver<S: GridSolver<T>>(&self, solver: &mut S) -> ErrorNorms<T> {
+        advance(solver, self.duration, self.timestep);
+        self.error(solver.grid(), solver.velocity(), self.duration)
+    }
+
+    /// Run with MacCormack advection, implicit viscosity and pressure projection.
+    pub fn run(&self) -> ErrorNorms<T> {
+        let grid = self.grid();
+        let mut pipeline = Pipeline::new(&grid);
+        pipeline.state.velocity = self.velocity(&grid, T::zero());
+        pipeline
+            .with(Advection::new(Scheme::MacCormack))
+            .with(accurate_diffusion(&grid, self.viscosity))
+            .with(accurate_projection(&grid));
+        self.run_solver(&mut pipeline)
+    }
+}
+
+fn accurate_diffusion<T: Real>(grid: &Grid2d, viscosity: T) -> ViscousDiffusion<T> {
+    let mut diffusion = ViscousDiffusion::new(grid, viscosity);
+    diffusion.solver.threshold = T::new(1.0e-10);
+    diffusion
+}
+
+fn accurate_projection<T: Real>(grid: &Grid2d) -> PressureProjection<T> {
+    let mut projection = PressureProjection::new(grid);
+    projection.projection.threshold = T::new(1.0e-10);
+    projection
+}
+
+/// Channel flow between no-slip walls at `y = 0` and `y = 1`, periodic along `x`.
+///
+/// A body force `f` along `x` drives the flow towards the parabolic profile
+/// `u = f/(2ν) y (1 - y)`.
+#[derive(Copy, Clone, Debug)]
+pub struct Poiseuille<T> {
+    /// Number of cells across the channel.
+    pub resolution: usize,
+    pub viscosity: T,
+    pub force: T,
+    pub duration: T,
+    pub timestep: T,
+}
+
+impl<T: Real> Poiseuille<T> {
+    /// Benchmark with unit peak velocity, run until the transient has decayed.
+    pub fn new(resolution: usize) -> Self {
+        Poiseuille {
+            resolution,
+            viscosity: T::one(),
+            force: T::new(8.0),
+            duration: T::new(2.0),
+            timestep: T::new(0.05),
+        }
+    }
+
+    pub fn grid(&self) -> Grid2d {
+        let spacing = 1.0 / self.resolution as f64;
+        let walls = AxisBoundary::uniform(BoundaryCondition::NoSlip);
+        Grid2d::with_boundary((self.resolution, 4), [walls, AxisBoundary::periodic()])
+            .with_spacing((spacing, spacing))
+    }
+
+    /// Steady point velocity `(v, u)` at position `(y, x)`.
+    pub fn exact(&self, (y, _): (f64, f64)) -> (f64, f64) {
+        let (f, nu) = (self.force.to_f64().unwrap(), self.viscosity.to_f64().unwrap());
+        (0.0, f / (2.0 * nu) * y * (1.0 - y))
+    }
+
+    pub fn error(&self, grid: &Grid2d, velocity: &Staggered2d<T>) -> ErrorNorms<T> {
+        velocity_errors(grid, velocity, |y, x| self.exact((y, x)))
+    }
+
+    /// Errors of a solver starting at rest, which needs to apply `force` along `x`.
+    pub fn run_solver<S: GridSolver<T>>(&self, solver: &mut S) -> ErrorNorms<T> {
+        advance(solver, self.duration, self.timestep);
+        self.error(solver.grid(), solver.velocity())
+    }
+
+    /// Run with the body force, implicit viscosity and pressure projection.
+    pub fn run(&self) -> ErrorNorms<T> {
+        let grid = self.grid();
+        let force = self.force * T::new(grid.spacing().1);
+        let mut pipeline = Pipeline::new(&grid);
+        pipeline
+            .with(move |state: &mut GridState<T>, timestep: T| {
+                let (_, mut horizontal) = state.velocity.split_mut();
+                horizontal.mapv_inplace(|u| u + force * timestep);
+            })
+            .with(accurate_diffusion(&grid, self.viscosity))
+            .with(accurate_projection(&grid));
+        self.run_solver(&mut pipeline)
+    }
+}
+
+/// Tangential velocity of the upper wall, coupled through the viscous diffusion.
+///
+/// No-slip walls are at rest in `Viscosity`. The moving wall adds the
+/// difference of its ghost values to the right hand side, the stage needs to
+/// run directly before the `ViscousDiffusion` stage with the same viscosity.
+#[derive(Copy, Clone, Debug)]
+pub struct MovingLid<T> {
+    pub speed: T,
+    pub viscosity: T,
+}
+
+impl<T: Real> Stage<T> for MovingLid<T> {
+    fn apply(&mut self, state: &mut GridState<T>, timestep: T) {
+        let (dy, dx) = state.grid.spacing();
+        let (h, w) = state.grid.dim();
+        // ghost value `2U - u` instead of `-u` of the resting wall
+        let source = T::new(2.0 * dx / (dy * dy)) * self.speed * self.viscosity * timestep;
+        let (_, mut horizontal) = state.velocity.split_mut();
+        for i in 1..w {
+            horizontal[(h - 1, i)] = horizontal[(h - 1, i)] + source;
+        }
+    }
+}
+
+/// Horizontal velocity along the vertical centerline at `Re = 100`, [GGS82] Table I.
+const GHIA_RE100: [(f64, f64); 17] = [
+    (0.0000, 0.00000), (0.0547, -0.03717), (0.0625, -0.04192), (0.0703, -0.04775),
+    (0.1016, -0.06434), (0.1719, -0.10150), (0.2813, -0.15662), (0.4531, -0.21090),
+    (0.5000, -0.20581), (0.6172, -0.13641), (0.7344, 0.00332), (0.8516, 0.23151),
+    (0.9531, 0.68717), (0.9609, 0.73722), (0.9688, 0.78871), (0.9766, 0.84123),
+    (1.0000, 1.00000),
+];
+
+/// Unit square with a lid moving at unit speed along `x` at `y = 1`, `Re = 100`.
+#[derive(Copy, Clone, Debug)]
+pub struct LidDrivenCavity<T> {
+    /// Number of cells per side, needs to be even.
+    pub resolution: usize,
+    pub duration: T,
+    pub timestep: T,
+}
+
+impl<T: Real> LidDrivenCavity<T> {
+    /// Benchmark run until the flow is close to steady, at a CFL number of `0.5`.
+    pub fn new(resolution: usize) -> Self {
+        assert!(resolution % 2 == 0, "resolution needs to be even");
+        LidDrivenCavity {
+            resolution,
+            duration: T::new(20.0),
+            timestep: T::new(0.5 / resolution as f64),
+        }
+    }
+
+    pub fn viscosity(&self) -> T {
+        T::new(0.01)
+    }
+
+    pub fn grid(&self) -> Grid2d {
+        let spacing = 1.0 / self.resolution as f64;
+        Grid2d::with_boundary((self.resolution, self.resolution), [AxisBoundary::uniform(BoundaryCondition::NoSlip); 2])
+            .with_spacing((spacing, spacing))
+    }
+
+    /// Errors of the centerline velocity at the sample points of [GGS82].
+    pub fn error(&self, grid: &Grid2d, velocity: &Staggered2d<T>) -> ErrorNorms<T> {
+        let (h, w) = grid.dim();
+        let (dy, dx) = grid.spacing();
+        let (_, horizontal) = velocity.split();
+
+        // centerline profile including the walls
+        let mut profile = vec![(0.0, 0.0)];
+        profile.extend((0..h).map(|j| ((j as f64 + 0.5) * dy, horizontal[(j, w / 2)].to_f64().unwrap() / dx)));
+        profile.push((1.0, 1.0));
+
+        let errors = GHIA_RE100[1..GHIA_RE100.len() - 1].iter().map(|&(y, reference)| {
+            let k = profile.iter().position(|&(py, _)| py >= y).unwrap().max(1);
+            let ((y0, u0), (y1, u1)) = (profile[k - 1], profile[k]);
+            let u = u0 + (u1 - u0) * (y - y0) / (y1 - y0);
+            T::new(u - reference)
+        });
+        ErrorNorms::from_errors(errors)
+    }
+
+    /// Errors of a solver starting at rest, which needs to drive the flow with a `MovingLid`.
+    pub fn run_solver<S: GridSolver<T>>(&self, solver: &mut S) -> ErrorNorms<T> {
+        advance(solver, self.duration, self.timestep);
+        self.error(solver.grid(), solver.velocity())
+    }
+
+    /// Run with MacCormack advection, the moving lid, implicit viscosity and pressure projection.
+    pub fn run(&self) -> ErrorNorms<T> {
+        let grid = self.grid();
+        let viscosity = self.viscosity();
+        let mut pipeline = Pipeline::new(&grid);
+        pipeline
+            .with(Advection::new(Scheme::MacCormack))
+            .with(BoundaryConditions)
+            .with(MovingLid { speed: T::one(), viscosity })
+            .with(accurate_diffusion(&grid, viscosity))
+            .with(accurate_projection(&grid));
+        self.run_solver(&mut pipeline)
+    }
+}
+
+/// Surge front `z/a` over the time `t sqrt(2g/a)` of a column with height `2a`, [MM52].
+const MARTIN_MOYCE: [(f64, f64); 15] = [
+    (0.41, 1.11), (0.84, 1.22), (1.19, 1.44), (1.43, 1.67), (1.63, 1.89),
+    (1.83, 2.11), (1.98, 2.33), (2.20, 2.56), (2.32, 2.78), (2.51, 3.00),
+    (2.65, 3.22), (2.83, 3.44), (2.98, 3.67), (3.11, 3.89), (3.33, 4.11),
+];
+
+/// Collapse of a water column of width `a` and height `2a` in a tank of length `5a`.
+#[derive(Copy, Clone, Debug)]
+pub struct DamBreak<T> {
+    /// Width `a` of the column.
+    pub width: T,
+    /// Particle spacing.
+    pub spacing: T,
+    pub rest_density: T,
+    pub timestep: T,
+}
+
+impl<T: Real> DamBreak<T> {
+    /// Column of `particles` particles across its width with a width of `0.146 m`.
+    ///
+    /// The timestep is small enough for the default speed of sound of `Wcsph`.
+    pub fn new(particles: usize) -> Self {
+        let width = 0.146;
+        let spacing = width / particles as f64;
+        DamBreak {
+            width: T::new(width),
+            spacing: T::new(spacing),
+            rest_density: T::new(1000.0),
+            timestep: T::new(0.025 * spacing),
+        }
+    }
+
+    /// Smoothing radius of the solver, twice the particle spacing.
+    pub fn smoothing_radius(&self) -> T {
+        self.spacing * T::new(2.0)
+    }
+
+    /// Neighborhood grid cells of the solver covering the tank.
+    pub fn num_cells(&self) -> VectorN<usize, U2> {
+        let cells = |length: T| (length / self.smoothing_radius()).ceil().to_usize().unwrap() + 4;
+        vec2(cells(self.width * T::new(5.0)), cells(self.width * T::new(3.0)))
+    }
+
+    /// Lower left corner of the tank, with space for the boundary particles.
+    fn origin(&self) -> VectorN<T, U2> {
+        let margin = self.smoothing_radius() * T::new(2.0);
+        vec2(margin, margin)
+    }
+
+    /// Set the tank boundary of the solver and add the column particles.
+    pub fn setup<S: Solver<T>>(&self, solver: &mut S, particles: &mut Particles) {
+        let (a, s, origin) = (self.width, self.spacing, self.origin());
+        let (length, height) = (a * T::new(5.0), a * T::new(3.0));
+        let tank = [
+            origin + vec2(T::zero(), height),
+            origin,
+            origin + vec2(length, T::zero()),
+            origin + vec2(length, height),
+        ];
+        solver.pipeline_mut().set_boundary(BoundarySampler::new(s).polyline(&tank, false));
+
+        let half = T::new(0.5);
+        let (nx, ny) = ((a / s).round().to_usize().unwrap(), (a * T::new(2.0) / s).round().to_usize().unwrap());
+        let positions = (0..ny)
+            .flat_map(|y| (0..nx).map(move |x| (x, y)))
+            .map(|(x, y)| origin + vec2((T::new(x) + half) * s, (T::new(y) + half) * s))
+            .collect::<Vec<_>>();
+        let masses = vec![self.rest_density * s * s; positions.len()];
+        particles.add_particles(positions.len())
+                 .with::<Position<T, U2>>(&positions)
+                 .with::<Mass<T>>(&masses);
+    }
+
+    /// Errors of the surge front `z/a` of a solver with the parameters of the benchmark.
+    ///
+    /// The solver needs the `num_cells`, `smoothing_radius` and `rest_density` of the benchmark.
+    pub fn run_solver<S: Solver<T>>(&self, solver: &mut S) -> ErrorNorms<T> {
+        let mut particles = Particles::new();
+        solver.init(&mut particles);
+        self.setup(solver, &mut particles);
+
+        let a = self.width;
+        let gravity = solver.pipeline().gravity[1].abs();
+        let time_scale = (T::new(2.0) * gravity / a).sqrt();
+        let x0 = self.origin()[0];
+        let front = |particles: &Particles| {
+            particles.read_property::<Position<T, U2>>().iter()
+                .fold(x0, |front, p| front.max(p[0])) - x0
+        };
+
+        let mut time = T::zero();
+        let mut errors = Vec::new();
+        for &(reference_time, reference_front) in MARTIN_MOYCE.iter() {
+            while time * time_scale < T::new(reference_time) {
+                solver.step(&mut particles, self.timestep);
+                time = time + self.timestep;
+            }
+            errors.push(front(&particles) / a - T::new(reference_front));
+        }
+        ErrorNorms::from_errors(errors)
+    }
+}
+
+/// Errors of a measurement repeated over increasing resolutions.
+///
+/// ```ignore
+/// let convergence = Convergence::measure(&[8, 16, 32], |n| TaylorGreen::<f64>::new(n).run());
+/// println!("{}", convergence);
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Convergence<T> {
+    /// Number of cells along the refined axis, increasing.
+    pub resolutions: Vec<usize>,
+    pub errors: Vec<ErrorNorms<T>>,
+}
+
+impl<T: Real> Convergence<T> {
+    /// Run `measure` for each of the increasing `resolutions`.
+    pub fn measure<F>(resolutions: &[usize], mut measure: F) -> Self
+        where F: FnMut(usize) -> ErrorNorms<T>
+    {
+        assert!(resolutions.len() >= 2, "convergence requires at least two resolutions");
+        assert!(resolutions.windows(2).all(|w| w[0] < w[1]), "resolutions need to be increasing");
+        Convergence {
+            resolutions: resolutions.to_vec(),
+            errors: resolutions.iter().map(|&n| measure(n)).collect(),
+        }
+    }
+
+    /// Observed order between consecutive resolutions, `log(e_i / e_{i+1}) / log(n_{i+1} / n_i)`.
+    pub fn orders(&self, norm: Norm) -> Vec<T> {
+        (1..self.resolutions.len()).map(|i| {
+            let ratio = T::new(self.resolutions[i] as f64 / self.resolutions[i - 1] as f64);
+            (self.errors[i - 1].get(norm) / self.errors[i].get(norm)).ln() / ratio.ln()
+        }).collect()
+    }
+
+    /// Order `p` of the least squares fit of `e = C n^{-p}` over all resolutions.
+    pub fn order(&self, norm: Norm) -> T {
+        let points = self.resolutions.iter().zip(self.errors.iter())
+            .map(|(&n, e)| (T::new(n).ln(), e.get(norm).ln()))
+            .collect::<Vec<_>>();
+        let count = T::new(points.len());
+        let (mx, my) = points.iter().fold((T::zero(), T::zero()), |(x, y), &(px, py)| (x + px, y + py));
+        let (mx, my) = (mx / count, my / count);
+        let (cov, var) = points.iter().fold((T::zero(), T::zero()), |(c, v), &(px, py)| {
+            (c + (px - mx) * (py - my), v + (px - mx) * (px - mx))
+        });
+        -cov / var
+    }
+}
+
+impl<T: Real> fmt::Display for Convergence<T> {
+    /// Table of the errors with the observed orders towards the previous resolution.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>6} {:>12} {:>6} {:>12} {:>6} {:>12} {:>6}", "n", "l1", "order", "l2", "order", "linf", "order")?;
+        let orders = [Norm::L1, Norm::L2, Norm::Linf].iter().map(|&norm| self.orders(norm)).collect::<Vec<_>>();
+        for (i, (n, e)) in self.resolutions.iter().zip(self.errors.iter()).enumerate() {
+            write!(f, "{:>6}", n)?;
+            for (k, &value) in [e.l1, e.l2, e.linf].iter().enumerate() {
+                write!(f, " {:>12.4e}", value.to_f64().unwrap())?;
+                if i > 0 {
+                    write!(f, " {:>6.2}", orders[k][i - 1].to_f64().unwrap())?;
+                } else {
+                    write!(f, " {:>6}", "-")?;
+                }
+            }
+            writeln!(f, "")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_benchmarks() {
+        // viscous decay is captured well below the decay of the amplitude
+        let taylor_green = TaylorGreen::<f64>::new(16);
+        let grid = taylor_green.grid();
+        let errors = taylor_green.run();
+        let undamped = taylor_green.error(&grid, &taylor_green.velocity(&grid, 0.0), taylor_green.duration);
+        assert!(undamped.linf > 0.08);
+        assert!(errors.linf < 0.75 * undamped.linf, "{:?}", errors);
+        assert!(errors.l1 <= errors.l2 && errors.l2 <= errors.linf);
+
+        // second order wall treatment offsets the parabola by `f dy² / 8ν`
+        let poiseuille = Poiseuille::<f64>::new(16);
+        let errors = poiseuille.run();
+        assert!(errors.linf < 0.01, "{:?}", errors);
+    }
+
+    #[test]
+    fn operator_convergence() {
+        use dec::ops;
+
+        // laplacian of `sin x cos 2y` on the periodic domain `[0, 2π]²`
+        let f = |y: f64, x: f64| x.sin() * (2.0 * y).cos();
+        let convergence = Convergence::measure(&[8, 16, 32, 64], |n| {
+            let grid = TaylorGreen::<f64>::new(n).grid();
+            let (dy, dx) = grid.spacing();
+            let scalar = Array2::from_shape_fn(grid.dim(), |(j, i)| f((j as f64 + 0.5) * dy, (i as f64 + 0.5) * dx));
+            let mut laplacian = <Grid2d as Manifold2d<f64>>::new_simplex_2(&grid);
+            ops::laplacian(&grid, &mut laplacian, &scalar);
+
+            // the error is a multiple of the sampled mode, whose maximum on the
+            // coarse grids lies between the cell centers: relative to the sampled
+            // solution the maximum norm only measures the truncation error
+            let exact = ErrorNorms::from_errors(scalar.iter().map(|&v| 5.0 * v));
+            cell_errors(&grid, &laplacian, |y, x| -5.0 * f(y, x)).relative(&exact)
+        });
+
+        for &norm in &[Norm::L2, Norm::Linf] {
+            for &order in &convergence.orders(norm) {
+                assert!((order - 2.0).abs() < 0.1, "{}", convergence);
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Vorticity confinement
+//!
+//! Reinjects small scale rotational motion lost due to numerical dissipation
+//! by adding the force `eps * (N x w)`, where `w` is the vorticity and `N` the
+//! normalized gradient of its magnitude. Vorticity is computed as curl on the
+//! dual grid and averaged to the cell centers.
+//!
+//! The curl functions return circulations on the primal vertices (2d) and edges (3d),
+//! see `dec::ops` for the point values on arbitrary manifolds.
+
+use dec::grid::{Staggered2d, Staggered3d};
+use dec::manifold::{Manifold2d, Manifold3d};
+use domain::{Grid2d, Grid3d};
+use math::Real;
+use ndarray::{Array2, Array3};
+
+/// Vorticity at the grid vertices, boundary vertices are set to zero.
+pub fn curl_2d<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>) -> Array2<T> {
+    let (h, w) = grid.dim();
+    let mut curl = <Grid2d as Manifold2d<T>>::new_simplex_0(grid);
+    grid.derivative_1_dual(&mut curl, velocity);
+
+    for ((y, x), c) in curl.indexed_iter_mut() {
+        // `d1 dual` is `-curl` due to the orientation of the horizontal edges
+        *c = if y == 0 || y == h || x == 0 || x == w { T::zero() } else { -*c };
+    }
+    curl
+}
+
+/// Vorticity on the grid edges, boundary edges are set to zero.
+pub fn curl_3d<T: Real>(grid: &Grid3d, velocity: &Staggered3d<T>) -> Staggered3d<T> {
+    let (d, h, w) = grid.dim();
+    let mut curl = <Grid3d as Manifold3d<T>>::new_simplex_1(grid);
+    grid.derivative_1_dual(&mut curl, velocity);
+
+    {
+        let (mut cz, mut cy, mut cx) = curl.split_mut();
+        for ((_, j, i), c) in cz.indexed_iter_mut() {
+            if j == 0 || j == h || i == 0 || i == w { *c = T::zero(); }
+        }
+        for ((k, _, i), c) in cy.indexed_iter_mut() {
+            if k == 0 || k == d || i == 0 || i == w { *c = T::zero(); }
+        }
+        for ((k, j, _), c) in cx.indexed_iter_mut() {
+            if k == 0 || k == d || j == 0 || j == h { *c = T::zero(); }
+        }
+    }
+    curl
+}
+
+/// Vorticity at the cell centers, averaged from the vertices of `curl_2d`.
+pub fn cell_vorticity_2d<T: Real>(grid: &Grid2d, velocity: &Staggered2d<T>) -> Array2<T> {
+    let quarter = T::new(0.25);
+    let curl = curl_2d(grid, velocity);
+    Array2::from_shape_fn(grid.dim(), |(y, x)| {
+        quarter * (curl[(y, x)] + curl[(y, x + 1)] + curl[(y + 1, x)] + curl[(y + 1, x + 1)])
+    })
+}
+
+/// Vorticity vectors `(z, y, x)` at the cell centers, averaged from the edges of `curl_3d`.
+pub fn cell_vorticity_3d<T: Real>(grid: &Grid3d, velocity: &Staggered3d<T>) -> Array3<(T, T, T)> {
+    let quarter = T::new(0.25);
+    let curl = curl_3d(grid, velocity);
+    let (cz, cy, cx) = curl.split();
+    Array3::from_shape_fn(grid.dim(), |(k, j, i)| (
+        quarter * (cz[(k, j, i)] + cz[(k, j + 1, i)] + cz[(k, j, i + 1)] + cz[(k, j + 1, i + 1)]),
+        quarter * (cy[(k, j, i)] + cy[(k + 1, j, i)] + cy[(k, j, i + 1)] + cy[(k + 1, j, i + 1)]),
+        quarter * (cx[(k, j, i)] + cx[(k + 1, j, i)] + cx[(k, j + 1, i)] + cx[(k + 1, j + 1, i)]),
+    ))
+}
+
+/// Normalize a vector `(z, y, x)`, zero vectors are kept.
+fn normalize<T: Real>(v: (T, T, T)) -> (T, T, T) {
+    let len = (v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt();
+    if len > T::new(1.0e-10) { (v.0 / len, v.1 / len, v.2 / len) } else { (T::zero(), T::zero(), T::zero()) }
+}
+
+/// Central difference of `f` at `i`, one-sided at the borders.
+fn difference<T: Real, F: Fn(usize) -> T>(f: F, i: usize, len: usize) -> T {
+    let (lo, hi) = (i.saturating_sub(1), (i + 1).min(len - 1));
+    if hi > lo { (f(hi) - f(lo)) / T::new(hi - lo) } else { T::zero() }
+}
+
+/// Apply vorticity confinement to a 2d velocity field.
+pub fn confinement_2d<T: Real>(grid: &Grid2d, velocity: &mut Staggered2d<T>, epsilon: T, timestep: T) {
+    let (h, w) = grid.dim();
+    let half = T::new(0.5);
+    let vorticity = cell_vorticity_2d(grid, velocity);
+
+    // force (y, x) at the cell centers
+    let force = Array2::from_shape_fn((h, w), |(y, x)| {
+        let grad_y = difference(|y| vorticity[(y, x)].abs(), y, h);
+        let grad_x = difference(|x| vorticity[(y, x)].abs(), x, w);
+        let (_, n_y, n_x) = normalize((T::zero(), grad_y, grad_x));
+        let omega = vorticity[(y, x)];
+        (-epsilon * n_x * omega, epsilon * n_y * omega)
+    });
+
+    let (mut vertical, mut horizontal) = velocity.split_mut();
+    for y in 1..h {
+        for x in 0..w {
+            vertical[(y, x)] += timestep * half * (force[(y - 1, x)].0 + force[(y, x)].0);
+        }
+    }
+    for y in 0..h {
+        for x in 1..w {
+            horizontal[(y, x)] += timestep * half * (force[(y, x - 1)].1 + force[(y, x)].1);
+        }
+    }
+}
+
+/// Apply vorticity confinement to a 3d velocity field stored on the faces.
+pub fn confinement_3d<T: Real>(grid: &Grid3d, velocity: &mut Staggered3d<T>, epsilon: T, timestep: T) {
+    let (d, h, w) = grid.dim();
+    let half = T::new(0.5);
+    let vorticity = cell_vorticity_3d(grid, velocity);
+
+    // force (z, y, x) at the cell centers
+    let force = Array3::from_shape_fn((d, h, w), |(k, j, i)| {
+        let magnitude = |k: usize, j: usize, i: usize| {
+            let v = vorticity[(k, j, i)];
+            (v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt()
+        };
+        let n = normalize((
+            difference(|k| magnitude(k, j, i), k, d),
+            difference(|j| magnitude(k, j, i), j, h),
+            difference(|i| magnitude(k, j, i), i, w),
+        ));
+        let omega = vorticity[(k, j, i)];
+        (
+            epsilon * (n.2 * omega.1 - n.1 * omega.2),
+            epsilon * (n.0 * omega.2 - n.2 * omega.0),
+            epsilon * (n.1 * omega.0 - n.0 * omega.1),
+        )
+    });
+
+    let (mut fz, mut fy, mut fx) = velocity.split_mut();
+    for ((k, j, i), v) in fz.indexed_iter_mut() {
+        if k > 0 && k < d { *v += timestep * half * (force[(k - 1, j, i)].0 + force[(k, j, i)].0); }
+    }
+    for ((k, j, i), v) in fy.indexed_iter_mut() {
+        if j > 0 && j < h { *v += timestep * half * (force[(k, j - 1, i)].1 + force[(k, j, i)].1); }
+    }
+    for ((k, j, i), v) in fx.indexed_iter_mut() {
+        if i > 0 && i < w { *v += timestep * half * (force[(k, j, i - 1)].2 + force[(k, j, i)].2); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rigid_rotation_2d() {
+        let grid = Grid2d::new((6, 7));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (mut vertical, mut horizontal) = velocity.split_mut();
+            for ((_, x), v) in vertical.indexed_iter_mut() { *v = x as f64 + 0.5; }
+            for ((y, _), v) in horizontal.indexed_iter_mut() { *v = -(y as f64 + 0.5); }
+        }
+
+        let curl = curl_2d(&grid, &velocity);
+        for ((y, x), &c) in curl.indexed_iter() {
+            if y == 0 || y == 6 || x == 0 || x == 7 {
+                assert_eq!(c, 0.0);
+            } else {
+                assert!((c - 2.0).abs() < 1.0e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn confinement_2d_vortex() {
+        // counter-clockwise vortex from the stream function `exp(-r^2 / 16)` at the vertices
+        let grid = Grid2d::new((32, 32));
+        let stream = Array2::from_shape_fn((33, 33), |(y, x)| {
+            let (dy, dx) = (y as f64 - 16.0, x as f64 - 16.0);
+            (-(dy * dy + dx * dx) / 16.0).exp()
+        });
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (mut vertical, mut horizontal) = velocity.split_mut();
+            for ((y, x), v) in vertical.indexed_iter_mut() { *v = stream[(y, x)] - stream[(y, x + 1)]; }
+            for ((y, x), v) in horizontal.indexed_iter_mut() { *v = stream[(y + 1, x)] - stream[(y, x)]; }
+        }
+        let before = cell_vorticity_2d(&grid, &velocity);
+        assert!(before[(15, 15)] > 0.0);
+
+        let reference = velocity.clone();
+        confinement_2d(&grid, &mut velocity, 1.0, 0.1);
+        let (vertical, horizontal) = velocity.split();
+        let (vertical_ref, horizontal_ref) = reference.split();
+
+        // inside the core `|w|` decreases outwards, the gradient `N` points toward the core and the force
+        // `N x w` is tangential, spinning the vortex up in its sense of rotation
+        assert!(vertical[(16, 18)] - vertical_ref[(16, 18)] > 0.0);
+        assert!(vertical[(16, 13)] - vertical_ref[(16, 13)] < 0.0);
+        assert!(horizontal[(18, 16)] - horizontal_ref[(18, 16)] < 0.0);
+        assert!(horizontal[(13, 16)] - horizontal_ref[(13, 16)] > 0.0);
+
+        // vorticity is concentrated at the core
+        let after = cell_vorticity_2d(&grid, &velocity);
+        let core = |w: &Array2<f64>| w.slice(s![14..18, 14..18]).scalar_sum();
+        assert!(core(&after) > core(&before));
+    }
+
+    #[test]
+    fn rigid_rotation_3d() {
+        let grid = Grid3d::new((3, 8, 8));
+        let mut velocity = <Grid3d as Manifold3d<f64>>::new_simplex_2(&grid);
+        {
+            let (_, mut fy, mut fx) = velocity.split_mut();
+            for ((_, _, i), v) in fy.indexed_iter_mut() { *v = i as f64 + 0.5; }
+            for ((_, j, _), v) in fx.indexed_iter_mut() { *v = -(j as f64 + 0.5); }
+        }
+
+        let curl = curl_3d(&grid, &velocity);
+        let (cz, cy, cx) = curl.split();
+        for ((_, j, i), &c) in cz.indexed_iter() {
+            if j > 0 && j < 8 && i > 0 && i < 8 {
+                assert!((c - 2.0).abs() < 1.0e-10);
+            }
+        }
+        assert!(cy.iter().chain(cx.iter()).all(|c| c.abs() < 1.0e-10));
+
+        // constant vorticity magnitude in the interior doesn't induce forces there
+        let reference = velocity.clone();
+        confinement_3d(&grid, &mut velocity, 1.0, 0.1);
+        let (_, fy, _) = velocity.split();
+        let (_, fy_ref, _) = reference.split();
+        assert!((fy[(1, 4, 4)] - fy_ref[(1, 4, 4)]).abs() < 1.0e-10);
+    }
+
+    #[test]
+    fn cell_vorticity() {
+        // rigid rotation `u = (-y, x)`
+        let grid = Grid2d::new((6, 7));
+        let mut velocity = <Grid2d as Manifold2d<f64>>::new_simplex_1(&grid);
+        {
+            let (mut vertical, mut horizontal) = velocity.split_mut();
+            for ((_, x), v) in vertical.indexed_iter_mut() { *v = x as f64 + 0.5; }
+            for ((y, _), v) in horizontal.indexed_iter_mut() { *v = -(y as f64 + 0.5); }
+        }
+        let vorticity = cell_vorticity_2d(&grid, &velocity);
+        assert_eq!(vorticity.dim(), (6, 7));
+        for &w in vorticity.slice(s![1..-1, 1..-1]).iter() {
+            assert!((w - 2.0).abs() < 1.0e-10);
+        }
+        // corner cells only see one interior vertex
+        assert!((vorticity[(0, 0)] - 0.5).abs() < 1.0e-10);
+
+        let grid = Grid3d::new((4, 6, 5));
+        let mut velocity = <Grid3d as Manifold3d<f64>>::new_simplex_2(&grid);
+        {
+            let (_, mut fy, mut fx) = velocity.split_mut();
+            for ((_, _, i), v) in fy.indexed_iter_mut() { *v = i as f64 + 0.5; }
+            for ((_, j, _), v) in fx.indexed_iter_mut() { *v = -(j as f64 + 0.5); }
+        }
+        let vorticity = cell_vorticity_3d(&grid, &velocity);
+        assert_eq!(vorticity.dim(), (4, 6, 5));
+        for &(wz, wy, wx) in vorticity.slice(s![1..-1, 1..-1, 1..-1]).iter() {
+            assert!((wz - 2.0).abs() < 1.0e-10 && wy.abs() < 1.0e-10 && wx.abs() < 1.0e-10);
+        }
+
+        // gradient of `x^2 y + z` is curl free
+        let phi = |x: f64, y: f64, z: f64| x * x * y + z;
+        {
+            let (mut fz, mut fy, mut fx) = velocity.split_mut();
+            for ((k, j, i), v) in fz.indexed_iter_mut() {
+                let (x, y, z) = (i as f64 + 0.5, j as f64 + 0.5, k as f64);
+                *v = phi(x, y, z + 0.5) - phi(x, y, z - 0.5);
+            }
+            for ((k, j, i), v) in fy.indexed_iter_mut() {
+                let (x, y, z) = (i as f64 + 0.5, j as f64, k as f64 + 0.5);
+                *v = phi(x, y + 0.5, z) - phi(x, y - 0.5, z);
+            }
+            for ((k, j, i), v) in fx.indexed_iter_mut() {
+                let (x, y, z) = (i as f64, j as f64 + 0.5, k as f64 + 0.5);
+                *v = phi(x + 0.5, y, z) - phi(x - 0.5, y, z);
+            }
+        }
+        let vorticity = cell_vorticity_3d(&grid, &velocity);
+        for &(wz, wy, wx) in vorticity.iter() {
+            assert!(wz.abs() < 1.0e-10 && wy.abs() < 1.0e-10 && wx.abs() < 1.0e-10);
+        }
+    }
+}